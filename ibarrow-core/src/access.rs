@@ -0,0 +1,708 @@
+use anyhow::{anyhow, Result};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, OnceLock};
+
+use crate::lexer::{self, Kind};
+use crate::QueryConfig;
+
+// Allowed tables for a role; `None` columns means every column of the table
+type RolePolicy = HashMap<String, Option<HashSet<String>>>;
+
+fn policies() -> &'static Mutex<HashMap<String, RolePolicy>> {
+    static POLICIES: OnceLock<Mutex<HashMap<String, RolePolicy>>> = OnceLock::new();
+    POLICIES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// Tables every role may read, with their columns. Naming one allows only these columns, so a
+// statement that reads RDB$DATABASE is checked like any other.
+const ALWAYS_ALLOWED: &[(&str, &[&str])] = &[(
+    "RDB$DATABASE",
+    &[
+        "RDB$DESCRIPTION",
+        "RDB$RELATION_ID",
+        "RDB$SECURITY_CLASS",
+        "RDB$CHARACTER_SET_NAME",
+        "RDB$LINGER",
+        "RDB$SQL_SECURITY",
+    ],
+)];
+
+fn always_allowed(table: &str) -> Option<&'static [&'static str]> {
+    ALWAYS_ALLOWED
+        .iter()
+        .find(|(name, _)| *name == table)
+        .map(|(_, columns)| *columns)
+}
+
+// Words the analyzer never treats as table, alias or column names
+const KEYWORDS: &[&str] = &[
+    "ALL",
+    "AND",
+    "ANY",
+    "AS",
+    "ASC",
+    "ASCENDING",
+    "AT",
+    "BETWEEN",
+    "BIGINT",
+    "BLOB",
+    "BLOCK",
+    "BOOLEAN",
+    "BOTH",
+    "BY",
+    "CASE",
+    "CAST",
+    "CHAR",
+    "CHARACTER",
+    "COLLATE",
+    "CONTAINING",
+    "CROSS",
+    "CURRENT_DATE",
+    "CURRENT_ROLE",
+    "CURRENT_TIME",
+    "CURRENT_TIMESTAMP",
+    "CURRENT_USER",
+    "DATE",
+    "DAY",
+    "DECIMAL",
+    "DELETE",
+    "DESC",
+    "DESCENDING",
+    "DISTINCT",
+    "DO",
+    "DOUBLE",
+    "ELSE",
+    "END",
+    "ESCAPE",
+    "EXECUTE",
+    "EXISTS",
+    "FALSE",
+    "FETCH",
+    "FIRST",
+    "FLOAT",
+    "FOR",
+    "FROM",
+    "FULL",
+    "GROUP",
+    "HAVING",
+    "HOUR",
+    "IN",
+    "INNER",
+    "INSERT",
+    "INTEGER",
+    "INTO",
+    "IS",
+    "JOIN",
+    "LAST",
+    "LATERAL",
+    "LEADING",
+    "LEFT",
+    "LIKE",
+    "LIMIT",
+    "MATCHING",
+    "MINUTE",
+    "MONTH",
+    "NATURAL",
+    "NEXT",
+    "NOT",
+    "NOW",
+    "NULL",
+    "NULLS",
+    "NUMERIC",
+    "OF",
+    "OFFSET",
+    "ON",
+    "ONLY",
+    "OR",
+    "ORDER",
+    "OUTER",
+    "OVER",
+    "PARTITION",
+    "PLAN",
+    "PRECISION",
+    "RECURSIVE",
+    "RETURNING",
+    "RIGHT",
+    "ROW",
+    "ROWS",
+    "SECOND",
+    "SELECT",
+    "SET",
+    "SIMILAR",
+    "SKIP",
+    "SMALLINT",
+    "SOME",
+    "STARTING",
+    "THEN",
+    "TIME",
+    "TIMESTAMP",
+    "TO",
+    "TODAY",
+    "TOMORROW",
+    "TRAILING",
+    "TRUE",
+    "UNION",
+    "UNKNOWN",
+    "UPDATE",
+    "USING",
+    "VALUES",
+    "VARCHAR",
+    "WHEN",
+    "WHERE",
+    "WINDOW",
+    "WITH",
+    "YEAR",
+    "YESTERDAY",
+];
+
+// Registers (or replaces) the tables and columns a role may query
+pub fn set_policy(role: &str, tables: HashMap<String, Option<Vec<String>>>) {
+    let policy = tables
+        .into_iter()
+        .map(|(table, columns)| {
+            let columns = columns.map(|cols| cols.iter().map(|c| c.to_uppercase()).collect());
+            (table.to_uppercase(), columns)
+        })
+        .collect();
+    let mut policies = policies().lock().unwrap_or_else(|e| e.into_inner());
+    policies.insert(role.to_uppercase(), policy);
+}
+
+// Removes one role's policy, or all of them
+pub fn clear_policy(role: Option<&str>) {
+    let mut policies = policies().lock().unwrap_or_else(|e| e.into_inner());
+    match role {
+        Some(role) => {
+            policies.remove(&role.to_uppercase());
+        }
+        None => policies.clear(),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    // Unquoted identifier or keyword, upper-cased
+    Word(String),
+    // Quoted identifier, never a keyword
+    Quoted(String),
+    // Literal, number or parameter
+    Number,
+    Symbol(char),
+}
+
+// The statement's tokens without whitespace and comments
+fn tokenize(sql: &str) -> Vec<Token> {
+    lexer::tokenize(sql)
+        .filter_map(|token| match token.kind {
+            Kind::Whitespace | Kind::LineComment | Kind::BlockComment => None,
+            Kind::Literal | Kind::Parameter | Kind::Placeholder | Kind::Number => {
+                Some(Token::Number)
+            }
+            Kind::Word => Some(Token::Word(token.text.to_uppercase())),
+            Kind::QuotedIdentifier => Some(Token::Quoted(lexer::unquote(token.text))),
+            Kind::Symbol => token.text.chars().next().map(Token::Symbol),
+        })
+        .collect()
+}
+
+fn is_keyword(word: &str) -> bool {
+    KEYWORDS.contains(&word)
+}
+
+// Words that end a FROM list
+const AFTER_FROM: &[&str] = &[
+    "EXCEPT",
+    "FETCH",
+    "FOR",
+    "GROUP",
+    "HAVING",
+    "INTERSECT",
+    "LIMIT",
+    "OFFSET",
+    "ORDER",
+    "PLAN",
+    "RETURNING",
+    "ROWS",
+    "UNION",
+    "WHERE",
+    "WINDOW",
+];
+
+// A SELECT and the names its FROM clause binds. A subquery also sees the names of the blocks
+// around it; UNION arms and sibling subqueries do not see each other's.
+#[derive(Debug, Default)]
+struct Block {
+    parent: Option<usize>,
+    // alias -> table; derived tables map to None
+    aliases: HashMap<String, Option<String>>,
+    // Names bound to different tables within the block
+    ambiguous: Vec<String>,
+}
+
+impl Block {
+    fn bind(&mut self, name: &str, table: Option<String>) {
+        match self.aliases.get(name) {
+            Some(bound) if *bound != table => self.ambiguous.push(name.to_string()),
+            Some(_) => {}
+            None => {
+                self.aliases.insert(name.to_string(), table);
+            }
+        }
+    }
+}
+
+// Object references found in a statement. Qualified names and stars carry the block they
+// appear in.
+#[derive(Debug, Default)]
+struct Analysis {
+    // Tables and views, without CTE references
+    tables: Vec<String>,
+    blocks: Vec<Block>,
+    // CTEs the statement reads
+    ctes: HashSet<String>,
+    output_aliases: HashSet<String>,
+    qualified: Vec<(usize, String, String)>,
+    bare: Vec<String>,
+    // `*` (None) or `alias.*`
+    stars: Vec<(usize, Option<String>)>,
+}
+
+impl Analysis {
+    // The table `qualifier` names in `block`, looking through the blocks around it
+    fn resolve(&self, block: usize, qualifier: &str) -> Option<&Option<String>> {
+        let mut block = Some(block);
+        while let Some(id) = block {
+            if let Some(table) = self.blocks[id].aliases.get(qualifier) {
+                return Some(table);
+            }
+            block = self.blocks[id].parent;
+        }
+        None
+    }
+
+    // A name bound to different tables in one block, or in a block and a block nested in it
+    fn ambiguous_alias(&self) -> Option<&str> {
+        self.blocks.iter().find_map(|block| {
+            block.ambiguous.first().map(String::as_str).or_else(|| {
+                let parent = block.parent?;
+                block
+                    .aliases
+                    .iter()
+                    .find_map(|(name, table)| match self.resolve(parent, name) {
+                        Some(outer) if outer != table => Some(name.as_str()),
+                        _ => None,
+                    })
+            })
+        })
+    }
+
+    fn is_alias(&self, name: &str) -> bool {
+        self.blocks
+            .iter()
+            .any(|block| block.aliases.contains_key(name))
+    }
+}
+
+// A CTE declared in a WITH list
+struct Cte {
+    name: String,
+    columns: Vec<String>,
+    // Tokens of its name and of the parenthesis closing its body, and where the query
+    // declaring it ends
+    at: usize,
+    body_end: usize,
+    scope_end: usize,
+    recursive: bool,
+}
+
+impl Cte {
+    // Whether `name` at token `i` reads this CTE rather than a table: after its body, or inside
+    // it for WITH RECURSIVE, and within the query that declares it
+    fn covers(&self, name: &str, i: usize) -> bool {
+        self.name == name
+            && i < self.scope_end
+            && (i > self.body_end || (self.recursive && i > self.at))
+    }
+}
+
+// CTEs declared by `WITH [RECURSIVE] name [(columns)] AS (...)`, and after each `,` that
+// continues the list
+fn declared_ctes(tokens: &[Token]) -> Vec<Cte> {
+    // Closing parenthesis of each opening one, and the innermost opening one around each token
+    let mut closing = vec![tokens.len(); tokens.len()];
+    let mut around = vec![None; tokens.len()];
+    let mut parens = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if *token == Token::Symbol(')') {
+            if let Some(open) = parens.pop() {
+                closing[open] = i;
+            }
+        }
+        around[i] = parens.last().copied();
+        if *token == Token::Symbol('(') {
+            parens.push(i);
+        }
+    }
+    let is_word = |i: usize, w: &str| matches!(tokens.get(i), Some(Token::Word(x)) if x == w);
+    let is_symbol = |i: usize, c: char| tokens.get(i) == Some(&Token::Symbol(c));
+    let named = |i: usize| match tokens.get(i) {
+        Some(Token::Word(w)) if !is_keyword(w) => Some(w.clone()),
+        Some(Token::Quoted(n)) => Some(n.clone()),
+        _ => None,
+    };
+
+    let mut ctes = Vec::new();
+    for (i, around) in around.iter().enumerate() {
+        if !is_word(i, "WITH") {
+            continue;
+        }
+        let scope_end = around.map_or(tokens.len(), |open| closing[open]);
+        let recursive = is_word(i + 1, "RECURSIVE");
+        let mut j = if recursive { i + 2 } else { i + 1 };
+        while let Some(name) = named(j) {
+            let mut k = j + 1;
+            let mut columns = Vec::new();
+            if is_symbol(k, '(') {
+                columns = (k + 1..closing[k]).filter_map(named).collect();
+                k = closing[k] + 1;
+            }
+            if !is_word(k, "AS") || !is_symbol(k + 1, '(') {
+                break;
+            }
+            let body_end = closing[k + 1];
+            ctes.push(Cte {
+                name,
+                columns,
+                at: j,
+                body_end,
+                scope_end,
+                recursive,
+            });
+            if !is_symbol(body_end + 1, ',') {
+                break;
+            }
+            j = body_end + 2;
+        }
+    }
+    ctes
+}
+
+fn analyze(sql: &str) -> Analysis {
+    let tokens = tokenize(sql);
+    let word = |i: usize| match tokens.get(i) {
+        Some(Token::Word(w)) => Some(w.as_str()),
+        _ => None,
+    };
+    // Table, alias and column names: unquoted words that are not keywords, and quoted names
+    let named = |i: usize| match tokens.get(i) {
+        Some(Token::Word(w)) if !is_keyword(w) => Some(w.as_str()),
+        Some(Token::Quoted(n)) => Some(n.as_str()),
+        _ => None,
+    };
+    let symbol = |i: usize| match tokens.get(i) {
+        Some(Token::Symbol(c)) => Some(*c),
+        _ => None,
+    };
+
+    let mut analysis = Analysis {
+        blocks: vec![Block::default()],
+        ..Default::default()
+    };
+    let mut consumed = HashSet::new();
+    let ctes = declared_ctes(&tokens);
+    for cte in &ctes {
+        consumed.insert(cte.at);
+        analysis.output_aliases.extend(cte.columns.iter().cloned());
+    }
+    // Name of the function each open parenthesis belongs to
+    let mut calls: Vec<Option<&str>> = Vec::new();
+    // Open blocks with the parenthesis depth each started at; the statement itself is block 0
+    let mut open: Vec<(usize, usize)> = vec![(0, 0)];
+    // Parenthesis depths of open FROM lists, where `,` starts another table reference
+    let mut from_lists: Vec<usize> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match symbol(i) {
+            Some('(') => calls.push(i.checked_sub(1).and_then(word)),
+            Some(')') => {
+                calls.pop();
+                // Closes the subqueries inside the parentheses
+                while open.len() > 1 && open[open.len() - 1].1 > calls.len() {
+                    open.pop();
+                }
+                from_lists.retain(|depth| *depth <= calls.len());
+            }
+            _ => {}
+        }
+        if word(i).is_some_and(|w| AFTER_FROM.contains(&w)) {
+            from_lists.retain(|depth| *depth != calls.len());
+        }
+        if word(i) == Some("SELECT") {
+            // A SELECT at the depth of an open block is the next arm of a UNION
+            while open.len() > 1 && open[open.len() - 1].1 >= calls.len() {
+                open.pop();
+            }
+            analysis.blocks.push(Block {
+                parent: Some(open[open.len() - 1].0),
+                ..Default::default()
+            });
+            open.push((analysis.blocks.len() - 1, calls.len()));
+        }
+        let block = open[open.len() - 1].0;
+        // EXTRACT(x FROM y), SUBSTRING(s FROM n) and TRIM(c FROM s) are not table references
+        let in_call = matches!(
+            calls.last(),
+            Some(Some(
+                "EXTRACT" | "SUBSTRING" | "TRIM" | "OVERLAY" | "POSITION"
+            ))
+        );
+        if word(i) == Some("FROM") && !in_call && !from_lists.contains(&calls.len()) {
+            from_lists.push(calls.len());
+        }
+        // Table positions: FROM a [alias], b [alias] / JOIN / UPDATE / INTO
+        let table_position = match word(i) {
+            Some("FROM" | "JOIN" | "UPDATE" | "INTO") => !in_call,
+            _ => symbol(i) == Some(',') && from_lists.last() == Some(&calls.len()),
+        };
+        match named(i) {
+            _ if table_position => {
+                let mut j = i + 1;
+                if let Some(name) = named(j).map(str::to_string) {
+                    consumed.insert(j);
+                    // A CTE is checked through its body, like a derived table
+                    let table = if ctes.iter().any(|cte| cte.covers(&name, j)) {
+                        analysis.ctes.insert(name.clone());
+                        None
+                    } else {
+                        analysis.tables.push(name.clone());
+                        Some(name.clone())
+                    };
+                    analysis.blocks[block].bind(&name, table.clone());
+                    j += 1;
+                    // Selectable procedure arguments are scanned like any other tokens
+                    if symbol(j) != Some('(') {
+                        if word(j) == Some("AS") {
+                            j += 1;
+                        }
+                        if let Some(alias) = named(j) {
+                            consumed.insert(j);
+                            analysis.blocks[block].bind(alias, table);
+                        }
+                    }
+                }
+            }
+            Some(name) if !consumed.contains(&i) => {
+                if i > 0 && word(i - 1) == Some("AS") {
+                    analysis.output_aliases.insert(name.to_string());
+                } else if i > 0 && symbol(i - 1) == Some(')') {
+                    // Derived table alias
+                    analysis.blocks[block].bind(name, None);
+                } else if symbol(i + 1) == Some('(') {
+                    // Function call
+                } else if symbol(i + 1) == Some('.') {
+                    match (named(i + 2).or(word(i + 2)), symbol(i + 2)) {
+                        (Some(column), _) => {
+                            analysis
+                                .qualified
+                                .push((block, name.to_string(), column.to_string()));
+                        }
+                        (None, Some('*')) => analysis.stars.push((block, Some(name.to_string()))),
+                        _ => {}
+                    }
+                    i += 3;
+                    continue;
+                } else {
+                    analysis.bare.push(name.to_string());
+                }
+            }
+            _ => {}
+        }
+        if symbol(i) == Some('*') {
+            let prev_word = i.checked_sub(1).and_then(word);
+            let after_first = i >= 2
+                && tokens[i - 1] == Token::Number
+                && matches!(word(i - 2), Some("FIRST" | "SKIP"));
+            if matches!(prev_word, Some("SELECT" | "DISTINCT" | "ALL"))
+                || symbol(i.wrapping_sub(1)) == Some(',')
+                || after_first
+            {
+                analysis.stars.push((block, None));
+            }
+        }
+        i += 1;
+    }
+    analysis
+}
+
+// Tables and views `sql` reads, in order of first mention, without the names of its CTEs
+pub fn tables(sql: &str) -> Vec<String> {
+    let analysis = analyze(sql);
+    let mut tables = Vec::new();
+    for table in analysis.tables {
+        if !tables.contains(&table) {
+            tables.push(table);
+        }
+    }
+    tables
+}
+
+// Words that make a statement change data, schema or session state
+const WRITE_WORDS: &[&str] = &[
+    "ALTER",
+    "COMMENT",
+    "COMMIT",
+    "CREATE",
+    "DECLARE",
+    "DELETE",
+    "DROP",
+    "EXECUTE",
+    "GRANT",
+    "INSERT",
+    "MERGE",
+    "RECREATE",
+    "RELEASE",
+    "REVOKE",
+    "ROLLBACK",
+    "SAVEPOINT",
+    "UPDATE",
+];
+
+// Whether `sql` only reads: one SELECT, or WITH ... SELECT, that changes nothing. `FOR UPDATE`
+// and `WITH LOCK` are still reads.
+pub fn is_query(sql: &str) -> bool {
+    let tokens = tokenize(sql);
+    let first = tokens.iter().find(|token| **token != Token::Symbol('('));
+    if !matches!(first, Some(Token::Word(w)) if w == "SELECT" || w == "WITH") {
+        return false;
+    }
+    tokens.iter().enumerate().all(|(i, token)| match token {
+        Token::Word(w) if w == "UPDATE" => i > 0 && tokens[i - 1] == Token::Word("FOR".to_string()),
+        Token::Word(w) => !WRITE_WORDS.contains(&w.as_str()),
+        // A second statement after the terminator
+        Token::Symbol(';') => i + 1 == tokens.len(),
+        _ => true,
+    })
+}
+
+// Rejects a statement that touches tables or columns the configured role may not access.
+// Policies only describe what a role may read, so anything but a query is rejected: DML and
+// DDL, and EXECUTE BLOCK/STATEMENT/PROCEDURE, whose SQL the analyzer cannot see.
+pub fn check(sql: &str, config: &QueryConfig) -> Result<()> {
+    let role = match &config.access_role {
+        Some(role) => role.to_uppercase(),
+        None => return Ok(()),
+    };
+    if !is_query(sql) {
+        return Err(anyhow!(
+            "Access denied: role {} may only run SELECT statements",
+            role
+        ));
+    }
+    let policies = policies().lock().unwrap_or_else(|e| e.into_inner());
+    let policy = policies.get(&role).ok_or_else(|| {
+        anyhow!(
+            "Access denied: no access policy registered for role {}",
+            role
+        )
+    })?;
+    let analysis = analyze(sql);
+    if let Some(alias) = analysis.ambiguous_alias() {
+        return Err(anyhow!(
+            "Access denied: role {} may not use alias {} for different tables; give each table its own alias",
+            role,
+            alias
+        ));
+    }
+
+    let mut restricted: Vec<&HashSet<String>> = Vec::new();
+    // The columns of CTEs were checked in their bodies
+    let mut unrestricted = !analysis.ctes.is_empty();
+    let mut system_columns: Vec<&str> = Vec::new();
+    for table in &analysis.tables {
+        if let Some(columns) = always_allowed(table) {
+            system_columns.extend(columns);
+            continue;
+        }
+        match policy.get(table) {
+            None => {
+                return Err(anyhow!(
+                    "Access denied: role {} may not query table {}",
+                    role,
+                    table
+                ))
+            }
+            Some(None) => unrestricted = true,
+            Some(Some(columns)) => restricted.push(columns),
+        }
+    }
+    let columns_of = |block: usize, qualifier: &str| -> Option<(&String, &HashSet<String>)> {
+        let table = analysis.resolve(block, qualifier)?.as_ref()?;
+        match policy.get(table) {
+            Some(Some(columns)) => Some((table, columns)),
+            _ => None,
+        }
+    };
+    for (block, qualifier, column) in &analysis.qualified {
+        let table = analysis.resolve(*block, qualifier).cloned().flatten();
+        if let Some(columns) = table.as_deref().and_then(always_allowed) {
+            if !columns.contains(&column.as_str()) {
+                return Err(anyhow!(
+                    "Access denied: role {} may not access column {}.{}",
+                    role,
+                    table.unwrap_or_default(),
+                    column
+                ));
+            }
+        }
+    }
+
+    for (block, star) in &analysis.stars {
+        let denied = match star {
+            Some(qualifier) => columns_of(*block, qualifier).map(|(table, _)| table.clone()),
+            None if !restricted.is_empty() => Some("a restricted table".to_string()),
+            None => None,
+        };
+        if let Some(table) = denied {
+            return Err(anyhow!(
+                "Access denied: role {} may not select * from {}; list the allowed columns",
+                role,
+                table
+            ));
+        }
+    }
+    for (block, qualifier, column) in &analysis.qualified {
+        if let Some((table, columns)) = columns_of(*block, qualifier) {
+            if !columns.contains(column) {
+                return Err(anyhow!(
+                    "Access denied: role {} may not access column {}.{}",
+                    role,
+                    table,
+                    column
+                ));
+            }
+        }
+    }
+    for column in &analysis.bare {
+        let known = analysis.output_aliases.contains(column)
+            || analysis.is_alias(column)
+            || analysis.ctes.contains(column)
+            || system_columns.contains(&column.as_str());
+        if known {
+            continue;
+        }
+        // Without the schema an unqualified name could belong to any restricted table
+        let allowed = if restricted.is_empty() {
+            unrestricted
+        } else {
+            restricted.iter().all(|cols| cols.contains(column))
+        };
+        if !allowed {
+            return Err(anyhow!(
+                "Access denied: role {} may not access column {} (qualify columns of restricted tables)",
+                role,
+                column
+            ));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use anyhow::{anyhow, Result};
+use arrow::datatypes::SchemaRef;
+use arrow::error::ArrowError;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use arrow_odbc::{ConcurrentOdbcReader, OdbcReader, OdbcReaderBuilder};
+use odbc_api::Cursor;
+use std::time::{Duration, Instant};
+
+use crate::QueryConfig;
+
+// Rows in the sampled first batch when QueryConfig.target_batch_bytes or adaptive_fetch is set
+// without batch_size
+pub const SAMPLE_ROWS: usize = 1000;
+// arrow-odbc's own ceiling on rows per batch
+const MAX_ROWS: usize = 65535;
+// Throughput gain a larger fetch size must bring for adaptive_fetch to keep growing it
+const MIN_GAIN: f64 = 1.1;
+
+pub fn validate(target_batch_bytes: Option<u64>) -> Result<()> {
+    if target_batch_bytes == Some(0) {
+        return Err(anyhow!("target_batch_bytes must be greater than 0"));
+    }
+    Ok(())
+}
+
+pub fn enabled(config: &QueryConfig) -> bool {
+    config.target_batch_bytes.is_some() || config.adaptive_fetch
+}
+
+// Rows per batch that bring a batch like `sample` close to `target` bytes of Arrow memory
+fn rows_for(sample: &RecordBatch, target: u64) -> usize {
+    let row_bytes = (sample.get_array_memory_size() / sample.num_rows()).max(1) as u64;
+    ((target / row_bytes) as usize).clamp(1, MAX_ROWS)
+}
+
+// QueryConfig.adaptive_fetch: doubles the fetch size while each step raises the bytes fetched
+// per second by at least MIN_GAIN, then settles on the best size seen
+struct Tuner {
+    best_rows: usize,
+    best_rate: f64,
+}
+
+impl Tuner {
+    // Next fetch size after a full batch of `rows` fetched at `rate`, or Err(rows) to settle on
+    fn step(&mut self, rows: usize, rate: f64, ceiling: usize) -> Result<usize, usize> {
+        if rate < self.best_rate * MIN_GAIN {
+            return Err(self.best_rows);
+        }
+        self.best_rows = rows;
+        self.best_rate = rate;
+        match (rows * 2).min(ceiling) {
+            next if next > rows => Ok(next),
+            _ => Err(rows.min(ceiling)),
+        }
+    }
+}
+
+enum Inner<C: Cursor> {
+    Plain(OdbcReader<C>),
+    Prefetching(ConcurrentOdbcReader<C>),
+}
+
+// Reads the first batch with the configured row count, then rebinds the cursor with new row
+// counts: one that makes batches hold about QueryConfig.target_batch_bytes of Arrow memory
+// and, with QueryConfig.adaptive_fetch, larger ones for as long as they fetch faster
+pub struct Adaptive<C: Cursor> {
+    inner: Option<Inner<C>>,
+    builder: OdbcReaderBuilder,
+    target: Option<u64>,
+    tuner: Option<Tuner>,
+    prefetch: bool,
+    // Rows per batch currently bound; None until the first batch
+    rows: Option<usize>,
+    // Largest fetch size allowed, from target_batch_bytes
+    ceiling: usize,
+    schema: SchemaRef,
+}
+
+impl<C: Cursor + Send + 'static> Adaptive<C> {
+    pub fn new(
+        builder: OdbcReaderBuilder,
+        cursor: C,
+        config: &QueryConfig,
+    ) -> Result<Self, arrow_odbc::Error> {
+        let reader = builder.build(cursor)?;
+        let schema = reader.schema();
+        Ok(Self {
+            inner: Some(Inner::Plain(reader)),
+            builder,
+            target: config.target_batch_bytes,
+            tuner: config.adaptive_fetch.then_some(Tuner {
+                best_rows: 0,
+                best_rate: 0.0,
+            }),
+            prefetch: config.prefetch,
+            rows: None,
+            ceiling: MAX_ROWS,
+            schema,
+        })
+    }
+
+    fn resize(&mut self, rows: usize) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let cursor = match self.inner.take() {
+            Some(Inner::Plain(reader)) => reader.into_cursor()?,
+            Some(Inner::Prefetching(reader)) => reader.into_cursor()?,
+            None => return Ok(()),
+        };
+        let mut builder = self.builder.clone();
+        builder.with_max_num_rows_per_batch(rows);
+        let reader = builder.build(cursor)?;
+        // Prefetching would time waits instead of fetches, so it starts once tuning is done
+        self.inner = Some(if self.prefetch && self.tuner.is_none() {
+            Inner::Prefetching(reader.into_concurrent()?)
+        } else {
+            Inner::Plain(reader)
+        });
+        self.rows = Some(rows);
+        Ok(())
+    }
+
+    // Fetch size to rebind to after `batch`, fetched in `elapsed`, if it should change
+    fn next_rows(&mut self, batch: &RecordBatch, elapsed: Duration) -> Option<usize> {
+        let rows = match self.rows {
+            Some(rows) => rows,
+            // The first batch is full unless the whole result fitted in it
+            None => {
+                self.rows = Some(batch.num_rows());
+                if let Some(target) = self.target {
+                    self.ceiling = rows_for(batch, target);
+                }
+                batch.num_rows()
+            }
+        };
+        let next = match self.tuner.as_mut() {
+            None => Err(self.ceiling),
+            // A short batch is the end of the result, or max_bytes_per_batch capping the rows
+            Some(_) if batch.num_rows() < rows => Err(rows),
+            Some(tuner) => {
+                let rate = batch.get_array_memory_size() as f64 / elapsed.as_secs_f64().max(1e-6);
+                tuner.step(rows, rate, self.ceiling)
+            }
+        };
+        match next {
+            Ok(next) => Some(next),
+            Err(settled) => {
+                if self.tuner.take().is_some() {
+                    log::debug!("adaptive_fetch settled on {} rows per batch", settled);
+                }
+                // Rebinding at the same size still starts prefetching
+                (settled != rows || self.prefetch).then_some(settled)
+            }
+        }
+    }
+}
+
+impl<C: Cursor + Send + 'static> Iterator for Adaptive<C> {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let started = Instant::now();
+        let batch = match self.inner.as_mut()? {
+            Inner::Plain(reader) => reader.next()?,
+            Inner::Prefetching(reader) => reader.next()?,
+        };
+        let elapsed = started.elapsed();
+        let settled = self.tuner.is_none() && self.rows.is_some();
+        if let (Ok(sample), false) = (&batch, settled) {
+            if sample.num_rows() > 0 {
+                if let Some(rows) = self.next_rows(sample, elapsed) {
+                    if let Err(e) = self.resize(rows) {
+                        return Some(Err(ArrowError::ExternalError(e)));
+                    }
+                }
+            }
+        }
+        Some(batch)
+    }
+}
+
+impl<C: Cursor + Send + 'static> RecordBatchReader for Adaptive<C> {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
@@ -0,0 +1,296 @@
+use anyhow::{anyhow, Result};
+use arrow::array::{Array, ArrayRef, AsArray, ListArray, StringArray};
+use arrow::buffer::{NullBuffer, OffsetBuffer};
+use arrow::compute::{cast, cast_with_options, CastOptions};
+use arrow::datatypes::{DataType, Field, FieldRef, Schema, SchemaRef, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use std::sync::Arc;
+
+use crate::{find_column, BatchReader, QueryConfig};
+
+// An ARRAY column declaration from QueryConfig.array_columns, such as "INTEGER",
+// "DOUBLE PRECISION[10]" or "NUMERIC(9,2)[3,4]"
+#[derive(Debug, Clone)]
+struct Declared {
+    element: DataType,
+    dimensions: usize,
+}
+
+// Arrow type of a Firebird array element type
+fn element_type(name: &str) -> Result<DataType> {
+    let name = name.trim().to_uppercase();
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    let (base, args) = match name.split_once('(') {
+        Some((base, args)) => {
+            let args = args
+                .strip_suffix(')')
+                .ok_or_else(|| anyhow!("Invalid array element type '{}'", name))?;
+            let args = args
+                .split(',')
+                .map(|arg| arg.trim().parse::<u8>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| anyhow!("Invalid array element type '{}'", name))?;
+            (base.trim(), args)
+        }
+        None => (name.as_str(), Vec::new()),
+    };
+    Ok(match (base, args.as_slice()) {
+        ("SMALLINT", []) => DataType::Int16,
+        ("INTEGER" | "INT", []) => DataType::Int32,
+        ("BIGINT", []) => DataType::Int64,
+        ("FLOAT", []) => DataType::Float32,
+        ("DOUBLE PRECISION" | "DOUBLE", []) => DataType::Float64,
+        ("NUMERIC" | "DECIMAL", [precision]) => DataType::Decimal128(*precision, 0),
+        ("NUMERIC" | "DECIMAL", [precision, scale]) if scale <= precision => {
+            DataType::Decimal128(*precision, *scale as i8)
+        }
+        ("CHAR" | "VARCHAR", [] | [_]) => DataType::Utf8,
+        ("BOOLEAN", []) => DataType::Boolean,
+        ("DATE", []) => DataType::Date32,
+        ("TIME", []) => DataType::Time64(TimeUnit::Microsecond),
+        ("TIMESTAMP", []) => DataType::Timestamp(TimeUnit::Microsecond, None),
+        _ => {
+            return Err(anyhow!(
+                "Unsupported array element type '{}': expected SMALLINT, INTEGER, BIGINT, \
+                 FLOAT, DOUBLE PRECISION, NUMERIC(p,s), DECIMAL(p,s), CHAR, VARCHAR, BOOLEAN, \
+                 DATE, TIME or TIMESTAMP",
+                name
+            ))
+        }
+    })
+}
+
+// Splits "TYPE[bounds]" into the element type and the number of dimensions; without bounds
+// the array has one
+fn parse_declared(declared: &str) -> Result<Declared> {
+    let declared = declared.trim();
+    let (element, dimensions) = match declared.split_once('[') {
+        Some((element, bounds)) => {
+            let bounds = bounds
+                .strip_suffix(']')
+                .ok_or_else(|| anyhow!("Invalid array declaration '{}'", declared))?;
+            (element, bounds.split(',').count())
+        }
+        None => (declared, 1),
+    };
+    Ok(Declared {
+        element: element_type(element)?,
+        dimensions,
+    })
+}
+
+pub fn validate(config: &QueryConfig) -> Result<()> {
+    for (column, declared) in config.array_columns.iter().flatten() {
+        parse_declared(declared).map_err(|e| anyhow!("array_columns['{}']: {}", column, e))?;
+    }
+    Ok(())
+}
+
+fn list_type(element: &DataType, dimensions: usize) -> DataType {
+    (0..dimensions).fold(element.clone(), |inner, _| {
+        DataType::List(Arc::new(Field::new("item", inner, true)))
+    })
+}
+
+// A parsed array value: nested lists down to the element text, None for NULL elements
+#[derive(Debug)]
+enum Node {
+    List(Vec<Node>),
+    Item(Option<String>),
+}
+
+// Parses the driver's text form of an array, "{1, 2, 3}" or "{{1, 2}, {3, 4}}" for two
+// dimensions. Square brackets are accepted too, elements may be quoted and NULL or an empty
+// element stands for a NULL element.
+fn parse_value(text: &str) -> Result<Node> {
+    let mut chars = text.trim().chars().peekable();
+    let node = parse_list(&mut chars, text)?;
+    if chars.any(|c| !c.is_whitespace()) {
+        return Err(anyhow!("Trailing characters in array value '{}'", text));
+    }
+    Ok(node)
+}
+
+fn parse_list(chars: &mut std::iter::Peekable<std::str::Chars<'_>>, text: &str) -> Result<Node> {
+    let close = match chars.next() {
+        Some('{') => '}',
+        Some('[') => ']',
+        _ => return Err(anyhow!("Array value '{}' does not start with '{{'", text)),
+    };
+    let mut items = Vec::new();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        match chars.peek() {
+            Some(c) if *c == close && items.is_empty() => {
+                chars.next();
+                return Ok(Node::List(items));
+            }
+            Some('{' | '[') => items.push(parse_list(chars, text)?),
+            Some(quote @ ('"' | '\'')) => {
+                let quote = *quote;
+                chars.next();
+                let mut item = String::new();
+                loop {
+                    match chars.next() {
+                        // A doubled quote stands for the quote itself
+                        Some(c) if c == quote && chars.next_if_eq(&quote).is_some() => item.push(c),
+                        Some(c) if c == quote => break,
+                        Some(c) => item.push(c),
+                        None => return Err(anyhow!("Unterminated string in array '{}'", text)),
+                    }
+                }
+                items.push(Node::Item(Some(item)));
+            }
+            _ => {
+                let mut item = String::new();
+                while let Some(c) = chars.next_if(|c| *c != ',' && *c != close) {
+                    item.push(c);
+                }
+                let item = item.trim();
+                let null = item.is_empty() || item.eq_ignore_ascii_case("NULL");
+                items.push(Node::Item((!null).then(|| item.to_string())));
+            }
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        match chars.next() {
+            Some(',') => continue,
+            Some(c) if c == close => return Ok(Node::List(items)),
+            _ => return Err(anyhow!("Malformed array value '{}'", text)),
+        }
+    }
+}
+
+// Builds the Arrow array for `values` at the given nesting depth; depth 0 holds the elements
+fn build(values: Vec<Option<Node>>, depth: usize, element: &DataType) -> Result<ArrayRef> {
+    if depth == 0 {
+        let text = values
+            .into_iter()
+            .map(|value| match value {
+                None | Some(Node::Item(None)) => Ok(None),
+                Some(Node::Item(Some(text))) => Ok(Some(text)),
+                Some(Node::List(_)) => {
+                    Err(anyhow!("Array value has more dimensions than declared"))
+                }
+            })
+            .collect::<Result<StringArray>>()?;
+        // Invalid elements are an error rather than NULL
+        let options = CastOptions {
+            safe: false,
+            ..Default::default()
+        };
+        return Ok(cast_with_options(&text, element, &options)?);
+    }
+    let mut lengths = Vec::with_capacity(values.len());
+    let mut valid = Vec::with_capacity(values.len());
+    let mut children = Vec::new();
+    for value in values {
+        match value {
+            None | Some(Node::Item(None)) => {
+                lengths.push(0);
+                valid.push(false);
+            }
+            Some(Node::List(items)) => {
+                lengths.push(items.len());
+                valid.push(true);
+                children.extend(items.into_iter().map(Some));
+            }
+            Some(Node::Item(Some(_))) => {
+                return Err(anyhow!("Array value has fewer dimensions than declared"))
+            }
+        }
+    }
+    let values = build(children, depth - 1, element)?;
+    let field = Arc::new(Field::new("item", values.data_type().clone(), true));
+    Ok(Arc::new(ListArray::try_new(
+        field,
+        OffsetBuffer::from_lengths(lengths),
+        values,
+        Some(NullBuffer::from(valid)),
+    )?))
+}
+
+fn decode_column(array: &ArrayRef, declared: &Declared, name: &str) -> Result<ArrayRef> {
+    // Drivers hand arrays over as text, or as binary holding the same text
+    let text = cast(array, &DataType::Utf8)?;
+    let values = text
+        .as_string::<i32>()
+        .iter()
+        .map(|value| value.map(parse_value).transpose())
+        .collect::<Result<Vec<_>>>()
+        .map_err(|e| anyhow!("Failed to decode ARRAY column '{}': {}", name, e))?;
+    build(values, declared.dimensions, &declared.element)
+        .map_err(|e| anyhow!("Failed to decode ARRAY column '{}': {}", name, e))
+}
+
+// Decodes the columns named in QueryConfig.array_columns into Arrow List arrays
+struct Decoded {
+    inner: BatchReader,
+    // Column index and declaration of every array column
+    columns: Vec<(usize, Declared)>,
+    schema: SchemaRef,
+}
+
+impl Iterator for Decoded {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = match self.inner.next()? {
+            Ok(batch) => batch,
+            Err(e) => return Some(Err(e)),
+        };
+        let mut arrays = batch.columns().to_vec();
+        for (index, declared) in &self.columns {
+            let name = self.schema.field(*index).name();
+            match decode_column(&arrays[*index], declared, name) {
+                Ok(array) => arrays[*index] = array,
+                Err(e) => return Some(Err(ArrowError::ExternalError(e.into()))),
+            }
+        }
+        Some(RecordBatch::try_new(self.schema.clone(), arrays))
+    }
+}
+
+impl RecordBatchReader for Decoded {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+pub fn wrap(reader: BatchReader, config: &QueryConfig) -> Result<BatchReader> {
+    let Some(array_columns) = config.array_columns.as_ref().filter(|c| !c.is_empty()) else {
+        return Ok(reader);
+    };
+    let schema = reader.schema();
+    let mut fields: Vec<FieldRef> = schema.fields().iter().cloned().collect();
+    let mut columns = Vec::new();
+    for (name, declared) in array_columns {
+        let index = find_column(&schema, name)
+            .ok_or_else(|| anyhow!("array column '{}' is not in the result set", name))?;
+        let declared = parse_declared(declared)?;
+        let field = &fields[index];
+        if !matches!(
+            field.data_type(),
+            DataType::Utf8
+                | DataType::LargeUtf8
+                | DataType::Utf8View
+                | DataType::Binary
+                | DataType::LargeBinary
+        ) {
+            return Err(anyhow!(
+                "array column '{}' must come back as text or binary, not {}",
+                name,
+                field.data_type()
+            ));
+        }
+        let data_type = list_type(&declared.element, declared.dimensions);
+        fields[index] = Arc::new(Field::clone(field).with_data_type(data_type));
+        columns.push((index, declared));
+    }
+    log::debug!("Decoding {} ARRAY columns", columns.len());
+    Ok(Box::new(Decoded {
+        inner: reader,
+        columns,
+        schema: Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone())),
+    }))
+}
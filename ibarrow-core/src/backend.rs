@@ -0,0 +1,328 @@
+use anyhow::{anyhow, Result};
+use arrow::array::{Array, AsArray};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::{RecordBatchIterator, RecordBatchReader};
+use odbc_api::handles::Statement;
+use odbc_api::ResultSetMetadata;
+use serde::Serialize;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+use crate::charset::Transcoded;
+use crate::domains::Domains;
+use crate::params::SqlParam;
+use crate::watchdog::Watchdog;
+use crate::{
+    access, batch_reader, build_connection_string, charset, diagnostics, domains, driverenv,
+    open_cursor, retry, wrap_batches, BatchReader, QueryConfig,
+};
+
+pub const ODBC: &str = "odbc";
+pub const FIREBIRD: &str = "firebird";
+
+// Field metadata key Backend::schema puts each column's driver type under
+pub const DB_TYPE_KEY: &str = "db_type";
+
+// How ibarrow reaches the database. The read pipeline (restarts, row limits, dictionary
+// encoding, progress) and the Python API sit on top of this, so another transport only has
+// to provide these operations.
+pub trait Backend: Send + Sync {
+    // Name QueryConfig.backend selects it by
+    fn name(&self) -> &'static str;
+
+    // Runs a statement that returns no rows and gives the number of rows it changed, when the
+    // transport reports it
+    fn execute(
+        &self,
+        sql: &str,
+        params: &[SqlParam],
+        config: &QueryConfig,
+    ) -> Result<Option<usize>>;
+
+    // Arrow schema of the result of `sql`, without fetching rows, with each field's driver type
+    // under DB_TYPE_KEY where the transport reports one
+    fn schema(&self, sql: &str, params: &[SqlParam], config: &QueryConfig) -> Result<SchemaRef>;
+
+    // Runs `sql` and returns its batches with QueryConfig's fetch settings and per-batch
+    // transformations applied, or None when the statement has no result set. The reader owns
+    // its connection. `watchdog` enforces query_timeout; backends that cannot cancel a running
+    // statement should still call its check() between round trips.
+    fn batches(
+        &self,
+        sql: &str,
+        params: &[SqlParam],
+        config: &QueryConfig,
+        watchdog: &Watchdog,
+    ) -> Result<Option<BatchReader>>;
+
+    // What the server reports about itself
+    fn metadata(&self, config: &QueryConfig) -> Result<Metadata>;
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Metadata {
+    pub backend: &'static str,
+    // Product name, e.g. "Firebird" or "InterBase"
+    pub dbms_name: String,
+    // ENGINE_VERSION as numbers, e.g. [4, 0, 2]; None before Firebird 2.1 and on InterBase
+    pub server_version: Option<Vec<u32>>,
+}
+
+pub fn validate(name: Option<&str>) -> Result<()> {
+    match name.map(str::to_lowercase).as_deref() {
+        None | Some(ODBC) => Ok(()),
+        #[cfg(feature = "firebird")]
+        Some(FIREBIRD) => Ok(()),
+        #[cfg(not(feature = "firebird"))]
+        Some(FIREBIRD) => Err(anyhow!(
+            "The 'firebird' backend needs ibarrow built with the firebird feature"
+        )),
+        Some(other) => Err(anyhow!(
+            "Unknown backend '{}': expected 'odbc' or 'firebird'",
+            other
+        )),
+    }
+}
+
+// The backend QueryConfig.backend selects for a data source
+pub fn open(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    config: &QueryConfig,
+) -> Result<Box<dyn Backend>> {
+    validate(config.backend.as_deref())?;
+    match config.backend.as_deref().map(str::to_lowercase).as_deref() {
+        #[cfg(feature = "firebird")]
+        Some(FIREBIRD) => Ok(Box::new(crate::firebird::Firebird::new(
+            dsn, user, password, config,
+        )?)),
+        _ => Ok(Box::new(Odbc::new(dsn, user, password))),
+    }
+}
+
+// The InterBase/Firebird ODBC driver, through the driver manager
+#[derive(Debug, Clone)]
+pub struct Odbc {
+    dsn: String,
+    user: String,
+    password: String,
+}
+
+impl Odbc {
+    pub fn new(dsn: &str, user: &str, password: &str) -> Self {
+        Self {
+            dsn: dsn.to_string(),
+            user: user.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    fn connect<T>(
+        &self,
+        config: &QueryConfig,
+        work: impl Fn(&odbc_api::Connection<'static>) -> Result<T>,
+    ) -> Result<T> {
+        diagnostics::clear();
+        let env = odbc_api::environment()?;
+        let conn_str = build_connection_string(&self.dsn, &self.user, &self.password, config);
+        retry::with_retry(config, || {
+            work(&driverenv::connect(env, &conn_str, config)?)
+        })
+    }
+}
+
+impl Backend for Odbc {
+    fn name(&self) -> &'static str {
+        ODBC
+    }
+
+    fn execute(
+        &self,
+        sql: &str,
+        params: &[SqlParam],
+        config: &QueryConfig,
+    ) -> Result<Option<usize>> {
+        access::check(sql, config)?;
+        let statement = charset::transcode(sql, params, config.sql_charset.as_deref())?;
+        self.connect(config, |conn| {
+            let mut handle = conn.preallocate()?.into_handle();
+            statement.execute(&mut handle)?;
+            let count = handle.row_count().into_result(&handle)?;
+            Ok(usize::try_from(count).ok())
+        })
+    }
+
+    fn schema(&self, sql: &str, params: &[SqlParam], config: &QueryConfig) -> Result<SchemaRef> {
+        access::check(sql, config)?;
+        let statement = charset::transcode(sql, params, config.sql_charset.as_deref())?;
+        let conn_str = build_connection_string(&self.dsn, &self.user, &self.password, config);
+        self.connect(config, |conn| describe(conn, &conn_str, &statement, config))
+    }
+
+    fn batches(
+        &self,
+        sql: &str,
+        params: &[SqlParam],
+        config: &QueryConfig,
+        watchdog: &Watchdog,
+    ) -> Result<Option<BatchReader>> {
+        // Prefetching hands the cursor to another thread, so it must not borrow a local
+        // environment
+        let env = odbc_api::environment()?;
+        match open_cursor(
+            env,
+            &self.dsn,
+            &self.user,
+            &self.password,
+            sql,
+            params,
+            config,
+            watchdog,
+        )? {
+            Some(cursor) => Ok(Some(batch_reader(cursor, config)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn metadata(&self, config: &QueryConfig) -> Result<Metadata> {
+        self.connect(config, |conn| {
+            let dbms_name = conn.database_management_system_name()?;
+            Ok(Metadata {
+                backend: ODBC,
+                dbms_name,
+                server_version: engine_version(conn, config)?,
+            })
+        })
+    }
+}
+
+// The Arrow schema `statement` returns as ibarrow reads it, with each field's driver type under
+// the DB_TYPE_KEY metadata key. One prepare gives both, without fetching rows.
+pub fn describe(
+    conn: &odbc_api::Connection<'_>,
+    conn_str: &str,
+    statement: &Transcoded,
+    config: &QueryConfig,
+) -> Result<SchemaRef> {
+    let domains = domains::resolve(conn, conn_str, statement)?;
+    let driver = driver_schema(&mut statement.prepare(conn.preallocate()?.into_handle())?)?;
+    wrapped_schema(driver, &domains, config)
+}
+
+// The schema batches with `driver`'s schema have after wrap_batches, keeping each field's
+// DB_TYPE_KEY
+pub fn wrapped_schema(
+    driver: Schema,
+    domains: &Domains,
+    config: &QueryConfig,
+) -> Result<SchemaRef> {
+    // An empty reader through the same wrappers as fetched batches gives their schema
+    let empty = RecordBatchIterator::new(Vec::new(), Arc::new(driver.clone()));
+    // Padding only changes values, never the schema
+    let schema = wrap_batches(Box::new(empty), domains, &[], config)?.schema();
+    // The wrappers may rebuild fields, so the driver types are carried over by position
+    let fields: Vec<Field> = schema
+        .fields()
+        .iter()
+        .zip(driver.fields())
+        .map(|(field, described)| {
+            let mut metadata = field.metadata().clone();
+            metadata.extend(described.metadata().clone());
+            field.as_ref().clone().with_metadata(metadata)
+        })
+        .collect();
+    Ok(Arc::new(Schema::new_with_metadata(
+        fields,
+        schema.metadata().clone(),
+    )))
+}
+
+// The Arrow schema arrow-odbc maps a prepared statement's columns to, before ibarrow's
+// wrappers, with each field's driver type under DB_TYPE_KEY
+pub fn driver_schema(prepared: &mut impl ResultSetMetadata) -> Result<Schema> {
+    let db_types = (1..=prepared.num_result_cols()? as u16)
+        .map(|column| Ok(db_type(&prepared.col_data_type(column)?)))
+        .collect::<Result<Vec<_>>>()?;
+    let schema = arrow_odbc::arrow_schema_from(prepared, None, false)?;
+    let fields: Vec<Field> = schema
+        .fields()
+        .iter()
+        .zip(db_types)
+        .map(|(field, db_type)| {
+            let mut metadata = field.metadata().clone();
+            metadata.insert(DB_TYPE_KEY.to_string(), db_type);
+            field.as_ref().clone().with_metadata(metadata)
+        })
+        .collect();
+    Ok(Schema::new_with_metadata(fields, schema.metadata().clone()))
+}
+
+// Driver type of a column as its ODBC SQL type name, e.g. "VARCHAR(40)" or "NUMERIC(18, 2)"
+fn db_type(data_type: &odbc_api::DataType) -> String {
+    use odbc_api::DataType::*;
+    let sized = |name: &str, length: &Option<NonZeroUsize>| match length {
+        Some(length) => format!("{}({})", name, length),
+        None => name.to_string(),
+    };
+    match data_type {
+        Unknown => "UNKNOWN".to_string(),
+        Char { length } => sized("CHAR", length),
+        WChar { length } => sized("WCHAR", length),
+        Varchar { length } => sized("VARCHAR", length),
+        WVarchar { length } => sized("WVARCHAR", length),
+        LongVarchar { length } => sized("LONGVARCHAR", length),
+        WLongVarchar { length } => sized("WLONGVARCHAR", length),
+        Binary { length } => sized("BINARY", length),
+        Varbinary { length } => sized("VARBINARY", length),
+        LongVarbinary { length } => sized("LONGVARBINARY", length),
+        Numeric { precision, scale } => format!("NUMERIC({}, {})", precision, scale),
+        Decimal { precision, scale } => format!("DECIMAL({}, {})", precision, scale),
+        Integer => "INTEGER".to_string(),
+        SmallInt => "SMALLINT".to_string(),
+        BigInt => "BIGINT".to_string(),
+        TinyInt => "TINYINT".to_string(),
+        Bit => "BIT".to_string(),
+        Float { precision } => format!("FLOAT({})", precision),
+        Real => "REAL".to_string(),
+        Double => "DOUBLE".to_string(),
+        Date => "DATE".to_string(),
+        Time { .. } => "TIME".to_string(),
+        Timestamp { .. } => "TIMESTAMP".to_string(),
+        Other { data_type, .. } => format!("SQL type {}", data_type.0),
+    }
+}
+
+// Server version from the ENGINE_VERSION context variable (Firebird 2.1+)
+fn engine_version(
+    conn: &odbc_api::Connection<'_>,
+    config: &QueryConfig,
+) -> Result<Option<Vec<u32>>> {
+    let sql = "SELECT RDB$GET_CONTEXT('SYSTEM', 'ENGINE_VERSION') FROM RDB$DATABASE";
+    // InterBase has no RDB$GET_CONTEXT
+    let Ok(Some(cursor)) = conn.execute(sql, (), None) else {
+        return Ok(None);
+    };
+    let Some(batch) = crate::build_reader(cursor, config)?.next().transpose()? else {
+        return Ok(None);
+    };
+    let text = cast(batch.column(0), &DataType::Utf8)?;
+    let text = text.as_string::<i32>();
+    if text.is_empty() || text.is_null(0) {
+        return Ok(None);
+    }
+    parse_version(text.value(0)).map(Some)
+}
+
+// ENGINE_VERSION text, e.g. "4.0.2", as numbers
+pub fn parse_version(version: &str) -> Result<Vec<u32>> {
+    version
+        .split('.')
+        .map(|part| {
+            part.trim()
+                .parse()
+                .map_err(|_| anyhow!("Unexpected ENGINE_VERSION '{}'", version))
+        })
+        .collect()
+}
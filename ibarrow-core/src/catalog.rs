@@ -0,0 +1,38 @@
+use anyhow::Result;
+use odbc_api::{Connection, Cursor, IntoParameter};
+
+const TABLE_EXISTS_SQL: &str =
+    "SELECT RDB$RELATION_NAME FROM RDB$RELATIONS WHERE RDB$RELATION_NAME = ?";
+
+// First column of every row, as text
+pub fn fetch_texts(conn: &Connection<'_>, sql: &str, param: Option<&str>) -> Result<Vec<String>> {
+    fetch_column(conn, sql, param, 1)
+}
+
+// Like fetch_texts, for the 1-based `column` of each row
+pub fn fetch_column(
+    conn: &Connection<'_>,
+    sql: &str,
+    param: Option<&str>,
+    column: u16,
+) -> Result<Vec<String>> {
+    let cursor = match param {
+        Some(value) => conn.execute(sql, &value.into_parameter(), None)?,
+        None => conn.execute(sql, (), None)?,
+    };
+    let mut texts = Vec::new();
+    if let Some(mut cursor) = cursor {
+        let mut buf = Vec::new();
+        while let Some(mut row) = cursor.next_row()? {
+            if row.get_text(column, &mut buf)? {
+                texts.push(String::from_utf8_lossy(&buf).trim().to_string());
+            }
+        }
+    }
+    Ok(texts)
+}
+
+// Whether a table or view is stored under exactly the name `table`
+pub fn table_exists(conn: &Connection<'_>, table: &str) -> Result<bool> {
+    Ok(!fetch_texts(conn, TABLE_EXISTS_SQL, Some(table))?.is_empty())
+}
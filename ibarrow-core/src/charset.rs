@@ -0,0 +1,201 @@
+use anyhow::{anyhow, Result};
+use encoding_rs::{Encoding, UTF_8};
+use odbc_api::handles::{AsStatementRef, SqlResult, SqlText, Statement, StatementRef};
+use odbc_api::sys::{Integer, SQLExecDirect, SQLPrepare, SqlReturn};
+use odbc_api::{CursorImpl, Preallocated, ResultSetMetadata};
+
+use crate::params::{self, SqlParam};
+
+// Resolves a Firebird/InterBase character set name (or any WHATWG label) to an encoding.
+// Returns None when no transcoding is needed.
+pub fn session_encoding(charset: &str) -> Result<Option<&'static Encoding>> {
+    let upper = charset.trim().to_uppercase();
+    let label = if upper == "NONE" || upper == "UTF8" || upper == "UNICODE_FSS" {
+        return Ok(None);
+    } else if let Some(code_page) = upper.strip_prefix("WIN") {
+        format!("windows-{}", code_page)
+    } else if let Some(part) = upper.strip_prefix("ISO8859_") {
+        format!("iso-8859-{}", part)
+    } else if upper == "KOI8R" {
+        "koi8-r".to_string()
+    } else if upper == "KOI8U" {
+        "koi8-u".to_string()
+    } else {
+        charset.to_string()
+    };
+    let encoding = Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| anyhow!("Unsupported sql_charset '{}'", charset))?;
+    Ok(if encoding == UTF_8 {
+        None
+    } else {
+        Some(encoding)
+    })
+}
+
+fn encode(text: &str, encoding: &'static Encoding) -> Result<Vec<u8>> {
+    let (bytes, _, unmappable) = encoding.encode(text);
+    if unmappable {
+        return Err(anyhow!(
+            "Text '{}' cannot be represented in {}",
+            text,
+            encoding.name()
+        ));
+    }
+    Ok(bytes.into_owned())
+}
+
+// A statement ready for a session charset. Without one the text goes through odbc-api as
+// UTF-8; with one it is sent as bytes in the session encoding, so string literals reach the
+// server in its charset, and text parameters are encoded the same way.
+pub struct Transcoded {
+    pub sql: String,
+    pub params: Vec<SqlParam>,
+    encoding: Option<&'static Encoding>,
+    text: Option<Vec<u8>>,
+}
+
+pub fn transcode(sql: &str, params: &[SqlParam], charset: Option<&str>) -> Result<Transcoded> {
+    let encoding = match charset {
+        Some(charset) => session_encoding(charset)?,
+        None => None,
+    };
+    Ok(Transcoded {
+        sql: sql.to_string(),
+        params: encode_params(params, encoding)?,
+        encoding,
+        text: encoding.map(|encoding| encode(sql, encoding)).transpose()?,
+    })
+}
+
+fn encode_params(
+    params: &[SqlParam],
+    encoding: Option<&'static Encoding>,
+) -> Result<Vec<SqlParam>> {
+    params
+        .iter()
+        .map(|param| match (param, encoding) {
+            (SqlParam::Text(text), Some(encoding)) => {
+                Ok(SqlParam::Encoded(encode(text, encoding)?))
+            }
+            (other, _) => Ok(other.clone()),
+        })
+        .collect()
+}
+
+// Maps a raw return code the way odbc-api's own calls do
+fn sql_result(ret: SqlReturn, function: &'static str) -> SqlResult<()> {
+    match ret {
+        SqlReturn::SUCCESS => SqlResult::Success(()),
+        SqlReturn::SUCCESS_WITH_INFO => SqlResult::SuccessWithInfo(()),
+        SqlReturn::NO_DATA => SqlResult::NoData,
+        SqlReturn::NEED_DATA => SqlResult::NeedData,
+        SqlReturn::STILL_EXECUTING => SqlResult::StillExecuting,
+        _ => SqlResult::Error { function },
+    }
+}
+
+// Binds `inputs` to the statement's parameter markers, in order
+fn bind(
+    stmt: &mut StatementRef<'_>,
+    inputs: &[Box<dyn odbc_api::parameter::InputParameter>],
+) -> Result<()> {
+    for (index, input) in inputs.iter().enumerate() {
+        unsafe { stmt.bind_input_parameter(index as u16 + 1, input.as_ref()) }.into_result(stmt)?;
+    }
+    Ok(())
+}
+
+// A cursor over the result of the statement just executed on `statement`, if it has one
+fn cursor<S: AsStatementRef>(mut statement: S) -> Result<Option<CursorImpl<S>>> {
+    let mut stmt = statement.as_stmt_ref();
+    let columns = stmt.num_result_cols().into_result(&stmt)?;
+    // The statement has just been executed and has a result set
+    Ok((columns > 0).then(|| unsafe { CursorImpl::new(statement) }))
+}
+
+impl Transcoded {
+    // Parameters of another execution, encoded like the statement's own
+    pub fn encode(&self, params: &[SqlParam]) -> Result<Vec<SqlParam>> {
+        encode_params(params, self.encoding)
+    }
+
+    // Executes the statement directly on a freshly allocated `statement`
+    pub fn execute<S: AsStatementRef>(&self, mut statement: S) -> Result<Option<CursorImpl<S>>> {
+        let inputs = params::to_inputs(&self.params);
+        let Some(text) = &self.text else {
+            // The handle comes straight from allocation, with nothing bound yet
+            let preallocated = unsafe { Preallocated::new(statement) };
+            return Ok(preallocated.into_cursor(&self.sql, inputs.as_slice())?);
+        };
+        let mut stmt = statement.as_stmt_ref();
+        bind(&mut stmt, &inputs)?;
+        let ret = unsafe { SQLExecDirect(stmt.as_sys(), text.as_ptr(), text.len() as Integer) };
+        let executed = sql_result(ret, "SQLExecDirect").into_result_bool(&stmt);
+        stmt.reset_parameters().into_result(&stmt)?;
+        if !executed? {
+            return Ok(None);
+        }
+        cursor(statement)
+    }
+
+    // SQLExecDirect of the text on a statement whose parameters the caller has bound and keeps
+    // alive until execution completes, for callers polling asynchronous execution. Returns the
+    // raw result, as odbc-api's exec_direct does.
+    pub fn exec_direct(&self, stmt: &mut impl Statement) -> SqlResult<()> {
+        match &self.text {
+            Some(text) => sql_result(
+                unsafe { SQLExecDirect(stmt.as_sys(), text.as_ptr(), text.len() as Integer) },
+                "SQLExecDirect",
+            ),
+            None => unsafe { stmt.exec_direct(&SqlText::new(&self.sql)) },
+        }
+    }
+
+    // Prepares the statement on `statement` without executing it, to describe its result or
+    // run it once per parameter set
+    pub fn prepare<S: AsStatementRef>(&self, mut statement: S) -> Result<Prepared<S>> {
+        let mut stmt = statement.as_stmt_ref();
+        match &self.text {
+            Some(text) => {
+                let ret =
+                    unsafe { SQLPrepare(stmt.as_sys(), text.as_ptr(), text.len() as Integer) };
+                sql_result(ret, "SQLPrepare").into_result(&stmt)?
+            }
+            None => stmt.prepare(&SqlText::new(&self.sql)).into_result(&stmt)?,
+        }
+        Ok(Prepared(statement))
+    }
+}
+
+// A statement prepared by Transcoded::prepare
+pub struct Prepared<S>(S);
+
+impl<S: AsStatementRef> AsStatementRef for Prepared<S> {
+    fn as_stmt_ref(&mut self) -> StatementRef<'_> {
+        self.0.as_stmt_ref()
+    }
+}
+
+impl<S: AsStatementRef> ResultSetMetadata for Prepared<S> {}
+
+impl<S: AsStatementRef> Prepared<S> {
+    // Executes the statement with already encoded `params`
+    pub fn execute(&mut self, params: &[SqlParam]) -> Result<Option<CursorImpl<StatementRef<'_>>>> {
+        let inputs = params::to_inputs(params);
+        let mut stmt = self.0.as_stmt_ref();
+        bind(&mut stmt, &inputs)?;
+        let executed = unsafe { stmt.execute() }.into_result_bool(&stmt);
+        stmt.reset_parameters().into_result(&stmt)?;
+        if !executed? {
+            return Ok(None);
+        }
+        cursor(self.0.as_stmt_ref())
+    }
+
+    // Rows affected by the last execution, if the driver knows
+    pub fn row_count(&mut self) -> Result<Option<usize>> {
+        let mut stmt = self.0.as_stmt_ref();
+        let count = stmt.row_count().into_result(&stmt)?;
+        Ok(usize::try_from(count).ok())
+    }
+}
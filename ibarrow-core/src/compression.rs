@@ -0,0 +1,40 @@
+use anyhow::{anyhow, Result};
+use arrow_ipc::writer::IpcWriteOptions;
+use arrow_ipc::CompressionType;
+
+use crate::QueryConfig;
+
+// Parses QueryConfig.ipc_compression; `auto` counts as no compression here
+fn codec(setting: Option<&str>) -> Result<Option<CompressionType>> {
+    match setting.map(|s| s.to_lowercase()).as_deref() {
+        None | Some("none") | Some("auto") => Ok(None),
+        Some("lz4") => Ok(Some(CompressionType::LZ4_FRAME)),
+        Some("zstd") => Ok(Some(CompressionType::ZSTD)),
+        Some(other) => Err(anyhow!(
+            "Unsupported ipc_compression '{}': use auto, none, lz4 or zstd",
+            other
+        )),
+    }
+}
+
+// Write options for the IPC streams handed to Python
+pub fn write_options(config: &QueryConfig) -> Result<IpcWriteOptions> {
+    Ok(IpcWriteOptions::default()
+        .try_with_compression(codec(config.ipc_compression.as_deref())?)?)
+}
+
+// Write options for a codec named by the caller: none, lz4 or zstd
+pub fn named_options(name: &str) -> Result<IpcWriteOptions> {
+    let codec = match name.to_lowercase().as_str() {
+        "none" | "uncompressed" => None,
+        "lz4" => Some(CompressionType::LZ4_FRAME),
+        "zstd" => Some(CompressionType::ZSTD),
+        other => {
+            return Err(anyhow!(
+                "Unknown IPC compression '{}': expected 'none', 'lz4' or 'zstd'",
+                other
+            ))
+        }
+    };
+    Ok(IpcWriteOptions::default().try_with_compression(codec)?)
+}
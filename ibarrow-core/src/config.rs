@@ -0,0 +1,569 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::progress::ProgressHook;
+use crate::{
+    adaptive, arrays, backend, context, driverenv, drivermgr, emptystr, interop, restart, snapshot,
+    txwatch,
+};
+
+// Options for connecting and fetching. With the `python` feature every field is readable from
+// Python; the setters, which validate where needed, are in the `python` module below.
+#[cfg_attr(feature = "python", pyo3::pyclass(get_all))]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryConfig {
+    pub batch_size: Option<u32>,
+    pub max_text_size: Option<u32>,
+    pub max_binary_size: Option<u32>,
+    pub read_only: bool,
+    pub connection_timeout: Option<u32>,
+    pub query_timeout: Option<u32>,
+    pub isolation_level: Option<String>,
+    pub max_restarts: Option<u32>,
+    pub restart_key: Option<String>,
+    pub sql_charset: Option<String>,
+    pub retry_attempts: Option<u32>,
+    pub retry_backoff_ms: Option<u64>,
+    pub retry_sqlstates: Option<Vec<String>>,
+    pub access_role: Option<String>,
+    pub ipc_compression: Option<String>,
+    pub max_rows: Option<u64>,
+    // Hooks cannot be serialized
+    #[serde(skip)]
+    pub progress: Option<ProgressHook>,
+    pub prefetch: bool,
+    pub max_bytes_per_batch: Option<u64>,
+    pub worker_process: bool,
+    pub driver_manager: Option<String>,
+    pub spill_threshold: Option<u64>,
+    pub spill_dir: Option<String>,
+    pub driver_env: Option<HashMap<String, String>>,
+    pub dictionary_columns: Option<Vec<String>>,
+    pub dictionaries: Option<HashMap<String, Vec<String>>>,
+    pub empty_as_null: bool,
+    pub null_as_empty: bool,
+    pub snapshot_number: Option<i64>,
+    pub target_batch_bytes: Option<u64>,
+    pub adaptive_fetch: bool,
+    pub storage_options: Option<HashMap<String, String>>,
+    pub array_columns: Option<HashMap<String, String>>,
+    pub max_transaction_age: Option<u64>,
+    pub transaction_age_action: Option<String>,
+    pub transaction_refresh_secs: Option<u64>,
+    pub session_context: Option<HashMap<String, HashMap<String, String>>>,
+    pub arrow_interop: Option<String>,
+    pub backend: Option<String>,
+}
+
+#[cfg(feature = "python")]
+mod python {
+    use pyo3::exceptions::PyValueError;
+    use pyo3::prelude::*;
+
+    use super::*;
+
+    fn invalid(e: anyhow::Error) -> PyErr {
+        PyValueError::new_err(e.to_string())
+    }
+
+    #[pymethods]
+    impl QueryConfig {
+        #[new]
+        #[allow(clippy::too_many_arguments)]
+        fn new(
+            batch_size: Option<u32>,
+            max_text_size: Option<u32>,
+            max_binary_size: Option<u32>,
+            read_only: Option<bool>,
+            connection_timeout: Option<u32>,
+            query_timeout: Option<u32>,
+            isolation_level: Option<String>,
+            max_restarts: Option<u32>,
+            restart_key: Option<String>,
+            sql_charset: Option<String>,
+            retry_attempts: Option<u32>,
+            retry_backoff_ms: Option<u64>,
+            retry_sqlstates: Option<Vec<String>>,
+            access_role: Option<String>,
+            ipc_compression: Option<String>,
+            max_rows: Option<u64>,
+            progress: Option<ProgressHook>,
+            prefetch: Option<bool>,
+            max_bytes_per_batch: Option<u64>,
+            worker_process: Option<bool>,
+            driver_manager: Option<String>,
+            spill_threshold: Option<u64>,
+            spill_dir: Option<String>,
+            driver_env: Option<HashMap<String, String>>,
+            dictionary_columns: Option<Vec<String>>,
+            dictionaries: Option<HashMap<String, Vec<String>>>,
+            empty_as_null: Option<bool>,
+            null_as_empty: Option<bool>,
+            snapshot_number: Option<i64>,
+            target_batch_bytes: Option<u64>,
+            adaptive_fetch: Option<bool>,
+            storage_options: Option<HashMap<String, String>>,
+            array_columns: Option<HashMap<String, String>>,
+            max_transaction_age: Option<u64>,
+            transaction_age_action: Option<String>,
+            transaction_refresh_secs: Option<u64>,
+            session_context: Option<HashMap<String, HashMap<String, String>>>,
+            arrow_interop: Option<String>,
+            backend: Option<String>,
+        ) -> PyResult<Self> {
+            let driver_manager = normalize_driver_manager(driver_manager).map_err(invalid)?;
+            let config = Self {
+                batch_size,
+                max_text_size,
+                max_binary_size,
+                read_only: read_only.unwrap_or(false),
+                connection_timeout,
+                query_timeout,
+                isolation_level,
+                max_restarts,
+                restart_key,
+                sql_charset,
+                retry_attempts,
+                retry_backoff_ms,
+                retry_sqlstates,
+                access_role,
+                ipc_compression,
+                max_rows,
+                progress,
+                prefetch: prefetch.unwrap_or(false),
+                max_bytes_per_batch,
+                worker_process: worker_process.unwrap_or(false),
+                driver_manager,
+                spill_threshold,
+                spill_dir,
+                driver_env,
+                dictionary_columns,
+                dictionaries,
+                empty_as_null: empty_as_null.unwrap_or(false),
+                null_as_empty: null_as_empty.unwrap_or(false),
+                snapshot_number,
+                target_batch_bytes,
+                adaptive_fetch: adaptive_fetch.unwrap_or(false),
+                storage_options,
+                array_columns,
+                max_transaction_age,
+                transaction_age_action,
+                transaction_refresh_secs,
+                session_context,
+                arrow_interop,
+                backend: normalize_backend(backend).map_err(invalid)?,
+            };
+            validate_config(&config).map_err(invalid)?;
+            Ok(config)
+        }
+
+        #[setter]
+        fn set_max_text_size(&mut self, max_text_size: Option<u32>) {
+            self.max_text_size = max_text_size;
+        }
+
+        #[setter]
+        fn set_max_binary_size(&mut self, max_binary_size: Option<u32>) {
+            self.max_binary_size = max_binary_size;
+        }
+
+        #[setter]
+        fn set_read_only(&mut self, read_only: bool) {
+            self.read_only = read_only;
+        }
+
+        #[setter]
+        fn set_connection_timeout(&mut self, connection_timeout: Option<u32>) {
+            self.connection_timeout = connection_timeout;
+        }
+
+        #[setter]
+        fn set_query_timeout(&mut self, query_timeout: Option<u32>) {
+            self.query_timeout = query_timeout;
+        }
+
+        #[setter]
+        fn set_isolation_level(&mut self, isolation_level: Option<String>) {
+            self.isolation_level = isolation_level;
+        }
+
+        #[setter]
+        fn set_max_restarts(&mut self, max_restarts: Option<u32>) {
+            self.max_restarts = max_restarts;
+        }
+
+        #[setter]
+        fn set_restart_key(&mut self, restart_key: Option<String>) {
+            self.restart_key = restart_key;
+        }
+
+        #[setter]
+        fn set_sql_charset(&mut self, sql_charset: Option<String>) {
+            self.sql_charset = sql_charset;
+        }
+
+        #[setter]
+        fn set_retry_attempts(&mut self, retry_attempts: Option<u32>) {
+            self.retry_attempts = retry_attempts;
+        }
+
+        #[setter]
+        fn set_retry_backoff_ms(&mut self, retry_backoff_ms: Option<u64>) {
+            self.retry_backoff_ms = retry_backoff_ms;
+        }
+
+        #[setter]
+        fn set_retry_sqlstates(&mut self, retry_sqlstates: Option<Vec<String>>) {
+            self.retry_sqlstates = retry_sqlstates;
+        }
+
+        #[setter]
+        fn set_access_role(&mut self, access_role: Option<String>) {
+            self.access_role = access_role;
+        }
+
+        #[setter]
+        fn set_ipc_compression(&mut self, ipc_compression: Option<String>) {
+            self.ipc_compression = ipc_compression;
+        }
+
+        #[setter]
+        fn set_max_rows(&mut self, max_rows: Option<u64>) {
+            self.max_rows = max_rows;
+        }
+
+        #[setter]
+        fn set_progress(&mut self, progress: Option<ProgressHook>) {
+            self.progress = progress;
+        }
+
+        #[setter]
+        fn set_prefetch(&mut self, prefetch: bool) {
+            self.prefetch = prefetch;
+        }
+
+        #[setter]
+        fn set_worker_process(&mut self, worker_process: bool) {
+            self.worker_process = worker_process;
+        }
+
+        #[setter]
+        fn set_spill_threshold(&mut self, spill_threshold: Option<u64>) {
+            self.spill_threshold = spill_threshold;
+        }
+
+        #[setter]
+        fn set_spill_dir(&mut self, spill_dir: Option<String>) {
+            self.spill_dir = spill_dir;
+        }
+
+        #[setter]
+        fn set_dictionary_columns(&mut self, dictionary_columns: Option<Vec<String>>) {
+            self.dictionary_columns = dictionary_columns;
+        }
+
+        #[setter]
+        fn set_dictionaries(&mut self, dictionaries: Option<HashMap<String, Vec<String>>>) {
+            self.dictionaries = dictionaries;
+        }
+
+        #[setter]
+        fn set_empty_as_null(&mut self, empty_as_null: bool) {
+            self.empty_as_null = empty_as_null;
+        }
+
+        #[setter]
+        fn set_null_as_empty(&mut self, null_as_empty: bool) {
+            self.null_as_empty = null_as_empty;
+        }
+
+        #[setter]
+        fn set_adaptive_fetch(&mut self, adaptive_fetch: bool) {
+            self.adaptive_fetch = adaptive_fetch;
+        }
+
+        #[setter]
+        fn set_storage_options(&mut self, storage_options: Option<HashMap<String, String>>) {
+            self.storage_options = storage_options;
+        }
+
+        #[setter]
+        fn set_batch_size(&mut self, batch_size: Option<u32>) -> PyResult<()> {
+            validate_batch_size(batch_size).map_err(invalid)?;
+            self.batch_size = batch_size;
+            Ok(())
+        }
+
+        #[setter]
+        fn set_max_bytes_per_batch(&mut self, max_bytes_per_batch: Option<u64>) -> PyResult<()> {
+            validate_max_bytes_per_batch(max_bytes_per_batch).map_err(invalid)?;
+            self.max_bytes_per_batch = max_bytes_per_batch;
+            Ok(())
+        }
+
+        #[setter]
+        fn set_driver_manager(&mut self, driver_manager: Option<String>) -> PyResult<()> {
+            self.driver_manager = normalize_driver_manager(driver_manager).map_err(invalid)?;
+            Ok(())
+        }
+
+        #[setter]
+        fn set_driver_env(&mut self, driver_env: Option<HashMap<String, String>>) -> PyResult<()> {
+            validate_driver_env(driver_env.as_ref()).map_err(invalid)?;
+            self.driver_env = driver_env;
+            Ok(())
+        }
+
+        #[setter]
+        fn set_snapshot_number(&mut self, snapshot_number: Option<i64>) -> PyResult<()> {
+            validate_snapshot_number(snapshot_number).map_err(invalid)?;
+            self.snapshot_number = snapshot_number;
+            Ok(())
+        }
+
+        #[setter]
+        fn set_target_batch_bytes(&mut self, target_batch_bytes: Option<u64>) -> PyResult<()> {
+            validate_target_batch_bytes(target_batch_bytes).map_err(invalid)?;
+            self.target_batch_bytes = target_batch_bytes;
+            Ok(())
+        }
+
+        #[setter]
+        fn set_array_columns(
+            &mut self,
+            array_columns: Option<HashMap<String, String>>,
+        ) -> PyResult<()> {
+            validate_array_columns(array_columns.clone()).map_err(invalid)?;
+            self.array_columns = array_columns;
+            Ok(())
+        }
+
+        #[setter]
+        fn set_max_transaction_age(&mut self, max_transaction_age: Option<u64>) -> PyResult<()> {
+            validate_transaction_age(max_transaction_age, self.transaction_age_action.clone())
+                .map_err(invalid)?;
+            self.max_transaction_age = max_transaction_age;
+            Ok(())
+        }
+
+        #[setter]
+        fn set_transaction_age_action(
+            &mut self,
+            transaction_age_action: Option<String>,
+        ) -> PyResult<()> {
+            validate_transaction_age(self.max_transaction_age, transaction_age_action.clone())
+                .map_err(invalid)?;
+            self.transaction_age_action = transaction_age_action;
+            Ok(())
+        }
+
+        #[setter]
+        fn set_transaction_refresh_secs(
+            &mut self,
+            transaction_refresh_secs: Option<u64>,
+        ) -> PyResult<()> {
+            validate_transaction_refresh(transaction_refresh_secs).map_err(invalid)?;
+            self.transaction_refresh_secs = transaction_refresh_secs;
+            Ok(())
+        }
+
+        #[setter]
+        fn set_session_context(
+            &mut self,
+            session_context: Option<HashMap<String, HashMap<String, String>>>,
+        ) -> PyResult<()> {
+            validate_session_context(session_context.as_ref()).map_err(invalid)?;
+            self.session_context = session_context;
+            Ok(())
+        }
+
+        #[setter]
+        fn set_arrow_interop(&mut self, arrow_interop: Option<String>) -> PyResult<()> {
+            validate_arrow_interop(arrow_interop.clone(), self.spill_threshold).map_err(invalid)?;
+            self.arrow_interop = arrow_interop;
+            Ok(())
+        }
+
+        #[setter]
+        fn set_backend(&mut self, backend: Option<String>) -> PyResult<()> {
+            self.backend = normalize_backend(backend).map_err(invalid)?;
+            Ok(())
+        }
+    }
+}
+
+// Checks every validated field, also for configs that did not come through the constructor
+pub fn validate_config(config: &QueryConfig) -> Result<()> {
+    validate_batch_size(config.batch_size)?;
+    validate_max_bytes_per_batch(config.max_bytes_per_batch)?;
+    normalize_driver_manager(config.driver_manager.clone())?;
+    validate_driver_env(config.driver_env.as_ref())?;
+    validate_snapshot_number(config.snapshot_number)?;
+    validate_target_batch_bytes(config.target_batch_bytes)?;
+    validate_array_columns(config.array_columns.clone())?;
+    validate_transaction_age(
+        config.max_transaction_age,
+        config.transaction_age_action.clone(),
+    )?;
+    validate_transaction_refresh(config.transaction_refresh_secs)?;
+    validate_session_context(config.session_context.as_ref())?;
+    validate_arrow_interop(config.arrow_interop.clone(), config.spill_threshold)?;
+    normalize_backend(config.backend.clone())?;
+    emptystr::validate(config)
+}
+
+impl QueryConfig {
+    // `over` laid on top of this config: its settings win where it sets them, and switches
+    // it turns on stay on. The access role and session context guard the data, so `over`
+    // can only add to them: this config's role and context values are kept.
+    pub fn overlaid(&self, over: &QueryConfig) -> QueryConfig {
+        fn pick<T: Clone>(over: &Option<T>, base: &Option<T>) -> Option<T> {
+            over.as_ref().or(base.as_ref()).cloned()
+        }
+        let session_context = match (&self.session_context, &over.session_context) {
+            (Some(base), Some(over)) => {
+                let mut merged = over.clone();
+                for (namespace, values) in base {
+                    merged
+                        .entry(namespace.clone())
+                        .or_default()
+                        .extend(values.clone());
+                }
+                Some(merged)
+            }
+            (base, over) => pick(over, base),
+        };
+        QueryConfig {
+            batch_size: pick(&over.batch_size, &self.batch_size),
+            max_text_size: pick(&over.max_text_size, &self.max_text_size),
+            max_binary_size: pick(&over.max_binary_size, &self.max_binary_size),
+            read_only: over.read_only || self.read_only,
+            connection_timeout: pick(&over.connection_timeout, &self.connection_timeout),
+            query_timeout: pick(&over.query_timeout, &self.query_timeout),
+            isolation_level: pick(&over.isolation_level, &self.isolation_level),
+            max_restarts: pick(&over.max_restarts, &self.max_restarts),
+            restart_key: pick(&over.restart_key, &self.restart_key),
+            sql_charset: pick(&over.sql_charset, &self.sql_charset),
+            retry_attempts: pick(&over.retry_attempts, &self.retry_attempts),
+            retry_backoff_ms: pick(&over.retry_backoff_ms, &self.retry_backoff_ms),
+            retry_sqlstates: pick(&over.retry_sqlstates, &self.retry_sqlstates),
+            access_role: pick(&self.access_role, &over.access_role),
+            ipc_compression: pick(&over.ipc_compression, &self.ipc_compression),
+            max_rows: pick(&over.max_rows, &self.max_rows),
+            progress: pick(&over.progress, &self.progress),
+            prefetch: over.prefetch || self.prefetch,
+            max_bytes_per_batch: pick(&over.max_bytes_per_batch, &self.max_bytes_per_batch),
+            worker_process: over.worker_process || self.worker_process,
+            driver_manager: pick(&over.driver_manager, &self.driver_manager),
+            spill_threshold: pick(&over.spill_threshold, &self.spill_threshold),
+            spill_dir: pick(&over.spill_dir, &self.spill_dir),
+            driver_env: pick(&over.driver_env, &self.driver_env),
+            dictionary_columns: pick(&over.dictionary_columns, &self.dictionary_columns),
+            dictionaries: pick(&over.dictionaries, &self.dictionaries),
+            empty_as_null: over.empty_as_null || self.empty_as_null,
+            null_as_empty: over.null_as_empty || self.null_as_empty,
+            snapshot_number: pick(&over.snapshot_number, &self.snapshot_number),
+            target_batch_bytes: pick(&over.target_batch_bytes, &self.target_batch_bytes),
+            adaptive_fetch: over.adaptive_fetch || self.adaptive_fetch,
+            storage_options: pick(&over.storage_options, &self.storage_options),
+            array_columns: pick(&over.array_columns, &self.array_columns),
+            max_transaction_age: pick(&over.max_transaction_age, &self.max_transaction_age),
+            transaction_age_action: pick(
+                &over.transaction_age_action,
+                &self.transaction_age_action,
+            ),
+            transaction_refresh_secs: pick(
+                &over.transaction_refresh_secs,
+                &self.transaction_refresh_secs,
+            ),
+            session_context,
+            arrow_interop: pick(&over.arrow_interop, &self.arrow_interop),
+            backend: pick(&over.backend, &self.backend),
+        }
+    }
+}
+
+fn validate_batch_size(batch_size: Option<u32>) -> Result<()> {
+    if batch_size == Some(0) {
+        return Err(anyhow!("batch_size must be greater than 0"));
+    }
+    Ok(())
+}
+
+fn validate_max_bytes_per_batch(max_bytes_per_batch: Option<u64>) -> Result<()> {
+    if max_bytes_per_batch == Some(0) {
+        return Err(anyhow!("max_bytes_per_batch must be greater than 0"));
+    }
+    Ok(())
+}
+
+fn normalize_driver_manager(driver_manager: Option<String>) -> Result<Option<String>> {
+    let driver_manager = driver_manager.map(|name| name.to_lowercase());
+    drivermgr::validate(driver_manager.as_deref())?;
+    Ok(driver_manager)
+}
+
+fn validate_driver_env(driver_env: Option<&HashMap<String, String>>) -> Result<()> {
+    if let Some(driver_env) = driver_env {
+        driverenv::validate(driver_env)?;
+    }
+    Ok(())
+}
+
+fn validate_snapshot_number(snapshot_number: Option<i64>) -> Result<()> {
+    snapshot::validate(snapshot_number)
+}
+
+fn validate_target_batch_bytes(target_batch_bytes: Option<u64>) -> Result<()> {
+    adaptive::validate(target_batch_bytes)
+}
+
+fn validate_array_columns(array_columns: Option<HashMap<String, String>>) -> Result<()> {
+    let config = QueryConfig {
+        array_columns,
+        ..Default::default()
+    };
+    arrays::validate(&config)
+}
+
+fn validate_transaction_age(
+    max_transaction_age: Option<u64>,
+    transaction_age_action: Option<String>,
+) -> Result<()> {
+    let config = QueryConfig {
+        max_transaction_age,
+        transaction_age_action,
+        ..Default::default()
+    };
+    txwatch::validate(&config)
+}
+
+fn validate_transaction_refresh(transaction_refresh_secs: Option<u64>) -> Result<()> {
+    restart::validate_refresh(transaction_refresh_secs)
+}
+
+fn validate_session_context(
+    session_context: Option<&HashMap<String, HashMap<String, String>>>,
+) -> Result<()> {
+    context::validate(session_context)
+}
+
+fn validate_arrow_interop(
+    arrow_interop: Option<String>,
+    spill_threshold: Option<u64>,
+) -> Result<()> {
+    let config = QueryConfig {
+        arrow_interop,
+        spill_threshold,
+        ..Default::default()
+    };
+    interop::validate(&config)
+}
+
+fn normalize_backend(backend: Option<String>) -> Result<Option<String>> {
+    let backend = backend.map(|name| name.to_lowercase());
+    backend::validate(backend.as_deref())?;
+    Ok(backend)
+}
@@ -0,0 +1,199 @@
+use anyhow::Result;
+use arrow::datatypes::{Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::record_batch::{RecordBatch, RecordBatchIterator, RecordBatchReader};
+use std::io::Write;
+use std::sync::Arc;
+
+use crate::backend::{self, Backend, Metadata};
+use crate::params::SqlParam;
+use crate::progress::Progress;
+use crate::sink::{BatchSink, Collected, IpcSink};
+use crate::watchdog::Watchdog;
+use crate::{
+    compression, diagnostics, drivermgr, fetch_from, rows_left, trim_batch, validate_config,
+    BatchReader, QueryConfig,
+};
+
+// A data source to query. Like the Python IbarrowConnection it holds no open connection:
+// every query connects, reads and disconnects through its backend.
+#[derive(Clone)]
+pub struct Connection {
+    backend: Arc<dyn Backend>,
+    config: QueryConfig,
+}
+
+// Checks `config` and the ODBC driver manager and returns a Connection for `dsn`, which is
+// either a DSN name, a database path or a full ODBC connection string. QueryConfig.backend
+// picks the transport.
+pub fn connect(dsn: &str, user: &str, password: &str, config: QueryConfig) -> Result<Connection> {
+    validate_config(&config)?;
+    diagnostics::install();
+    drivermgr::check(config.driver_manager.as_deref())?;
+    let backend = backend::open(dsn, user, password, &config)?;
+    Ok(Connection {
+        backend: backend.into(),
+        config,
+    })
+}
+
+impl Connection {
+    // A Connection reading through a backend of the caller's own
+    pub fn with_backend(backend: Arc<dyn Backend>, config: QueryConfig) -> Result<Self> {
+        validate_config(&config)?;
+        Ok(Self { backend, config })
+    }
+
+    pub fn config(&self) -> &QueryConfig {
+        &self.config
+    }
+
+    pub fn backend(&self) -> &dyn Backend {
+        self.backend.as_ref()
+    }
+
+    // Runs a statement that returns no rows; see Backend::execute
+    pub fn execute(&self, sql: &str, params: &[SqlParam]) -> Result<Option<usize>> {
+        self.backend.execute(sql, params, &self.config)
+    }
+
+    // Arrow schema of the result of `sql`, without fetching rows
+    pub fn schema(&self, sql: &str, params: &[SqlParam]) -> Result<SchemaRef> {
+        self.backend.schema(sql, params, &self.config)
+    }
+
+    pub fn metadata(&self) -> Result<Metadata> {
+        self.backend.metadata(&self.config)
+    }
+
+    // Runs `sql` and returns a reader over its batches. The connection stays open until the
+    // reader is dropped or exhausted. A statement without a result set gives an empty schema.
+    pub fn query(&self, sql: &str, params: &[SqlParam]) -> Result<QueryReader> {
+        let watchdog = Watchdog::start(&self.config);
+        let opened = self
+            .backend
+            .batches(sql, params, &self.config, &watchdog)
+            .map(|reader| {
+                reader.unwrap_or_else(|| {
+                    Box::new(RecordBatchIterator::new(
+                        Vec::new(),
+                        Arc::new(Schema::empty()),
+                    ))
+                })
+            });
+        let reader = match opened {
+            Ok(reader) => reader,
+            Err(e) => return watchdog.finish(Err(e)),
+        };
+        Ok(QueryReader {
+            schema: reader.schema(),
+            reader: Some(reader),
+            watchdog: Some(watchdog),
+            progress: Progress::new(&self.config),
+            left: rows_left(&self.config, 0),
+        })
+    }
+
+    // Runs `sql` and keeps every batch in memory
+    pub fn query_all(
+        &self,
+        sql: &str,
+        params: &[SqlParam],
+    ) -> Result<(SchemaRef, Vec<RecordBatch>)> {
+        let mut collected = Collected::default();
+        self.query_into(sql, params, &mut collected)?;
+        let schema = collected
+            .schema
+            .unwrap_or_else(|| Arc::new(Schema::empty()));
+        Ok((schema, collected.batches))
+    }
+
+    // Reads the result of `sql` into `sink` and returns the number of rows read; nothing is
+    // written to the database. Unlike query(), this restarts the read after transient failures
+    // (QueryConfig.max_restarts) and applies dictionary encoding.
+    pub fn query_into(
+        &self,
+        sql: &str,
+        params: &[SqlParam],
+        sink: &mut dyn BatchSink,
+    ) -> Result<usize> {
+        fetch_from(
+            self.backend.as_ref(),
+            sql,
+            params,
+            &self.config,
+            sink,
+            &mut |_| Ok(()),
+        )
+    }
+
+    // Writes the result of `sql` to `out` as an Arrow IPC stream, compressed as
+    // QueryConfig.ipc_compression says
+    pub fn write_ipc<W: Write>(
+        &self,
+        sql: &str,
+        params: &[SqlParam],
+        out: &mut W,
+    ) -> Result<usize> {
+        let mut sink = IpcSink::new(out, compression::write_options(&self.config)?);
+        self.query_into(sql, params, &mut sink)
+    }
+}
+
+// Batches of a running query, as returned by Connection::query
+pub struct QueryReader {
+    reader: Option<BatchReader>,
+    schema: SchemaRef,
+    // Keeps query_timeout enforced for as long as the cursor is read
+    watchdog: Option<Watchdog>,
+    progress: Progress,
+    // Rows QueryConfig.max_rows still allows
+    left: Option<usize>,
+}
+
+impl QueryReader {
+    // Frees the cursor and its connection
+    fn release(&mut self) {
+        self.reader = None;
+        self.watchdog = None;
+    }
+
+    fn accept(&mut self, batch: RecordBatch) -> Result<RecordBatch> {
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.check()?;
+        }
+        let batch = trim_batch(batch, self.left);
+        self.left = self.left.map(|left| left - batch.num_rows());
+        self.progress.batch(&batch)?;
+        Ok(batch)
+    }
+}
+
+impl Iterator for QueryReader {
+    type Item = std::result::Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = match self.left {
+            Some(0) => None,
+            _ => self.reader.as_mut()?.next(),
+        };
+        let result = match next {
+            Some(Ok(batch)) => self.accept(batch),
+            Some(Err(e)) => Err(e.into()),
+            None => {
+                self.release();
+                return None;
+            }
+        };
+        if result.is_err() {
+            self.release();
+        }
+        Some(result.map_err(|e| ArrowError::ExternalError(e.into())))
+    }
+}
+
+impl RecordBatchReader for QueryReader {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
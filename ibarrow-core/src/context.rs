@@ -0,0 +1,110 @@
+use anyhow::{anyhow, Result};
+use odbc_api::{Connection, IntoParameter};
+use std::collections::HashMap;
+
+use crate::QueryConfig;
+
+const SET_CONTEXT_SQL: &str = "SELECT RDB$SET_CONTEXT(?, ?, ?) FROM RDB$DATABASE";
+
+// Firebird limits context variable names to 80 characters and values to 255
+const MAX_NAME_LEN: usize = 80;
+const MAX_VALUE_LEN: usize = 255;
+
+// The one namespace the variables can go in. SYSTEM and DDL_TRIGGER are read-only, and
+// USER_TRANSACTION variables would end with the autocommitted SELECT that sets them, before the
+// query they are meant for starts.
+const NAMESPACE: &str = "USER_SESSION";
+
+fn validate_variable(namespace: &str, name: &str, value: &str) -> Result<()> {
+    if namespace == "USER_TRANSACTION" {
+        return Err(anyhow!(
+            "USER_TRANSACTION variables would not outlive the statement setting them; \
+             use 'USER_SESSION'"
+        ));
+    }
+    if namespace != NAMESPACE {
+        return Err(anyhow!(
+            "Unknown context namespace '{}': expected '{}'",
+            namespace,
+            NAMESPACE
+        ));
+    }
+    if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+        return Err(anyhow!(
+            "Context variable name must be 1 to {} characters, got '{}'",
+            MAX_NAME_LEN,
+            name
+        ));
+    }
+    if value.chars().count() > MAX_VALUE_LEN {
+        return Err(anyhow!(
+            "Value of context variable '{}' exceeds {} characters",
+            name,
+            MAX_VALUE_LEN
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate(context: Option<&HashMap<String, HashMap<String, String>>>) -> Result<()> {
+    for (namespace, variables) in context.into_iter().flatten() {
+        for (name, value) in variables {
+            validate_variable(namespace, name, value)?;
+        }
+    }
+    Ok(())
+}
+
+// Records a variable in QueryConfig.session_context; a None value removes it
+pub fn set(
+    config: &mut QueryConfig,
+    namespace: &str,
+    name: &str,
+    value: Option<String>,
+) -> Result<()> {
+    match value {
+        Some(value) => {
+            validate_variable(namespace, name, &value)?;
+            config
+                .session_context
+                .get_or_insert_with(HashMap::new)
+                .entry(namespace.to_string())
+                .or_default()
+                .insert(name.to_string(), value);
+        }
+        None => {
+            validate_variable(namespace, name, "")?;
+            let context = config.session_context.get_or_insert_with(HashMap::new);
+            if let Some(variables) = context.get_mut(namespace) {
+                variables.remove(name);
+                if variables.is_empty() {
+                    context.remove(namespace);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Sets the variables of QueryConfig.session_context on a new connection. Connections are opened
+// per call and on every retry, so each one starts out with the same context.
+pub fn apply(conn: &Connection<'_>, config: &QueryConfig) -> Result<()> {
+    for (namespace, variables) in config.session_context.iter().flatten() {
+        for (name, value) in variables {
+            let params = (
+                &namespace.as_str().into_parameter(),
+                &name.as_str().into_parameter(),
+                &value.as_str().into_parameter(),
+            );
+            conn.execute(SET_CONTEXT_SQL, params, None).map_err(|e| {
+                anyhow!(
+                    "Failed to set context variable {}.{}: {}",
+                    namespace,
+                    name,
+                    e
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
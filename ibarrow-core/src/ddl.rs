@@ -0,0 +1,145 @@
+use anyhow::{anyhow, Result};
+use arrow::datatypes::{DataType, Schema};
+use std::collections::HashMap;
+
+use crate::quoting::quote_identifier;
+
+// Which server the CREATE TABLE is written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    // Firebird 4 and later
+    Firebird,
+    // InterBase, which has no BIGINT, time zones or NUMERIC beyond 18 digits
+    Interbase,
+}
+
+impl Dialect {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "firebird" => Ok(Self::Firebird),
+            "interbase" => Ok(Self::Interbase),
+            _ => Err(anyhow!(
+                "Unknown dialect '{}': expected 'firebird' or 'interbase'",
+                name
+            )),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Firebird => "Firebird",
+            Self::Interbase => "InterBase",
+        }
+    }
+}
+
+// VARCHAR length schema_to_ddl gives string columns unless told otherwise
+pub const DEFAULT_VARCHAR_LENGTH: u32 = 255;
+
+// Longest VARCHAR the server accepts, in characters of a single-byte charset
+const MAX_VARCHAR_LENGTH: u32 = 32765;
+
+pub fn validate_varchar_length(length: Option<u32>) -> Result<()> {
+    match length {
+        Some(length) if length == 0 || length > MAX_VARCHAR_LENGTH => Err(anyhow!(
+            "varchar_length must be between 1 and {}",
+            MAX_VARCHAR_LENGTH
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Column type for an Arrow type in CREATE TABLE. Strings become VARCHAR(varchar_length), or
+// text blobs without a length.
+pub fn column_type(
+    data_type: &DataType,
+    dialect: Dialect,
+    varchar_length: Option<u32>,
+) -> Result<String> {
+    let interbase = dialect == Dialect::Interbase;
+    Ok(match data_type {
+        DataType::Boolean => "BOOLEAN".to_string(),
+        DataType::Int8 | DataType::Int16 | DataType::UInt8 => "SMALLINT".to_string(),
+        DataType::Int32 | DataType::UInt16 => "INTEGER".to_string(),
+        DataType::Int64 | DataType::UInt32 if interbase => "NUMERIC(18, 0)".to_string(),
+        DataType::Int64 | DataType::UInt32 => "BIGINT".to_string(),
+        DataType::UInt64 if !interbase => "NUMERIC(20, 0)".to_string(),
+        DataType::Float16 | DataType::Float32 => "FLOAT".to_string(),
+        DataType::Float64 => "DOUBLE PRECISION".to_string(),
+        DataType::Decimal128(precision, scale) | DataType::Decimal256(precision, scale) => {
+            let max = if interbase { 18 } else { 38 };
+            let precision = (*precision).min(max);
+            // The server takes neither a negative scale nor one beyond the precision
+            let scale = (*scale).clamp(0, precision as i8);
+            format!("NUMERIC({}, {})", precision, scale)
+        }
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => match varchar_length {
+            Some(length) => format!("VARCHAR({})", length),
+            None if interbase => "BLOB SUB_TYPE 1".to_string(),
+            None => "BLOB SUB_TYPE TEXT".to_string(),
+        },
+        DataType::Binary
+        | DataType::LargeBinary
+        | DataType::BinaryView
+        | DataType::FixedSizeBinary(_) => "BLOB".to_string(),
+        DataType::Date32 | DataType::Date64 => "DATE".to_string(),
+        DataType::Time32(_) | DataType::Time64(_) => "TIME".to_string(),
+        DataType::Timestamp(_, None) => "TIMESTAMP".to_string(),
+        DataType::Timestamp(_, Some(_)) if !interbase => "TIMESTAMP WITH TIME ZONE".to_string(),
+        DataType::Dictionary(_, values) => column_type(values, dialect, varchar_length)?,
+        other => {
+            return Err(anyhow!(
+                "{} has no column type for {}",
+                dialect.name(),
+                other
+            ))
+        }
+    })
+}
+
+// CREATE TABLE for `schema`, one column per line. `table` is used as given, so it must
+// already be quoted where it needs to be. `overrides` maps column names to the type to use
+// instead of the one column_type picks.
+pub fn create_table_sql(
+    table: &str,
+    schema: &Schema,
+    dialect: Dialect,
+    overrides: &HashMap<String, String>,
+    varchar_length: Option<u32>,
+) -> Result<String> {
+    if schema.fields().is_empty() {
+        return Err(anyhow!("Cannot create a table without columns"));
+    }
+    for name in overrides.keys() {
+        if schema.field_with_name(name).is_err() {
+            return Err(anyhow!(
+                "Override for column {} which is not in the schema",
+                name
+            ));
+        }
+    }
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let data_type = match overrides.get(field.name()) {
+                Some(data_type) => data_type.trim().to_string(),
+                None => column_type(field.data_type(), dialect, varchar_length).map_err(|e| {
+                    anyhow!("Column {}: {}; pass a type in overrides", field.name(), e)
+                })?,
+            };
+            let not_null = if field.is_nullable() { "" } else { " NOT NULL" };
+            Ok(format!(
+                "    {} {}{}",
+                quote_identifier(field.name()),
+                data_type,
+                not_null
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(format!(
+        "CREATE TABLE {} (\n{}\n)",
+        table,
+        columns.join(",\n")
+    ))
+}
@@ -0,0 +1,101 @@
+use log::{LevelFilter, Log, Metadata, Record};
+use std::cell::RefCell;
+use std::fmt;
+
+// Records kept per thread; older ones are dropped first
+const MAX_RECORDS: usize = 32;
+
+thread_local! {
+    static RECORDS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+// odbc-api logs every diagnostic record (warnings and errors) of a failing or
+// SQL_SUCCESS_WITH_INFO call at warn level. This logger keeps them for the calling thread,
+// while anyhow errors only carry the first record.
+struct Collector;
+
+impl Log for Collector {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target().starts_with("odbc_api")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let text = record.args().to_string();
+        RECORDS.with(|records| {
+            let mut records = records.borrow_mut();
+            if records.len() == MAX_RECORDS {
+                records.remove(0);
+            }
+            records.push(text);
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+static COLLECTOR: Collector = Collector;
+
+// Installs the collector unless the process already has a logger
+pub fn install() {
+    if log::set_logger(&COLLECTOR).is_ok() {
+        log::set_max_level(LevelFilter::Warn);
+    }
+}
+
+// Forgets records left over from earlier operations on this thread
+pub fn clear() {
+    RECORDS.with(|records| records.borrow_mut().clear());
+}
+
+pub fn take() -> Vec<String> {
+    RECORDS.with(|records| std::mem::take(&mut *records.borrow_mut()))
+}
+
+// An error carrying the diagnostic records of the thread it happened on, so they survive being
+// sent to another thread. Displays exactly like the wrapped error.
+#[derive(Debug)]
+pub struct Diagnosed {
+    pub records: Vec<String>,
+    source: anyhow::Error,
+}
+
+impl fmt::Display for Diagnosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.source, f)
+    }
+}
+
+impl std::error::Error for Diagnosed {}
+
+pub fn attach(error: anyhow::Error) -> anyhow::Error {
+    // Python exceptions carry no ODBC records and must reach the caller unchanged
+    #[cfg(feature = "python")]
+    if error.is::<pyo3::PyErr>() {
+        return error;
+    }
+    anyhow::Error::new(Diagnosed {
+        records: take(),
+        source: error,
+    })
+}
+
+// Records for an error being raised on this thread
+pub fn records_for(error: &anyhow::Error) -> Vec<String> {
+    match error.downcast_ref::<Diagnosed>() {
+        Some(diagnosed) => diagnosed.records.clone(),
+        None => take(),
+    }
+}
+
+// Splits odbc-api's "State: S, Native error: N, Message: M" into its parts
+pub fn parse(record: &str) -> (String, Option<i32>, String) {
+    let parsed = record.strip_prefix("State: ").and_then(|rest| {
+        let (state, rest) = rest.split_once(", Native error: ")?;
+        let (native, message) = rest.split_once(", Message: ")?;
+        Some((state.to_string(), native.parse().ok(), message.to_string()))
+    });
+    parsed.unwrap_or_else(|| (String::new(), None, record.to_string()))
+}
@@ -0,0 +1,164 @@
+use anyhow::{anyhow, Result};
+use arrow::array::{Array, ArrayRef, AsArray, DictionaryArray, Int32Builder, StringArray};
+use arrow::compute::{cast, concat};
+use arrow::datatypes::{DataType, Field, Int32Type, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::{find_column, QueryConfig};
+
+struct Column {
+    name: String,
+    index: Option<usize>,
+    codes: HashMap<String, i32>,
+    // Categories in code order. Batches share the array until a new category appears, so it is
+    // neither copied per batch nor written again by the IPC writer when nothing changed.
+    values: ArrayRef,
+}
+
+// Dictionary-encodes the text columns named in QueryConfig.dictionary_columns or
+// QueryConfig.dictionaries. Codes stay stable for the whole result: every batch carries the
+// dictionary built so far, so the last one holds every category. Categories passed in
+// QueryConfig.dictionaries keep their codes, and new ones are appended after them.
+pub struct Encoder {
+    columns: Vec<Column>,
+}
+
+fn dictionary_type() -> DataType {
+    DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8))
+}
+
+impl Encoder {
+    pub fn new(config: &QueryConfig) -> Self {
+        let seeds = config.dictionaries.clone().unwrap_or_default();
+        let mut names: Vec<String> = config.dictionary_columns.clone().unwrap_or_default();
+        let mut seeded: Vec<&String> = seeds.keys().collect();
+        seeded.sort();
+        for name in seeded {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        let columns = names
+            .into_iter()
+            .map(|name| {
+                let values = seeds.get(&name).cloned().unwrap_or_default();
+                let codes = values
+                    .iter()
+                    .enumerate()
+                    .map(|(code, value)| (value.clone(), code as i32))
+                    .collect();
+                Column {
+                    name,
+                    index: None,
+                    codes,
+                    values: Arc::new(StringArray::from(values)),
+                }
+            })
+            .collect();
+        Self { columns }
+    }
+
+    // Schema of the encoded batches
+    pub fn schema(&mut self, schema: &SchemaRef) -> Result<SchemaRef> {
+        if self.columns.is_empty() {
+            return Ok(schema.clone());
+        }
+        let mut fields: Vec<Field> = schema.fields().iter().map(|f| f.as_ref().clone()).collect();
+        for column in &mut self.columns {
+            let index = find_column(schema, &column.name).ok_or_else(|| {
+                anyhow!(
+                    "dictionary column '{}' is not in the result set",
+                    column.name
+                )
+            })?;
+            let field = &fields[index];
+            if !matches!(
+                field.data_type(),
+                DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View
+            ) {
+                return Err(anyhow!(
+                    "dictionary column '{}' must be a text column, not {}",
+                    column.name,
+                    field.data_type()
+                ));
+            }
+            fields[index] = field.clone().with_data_type(dictionary_type());
+            column.index = Some(index);
+        }
+        Ok(Arc::new(Schema::new_with_metadata(
+            fields,
+            schema.metadata().clone(),
+        )))
+    }
+
+    pub fn encode(&mut self, batch: RecordBatch, schema: &SchemaRef) -> Result<RecordBatch> {
+        if self.columns.is_empty() {
+            return Ok(batch);
+        }
+        let mut arrays: Vec<ArrayRef> = batch.columns().to_vec();
+        for column in &mut self.columns {
+            let Some(index) = column.index else {
+                continue;
+            };
+            let text = cast(&arrays[index], &DataType::Utf8)?;
+            let text = text.as_string::<i32>();
+            let mut keys = Int32Builder::with_capacity(text.len());
+            // Categories first seen in this batch
+            let mut added: Vec<&str> = Vec::new();
+            for value in text.iter() {
+                match value {
+                    None => keys.append_null(),
+                    Some(value) => {
+                        let code = match column.codes.get(value) {
+                            Some(code) => *code,
+                            None => {
+                                let code = i32::try_from(column.codes.len()).map_err(|_| {
+                                    anyhow!(
+                                        "dictionary column '{}' has too many categories",
+                                        column.name
+                                    )
+                                })?;
+                                column.codes.insert(value.to_string(), code);
+                                added.push(value);
+                                code
+                            }
+                        };
+                        keys.append_value(code);
+                    }
+                }
+            }
+            if !added.is_empty() {
+                let added = StringArray::from(added);
+                column.values = concat(&[column.values.as_ref(), &added])?;
+            }
+            arrays[index] = Arc::new(DictionaryArray::<Int32Type>::try_new(
+                keys.finish(),
+                column.values.clone(),
+            )?);
+        }
+        Ok(RecordBatch::try_new(schema.clone(), arrays)?)
+    }
+}
+
+// Categories of every dictionary column of `batch`, in code order. For a batch written by an
+// Encoder, the last batch of a result holds the complete dictionaries.
+pub fn categories(batch: &RecordBatch) -> Vec<(String, Vec<String>)> {
+    batch
+        .schema()
+        .fields()
+        .iter()
+        .zip(batch.columns())
+        .filter_map(|(field, array)| {
+            let dictionary = array.as_any_dictionary_opt()?;
+            let values = cast(dictionary.values(), &DataType::Utf8).ok()?;
+            let values = values
+                .as_string::<i32>()
+                .iter()
+                .map(|value| value.unwrap_or_default().to_string())
+                .collect();
+            Some((field.name().clone(), values))
+        })
+        .collect()
+}
@@ -0,0 +1,263 @@
+use anyhow::Result;
+use arrow::datatypes::{Field, Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use odbc_api::handles::{AsStatementRef, Statement};
+use odbc_api::sys::{Desc, SQLColAttribute, SmallInt, SqlReturn};
+use odbc_api::{Connection, Cursor, IntoParameter, ResultSetMetadata};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::charset::Transcoded;
+use crate::BatchReader;
+
+// Firebird names the implicit domain of a column declared without one RDB$<n>
+const IMPLICIT_PREFIX: &str = "RDB$";
+
+const FIELD_SOURCES_SQL: &str = "SELECT TRIM(RDB$FIELD_NAME), TRIM(RDB$FIELD_SOURCE) \
+     FROM RDB$RELATION_FIELDS WHERE RDB$RELATION_NAME = ?";
+
+// Statements whose resolved domains are kept; the cache starts over once it is full
+const CACHED_STATEMENTS: usize = 256;
+
+type Rule = (String, HashMap<String, String>);
+
+// Domains by database (a hash of the connection string, which holds the password) and statement
+type Cache = HashMap<(u64, String), Domains>;
+
+// Registered domain rules, in registration order
+fn rules() -> &'static Mutex<Vec<Rule>> {
+    static RULES: OnceLock<Mutex<Vec<Rule>>> = OnceLock::new();
+    RULES.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+// Registers the field metadata for columns whose domain matches `pattern`. Registering the
+// same pattern again replaces its metadata.
+pub fn set_rule(pattern: &str, metadata: HashMap<String, String>) {
+    let pattern = pattern.to_uppercase();
+    let mut rules = rules().lock().unwrap_or_else(|e| e.into_inner());
+    match rules.iter_mut().find(|(existing, _)| *existing == pattern) {
+        Some(rule) => rule.1 = metadata,
+        None => rules.push((pattern, metadata)),
+    }
+}
+
+// Domains resolved so far, so running a statement again skips the prepare and the catalog
+// queries
+fn cache() -> &'static Mutex<Cache> {
+    static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// Also forgets the cached domains, so columns moved to another domain since are looked up again
+pub fn clear_rules(pattern: Option<&str>) {
+    cache().lock().unwrap_or_else(|e| e.into_inner()).clear();
+    let mut rules = rules().lock().unwrap_or_else(|e| e.into_inner());
+    match pattern {
+        Some(pattern) => {
+            let pattern = pattern.to_uppercase();
+            rules.retain(|(existing, _)| *existing != pattern);
+        }
+        None => rules.clear(),
+    }
+}
+
+pub fn registered() -> Vec<Rule> {
+    rules().lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+// `*` matches any run of characters and `?` any single one. On a mismatch only the last `*`
+// takes one more character, which is enough since earlier ones could only match less, so the
+// match takes at most pattern length times name length steps.
+fn matches(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` and of the name character it matches up to
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((at, matched)) => {
+                    star = Some((at, matched + 1));
+                    p = at + 1;
+                    n = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+// Metadata the rules give a column of `domain`; later rules win on conflicting keys
+fn metadata_for(rules: &[Rule], domain: &str) -> HashMap<String, String> {
+    let domain: Vec<char> = domain.to_uppercase().chars().collect();
+    let mut metadata = HashMap::new();
+    for (pattern, entries) in rules {
+        let pattern: Vec<char> = pattern.chars().collect();
+        if matches(&pattern, &domain) {
+            metadata.extend(entries.clone());
+        }
+    }
+    metadata
+}
+
+// Domain of each result column; empty when no rules are registered
+#[derive(Debug, Clone, Default)]
+pub struct Domains(Vec<Option<String>>);
+
+// A text column attribute of a described statement, empty when the driver does not know it
+fn column_attribute(statement: &mut impl AsStatementRef, column: u16, attribute: Desc) -> String {
+    let mut buffer = vec![0u8; 256];
+    let mut length: SmallInt = 0;
+    let ret = unsafe {
+        SQLColAttribute(
+            statement.as_stmt_ref().as_sys(),
+            column,
+            attribute,
+            buffer.as_mut_ptr() as _,
+            buffer.len() as SmallInt,
+            &mut length,
+            std::ptr::null_mut(),
+        )
+    };
+    if !matches!(ret, SqlReturn::SUCCESS | SqlReturn::SUCCESS_WITH_INFO) {
+        return String::new();
+    }
+    buffer.truncate((length.max(0) as usize).min(buffer.len()));
+    String::from_utf8_lossy(&buffer).trim().to_string()
+}
+
+// Looks up the domain behind each column of `statement` on `conn`, which is connected with
+// `conn_str`. Each statement is resolved once per database and then served from the cache.
+pub fn resolve(conn: &Connection<'_>, conn_str: &str, statement: &Transcoded) -> Result<Domains> {
+    if rules().lock().unwrap_or_else(|e| e.into_inner()).is_empty() {
+        return Ok(Domains::default());
+    }
+    let mut hasher = DefaultHasher::new();
+    conn_str.hash(&mut hasher);
+    let key = (hasher.finish(), statement.sql.clone());
+    if let Some(domains) = cache().lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+        return Ok(domains.clone());
+    }
+    let domains = lookup(conn, statement)?;
+    let mut cache = cache().lock().unwrap_or_else(|e| e.into_inner());
+    if cache.len() >= CACHED_STATEMENTS {
+        cache.clear();
+    }
+    cache.insert(key, domains.clone());
+    Ok(domains)
+}
+
+// The statement is only prepared, to learn the table and column every result column comes from;
+// computed columns have none
+fn lookup(conn: &Connection<'_>, statement: &Transcoded) -> Result<Domains> {
+    let mut prepared = statement.prepare(conn.preallocate()?.into_handle())?;
+    let columns = prepared.num_result_cols()?.max(0) as u16;
+    let origins: Vec<(String, String)> = (1..=columns)
+        .map(|column| {
+            (
+                column_attribute(&mut prepared, column, Desc::BaseTableName),
+                column_attribute(&mut prepared, column, Desc::BaseColumnName),
+            )
+        })
+        .collect();
+    drop(prepared);
+
+    let mut sources: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut domains = Vec::with_capacity(origins.len());
+    for (table, column) in origins {
+        if table.is_empty() || column.is_empty() {
+            domains.push(None);
+            continue;
+        }
+        if !sources.contains_key(&table) {
+            sources.insert(table.clone(), field_sources(conn, &table)?);
+        }
+        let domain = sources[&table]
+            .get(&column)
+            .filter(|domain| !domain.starts_with(IMPLICIT_PREFIX))
+            .cloned();
+        domains.push(domain);
+    }
+    log::debug!(
+        "Resolved domains for {} of {} columns",
+        domains.iter().flatten().count(),
+        domains.len()
+    );
+    Ok(Domains(domains))
+}
+
+// Domain of every column of `table`
+fn field_sources(conn: &Connection<'_>, table: &str) -> Result<HashMap<String, String>> {
+    let mut sources = HashMap::new();
+    if let Some(mut cursor) = conn.execute(FIELD_SOURCES_SQL, &table.into_parameter(), None)? {
+        let (mut field, mut source) = (Vec::new(), Vec::new());
+        while let Some(mut row) = cursor.next_row()? {
+            if row.get_text(1, &mut field)? && row.get_text(2, &mut source)? {
+                sources.insert(
+                    String::from_utf8_lossy(&field).to_string(),
+                    String::from_utf8_lossy(&source).to_string(),
+                );
+            }
+        }
+    }
+    Ok(sources)
+}
+
+// Adds the metadata of the matching domain rules to the fields of the result
+struct Annotated {
+    inner: BatchReader,
+    schema: SchemaRef,
+}
+
+impl Iterator for Annotated {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(
+            self.inner
+                .next()?
+                .and_then(|batch| batch.with_schema(self.schema.clone())),
+        )
+    }
+}
+
+impl RecordBatchReader for Annotated {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+pub fn wrap(reader: BatchReader, domains: &Domains) -> BatchReader {
+    let rules = registered();
+    if domains.0.iter().all(Option::is_none) || rules.is_empty() {
+        return reader;
+    }
+    let schema = reader.schema();
+    let fields: Vec<Field> = schema
+        .fields()
+        .iter()
+        .zip(domains.0.iter().chain(std::iter::repeat(&None)))
+        .map(|(field, domain)| {
+            let Some(domain) = domain else {
+                return field.as_ref().clone();
+            };
+            let mut metadata = field.metadata().clone();
+            metadata.extend(metadata_for(&rules, domain));
+            field.as_ref().clone().with_metadata(metadata)
+        })
+        .collect();
+    Box::new(Annotated {
+        inner: reader,
+        schema: Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone())),
+    })
+}
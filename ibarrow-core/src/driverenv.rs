@@ -0,0 +1,58 @@
+use anyhow::{anyhow, Result};
+use odbc_api::{Connection, ConnectionOptions, Environment};
+use std::collections::HashMap;
+
+use crate::{context, faults, snapshot, QueryConfig};
+
+// Firebird client variables QueryConfig.driver_env may set. They go into the connection
+// string instead of the process environment: changing the environment while other threads
+// (Python's among them) may read it is undefined behaviour.
+const VARIABLES: [&str; 2] = ["ISC_USER", "ISC_PASSWORD"];
+
+pub fn validate(driver_env: &HashMap<String, String>) -> Result<()> {
+    for (name, value) in driver_env {
+        if !VARIABLES.contains(&name.as_str()) {
+            return Err(anyhow!(
+                "driver_env variable '{}' cannot be passed to the driver: only {} have a \
+                 connection string equivalent; set others in the environment before the \
+                 process starts",
+                name,
+                VARIABLES.join(" and ")
+            ));
+        }
+        if value.contains(['\0', ';']) {
+            return Err(anyhow!(
+                "driver_env value of '{}' contains a NUL byte or ';'",
+                name
+            ));
+        }
+    }
+    Ok(())
+}
+
+// `value` unless it is empty, else what driver_env sets the client variable `name` to: the
+// fallback the Firebird client library itself applies to ISC_USER and ISC_PASSWORD
+pub fn or_variable<'a>(value: &'a str, name: &str, config: &'a QueryConfig) -> &'a str {
+    if !value.is_empty() {
+        return value;
+    }
+    config
+        .driver_env
+        .as_ref()
+        .and_then(|vars| vars.get(name))
+        .map_or(value, String::as_str)
+}
+
+// Opens a connection. With QueryConfig.snapshot_number the connection starts in a
+// transaction at that snapshot, and the variables of QueryConfig.session_context are set on it.
+pub fn connect<'env>(
+    env: &'env Environment,
+    conn_str: &str,
+    config: &QueryConfig,
+) -> Result<Connection<'env>> {
+    faults::hit(faults::Point::Connect)?;
+    let conn = env.connect_with_connection_string(conn_str, ConnectionOptions::default())?;
+    snapshot::begin(&conn, config)?;
+    context::apply(&conn, config)?;
+    Ok(conn)
+}
@@ -0,0 +1,151 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::path::Path;
+
+pub const UNIXODBC: &str = "unixodbc";
+pub const IODBC: &str = "iodbc";
+
+// The ODBC driver manager libraries mapped into this process. ibarrow links against
+// libodbc.so.2 and the dynamic loader decides which file that is, so the result is only
+// known at runtime.
+#[derive(Debug, Clone, Serialize)]
+pub struct Info {
+    // unixodbc, iodbc or windows; None when none or both are loaded
+    pub name: Option<&'static str>,
+    pub library: Option<String>,
+    // Every driver manager library found
+    pub loaded: Vec<Library>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Library {
+    pub name: &'static str,
+    pub path: String,
+}
+
+// Which driver manager a shared library belongs to, from its file name
+fn classify(path: &str) -> Option<&'static str> {
+    let file = Path::new(path).file_name()?.to_str()?;
+    if file.starts_with("libiodbc.") {
+        Some(IODBC)
+    } else if file.starts_with("libodbc.") {
+        Some(UNIXODBC)
+    } else {
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn loaded_libraries() -> Vec<Library> {
+    let maps = std::fs::read_to_string("/proc/self/maps").unwrap_or_default();
+    let mut loaded: Vec<Library> = Vec::new();
+    for path in maps
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(5))
+    {
+        if let Some(name) = classify(path) {
+            if !loaded.iter().any(|library| library.path == path) {
+                loaded.push(Library {
+                    name,
+                    path: path.to_string(),
+                });
+            }
+        }
+    }
+    loaded
+}
+
+#[cfg(not(target_os = "linux"))]
+fn loaded_libraries() -> Vec<Library> {
+    Vec::new()
+}
+
+pub fn detect() -> Info {
+    if cfg!(windows) {
+        return Info {
+            name: Some("windows"),
+            library: Some("odbc32.dll".to_string()),
+            loaded: Vec::new(),
+        };
+    }
+    let loaded = loaded_libraries();
+    let mixed = loaded.iter().any(|library| library.name != loaded[0].name);
+    let (name, library) = match loaded.first() {
+        Some(first) if !mixed => (Some(first.name), Some(first.path.clone())),
+        _ => (None, None),
+    };
+    Info {
+        name,
+        library,
+        loaded,
+    }
+}
+
+pub fn validate(driver_manager: Option<&str>) -> Result<()> {
+    match driver_manager {
+        None | Some(UNIXODBC) | Some(IODBC) => Ok(()),
+        Some(other) => Err(anyhow!(
+            "Unknown driver_manager '{}': expected '{}' or '{}'",
+            other,
+            UNIXODBC,
+            IODBC
+        )),
+    }
+}
+
+fn paths(info: &Info, name: &str) -> String {
+    info.loaded
+        .iter()
+        .filter(|library| library.name == name)
+        .map(|library| library.path.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Fails with an explanation when both driver managers are loaded, which otherwise shows up as
+// missing symbols or garbled text, or when `expected` names one that is not loaded.
+// Naming the expected manager accepts a mixed process.
+pub fn check(expected: Option<&str>) -> Result<()> {
+    validate(expected)?;
+    let info = detect();
+    if info.loaded.is_empty() {
+        return Ok(());
+    }
+    for library in &info.loaded {
+        log::debug!(
+            "ODBC driver manager {} loaded from {}",
+            library.name,
+            library.path
+        );
+    }
+    let has = |name: &str| info.loaded.iter().any(|library| library.name == name);
+    match expected {
+        None if info.name.is_none() => Err(anyhow!(
+            "Both unixODBC ({}) and iODBC ({}) are loaded in this process, so ODBC calls may \
+             resolve to either and fail with symbol or encoding errors. Remove one from \
+             LD_PRELOAD/LD_LIBRARY_PATH, or set QueryConfig(driver_manager=...) to the one \
+             your DSN is configured for",
+            paths(&info, UNIXODBC),
+            paths(&info, IODBC)
+        )),
+        Some(expected) if !has(expected) => {
+            let Library { name, path } = &info.loaded[0];
+            Err(anyhow!(
+                "driver_manager '{}' was requested but {} is loaded ({}). Put the {} \
+                 libodbc.so.2 first on LD_LIBRARY_PATH",
+                expected,
+                name,
+                path,
+                expected
+            ))
+        }
+        Some(expected) if info.name.is_none() => {
+            log::warn!(
+                "unixODBC and iODBC are both loaded; continuing as driver_manager='{}'",
+                expected
+            );
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,175 @@
+use anyhow::{anyhow, Result};
+use arrow::array::{
+    Array, ArrayRef, AsArray, BooleanArray, LargeStringArray, StringArray, StringViewArray,
+};
+use arrow::compute::nullif;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use odbc_api::ResultSetMetadata;
+use std::sync::Arc;
+
+use crate::{BatchReader, QueryConfig};
+
+#[derive(Debug, Clone, Copy)]
+enum Mode {
+    EmptyAsNull,
+    NullAsEmpty,
+}
+
+fn mode(config: &QueryConfig) -> Result<Option<Mode>> {
+    match (config.empty_as_null, config.null_as_empty) {
+        (true, true) => Err(anyhow!(
+            "empty_as_null and null_as_empty cannot both be set"
+        )),
+        (true, false) => Ok(Some(Mode::EmptyAsNull)),
+        (false, true) => Ok(Some(Mode::NullAsEmpty)),
+        (false, false) => Ok(None),
+    }
+}
+
+pub fn validate(config: &QueryConfig) -> Result<()> {
+    mode(config).map(|_| ())
+}
+
+fn is_text(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View
+    )
+}
+
+// Which result columns the driver blank-pads, by position: CHAR and NCHAR, not VARCHAR
+pub fn padded(result: &mut impl ResultSetMetadata) -> Result<Vec<bool>> {
+    (1..=result.num_result_cols()? as u16)
+        .map(|column| {
+            Ok(matches!(
+                result.col_data_type(column)?,
+                odbc_api::DataType::Char { .. } | odbc_api::DataType::WChar { .. }
+            ))
+        })
+        .collect()
+}
+
+// CHAR columns come back blank-padded, so '' stored in a CHAR(n) arrives as n spaces. In a
+// VARCHAR the spaces are data, and only '' itself is empty.
+fn blank<'a>(values: impl Iterator<Item = Option<&'a str>>, padded: bool) -> BooleanArray {
+    values
+        .map(|value| {
+            Some(value.is_some_and(|v| {
+                if padded {
+                    v.trim_end_matches(' ').is_empty()
+                } else {
+                    v.is_empty()
+                }
+            }))
+        })
+        .collect()
+}
+
+fn normalize_column(array: &ArrayRef, padded: bool, mode: Mode) -> Result<ArrayRef, ArrowError> {
+    match mode {
+        Mode::EmptyAsNull => {
+            let mask = match array.data_type() {
+                DataType::Utf8 => blank(array.as_string::<i32>().iter(), padded),
+                DataType::LargeUtf8 => blank(array.as_string::<i64>().iter(), padded),
+                DataType::Utf8View => blank(array.as_string_view().iter(), padded),
+                _ => return Ok(array.clone()),
+            };
+            nullif(array, &mask)
+        }
+        Mode::NullAsEmpty => {
+            if array.null_count() == 0 {
+                return Ok(array.clone());
+            }
+            Ok(match array.data_type() {
+                DataType::Utf8 => Arc::new(
+                    array
+                        .as_string::<i32>()
+                        .iter()
+                        .map(|v| Some(v.unwrap_or("")))
+                        .collect::<StringArray>(),
+                ),
+                DataType::LargeUtf8 => Arc::new(
+                    array
+                        .as_string::<i64>()
+                        .iter()
+                        .map(|v| Some(v.unwrap_or("")))
+                        .collect::<LargeStringArray>(),
+                ),
+                DataType::Utf8View => Arc::new(
+                    array
+                        .as_string_view()
+                        .iter()
+                        .map(|v| Some(v.unwrap_or("")))
+                        .collect::<StringViewArray>(),
+                ),
+                _ => array.clone(),
+            })
+        }
+    }
+}
+
+// Applies QueryConfig.empty_as_null / null_as_empty to the text columns of every batch
+struct Normalized {
+    inner: BatchReader,
+    padded: Vec<bool>,
+    mode: Mode,
+    schema: SchemaRef,
+}
+
+impl Iterator for Normalized {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = match self.inner.next()? {
+            Ok(batch) => batch,
+            Err(e) => return Some(Err(e)),
+        };
+        let columns = batch
+            .columns()
+            .iter()
+            .enumerate()
+            .map(|(i, array)| {
+                let padded = self.padded.get(i).copied().unwrap_or(false);
+                normalize_column(array, padded, self.mode)
+            })
+            .collect::<Result<Vec<_>, _>>();
+        Some(columns.and_then(|columns| RecordBatch::try_new(self.schema.clone(), columns)))
+    }
+}
+
+impl RecordBatchReader for Normalized {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+// `padded` comes from padded() on the cursor the batches are read from
+pub fn wrap(reader: BatchReader, padded: &[bool], config: &QueryConfig) -> Result<BatchReader> {
+    let Some(mode) = mode(config)? else {
+        return Ok(reader);
+    };
+    let schema = reader.schema();
+    let schema = match mode {
+        // Text columns declared NOT NULL can now hold NULLs
+        Mode::EmptyAsNull => Arc::new(Schema::new_with_metadata(
+            schema
+                .fields()
+                .iter()
+                .map(|field| {
+                    let nullable = field.is_nullable() || is_text(field.data_type());
+                    Field::clone(field).with_nullable(nullable)
+                })
+                .collect::<Vec<_>>(),
+            schema.metadata().clone(),
+        )),
+        Mode::NullAsEmpty => schema,
+    };
+    Ok(Box::new(Normalized {
+        inner: reader,
+        padded: padded.to_vec(),
+        mode,
+        schema,
+    }))
+}
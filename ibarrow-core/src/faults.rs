@@ -0,0 +1,235 @@
+#[cfg(not(feature = "testing"))]
+use anyhow::Result;
+
+#[cfg(not(feature = "testing"))]
+use crate::BatchReader;
+
+// Places in the read pipeline where builds with the `testing` feature can inject faults
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(not(feature = "testing"), allow(dead_code))]
+pub enum Point {
+    // Opening a connection
+    Connect,
+    // Fetching the next batch from the cursor
+    Fetch,
+    // Converting a fetched batch for the consumer
+    Convert,
+}
+
+#[cfg(not(feature = "testing"))]
+pub fn hit(_point: Point) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(not(feature = "testing"))]
+pub fn wrap(reader: BatchReader) -> BatchReader {
+    reader
+}
+
+#[cfg(feature = "testing")]
+pub use injected::{hit, register, wrap};
+
+#[cfg(feature = "testing")]
+mod injected {
+    use anyhow::{anyhow, Result};
+    use arrow::datatypes::SchemaRef;
+    use arrow::error::ArrowError;
+    use arrow::record_batch::{RecordBatch, RecordBatchReader};
+    use odbc_api::handles::{Record, SqlChar, State};
+    use pyo3::exceptions::PyValueError;
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::Point;
+    use crate::BatchReader;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Kind {
+        ConnectionDrop,
+        ReadDrop,
+        SlowBatch,
+        ConversionError,
+    }
+
+    impl Kind {
+        fn parse(name: &str) -> PyResult<Self> {
+            match name {
+                "connection_drop" => Ok(Kind::ConnectionDrop),
+                "read_drop" => Ok(Kind::ReadDrop),
+                "slow_batch" => Ok(Kind::SlowBatch),
+                "conversion_error" => Ok(Kind::ConversionError),
+                other => Err(PyValueError::new_err(format!(
+                    "Unknown fault '{}': expected 'connection_drop', 'read_drop', \
+                     'slow_batch' or 'conversion_error'",
+                    other
+                ))),
+            }
+        }
+
+        fn name(self) -> &'static str {
+            match self {
+                Kind::ConnectionDrop => "connection_drop",
+                Kind::ReadDrop => "read_drop",
+                Kind::SlowBatch => "slow_batch",
+                Kind::ConversionError => "conversion_error",
+            }
+        }
+
+        fn point(self) -> Point {
+            match self {
+                Kind::ConnectionDrop => Point::Connect,
+                Kind::ReadDrop | Kind::SlowBatch => Point::Fetch,
+                Kind::ConversionError => Point::Convert,
+            }
+        }
+    }
+
+    struct Fault {
+        kind: Kind,
+        // Passes through its point this many times before firing
+        after: u64,
+        // Fires this many times; None keeps firing
+        times: Option<u64>,
+        delay: Duration,
+        seen: u64,
+        fired: u64,
+    }
+
+    impl Fault {
+        fn fires(&mut self) -> bool {
+            self.seen += 1;
+            if self.seen <= self.after || self.times.is_some_and(|times| self.fired >= times) {
+                return false;
+            }
+            self.fired += 1;
+            true
+        }
+    }
+
+    static FAULTS: Mutex<Vec<Fault>> = Mutex::new(Vec::new());
+
+    // The error a dropped link produces, SQLSTATE 08S01, so retry and restart handling apply
+    fn link_failure(function: &'static str) -> odbc_api::Error {
+        let message = "[ibarrow] Communication link failure: connection lost (injected fault)";
+        // SqlChar is u16 in wide-character builds
+        #[allow(clippy::useless_conversion)]
+        let message = message.bytes().map(SqlChar::from).collect();
+        odbc_api::Error::Diagnostics {
+            record: Record {
+                state: State(*b"08S01"),
+                native_error: 0,
+                message,
+            },
+            function,
+        }
+    }
+
+    // Applies the faults registered for `point`
+    pub fn hit(point: Point) -> Result<()> {
+        let mut delay = Duration::ZERO;
+        let mut failure = None;
+        {
+            let mut faults = FAULTS.lock().unwrap_or_else(|e| e.into_inner());
+            for fault in faults
+                .iter_mut()
+                .filter(|fault| fault.kind.point() == point)
+            {
+                if !fault.fires() {
+                    continue;
+                }
+                log::warn!("Injecting fault '{}'", fault.kind.name());
+                match fault.kind {
+                    Kind::SlowBatch => delay += fault.delay,
+                    kind => failure = failure.or(Some(kind)),
+                }
+            }
+        }
+        thread::sleep(delay);
+        match failure {
+            None | Some(Kind::SlowBatch) => Ok(()),
+            Some(Kind::ConnectionDrop) => Err(link_failure("SQLDriverConnect").into()),
+            Some(Kind::ReadDrop) => Err(link_failure("SQLFetch").into()),
+            Some(Kind::ConversionError) => Err(anyhow!("Arrow conversion failed (injected fault)")),
+        }
+    }
+
+    // Fetch faults surface from the reader, where real fetch errors do
+    struct Faulty {
+        inner: BatchReader,
+    }
+
+    impl Iterator for Faulty {
+        type Item = Result<RecordBatch, ArrowError>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            if let Err(e) = hit(Point::Fetch) {
+                return Some(Err(ArrowError::ExternalError(e.into())));
+            }
+            self.inner.next()
+        }
+    }
+
+    impl RecordBatchReader for Faulty {
+        fn schema(&self) -> SchemaRef {
+            self.inner.schema()
+        }
+    }
+
+    pub fn wrap(reader: BatchReader) -> BatchReader {
+        Box::new(Faulty { inner: reader })
+    }
+
+    // Registers a fault. `after` lets that many passes through its point go by first, `times`
+    // limits how often it fires (None: every time) and `delay` is slow_batch's pause in seconds.
+    #[pyfunction]
+    #[pyo3(signature = (kind, after=0, times=Some(1), delay=1.0))]
+    fn inject_fault(kind: &str, after: u64, times: Option<u64>, delay: f64) -> PyResult<()> {
+        let kind = Kind::parse(kind)?;
+        let delay = Duration::try_from_secs_f64(delay)
+            .map_err(|e| PyValueError::new_err(format!("Invalid delay: {}", e)))?;
+        FAULTS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Fault {
+                kind,
+                after,
+                times,
+                delay,
+                seen: 0,
+                fired: 0,
+            });
+        Ok(())
+    }
+
+    // Registered faults with how often each has fired
+    #[pyfunction]
+    fn injected_faults(py: Python<'_>) -> PyResult<Vec<Py<PyDict>>> {
+        let faults = FAULTS.lock().unwrap_or_else(|e| e.into_inner());
+        faults
+            .iter()
+            .map(|fault| {
+                let entry = PyDict::new_bound(py);
+                entry.set_item("kind", fault.kind.name())?;
+                entry.set_item("after", fault.after)?;
+                entry.set_item("times", fault.times)?;
+                entry.set_item("fired", fault.fired)?;
+                Ok(entry.unbind())
+            })
+            .collect()
+    }
+
+    #[pyfunction]
+    fn clear_faults() {
+        FAULTS.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    pub fn register(testing: &Bound<'_, PyModule>) -> PyResult<()> {
+        testing.add_function(wrap_pyfunction!(inject_fault, testing)?)?;
+        testing.add_function(wrap_pyfunction!(injected_faults, testing)?)?;
+        testing.add_function(wrap_pyfunction!(clear_faults, testing)?)?;
+        Ok(())
+    }
+}
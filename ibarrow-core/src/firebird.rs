@@ -0,0 +1,908 @@
+use anyhow::{anyhow, Result};
+use arrow::array::{
+    ArrayRef, BinaryBuilder, BooleanBuilder, Decimal128Builder, Float64Builder, Int64Builder,
+    StringBuilder, TimestampMicrosecondBuilder,
+};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use rsfbclient_core::{
+    charset, ibase, Column, Dialect, FirebirdClientDbOps, FirebirdClientSqlOps, FreeStmtOp,
+    SqlType, StmtType, TrDataAccessMode, TrIsolationLevel, TrLockResolution, TrOp, TrRecordVersion,
+    TransactionConfiguration,
+};
+use rsfbclient_rust::{DbHandle, RustFbClient, RustFbClientAttachmentConfig, TrHandle};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::backend::{self, Backend, Metadata, DB_TYPE_KEY, FIREBIRD};
+use crate::domains::Domains;
+use crate::params::SqlParam;
+use crate::watchdog::Watchdog;
+use crate::{access, driverenv, wrap_batches, BatchReader, QueryConfig};
+
+pub const DEFAULT_PORT: u16 = 3050;
+
+// Rows per batch without QueryConfig.batch_size, as arrow-odbc
+const DEFAULT_BATCH_ROWS: usize = 65535;
+
+type Statement = <RustFbClient as FirebirdClientSqlOps>::StmtHandle;
+
+// Where a Firebird connection string points: `[host[/port]:]database` as isql takes it, e.g.
+// "dbserver/3051:/data/sales.fdb", "[::1]:employee" or "C:\data\sales.fdb" on localhost
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub host: String,
+    pub port: u16,
+    pub database: String,
+}
+
+pub fn parse_dsn(dsn: &str) -> Result<Target> {
+    let dsn = dsn.trim();
+    if dsn.contains('=') {
+        return Err(anyhow!(
+            "The firebird backend takes '[host[/port]:]database', not an ODBC connection string"
+        ));
+    }
+    let (host, port, database) = if let Some(rest) = dsn.strip_prefix('[') {
+        let end = rest
+            .find(']')
+            .ok_or_else(|| anyhow!("Unclosed '[' in Firebird address '{}'", dsn))?;
+        let (port, database) = rest[end + 1..]
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Missing ':' before the database in '{}'", dsn))?;
+        (&rest[..end], port, database)
+    } else {
+        match dsn.split_once(':') {
+            // A drive letter, not a host
+            Some((drive, _)) if drive.len() == 1 => ("", "", dsn),
+            Some((server, database)) => match server.split_once('/') {
+                Some((host, port)) => (host, port, database),
+                None => (server, "", database),
+            },
+            None => ("", "", dsn),
+        }
+    };
+    let port = match port.strip_prefix('/').unwrap_or(port) {
+        "" => DEFAULT_PORT,
+        port => port
+            .parse()
+            .map_err(|_| anyhow!("Invalid port '{}' in Firebird address '{}'", port, dsn))?,
+    };
+    if database.is_empty() {
+        return Err(anyhow!("Missing database in Firebird address '{}'", dsn));
+    }
+    Ok(Target {
+        host: if host.is_empty() { "localhost" } else { host }.to_string(),
+        port,
+        database: database.to_string(),
+    })
+}
+
+// Firebird through its own wire protocol (rsfbclient), without an ODBC driver. The client
+// connects as UTF8, so sql_charset does not apply, and has no describe call of its own: the
+// column types come from the first fetched row.
+#[derive(Debug, Clone)]
+pub struct Firebird {
+    target: Target,
+    user: String,
+    password: String,
+}
+
+impl Firebird {
+    // User and password fall back to ISC_USER and ISC_PASSWORD from driver_env, then from the
+    // environment, as fbclient does
+    pub fn new(dsn: &str, user: &str, password: &str, config: &QueryConfig) -> Result<Self> {
+        let credential = |value: &str, name: &str| match driverenv::or_variable(value, name, config)
+        {
+            "" => std::env::var(name).unwrap_or_default(),
+            value => value.to_string(),
+        };
+        Ok(Self {
+            target: parse_dsn(dsn)?,
+            user: credential(user, "ISC_USER"),
+            password: credential(password, "ISC_PASSWORD"),
+        })
+    }
+
+    fn attach(&self, config: &QueryConfig) -> Result<Session> {
+        let mut client = RustFbClient::new(charset::UTF_8);
+        let attachment = RustFbClientAttachmentConfig {
+            host: self.target.host.clone(),
+            port: self.target.port,
+            db_name: self.target.database.clone(),
+            user: self.user.clone(),
+            pass: self.password.clone(),
+            role_name: None,
+        };
+        let mut db = client
+            .attach_database(&attachment, Dialect::D3, false)
+            .map_err(|e| {
+                anyhow!(
+                    "Firebird connection to {}/{}:{} failed: {}",
+                    self.target.host,
+                    self.target.port,
+                    self.target.database,
+                    e
+                )
+            })?;
+        let tr = transaction(config).and_then(|tpb| Ok(client.begin_transaction(&mut db, tpb)?));
+        let mut session = Session {
+            client,
+            db,
+            tr: None,
+        };
+        session.tr = Some(tr?);
+        Ok(session)
+    }
+
+    // Executes `sql` and reads its first row, which gives the column types. None when the
+    // statement has no result set, which only runs with `run_other`.
+    fn open_rows(
+        &self,
+        sql: &str,
+        params: &[SqlParam],
+        config: &QueryConfig,
+        run_other: bool,
+    ) -> Result<Option<Rows>> {
+        access::check(sql, config)?;
+        let mut session = self.attach(config)?;
+        let (kind, mut statement) = session.prepare(sql)?;
+        if !matches!(kind, StmtType::Select | StmtType::SelectForUpd) {
+            let result = if run_other {
+                session.execute(&mut statement, params).map(|_| ())
+            } else {
+                Ok(())
+            };
+            session.free(&mut statement);
+            result?;
+            if run_other {
+                session.commit()?;
+            }
+            return Ok(None);
+        }
+        session.execute(&mut statement, params)?;
+        let first = session.fetch(&mut statement)?;
+        let driver = match &first {
+            Some(row) => row_schema(row)?,
+            None => session.probe(sql, params)?,
+        };
+        Ok(Some(Rows {
+            session,
+            statement: Some(statement),
+            builder: Builder::new(Arc::new(driver))?,
+            first,
+            batch_rows: config
+                .batch_size
+                .map_or(DEFAULT_BATCH_ROWS, |rows| rows.max(1) as usize),
+        }))
+    }
+}
+
+impl Backend for Firebird {
+    fn name(&self) -> &'static str {
+        FIREBIRD
+    }
+
+    fn execute(
+        &self,
+        sql: &str,
+        params: &[SqlParam],
+        config: &QueryConfig,
+    ) -> Result<Option<usize>> {
+        access::check(sql, config)?;
+        let mut session = self.attach(config)?;
+        let (_, mut statement) = session.prepare(sql)?;
+        let count = session.execute(&mut statement, params);
+        session.free(&mut statement);
+        let count = count?;
+        session.commit()?;
+        Ok(Some(count))
+    }
+
+    fn schema(&self, sql: &str, params: &[SqlParam], config: &QueryConfig) -> Result<SchemaRef> {
+        let driver = match self.open_rows(sql, params, config, false)? {
+            Some(rows) => rows.builder.schema.as_ref().clone(),
+            None => Schema::empty(),
+        };
+        backend::wrapped_schema(driver, &Domains::default(), config)
+    }
+
+    // The wire client cannot cancel a running statement; the watchdog is checked before
+    // executing and, by the read loop, between batches
+    fn batches(
+        &self,
+        sql: &str,
+        params: &[SqlParam],
+        config: &QueryConfig,
+        watchdog: &Watchdog,
+    ) -> Result<Option<BatchReader>> {
+        watchdog.check()?;
+        match self.open_rows(sql, params, config, true)? {
+            Some(rows) => Ok(Some(wrap_batches(
+                Box::new(rows),
+                &Domains::default(),
+                &[],
+                config,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    fn metadata(&self, config: &QueryConfig) -> Result<Metadata> {
+        let mut session = self.attach(config)?;
+        Ok(Metadata {
+            backend: FIREBIRD,
+            dbms_name: "Firebird".to_string(),
+            server_version: session.engine_version()?,
+        })
+    }
+}
+
+// Transaction parameters for QueryConfig's read_only and isolation_level
+pub fn transaction(config: &QueryConfig) -> Result<TransactionConfiguration> {
+    let isolation = match config
+        .isolation_level
+        .as_deref()
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        None | Some("read_uncommitted") | Some("read_committed") => {
+            TrIsolationLevel::ReadCommited(TrRecordVersion::RecordVersion)
+        }
+        Some("repeatable_read") | Some("snapshot") => TrIsolationLevel::Concurrency,
+        Some("serializable") => TrIsolationLevel::Consistency,
+        Some(other) => {
+            return Err(anyhow!(
+                "Unknown isolation_level '{}' for the firebird backend",
+                other
+            ))
+        }
+    };
+    Ok(TransactionConfiguration {
+        data_access: if config.read_only {
+            TrDataAccessMode::ReadOnly
+        } else {
+            TrDataAccessMode::ReadWrite
+        },
+        isolation,
+        lock_resolution: TrLockResolution::Wait(None),
+    })
+}
+
+pub fn sql_param(param: &SqlParam) -> SqlType {
+    match param {
+        SqlParam::Null => SqlType::Null,
+        SqlParam::Bool(value) => SqlType::Boolean(*value),
+        SqlParam::Int(value) => SqlType::Integer(*value),
+        SqlParam::Float(value) => SqlType::Floating(*value),
+        SqlParam::Text(value) => SqlType::Text(value.clone()),
+        SqlParam::Encoded(bytes) => SqlType::Binary(bytes.clone()),
+    }
+}
+
+// One row of `sql`, or a row of NULLs when it has none, so that an empty result still has
+// column types. Expressions need an alias, as in any derived table.
+pub fn probe_sql(sql: &str) -> String {
+    format!(
+        "SELECT FIRST 1 q.* FROM RDB$DATABASE LEFT JOIN ({}) q ON 1 = 1",
+        sql.trim().trim_end_matches(';')
+    )
+}
+
+// Arrow type and wire type of a fetched column. The client has the server send every column as
+// one of a few types: SMALLINT and INTEGER as BIGINT, NUMERIC/DECIMAL with a scale and FLOAT as
+// DOUBLE PRECISION, CHAR as VARCHAR and DATE/TIME as TIMESTAMP. A BLOB is binary or text by its
+// value; a NULL one is taken as text.
+pub fn column_type(column: &Column) -> Result<(DataType, &'static str)> {
+    Ok(match column.raw_type & !1 {
+        ibase::SQL_VARYING | ibase::SQL_TEXT => (DataType::Utf8, "VARCHAR"),
+        ibase::SQL_SHORT | ibase::SQL_LONG | ibase::SQL_INT64 => (DataType::Int64, "BIGINT"),
+        ibase::SQL_INT128 => (DataType::Decimal128(38, 0), "INT128"),
+        ibase::SQL_FLOAT | ibase::SQL_DOUBLE => (DataType::Float64, "DOUBLE PRECISION"),
+        ibase::SQL_TIMESTAMP | ibase::SQL_TYPE_DATE | ibase::SQL_TYPE_TIME => (
+            DataType::Timestamp(TimeUnit::Microsecond, None),
+            "TIMESTAMP",
+        ),
+        ibase::SQL_BOOLEAN => (DataType::Boolean, "BOOLEAN"),
+        ibase::SQL_BLOB => match column.value {
+            SqlType::Binary(_) => (DataType::Binary, "BLOB SUB_TYPE BINARY"),
+            _ => (DataType::Utf8, "BLOB SUB_TYPE TEXT"),
+        },
+        other => {
+            return Err(anyhow!(
+                "Column {}: unsupported Firebird type {}",
+                column.name,
+                other
+            ))
+        }
+    })
+}
+
+// Arrow schema of a fetched row, with each column's wire type under DB_TYPE_KEY
+pub fn row_schema(row: &[Column]) -> Result<Schema> {
+    let fields =
+        row.iter()
+            .map(|column| {
+                let (data_type, db_type) = column_type(column)?;
+                Ok(
+                    Field::new(column.name.as_ref(), data_type, true).with_metadata(HashMap::from(
+                        [(DB_TYPE_KEY.to_string(), db_type.to_string())],
+                    )),
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+    Ok(Schema::new(fields))
+}
+
+fn open(tr: &mut Option<TrHandle>) -> Result<&mut TrHandle> {
+    tr.as_mut()
+        .ok_or_else(|| anyhow!("Firebird transaction already ended"))
+}
+
+// An attachment with one open transaction, rolled back and detached when dropped
+struct Session {
+    client: RustFbClient,
+    db: DbHandle,
+    tr: Option<TrHandle>,
+}
+
+impl Session {
+    fn prepare(&mut self, sql: &str) -> Result<(StmtType, Statement)> {
+        let tr = open(&mut self.tr)?;
+        Ok(self
+            .client
+            .prepare_statement(&mut self.db, tr, Dialect::D3, sql)?)
+    }
+
+    fn execute(&mut self, statement: &mut Statement, params: &[SqlParam]) -> Result<usize> {
+        let tr = open(&mut self.tr)?;
+        let params = params.iter().map(sql_param).collect();
+        Ok(self.client.execute(&mut self.db, tr, statement, params)?)
+    }
+
+    fn fetch(&mut self, statement: &mut Statement) -> Result<Option<Vec<Column>>> {
+        let tr = open(&mut self.tr)?;
+        Ok(self.client.fetch(&mut self.db, tr, statement)?)
+    }
+
+    fn free(&mut self, statement: &mut Statement) {
+        let _ = self.client.free_statement(statement, FreeStmtOp::Drop);
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        let tr = open(&mut self.tr)?;
+        self.client.transaction_operation(tr, TrOp::Commit)?;
+        self.tr = None;
+        Ok(())
+    }
+
+    // Schema of `sql` from probe_sql's single row
+    fn probe(&mut self, sql: &str, params: &[SqlParam]) -> Result<Schema> {
+        let (_, mut statement) = self.prepare(&probe_sql(sql))?;
+        let row = self
+            .execute(&mut statement, params)
+            .and_then(|_| self.fetch(&mut statement));
+        self.free(&mut statement);
+        match row? {
+            Some(row) => row_schema(&row),
+            None => Err(anyhow!("Could not describe the empty result")),
+        }
+    }
+
+    // ENGINE_VERSION (Firebird 2.1+)
+    fn engine_version(&mut self) -> Result<Option<Vec<u32>>> {
+        let sql = "SELECT RDB$GET_CONTEXT('SYSTEM', 'ENGINE_VERSION') FROM RDB$DATABASE";
+        let Ok((_, mut statement)) = self.prepare(sql) else {
+            return Ok(None);
+        };
+        let row = self
+            .execute(&mut statement, &[])
+            .and_then(|_| self.fetch(&mut statement));
+        self.free(&mut statement);
+        match row?.as_deref() {
+            Some(
+                [Column {
+                    value: SqlType::Text(version),
+                    ..
+                }],
+            ) => backend::parse_version(version).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Some(mut tr) = self.tr.take() {
+            let _ = self.client.transaction_operation(&mut tr, TrOp::Rollback);
+        }
+        let _ = self.client.detach_database(&mut self.db);
+    }
+}
+
+// Builds record batches from fetched rows
+pub struct Builder {
+    schema: SchemaRef,
+    columns: Vec<Values>,
+}
+
+impl Builder {
+    pub fn new(schema: SchemaRef) -> Result<Self> {
+        let columns = schema
+            .fields()
+            .iter()
+            .map(|field| Values::new(field.data_type()))
+            .collect::<Result<_>>()?;
+        Ok(Self { schema, columns })
+    }
+
+    pub fn append(&mut self, row: &[Column]) -> Result<()> {
+        if row.len() != self.columns.len() {
+            return Err(anyhow!(
+                "Fetched a row of {} columns for a result of {}",
+                row.len(),
+                self.columns.len()
+            ));
+        }
+        for ((values, column), field) in self.columns.iter_mut().zip(row).zip(self.schema.fields())
+        {
+            values
+                .append(&column.value)
+                .map_err(|e| anyhow!("Column {}: {}", field.name(), e))?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<RecordBatch> {
+        let arrays = self.columns.iter_mut().map(Values::finish).collect();
+        Ok(RecordBatch::try_new(self.schema.clone(), arrays)?)
+    }
+}
+
+enum Values {
+    Text(StringBuilder),
+    Binary(BinaryBuilder),
+    Int(Int64Builder),
+    Int128(Decimal128Builder),
+    Float(Float64Builder),
+    Timestamp(TimestampMicrosecondBuilder),
+    Bool(BooleanBuilder),
+}
+
+impl Values {
+    fn new(data_type: &DataType) -> Result<Self> {
+        Ok(match data_type {
+            DataType::Utf8 => Values::Text(StringBuilder::new()),
+            DataType::Binary => Values::Binary(BinaryBuilder::new()),
+            DataType::Int64 => Values::Int(Int64Builder::new()),
+            DataType::Decimal128(..) => {
+                Values::Int128(Decimal128Builder::new().with_data_type(data_type.clone()))
+            }
+            DataType::Float64 => Values::Float(Float64Builder::new()),
+            DataType::Timestamp(TimeUnit::Microsecond, None) => {
+                Values::Timestamp(TimestampMicrosecondBuilder::new())
+            }
+            DataType::Boolean => Values::Bool(BooleanBuilder::new()),
+            other => return Err(anyhow!("No Firebird column reads as {}", other)),
+        })
+    }
+
+    fn append(&mut self, value: &SqlType) -> Result<()> {
+        match (self, value) {
+            (Values::Text(b), SqlType::Null) => b.append_null(),
+            (Values::Binary(b), SqlType::Null) => b.append_null(),
+            (Values::Int(b), SqlType::Null) => b.append_null(),
+            (Values::Int128(b), SqlType::Null) => b.append_null(),
+            (Values::Float(b), SqlType::Null) => b.append_null(),
+            (Values::Timestamp(b), SqlType::Null) => b.append_null(),
+            (Values::Bool(b), SqlType::Null) => b.append_null(),
+            (Values::Text(b), SqlType::Text(v)) => b.append_value(v),
+            // A binary BLOB in a column whose first value was NULL
+            (Values::Text(b), SqlType::Binary(v)) => b.append_value(
+                std::str::from_utf8(v)
+                    .map_err(|_| anyhow!("binary BLOB in a column read as text"))?,
+            ),
+            (Values::Binary(b), SqlType::Binary(v)) => b.append_value(v),
+            (Values::Binary(b), SqlType::Text(v)) => b.append_value(v.as_bytes()),
+            (Values::Int(b), SqlType::Integer(v)) => b.append_value(*v),
+            (Values::Int128(b), SqlType::Int128(v)) => b.append_value(*v),
+            (Values::Float(b), SqlType::Floating(v)) => b.append_value(*v),
+            (Values::Timestamp(b), SqlType::Timestamp(v)) => {
+                b.append_value(v.and_utc().timestamp_micros())
+            }
+            (Values::Bool(b), SqlType::Boolean(v)) => b.append_value(*v),
+            (_, value) => return Err(anyhow!("unexpected value {:?}", value)),
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            Values::Text(b) => Arc::new(b.finish()),
+            Values::Binary(b) => Arc::new(b.finish()),
+            Values::Int(b) => Arc::new(b.finish()),
+            Values::Int128(b) => Arc::new(b.finish()),
+            Values::Float(b) => Arc::new(b.finish()),
+            Values::Timestamp(b) => Arc::new(b.finish()),
+            Values::Bool(b) => Arc::new(b.finish()),
+        }
+    }
+}
+
+// Batches of an executed statement. The transaction is committed once the rows run out.
+struct Rows {
+    session: Session,
+    statement: Option<Statement>,
+    builder: Builder,
+    first: Option<Vec<Column>>,
+    batch_rows: usize,
+}
+
+impl Rows {
+    fn read(&mut self) -> Result<Option<RecordBatch>> {
+        let Some(statement) = self.statement.as_mut() else {
+            return Ok(None);
+        };
+        let mut rows = 0;
+        if let Some(row) = self.first.take() {
+            self.builder.append(&row)?;
+            rows += 1;
+        }
+        while rows < self.batch_rows {
+            match self.session.fetch(statement)? {
+                Some(row) => {
+                    self.builder.append(&row)?;
+                    rows += 1;
+                }
+                None => {
+                    self.session.free(statement);
+                    self.statement = None;
+                    self.session.commit()?;
+                    break;
+                }
+            }
+        }
+        if rows == 0 {
+            return Ok(None);
+        }
+        self.builder.finish().map(Some)
+    }
+}
+
+impl Iterator for Rows {
+    type Item = std::result::Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.read();
+        if result.is_err() {
+            self.statement = None;
+        }
+        result
+            .map_err(|e| ArrowError::ExternalError(e.into()))
+            .transpose()
+    }
+}
+
+impl RecordBatchReader for Rows {
+    fn schema(&self) -> SchemaRef {
+        self.builder.schema.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Array, AsArray};
+    use arrow::datatypes::{Decimal128Type, Float64Type, Int64Type, TimestampMicrosecondType};
+    use std::net::TcpListener;
+
+    fn column(name: &str, raw_type: u32, value: SqlType) -> Column {
+        Column::new(name, raw_type, value)
+    }
+
+    fn target(host: &str, port: u16, database: &str) -> Target {
+        Target {
+            host: host.to_string(),
+            port,
+            database: database.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_firebird_addresses() {
+        let cases = [
+            ("employee", target("localhost", 3050, "employee")),
+            (
+                "/data/sales.fdb",
+                target("localhost", 3050, "/data/sales.fdb"),
+            ),
+            (
+                "C:\\data\\sales.fdb",
+                target("localhost", 3050, "C:\\data\\sales.fdb"),
+            ),
+            (
+                "db1:/data/sales.fdb",
+                target("db1", 3050, "/data/sales.fdb"),
+            ),
+            ("db1/3051:employee", target("db1", 3051, "employee")),
+            (
+                "db1:C:\\data\\sales.fdb",
+                target("db1", 3050, "C:\\data\\sales.fdb"),
+            ),
+            ("[::1]:employee", target("::1", 3050, "employee")),
+            ("[::1]/3051:employee", target("::1", 3051, "employee")),
+        ];
+        for (dsn, expected) in cases {
+            assert_eq!(parse_dsn(dsn).unwrap(), expected, "{}", dsn);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_addresses() {
+        for dsn in [
+            "",
+            "db1:",
+            "db1/port:employee",
+            "db1/70000:employee",
+            "[::1:employee",
+            "[::1]employee",
+            "DRIVER={Firebird};DBNAME=employee",
+        ] {
+            assert!(parse_dsn(dsn).is_err(), "{}", dsn);
+        }
+    }
+
+    #[test]
+    fn maps_wire_types() {
+        let nullable = |sqltype: u32| sqltype + 1;
+        let row = vec![
+            column(
+                "NAME",
+                nullable(ibase::SQL_VARYING),
+                SqlType::Text("a".into()),
+            ),
+            column("ID", ibase::SQL_INT64, SqlType::Integer(1)),
+            column("BIG", ibase::SQL_INT128, SqlType::Int128(1)),
+            column("PRICE", ibase::SQL_DOUBLE, SqlType::Null),
+            column("AT", ibase::SQL_TIMESTAMP, SqlType::Null),
+            column("OK", ibase::SQL_BOOLEAN, SqlType::Boolean(true)),
+            column("MEMO", ibase::SQL_BLOB, SqlType::Null),
+            column("PHOTO", ibase::SQL_BLOB, SqlType::Binary(vec![1])),
+        ];
+        let schema = row_schema(&row).unwrap();
+        let types: Vec<_> = schema
+            .fields()
+            .iter()
+            .map(|f| f.data_type().clone())
+            .collect();
+        assert_eq!(
+            types,
+            [
+                DataType::Utf8,
+                DataType::Int64,
+                DataType::Decimal128(38, 0),
+                DataType::Float64,
+                DataType::Timestamp(TimeUnit::Microsecond, None),
+                DataType::Boolean,
+                DataType::Utf8,
+                DataType::Binary,
+            ]
+        );
+        assert_eq!(
+            schema.field(6).metadata()[DB_TYPE_KEY],
+            "BLOB SUB_TYPE TEXT"
+        );
+        assert!(schema.fields().iter().all(|f| f.is_nullable()));
+        assert!(row_schema(&[column("A", ibase::SQL_ARRAY, SqlType::Null)]).is_err());
+    }
+
+    #[test]
+    fn builds_batches_from_rows() {
+        let at = chrono_at(1_700_000_000_123_400);
+        let rows = vec![
+            vec![
+                column("ID", ibase::SQL_INT64, SqlType::Integer(7)),
+                column("PRICE", ibase::SQL_DOUBLE, SqlType::Floating(1.5)),
+                column("BIG", ibase::SQL_INT128, SqlType::Int128(-3)),
+                column("AT", ibase::SQL_TIMESTAMP, at),
+                column("MEMO", ibase::SQL_BLOB, SqlType::Null),
+            ],
+            vec![
+                column("ID", ibase::SQL_INT64, SqlType::Null),
+                column("PRICE", ibase::SQL_DOUBLE, SqlType::Null),
+                column("BIG", ibase::SQL_INT128, SqlType::Null),
+                column("AT", ibase::SQL_TIMESTAMP, SqlType::Null),
+                column("MEMO", ibase::SQL_BLOB, SqlType::Binary(b"text".to_vec())),
+            ],
+        ];
+        let mut builder = Builder::new(Arc::new(row_schema(&rows[0]).unwrap())).unwrap();
+        for row in &rows {
+            builder.append(row).unwrap();
+        }
+        let batch = builder.finish().unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.column(0).as_primitive::<Int64Type>().value(0), 7);
+        assert!(batch.column(0).is_null(1));
+        assert_eq!(batch.column(1).as_primitive::<Float64Type>().value(0), 1.5);
+        assert_eq!(
+            batch.column(2).as_primitive::<Decimal128Type>().value(0),
+            -3
+        );
+        assert_eq!(
+            batch
+                .column(3)
+                .as_primitive::<TimestampMicrosecondType>()
+                .value(0),
+            1_700_000_000_123_400
+        );
+        assert!(batch.column(4).is_null(0));
+        assert_eq!(batch.column(4).as_string::<i32>().value(1), "text");
+
+        // The builder starts over after each batch
+        builder.append(&rows[0]).unwrap();
+        assert_eq!(builder.finish().unwrap().num_rows(), 1);
+    }
+
+    #[test]
+    fn rejects_values_of_another_type() {
+        let row = [column("ID", ibase::SQL_INT64, SqlType::Integer(1))];
+        let mut builder = Builder::new(Arc::new(row_schema(&row).unwrap())).unwrap();
+        let error = builder
+            .append(&[column("ID", ibase::SQL_INT64, SqlType::Text("x".into()))])
+            .unwrap_err();
+        assert!(error.to_string().starts_with("Column ID:"), "{}", error);
+        assert!(builder.append(&[]).is_err());
+        let memo = [column("MEMO", ibase::SQL_BLOB, SqlType::Null)];
+        let mut builder = Builder::new(Arc::new(row_schema(&memo).unwrap())).unwrap();
+        assert!(builder
+            .append(&[column("MEMO", ibase::SQL_BLOB, SqlType::Binary(vec![0xff]))])
+            .is_err());
+    }
+
+    #[test]
+    fn wrappers_keep_the_wire_schema() {
+        let row = [column("ID", ibase::SQL_INT64, SqlType::Integer(1))];
+        let driver = row_schema(&row).unwrap();
+        let schema =
+            backend::wrapped_schema(driver, &Domains::default(), &QueryConfig::default()).unwrap();
+        assert_eq!(schema.field(0).data_type(), &DataType::Int64);
+        assert_eq!(schema.field(0).metadata()[DB_TYPE_KEY], "BIGINT");
+    }
+
+    #[test]
+    fn probes_empty_results() {
+        assert_eq!(
+            probe_sql(" SELECT ID FROM T WHERE 1 = 0; "),
+            "SELECT FIRST 1 q.* FROM RDB$DATABASE LEFT JOIN (SELECT ID FROM T WHERE 1 = 0) q ON 1 = 1"
+        );
+    }
+
+    #[test]
+    fn maps_transaction_settings() {
+        let config = QueryConfig {
+            read_only: true,
+            ..Default::default()
+        };
+        let tpb = transaction(&config).unwrap();
+        assert_eq!(tpb.data_access, TrDataAccessMode::ReadOnly);
+        assert_eq!(
+            tpb.isolation,
+            TrIsolationLevel::ReadCommited(TrRecordVersion::RecordVersion)
+        );
+        for (level, isolation) in [
+            ("SNAPSHOT", TrIsolationLevel::Concurrency),
+            ("repeatable_read", TrIsolationLevel::Concurrency),
+            ("serializable", TrIsolationLevel::Consistency),
+        ] {
+            let config = QueryConfig {
+                isolation_level: Some(level.to_string()),
+                ..Default::default()
+            };
+            let tpb = transaction(&config).unwrap();
+            assert_eq!(tpb.isolation, isolation, "{}", level);
+            assert_eq!(tpb.data_access, TrDataAccessMode::ReadWrite);
+        }
+        let config = QueryConfig {
+            isolation_level: Some("chaos".to_string()),
+            ..Default::default()
+        };
+        assert!(transaction(&config).is_err());
+    }
+
+    #[test]
+    fn binds_parameters() {
+        let params = [
+            SqlParam::Null,
+            SqlParam::Bool(true),
+            SqlParam::Int(3),
+            SqlParam::Float(0.5),
+            SqlParam::Text("x".to_string()),
+        ];
+        let bound: Vec<_> = params.iter().map(sql_param).collect();
+        assert!(matches!(
+            bound.as_slice(),
+            [
+                SqlType::Null,
+                SqlType::Boolean(true),
+                SqlType::Integer(3),
+                SqlType::Floating(f),
+                SqlType::Text(t),
+            ] if *f == 0.5 && t == "x"
+        ));
+    }
+
+    #[test]
+    fn credentials_fall_back_to_driver_env() {
+        let config = QueryConfig {
+            driver_env: Some(HashMap::from([
+                ("ISC_USER".to_string(), "SYSDBA".to_string()),
+                ("ISC_PASSWORD".to_string(), "masterkey".to_string()),
+            ])),
+            ..Default::default()
+        };
+        let firebird = Firebird::new("db1:employee", "", "", &config).unwrap();
+        assert_eq!(firebird.user, "SYSDBA");
+        assert_eq!(firebird.password, "masterkey");
+        let firebird = Firebird::new("db1:employee", "ALICE", "secret", &config).unwrap();
+        assert_eq!(firebird.user, "ALICE");
+    }
+
+    #[test]
+    fn unreachable_server_fails_to_attach() {
+        // A port nothing listens on
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = QueryConfig {
+            backend: Some(FIREBIRD.to_string()),
+            ..Default::default()
+        };
+        let dsn = format!("127.0.0.1/{}:employee", port);
+        let firebird = backend::open(&dsn, "SYSDBA", "masterkey", &config).unwrap();
+        assert_eq!(firebird.name(), FIREBIRD);
+        assert!(firebird.metadata(&config).is_err());
+        assert!(firebird
+            .batches(
+                "SELECT 1 AS X FROM RDB$DATABASE",
+                &[],
+                &config,
+                &Watchdog::start(&config)
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn attaches_with_the_wire_protocol() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (mut socket, _) = listener.accept().unwrap();
+            let mut request = vec![0; 512];
+            let read = std::io::Read::read(&mut socket, &mut request).unwrap();
+            request.truncate(read);
+            // Hanging up instead of answering fails the attachment
+            request
+        });
+        let config = QueryConfig::default();
+        let firebird = Firebird::new(
+            &format!("127.0.0.1/{}:employee", port),
+            "SYSDBA",
+            "x",
+            &config,
+        )
+        .unwrap();
+        assert!(firebird.metadata(&config).is_err());
+        let request = server.join().unwrap();
+        // op_connect, then the database name among the connection details
+        assert_eq!(request[..4], [0, 0, 0, 1]);
+        assert!(request.windows(8).any(|w| w == b"employee"));
+    }
+
+    fn chrono_at(micros: i64) -> SqlType {
+        let at = arrow::temporal_conversions::timestamp_us_to_datetime(micros).unwrap();
+        SqlType::Timestamp(at)
+    }
+}
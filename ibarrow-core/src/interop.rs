@@ -0,0 +1,40 @@
+use anyhow::{anyhow, Result};
+
+use crate::QueryConfig;
+
+// How query results reach pandas, set by QueryConfig.arrow_interop
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interop {
+    // pyarrow when it can be imported, the capsule path otherwise
+    Auto,
+    // Through pyarrow Tables
+    PyArrow,
+    // Through Arrow PyCapsule streams only, so pyarrow need not be installed
+    Capsule,
+}
+
+pub fn mode(config: &QueryConfig) -> Result<Interop> {
+    match config
+        .arrow_interop
+        .as_deref()
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        None | Some("auto") => Ok(Interop::Auto),
+        Some("pyarrow") => Ok(Interop::PyArrow),
+        Some("capsule") => Ok(Interop::Capsule),
+        Some(other) => Err(anyhow!(
+            "Unknown arrow_interop '{}': expected 'auto', 'pyarrow' or 'capsule'",
+            other
+        )),
+    }
+}
+
+pub fn validate(config: &QueryConfig) -> Result<()> {
+    if mode(config)? == Interop::Capsule && config.spill_threshold.is_some() {
+        return Err(anyhow!(
+            "arrow_interop='capsule' cannot be combined with spill_threshold, whose spill files are memory-mapped by pyarrow"
+        ));
+    }
+    Ok(())
+}
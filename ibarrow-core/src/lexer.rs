@@ -0,0 +1,169 @@
+// The one scanner for Firebird SQL and PSQL text. Everything that looks inside a statement
+// (access checks, parameter rewriting, statement splitting, comment stripping, wrapping a query
+// in a derived table) goes through it, so they all agree on where string literals, quoted
+// identifiers and comments begin and end.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Whitespace,
+    // `--` up to, not including, the end of the line
+    LineComment,
+    // `/* ... */`
+    BlockComment,
+    // `'...'`, with `''` as an escaped quote
+    Literal,
+    // `"..."`, with `""` as an escaped quote
+    QuotedIdentifier,
+    // `:name`, a named parameter or PSQL variable
+    Parameter,
+    // `?`
+    Placeholder,
+    Number,
+    // Unquoted identifier or keyword
+    Word,
+    // Any other single character
+    Symbol,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: Kind,
+    // The token as written; concatenating every token's text gives back the input
+    pub text: &'a str,
+}
+
+impl Token<'_> {
+    // Whitespace and comments, which separate tokens but mean nothing
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self.kind,
+            Kind::Whitespace | Kind::LineComment | Kind::BlockComment
+        )
+    }
+
+    pub fn is_symbol(&self, symbol: char) -> bool {
+        self.kind == Kind::Symbol && self.text.starts_with(symbol)
+    }
+
+    // The name an identifier refers to: an unquoted word upper-cased the way Firebird stores
+    // it, a quoted identifier as written
+    pub fn identifier(&self) -> Option<String> {
+        match self.kind {
+            Kind::Word => Some(self.text.to_uppercase()),
+            Kind::QuotedIdentifier => Some(unquote(self.text)),
+            _ => None,
+        }
+    }
+}
+
+pub fn tokenize(sql: &str) -> Tokens<'_> {
+    Tokens {
+        sql,
+        pos: 0,
+        prev: None,
+    }
+}
+
+pub struct Tokens<'a> {
+    sql: &'a str,
+    pos: usize,
+    prev: Option<char>,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let rest = &self.sql[self.pos..];
+        let mut chars = rest.chars();
+        let c = chars.next()?;
+        let next = chars.next();
+        let (kind, len) = match c {
+            c if c.is_whitespace() => (Kind::Whitespace, span(rest, char::is_whitespace)),
+            '-' if next == Some('-') => (Kind::LineComment, rest.find('\n').unwrap_or(rest.len())),
+            '/' if next == Some('*') => (
+                Kind::BlockComment,
+                rest[2..].find("*/").map_or(rest.len(), |end| end + 4),
+            ),
+            '\'' => (Kind::Literal, quoted(rest, '\'')),
+            '"' => (Kind::QuotedIdentifier, quoted(rest, '"')),
+            // `::` is not the start of a parameter
+            ':' if next.is_some_and(|n| n.is_alphabetic() || n == '_')
+                && self.prev != Some(':') =>
+            {
+                (
+                    Kind::Parameter,
+                    1 + span(&rest[1..], |c| c.is_alphanumeric() || c == '_'),
+                )
+            }
+            '?' => (Kind::Placeholder, 1),
+            c if c.is_ascii_digit() => (
+                Kind::Number,
+                span(rest, |c| c.is_ascii_alphanumeric() || c == '.'),
+            ),
+            c if c.is_alphabetic() || c == '_' => (
+                Kind::Word,
+                span(rest, |c| c.is_alphanumeric() || c == '_' || c == '$'),
+            ),
+            c => (Kind::Symbol, c.len_utf8()),
+        };
+        let text = &rest[..len];
+        self.pos += len;
+        self.prev = text.chars().last();
+        Some(Token { kind, text })
+    }
+}
+
+// Length of the leading run of characters `accept` takes
+fn span(text: &str, accept: impl Fn(char) -> bool) -> usize {
+    text.find(|c| !accept(c)).unwrap_or(text.len())
+}
+
+// Length of a quoted token up to its closing quote; an unterminated one runs to the end
+fn quoted(text: &str, quote: char) -> usize {
+    let mut chars = text.char_indices().skip(1).peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == quote {
+            if chars.peek().map(|&(_, n)| n) != Some(quote) {
+                return i + 1;
+            }
+            chars.next();
+        }
+    }
+    text.len()
+}
+
+// The name inside a quoted identifier
+pub fn unquote(text: &str) -> String {
+    let inner = text.strip_prefix('"').unwrap_or(text);
+    let inner = inner.strip_suffix('"').unwrap_or(inner);
+    inner.replace("\"\"", "\"")
+}
+
+// `sql` without its comments. A line comment keeps its line break and a block comment becomes
+// a space, so the tokens on either side stay apart.
+pub fn strip_comments(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    for token in tokenize(sql) {
+        match token.kind {
+            Kind::LineComment => {}
+            Kind::BlockComment => out.push(' '),
+            _ => out.push_str(token.text),
+        }
+    }
+    out
+}
+
+// One statement without the whitespace, comments and `;` terminators after it, ready to be
+// wrapped in a derived table or followed by more SQL
+pub fn statement(sql: &str) -> &str {
+    let mut pos = 0;
+    let mut end = 0;
+    for token in tokenize(sql) {
+        pos += token.text.len();
+        if !token.is_trivia() && !token.is_symbol(';') {
+            end = pos;
+        }
+    }
+    sql[..end].trim_start()
+}
@@ -0,0 +1,479 @@
+// ODBC to Arrow for InterBase/Firebird without Python: connects through ODBC, runs queries and
+// streams the results as Arrow record batches. The `ibarrow` Python module is built on top of
+// this crate; with the `python` feature QueryConfig is also a Python class.
+use anyhow::{anyhow, Result};
+use arrow::datatypes::Schema;
+use arrow::record_batch::{RecordBatch, RecordBatchIterator, RecordBatchReader};
+use arrow_odbc::{OdbcReader, OdbcReaderBuilder};
+use odbc_api::handles::StatementConnection;
+use odbc_api::{Cursor, CursorImpl, Environment};
+use std::time::Instant;
+
+pub mod access;
+pub mod adaptive;
+pub mod arrays;
+pub mod backend;
+pub mod catalog;
+pub mod charset;
+pub mod compression;
+mod config;
+mod connection;
+pub mod context;
+pub mod ddl;
+pub mod diagnostics;
+pub mod dictionary;
+pub mod domains;
+pub mod driverenv;
+pub mod drivermgr;
+pub mod emptystr;
+pub mod faults;
+#[cfg(feature = "firebird")]
+pub mod firebird;
+pub mod interop;
+pub mod lexer;
+pub mod params;
+pub mod progress;
+pub mod quoting;
+pub mod restart;
+pub mod retry;
+pub mod sink;
+pub mod snapshot;
+pub mod txwatch;
+pub mod watchdog;
+pub mod write;
+
+pub use config::{validate_config, QueryConfig};
+pub use connection::{connect, Connection, QueryReader};
+
+use backend::Backend;
+use domains::Domains;
+use params::SqlParam;
+use progress::Progress;
+use sink::BatchSink;
+use watchdog::{Watchdog, WatchedStatement};
+
+// Helper function to handle long DSN names by converting to direct connection string
+pub fn build_connection_string(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    config: &QueryConfig,
+) -> String {
+    let user = driverenv::or_variable(user, "ISC_USER", config);
+    let password = driverenv::or_variable(password, "ISC_PASSWORD", config);
+    // Check if dsn is already a full connection string
+    let mut conn_str = if dsn.contains("DRIVER=") || dsn.contains("SERVER=") {
+        // It's already a connection string, use it directly
+        format!("{};UID={};PWD={};", dsn, user, password)
+    } else {
+        // Check if DSN contains a file path (common cause of long DSN names)
+        let is_file_path = dsn.contains("\\")
+            || dsn.contains("/")
+            || dsn.contains(":")
+            || dsn.ends_with(".fdb")
+            || dsn.ends_with(".gdb");
+
+        if is_file_path || dsn.len() > 32 {
+            // Convert file path or long DSN to direct connection string
+            // Use DATABASE parameter for file paths, which is more elegant
+            if is_file_path {
+                format!(
+                    "DRIVER={{InterBase ODBC Driver}};DATABASE={};UID={};PWD={};",
+                    dsn, user, password
+                )
+            } else {
+                format!(
+                    "DRIVER={{InterBase ODBC Driver}};DSN={};UID={};PWD={};",
+                    dsn, user, password
+                )
+            }
+        } else {
+            // It's a DSN, use DSN format
+            format!("DSN={};UID={};PWD={};", dsn, user, password)
+        }
+    };
+
+    if config.read_only {
+        conn_str.push_str("ReadOnly=1;");
+    }
+
+    if let Some(timeout) = config.connection_timeout {
+        conn_str.push_str(&format!("Connection Timeout={};", timeout));
+    }
+
+    if let Some(timeout) = config.query_timeout {
+        conn_str.push_str(&format!("Query Timeout={};", timeout));
+    }
+
+    if let Some(level) = &config.isolation_level {
+        match level.to_lowercase().as_str() {
+            "read_uncommitted" => conn_str.push_str("Isolation Level=ReadUncommitted;"),
+            "read_committed" => conn_str.push_str("Isolation Level=ReadCommitted;"),
+            "repeatable_read" => conn_str.push_str("Isolation Level=RepeatableRead;"),
+            "serializable" => conn_str.push_str("Isolation Level=Serializable;"),
+            "snapshot" => conn_str.push_str("Isolation Level=Snapshot;"),
+            _ => {
+                // If unknown level, pass through as-is (driver-specific)
+                conn_str.push_str(&format!("Isolation Level={};", level));
+            }
+        }
+    }
+
+    conn_str
+}
+
+pub type OwnedCursor<'env> =
+    CursorImpl<WatchedStatement<StatementConnection<odbc_api::Connection<'env>>>>;
+
+// Connects and executes `sql`, returning a cursor that owns its connection along with the
+// domains of its columns
+#[allow(clippy::too_many_arguments)]
+pub fn open_cursor<'env>(
+    env: &'env Environment,
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    params: &[SqlParam],
+    config: &QueryConfig,
+    watchdog: &Watchdog,
+) -> Result<Option<(OwnedCursor<'env>, Domains)>> {
+    access::check(sql, config)?;
+    diagnostics::clear();
+
+    // Build connection string with long DSN name handling
+    let conn_str = build_connection_string(dsn, user, password, config);
+
+    // Sessions that do not speak UTF-8 get the statement in their own charset
+    let statement = charset::transcode(sql, params, config.sql_charset.as_deref())?;
+    // Nothing has been fetched yet, so transient failures can simply be retried
+    retry::with_retry(config, || {
+        let conn = driverenv::connect(env, &conn_str, config)?;
+        let domains = domains::resolve(&conn, &conn_str, &statement)?;
+        watchdog.check()?;
+        let cursor = statement.execute(watchdog.watch(conn.into_preallocated()?.into_handle()))?;
+        Ok(cursor.map(|cursor| (cursor, domains)))
+    })
+}
+
+// Applies the fetch buffer settings from QueryConfig to a new Arrow reader
+pub fn build_reader<C: Cursor>(cursor: C, config: &QueryConfig) -> Result<OdbcReader<C>> {
+    Ok(reader_builder(config).build(cursor)?)
+}
+
+// Arrow batches from a cursor owning its connection
+pub type BatchReader = Box<dyn RecordBatchReader + Send>;
+
+// Like build_reader, but with QueryConfig.prefetch the next batch is fetched on a background
+// thread while the current one is converted and written
+pub fn batch_reader<C: Cursor + Send + 'static>(
+    (cursor, domains): (C, Domains),
+    config: &QueryConfig,
+) -> Result<BatchReader> {
+    into_batches(reader_builder(config), cursor, &domains, config)
+}
+
+pub fn into_batches<C: Cursor + Send + 'static>(
+    builder: OdbcReaderBuilder,
+    mut cursor: C,
+    domains: &Domains,
+    config: &QueryConfig,
+) -> Result<BatchReader> {
+    let padded = emptystr::padded(&mut cursor)?;
+    if adaptive::enabled(config) {
+        let reader = adaptive::Adaptive::new(builder, cursor, config)?;
+        return wrap_batches(Box::new(reader), domains, &padded, config);
+    }
+    let reader = builder.build(cursor)?;
+    let reader: BatchReader = if config.prefetch {
+        log::debug!("Prefetching batches on a background thread");
+        Box::new(reader.into_concurrent()?)
+    } else {
+        Box::new(reader)
+    };
+    wrap_batches(reader, domains, &padded, config)
+}
+
+// Applies the per-batch transformations QueryConfig asks for to the fetched batches. `padded`
+// marks the blank-padded CHAR columns, see emptystr::padded.
+pub fn wrap_batches(
+    reader: BatchReader,
+    domains: &Domains,
+    padded: &[bool],
+    config: &QueryConfig,
+) -> Result<BatchReader> {
+    let reader = domains::wrap(faults::wrap(reader), domains);
+    emptystr::wrap(arrays::wrap(reader, config)?, padded, config)
+}
+
+pub fn reader_builder(config: &QueryConfig) -> OdbcReaderBuilder {
+    let text_size = config.max_text_size.unwrap_or(65536);
+    let binary_size = config.max_binary_size.unwrap_or(65536);
+
+    let mut builder = OdbcReaderBuilder::new();
+    builder.with_max_text_size(text_size as usize);
+    builder.with_max_binary_size(binary_size as usize);
+    if let Some(rows) = config.batch_size {
+        builder.with_max_num_rows_per_batch(rows as usize);
+    } else if adaptive::enabled(config) {
+        // Only the first batch uses this; see adaptive::Adaptive
+        builder.with_max_num_rows_per_batch(adaptive::SAMPLE_ROWS);
+    }
+    // Whichever limit is smaller wins, so wide rows get fewer rows per batch
+    if let Some(bytes) = config.max_bytes_per_batch {
+        builder.with_max_bytes_per_batch(bytes as usize);
+    }
+    builder
+}
+
+// Rows QueryConfig.max_rows still allows after `produced`; None means no limit
+pub fn rows_left(config: &QueryConfig, produced: usize) -> Option<usize> {
+    config
+        .max_rows
+        .map(|max| (max as usize).saturating_sub(produced))
+}
+
+// Drops the rows of `batch` beyond `left`
+pub fn trim_batch(batch: RecordBatch, left: Option<usize>) -> RecordBatch {
+    match left {
+        Some(left) if left < batch.num_rows() => batch.slice(0, left),
+        _ => batch,
+    }
+}
+
+// Position of a result column by name. Unquoted Firebird identifiers come back in upper
+// case, so a case-insensitive match is accepted when there is no exact one.
+pub fn find_column(schema: &Schema, name: &str) -> Option<usize> {
+    let fields = schema.fields();
+    fields.iter().position(|f| f.name() == name).or_else(|| {
+        fields
+            .iter()
+            .position(|f| f.name().eq_ignore_ascii_case(name))
+    })
+}
+
+// Ends a batch iterator once QueryConfig.max_rows rows have been produced, without asking the
+// driver for another batch
+pub struct RowLimit<I> {
+    batches: I,
+    left: Option<usize>,
+}
+
+impl<I, E> Iterator for RowLimit<I>
+where
+    I: Iterator<Item = std::result::Result<RecordBatch, E>>,
+{
+    type Item = std::result::Result<RecordBatch, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.left == Some(0) {
+            return None;
+        }
+        let batch = self
+            .batches
+            .next()?
+            .map(|batch| trim_batch(batch, self.left));
+        if let (Ok(batch), Some(left)) = (&batch, self.left.as_mut()) {
+            *left -= batch.num_rows();
+        }
+        Some(batch)
+    }
+}
+
+pub fn limit_rows<I>(batches: I, config: &QueryConfig) -> RowLimit<I> {
+    RowLimit {
+        batches,
+        left: rows_left(config, 0),
+    }
+}
+
+// Runs `sql` in this process and hands the result to `sink` batch by batch, observing every
+// batch
+#[allow(clippy::too_many_arguments)]
+pub fn fetch_into(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    params: &[SqlParam],
+    config: &QueryConfig,
+    sink: &mut dyn BatchSink,
+    observe: &mut dyn FnMut(&RecordBatch) -> Result<()>,
+) -> Result<usize> {
+    let backend = backend::open(dsn, user, password, config)?;
+    fetch_from(backend.as_ref(), sql, params, config, sink, observe)
+}
+
+// Like fetch_into, reading through `backend`
+pub fn fetch_from(
+    backend: &dyn Backend,
+    sql: &str,
+    params: &[SqlParam],
+    config: &QueryConfig,
+    sink: &mut dyn BatchSink,
+    observe: &mut dyn FnMut(&RecordBatch) -> Result<()>,
+) -> Result<usize> {
+    let watchdog = Watchdog::start(config);
+    let result = fetch_watched(backend, sql, params, config, sink, observe, &watchdog);
+    watchdog.finish(result)
+}
+
+fn fetch_watched(
+    backend: &dyn Backend,
+    sql: &str,
+    params: &[SqlParam],
+    config: &QueryConfig,
+    sink: &mut dyn BatchSink,
+    observe: &mut dyn FnMut(&RecordBatch) -> Result<()>,
+    watchdog: &Watchdog,
+) -> Result<usize> {
+    restart::validate(config)?;
+    let first_sql = restart::initial_sql(sql, config);
+    let mut arrow_record_batches = match backend.batches(&first_sql, params, config, watchdog)? {
+        Some(reader) => reader,
+        None => {
+            // Query executed successfully but returned no result set
+            // Return a valid empty Arrow stream with empty schema
+            log::debug!("Creating empty Arrow stream for cursor None");
+            let schema_ref = std::sync::Arc::new(Schema::empty());
+            sink.begin(&schema_ref)?;
+            let empty_batch = RecordBatch::new_empty(schema_ref);
+            sink.write(&empty_batch)
+                .map_err(|e| anyhow!("ERROR: Failed to write empty batch: {}", e))?;
+            sink.finish()?;
+            log::debug!("Successfully created empty Arrow stream");
+            return Ok(0);
+        }
+    };
+
+    let refresh = restart::refresh_interval(config)?;
+    // Continues the query in a new statement and transaction after the rows already delivered
+    let reopen = |last_key: Option<&str>| -> Result<BatchReader> {
+        let resume_sql = restart::resume_sql(sql, config, last_key);
+        backend
+            .batches(&resume_sql, params, config, watchdog)?
+            .ok_or_else(|| anyhow!("Restarted query returned no result set"))
+    };
+
+    let mut num_rows = 0;
+    {
+        let mut encoder = dictionary::Encoder::new(config);
+        let schema = encoder.schema(&arrow_record_batches.schema())?;
+        log::debug!(
+            "Creating StreamWriter with schema: {} fields",
+            schema.fields().len()
+        );
+
+        // Pipelining: write each batch immediately as it's fetched
+        // This keeps memory usage constant instead of accumulating all data
+        sink.begin(&schema)?;
+
+        let mut progress = Progress::new(config);
+        let mut wrote = false;
+        let mut batch_count = 0;
+        let mut restarts = 0;
+        let mut checkpoint = config.restart_key.as_deref().map(restart::Checkpoint::new);
+        let mut transaction_started = Instant::now();
+        while rows_left(config, num_rows) != Some(0) {
+            let Some(batch) = arrow_record_batches.next() else {
+                break;
+            };
+            // A cancelled statement must not be restarted
+            watchdog.check()?;
+            let batch = match batch {
+                Ok(batch) => batch,
+                Err(e) => {
+                    // Transient failure mid-stream: resume after the last delivered row
+                    let mut error = anyhow!("ERROR: Failed to read batch {}: {}", batch_count, e);
+                    loop {
+                        if restarts >= config.max_restarts.unwrap_or(0) {
+                            return Err(error);
+                        }
+                        restarts += 1;
+                        log::warn!(
+                            "{} - restarting after row {} (attempt {})",
+                            error,
+                            num_rows,
+                            restarts
+                        );
+                        match reopen(checkpoint.as_ref().and_then(|c| c.last_key())) {
+                            Ok(reader) => {
+                                arrow_record_batches = reader;
+                                if let Some(checkpoint) = &mut checkpoint {
+                                    checkpoint.resumed();
+                                }
+                                break;
+                            }
+                            Err(e) => error = e,
+                        }
+                    }
+                    continue;
+                }
+            };
+            let batch = match &mut checkpoint {
+                Some(checkpoint) => checkpoint.skip_seen(batch)?,
+                None => batch,
+            };
+            if batch.num_rows() == 0 {
+                continue;
+            }
+            let batch = trim_batch(batch, rows_left(config, num_rows));
+            if let Some(checkpoint) = &mut checkpoint {
+                checkpoint.record(&batch)?;
+            }
+            faults::hit(faults::Point::Convert)?;
+            let batch = encoder.encode(batch, &schema)?;
+            observe(&batch)?;
+            progress.batch(&batch)?;
+            sink.write(&batch)
+                .map_err(|e| anyhow!("ERROR: Failed to write batch {}: {}", batch_count, e))?;
+            wrote = true;
+            batch_count += 1;
+            num_rows += batch.num_rows();
+            // Each batch is written immediately, freeing memory
+            // Memory usage stays constant regardless of dataset size
+
+            // A long read keeps its transaction open, holding back garbage collection on the
+            // server; end it and carry on after the last key in a fresh one
+            if refresh.is_some_and(|every| transaction_started.elapsed() >= every)
+                && rows_left(config, num_rows) != Some(0)
+            {
+                log::debug!(
+                    "Refreshing read transaction after row {} (key {})",
+                    num_rows,
+                    checkpoint
+                        .as_ref()
+                        .and_then(|c| c.last_key())
+                        .unwrap_or("none")
+                );
+                // The old statement and its connection go first so the transactions never overlap
+                let finished: BatchReader = Box::new(RecordBatchIterator::new(
+                    Vec::new(),
+                    arrow_record_batches.schema(),
+                ));
+                drop(std::mem::replace(&mut arrow_record_batches, finished));
+                arrow_record_batches = reopen(checkpoint.as_ref().and_then(|c| c.last_key()))?;
+                if let Some(checkpoint) = &mut checkpoint {
+                    checkpoint.resumed();
+                }
+                transaction_started = Instant::now();
+            }
+        }
+
+        // If no data was written, write an empty batch to ensure valid stream
+        if !wrote {
+            log::debug!("No data batches, writing empty batch");
+            let empty_batch = RecordBatch::new_empty(schema.clone());
+            sink.write(&empty_batch)
+                .map_err(|e| anyhow!("ERROR: Failed to write empty batch: {}", e))?;
+        } else {
+            log::debug!("Wrote {} data batches", batch_count);
+        }
+
+        // Always finish the writer to ensure proper footer - guaranteed execution
+        sink.finish()?;
+        log::debug!("Successfully finished Arrow stream ({} rows)", num_rows);
+    }
+
+    Ok(num_rows)
+}
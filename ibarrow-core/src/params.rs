@@ -0,0 +1,134 @@
+use anyhow::{anyhow, Result};
+use odbc_api::parameter::{InputParameter, VarCharBox};
+use odbc_api::{Bit, IntoParameter};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::lexer::{self, Kind};
+
+// A query parameter, bound through ODBC as `?`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SqlParam {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    // Text already encoded in the session charset
+    Encoded(Vec<u8>),
+}
+
+impl SqlParam {
+    fn to_input(&self) -> Box<dyn InputParameter> {
+        match self {
+            SqlParam::Null => Box::new(None::<String>.into_parameter()),
+            SqlParam::Bool(b) => Box::new(Bit::from_bool(*b)),
+            SqlParam::Int(i) => Box::new(*i),
+            SqlParam::Float(f) => Box::new(*f),
+            SqlParam::Text(s) => Box::new(s.clone().into_parameter()),
+            SqlParam::Encoded(bytes) => Box::new(VarCharBox::from_vec(bytes.clone())),
+        }
+    }
+}
+
+// Parameters as given by the caller: a map for `:name` placeholders or a sequence for `?`
+pub enum QueryParams {
+    Named(HashMap<String, SqlParam>),
+    Positional(Vec<SqlParam>),
+}
+
+// Turns the SQL text and parameters into ODBC text with `?` markers plus values in order
+pub fn bind(sql: &str, params: Option<QueryParams>) -> Result<(String, Vec<SqlParam>)> {
+    match params {
+        None => Ok((sql.to_string(), Vec::new())),
+        Some(QueryParams::Positional(values)) => Ok((sql.to_string(), values)),
+        Some(QueryParams::Named(values)) => bind_named(sql, &values),
+    }
+}
+
+// Rewrites `:name` placeholders outside literals and comments into `?`
+pub fn bind_named(
+    sql: &str,
+    values: &HashMap<String, SqlParam>,
+) -> Result<(String, Vec<SqlParam>)> {
+    let mut bound = Vec::new();
+    let out = rewrite(sql, &mut |name| {
+        let value = values
+            .get(name)
+            .ok_or_else(|| anyhow!("Missing value for SQL parameter :{}", name))?;
+        bound.push(value.clone());
+        Ok(())
+    })?;
+    Ok((out, bound))
+}
+
+// Names of the `:name` placeholders in `sql`, in order of appearance and possibly repeated
+pub fn placeholders(sql: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let _ = rewrite(sql, &mut |name| {
+        names.push(name.to_string());
+        Ok(())
+    });
+    names
+}
+
+// Replaces each `:name` placeholder with `?`, passing its name to `on_name`
+fn rewrite(sql: &str, on_name: &mut dyn FnMut(&str) -> Result<()>) -> Result<String> {
+    let mut out = String::with_capacity(sql.len());
+    for token in lexer::tokenize(sql) {
+        if token.kind == Kind::Parameter {
+            on_name(&token.text[1..])?;
+            out.push('?');
+        } else {
+            out.push_str(token.text);
+        }
+    }
+    Ok(out)
+}
+
+// ODBC input parameters for a bound statement
+pub fn to_inputs(params: &[SqlParam]) -> Vec<Box<dyn InputParameter>> {
+    params.iter().map(SqlParam::to_input).collect()
+}
+
+#[cfg(feature = "python")]
+mod python {
+    use pyo3::prelude::*;
+    use pyo3::types::{PyBool, PyDict, PyFloat, PyList, PyLong, PyString, PyTuple};
+
+    use super::{QueryParams, SqlParam};
+
+    impl<'py> FromPyObject<'py> for SqlParam {
+        fn extract_bound(value: &Bound<'py, PyAny>) -> PyResult<Self> {
+            if value.is_none() {
+                Ok(SqlParam::Null)
+            } else if value.is_instance_of::<PyBool>() {
+                Ok(SqlParam::Bool(value.extract()?))
+            } else if value.is_instance_of::<PyLong>() {
+                Ok(SqlParam::Int(value.extract()?))
+            } else if value.is_instance_of::<PyFloat>() {
+                Ok(SqlParam::Float(value.extract()?))
+            } else if value.is_instance_of::<PyString>() {
+                Ok(SqlParam::Text(value.extract()?))
+            } else {
+                // Dates, datetimes and decimals bind through their text form,
+                // which Firebird/InterBase cast implicitly
+                Ok(SqlParam::Text(value.str()?.extract()?))
+            }
+        }
+    }
+
+    impl<'py> FromPyObject<'py> for QueryParams {
+        fn extract_bound(value: &Bound<'py, PyAny>) -> PyResult<Self> {
+            if let Ok(dict) = value.downcast::<PyDict>() {
+                Ok(QueryParams::Named(dict.extract()?))
+            } else if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+                Ok(QueryParams::Positional(value.extract()?))
+            } else {
+                Err(pyo3::exceptions::PyTypeError::new_err(
+                    "params must be a dict or a list/tuple",
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use anyhow::Result;
+use arrow::record_batch::RecordBatch;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::QueryConfig;
+
+// Called with the rows and bytes fetched so far plus the rows of the latest batch; an error
+// aborts the fetch
+pub type ProgressFn = dyn Fn(usize, usize, usize) -> Result<()> + Send + Sync;
+
+// QueryConfig.progress: a Rust closure, or from Python a callable or an object with `update`
+#[derive(Clone)]
+pub enum ProgressHook {
+    Rust(Arc<ProgressFn>),
+    #[cfg(feature = "python")]
+    Python(pyo3::Py<pyo3::PyAny>),
+}
+
+impl ProgressHook {
+    pub fn new(hook: impl Fn(usize, usize, usize) -> Result<()> + Send + Sync + 'static) -> Self {
+        ProgressHook::Rust(Arc::new(hook))
+    }
+
+    fn call(&self, rows: usize, bytes: usize, batch_rows: usize) -> Result<()> {
+        match self {
+            ProgressHook::Rust(hook) => hook(rows, bytes, batch_rows),
+            #[cfg(feature = "python")]
+            ProgressHook::Python(hook) => python::call(hook, rows, bytes, batch_rows),
+        }
+    }
+}
+
+impl fmt::Debug for ProgressHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgressHook::Rust(_) => f.write_str("ProgressHook::Rust"),
+            #[cfg(feature = "python")]
+            ProgressHook::Python(hook) => write!(f, "ProgressHook::Python({:?})", hook),
+        }
+    }
+}
+
+// Reports fetch progress to QueryConfig.progress after every batch. A Python callable is called
+// as `progress(rows, bytes)` with the totals so far; an object with an `update` method, such as
+// a tqdm bar, gets `update(rows_in_batch)`. An exception raised by the hook aborts the fetch
+// and is re-raised unchanged.
+pub struct Progress {
+    hook: Option<ProgressHook>,
+    rows: usize,
+    bytes: usize,
+}
+
+impl Progress {
+    pub fn new(config: &QueryConfig) -> Self {
+        Self {
+            hook: config.progress.clone(),
+            rows: 0,
+            bytes: 0,
+        }
+    }
+
+    pub fn batch(&mut self, batch: &RecordBatch) -> Result<()> {
+        self.rows += batch.num_rows();
+        self.bytes += batch.get_array_memory_size();
+        match &self.hook {
+            Some(hook) => hook.call(self.rows, self.bytes, batch.num_rows()),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+mod python {
+    use anyhow::Result;
+    use pyo3::prelude::*;
+
+    use super::ProgressHook;
+
+    pub fn call(hook: &Py<PyAny>, rows: usize, bytes: usize, batch_rows: usize) -> Result<()> {
+        Python::with_gil(|py| {
+            let hook = hook.bind(py);
+            if hook.is_callable() {
+                hook.call1((rows, bytes))?;
+            } else {
+                hook.call_method1("update", (batch_rows,))?;
+            }
+            Ok(())
+        })
+    }
+
+    impl<'py> FromPyObject<'py> for ProgressHook {
+        fn extract_bound(hook: &Bound<'py, PyAny>) -> PyResult<Self> {
+            Ok(ProgressHook::Python(hook.clone().unbind()))
+        }
+    }
+
+    // Closures set from Rust read back as None
+    impl IntoPy<PyObject> for ProgressHook {
+        fn into_py(self, py: Python<'_>) -> PyObject {
+            match self {
+                ProgressHook::Python(hook) => hook,
+                ProgressHook::Rust(_) => py.None(),
+            }
+        }
+    }
+}
@@ -0,0 +1,243 @@
+// Firebird 5 reserved words; identifiers spelling one of them must be quoted
+pub const RESERVED: &[&str] = &[
+    "ADD",
+    "ADMIN",
+    "ALL",
+    "ALTER",
+    "AND",
+    "ANY",
+    "AS",
+    "AT",
+    "AVG",
+    "BEGIN",
+    "BETWEEN",
+    "BIGINT",
+    "BINARY",
+    "BIT_LENGTH",
+    "BLOB",
+    "BOOLEAN",
+    "BOTH",
+    "BY",
+    "CASE",
+    "CAST",
+    "CHAR",
+    "CHAR_LENGTH",
+    "CHARACTER",
+    "CHARACTER_LENGTH",
+    "CHECK",
+    "CLOSE",
+    "COLLATE",
+    "COLUMN",
+    "COMMENT",
+    "COMMIT",
+    "CONNECT",
+    "CONSTRAINT",
+    "CORR",
+    "COUNT",
+    "COVAR_POP",
+    "COVAR_SAMP",
+    "CREATE",
+    "CROSS",
+    "CURRENT",
+    "CURRENT_CONNECTION",
+    "CURRENT_DATE",
+    "CURRENT_ROLE",
+    "CURRENT_TIME",
+    "CURRENT_TIMESTAMP",
+    "CURRENT_TRANSACTION",
+    "CURRENT_USER",
+    "CURSOR",
+    "DATE",
+    "DAY",
+    "DEC",
+    "DECFLOAT",
+    "DECIMAL",
+    "DECLARE",
+    "DEFAULT",
+    "DELETE",
+    "DELETING",
+    "DETERMINISTIC",
+    "DISCONNECT",
+    "DISTINCT",
+    "DOUBLE",
+    "DROP",
+    "ELSE",
+    "END",
+    "ESCAPE",
+    "EXECUTE",
+    "EXISTS",
+    "EXTERNAL",
+    "EXTRACT",
+    "FALSE",
+    "FETCH",
+    "FILTER",
+    "FLOAT",
+    "FOR",
+    "FOREIGN",
+    "FROM",
+    "FULL",
+    "FUNCTION",
+    "GDSCODE",
+    "GLOBAL",
+    "GRANT",
+    "GROUP",
+    "HAVING",
+    "HOUR",
+    "IN",
+    "INDEX",
+    "INNER",
+    "INSENSITIVE",
+    "INSERT",
+    "INSERTING",
+    "INT",
+    "INT128",
+    "INTEGER",
+    "INTO",
+    "IS",
+    "JOIN",
+    "LATERAL",
+    "LEADING",
+    "LEFT",
+    "LIKE",
+    "LOCAL",
+    "LOCALTIME",
+    "LOCALTIMESTAMP",
+    "LONG",
+    "LOWER",
+    "MAX",
+    "MERGE",
+    "MIN",
+    "MINUTE",
+    "MONTH",
+    "NATIONAL",
+    "NATURAL",
+    "NCHAR",
+    "NO",
+    "NOT",
+    "NULL",
+    "NUMERIC",
+    "OCTET_LENGTH",
+    "OF",
+    "OFFSET",
+    "ON",
+    "ONLY",
+    "OPEN",
+    "OR",
+    "ORDER",
+    "OUTER",
+    "OVER",
+    "PARAMETER",
+    "PLAN",
+    "POSITION",
+    "POST_EVENT",
+    "PRECISION",
+    "PRIMARY",
+    "PROCEDURE",
+    "PUBLICATION",
+    "RDB$DB_KEY",
+    "RDB$ERROR",
+    "RDB$GET_CONTEXT",
+    "RDB$GET_TRANSACTION_CN",
+    "RDB$RECORD_VERSION",
+    "RDB$ROLE_IN_USE",
+    "RDB$SET_CONTEXT",
+    "RDB$SYSTEM_PRIVILEGE",
+    "REAL",
+    "RECORD_VERSION",
+    "RECREATE",
+    "RECURSIVE",
+    "REFERENCES",
+    "REGR_AVGX",
+    "REGR_AVGY",
+    "REGR_COUNT",
+    "REGR_INTERCEPT",
+    "REGR_R2",
+    "REGR_SLOPE",
+    "REGR_SXX",
+    "REGR_SXY",
+    "REGR_SYY",
+    "RELEASE",
+    "RESETTING",
+    "RETURN",
+    "RETURNING_VALUES",
+    "RETURNS",
+    "REVOKE",
+    "RIGHT",
+    "ROLLBACK",
+    "ROW",
+    "ROW_COUNT",
+    "ROWS",
+    "SAVEPOINT",
+    "SCROLL",
+    "SECOND",
+    "SELECT",
+    "SENSITIVE",
+    "SET",
+    "SIMILAR",
+    "SMALLINT",
+    "SOME",
+    "SQLCODE",
+    "SQLSTATE",
+    "START",
+    "STDDEV_POP",
+    "STDDEV_SAMP",
+    "SUM",
+    "TABLE",
+    "THEN",
+    "TIME",
+    "TIMESTAMP",
+    "TIMEZONE_HOUR",
+    "TIMEZONE_MINUTE",
+    "TO",
+    "TRAILING",
+    "TRIGGER",
+    "TRIM",
+    "TRUE",
+    "UNBOUNDED",
+    "UNION",
+    "UNIQUE",
+    "UNKNOWN",
+    "UPDATE",
+    "UPDATING",
+    "UPPER",
+    "USER",
+    "USING",
+    "VALUE",
+    "VALUES",
+    "VAR_POP",
+    "VAR_SAMP",
+    "VARBINARY",
+    "VARCHAR",
+    "VARIABLE",
+    "VARYING",
+    "VIEW",
+    "WHEN",
+    "WHERE",
+    "WHILE",
+    "WINDOW",
+    "WITH",
+    "WITHOUT",
+    "YEAR",
+];
+
+// Whether `name` must be double-quoted to keep its spelling: Firebird folds unquoted
+// identifiers to upper case, so only upper-case names of letters, digits, `_` and `$` that
+// start with a letter and are not reserved can go unquoted
+pub fn requires_quotes(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_with_letter = chars.next().is_some_and(|c| c.is_ascii_uppercase());
+    let legal = name
+        .chars()
+        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_' || c == '$');
+    !starts_with_letter || !legal || name.len() > 63 || RESERVED.contains(&name)
+}
+
+// `name` as it must appear in Firebird SQL: unchanged when it needs no quotes, otherwise
+// between double quotes with embedded quotes doubled
+pub fn quote_identifier(name: &str) -> String {
+    if requires_quotes(name) {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    } else {
+        name.to_string()
+    }
+}
@@ -0,0 +1,233 @@
+use anyhow::{anyhow, Result};
+use arrow::array::{Array, ArrayRef, BooleanArray};
+use arrow::compute::filter_record_batch;
+use arrow::datatypes::DataType;
+use arrow::record_batch::RecordBatch;
+use arrow::row::{OwnedRow, RowConverter, SortField};
+use arrow::util::display::array_value_to_string;
+use std::time::Duration;
+
+use crate::lexer;
+use crate::QueryConfig;
+
+// Alias for the derived table used when wrapping the user's query
+const RESUME_ALIAS: &str = "ibarrow_resume";
+
+// First execution of a restartable query; keyset restarts need a stable key order. The query
+// goes into a derived table without its terminator and trailing comments, which would
+// otherwise swallow the closing parenthesis.
+pub fn initial_sql(sql: &str, config: &QueryConfig) -> String {
+    let resumable =
+        config.max_restarts.unwrap_or(0) > 0 || config.transaction_refresh_secs.is_some();
+    match &config.restart_key {
+        Some(key) if resumable => {
+            format!(
+                "SELECT * FROM ({}) {} ORDER BY {}",
+                lexer::statement(sql),
+                RESUME_ALIAS,
+                key
+            )
+        }
+        _ => sql.to_string(),
+    }
+}
+
+pub fn validate_refresh(secs: Option<u64>) -> Result<()> {
+    if secs == Some(0) {
+        return Err(anyhow!("transaction_refresh_secs must be greater than 0"));
+    }
+    Ok(())
+}
+
+// How often a long extract ends its read transaction and continues in a new one, from
+// QueryConfig.transaction_refresh_secs. Continuing needs restart_key, resumed like a restart
+// (see resume_sql and Checkpoint), so a refresh in the middle of rows sharing a key value
+// neither loses nor repeats them.
+pub fn refresh_interval(config: &QueryConfig) -> Result<Option<Duration>> {
+    let Some(secs) = config.transaction_refresh_secs else {
+        return Ok(None);
+    };
+    validate_refresh(Some(secs))?;
+    if config.restart_key.is_none() {
+        return Err(anyhow!(
+            "transaction_refresh_secs needs restart_key to resume in the new transaction"
+        ));
+    }
+    Ok(Some(Duration::from_secs(secs)))
+}
+
+// Restarts resume by restart_key: skipping a count of rows (ROWS n TO m) is only right when
+// the query's order is total, which ibarrow cannot check
+pub fn validate(config: &QueryConfig) -> Result<()> {
+    if config.max_restarts.unwrap_or(0) > 0 && config.restart_key.is_none() {
+        return Err(anyhow!(
+            "max_restarts needs restart_key to know where to resume"
+        ));
+    }
+    Ok(())
+}
+
+// Re-execution after a failure or a transaction refresh, from the last key delivered on. The
+// key need not be unique, so the rows holding it are read again; Checkpoint drops the ones
+// that were already delivered.
+pub fn resume_sql(sql: &str, config: &QueryConfig, last_key: Option<&str>) -> String {
+    match (&config.restart_key, last_key) {
+        (Some(key), Some(last)) => format!(
+            "SELECT * FROM ({}) {} WHERE {} >= {} ORDER BY {}",
+            lexer::statement(sql),
+            RESUME_ALIAS,
+            key,
+            last,
+            key
+        ),
+        _ => initial_sql(sql, config),
+    }
+}
+
+fn key_column<'a>(batch: &'a RecordBatch, key: &str) -> Result<&'a ArrayRef> {
+    batch
+        .column_by_name(key)
+        .or_else(|| {
+            // ODBC drivers commonly report upper-case column names
+            let upper = key.to_uppercase();
+            batch.column_by_name(&upper)
+        })
+        .ok_or_else(|| anyhow!("restart_key column '{}' is not in the result set", key))
+}
+
+// Where a restartable query stands: the last restart key delivered and every delivered row
+// holding it. A resumed query reads those rows again, and skip_seen drops them.
+pub struct Checkpoint {
+    key: String,
+    // SQL literal and display text of the last key
+    last: Option<(String, String)>,
+    ties: Vec<OwnedRow>,
+    // Rows of `ties` the resumed query has not yet read again
+    pending: Vec<OwnedRow>,
+    converter: Option<RowConverter>,
+}
+
+impl Checkpoint {
+    pub fn new(key: &str) -> Self {
+        Self {
+            key: key.to_string(),
+            last: None,
+            ties: Vec::new(),
+            pending: Vec::new(),
+            converter: None,
+        }
+    }
+
+    // SQL literal of the last key delivered, for resume_sql
+    pub fn last_key(&self) -> Option<&str> {
+        self.last.as_ref().map(|(literal, _)| literal.as_str())
+    }
+
+    fn converter(&mut self, batch: &RecordBatch) -> Result<&RowConverter> {
+        if self.converter.is_none() {
+            let fields = batch
+                .schema()
+                .fields()
+                .iter()
+                .map(|field| SortField::new(field.data_type().clone()))
+                .collect();
+            self.converter = Some(RowConverter::new(fields)?);
+        }
+        Ok(self.converter.as_ref().expect("converter was just set"))
+    }
+
+    // Notes a delivered batch, which comes in key order
+    pub fn record(&mut self, batch: &RecordBatch) -> Result<()> {
+        let Some(literal) = last_key_literal(batch, &self.key)? else {
+            return Ok(());
+        };
+        let column = key_column(batch, &self.key)?.clone();
+        let last_row = batch.num_rows() - 1;
+        let text = array_value_to_string(&column, last_row)?;
+        // Rows with the same key sit together at the end of the batch
+        let mut first = last_row;
+        while first > 0
+            && !column.is_null(first - 1)
+            && array_value_to_string(&column, first - 1)? == text
+        {
+            first -= 1;
+        }
+        let rows = self.converter(batch)?.convert_columns(batch.columns())?;
+        let ties = (first..=last_row).map(|i| rows.row(i).owned());
+        if self.last.as_ref().is_some_and(|(_, last)| *last == text) {
+            self.ties.extend(ties);
+        } else {
+            self.ties = ties.collect();
+            self.last = Some((literal, text));
+        }
+        Ok(())
+    }
+
+    // Called when the query is executed again from the last key
+    pub fn resumed(&mut self) {
+        self.pending = self.ties.clone();
+    }
+
+    // Drops the rows of a resumed query that were delivered before it resumed
+    pub fn skip_seen(&mut self, batch: RecordBatch) -> Result<RecordBatch> {
+        if self.pending.is_empty() || batch.num_rows() == 0 {
+            return Ok(batch);
+        }
+        let column = key_column(&batch, &self.key)?.clone();
+        let rows = self.converter(&batch)?.convert_columns(batch.columns())?;
+        let last = self.last.as_ref().map(|(_, text)| text.clone());
+        let mut keep = Vec::with_capacity(batch.num_rows());
+        for i in 0..batch.num_rows() {
+            if self.pending.is_empty() {
+                keep.push(true);
+                continue;
+            }
+            let same_key = !column.is_null(i) && Some(array_value_to_string(&column, i)?) == last;
+            if !same_key {
+                // Past the rows holding the last key: nothing more to drop
+                self.pending.clear();
+                keep.push(true);
+                continue;
+            }
+            match self.pending.iter().position(|row| row.row() == rows.row(i)) {
+                Some(seen) => {
+                    self.pending.swap_remove(seen);
+                    keep.push(false);
+                }
+                None => keep.push(true),
+            }
+        }
+        Ok(filter_record_batch(&batch, &BooleanArray::from(keep))?)
+    }
+}
+
+// SQL literal of the restart key in the last row of a batch
+pub fn last_key_literal(batch: &RecordBatch, key: &str) -> Result<Option<String>> {
+    if batch.num_rows() == 0 {
+        return Ok(None);
+    }
+    let column = key_column(batch, key)?;
+    let row = batch.num_rows() - 1;
+    if column.is_null(row) {
+        return Err(anyhow!("restart_key column '{}' contains NULL values", key));
+    }
+    let text = array_value_to_string(column, row)?;
+    let literal = match column.data_type() {
+        DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64
+        | DataType::Float32
+        | DataType::Float64
+        | DataType::Decimal128(_, _)
+        | DataType::Decimal256(_, _) => text,
+        // Firebird expects a space between date and time
+        DataType::Timestamp(_, _) => format!("'{}'", text.replace('T', " ")),
+        _ => format!("'{}'", text.replace('\'', "''")),
+    };
+    Ok(Some(literal))
+}
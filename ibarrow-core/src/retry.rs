@@ -0,0 +1,53 @@
+use anyhow::Result;
+use std::thread;
+use std::time::Duration;
+
+use crate::QueryConfig;
+
+// Connection drops, link failures and lock conflicts/deadlocks
+const DEFAULT_RETRY_SQLSTATES: [&str; 4] = ["08001", "08004", "08S01", "40001"];
+
+// SQLSTATE of the diagnostic record behind an ODBC error, if any
+pub fn sqlstate(error: &anyhow::Error) -> Option<String> {
+    match error.downcast_ref::<odbc_api::Error>()? {
+        odbc_api::Error::Diagnostics { record, .. } => Some(record.state.as_str().to_string()),
+        _ => None,
+    }
+}
+
+fn is_retryable(error: &anyhow::Error, config: &QueryConfig) -> bool {
+    let state = match sqlstate(error) {
+        Some(state) => state,
+        None => return false,
+    };
+    match &config.retry_sqlstates {
+        Some(states) => states.iter().any(|s| s.eq_ignore_ascii_case(&state)),
+        None => DEFAULT_RETRY_SQLSTATES.contains(&state.as_str()),
+    }
+}
+
+// Runs `attempt` until it succeeds, fails with a non-retryable error or retry_attempts is
+// exhausted. The wait doubles after every failure, starting at retry_backoff_ms.
+pub fn with_retry<T>(config: &QueryConfig, mut attempt: impl FnMut() -> Result<T>) -> Result<T> {
+    let max_attempts = config.retry_attempts.unwrap_or(1).max(1);
+    let mut backoff = Duration::from_millis(config.retry_backoff_ms.unwrap_or(200));
+    let mut tries = 1;
+    loop {
+        match attempt() {
+            Err(e) if tries < max_attempts && is_retryable(&e, config) => {
+                log::warn!(
+                    "transient error (SQLSTATE {}), retrying in {:?} (attempt {} of {}): {}",
+                    sqlstate(&e).unwrap_or_default(),
+                    backoff,
+                    tries + 1,
+                    max_attempts,
+                    e
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+                tries += 1;
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use anyhow::{anyhow, Result};
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
+use arrow_ipc::writer::{IpcWriteOptions, StreamWriter};
+use std::io::Write;
+
+// Where the batches of a query go: `begin` is called once with the result schema, then
+// `write` for every batch and `finish` at the end
+pub trait BatchSink {
+    fn begin(&mut self, schema: &SchemaRef) -> Result<()>;
+    fn write(&mut self, batch: &RecordBatch) -> Result<()>;
+    fn finish(&mut self) -> Result<()>;
+}
+
+// Encodes the batches as an Arrow IPC stream into `out`
+pub struct IpcSink<'a, W: Write> {
+    out: Option<&'a mut W>,
+    options: IpcWriteOptions,
+    writer: Option<StreamWriter<&'a mut W>>,
+}
+
+impl<'a, W: Write> IpcSink<'a, W> {
+    pub fn new(out: &'a mut W, options: IpcWriteOptions) -> Self {
+        Self {
+            out: Some(out),
+            options,
+            writer: None,
+        }
+    }
+}
+
+impl<W: Write> BatchSink for IpcSink<'_, W> {
+    fn begin(&mut self, schema: &SchemaRef) -> Result<()> {
+        let out = self
+            .out
+            .take()
+            .ok_or_else(|| anyhow!("ERROR: Arrow stream was already started"))?;
+        let writer = StreamWriter::try_new_with_options(out, schema, self.options.clone())
+            .map_err(|e| anyhow!("ERROR: Failed to create StreamWriter: {}", e))?;
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| anyhow!("ERROR: Arrow stream was not started"))?;
+        Ok(writer.write(batch)?)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(writer) = self.writer.as_mut() {
+            writer
+                .finish()
+                .map_err(|e| anyhow!("ERROR: Failed to finish StreamWriter: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+// Keeps the batches as they are, for consumers that take Arrow memory directly
+#[derive(Default)]
+pub struct Collected {
+    pub schema: Option<SchemaRef>,
+    pub batches: Vec<RecordBatch>,
+}
+
+impl BatchSink for Collected {
+    fn begin(&mut self, schema: &SchemaRef) -> Result<()> {
+        self.schema = Some(schema.clone());
+        Ok(())
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        self.batches.push(batch.clone());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use anyhow::{anyhow, Result};
+use odbc_api::{Connection, Cursor};
+
+use crate::QueryConfig;
+
+// Firebird 4+ reports the snapshot number of the current transaction as a context variable
+const SNAPSHOT_NUMBER_SQL: &str =
+    "SELECT RDB$GET_CONTEXT('SYSTEM', 'SNAPSHOT_NUMBER') FROM RDB$DATABASE";
+
+pub fn validate(number: Option<i64>) -> Result<()> {
+    match number {
+        Some(number) if number <= 0 => Err(anyhow!("snapshot_number must be greater than 0")),
+        _ => Ok(()),
+    }
+}
+
+// Starts a read-only transaction that sees the database as of snapshot `number` (Firebird 4+)
+pub fn transaction_sql(number: i64) -> String {
+    format!(
+        "SET TRANSACTION READ ONLY ISOLATION LEVEL SNAPSHOT AT NUMBER {}",
+        number
+    )
+}
+
+// Moves a new connection into a transaction at QueryConfig.snapshot_number, if set. The
+// transaction is rolled back when the connection is dropped.
+pub fn begin(conn: &Connection<'_>, config: &QueryConfig) -> Result<()> {
+    let Some(number) = config.snapshot_number else {
+        return Ok(());
+    };
+    conn.set_autocommit(false)?;
+    conn.execute(&transaction_sql(number), (), None)
+        .map_err(|e| {
+            anyhow!(
+                "Failed to start a transaction at snapshot {}: {}",
+                number,
+                e
+            )
+        })?;
+    Ok(())
+}
+
+// Snapshot number of the transaction running on `conn`
+pub fn current(conn: &Connection<'_>) -> Result<i64> {
+    let mut cursor = conn
+        .execute(SNAPSHOT_NUMBER_SQL, (), None)
+        .map_err(|e| anyhow!("Snapshot numbers need Firebird 4 or later: {}", e))?
+        .ok_or_else(|| anyhow!("Snapshot number query did not return a result set"))?;
+    let mut text = Vec::new();
+    let reported = match cursor.next_row()? {
+        Some(mut row) => row.get_text(1, &mut text)?,
+        None => false,
+    };
+    if !reported {
+        return Err(anyhow!(
+            "The server did not report a snapshot number; snapshot numbers need Firebird 4 or later"
+        ));
+    }
+    let text = String::from_utf8_lossy(&text);
+    text.trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid snapshot number '{}'", text.trim()))
+}
@@ -0,0 +1,119 @@
+use anyhow::{anyhow, Result};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::QueryConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Warn,
+    Rollback,
+}
+
+fn action(config: &QueryConfig) -> Result<Action> {
+    match config
+        .transaction_age_action
+        .as_deref()
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        None | Some("warn") => Ok(Action::Warn),
+        Some("rollback") => Ok(Action::Rollback),
+        Some(other) => Err(anyhow!(
+            "Unknown transaction_age_action '{}': expected 'warn' or 'rollback'",
+            other
+        )),
+    }
+}
+
+pub fn validate(config: &QueryConfig) -> Result<()> {
+    if config.max_transaction_age == Some(0) {
+        return Err(anyhow!("max_transaction_age must be greater than 0"));
+    }
+    action(config).map(|_| ())
+}
+
+#[derive(Default)]
+struct State {
+    done: bool,
+    rolled_back: bool,
+}
+
+type Shared = Arc<(Mutex<State>, Condvar)>;
+
+fn lock(shared: &Shared) -> MutexGuard<'_, State> {
+    shared.0.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Watches a transaction held open across calls, such as a Snapshot or a ScrollCursor. Open
+// transactions hold back Firebird's garbage collection, so once one is older than
+// QueryConfig.max_transaction_age a warning is printed and, with transaction_age_action set to
+// "rollback", `rollback` ends it. Dropping the watch stops it.
+pub struct TransactionWatch {
+    shared: Shared,
+    max_age: Duration,
+}
+
+impl TransactionWatch {
+    pub fn start(
+        config: &QueryConfig,
+        holder: &'static str,
+        rollback: impl FnOnce() + Send + 'static,
+    ) -> Result<Option<Self>> {
+        let Some(max_age) = config.max_transaction_age else {
+            return Ok(None);
+        };
+        let action = action(config)?;
+        let max_age = Duration::from_secs(max_age);
+        let shared: Shared = Arc::default();
+        let watched = Arc::clone(&shared);
+        thread::spawn(move || {
+            let deadline = Instant::now() + max_age;
+            let mut state = lock(&watched);
+            while !state.done {
+                let now = Instant::now();
+                if now < deadline {
+                    state = watched
+                        .1
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0;
+                    continue;
+                }
+                log::warn!(
+                    "{} has kept its transaction open for over {} seconds, holding back \
+                     garbage collection on the server",
+                    holder,
+                    max_age.as_secs()
+                );
+                if action == Action::Rollback {
+                    log::warn!("Rolling back the transaction of {}", holder);
+                    state.rolled_back = true;
+                    drop(state);
+                    rollback();
+                }
+                break;
+            }
+        });
+        Ok(Some(Self { shared, max_age }))
+    }
+
+    // Error for calls on a holder whose transaction the watch rolled back, if it did
+    pub fn rolled_back(&self) -> Option<anyhow::Error> {
+        lock(&self.shared).rolled_back.then(|| {
+            anyhow!(
+                "The transaction was rolled back after being open for over {} seconds \
+                 (max_transaction_age)",
+                self.max_age.as_secs()
+            )
+        })
+    }
+}
+
+impl Drop for TransactionWatch {
+    fn drop(&mut self) {
+        lock(&self.shared).done = true;
+        self.shared.1.notify_all();
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::{anyhow, Result};
+use odbc_api::handles::{AsStatementRef, Statement, StatementRef};
+use odbc_api::sys::{HStmt, SQLCancel};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::QueryConfig;
+
+// Raw statement handle the watchdog may cancel
+struct RawStatement(HStmt);
+
+// SQLCancel is the one ODBC call meant to be made from another thread
+unsafe impl Send for RawStatement {}
+
+#[derive(Default)]
+struct State {
+    statement: Option<RawStatement>,
+    fired: bool,
+    done: bool,
+}
+
+type Shared = Arc<(Mutex<State>, Condvar)>;
+
+fn lock(shared: &Shared) -> MutexGuard<'_, State> {
+    shared.0.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Client-side enforcement of query_timeout over execution plus fetch. Some InterBase drivers
+// ignore the statement timeout, so a background thread cancels the running statement once
+// the deadline passes and the query fails with a timeout error.
+pub struct Watchdog {
+    timeout: Option<Duration>,
+    shared: Shared,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    pub fn start(config: &QueryConfig) -> Self {
+        let timeout = config
+            .query_timeout
+            .filter(|secs| *secs > 0)
+            .map(|secs| Duration::from_secs(secs.into()));
+        let shared: Shared = Arc::default();
+        let thread = timeout.map(|timeout| {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || {
+                let deadline = Instant::now() + timeout;
+                let mut state = lock(&shared);
+                while !state.done {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        state.fired = true;
+                        if let Some(statement) = &state.statement {
+                            log::warn!("query_timeout exceeded, cancelling statement");
+                            let _ = unsafe { SQLCancel(statement.0) };
+                        }
+                        break;
+                    }
+                    state = shared
+                        .1
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0;
+                }
+            })
+        });
+        Self {
+            timeout,
+            shared,
+            thread,
+        }
+    }
+
+    fn error(&self) -> anyhow::Error {
+        anyhow!(
+            "Query timed out after {} seconds (query_timeout)",
+            self.timeout.unwrap_or_default().as_secs()
+        )
+    }
+
+    // Fails once the deadline has passed; called before executing and between batches
+    pub fn check(&self) -> Result<()> {
+        if lock(&self.shared).fired {
+            return Err(self.error());
+        }
+        Ok(())
+    }
+
+    // Replaces the error of a cancelled statement with the timeout error
+    pub fn finish<T>(self, result: Result<T>) -> Result<T> {
+        result.map_err(|e| {
+            if lock(&self.shared).fired {
+                self.error()
+            } else {
+                e
+            }
+        })
+    }
+
+    // Registers a statement handle as the one to cancel when the deadline passes
+    pub fn watch<S: AsStatementRef>(&self, mut statement: S) -> WatchedStatement<S> {
+        let handle = statement.as_stmt_ref().as_sys();
+        if self.thread.is_some() {
+            lock(&self.shared).statement = Some(RawStatement(handle));
+        }
+        WatchedStatement {
+            shared: Arc::clone(&self.shared),
+            handle: RawStatement(handle),
+            statement,
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        lock(&self.shared).done = true;
+        self.shared.1.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Statement handle registered with a Watchdog; unregisters itself before the handle is freed
+pub struct WatchedStatement<S> {
+    shared: Shared,
+    handle: RawStatement,
+    statement: S,
+}
+
+impl<S: AsStatementRef> AsStatementRef for WatchedStatement<S> {
+    fn as_stmt_ref(&mut self) -> StatementRef<'_> {
+        self.statement.as_stmt_ref()
+    }
+}
+
+impl<S> Drop for WatchedStatement<S> {
+    fn drop(&mut self) {
+        let mut state = lock(&self.shared);
+        // A restarted query may already have registered its replacement
+        if state.statement.as_ref().map(|s| s.0) == Some(self.handle.0) {
+            state.statement = None;
+        }
+    }
+}
+
+// Cancels a statement owned by the calling thread, e.g. one executing in polling mode
+pub fn cancel(statement: &mut impl AsStatementRef) {
+    let _ = unsafe { SQLCancel(statement.as_stmt_ref().as_sys()) };
+}
@@ -0,0 +1,697 @@
+use anyhow::{anyhow, Result};
+use arrow::compute::{cast_with_options, CastOptions};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use arrow_odbc::OdbcWriter;
+use odbc_api::{Connection, Environment};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::catalog::{fetch_texts, table_exists};
+use crate::ddl::{self, Dialect};
+use crate::quoting::{quote_identifier, requires_quotes};
+use crate::{
+    access, backend, build_connection_string, charset, diagnostics, driverenv, retry, QueryConfig,
+};
+
+// Rows bound per INSERT execution when QueryConfig.batch_size is not set
+pub const WRITE_BATCH_ROWS: usize = 10_000;
+
+// Arrow data to insert, read one batch at a time
+pub type Batches = Box<dyn RecordBatchReader + Send>;
+
+// The type a column is bound as: the ODBC writer has no buffers for views, large binaries,
+// dictionaries or wide unsigned integers, so those are cast first
+fn insert_type(name: &str, data_type: &DataType) -> Result<DataType> {
+    Ok(match data_type {
+        DataType::Utf8
+        | DataType::LargeUtf8
+        | DataType::Boolean
+        | DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::Float16
+        | DataType::Float32
+        | DataType::Float64
+        | DataType::Timestamp(_, _)
+        | DataType::Date32
+        | DataType::Date64
+        | DataType::Time32(TimeUnit::Second | TimeUnit::Millisecond)
+        | DataType::Time64(TimeUnit::Microsecond | TimeUnit::Nanosecond)
+        | DataType::Binary
+        | DataType::FixedSizeBinary(_)
+        | DataType::Decimal128(_, _)
+        | DataType::Decimal256(_, _) => data_type.clone(),
+        DataType::Utf8View | DataType::Null => DataType::Utf8,
+        DataType::BinaryView | DataType::LargeBinary => DataType::Binary,
+        DataType::UInt16 => DataType::Int32,
+        DataType::UInt32 | DataType::UInt64 => DataType::Int64,
+        DataType::Dictionary(_, values) => insert_type(name, values)?,
+        other => return Err(anyhow!("Cannot insert column {} of type {}", name, other)),
+    })
+}
+
+// Schema the batches are bound with
+pub fn insert_schema(schema: &Schema) -> Result<SchemaRef> {
+    let fields = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let data_type = insert_type(field.name(), field.data_type())?;
+            Ok(Field::new(field.name(), data_type, field.is_nullable()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if fields.is_empty() {
+        return Err(anyhow!("Cannot insert data without columns"));
+    }
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+fn cast_batch(batch: &RecordBatch, schema: &SchemaRef) -> Result<RecordBatch> {
+    // Unsigned values too large for the signed type fail instead of becoming NULL
+    let options = CastOptions {
+        safe: false,
+        ..Default::default()
+    };
+    let columns = batch
+        .columns()
+        .iter()
+        .zip(schema.fields())
+        .map(|(column, field)| {
+            if column.data_type() == field.data_type() {
+                Ok(column.clone())
+            } else {
+                cast_with_options(column, field.data_type(), &options)
+                    .map_err(|e| anyhow!("Arrow cast of column {} failed: {}", field.name(), e))
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+// What happens to the rows already in the table
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    // Kept
+    Append,
+    // Deleted in the transaction that writes the new ones
+    Truncate,
+    // Dropped with the table, which is created again from the data's schema once the new rows
+    // are safely staged
+    Replace,
+}
+
+impl Mode {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "append" => Ok(Self::Append),
+            "truncate" => Ok(Self::Truncate),
+            "replace" => Ok(Self::Replace),
+            _ => Err(anyhow!(
+                "Unknown mode '{}': expected 'append', 'truncate' or 'replace'",
+                name
+            )),
+        }
+    }
+}
+
+// Where and how write_arrow writes the batches
+#[derive(Clone, Copy)]
+pub struct Target<'a> {
+    pub table: &'a str,
+    pub columns: Columns,
+    pub mode: Mode,
+    // Key columns of an UPDATE OR INSERT; empty for plain inserts
+    pub matching: &'a [String],
+}
+
+impl<'a> Target<'a> {
+    pub fn insert(table: &'a str, columns: Columns, mode: Mode) -> Self {
+        Self {
+            table,
+            columns,
+            mode,
+            matching: &[],
+        }
+    }
+
+    // Rejects key columns the data does not have
+    pub fn validate(&self, schema: &Schema) -> Result<()> {
+        for key in self.matching {
+            if schema.field_with_name(key).is_err() {
+                return Err(anyhow!("Key column {} is not in the data", key));
+            }
+        }
+        Ok(())
+    }
+
+    // INSERT, or UPDATE OR INSERT, of every column of `schema`, names quoted as Firebird needs
+    pub fn sql(&self, schema: &Schema) -> String {
+        let columns: Vec<String> = schema
+            .fields()
+            .iter()
+            .map(|field| quote_identifier(field.name()))
+            .collect();
+        let values = format!(
+            "INTO {} ({}) VALUES ({})",
+            quote_identifier(self.table),
+            columns.join(", "),
+            vec!["?"; columns.len()].join(", ")
+        );
+        if self.matching.is_empty() {
+            return format!("INSERT {}", values);
+        }
+        let keys: Vec<String> = self
+            .matching
+            .iter()
+            .map(|key| quote_identifier(key))
+            .collect();
+        format!("UPDATE OR INSERT {} MATCHING ({})", values, keys.join(", "))
+    }
+
+    // DELETE of every row, which Truncate runs in the transaction that writes the new ones:
+    // Firebird has no TRUNCATE, and a DELETE can still be rolled back
+    pub fn delete_sql(&self) -> String {
+        format!("DELETE FROM {}", quote_identifier(self.table))
+    }
+}
+
+// CREATE TABLE for data that has not been read yet, so strings become text blobs: no VARCHAR
+// length is known to hold them. `table` must already be quoted where it needs to be.
+fn create_table_sql(table: &str, schema: &Schema) -> Result<String> {
+    for field in schema.fields() {
+        if ddl::column_type(field.data_type(), Dialect::Firebird, None).is_err() {
+            return Err(anyhow!(
+                "Cannot ingest columns of type {}",
+                field.data_type()
+            ));
+        }
+    }
+    ddl::create_table_sql(table, schema, Dialect::Firebird, &HashMap::new(), None)
+}
+
+// Creates `table` (quoted where it needs to be) with a column per field of `schema`
+pub fn create_table(conn: &Connection<'_>, table: &str, schema: &Schema) -> Result<()> {
+    let sql = create_table_sql(table, schema)?;
+    log::debug!("{}", sql);
+    conn.execute(&sql, (), None)?;
+    Ok(())
+}
+
+// Executes `sql` once per row of the batches, binding up to batch_size rows per round trip.
+// Returns the rows sent.
+pub fn insert_batches(
+    conn: &Connection<'_>,
+    sql: &str,
+    schema: &SchemaRef,
+    batches: Batches,
+    config: &QueryConfig,
+) -> Result<usize> {
+    let capacity = config
+        .batch_size
+        .map_or(WRITE_BATCH_ROWS, |rows| rows as usize);
+    let mut writer = OdbcWriter::new(capacity, schema, conn.prepare(sql)?)?;
+    let mut rows = 0;
+    for batch in batches {
+        let batch = cast_batch(&batch?, schema)?;
+        writer.write_batch(&batch)?;
+        rows += batch.num_rows();
+    }
+    writer.flush()?;
+    Ok(rows)
+}
+
+// Opens a connection for writing, with autocommit off
+pub fn connect<'env>(
+    env: &'env Environment,
+    dsn: &str,
+    user: &str,
+    password: &str,
+    config: &QueryConfig,
+) -> Result<Connection<'env>> {
+    diagnostics::clear();
+    let conn_str = build_connection_string(dsn, user, password, config);
+    let conn = retry::with_retry(config, || driverenv::connect(env, &conn_str, config))?;
+    conn.set_autocommit(false)?;
+    Ok(conn)
+}
+
+// How the data's columns are matched to the columns of an existing table
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Columns {
+    // Renamed to the table column of the same name, ignoring case, keeping their types
+    Matched,
+    // Matched, then cast to the Arrow type of the table column
+    Coerced,
+}
+
+// Objects that would break or vanish if `table` were dropped: views, procedures and triggers
+// depending on it, and foreign keys of other tables referencing it
+fn dependents(conn: &Connection<'_>, table: &str) -> Result<Vec<String>> {
+    let mut names = fetch_texts(
+        conn,
+        "SELECT DISTINCT RDB$DEPENDENT_NAME FROM RDB$DEPENDENCIES \
+         WHERE RDB$DEPENDED_ON_TYPE = 0 AND RDB$DEPENDED_ON_NAME = ?",
+        Some(table),
+    )?;
+    // Computed columns of the table depend on the table itself
+    names.retain(|name| name != table);
+    names.extend(fetch_texts(
+        conn,
+        "SELECT FK.RDB$RELATION_NAME FROM RDB$REF_CONSTRAINTS RC \
+         JOIN RDB$RELATION_CONSTRAINTS UQ ON UQ.RDB$CONSTRAINT_NAME = RC.RDB$CONST_NAME_UQ \
+         JOIN RDB$RELATION_CONSTRAINTS FK ON FK.RDB$CONSTRAINT_NAME = RC.RDB$CONSTRAINT_NAME \
+         WHERE UQ.RDB$RELATION_NAME = ? AND FK.RDB$RELATION_NAME <> UQ.RDB$RELATION_NAME",
+        Some(table),
+    )?);
+    Ok(names)
+}
+
+// The stored name of an existing `table`: as typed, or else upper-cased the way Firebird folds
+// unquoted names
+pub fn find_table(conn: &Connection<'_>, table: &str) -> Result<Option<String>> {
+    let table = table.trim();
+    for candidate in [table.to_string(), table.to_uppercase()] {
+        if table_exists(conn, &candidate)? {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+// The name `table` is written under: the stored name of an existing table, and for a new one
+// the name upper-cased as an unquoted name would be, unless it needs quotes all the same
+fn stored_table(conn: &Connection<'_>, table: &str) -> Result<String> {
+    if let Some(found) = find_table(conn, table)? {
+        return Ok(found);
+    }
+    let folded = table.trim().to_uppercase();
+    Ok(if requires_quotes(&folded) {
+        table.trim().to_string()
+    } else {
+        folded
+    })
+}
+
+// `target` resolved against the database: the table's stored name and, unless the table is
+// replaced from the data, each column and key renamed to the table column it matches (cast
+// to that column's type with Columns::Coerced). Returns the table, schema and key columns.
+pub fn resolve(
+    conn: &Connection<'_>,
+    target: &Target<'_>,
+    schema: &SchemaRef,
+    config: &QueryConfig,
+) -> Result<(String, SchemaRef, Vec<String>)> {
+    let table = stored_table(conn, target.table)?;
+    if target.mode == Mode::Replace || !table_exists(conn, &table)? {
+        return Ok((table, schema.clone(), target.matching.to_vec()));
+    }
+    let existing = table_schema(conn, &table, config)?;
+    let matched = match_columns(schema, &existing, target.columns == Columns::Coerced)?;
+    let matching = target
+        .matching
+        .iter()
+        .map(|key| {
+            let index = schema.index_of(key)?;
+            Ok(matched.field(index).name().clone())
+        })
+        .collect::<Result<Vec<String>>>()?;
+    Ok((table, matched, matching))
+}
+
+// Name of the table Replace stages the new rows in, within Firebird's 63 characters
+fn staging_name(table: &str) -> String {
+    let stem: String = table.chars().take(57).collect();
+    format!("{}$STAGE", stem)
+}
+
+// Statements of a Replace, in the order they run
+#[derive(Debug)]
+struct Replace {
+    staging: String,
+    // CREATE TABLE of the staging table, then the INSERT that fills it
+    create_stage: String,
+    fill_stage: String,
+    // DROP TABLE of the old table, run only when it exists
+    drop_table: String,
+    create_table: String,
+    // INSERT ... SELECT from the stage into the new table
+    copy: String,
+    drop_stage: String,
+}
+
+impl Replace {
+    fn new(table: &str, schema: &Schema) -> Result<Self> {
+        let staging = staging_name(table);
+        let quoted = quote_identifier(table);
+        let quoted_staging = quote_identifier(&staging);
+        Ok(Self {
+            create_stage: create_table_sql(&quoted_staging, schema)?,
+            fill_stage: Target::insert(&staging, Columns::Matched, Mode::Append).sql(schema),
+            drop_table: format!("DROP TABLE {}", quoted),
+            create_table: create_table_sql(&quoted, schema)?,
+            copy: format!("INSERT INTO {} SELECT * FROM {}", quoted, quoted_staging),
+            drop_stage: format!("DROP TABLE {}", quoted_staging),
+            staging,
+        })
+    }
+}
+
+// Replace without a window where the data exists nowhere: the rows are first written and
+// committed into a staging table, and only then is the table dropped, created again and
+// filled from the stage. Firebird only applies DDL on commit, so the swap cannot be atomic:
+// if copying from the stage fails, the table is left empty and the staging table, which
+// still holds every row, is named in the error. Returns the rows written.
+fn replace_table(
+    conn: &Connection<'_>,
+    table: &str,
+    schema: &SchemaRef,
+    batches: Batches,
+    config: &QueryConfig,
+) -> Result<usize> {
+    let exists = table_exists(conn, table)?;
+    if exists {
+        let dependents = dependents(conn, table)?;
+        if !dependents.is_empty() {
+            return Err(anyhow!(
+                "Cannot replace {}: {} depend on it; drop them first or use mode='truncate'",
+                table,
+                dependents.join(", ")
+            ));
+        }
+    }
+    let replace = Replace::new(table, schema)?;
+    if table_exists(conn, &replace.staging)? {
+        return Err(anyhow!(
+            "Staging table {} is left over from an earlier replace of {}; copy its rows back \
+             or drop it first",
+            replace.staging,
+            table
+        ));
+    }
+    log::debug!("{}", replace.create_stage);
+    conn.execute(&replace.create_stage, (), None)?;
+    conn.commit()?;
+    let staged =
+        insert_batches(conn, &replace.fill_stage, schema, batches, config).and_then(|rows| {
+            conn.commit()?;
+            Ok(rows)
+        });
+    let rows = match staged {
+        Ok(rows) => rows,
+        Err(e) => {
+            let _ = conn.rollback();
+            if let Err(drop_error) = conn
+                .execute(&replace.drop_stage, (), None)
+                .and_then(|_| conn.commit())
+            {
+                log::warn!(
+                    "Failed to drop staging table {}: {}",
+                    replace.staging,
+                    drop_error
+                );
+            }
+            return Err(e);
+        }
+    };
+    if exists {
+        conn.execute(&replace.drop_table, (), None)?;
+    }
+    log::debug!("{}", replace.create_table);
+    conn.execute(&replace.create_table, (), None)?;
+    conn.commit()?;
+    log::debug!("{}", replace.copy);
+    if let Err(e) = conn
+        .execute(&replace.copy, (), None)
+        .and_then(|_| conn.commit())
+    {
+        let _ = conn.rollback();
+        return Err(anyhow!(
+            "Replacing {} failed after it was emptied; its {} new rows are kept in {}: {}",
+            table,
+            rows,
+            replace.staging,
+            e
+        ));
+    }
+    conn.execute(&replace.drop_stage, (), None)?;
+    conn.commit()?;
+    Ok(rows)
+}
+
+// Arrow schema of `table`'s columns, read without fetching rows
+fn table_schema(conn: &Connection<'_>, table: &str, config: &QueryConfig) -> Result<Schema> {
+    let sql = format!("SELECT * FROM {}", quote_identifier(table));
+    access::check(&sql, config)?;
+    let statement = charset::transcode(&sql, &[], config.sql_charset.as_deref())?;
+    backend::driver_schema(&mut statement.prepare(conn.preallocate()?.into_handle())?)
+}
+
+// Gives every column of `schema` the name of the table column it matches, exactly or else
+// ignoring case, and with `coerce` that column's type
+fn match_columns(schema: &Schema, table: &Schema, coerce: bool) -> Result<SchemaRef> {
+    let fields = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let target = table
+                .fields()
+                .iter()
+                .find(|target| target.name() == field.name())
+                .or_else(|| {
+                    table
+                        .fields()
+                        .iter()
+                        .find(|target| target.name().eq_ignore_ascii_case(field.name()))
+                })
+                .ok_or_else(|| {
+                    let names: Vec<&str> =
+                        table.fields().iter().map(|f| f.name().as_str()).collect();
+                    anyhow!(
+                        "Column {} is not in the table, whose columns are {}",
+                        field.name(),
+                        names.join(", ")
+                    )
+                })?;
+            let data_type = if coerce {
+                insert_type(target.name(), target.data_type())?
+            } else {
+                field.data_type().clone()
+            };
+            Ok(Field::new(target.name(), data_type, field.is_nullable()))
+        })
+        .collect::<Result<Vec<Field>>>()?;
+    for (i, field) in fields.iter().enumerate() {
+        if let Some(other) = fields[..i].iter().position(|f| f.name() == field.name()) {
+            return Err(anyhow!(
+                "Columns {} and {} both match table column {}",
+                schema.field(other).name(),
+                schema.field(i).name(),
+                field.name()
+            ));
+        }
+    }
+    Ok(Arc::new(Schema::new(fields)))
+}
+
+// Bulk-writes the batches into the target table in one transaction, rolled back if any row
+// fails; Replace stages the rows first (see replace_table). Returns the rows written.
+pub fn write_arrow(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    target: &Target<'_>,
+    batches: Batches,
+    config: &QueryConfig,
+) -> Result<usize> {
+    let schema = insert_schema(&batches.schema())?;
+    // Before any DDL: an access role may not write at all
+    access::check(&target.sql(&schema), config)?;
+    let env = Environment::new()?;
+    let conn = connect(&env, dsn, user, password, config)?;
+    let (table, schema, matching) = resolve(&conn, target, &schema, config)?;
+    let target = Target {
+        table: &table,
+        matching: &matching,
+        ..*target
+    };
+    match target.mode {
+        Mode::Append => {}
+        Mode::Truncate => {
+            let sql = target.delete_sql();
+            access::check(&sql, config)?;
+            log::debug!("{}", sql);
+            conn.execute(&sql, (), None)?;
+        }
+        Mode::Replace => {
+            let rows = replace_table(&conn, target.table, &schema, batches, config)?;
+            log::debug!("Replaced {} with {} rows", target.table, rows);
+            return Ok(rows);
+        }
+    }
+    let sql = target.sql(&schema);
+    access::check(&sql, config)?;
+    log::debug!("{}", sql);
+    match insert_batches(&conn, &sql, &schema, batches, config) {
+        Ok(rows) => {
+            conn.commit()?;
+            log::debug!("Wrote {} rows into {}", rows, target.table);
+            Ok(rows)
+        }
+        Err(e) => {
+            let _ = conn.rollback();
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(fields: &[(&str, DataType)]) -> Schema {
+        Schema::new(
+            fields
+                .iter()
+                .map(|(name, data_type)| Field::new(*name, data_type.clone(), true))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn quotes_identifiers_in_inserts() {
+        let data = schema(&[
+            ("ID", DataType::Int64),
+            ("note", DataType::Utf8),
+            ("ORDER", DataType::Int32),
+            ("say \"hi\"", DataType::Utf8),
+        ]);
+        let target = Target::insert("Order Lines", Columns::Matched, Mode::Append);
+        assert_eq!(
+            target.sql(&data),
+            "INSERT INTO \"Order Lines\" (ID, \"note\", \"ORDER\", \"say \"\"hi\"\"\") \
+             VALUES (?, ?, ?, ?)"
+        );
+        let keys = ["ID".to_string(), "note".to_string()];
+        let upsert = Target {
+            matching: &keys,
+            ..Target::insert("SALES", Columns::Matched, Mode::Append)
+        };
+        assert_eq!(
+            upsert.sql(&data),
+            "UPDATE OR INSERT INTO SALES (ID, \"note\", \"ORDER\", \"say \"\"hi\"\"\") \
+             VALUES (?, ?, ?, ?) MATCHING (ID, \"note\")"
+        );
+    }
+
+    #[test]
+    fn truncates_with_delete() {
+        let target = Target::insert("SALES", Columns::Matched, Mode::Truncate);
+        assert_eq!(target.delete_sql(), "DELETE FROM SALES");
+        let target = Target::insert("Sales 2024", Columns::Matched, Mode::Truncate);
+        assert_eq!(target.delete_sql(), "DELETE FROM \"Sales 2024\"");
+    }
+
+    #[test]
+    fn replaces_through_a_staging_table() {
+        let data = schema(&[("ID", DataType::Int64), ("NOTE", DataType::Utf8)]);
+        let replace = Replace::new("ORDERS", &data).unwrap();
+        assert_eq!(replace.staging, "ORDERS$STAGE");
+        assert_eq!(
+            replace.create_stage,
+            "CREATE TABLE ORDERS$STAGE (\n    ID BIGINT,\n    NOTE BLOB SUB_TYPE TEXT\n)"
+        );
+        assert_eq!(
+            replace.fill_stage,
+            "INSERT INTO ORDERS$STAGE (ID, NOTE) VALUES (?, ?)"
+        );
+        assert_eq!(replace.drop_table, "DROP TABLE ORDERS");
+        assert_eq!(
+            replace.create_table,
+            "CREATE TABLE ORDERS (\n    ID BIGINT,\n    NOTE BLOB SUB_TYPE TEXT\n)"
+        );
+        assert_eq!(
+            replace.copy,
+            "INSERT INTO ORDERS SELECT * FROM ORDERS$STAGE"
+        );
+        assert_eq!(replace.drop_stage, "DROP TABLE ORDERS$STAGE");
+
+        let replace = Replace::new("orders", &data).unwrap();
+        assert_eq!(replace.staging, "orders$STAGE");
+        assert_eq!(
+            replace.copy,
+            "INSERT INTO \"orders\" SELECT * FROM \"orders$STAGE\""
+        );
+        assert_eq!(replace.drop_stage, "DROP TABLE \"orders$STAGE\"");
+    }
+
+    #[test]
+    fn staging_names_fit_firebird_identifiers() {
+        let long = "T".repeat(63);
+        let staging = staging_name(&long);
+        assert_eq!(staging.chars().count(), 63);
+        assert!(staging.ends_with("$STAGE"));
+        let data = schema(&[("ID", DataType::Int64)]);
+        assert!(!requires_quotes(
+            &Replace::new(&long, &data).unwrap().staging
+        ));
+    }
+
+    #[test]
+    fn rejects_columns_without_a_firebird_type() {
+        let data = schema(&[("TAGS", DataType::new_list(DataType::Utf8, true))]);
+        let error = Replace::new("ORDERS", &data).unwrap_err();
+        assert!(error.to_string().contains("Cannot ingest columns of type"));
+        assert!(insert_schema(&data).is_err());
+    }
+
+    #[test]
+    fn binds_unsupported_types_as_wider_ones() {
+        let data = schema(&[
+            ("A", DataType::UInt32),
+            ("B", DataType::Utf8View),
+            (
+                "C",
+                DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)),
+            ),
+            ("D", DataType::LargeBinary),
+        ]);
+        let bound = insert_schema(&data).unwrap();
+        let types: Vec<&DataType> = bound.fields().iter().map(|f| f.data_type()).collect();
+        assert_eq!(
+            types,
+            [
+                &DataType::Int64,
+                &DataType::Utf8,
+                &DataType::Utf8,
+                &DataType::Binary
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_columns_ignoring_case() {
+        let data = schema(&[("id", DataType::Int32), ("Note", DataType::Utf8)]);
+        let table = schema(&[("ID", DataType::Int64), ("NOTE", DataType::Utf8)]);
+        let matched = match_columns(&data, &table, false).unwrap();
+        assert_eq!(matched.field(0).name(), "ID");
+        assert_eq!(matched.field(0).data_type(), &DataType::Int32);
+        let coerced = match_columns(&data, &table, true).unwrap();
+        assert_eq!(coerced.field(0).data_type(), &DataType::Int64);
+
+        let twice = schema(&[("id", DataType::Int32), ("ID", DataType::Int32)]);
+        let table = schema(&[("ID", DataType::Int64)]);
+        assert!(match_columns(&twice, &table, false).is_err());
+        let missing = schema(&[("CODE", DataType::Int32)]);
+        assert!(match_columns(&missing, &table, false).is_err());
+    }
+
+    #[test]
+    fn parses_modes() {
+        assert_eq!(Mode::parse("Replace").unwrap(), Mode::Replace);
+        assert_eq!(Mode::parse("truncate").unwrap(), Mode::Truncate);
+        assert!(Mode::parse("upsert").is_err());
+    }
+}
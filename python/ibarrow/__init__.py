@@ -0,0 +1,20 @@
+"""High-performance ODBC to Arrow data connector for InterBase/Firebird."""
+
+from ._ibarrow import *  # noqa: F401,F403
+from ._ibarrow import (  # noqa: F401
+    _read_partition,
+    _read_partition_arrow,
+    _reserved_words,
+    _url_dsn,
+    _worker_main,
+)
+
+
+def __getattr__(name):
+    # The SQLAlchemy dialect is defined on first use, so that importing ibarrow never imports
+    # SQLAlchemy
+    if name == "IbarrowDialect":
+        from .dialect import IbarrowDialect
+
+        return IbarrowDialect
+    raise AttributeError("module 'ibarrow' has no attribute '%s'" % name)
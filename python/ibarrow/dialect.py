@@ -0,0 +1,77 @@
+"""SQLAlchemy 2.0 dialect for ibarrow+firebird:// URLs.
+
+It builds on sqlalchemy-firebird's FBDialect when that is installed, for its SQL compiler,
+types and reflection, and on SQLAlchemy's DefaultDialect otherwise.
+"""
+
+try:
+    from sqlalchemy.engine import default
+except ImportError as e:
+    raise ImportError(
+        "The SQLAlchemy dialect needs sqlalchemy 2.0 (pip install sqlalchemy)"
+    ) from e
+
+import ibarrow
+
+try:
+    from sqlalchemy_firebird.base import FBDialect as Base
+except ImportError:
+    Base = default.DefaultDialect
+
+
+class IbarrowIdentifierPreparer(Base.preparer):
+    reserved_words = {word.lower() for word in ibarrow._reserved_words()}
+
+    def _requires_quotes(self, value):
+        # SQLAlchemy hands over normalized, lower-case names for case-insensitive ones
+        return ibarrow.requires_quotes(value.upper() if value.islower() else value)
+
+
+class IbarrowDialect(Base):
+    name = "firebird"
+    driver = "ibarrow"
+    preparer = IbarrowIdentifierPreparer
+    supports_statement_cache = True
+    default_paramstyle = "qmark"
+    supports_sane_rowcount = True
+    supports_sane_multi_rowcount = True
+    supports_native_decimal = True
+    supports_native_boolean = True
+    requires_name_normalize = True
+    max_identifier_length = 63
+
+    @classmethod
+    def import_dbapi(cls):
+        return ibarrow
+
+    def create_connect_args(self, url):
+        query = dict(url.query)
+        dsn = ibarrow._url_dsn(url.host, url.port, url.database, query.pop("dsn", None))
+        if query:
+            raise ValueError(
+                "Unknown URL arguments %s; pass settings as "
+                "connect_args={'config': ibarrow.QueryConfig(...)}" % sorted(query)
+            )
+        return [], {"dsn": dsn, "user": url.username or "", "password": url.password or ""}
+
+    def _get_server_version_info(self, connection):
+        return tuple(connection.connection.dbapi_connection.server_version())
+
+    def _get_default_schema_name(self, connection):
+        return None
+
+    def get_isolation_level(self, dbapi_connection):
+        return "READ COMMITTED"
+
+    def do_ping(self, dbapi_connection):
+        return dbapi_connection.test_connection()
+
+    if Base is default.DefaultDialect:
+
+        def get_table_names(self, connection, schema=None, **kw):
+            tables = connection.connection.dbapi_connection.table_names()
+            return [self.normalize_name(table) for table in tables]
+
+        def has_table(self, connection, table_name, schema=None, **kw):
+            tables = connection.connection.dbapi_connection.table_names()
+            return self.denormalize_name(table_name) in tables
@@ -0,0 +1,1627 @@
+use anyhow::Result;
+use arrow::array::{
+    new_empty_array, new_null_array, Array, ArrayRef, AsArray, Int16Array, Int32Array, ListArray,
+    RecordBatch, RecordBatchIterator, RecordBatchReader, StringArray, StructArray, UInt32Array,
+    UnionArray,
+};
+use arrow::buffer::OffsetBuffer;
+use arrow::compute::cast;
+use arrow::datatypes::{
+    DataType, Field, FieldRef, Fields, Float16Type, Float32Type, Float64Type, Int16Type, Int32Type,
+    Int64Type, Int8Type, Schema, SchemaRef, UInt16Type, UInt32Type, UInt64Type, UInt8Type,
+    UnionFields,
+};
+use arrow::ffi::{from_ffi, FFI_ArrowArray, FFI_ArrowSchema};
+use arrow::ffi_stream::{ArrowArrayStreamReader, FFI_ArrowArrayStream};
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use odbc_api::Connection;
+use pyo3::exceptions::PyImportError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::Arc;
+
+use crate::dbapi::execute_on;
+use crate::ddl::{self, Dialect};
+use crate::dialect::quote_identifier;
+use crate::params::SqlParam;
+use crate::{
+    access, build_connection_string, catalog, diagnostics, driverenv, migrate, retry, write,
+    QueryConfig,
+};
+
+// ADBC 1.0.0 C API (https://arrow.apache.org/adbc/), so ADBC driver managers (DuckDB, R's
+// adbcdrivermanager, Go, Python's adbc_driver_manager) can load this library as a
+// Firebird/InterBase driver. Each ADBC connection holds one ODBC connection; statements run
+// through the same plumbing as the DB-API cursor and return their rows as an Arrow stream.
+
+type Status = u8;
+
+const OK: Status = 0;
+const UNKNOWN: Status = 1;
+const NOT_IMPLEMENTED: Status = 2;
+const NOT_FOUND: Status = 3;
+const INVALID_ARGUMENT: Status = 5;
+const INVALID_STATE: Status = 6;
+const INTEGRITY: Status = 8;
+const INTERNAL: Status = 9;
+const IO: Status = 10;
+const TIMEOUT: Status = 12;
+const UNAUTHENTICATED: Status = 13;
+const UNAUTHORIZED: Status = 14;
+
+const VERSION_1_0_0: c_int = 1_000_000;
+
+const OPTION_URI: &str = "uri";
+const OPTION_USERNAME: &str = "username";
+const OPTION_PASSWORD: &str = "password";
+// QueryConfig as JSON, e.g. {"batch_size": 5000, "sql_charset": "WIN1252"}
+const OPTION_CONFIG: &str = "ibarrow.config";
+const OPTION_AUTOCOMMIT: &str = "adbc.connection.autocommit";
+const OPTION_READ_ONLY: &str = "adbc.connection.readonly";
+const OPTION_INGEST_TARGET: &str = "adbc.ingest.target_table";
+const OPTION_INGEST_MODE: &str = "adbc.ingest.mode";
+
+const INFO_VENDOR_NAME: u32 = 0;
+const INFO_VENDOR_VERSION: u32 = 1;
+const INFO_DRIVER_NAME: u32 = 100;
+const INFO_DRIVER_VERSION: u32 = 101;
+
+const DEPTH_CATALOGS: c_int = 1;
+const DEPTH_DB_SCHEMAS: c_int = 2;
+const DEPTH_TABLES: c_int = 3;
+
+const TABLE_TYPES: [&str; 3] = ["TABLE", "VIEW", "SYSTEM TABLE"];
+
+// A column is nullable unless the column or its domain is NOT NULL
+const COLUMNS_SQL: &str = "SELECT TRIM(RF.RDB$RELATION_NAME), TRIM(RF.RDB$FIELD_NAME), \
+     RF.RDB$FIELD_POSITION, COALESCE(RF.RDB$NULL_FLAG, F.RDB$NULL_FLAG, 0) \
+     FROM RDB$RELATION_FIELDS RF LEFT JOIN RDB$FIELDS F ON F.RDB$FIELD_NAME = RF.RDB$FIELD_SOURCE \
+     WHERE TRIM(RF.RDB$RELATION_NAME) LIKE ? AND TRIM(RF.RDB$FIELD_NAME) LIKE ? ORDER BY 1, 3";
+
+const VERSION_SQL: &str = "SELECT RDB$GET_CONTEXT('SYSTEM', 'ENGINE_VERSION') FROM RDB$DATABASE";
+
+#[repr(C)]
+pub struct AdbcError {
+    message: *mut c_char,
+    vendor_code: i32,
+    sqlstate: [c_char; 5],
+    release: Option<unsafe extern "C" fn(*mut AdbcError)>,
+}
+
+#[repr(C)]
+pub struct AdbcDatabase {
+    private_data: *mut c_void,
+    private_driver: *mut AdbcDriver,
+}
+
+#[repr(C)]
+pub struct AdbcConnection {
+    private_data: *mut c_void,
+    private_driver: *mut AdbcDriver,
+}
+
+#[repr(C)]
+pub struct AdbcStatement {
+    private_data: *mut c_void,
+    private_driver: *mut AdbcDriver,
+}
+
+// The ADBC 1.0.0 function table, in the order of adbc.h
+#[repr(C)]
+pub struct AdbcDriver {
+    private_data: *mut c_void,
+    private_manager: *mut c_void,
+    release: unsafe extern "C" fn(*mut AdbcDriver, *mut AdbcError) -> Status,
+    database_init: unsafe extern "C" fn(*mut AdbcDatabase, *mut AdbcError) -> Status,
+    database_new: unsafe extern "C" fn(*mut AdbcDatabase, *mut AdbcError) -> Status,
+    database_set_option: unsafe extern "C" fn(
+        *mut AdbcDatabase,
+        *const c_char,
+        *const c_char,
+        *mut AdbcError,
+    ) -> Status,
+    database_release: unsafe extern "C" fn(*mut AdbcDatabase, *mut AdbcError) -> Status,
+    connection_commit: unsafe extern "C" fn(*mut AdbcConnection, *mut AdbcError) -> Status,
+    connection_get_info: unsafe extern "C" fn(
+        *mut AdbcConnection,
+        *const u32,
+        usize,
+        *mut FFI_ArrowArrayStream,
+        *mut AdbcError,
+    ) -> Status,
+    connection_get_objects: unsafe extern "C" fn(
+        *mut AdbcConnection,
+        c_int,
+        *const c_char,
+        *const c_char,
+        *const c_char,
+        *const *const c_char,
+        *const c_char,
+        *mut FFI_ArrowArrayStream,
+        *mut AdbcError,
+    ) -> Status,
+    connection_get_table_schema: unsafe extern "C" fn(
+        *mut AdbcConnection,
+        *const c_char,
+        *const c_char,
+        *const c_char,
+        *mut FFI_ArrowSchema,
+        *mut AdbcError,
+    ) -> Status,
+    connection_get_table_types: unsafe extern "C" fn(
+        *mut AdbcConnection,
+        *mut FFI_ArrowArrayStream,
+        *mut AdbcError,
+    ) -> Status,
+    connection_init:
+        unsafe extern "C" fn(*mut AdbcConnection, *mut AdbcDatabase, *mut AdbcError) -> Status,
+    connection_new: unsafe extern "C" fn(*mut AdbcConnection, *mut AdbcError) -> Status,
+    connection_set_option: unsafe extern "C" fn(
+        *mut AdbcConnection,
+        *const c_char,
+        *const c_char,
+        *mut AdbcError,
+    ) -> Status,
+    connection_read_partition: unsafe extern "C" fn(
+        *mut AdbcConnection,
+        *const u8,
+        usize,
+        *mut FFI_ArrowArrayStream,
+        *mut AdbcError,
+    ) -> Status,
+    connection_release: unsafe extern "C" fn(*mut AdbcConnection, *mut AdbcError) -> Status,
+    connection_rollback: unsafe extern "C" fn(*mut AdbcConnection, *mut AdbcError) -> Status,
+    statement_bind: unsafe extern "C" fn(
+        *mut AdbcStatement,
+        *mut FFI_ArrowArray,
+        *mut FFI_ArrowSchema,
+        *mut AdbcError,
+    ) -> Status,
+    statement_bind_stream: unsafe extern "C" fn(
+        *mut AdbcStatement,
+        *mut FFI_ArrowArrayStream,
+        *mut AdbcError,
+    ) -> Status,
+    statement_execute_query: unsafe extern "C" fn(
+        *mut AdbcStatement,
+        *mut FFI_ArrowArrayStream,
+        *mut i64,
+        *mut AdbcError,
+    ) -> Status,
+    statement_execute_partitions: unsafe extern "C" fn(
+        *mut AdbcStatement,
+        *mut FFI_ArrowSchema,
+        *mut c_void,
+        *mut i64,
+        *mut AdbcError,
+    ) -> Status,
+    statement_get_parameter_schema:
+        unsafe extern "C" fn(*mut AdbcStatement, *mut FFI_ArrowSchema, *mut AdbcError) -> Status,
+    statement_new:
+        unsafe extern "C" fn(*mut AdbcConnection, *mut AdbcStatement, *mut AdbcError) -> Status,
+    statement_prepare: unsafe extern "C" fn(*mut AdbcStatement, *mut AdbcError) -> Status,
+    statement_release: unsafe extern "C" fn(*mut AdbcStatement, *mut AdbcError) -> Status,
+    statement_set_option: unsafe extern "C" fn(
+        *mut AdbcStatement,
+        *const c_char,
+        *const c_char,
+        *mut AdbcError,
+    ) -> Status,
+    statement_set_sql_query:
+        unsafe extern "C" fn(*mut AdbcStatement, *const c_char, *mut AdbcError) -> Status,
+    statement_set_substrait_plan:
+        unsafe extern "C" fn(*mut AdbcStatement, *const u8, usize, *mut AdbcError) -> Status,
+}
+
+// An error reported with a specific ADBC status code
+#[derive(Debug)]
+struct StatusError {
+    status: Status,
+    message: String,
+}
+
+impl fmt::Display for StatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for StatusError {}
+
+fn status_error(status: Status, message: impl Into<String>) -> anyhow::Error {
+    anyhow::Error::new(StatusError {
+        status,
+        message: message.into(),
+    })
+}
+
+// ADBC status of an ODBC or Arrow error, chosen by SQLSTATE class like map_query_error
+fn status_of(error: &anyhow::Error, state: &str) -> Status {
+    if let Some(error) = error.downcast_ref::<StatusError>() {
+        return error.status;
+    }
+    let msg = error.to_string();
+    if msg.contains("timed out") || state == "HYT00" || state == "HYT01" {
+        TIMEOUT
+    } else if msg.starts_with("Access denied") {
+        UNAUTHORIZED
+    } else if state == "42S02" {
+        NOT_FOUND
+    } else if state.starts_with("23") {
+        INTEGRITY
+    } else if state.starts_with("28") {
+        UNAUTHENTICATED
+    } else if state.starts_with("08") || state == "IM002" {
+        IO
+    } else if state.starts_with("42") || state.starts_with("07") {
+        INVALID_ARGUMENT
+    } else {
+        UNKNOWN
+    }
+}
+
+unsafe extern "C" fn release_error(error: *mut AdbcError) {
+    if error.is_null() {
+        return;
+    }
+    if !(*error).message.is_null() {
+        drop(CString::from_raw((*error).message));
+    }
+    (*error).message = ptr::null_mut();
+    (*error).release = None;
+}
+
+// Fills the caller's AdbcError, releasing whatever it held before
+unsafe fn set_error(out: *mut AdbcError, error: &anyhow::Error, state: &str) {
+    if out.is_null() {
+        return;
+    }
+    if let Some(release) = (*out).release {
+        release(out);
+    }
+    let vendor_code = match error.downcast_ref::<odbc_api::Error>() {
+        Some(odbc_api::Error::Diagnostics { record, .. }) => record.native_error,
+        _ => 0,
+    };
+    let message = CString::new(error.to_string().replace('\0', " ")).unwrap_or_default();
+    (*out).message = message.into_raw();
+    (*out).vendor_code = vendor_code;
+    (*out).sqlstate = [0; 5];
+    for (slot, byte) in (*out).sqlstate.iter_mut().zip(state.bytes()) {
+        *slot = byte as c_char;
+    }
+    (*out).release = Some(release_error);
+}
+
+// Runs one API call, turning its error or panic into a status code plus AdbcError
+unsafe fn guard(error: *mut AdbcError, body: impl FnOnce() -> Result<()>) -> Status {
+    let outcome = panic::catch_unwind(AssertUnwindSafe(body))
+        .unwrap_or_else(|_| Err(status_error(INTERNAL, "ibarrow's ADBC driver panicked")));
+    match outcome {
+        Ok(()) => OK,
+        Err(e) => {
+            let state = retry::sqlstate(&e).unwrap_or_default();
+            let status = status_of(&e, &state);
+            set_error(error, &e, &state);
+            status
+        }
+    }
+}
+
+unsafe fn text(value: *const c_char, what: &str) -> Result<Option<String>> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    let value = CStr::from_ptr(value)
+        .to_str()
+        .map_err(|_| status_error(INVALID_ARGUMENT, format!("{} is not valid UTF-8", what)))?;
+    Ok(Some(value.to_string()))
+}
+
+unsafe fn required_text(value: *const c_char, what: &str) -> Result<String> {
+    text(value, what)?.ok_or_else(|| status_error(INVALID_ARGUMENT, format!("{} is NULL", what)))
+}
+
+// The database, connection and statement structs the driver manager passes in
+trait Handle {
+    fn private_data(&mut self) -> &mut *mut c_void;
+}
+
+impl Handle for AdbcDatabase {
+    fn private_data(&mut self) -> &mut *mut c_void {
+        &mut self.private_data
+    }
+}
+
+impl Handle for AdbcConnection {
+    fn private_data(&mut self) -> &mut *mut c_void {
+        &mut self.private_data
+    }
+}
+
+impl Handle for AdbcStatement {
+    fn private_data(&mut self) -> &mut *mut c_void {
+        &mut self.private_data
+    }
+}
+
+// The struct behind a handle pointer, or INVALID_ARGUMENT for a NULL one
+unsafe fn outer<'a, H: Handle>(handle: *mut H, what: &str) -> Result<&'a mut H> {
+    handle
+        .as_mut()
+        .ok_or_else(|| status_error(INVALID_ARGUMENT, format!("{} is NULL", what)))
+}
+
+unsafe fn handle<'a, T, H: Handle>(handle: *mut H, what: &str) -> Result<&'a mut T> {
+    (*outer(handle, what)?.private_data() as *mut T)
+        .as_mut()
+        .ok_or_else(|| status_error(INVALID_STATE, format!("The {} is not initialized", what)))
+}
+
+fn flag(value: &str) -> Result<bool> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(status_error(
+            INVALID_ARGUMENT,
+            format!("Expected 'true' or 'false', got '{}'", value),
+        )),
+    }
+}
+
+#[derive(Default)]
+struct DatabaseState {
+    uri: Option<String>,
+    username: String,
+    password: String,
+    config: QueryConfig,
+}
+
+struct ConnectionState {
+    conn: Option<Connection<'static>>,
+    config: QueryConfig,
+    autocommit: bool,
+}
+
+impl ConnectionState {
+    fn conn(&self) -> Result<&Connection<'static>> {
+        self.conn
+            .as_ref()
+            .ok_or_else(|| status_error(INVALID_STATE, "The connection is not initialized"))
+    }
+
+    // Statement settings for catalog queries, which must not be cut short by max_rows
+    fn catalog_config(&self) -> QueryConfig {
+        QueryConfig {
+            max_rows: None,
+            ..self.config.clone()
+        }
+    }
+
+    fn transaction(&self) -> Result<&Connection<'static>> {
+        if self.autocommit {
+            return Err(status_error(
+                INVALID_STATE,
+                "No transaction to end: autocommit is enabled",
+            ));
+        }
+        self.conn()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum IngestMode {
+    Create,
+    Append,
+    Replace,
+    CreateAppend,
+}
+
+impl IngestMode {
+    fn parse(mode: &str) -> Result<Self> {
+        match mode {
+            "adbc.ingest.mode.create" => Ok(IngestMode::Create),
+            "adbc.ingest.mode.append" => Ok(IngestMode::Append),
+            "adbc.ingest.mode.replace" => Ok(IngestMode::Replace),
+            "adbc.ingest.mode.create_append" => Ok(IngestMode::CreateAppend),
+            _ => Err(status_error(
+                INVALID_ARGUMENT,
+                format!("Unknown ingest mode '{}'", mode),
+            )),
+        }
+    }
+}
+
+struct StatementState {
+    // The connection must outlive its statements, as ADBC requires
+    connection: *mut ConnectionState,
+    sql: Option<String>,
+    bound: Option<(SchemaRef, Vec<RecordBatch>)>,
+    target: Option<String>,
+    mode: IngestMode,
+}
+
+impl StatementState {
+    fn connection(&self) -> &ConnectionState {
+        unsafe { &*self.connection }
+    }
+
+    fn sql(&self) -> Result<&str> {
+        self.sql
+            .as_deref()
+            .ok_or_else(|| status_error(INVALID_STATE, "No SQL query was set"))
+    }
+}
+
+unsafe extern "C" fn release_driver(driver: *mut AdbcDriver, _error: *mut AdbcError) -> Status {
+    if !driver.is_null() {
+        (*driver).private_data = ptr::null_mut();
+    }
+    OK
+}
+
+unsafe extern "C" fn database_new(database: *mut AdbcDatabase, error: *mut AdbcError) -> Status {
+    guard(error, || {
+        *outer(database, "database")?.private_data() =
+            Box::into_raw(Box::<DatabaseState>::default()) as *mut c_void;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn database_set_option(
+    database: *mut AdbcDatabase,
+    key: *const c_char,
+    value: *const c_char,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let state = handle::<DatabaseState, _>(database, "database")?;
+        let key = required_text(key, "The option key")?;
+        let value = required_text(value, "The option value")?;
+        match key.as_str() {
+            OPTION_URI => state.uri = Some(value),
+            OPTION_USERNAME => state.username = value,
+            OPTION_PASSWORD => state.password = value,
+            OPTION_CONFIG => {
+                state.config = serde_json::from_str(&value).map_err(|e| {
+                    status_error(
+                        INVALID_ARGUMENT,
+                        format!("Invalid {}: {}", OPTION_CONFIG, e),
+                    )
+                })?
+            }
+            _ => {
+                return Err(status_error(
+                    NOT_IMPLEMENTED,
+                    format!("Unknown database option '{}'", key),
+                ))
+            }
+        }
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn database_init(database: *mut AdbcDatabase, error: *mut AdbcError) -> Status {
+    guard(error, || {
+        let state = handle::<DatabaseState, _>(database, "database")?;
+        if state.uri.is_none() {
+            return Err(status_error(
+                INVALID_ARGUMENT,
+                "Set the 'uri' option to a DSN, database path or connection string",
+            ));
+        }
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn database_release(
+    database: *mut AdbcDatabase,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let database = outer(database, "database")?;
+        let private_data = *database.private_data();
+        if private_data.is_null() {
+            return Err(status_error(
+                INVALID_STATE,
+                "The database was already released",
+            ));
+        }
+        drop(Box::from_raw(private_data as *mut DatabaseState));
+        *database.private_data() = ptr::null_mut();
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn connection_new(
+    connection: *mut AdbcConnection,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let connection = outer(connection, "connection")?;
+        let state = ConnectionState {
+            conn: None,
+            config: QueryConfig::default(),
+            autocommit: true,
+        };
+        *connection.private_data() = Box::into_raw(Box::new(state)) as *mut c_void;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn connection_set_option(
+    connection: *mut AdbcConnection,
+    key: *const c_char,
+    value: *const c_char,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let state = handle::<ConnectionState, _>(connection, "connection")?;
+        let key = required_text(key, "The option key")?;
+        let value = required_text(value, "The option value")?;
+        match key.as_str() {
+            OPTION_AUTOCOMMIT => {
+                let autocommit = flag(&value)?;
+                // Turning autocommit back on commits the open transaction
+                if let Some(conn) = &state.conn {
+                    conn.set_autocommit(autocommit)?;
+                }
+                state.autocommit = autocommit;
+            }
+            OPTION_READ_ONLY if state.conn.is_none() => state.config.read_only = flag(&value)?,
+            _ => {
+                return Err(status_error(
+                    NOT_IMPLEMENTED,
+                    format!("Unknown or read-only connection option '{}'", key),
+                ))
+            }
+        }
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn connection_init(
+    connection: *mut AdbcConnection,
+    database: *mut AdbcDatabase,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let state = handle::<ConnectionState, _>(connection, "connection")?;
+        let database = handle::<DatabaseState, _>(database, "database")?;
+        let uri = database
+            .uri
+            .as_deref()
+            .ok_or_else(|| status_error(INVALID_STATE, "The database is not initialized"))?;
+        let config = QueryConfig {
+            read_only: database.config.read_only || state.config.read_only,
+            ..database.config.clone()
+        };
+        diagnostics::clear();
+        let env = odbc_api::environment()?;
+        let conn_str =
+            build_connection_string(uri, &database.username, &database.password, &config);
+        let conn = retry::with_retry(&config, || driverenv::connect(env, &conn_str, &config))
+            .map_err(diagnostics::attach)?;
+        if !state.autocommit {
+            conn.set_autocommit(false)?;
+        }
+        state.conn = Some(conn);
+        state.config = config;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn connection_commit(
+    connection: *mut AdbcConnection,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let state = handle::<ConnectionState, _>(connection, "connection")?;
+        Ok(state.transaction()?.commit()?)
+    })
+}
+
+unsafe extern "C" fn connection_rollback(
+    connection: *mut AdbcConnection,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let state = handle::<ConnectionState, _>(connection, "connection")?;
+        Ok(state.transaction()?.rollback()?)
+    })
+}
+
+unsafe extern "C" fn connection_release(
+    connection: *mut AdbcConnection,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let connection = outer(connection, "connection")?;
+        let private_data = *connection.private_data();
+        if private_data.is_null() {
+            return Err(status_error(
+                INVALID_STATE,
+                "The connection was already released",
+            ));
+        }
+        let state = Box::from_raw(private_data as *mut ConnectionState);
+        *connection.private_data() = ptr::null_mut();
+        // Work not committed is discarded, as when a DB-API connection is closed
+        if let (Some(conn), false) = (&state.conn, state.autocommit) {
+            let _ = conn.rollback();
+        }
+        Ok(())
+    })
+}
+
+unsafe fn export_stream(
+    out: *mut FFI_ArrowArrayStream,
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+) -> Result<()> {
+    if out.is_null() {
+        return Err(status_error(INVALID_ARGUMENT, "The output stream is NULL"));
+    }
+    let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema);
+    ptr::write(out, FFI_ArrowArrayStream::new(Box::new(reader)));
+    Ok(())
+}
+
+unsafe fn export_schema(out: *mut FFI_ArrowSchema, schema: &Schema) -> Result<()> {
+    if out.is_null() {
+        return Err(status_error(INVALID_ARGUMENT, "The output schema is NULL"));
+    }
+    ptr::write(out, FFI_ArrowSchema::try_from(schema)?);
+    Ok(())
+}
+
+// Values of one result column across batches, as text
+fn texts(batches: &[RecordBatch], column: usize) -> Result<Vec<Option<String>>> {
+    let mut values = Vec::new();
+    for batch in batches {
+        let array = cast(batch.column(column), &DataType::Utf8)?;
+        let array = array.as_string::<i32>();
+        values.extend(
+            array
+                .iter()
+                .map(|value| value.map(|v| v.trim().to_string())),
+        );
+    }
+    Ok(values)
+}
+
+fn integers(batches: &[RecordBatch], column: usize) -> Result<Vec<Option<i64>>> {
+    let mut values = Vec::new();
+    for batch in batches {
+        let array = cast(batch.column(column), &DataType::Int64)?;
+        values.extend(array.as_primitive::<Int64Type>().iter());
+    }
+    Ok(values)
+}
+
+fn catalog_rows(
+    conn: &Connection<'_>,
+    sql: &str,
+    params: Vec<SqlParam>,
+    config: &QueryConfig,
+) -> Result<Vec<RecordBatch>> {
+    let executed = execute_on(conn, sql, &[params], config)?;
+    Ok(executed
+        .rows
+        .map(|(_, batches)| batches)
+        .unwrap_or_default())
+}
+
+fn info_union_fields() -> Result<UnionFields> {
+    let entries = Fields::from(vec![
+        Field::new("key", DataType::Int32, false),
+        Field::new("value", DataType::List(item(DataType::Int32)), true),
+    ]);
+    let map = DataType::Map(
+        Arc::new(Field::new("entries", DataType::Struct(entries), false)),
+        false,
+    );
+    Ok(UnionFields::try_new(
+        0..=5,
+        vec![
+            Field::new("string_value", DataType::Utf8, true),
+            Field::new("bool_value", DataType::Boolean, true),
+            Field::new("int64_value", DataType::Int64, true),
+            Field::new("int32_bitmask", DataType::Int32, true),
+            Field::new("string_list", DataType::List(item(DataType::Utf8)), true),
+            Field::new("int32_to_int32_list_map", map, true),
+        ],
+    )?)
+}
+
+unsafe extern "C" fn connection_get_info(
+    connection: *mut AdbcConnection,
+    info_codes: *const u32,
+    info_codes_length: usize,
+    out: *mut FFI_ArrowArrayStream,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let state = handle::<ConnectionState, _>(connection, "connection")?;
+        let codes = if info_codes.is_null() {
+            vec![
+                INFO_VENDOR_NAME,
+                INFO_VENDOR_VERSION,
+                INFO_DRIVER_NAME,
+                INFO_DRIVER_VERSION,
+            ]
+        } else {
+            std::slice::from_raw_parts(info_codes, info_codes_length).to_vec()
+        };
+        let mut names = Vec::new();
+        let mut values = Vec::new();
+        for code in codes {
+            let value = match code {
+                INFO_VENDOR_NAME => "Firebird".to_string(),
+                INFO_VENDOR_VERSION => migrate::fetch_texts(state.conn()?, VERSION_SQL, None)?
+                    .pop()
+                    .unwrap_or_default(),
+                INFO_DRIVER_NAME => "ibarrow".to_string(),
+                INFO_DRIVER_VERSION => env!("CARGO_PKG_VERSION").to_string(),
+                // Codes the driver does not know are left out, as ADBC asks
+                _ => continue,
+            };
+            names.push(code);
+            values.push(value);
+        }
+        let fields = info_union_fields()?;
+        let rows = values.len();
+        let children = fields
+            .iter()
+            .map(|(type_id, field)| match type_id {
+                0 => Arc::new(StringArray::from(values.clone())) as ArrayRef,
+                _ => new_empty_array(field.data_type()),
+            })
+            .collect();
+        let info_value = UnionArray::try_new(
+            fields.clone(),
+            vec![0_i8; rows].into(),
+            Some((0..rows as i32).collect::<Vec<_>>().into()),
+            children,
+        )?;
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("info_name", DataType::UInt32, false),
+            Field::new("info_value", info_value.data_type().clone(), true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(UInt32Array::from(names)), Arc::new(info_value)],
+        )?;
+        export_stream(out, schema, vec![batch])
+    })
+}
+
+fn item(data_type: DataType) -> FieldRef {
+    Arc::new(Field::new("item", data_type, true))
+}
+
+fn utf8(name: &str, nullable: bool) -> Field {
+    Field::new(name, DataType::Utf8, nullable)
+}
+
+// The nested GetObjects schema of the ADBC specification, innermost first
+fn usage_fields() -> Fields {
+    Fields::from(vec![
+        utf8("fk_catalog", true),
+        utf8("fk_db_schema", true),
+        utf8("fk_table", false),
+        utf8("fk_column_name", false),
+    ])
+}
+
+fn constraint_fields() -> Fields {
+    Fields::from(vec![
+        utf8("constraint_name", true),
+        utf8("constraint_type", false),
+        Field::new(
+            "constraint_column_names",
+            DataType::List(item(DataType::Utf8)),
+            false,
+        ),
+        Field::new(
+            "constraint_column_usage",
+            DataType::List(item(DataType::Struct(usage_fields()))),
+            true,
+        ),
+    ])
+}
+
+fn column_fields() -> Fields {
+    Fields::from(vec![
+        utf8("column_name", false),
+        Field::new("ordinal_position", DataType::Int32, true),
+        utf8("remarks", true),
+        Field::new("xdbc_data_type", DataType::Int16, true),
+        utf8("xdbc_type_name", true),
+        Field::new("xdbc_column_size", DataType::Int32, true),
+        Field::new("xdbc_decimal_digits", DataType::Int16, true),
+        Field::new("xdbc_num_prec_radix", DataType::Int16, true),
+        Field::new("xdbc_nullable", DataType::Int16, true),
+        utf8("xdbc_column_def", true),
+        Field::new("xdbc_sql_data_type", DataType::Int16, true),
+        Field::new("xdbc_datetime_sub", DataType::Int16, true),
+        Field::new("xdbc_char_octet_length", DataType::Int32, true),
+        utf8("xdbc_is_nullable", true),
+        utf8("xdbc_scope_catalog", true),
+        utf8("xdbc_scope_schema", true),
+        utf8("xdbc_scope_table", true),
+        Field::new("xdbc_is_autoincrement", DataType::Boolean, true),
+        Field::new("xdbc_is_generated_column", DataType::Boolean, true),
+    ])
+}
+
+fn table_fields() -> Fields {
+    Fields::from(vec![
+        utf8("table_name", false),
+        utf8("table_type", false),
+        Field::new(
+            "table_columns",
+            DataType::List(item(DataType::Struct(column_fields()))),
+            true,
+        ),
+        Field::new(
+            "table_constraints",
+            DataType::List(item(DataType::Struct(constraint_fields()))),
+            true,
+        ),
+    ])
+}
+
+fn db_schema_fields() -> Fields {
+    Fields::from(vec![
+        utf8("db_schema_name", true),
+        Field::new(
+            "db_schema_tables",
+            DataType::List(item(DataType::Struct(table_fields()))),
+            true,
+        ),
+    ])
+}
+
+fn objects_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        utf8("catalog_name", true),
+        Field::new(
+            "catalog_db_schemas",
+            DataType::List(item(DataType::Struct(db_schema_fields()))),
+            true,
+        ),
+    ]))
+}
+
+// A list column holding `lengths[i]` consecutive entries of `values` in row i, or all nulls
+fn list(values: ArrayRef, lengths: Option<Vec<usize>>, rows: usize) -> Result<ArrayRef> {
+    let field = item(values.data_type().clone());
+    Ok(match lengths {
+        Some(lengths) => Arc::new(ListArray::try_new(
+            field,
+            OffsetBuffer::from_lengths(lengths),
+            values,
+            None,
+        )?),
+        None => Arc::new(ListArray::new_null(field, rows)),
+    })
+}
+
+struct ObjectColumn {
+    table: String,
+    name: String,
+    position: i32,
+    nullable: bool,
+}
+
+fn columns_array(columns: &[ObjectColumn]) -> Result<ArrayRef> {
+    let fields = column_fields();
+    let rows = columns.len();
+    let arrays = fields
+        .iter()
+        .map(|field| match field.name().as_str() {
+            "column_name" => Arc::new(StringArray::from_iter_values(
+                columns.iter().map(|column| column.name.as_str()),
+            )) as ArrayRef,
+            "ordinal_position" => Arc::new(Int32Array::from_iter_values(
+                columns.iter().map(|column| column.position),
+            )),
+            "xdbc_nullable" => Arc::new(Int16Array::from_iter_values(
+                columns.iter().map(|column| column.nullable as i16),
+            )),
+            "xdbc_is_nullable" => Arc::new(StringArray::from_iter_values(
+                columns
+                    .iter()
+                    .map(|column| if column.nullable { "YES" } else { "NO" }),
+            )),
+            _ => new_null_array(field.data_type(), rows),
+        })
+        .collect();
+    Ok(Arc::new(StructArray::try_new(fields, arrays, None)?))
+}
+
+// Firebird has no catalogs or schemas, so its objects sit in one unnamed catalog and schema,
+// which only unset, empty or match-all patterns select
+fn matches_unnamed(pattern: Option<&str>) -> bool {
+    pattern.is_none_or(|pattern| pattern.chars().all(|c| c == '%'))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn get_objects(
+    state: &ConnectionState,
+    depth: c_int,
+    catalog: Option<&str>,
+    db_schema: Option<&str>,
+    table_name: Option<&str>,
+    table_types: Option<Vec<String>>,
+    column_name: Option<&str>,
+) -> Result<RecordBatch> {
+    if !matches_unnamed(catalog) || !matches_unnamed(db_schema) {
+        return Ok(RecordBatch::new_empty(objects_schema()));
+    }
+    let conn = state.conn()?;
+    let config = state.catalog_config();
+    let table_pattern = table_name.unwrap_or("%").to_string();
+    let mut tables = Vec::new();
+    if depth != DEPTH_CATALOGS && depth != DEPTH_DB_SCHEMAS {
+        let batches = catalog_rows(
+            conn,
+            &catalog::tables_sql(true),
+            vec![SqlParam::Text(table_pattern.clone())],
+            &config,
+        )?;
+        for (name, kind) in texts(&batches, 0)?.into_iter().zip(texts(&batches, 1)?) {
+            let (Some(name), Some(kind)) = (name, kind) else {
+                continue;
+            };
+            if table_types
+                .as_ref()
+                .is_none_or(|types| types.iter().any(|t| t.eq_ignore_ascii_case(&kind)))
+            {
+                tables.push((name, kind));
+            }
+        }
+    }
+    let with_columns =
+        depth != DEPTH_CATALOGS && depth != DEPTH_DB_SCHEMAS && depth != DEPTH_TABLES;
+    let mut columns = Vec::new();
+    if with_columns {
+        let params = vec![
+            SqlParam::Text(table_pattern),
+            SqlParam::Text(column_name.unwrap_or("%").to_string()),
+        ];
+        let batches = catalog_rows(conn, COLUMNS_SQL, params, &config)?;
+        let rows = texts(&batches, 0)?
+            .into_iter()
+            .zip(texts(&batches, 1)?)
+            .zip(integers(&batches, 2)?)
+            .zip(integers(&batches, 3)?);
+        for (((table, name), position), not_null) in rows {
+            let (Some(table), Some(name)) = (table, name) else {
+                continue;
+            };
+            columns.push(ObjectColumn {
+                table,
+                name,
+                position: position.unwrap_or_default() as i32 + 1,
+                nullable: not_null.unwrap_or_default() == 0,
+            });
+        }
+        // Only the columns of the tables listed, grouped in table order
+        columns.retain(|column| tables.iter().any(|(name, _)| *name == column.table));
+        columns.sort_by_key(|column| {
+            let table = tables.iter().position(|(name, _)| *name == column.table);
+            (table, column.position)
+        });
+    }
+    objects_batch(depth, &tables, &columns)
+}
+
+// The single-catalog GetObjects result holding `tables` (name, type) and, at column depth,
+// their `columns` grouped in table order
+fn objects_batch(
+    depth: c_int,
+    tables: &[(String, String)],
+    columns: &[ObjectColumn],
+) -> Result<RecordBatch> {
+    let with_columns =
+        depth != DEPTH_CATALOGS && depth != DEPTH_DB_SCHEMAS && depth != DEPTH_TABLES;
+    let table_count = tables.len();
+    let column_lengths = with_columns.then(|| {
+        tables
+            .iter()
+            .map(|(name, _)| columns.iter().filter(|c| c.table == *name).count())
+            .collect()
+    });
+    // Constraints are not reported: every table gets an empty list
+    let constraints = new_empty_array(&DataType::Struct(constraint_fields()));
+    let table_arrays: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(
+            tables.iter().map(|(name, _)| name.as_str()),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            tables.iter().map(|(_, kind)| kind.as_str()),
+        )),
+        list(columns_array(columns)?, column_lengths, table_count)?,
+        list(
+            constraints,
+            with_columns.then(|| vec![0; table_count]),
+            table_count,
+        )?,
+    ];
+    let tables_array = Arc::new(StructArray::try_new(table_fields(), table_arrays, None)?);
+    let with_tables = depth != DEPTH_CATALOGS && depth != DEPTH_DB_SCHEMAS;
+    let db_schemas = Arc::new(StructArray::try_new(
+        db_schema_fields(),
+        vec![
+            Arc::new(StringArray::from(vec![None::<&str>])),
+            list(tables_array, with_tables.then(|| vec![table_count]), 1)?,
+        ],
+        None,
+    )?);
+    let with_db_schemas = depth != DEPTH_CATALOGS;
+    Ok(RecordBatch::try_new(
+        objects_schema(),
+        vec![
+            Arc::new(StringArray::from(vec![None::<&str>])),
+            list(db_schemas, with_db_schemas.then(|| vec![1]), 1)?,
+        ],
+    )?)
+}
+
+unsafe extern "C" fn connection_get_objects(
+    connection: *mut AdbcConnection,
+    depth: c_int,
+    catalog: *const c_char,
+    db_schema: *const c_char,
+    table_name: *const c_char,
+    table_type: *const *const c_char,
+    column_name: *const c_char,
+    out: *mut FFI_ArrowArrayStream,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let state = handle::<ConnectionState, _>(connection, "connection")?;
+        let table_types = if table_type.is_null() {
+            None
+        } else {
+            let mut types = Vec::new();
+            let mut next = table_type;
+            while !(*next).is_null() {
+                types.push(required_text(*next, "A table type")?);
+                next = next.add(1);
+            }
+            Some(types)
+        };
+        let batch = get_objects(
+            state,
+            depth,
+            text(catalog, "The catalog")?.as_deref(),
+            text(db_schema, "The schema")?.as_deref(),
+            text(table_name, "The table name")?.as_deref(),
+            table_types,
+            text(column_name, "The column name")?.as_deref(),
+        )?;
+        export_stream(out, batch.schema(), vec![batch])
+    })
+}
+
+unsafe extern "C" fn connection_get_table_schema(
+    connection: *mut AdbcConnection,
+    _catalog: *const c_char,
+    _db_schema: *const c_char,
+    table_name: *const c_char,
+    schema: *mut FFI_ArrowSchema,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let state = handle::<ConnectionState, _>(connection, "connection")?;
+        let table = required_text(table_name, "The table name")?;
+        let sql = format!("SELECT * FROM {} WHERE 1 = 0", quote_identifier(&table));
+        access::check(&sql, &state.config)?;
+        let executed = execute_on(state.conn()?, &sql, &[vec![]], &state.config)?;
+        let (table_schema, _) = executed
+            .rows
+            .ok_or_else(|| status_error(NOT_FOUND, format!("Table {} not found", table)))?;
+        export_schema(schema, &table_schema)
+    })
+}
+
+unsafe extern "C" fn connection_get_table_types(
+    connection: *mut AdbcConnection,
+    out: *mut FFI_ArrowArrayStream,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        handle::<ConnectionState, _>(connection, "connection")?;
+        let schema = Arc::new(Schema::new(vec![utf8("table_type", false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(StringArray::from(TABLE_TYPES.to_vec()))],
+        )?;
+        export_stream(out, schema, vec![batch])
+    })
+}
+
+unsafe extern "C" fn connection_read_partition(
+    _connection: *mut AdbcConnection,
+    _partition: *const u8,
+    _length: usize,
+    _out: *mut FFI_ArrowArrayStream,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        Err(status_error(
+            NOT_IMPLEMENTED,
+            "Partitioned results are not supported",
+        ))
+    })
+}
+
+unsafe extern "C" fn statement_new(
+    connection: *mut AdbcConnection,
+    statement: *mut AdbcStatement,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let connection = handle::<ConnectionState, _>(connection, "connection")?;
+        connection.conn()?;
+        let statement = outer(statement, "statement")?;
+        let state = StatementState {
+            connection,
+            sql: None,
+            bound: None,
+            target: None,
+            mode: IngestMode::Create,
+        };
+        *statement.private_data() = Box::into_raw(Box::new(state)) as *mut c_void;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn statement_release(
+    statement: *mut AdbcStatement,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let statement = outer(statement, "statement")?;
+        let private_data = *statement.private_data();
+        if private_data.is_null() {
+            return Err(status_error(
+                INVALID_STATE,
+                "The statement was already released",
+            ));
+        }
+        drop(Box::from_raw(private_data as *mut StatementState));
+        *statement.private_data() = ptr::null_mut();
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn statement_set_sql_query(
+    statement: *mut AdbcStatement,
+    query: *const c_char,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let state = handle::<StatementState, _>(statement, "statement")?;
+        state.sql = Some(required_text(query, "The query")?);
+        state.target = None;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn statement_set_substrait_plan(
+    _statement: *mut AdbcStatement,
+    _plan: *const u8,
+    _length: usize,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        Err(status_error(
+            NOT_IMPLEMENTED,
+            "Substrait plans are not supported",
+        ))
+    })
+}
+
+unsafe extern "C" fn statement_set_option(
+    statement: *mut AdbcStatement,
+    key: *const c_char,
+    value: *const c_char,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let state = handle::<StatementState, _>(statement, "statement")?;
+        let key = required_text(key, "The option key")?;
+        let value = required_text(value, "The option value")?;
+        match key.as_str() {
+            OPTION_INGEST_TARGET => {
+                state.target = Some(value);
+                state.sql = None;
+            }
+            OPTION_INGEST_MODE => state.mode = IngestMode::parse(&value)?,
+            _ => {
+                return Err(status_error(
+                    NOT_IMPLEMENTED,
+                    format!("Unknown statement option '{}'", key),
+                ))
+            }
+        }
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn statement_prepare(
+    statement: *mut AdbcStatement,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let state = handle::<StatementState, _>(statement, "statement")?;
+        if state.target.is_some() {
+            return Ok(());
+        }
+        // Statements are prepared again when executed; this only checks the SQL
+        state.connection().conn()?.prepare(state.sql()?)?;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn statement_get_parameter_schema(
+    statement: *mut AdbcStatement,
+    schema: *mut FFI_ArrowSchema,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let state = handle::<StatementState, _>(statement, "statement")?;
+        let mut prepared = state.connection().conn()?.prepare(state.sql()?)?;
+        // ODBC reports how many markers there are but not their types
+        let fields: Vec<Field> = (0..prepared.num_params()?)
+            .map(|index| Field::new(index.to_string(), DataType::Null, true))
+            .collect();
+        export_schema(schema, &Schema::new(fields))
+    })
+}
+
+unsafe extern "C" fn statement_bind(
+    statement: *mut AdbcStatement,
+    values: *mut FFI_ArrowArray,
+    schema: *mut FFI_ArrowSchema,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let state = handle::<StatementState, _>(statement, "statement")?;
+        if values.is_null() || schema.is_null() {
+            return Err(status_error(
+                INVALID_ARGUMENT,
+                "The bound array or schema is NULL",
+            ));
+        }
+        let array = FFI_ArrowArray::from_raw(values);
+        let schema = FFI_ArrowSchema::from_raw(schema);
+        let data = from_ffi(array, &schema)?;
+        let batch = RecordBatch::from(StructArray::from(data));
+        state.bound = Some((batch.schema(), vec![batch]));
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn statement_bind_stream(
+    statement: *mut AdbcStatement,
+    stream: *mut FFI_ArrowArrayStream,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let state = handle::<StatementState, _>(statement, "statement")?;
+        if stream.is_null() {
+            return Err(status_error(INVALID_ARGUMENT, "The bound stream is NULL"));
+        }
+        let reader = ArrowArrayStreamReader::from_raw(stream)?;
+        let schema = reader.schema();
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        state.bound = Some((schema, batches));
+        Ok(())
+    })
+}
+
+// One bound value. Dates, times, timestamps and decimals bind through their text form, which
+// Firebird/InterBase cast implicitly, as in params::SqlParam.
+fn param(array: &ArrayRef, formatter: &ArrayFormatter<'_>, row: usize) -> SqlParam {
+    if array.is_null(row) {
+        return SqlParam::Null;
+    }
+    match array.data_type() {
+        DataType::Null => SqlParam::Null,
+        DataType::Boolean => SqlParam::Bool(array.as_boolean().value(row)),
+        DataType::Int8 => SqlParam::Int(array.as_primitive::<Int8Type>().value(row).into()),
+        DataType::Int16 => SqlParam::Int(array.as_primitive::<Int16Type>().value(row).into()),
+        DataType::Int32 => SqlParam::Int(array.as_primitive::<Int32Type>().value(row).into()),
+        DataType::Int64 => SqlParam::Int(array.as_primitive::<Int64Type>().value(row)),
+        DataType::UInt8 => SqlParam::Int(array.as_primitive::<UInt8Type>().value(row).into()),
+        DataType::UInt16 => SqlParam::Int(array.as_primitive::<UInt16Type>().value(row).into()),
+        DataType::UInt32 => SqlParam::Int(array.as_primitive::<UInt32Type>().value(row).into()),
+        DataType::UInt64 => {
+            let value = array.as_primitive::<UInt64Type>().value(row);
+            i64::try_from(value)
+                .map(SqlParam::Int)
+                .unwrap_or_else(|_| SqlParam::Text(value.to_string()))
+        }
+        DataType::Float16 => {
+            SqlParam::Float(array.as_primitive::<Float16Type>().value(row).to_f64())
+        }
+        DataType::Float32 => SqlParam::Float(array.as_primitive::<Float32Type>().value(row).into()),
+        DataType::Float64 => SqlParam::Float(array.as_primitive::<Float64Type>().value(row)),
+        DataType::Binary => SqlParam::Encoded(array.as_binary::<i32>().value(row).to_vec()),
+        DataType::LargeBinary => SqlParam::Encoded(array.as_binary::<i64>().value(row).to_vec()),
+        _ => SqlParam::Text(formatter.value(row).to_string()),
+    }
+}
+
+// One parameter set per bound row
+fn param_sets(batches: &[RecordBatch]) -> Result<Vec<Vec<SqlParam>>> {
+    let options = FormatOptions::new()
+        .with_timestamp_format(Some("%Y-%m-%d %H:%M:%S%.f"))
+        .with_timestamp_tz_format(Some("%Y-%m-%d %H:%M:%S%.f %:z"));
+    let mut sets = Vec::new();
+    for batch in batches {
+        let formatters = batch
+            .columns()
+            .iter()
+            .map(|column| ArrayFormatter::try_new(column.as_ref(), &options))
+            .collect::<Result<Vec<_>, _>>()?;
+        for row in 0..batch.num_rows() {
+            sets.push(
+                batch
+                    .columns()
+                    .iter()
+                    .zip(&formatters)
+                    .map(|(column, formatter)| param(column, formatter, row))
+                    .collect(),
+            );
+        }
+    }
+    Ok(sets)
+}
+
+// Creates `table` for data that has not been read yet, so strings become text blobs: no
+// VARCHAR length is known to hold them
+fn create_table(conn: &Connection<'_>, table: &str, schema: &Schema) -> Result<()> {
+    for field in schema.fields() {
+        if ddl::column_type(field.data_type(), Dialect::Firebird, None).is_err() {
+            return Err(status_error(
+                NOT_IMPLEMENTED,
+                format!("Cannot ingest columns of type {}", field.data_type()),
+            ));
+        }
+    }
+    write::create_table(conn, table, schema)
+}
+
+// Bulk ingestion of the bound data into `target`, creating or replacing the table first as
+// the ingest mode asks. Returns the rows inserted.
+//
+// Firebird only applies DDL when its transaction commits, so the table cannot be created and
+// filled atomically: under autocommit the DROP/CREATE is committed on its own and the rows
+// then go in as one transaction. A failed insert therefore leaves a Replace target empty,
+// never half filled.
+fn ingest(state: &StatementState, target: &str) -> Result<usize> {
+    let (schema, batches) = state.bound.as_ref().ok_or_else(|| {
+        status_error(INVALID_STATE, "Bind data before executing a bulk ingestion")
+    })?;
+    let connection = state.connection();
+    let conn = connection.conn()?;
+    let table = quote_identifier(target);
+    let columns: Vec<String> = schema
+        .fields()
+        .iter()
+        .map(|field| quote_identifier(field.name()))
+        .collect();
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        table,
+        columns.join(", "),
+        vec!["?"; columns.len()].join(", ")
+    );
+    // Before any DDL: an access role may not write at all
+    access::check(&sql, &connection.config)?;
+    let sets = param_sets(batches)?;
+    let exists = catalog::table_exists(conn, target)?;
+    match state.mode {
+        IngestMode::Create => create_table(conn, &table, schema)?,
+        IngestMode::CreateAppend if !exists => create_table(conn, &table, schema)?,
+        IngestMode::Replace => {
+            if exists {
+                conn.execute(&format!("DROP TABLE {}", table), (), None)?;
+            }
+            create_table(conn, &table, schema)?;
+        }
+        IngestMode::Append | IngestMode::CreateAppend => {}
+    }
+    if !connection.autocommit {
+        let executed = execute_on(conn, &sql, &sets, &connection.config)?;
+        return Ok(executed.rowcount.unwrap_or(sets.len()));
+    }
+    conn.set_autocommit(false)?;
+    let inserted = execute_on(conn, &sql, &sets, &connection.config)
+        .and_then(|executed| Ok(conn.commit().map(|_| executed)?));
+    if inserted.is_err() {
+        // The failed insert is the error reported, not a failed rollback
+        let _ = conn.rollback();
+    }
+    conn.set_autocommit(true)?;
+    let executed = inserted?;
+    Ok(executed.rowcount.unwrap_or(sets.len()))
+}
+
+unsafe extern "C" fn statement_execute_query(
+    statement: *mut AdbcStatement,
+    out: *mut FFI_ArrowArrayStream,
+    rows_affected: *mut i64,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        let state = handle::<StatementState, _>(statement, "statement")?;
+        diagnostics::clear();
+        let rowcount = if let Some(target) = state.target.clone() {
+            if !out.is_null() {
+                return Err(status_error(
+                    INVALID_ARGUMENT,
+                    "A bulk ingestion returns no result set; pass a NULL stream",
+                ));
+            }
+            Some(ingest(state, &target)?)
+        } else {
+            let sql = state.sql()?;
+            let connection = state.connection();
+            access::check(sql, &connection.config)?;
+            // Without bound data the statement runs once, with one execution per bound row
+            // otherwise
+            let sets = match &state.bound {
+                Some((_, batches)) => param_sets(batches)?,
+                None => vec![vec![]],
+            };
+            let executed = execute_on(connection.conn()?, sql, &sets, &connection.config)
+                .map_err(diagnostics::attach)?;
+            if !out.is_null() {
+                let (schema, batches) = executed
+                    .rows
+                    .unwrap_or_else(|| (Arc::new(Schema::empty()), Vec::new()));
+                export_stream(out, schema, batches)?;
+            }
+            executed.rowcount
+        };
+        if !rows_affected.is_null() {
+            *rows_affected = rowcount.map_or(-1, |rows| rows as i64);
+        }
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn statement_execute_partitions(
+    _statement: *mut AdbcStatement,
+    _schema: *mut FFI_ArrowSchema,
+    _partitions: *mut c_void,
+    _rows_affected: *mut i64,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        Err(status_error(
+            NOT_IMPLEMENTED,
+            "Partitioned results are not supported",
+        ))
+    })
+}
+
+// Driver entry point. Only ADBC 1.0.0 is offered; driver managers asking for 1.1.0 first get
+// NOT_IMPLEMENTED and retry with 1.0.0.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn AdbcDriverInit(
+    version: c_int,
+    driver: *mut c_void,
+    error: *mut AdbcError,
+) -> Status {
+    guard(error, || {
+        if version != VERSION_1_0_0 {
+            return Err(status_error(
+                NOT_IMPLEMENTED,
+                format!("Only ADBC 1.0.0 is supported, not version {}", version),
+            ));
+        }
+        if driver.is_null() {
+            return Err(status_error(INVALID_ARGUMENT, "driver is NULL"));
+        }
+        ptr::write(
+            driver as *mut AdbcDriver,
+            AdbcDriver {
+                private_data: ptr::null_mut(),
+                private_manager: ptr::null_mut(),
+                release: release_driver,
+                database_init,
+                database_new,
+                database_set_option,
+                database_release,
+                connection_commit,
+                connection_get_info,
+                connection_get_objects,
+                connection_get_table_schema,
+                connection_get_table_types,
+                connection_init,
+                connection_new,
+                connection_set_option,
+                connection_read_partition,
+                connection_release,
+                connection_rollback,
+                statement_bind,
+                statement_bind_stream,
+                statement_execute_query,
+                statement_execute_partitions,
+                statement_get_parameter_schema,
+                statement_new,
+                statement_prepare,
+                statement_release,
+                statement_set_option,
+                statement_set_sql_query,
+                statement_set_substrait_plan,
+            },
+        );
+        Ok(())
+    })
+}
+
+// The entry point driver managers derive from the library name (libibarrow.so)
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn AdbcIbarrowDriverInit(
+    version: c_int,
+    driver: *mut c_void,
+    error: *mut AdbcError,
+) -> Status {
+    AdbcDriverInit(version, driver, error)
+}
+
+// Path of the shared library that holds the ADBC entry point
+#[pyfunction]
+pub fn adbc_driver_path(py: Python<'_>) -> PyResult<String> {
+    py.import_bound("ibarrow._ibarrow")?
+        .getattr("__file__")?
+        .extract()
+}
+
+// An adbc_driver_manager DB-API connection through this library's ADBC driver
+#[pyfunction]
+#[pyo3(signature = (uri, user="", password="", config=None, autocommit=false))]
+pub fn adbc_connect(
+    py: Python<'_>,
+    uri: &str,
+    user: &str,
+    password: &str,
+    config: Option<&QueryConfig>,
+    autocommit: bool,
+) -> PyResult<Py<PyAny>> {
+    let dbapi = py.import_bound("adbc_driver_manager.dbapi").map_err(|_| {
+        PyImportError::new_err(
+            "adbc_connect needs adbc-driver-manager (pip install adbc-driver-manager)",
+        )
+    })?;
+    let db_kwargs = PyDict::new_bound(py);
+    db_kwargs.set_item(OPTION_URI, uri)?;
+    db_kwargs.set_item(OPTION_USERNAME, user)?;
+    db_kwargs.set_item(OPTION_PASSWORD, password)?;
+    if let Some(config) = config {
+        let json = serde_json::to_string(config)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        db_kwargs.set_item(OPTION_CONFIG, json)?;
+    }
+    let kwargs = PyDict::new_bound(py);
+    kwargs.set_item("driver", adbc_driver_path(py)?)?;
+    kwargs.set_item("entrypoint", "AdbcDriverInit")?;
+    kwargs.set_item("db_kwargs", db_kwargs)?;
+    kwargs.set_item("autocommit", autocommit)?;
+    Ok(dbapi.call_method("connect", (), Some(&kwargs))?.unbind())
+}
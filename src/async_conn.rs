@@ -0,0 +1,374 @@
+use anyhow::{anyhow, Result};
+use arrow::record_batch::RecordBatch;
+use pyo3::exceptions::{PyRuntimeError, PyStopAsyncIteration, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyCFunction, PyTuple};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+
+use crate::diagnostics;
+use crate::interop::batch_to_pyarrow;
+use crate::progress::Progress;
+use crate::watchdog::Watchdog;
+use crate::{
+    backend, check_driver_manager, limit_rows, map_query_error, query_arrow_ipc_impl,
+    query_pandas_impl, query_polars_impl, DtypeBackend, IbarrowConnection, QueryConfig,
+};
+
+// Threads running the blocking side of async calls; further calls wait for a free one
+const ASYNC_WORKERS: usize = 8;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// Hands `job` to the async worker pool, started on first use
+fn submit(job: Job) -> PyResult<()> {
+    static POOL: OnceLock<Sender<Job>> = OnceLock::new();
+    let pool = POOL.get_or_init(|| {
+        let (sender, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for worker in 0..ASYNC_WORKERS {
+            let receiver = Arc::clone(&receiver);
+            let spawned = thread::Builder::new()
+                .name(format!("ibarrow-async-{}", worker))
+                .spawn(move || loop {
+                    let job = receiver.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                    match job {
+                        // A panicking call must not take the worker with it
+                        Ok(job) => drop(panic::catch_unwind(AssertUnwindSafe(job))),
+                        Err(_) => break,
+                    }
+                });
+            if let Err(e) = spawned {
+                eprintln!("WARN: Failed to start async worker: {}", e);
+            }
+        }
+        sender
+    });
+    pool.send(job)
+        .map_err(|_| PyRuntimeError::new_err("The async worker pool has stopped"))
+}
+
+// Runs blocking ODBC work on the async worker pool and returns an asyncio future for its
+// result. `undelivered` is called once the future is settled: with None when it got the
+// result, or with the result itself when the future was cancelled while the work ran or the
+// event loop was closed.
+fn spawn_future<F, U>(py: Python<'_>, work: F, undelivered: U) -> PyResult<Py<PyAny>>
+where
+    F: FnOnce(Python<'_>) -> PyResult<Py<PyAny>> + Send + 'static,
+    U: FnOnce(Option<PyResult<Py<PyAny>>>) + Send + 'static,
+{
+    let event_loop = py
+        .import_bound("asyncio")?
+        .call_method0("get_running_loop")?;
+    let future = event_loop.call_method0("create_future")?;
+    let (event_loop_ref, future_ref) = (event_loop.clone().unbind(), future.clone().unbind());
+
+    submit(Box::new(move || {
+        Python::with_gil(|py| {
+            let finished = Arc::new(Mutex::new(Some((work(py), undelivered))));
+            let held = Arc::clone(&finished);
+            let complete = PyCFunction::new_closure_bound(
+                py,
+                None,
+                None,
+                move |args: &Bound<'_, PyTuple>, _kwargs| -> PyResult<()> {
+                    let py = args.py();
+                    let future = args.get_item(0)?;
+                    let Some((outcome, undelivered)) =
+                        held.lock().unwrap_or_else(|e| e.into_inner()).take()
+                    else {
+                        return Ok(());
+                    };
+                    // The future may have been cancelled while the query was running
+                    if future.call_method0("done")?.is_truthy()? {
+                        undelivered(Some(outcome));
+                        return Ok(());
+                    }
+                    undelivered(None);
+                    match outcome {
+                        Ok(value) => future.call_method1("set_result", (value,)),
+                        Err(err) => future.call_method1("set_exception", (err.into_value(py),)),
+                    }?;
+                    Ok(())
+                },
+            );
+            let scheduled = complete.and_then(|complete| {
+                event_loop_ref
+                    .bind(py)
+                    .call_method1("call_soon_threadsafe", (complete, future_ref.bind(py)))
+            });
+            if let Err(err) = scheduled {
+                // The event loop was closed before the query finished
+                err.print(py);
+                if let Some((outcome, undelivered)) =
+                    finished.lock().unwrap_or_else(|e| e.into_inner()).take()
+                {
+                    undelivered(Some(outcome));
+                }
+            }
+        })
+    }))?;
+
+    Ok(future.unbind())
+}
+
+// `undelivered` for calls whose result is of no use once their future is gone
+fn discard(_: Option<PyResult<Py<PyAny>>>) {}
+
+// asyncio flavour of IbarrowConnection: every query returns an awaitable
+#[pyclass]
+pub struct AsyncIbarrowConnection {
+    dsn: String,
+    user: String,
+    password: String,
+    config: QueryConfig,
+}
+
+#[pymethods]
+impl AsyncIbarrowConnection {
+    #[new]
+    fn new(dsn: &str, user: &str, password: &str, config: Option<&QueryConfig>) -> Self {
+        let sync = IbarrowConnection::new(dsn, user, password, config);
+        Self {
+            dsn: sync.dsn,
+            user: sync.user,
+            password: sync.password,
+            config: sync.config,
+        }
+    }
+
+    fn query_arrow_ipc(&self, py: Python<'_>, sql: &str) -> PyResult<Py<PyAny>> {
+        let (dsn, user, password, sql, config) = self.owned_args(sql);
+        spawn_future(
+            py,
+            move |py| {
+                let bytes = py
+                    .allow_threads(|| query_arrow_ipc_impl(&dsn, &user, &password, &sql, &config))
+                    .map_err(map_query_error)?;
+                Ok(PyBytes::new_bound(py, &bytes).into())
+            },
+            discard,
+        )
+    }
+
+    fn query_polars(&self, py: Python<'_>, sql: &str) -> PyResult<Py<PyAny>> {
+        let (dsn, user, password, sql, config) = self.owned_args(sql);
+        spawn_future(
+            py,
+            move |py| query_polars_impl(py, &dsn, &user, &password, &sql, &config),
+            discard,
+        )
+    }
+
+    #[pyo3(signature = (sql, dtype_backend=None))]
+    fn query_pandas(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        dtype_backend: Option<&str>,
+    ) -> PyResult<Py<PyAny>> {
+        let backend =
+            DtypeBackend::parse(dtype_backend).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let (dsn, user, password, sql, config) = self.owned_args(sql);
+        spawn_future(
+            py,
+            move |py| query_pandas_impl(py, &dsn, &user, &password, &sql, &config, backend),
+            discard,
+        )
+    }
+
+    fn test_connection(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        let (dsn, user, password, sql, config) =
+            self.owned_args("SELECT 1 as test_value FROM RDB$DATABASE");
+        spawn_future(
+            py,
+            move |py| {
+                let ok = py
+                    .allow_threads(|| query_arrow_ipc_impl(&dsn, &user, &password, &sql, &config))
+                    .is_ok();
+                Ok(ok.into_py(py))
+            },
+            discard,
+        )
+    }
+
+    // Async iterator of pyarrow.RecordBatch objects; fetching pauses while the consumer is busy
+    #[pyo3(signature = (sql, prefetch=1))]
+    fn stream(&self, sql: &str, prefetch: usize) -> AsyncBatchStream {
+        let (dsn, user, password, sql, config) = self.owned_args(sql);
+        // A bounded channel is the backpressure: the producer blocks once it is full
+        let (sender, receiver) = sync_channel(prefetch.max(1));
+        // The producer runs for as long as the stream is read, so it gets its own thread rather
+        // than holding a worker of the pool the __anext__ calls need
+        thread::spawn(move || {
+            if let Err(e) = produce_batches(&dsn, &user, &password, &sql, &config, None, &sender) {
+                let _ = sender.send(Err(diagnostics::attach(e)));
+            }
+        });
+        AsyncBatchStream {
+            state: Arc::new((
+                Mutex::new(StreamState {
+                    receiver,
+                    pending: None,
+                    busy: false,
+                }),
+                Condvar::new(),
+            )),
+        }
+    }
+
+    fn close(&self) -> PyResult<()> {
+        // Same stateless model as IbarrowConnection
+        Ok(())
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "AsyncIbarrowConnection(dsn='{}', user='{}')",
+            self.dsn, self.user
+        )
+    }
+}
+
+impl AsyncIbarrowConnection {
+    // Worker threads outlive the call, so they get their own copies of the settings
+    fn owned_args(&self, sql: &str) -> (String, String, String, String, QueryConfig) {
+        (
+            self.dsn.clone(),
+            self.user.clone(),
+            self.password.clone(),
+            sql.to_string(),
+            self.config.clone(),
+        )
+    }
+}
+
+// Async counterpart of connect()
+#[pyfunction]
+pub fn connect_async(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    config: Option<&QueryConfig>,
+) -> PyResult<AsyncIbarrowConnection> {
+    check_driver_manager(config)?;
+    Ok(AsyncIbarrowConnection::new(dsn, user, password, config))
+}
+
+// Producer side of AsyncBatchStream and BatchIterator; stops as soon as the consumer goes away.
+// `rows_per_batch` overrides the reader's default batch length.
+pub(crate) fn produce_batches(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+    rows_per_batch: Option<usize>,
+    sender: &SyncSender<Result<RecordBatch>>,
+) -> Result<()> {
+    let watchdog = Watchdog::start(config);
+    let result = produce_batches_watched(
+        dsn,
+        user,
+        password,
+        sql,
+        config,
+        rows_per_batch,
+        sender,
+        &watchdog,
+    );
+    watchdog.finish(result)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn produce_batches_watched(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+    rows_per_batch: Option<usize>,
+    sender: &SyncSender<Result<RecordBatch>>,
+    watchdog: &Watchdog,
+) -> Result<()> {
+    let config = &QueryConfig {
+        batch_size: rows_per_batch.map(|rows| rows as u32).or(config.batch_size),
+        ..config.clone()
+    };
+    let reader =
+        match backend::open(dsn, user, password, config)?.batches(sql, &[], config, watchdog)? {
+            Some(reader) => reader,
+            None => return Ok(()),
+        };
+    let mut progress = Progress::new(config);
+    for batch in limit_rows(reader, config) {
+        watchdog.check()?;
+        let batch = batch.map_err(|e| anyhow!("ERROR: Failed to read batch: {}", e))?;
+        progress.batch(&batch)?;
+        if sender.send(Ok(batch)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+struct StreamState {
+    receiver: Receiver<Result<RecordBatch>>,
+    // Result taken by an __anext__ whose future was cancelled, for the next call to return
+    pending: Option<PyResult<Py<PyAny>>>,
+    // An __anext__ holds the next result and has not yet delivered or returned it
+    busy: bool,
+}
+
+// Returned by AsyncIbarrowConnection.stream(); use with `async for`. Cancelling an __anext__
+// does not lose its batch: the next call returns it.
+#[pyclass]
+pub struct AsyncBatchStream {
+    state: Arc<(Mutex<StreamState>, Condvar)>,
+}
+
+#[pymethods]
+impl AsyncBatchStream {
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __anext__(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        let stream = Arc::clone(&self.state);
+        let returned = Arc::clone(&self.state);
+        spawn_future(
+            py,
+            move |py| {
+                let next = py.allow_threads(|| {
+                    let (state, idle) = &*stream;
+                    let mut state = idle
+                        .wait_while(state.lock().unwrap_or_else(|e| e.into_inner()), |state| {
+                            state.busy
+                        })
+                        .unwrap_or_else(|e| e.into_inner());
+                    state.busy = true;
+                    match state.pending.take() {
+                        Some(pending) => Err(pending),
+                        None => Ok(state.receiver.recv().ok()),
+                    }
+                });
+                match next {
+                    Err(pending) => pending,
+                    Ok(Some(Ok(batch))) => batch_to_pyarrow(py, batch),
+                    Ok(Some(Err(e))) => Err(map_query_error(e)),
+                    // Producer finished and hung up
+                    Ok(None) => Err(PyStopAsyncIteration::new_err(())),
+                }
+            },
+            move |undelivered| {
+                let (state, idle) = &*returned;
+                let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+                state.pending = undelivered;
+                state.busy = false;
+                idle.notify_one();
+            },
+        )
+    }
+}
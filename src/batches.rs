@@ -0,0 +1,106 @@
+use anyhow::Result;
+use arrow::record_batch::RecordBatch;
+use pyo3::exceptions::PyStopIteration;
+use pyo3::prelude::*;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::async_conn::produce_batches;
+use crate::diagnostics;
+use crate::interop::batch_to_pyarrow;
+use crate::{map_query_error, QueryConfig};
+
+// Returned by IbarrowConnection.fetch_batches(); use with `for batch in ...`.
+// A worker thread owns the cursor and stays at most `prefetch` batches ahead of the consumer.
+#[pyclass]
+pub struct BatchIterator {
+    receiver: Mutex<Option<Receiver<Result<RecordBatch>>>>,
+}
+
+impl BatchIterator {
+    pub fn start(
+        dsn: &str,
+        user: &str,
+        password: &str,
+        sql: &str,
+        config: &QueryConfig,
+        rows_per_batch: Option<usize>,
+        prefetch: usize,
+    ) -> Self {
+        let (dsn, user, password, sql, config) = (
+            dsn.to_string(),
+            user.to_string(),
+            password.to_string(),
+            sql.to_string(),
+            config.clone(),
+        );
+        let (sender, receiver) = sync_channel(prefetch.max(1));
+        thread::spawn(move || {
+            let result = produce_batches(
+                &dsn,
+                &user,
+                &password,
+                &sql,
+                &config,
+                rows_per_batch,
+                &sender,
+            );
+            if let Err(e) = result {
+                let _ = sender.send(Err(diagnostics::attach(e)));
+            }
+        });
+        Self {
+            receiver: Mutex::new(Some(receiver)),
+        }
+    }
+
+    // Next fetched batch; None once the producer finished or the iterator was closed
+    pub fn recv(&self, py: Python<'_>) -> Option<Result<RecordBatch>> {
+        py.allow_threads(|| {
+            let receiver = self.receiver.lock().unwrap_or_else(|e| e.into_inner());
+            receiver.as_ref().and_then(|rx| rx.recv().ok())
+        })
+    }
+}
+
+#[pymethods]
+impl BatchIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        match self.recv(py) {
+            Some(Ok(batch)) => batch_to_pyarrow(py, batch),
+            Some(Err(e)) => {
+                self.close();
+                Err(map_query_error(e))
+            }
+            // Producer finished and hung up, or the iterator was closed
+            None => Err(PyStopIteration::new_err(())),
+        }
+    }
+
+    // Stops fetching and releases the cursor and its connection
+    pub fn close(&self) {
+        self.receiver
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &self,
+        _exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> bool {
+        self.close();
+        false
+    }
+}
@@ -0,0 +1,5 @@
+// `ibarrow query ...`: see src/cli.rs
+
+fn main() {
+    std::process::exit(ibarrow::cli::run(std::env::args().skip(1).collect()));
+}
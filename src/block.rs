@@ -0,0 +1,125 @@
+use anyhow::{anyhow, Result};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::params::SqlParam;
+use ibarrow_core::lexer::{self, strip_comments, Kind};
+
+// Python dict items in insertion order, which fixes the declaration order in the block
+pub fn ordered_items<'py, T: FromPyObject<'py>>(
+    dict: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Vec<(String, T)>> {
+    dict.map(|dict| {
+        dict.iter()
+            .map(|(key, value)| Ok((key.extract()?, value.extract()?)))
+            .collect()
+    })
+    .unwrap_or_else(|| Ok(Vec::new()))
+}
+
+// Firebird/InterBase type for an EXECUTE BLOCK input parameter, inferred from its value
+fn infer_type(value: &SqlParam) -> String {
+    match value {
+        SqlParam::Null => "VARCHAR(1)".to_string(),
+        SqlParam::Bool(_) => "BOOLEAN".to_string(),
+        SqlParam::Int(_) => "BIGINT".to_string(),
+        SqlParam::Float(_) => "DOUBLE PRECISION".to_string(),
+        SqlParam::Text(text) => format!("VARCHAR({})", text.chars().count().max(1)),
+        SqlParam::Encoded(bytes) => format!("VARCHAR({})", bytes.len().max(1)),
+    }
+}
+
+// Splits PSQL into statements on `;`, ignoring semicolons inside literals, comments and
+// nested BEGIN ... END / CASE ... END blocks. Each statement comes back with the terminator
+// PSQL expects: `;` for simple statements, none after a closing BEGIN ... END.
+pub fn split_statements(body: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut openers: Vec<String> = Vec::new();
+    for token in lexer::tokenize(body) {
+        match token.kind {
+            Kind::Symbol if token.is_symbol(';') && openers.is_empty() => {
+                if !strip_comments(&current).trim().is_empty() {
+                    statements.push(format!("{};", current.trim()));
+                }
+                current.clear();
+            }
+            Kind::Word => {
+                current.push_str(token.text);
+                let word = token.text.to_uppercase();
+                match word.as_str() {
+                    "BEGIN" | "CASE" => openers.push(word),
+                    "END" => {
+                        let closed = openers.pop();
+                        if closed.as_deref() == Some("BEGIN") && openers.is_empty() {
+                            statements.push(current.trim().to_string());
+                            current.clear();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => current.push_str(token.text),
+        }
+    }
+    // The last statement may omit its semicolon
+    if !strip_comments(&current).trim().is_empty() {
+        statements.push(format!("{};", current.trim()));
+    }
+    statements
+}
+
+// Builds `EXECUTE BLOCK (...) RETURNS (...) AS DECLARE ...; BEGIN ... END` from its parts.
+// Parameters are referenced as `:name` inside the body and bound through `?` in the header.
+pub fn build_execute_block(
+    body: &str,
+    params: &[(String, SqlParam)],
+    param_types: &[(String, String)],
+    returns: &[(String, String)],
+    declare: &[(String, String)],
+) -> Result<(String, Vec<SqlParam>)> {
+    for (name, _) in param_types {
+        if !params.iter().any(|(param, _)| param == name) {
+            return Err(anyhow!("param_types names unknown parameter '{}'", name));
+        }
+    }
+    let statements = split_statements(body);
+    if statements.is_empty() {
+        return Err(anyhow!("EXECUTE BLOCK body is empty"));
+    }
+
+    let mut sql = String::from("EXECUTE BLOCK");
+    if !params.is_empty() {
+        let inputs: Vec<String> = params
+            .iter()
+            .map(|(name, value)| {
+                let data_type = param_types
+                    .iter()
+                    .find(|(param, _)| param == name)
+                    .map(|(_, data_type)| data_type.clone())
+                    .unwrap_or_else(|| infer_type(value));
+                format!("{} {} = ?", name, data_type)
+            })
+            .collect();
+        sql.push_str(&format!(" ({})", inputs.join(", ")));
+    }
+    if !returns.is_empty() {
+        let outputs: Vec<String> = returns
+            .iter()
+            .map(|(name, data_type)| format!("{} {}", name, data_type))
+            .collect();
+        sql.push_str(&format!("\nRETURNS ({})", outputs.join(", ")));
+    }
+    sql.push_str("\nAS\n");
+    for (name, data_type) in declare {
+        sql.push_str(&format!("DECLARE VARIABLE {} {};\n", name, data_type));
+    }
+    sql.push_str("BEGIN\n");
+    for statement in statements {
+        sql.push_str(&format!("  {}\n", statement));
+    }
+    sql.push_str("END");
+
+    let values = params.iter().map(|(_, value)| value.clone()).collect();
+    Ok((sql, values))
+}
@@ -0,0 +1,325 @@
+use anyhow::{anyhow, Result};
+use arrow::datatypes::Schema;
+use arrow::record_batch::RecordBatchReader;
+use arrow_ipc::writer::StreamWriter;
+use bytes::Bytes;
+use odbc_api::Environment;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::driverenv;
+use crate::progress::Progress;
+use crate::watchdog::Watchdog;
+use crate::zonemap::{Zone, ZoneMap};
+use crate::{
+    access, build_connection_string, build_reader, charset, diagnostics, limit_rows, retry,
+    QueryConfig,
+};
+
+const MANIFEST: &str = "manifest.json";
+const FORMAT: &str = "ibarrow-bundle";
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BundleColumn {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub data_type: String,
+    pub nullable: bool,
+}
+
+// One exported query: its Parquet file inside the archive and how it was produced
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BundleTable {
+    pub name: String,
+    pub file: String,
+    pub sql: String,
+    pub rows: usize,
+    pub bytes: usize,
+    pub sha256: String,
+    pub fetched_at: u64,
+    pub columns: Vec<BundleColumn>,
+    // Min/max of the columns chosen at export time, so readers can skip the file
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub zone_map: BTreeMap<String, Zone>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    pub ibarrow_version: String,
+    pub created_at: u64,
+    pub tables: Vec<BundleTable>,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn columns_of(schema: &Schema) -> Vec<BundleColumn> {
+    schema
+        .fields()
+        .iter()
+        .map(|field| BundleColumn {
+            name: field.name().clone(),
+            data_type: field.data_type().to_string(),
+            nullable: field.is_nullable(),
+        })
+        .collect()
+}
+
+// Table names become file names inside the archive
+pub fn validate_names(queries: &[(String, String)]) -> Result<()> {
+    if queries.is_empty() {
+        return Err(anyhow!("export_bundle needs at least one query"));
+    }
+    queries.iter().try_for_each(|(name, _)| validate_name(name))
+}
+
+// Zone map columns may only be requested for tables being exported
+pub fn validate_zone_map(
+    queries: &[(String, String)],
+    zone_map: &HashMap<String, Vec<String>>,
+) -> Result<()> {
+    match zone_map
+        .keys()
+        .find(|table| !queries.iter().any(|(name, _)| name == *table))
+    {
+        Some(table) => Err(anyhow!("zone_map names unknown bundle table '{}'", table)),
+        None => Ok(()),
+    }
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Invalid bundle table name '{}': use letters, digits, '_' and '-'",
+            name
+        ))
+    }
+}
+
+fn append_file<W: Write>(
+    archive: &mut tar::Builder<W>,
+    name: &str,
+    data: &[u8],
+    mtime: u64,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    archive.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+// Runs every query inside one read-only snapshot transaction and writes the results as
+// Parquet files plus manifest.json into a tar archive at `path`. Names must have passed
+// validate_names. `zone_map` lists, per table, the columns whose min/max go into the manifest.
+pub fn export_bundle(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    config: &QueryConfig,
+    queries: &[(String, String)],
+    zone_map: &HashMap<String, Vec<String>>,
+    path: &Path,
+) -> Result<Manifest> {
+    for (_, sql) in queries {
+        access::check(sql, config)?;
+    }
+
+    // Write to a staging file so an existing bundle is only replaced by a complete one
+    let staging = format!("{}.tmp", path.display());
+    let watchdog = Watchdog::start(config);
+    let result = write_bundle(
+        dsn, user, password, config, queries, zone_map, &staging, &watchdog,
+    );
+    match watchdog.finish(result) {
+        Ok(manifest) => {
+            fs::rename(&staging, path).map_err(|e| {
+                anyhow!("Failed to move bundle into place {}: {}", path.display(), e)
+            })?;
+            Ok(manifest)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&staging);
+            Err(e)
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_bundle(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    config: &QueryConfig,
+    queries: &[(String, String)],
+    zone_map: &HashMap<String, Vec<String>>,
+    staging: &str,
+    watchdog: &Watchdog,
+) -> Result<Manifest> {
+    // All queries see the same committed state unless another isolation level was asked for
+    let config = QueryConfig {
+        read_only: true,
+        isolation_level: config
+            .isolation_level
+            .clone()
+            .or_else(|| Some("snapshot".to_string())),
+        ..config.clone()
+    };
+    diagnostics::clear();
+    let env = Environment::new()?;
+    let conn_str = build_connection_string(dsn, user, password, &config);
+    let conn = retry::with_retry(&config, || driverenv::connect(&env, &conn_str, &config))?;
+    conn.set_autocommit(false)?;
+
+    let file = File::create(staging)
+        .map_err(|e| anyhow!("Failed to create bundle file {}: {}", staging, e))?;
+    let mut archive = tar::Builder::new(BufWriter::new(file));
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut tables = Vec::new();
+    // Totals run across all tables
+    let mut progress = Progress::new(&config);
+    for (name, sql) in queries {
+        eprintln!("DEBUG: Exporting bundle table {}", name);
+        let fetched_at = unix_now();
+        let statement = charset::transcode(sql, &[], config.sql_charset.as_deref())?;
+        watchdog.check()?;
+        let cursor = statement
+            .execute(watchdog.watch(conn.preallocate()?.into_handle()))?
+            .ok_or_else(|| anyhow!("Bundle query {} did not return a result set", name))?;
+
+        let reader = build_reader(cursor, &config)?;
+        let schema = reader.schema();
+        let mut writer =
+            ArrowWriter::try_new(Vec::new(), schema.clone(), Some(properties.clone()))?;
+        let mut zones = ZoneMap::new(zone_map.get(name).map_or(&[], |c| c.as_slice()));
+        let mut rows = 0;
+        for batch in limit_rows(reader, &config) {
+            watchdog.check()?;
+            let batch = batch.map_err(|e| anyhow!("ERROR: Failed to read batch: {}", e))?;
+            progress.batch(&batch)?;
+            rows += batch.num_rows();
+            zones.observe(&batch)?;
+            writer.write(&batch)?;
+        }
+        let data = writer.into_inner()?;
+
+        let file = format!("{}.parquet", name);
+        append_file(&mut archive, &file, &data, fetched_at)?;
+        tables.push(BundleTable {
+            name: name.clone(),
+            file,
+            sql: sql.clone(),
+            rows,
+            bytes: data.len(),
+            sha256: sha256_hex(&data),
+            fetched_at,
+            columns: columns_of(&schema),
+            zone_map: zones.finish()?,
+        });
+    }
+    // Read-only transaction: nothing to keep
+    let _ = conn.rollback();
+
+    let manifest = Manifest {
+        format: FORMAT.to_string(),
+        version: FORMAT_VERSION,
+        ibarrow_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: unix_now(),
+        tables,
+    };
+    let text = serde_json::to_vec_pretty(&manifest)?;
+    append_file(&mut archive, MANIFEST, &text, manifest.created_at)?;
+    archive.into_inner()?.flush()?;
+    Ok(manifest)
+}
+
+// Table name and its data as an Arrow IPC stream
+pub type LoadedTable = (String, Vec<u8>);
+
+// Reads a bundle back as its manifest and one Arrow IPC stream per table, in manifest order.
+// With `verify` every Parquet file is checked against its recorded SHA-256.
+pub fn load_bundle(path: &Path, verify: bool) -> Result<(Manifest, Vec<LoadedTable>)> {
+    let file =
+        File::open(path).map_err(|e| anyhow!("Failed to open bundle {}: {}", path.display(), e))?;
+    let mut files: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut archive = tar::Archive::new(file);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        files.insert(name, data);
+    }
+
+    let manifest: Manifest = files
+        .get(MANIFEST)
+        .ok_or_else(|| anyhow!("Invalid bundle {}: no {}", path.display(), MANIFEST))
+        .and_then(|text| {
+            serde_json::from_slice(text)
+                .map_err(|e| anyhow!("Invalid bundle manifest {}: {}", path.display(), e))
+        })?;
+    if manifest.format != FORMAT || manifest.version > FORMAT_VERSION {
+        return Err(anyhow!(
+            "Unsupported bundle format {} version {}",
+            manifest.format,
+            manifest.version
+        ));
+    }
+
+    let mut tables = Vec::new();
+    for table in &manifest.tables {
+        let data = files
+            .remove(&table.file)
+            .ok_or_else(|| anyhow!("Invalid bundle: {} is missing", table.file))?;
+        if verify && sha256_hex(&data) != table.sha256 {
+            return Err(anyhow!(
+                "Invalid bundle: checksum mismatch for {}",
+                table.file
+            ));
+        }
+        let reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(data))?.build()?;
+        let mut ipc = Vec::new();
+        {
+            let mut writer = StreamWriter::try_new(&mut ipc, &reader.schema())?;
+            for batch in reader {
+                writer.write(&batch?)?;
+            }
+            writer.finish()?;
+        }
+        tables.push((table.name.clone(), ipc));
+    }
+    Ok((manifest, tables))
+}
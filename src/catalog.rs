@@ -0,0 +1,257 @@
+use anyhow::{anyhow, Result};
+use arrow::array::AsArray;
+use arrow::compute::cast;
+use arrow::datatypes::DataType as ArrowType;
+use arrow::datatypes::SchemaRef;
+use arrow::ffi::FFI_ArrowSchema;
+use arrow_ipc::reader::StreamReader;
+use pyo3::exceptions::{PyDeprecationWarning, PyImportError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyCapsule, PyList};
+use std::ffi::CString;
+
+use crate::compression::{self, Consumer};
+use crate::dialect::quote_identifier;
+use crate::params::SqlParam;
+use crate::result::IbarrowResult;
+use crate::{backend, map_query_error, write_arrow_ipc_observed, IbarrowConnection, QueryConfig};
+
+pub use ibarrow_core::catalog::table_exists;
+
+// Tables and views with their kind; system relations have RDB$SYSTEM_FLAG set
+const TABLES_SQL: &str = "SELECT TRIM(RDB$RELATION_NAME) AS TABLE_NAME, CASE \
+     WHEN COALESCE(RDB$SYSTEM_FLAG, 0) <> 0 THEN 'SYSTEM TABLE' \
+     WHEN RDB$VIEW_BLR IS NOT NULL THEN 'VIEW' ELSE 'TABLE' END AS TABLE_TYPE, \
+     TRIM(RDB$OWNER_NAME) AS OWNER_NAME, RDB$DESCRIPTION AS DESCRIPTION \
+     FROM RDB$RELATIONS WHERE TRIM(RDB$RELATION_NAME) LIKE ?";
+
+// Columns of the table's primary key, in key order
+const PRIMARY_KEYS_SQL: &str = "SELECT TRIM(RC.RDB$RELATION_NAME) AS TABLE_NAME, \
+     TRIM(S.RDB$FIELD_NAME) AS COLUMN_NAME, S.RDB$FIELD_POSITION + 1 AS KEY_SEQ, \
+     TRIM(RC.RDB$CONSTRAINT_NAME) AS PK_NAME \
+     FROM RDB$RELATION_CONSTRAINTS RC \
+     JOIN RDB$INDEX_SEGMENTS S ON S.RDB$INDEX_NAME = RC.RDB$INDEX_NAME \
+     WHERE RC.RDB$CONSTRAINT_TYPE = 'PRIMARY KEY' AND TRIM(RC.RDB$RELATION_NAME) = ? \
+     ORDER BY 3";
+
+// Foreign keys the table declares, one row per column pair; the referenced key's columns
+// line up with the foreign key's by position
+const FOREIGN_KEYS_SQL: &str = "SELECT TRIM(RC.RDB$RELATION_NAME) AS FK_TABLE_NAME, \
+     TRIM(FS.RDB$FIELD_NAME) AS FK_COLUMN_NAME, TRIM(PK.RDB$RELATION_NAME) AS PK_TABLE_NAME, \
+     TRIM(PS.RDB$FIELD_NAME) AS PK_COLUMN_NAME, FS.RDB$FIELD_POSITION + 1 AS KEY_SEQ, \
+     TRIM(REF.RDB$UPDATE_RULE) AS UPDATE_RULE, TRIM(REF.RDB$DELETE_RULE) AS DELETE_RULE, \
+     TRIM(RC.RDB$CONSTRAINT_NAME) AS FK_NAME, TRIM(PK.RDB$CONSTRAINT_NAME) AS PK_NAME \
+     FROM RDB$RELATION_CONSTRAINTS RC \
+     JOIN RDB$REF_CONSTRAINTS REF ON REF.RDB$CONSTRAINT_NAME = RC.RDB$CONSTRAINT_NAME \
+     JOIN RDB$RELATION_CONSTRAINTS PK ON PK.RDB$CONSTRAINT_NAME = REF.RDB$CONST_NAME_UQ \
+     JOIN RDB$INDEX_SEGMENTS FS ON FS.RDB$INDEX_NAME = RC.RDB$INDEX_NAME \
+     JOIN RDB$INDEX_SEGMENTS PS ON PS.RDB$INDEX_NAME = PK.RDB$INDEX_NAME \
+     AND PS.RDB$FIELD_POSITION = FS.RDB$FIELD_POSITION \
+     WHERE RC.RDB$CONSTRAINT_TYPE = 'FOREIGN KEY' AND TRIM(RC.RDB$RELATION_NAME) = ? \
+     ORDER BY 8, 5";
+
+// Indexes on the table, one row per column; expression indexes have no segments and give
+// one row without a column
+const INDEXES_SQL: &str = "SELECT TRIM(I.RDB$RELATION_NAME) AS TABLE_NAME, \
+     TRIM(I.RDB$INDEX_NAME) AS INDEX_NAME, \
+     CASE WHEN COALESCE(I.RDB$UNIQUE_FLAG, 0) = 1 THEN 0 ELSE 1 END AS NON_UNIQUE, \
+     COALESCE(S.RDB$FIELD_POSITION + 1, 1) AS ORDINAL_POSITION, \
+     TRIM(S.RDB$FIELD_NAME) AS COLUMN_NAME, \
+     CASE WHEN COALESCE(I.RDB$INDEX_TYPE, 0) = 1 THEN 'D' ELSE 'A' END AS ASC_OR_DESC, \
+     CASE WHEN COALESCE(I.RDB$INDEX_INACTIVE, 0) = 1 THEN 0 ELSE 1 END AS IS_ACTIVE, \
+     TRIM(RC.RDB$CONSTRAINT_TYPE) AS CONSTRAINT_TYPE \
+     FROM RDB$INDICES I \
+     LEFT JOIN RDB$INDEX_SEGMENTS S ON S.RDB$INDEX_NAME = I.RDB$INDEX_NAME \
+     LEFT JOIN RDB$RELATION_CONSTRAINTS RC ON RC.RDB$INDEX_NAME = I.RDB$INDEX_NAME \
+     WHERE TRIM(I.RDB$RELATION_NAME) = ? ORDER BY 2, 4";
+
+// The relations listing every caller shares (list_tables, table_names, the CLI's tables and
+// ADBC's GetObjects): TABLE_NAME, TABLE_TYPE, OWNER_NAME and DESCRIPTION of the relations whose
+// name matches the LIKE parameter, ordered by name
+pub fn tables_sql(include_system: bool) -> String {
+    let system = if include_system {
+        ""
+    } else {
+        " AND COALESCE(RDB$SYSTEM_FLAG, 0) = 0"
+    };
+    format!("{}{} ORDER BY 1", TABLES_SQL, system)
+}
+
+// Columns of `table`'s primary key in key order; empty without one
+#[cfg(feature = "cli")]
+pub fn primary_key(conn: &odbc_api::Connection<'_>, table: &str) -> Result<Vec<String>> {
+    ibarrow_core::catalog::fetch_column(conn, PRIMARY_KEYS_SQL, Some(table), 2)
+}
+
+// Which of a table's keys or indexes to list
+#[derive(Debug, Clone, Copy)]
+pub enum Constraints {
+    PrimaryKeys,
+    ForeignKeys,
+    Indexes,
+}
+
+// Runs a catalog query and returns its rows like IbarrowConnection.query()
+fn fetch(
+    py: Python<'_>,
+    conn: &IbarrowConnection,
+    sql: &str,
+    params: &[SqlParam],
+) -> PyResult<IbarrowResult> {
+    let (bytes, rows) = fetch_ipc(py, conn, sql, params)?;
+    IbarrowResult::new(bytes, rows)
+}
+
+// A catalog query's rows as an Arrow IPC stream, with the row count
+fn fetch_ipc(
+    py: Python<'_>,
+    conn: &IbarrowConnection,
+    sql: &str,
+    params: &[SqlParam],
+) -> PyResult<(Vec<u8>, usize)> {
+    let config = compression::for_consumer(py, &conn.config, Consumer::Bytes)?;
+    let mut bytes = Vec::new();
+    let rows = py
+        .allow_threads(|| {
+            write_arrow_ipc_observed(
+                &conn.dsn,
+                &conn.user,
+                &conn.password,
+                sql,
+                params,
+                &config,
+                &mut bytes,
+                &mut |_| Ok(()),
+            )
+        })
+        .map_err(map_query_error)?;
+    Ok((bytes, rows))
+}
+
+// What conn.list_tables() returns: the table metadata as an IbarrowResult. list_tables() used
+// to return a list of the user tables' names, so for code written against that it still
+// iterates, indexes and tests membership like that list, with a DeprecationWarning pointing
+// to conn.table_names().
+#[pyclass(extends = IbarrowResult)]
+pub struct TableList {
+    names: Vec<String>,
+}
+
+impl TableList {
+    fn deprecated(py: Python<'_>) -> PyResult<()> {
+        PyErr::warn_bound(
+            py,
+            &py.get_type_bound::<PyDeprecationWarning>(),
+            "Using conn.list_tables() as a list of names is deprecated; it returns an \
+             IbarrowResult with table metadata. Use conn.table_names() for the names.",
+            2,
+        )
+    }
+
+    fn names_list<'py>(&self, py: Python<'py>) -> Bound<'py, PyList> {
+        PyList::new_bound(py, &self.names)
+    }
+}
+
+#[pymethods]
+impl TableList {
+    fn __iter__(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        Self::deprecated(py)?;
+        Ok(self.names_list(py).as_any().iter()?.into_any().unbind())
+    }
+
+    fn __getitem__(&self, py: Python<'_>, index: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        Self::deprecated(py)?;
+        Ok(self.names_list(py).as_any().get_item(index)?.unbind())
+    }
+
+    fn __contains__(&self, py: Python<'_>, name: &str) -> PyResult<bool> {
+        Self::deprecated(py)?;
+        Ok(self.names.iter().any(|table| table == name))
+    }
+}
+
+// Text of the first column of an Arrow IPC stream
+fn first_column(bytes: &[u8]) -> Result<Vec<String>> {
+    let mut texts = Vec::new();
+    for batch in StreamReader::try_new(std::io::Cursor::new(bytes), None)? {
+        let column = cast(batch?.column(0), &ArrowType::Utf8)?;
+        let column = column.as_string::<i32>();
+        texts.extend(column.iter().flatten().map(str::to_string));
+    }
+    Ok(texts)
+}
+
+// TABLE_NAME, TABLE_TYPE ("TABLE", "VIEW" or "SYSTEM TABLE"), OWNER_NAME and DESCRIPTION of
+// the relations whose name matches the LIKE `pattern`, ordered by name
+pub fn list_tables(
+    py: Python<'_>,
+    conn: &IbarrowConnection,
+    include_system: bool,
+    pattern: Option<&str>,
+) -> PyResult<Py<TableList>> {
+    let sql = tables_sql(include_system);
+    let pattern = pattern.unwrap_or("%").to_string();
+    let (bytes, rows) = fetch_ipc(py, conn, &sql, &[SqlParam::Text(pattern)])?;
+    let names = first_column(&bytes).map_err(map_query_error)?;
+    let result = IbarrowResult::new(bytes, rows)?;
+    Py::new(
+        py,
+        PyClassInitializer::from(result).add_subclass(TableList { names }),
+    )
+}
+
+// The table's primary key, foreign keys or indexes as an IbarrowResult, with the column names
+// of the ODBC catalog functions (SQLPrimaryKeys, SQLForeignKeys, SQLStatistics). `table` is the
+// name as the catalog stores it: upper case unless it was created quoted.
+pub fn constraints(
+    py: Python<'_>,
+    conn: &IbarrowConnection,
+    table: &str,
+    kind: Constraints,
+) -> PyResult<IbarrowResult> {
+    if table.trim().is_empty() {
+        return Err(PyValueError::new_err("table must not be empty"));
+    }
+    let sql = match kind {
+        Constraints::PrimaryKeys => PRIMARY_KEYS_SQL,
+        Constraints::ForeignKeys => FOREIGN_KEYS_SQL,
+        Constraints::Indexes => INDEXES_SQL,
+    };
+    fetch(py, conn, sql, &[SqlParam::Text(table.to_string())])
+}
+
+pub fn table_schema(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    table: &str,
+    config: &QueryConfig,
+) -> Result<SchemaRef> {
+    let sql = format!("SELECT * FROM {}", quote_identifier(table));
+    backend::open(dsn, user, password, config)?.schema(&sql, &[], config)
+}
+
+// pyarrow.Schema of a table (see table_schema)
+pub fn table_schema_py(
+    py: Python<'_>,
+    conn: &IbarrowConnection,
+    table: &str,
+) -> PyResult<Py<PyAny>> {
+    if table.trim().is_empty() {
+        return Err(PyValueError::new_err("table must not be empty"));
+    }
+    let pyarrow = py
+        .import_bound("pyarrow")
+        .map_err(|_| PyImportError::new_err("table_schema needs pyarrow (pip install pyarrow)"))?;
+    let schema = py
+        .allow_threads(|| table_schema(&conn.dsn, &conn.user, &conn.password, table, &conn.config))
+        .map_err(map_query_error)?;
+    let ffi = FFI_ArrowSchema::try_from(schema.as_ref())
+        .map_err(|e| map_query_error(anyhow!("Arrow C schema export failed: {}", e)))?;
+    let capsule = PyCapsule::new_bound(py, ffi, Some(CString::new("arrow_schema")?))?;
+    Ok(pyarrow
+        .getattr("Schema")?
+        .call_method1("_import_from_c_capsule", (capsule,))?
+        .unbind())
+}
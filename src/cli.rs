@@ -0,0 +1,1311 @@
+// The `ibarrow` command-line client: runs one query and writes the result as Parquet, CSV,
+// Arrow IPC or newline-delimited JSON, or prints catalog information, without a Python
+// interpreter. Built only with the `cli` feature; see src/bin/ibarrow.rs.
+
+use anyhow::{anyhow, Context, Result};
+use arrow::csv::WriterBuilder;
+use arrow::datatypes::{Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use arrow_odbc::OdbcWriter;
+use odbc_api::handles::StatementImpl;
+use odbc_api::{Connection, Cursor, IntoParameter, ResultSetMetadata};
+use serde_json::json;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use ibarrow_core::charset::Transcoded;
+use ibarrow_core::lexer;
+use ibarrow_core::restart;
+
+use crate::catalog;
+use crate::dialect::quote_identifier;
+use crate::export::{self, CsvSink, NdjsonSink, ParquetSink, Rollover};
+use crate::sink::BatchSink;
+use crate::write::create_table;
+use crate::{
+    access, build_connection_string, build_reader, charset, collect_batches, diagnostics,
+    driverenv, drivermgr, fetch_into, retry, scan, write, write_arrow_ipc_impl, QueryConfig,
+    IPC_WRITE_BUFFER,
+};
+
+const USAGE: &str = "\
+Usage: ibarrow <COMMAND> [OPTIONS]
+
+Commands:
+  query [SQL]           Run SQL and write the result to stdout or a file
+  tables                List the tables and views
+  columns <TABLE>       List a table's columns with their Firebird and Arrow types
+  schema <TABLE|SQL>    Print the Arrow schema of a table or query
+  copy                  Copy a table from one data source into another
+  bench [SQL]           Time a query at several batch sizes
+
+Connection options:
+  -d, --dsn <DSN>            DSN name or full connection string [env: IBARROW_DSN]
+  -u, --user <USER>          Database user [env: IBARROW_USER]
+  -p, --password <PASSWORD>  Database password [env: IBARROW_PASSWORD]
+      --config <PATH>        QueryConfig settings as a JSON file
+
+Query options:
+  -f, --sql-file <PATH>      Read the SQL from a file (- for stdin) instead of the argument
+                             (query, bench)
+  -o, --output <PATH>        Write to a file or s3://, gs://, az:// URL instead of stdout
+  -F, --format <FORMAT>      parquet, csv, ipc or json; defaults to the output extension,
+                             csv on stdout
+      --compression <NAME>   Parquet compression codec [default: snappy]
+      --delimiter <CHAR>     CSV field delimiter [default: ,]
+      --no-header            Leave out the CSV header row
+      --max-rows <N>         Stop after N rows (query, bench)
+      --batch-size <N>       Rows fetched per ODBC round trip (query, copy)
+
+Catalog options:
+      --system               Include system tables (tables)
+      --json                 Print one JSON object per line instead of a table (also bench)
+
+Copy options:
+      --from <DSN>           Source data source (same as --dsn)
+      --to <DSN>             Target data source
+      --to-user <USER>       Target user [default: --user]
+      --to-password <PASS>   Target password [default: --password]
+  -t, --table <TABLE>        Table to copy
+      --target-table <NAME>  Table to insert into [default: --table]
+      --key <COLUMN>         Column to copy in order of and resume from; --resume needs
+                             it to be unique [default: the single-column primary key]
+      --create               Create the target table when it does not exist
+      --resume               Continue after the highest key already in the target
+
+Bench options:
+      --batch-sizes <LIST>   Comma-separated batch sizes [default: 1000,10000,50000]
+      --runs <N>             Runs per batch size; the fastest is reported [default: 1]
+
+  -h, --help                 Print this help
+  -V, --version              Print the version
+";
+
+// Columns that alone hold an active unique index (primary keys included)
+const UNIQUE_COLUMNS_SQL: &str = "SELECT TRIM(S.RDB$FIELD_NAME) FROM RDB$INDICES I \
+     JOIN RDB$INDEX_SEGMENTS S ON S.RDB$INDEX_NAME = I.RDB$INDEX_NAME \
+     WHERE TRIM(I.RDB$RELATION_NAME) = ? AND I.RDB$UNIQUE_FLAG = 1 \
+     AND I.RDB$SEGMENT_COUNT = 1 AND COALESCE(I.RDB$INDEX_INACTIVE, 0) = 0";
+
+// Progress lines of `copy` are at most this often
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+// Declared type of each column; a column is nullable unless it or its domain is NOT NULL
+const COLUMNS_SQL: &str = "SELECT TRIM(RF.RDB$FIELD_NAME), F.RDB$FIELD_TYPE, \
+     F.RDB$FIELD_SUB_TYPE, F.RDB$FIELD_LENGTH, F.RDB$FIELD_PRECISION, F.RDB$FIELD_SCALE, \
+     F.RDB$CHARACTER_LENGTH, COALESCE(RF.RDB$NULL_FLAG, F.RDB$NULL_FLAG, 0) \
+     FROM RDB$RELATION_FIELDS RF JOIN RDB$FIELDS F ON F.RDB$FIELD_NAME = RF.RDB$FIELD_SOURCE \
+     WHERE TRIM(RF.RDB$RELATION_NAME) = ? ORDER BY RF.RDB$FIELD_POSITION";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Parquet,
+    Csv,
+    Ipc,
+    Json,
+}
+
+impl Format {
+    fn parse(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "parquet" => Ok(Format::Parquet),
+            "csv" => Ok(Format::Csv),
+            "ipc" | "arrow" => Ok(Format::Ipc),
+            "json" | "ndjson" | "jsonl" => Ok(Format::Json),
+            other => Err(anyhow!(
+                "unknown format '{}' (expected parquet, csv, ipc or json)",
+                other
+            )),
+        }
+    }
+
+    fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "parquet" | "pq" => Some(Format::Parquet),
+            "csv" => Some(Format::Csv),
+            "arrows" | "ipc" => Some(Format::Ipc),
+            "json" | "ndjson" | "jsonl" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Query,
+    Tables,
+    Columns,
+    Schema,
+    Copy,
+    Bench,
+}
+
+impl Command {
+    fn name(self) -> &'static str {
+        match self {
+            Command::Query => "query",
+            Command::Tables => "tables",
+            Command::Columns => "columns",
+            Command::Schema => "schema",
+            Command::Copy => "copy",
+            Command::Bench => "bench",
+        }
+    }
+}
+
+// Connection settings shared by every command
+#[derive(Default)]
+struct SourceArgs {
+    dsn: Option<String>,
+    user: Option<String>,
+    password: Option<String>,
+    config: Option<PathBuf>,
+    batch_size: Option<u32>,
+}
+
+#[derive(Default)]
+struct QueryArgs {
+    sql: Option<String>,
+    sql_file: Option<PathBuf>,
+    output: Option<PathBuf>,
+    format: Option<Format>,
+    compression: Option<String>,
+    delimiter: Option<u8>,
+    no_header: bool,
+    max_rows: Option<u64>,
+}
+
+#[derive(Default)]
+struct CatalogArgs {
+    // The table of `columns`, or the table or query of `schema`
+    target: Option<String>,
+    system: bool,
+    json: bool,
+}
+
+#[derive(Default)]
+struct CopyArgs {
+    to: Option<String>,
+    to_user: Option<String>,
+    to_password: Option<String>,
+    table: Option<String>,
+    target_table: Option<String>,
+    key: Option<String>,
+    create: bool,
+    resume: bool,
+}
+
+#[derive(Default)]
+struct BenchArgs {
+    batch_sizes: Vec<u32>,
+    runs: Option<u32>,
+}
+
+// Everything one command line asks for
+struct Invocation {
+    command: Command,
+    source: SourceArgs,
+    query: QueryArgs,
+    catalog: CatalogArgs,
+    copy: CopyArgs,
+    bench: BenchArgs,
+}
+
+// Why the command line was rejected; reported with exit status 2 like other CLIs
+struct Usage(String);
+
+enum Parsed {
+    Run(Box<Invocation>),
+    Help,
+    Version,
+}
+
+fn parse_args(args: &[String]) -> Result<Parsed, Usage> {
+    let mut args = args.iter();
+    let command = match args.next().map(String::as_str) {
+        Some("query") => Command::Query,
+        Some("tables") => Command::Tables,
+        Some("columns") => Command::Columns,
+        Some("schema") => Command::Schema,
+        Some("copy") => Command::Copy,
+        Some("bench") => Command::Bench,
+        Some("-h") | Some("--help") | Some("help") | None => return Ok(Parsed::Help),
+        Some("-V") | Some("--version") => return Ok(Parsed::Version),
+        Some(other) => return Err(Usage(format!("unknown command '{}'", other))),
+    };
+
+    let mut source = SourceArgs::default();
+    let mut query = QueryArgs::default();
+    let mut catalog = CatalogArgs::default();
+    let mut copy = CopyArgs::default();
+    let mut bench = BenchArgs::default();
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        // --name=value and --name value are both accepted
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let only = |wanted: &[Command]| -> Result<(), Usage> {
+            if wanted.contains(&command) {
+                Ok(())
+            } else {
+                Err(Usage(format!(
+                    "{} is not an option of {}",
+                    name,
+                    command.name()
+                )))
+            }
+        };
+        let mut value = || -> Result<String, Usage> {
+            inline
+                .clone()
+                .or_else(|| args.next().cloned())
+                .ok_or_else(|| Usage(format!("{} needs a value", name)))
+        };
+        match name {
+            "-h" | "--help" => return Ok(Parsed::Help),
+            "-V" | "--version" => return Ok(Parsed::Version),
+            "-d" | "--dsn" => source.dsn = Some(value()?),
+            "-u" | "--user" => source.user = Some(value()?),
+            "-p" | "--password" => source.password = Some(value()?),
+            "--config" => source.config = Some(PathBuf::from(value()?)),
+            "-f" | "--sql-file" => {
+                only(&[Command::Query, Command::Bench])?;
+                query.sql_file = Some(PathBuf::from(value()?))
+            }
+            "-o" | "--output" => {
+                only(&[Command::Query])?;
+                query.output = Some(PathBuf::from(value()?))
+            }
+            "-F" | "--format" => {
+                only(&[Command::Query])?;
+                query.format = Some(Format::parse(&value()?).map_err(|e| Usage(e.to_string()))?)
+            }
+            "--compression" => {
+                only(&[Command::Query])?;
+                query.compression = Some(value()?)
+            }
+            "--delimiter" => {
+                only(&[Command::Query])?;
+                let delimiter = value()?;
+                match delimiter.as_bytes() {
+                    [byte] => query.delimiter = Some(*byte),
+                    _ if delimiter == "\\t" => query.delimiter = Some(b'\t'),
+                    _ => return Err(Usage("--delimiter must be a single character".into())),
+                }
+            }
+            "--no-header" => {
+                only(&[Command::Query])?;
+                query.no_header = true
+            }
+            "--max-rows" => {
+                only(&[Command::Query, Command::Bench])?;
+                query.max_rows = Some(number(name, &value()?)?)
+            }
+            "--batch-size" => {
+                only(&[Command::Query, Command::Copy])?;
+                source.batch_size = Some(number(name, &value()?)?)
+            }
+            "--system" => {
+                only(&[Command::Tables])?;
+                catalog.system = true
+            }
+            "--json" => {
+                only(&[
+                    Command::Tables,
+                    Command::Columns,
+                    Command::Schema,
+                    Command::Bench,
+                ])?;
+                catalog.json = true
+            }
+            "--from" => {
+                only(&[Command::Copy])?;
+                source.dsn = Some(value()?)
+            }
+            "--to" => {
+                only(&[Command::Copy])?;
+                copy.to = Some(value()?)
+            }
+            "--to-user" => {
+                only(&[Command::Copy])?;
+                copy.to_user = Some(value()?)
+            }
+            "--to-password" => {
+                only(&[Command::Copy])?;
+                copy.to_password = Some(value()?)
+            }
+            "-t" | "--table" => {
+                only(&[Command::Copy])?;
+                copy.table = Some(value()?)
+            }
+            "--target-table" => {
+                only(&[Command::Copy])?;
+                copy.target_table = Some(value()?)
+            }
+            "--key" => {
+                only(&[Command::Copy])?;
+                copy.key = Some(value()?)
+            }
+            "--create" => {
+                only(&[Command::Copy])?;
+                copy.create = true
+            }
+            "--resume" => {
+                only(&[Command::Copy])?;
+                copy.resume = true
+            }
+            "--batch-sizes" => {
+                only(&[Command::Bench])?;
+                bench.batch_sizes = value()?
+                    .split(',')
+                    .map(|size| match number::<u32>(name, size.trim())? {
+                        0 => Err(Usage("batch sizes must be greater than 0".into())),
+                        size => Ok(size),
+                    })
+                    .collect::<Result<_, _>>()?
+            }
+            "--runs" => {
+                only(&[Command::Bench])?;
+                match number(name, &value()?)? {
+                    0 => return Err(Usage("--runs must be greater than 0".into())),
+                    runs => bench.runs = Some(runs),
+                }
+            }
+            "--" => positional.extend(args.by_ref().cloned()),
+            _ if name.starts_with('-') && name != "-" => {
+                return Err(Usage(format!("unknown option '{}'", name)))
+            }
+            _ => positional.push(arg.clone()),
+        }
+    }
+
+    match command {
+        Command::Query | Command::Bench => match (positional.len(), &query.sql_file) {
+            (0, None) => {
+                return Err(Usage(
+                    "no SQL given (pass it as an argument or use --sql-file)".into(),
+                ))
+            }
+            (0, Some(_)) => {}
+            (1, None) => query.sql = positional.pop(),
+            (_, Some(_)) => return Err(Usage("pass either SQL or --sql-file, not both".into())),
+            _ => return Err(Usage("quote the SQL as a single argument".into())),
+        },
+        Command::Tables => {
+            if !positional.is_empty() {
+                return Err(Usage("tables takes no arguments".into()));
+            }
+        }
+        Command::Columns | Command::Schema => match positional.len() {
+            1 => catalog.target = positional.pop(),
+            0 => return Err(Usage(format!("{} needs a table name", command.name()))),
+            _ => return Err(Usage(format!("{} takes a single table", command.name()))),
+        },
+        Command::Copy => {
+            if !positional.is_empty() {
+                return Err(Usage(
+                    "copy takes no arguments; name the table with --table".into(),
+                ));
+            }
+            if copy.to.is_none() {
+                return Err(Usage("copy needs a target data source (--to)".into()));
+            }
+            if copy.table.is_none() {
+                return Err(Usage("copy needs a table (--table)".into()));
+            }
+        }
+    }
+    Ok(Parsed::Run(Box::new(Invocation {
+        command,
+        source,
+        query,
+        catalog,
+        copy,
+        bench,
+    })))
+}
+
+fn number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, Usage> {
+    value
+        .parse()
+        .map_err(|_| Usage(format!("{} expects a whole number, got '{}'", name, value)))
+}
+
+// Runs the command line `args` (without the program name) and returns the process exit status
+pub fn run(args: Vec<String>) -> i32 {
+    let Invocation {
+        command,
+        source,
+        query: query_args,
+        catalog,
+        copy: copy_args,
+        bench: bench_args,
+    } = match parse_args(&args) {
+        Ok(Parsed::Run(invocation)) => *invocation,
+        Ok(Parsed::Help) => {
+            print!("{}", USAGE);
+            return 0;
+        }
+        Ok(Parsed::Version) => {
+            println!("ibarrow {}", env!("CARGO_PKG_VERSION"));
+            return 0;
+        }
+        Err(Usage(message)) => {
+            eprintln!("ibarrow: {}\nRun 'ibarrow --help' for usage.", message);
+            return 2;
+        }
+    };
+    let result = Source::resolve(source).and_then(|source| match command {
+        Command::Query => query(source, query_args),
+        Command::Tables => tables(&source, &catalog),
+        Command::Columns => columns(&source, &catalog),
+        Command::Schema => schema(&source, &catalog),
+        Command::Copy => copy_table(&source, &copy_args),
+        Command::Bench => bench(source, &query_args, &bench_args, &catalog),
+    });
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("ERROR: {:#}", e);
+            1
+        }
+    }
+}
+
+// Connection settings with the environment fallbacks and --config applied
+struct Source {
+    dsn: String,
+    user: String,
+    password: String,
+    config: QueryConfig,
+}
+
+impl Source {
+    fn resolve(args: SourceArgs) -> Result<Self> {
+        let env = |name: &str| std::env::var(name).ok();
+        let dsn = args
+            .dsn
+            .or_else(|| env("IBARROW_DSN"))
+            .ok_or_else(|| anyhow!("no data source given (use --dsn or set IBARROW_DSN)"))?;
+        let user = args
+            .user
+            .or_else(|| env("IBARROW_USER"))
+            .unwrap_or_default();
+        let password = args
+            .password
+            .or_else(|| env("IBARROW_PASSWORD"))
+            .unwrap_or_default();
+        let mut config = match &args.config {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                serde_json::from_str::<QueryConfig>(&text)
+                    .with_context(|| format!("Invalid config in {}", path.display()))?
+            }
+            None => QueryConfig::default(),
+        };
+        if args.batch_size.is_some() {
+            config.batch_size = args.batch_size;
+        }
+        drivermgr::check(config.driver_manager.as_deref())
+            .map_err(|e| anyhow!("Connection Error: {}", e))?;
+        Ok(Self {
+            dsn,
+            user,
+            password,
+            config,
+        })
+    }
+
+    fn connect(&self) -> Result<Connection<'static>> {
+        let env = odbc_api::environment()?;
+        let conn_str = build_connection_string(&self.dsn, &self.user, &self.password, &self.config);
+        retry::with_retry(&self.config, || {
+            driverenv::connect(env, &conn_str, &self.config)
+        })
+        .map_err(diagnostics::attach)
+    }
+}
+
+// The SQL argument, or the contents of --sql-file
+fn read_sql(args: &QueryArgs) -> Result<String> {
+    Ok(match (&args.sql, &args.sql_file) {
+        (Some(sql), _) => sql.clone(),
+        (None, Some(path)) if path.as_os_str() == "-" => {
+            let mut sql = String::new();
+            io::stdin()
+                .read_to_string(&mut sql)
+                .context("Failed to read SQL from stdin")?;
+            sql
+        }
+        (None, Some(path)) => std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?,
+        (None, None) => unreachable!("parse_args requires SQL"),
+    })
+}
+
+fn query(source: Source, args: QueryArgs) -> Result<()> {
+    let Source {
+        dsn,
+        user,
+        password,
+        mut config,
+    } = source;
+
+    let sql = read_sql(&args)?;
+
+    if args.max_rows.is_some() {
+        config.max_rows = args.max_rows;
+    }
+
+    let format = match (args.format, &args.output) {
+        (Some(format), _) => format,
+        (None, Some(path)) => Format::from_path(path).ok_or_else(|| {
+            anyhow!(
+                "cannot tell the format of {} from its extension; pass --format",
+                path.display()
+            )
+        })?,
+        (None, None) => Format::Csv,
+    };
+    let compression = export::compression(args.compression.as_deref().unwrap_or("snappy"), None)?;
+    let csv = WriterBuilder::new()
+        .with_delimiter(args.delimiter.unwrap_or(b','))
+        .with_header(!args.no_header);
+
+    let rows = match &args.output {
+        Some(path) => match format {
+            Format::Parquet => export::query_to_parquet(
+                &dsn,
+                &user,
+                &password,
+                &sql,
+                &config,
+                path,
+                compression,
+                &[],
+                &[],
+                Rollover::default(),
+                None,
+            )?,
+            Format::Csv => export::query_to_csv(
+                &dsn,
+                &user,
+                &password,
+                &sql,
+                &config,
+                path,
+                args.delimiter.unwrap_or(b','),
+                !args.no_header,
+                None,
+            )?,
+            Format::Json => export::query_to_ndjson(
+                &dsn,
+                &user,
+                &password,
+                &sql,
+                &config,
+                path,
+                export::timestamp_format("iso")?,
+                export::decimal_format("number")?,
+                None,
+            )?,
+            Format::Ipc => export::ipc_to_path(&dsn, &user, &password, &sql, &config, path)?,
+        },
+        None => {
+            let mut out = BufWriter::with_capacity(IPC_WRITE_BUFFER, io::stdout());
+            let rows = match format {
+                Format::Ipc => {
+                    write_arrow_ipc_impl(&dsn, &user, &password, &sql, &[], &config, &mut out)?
+                }
+                Format::Parquet => fetch_into(
+                    &dsn,
+                    &user,
+                    &password,
+                    &sql,
+                    &[],
+                    &config,
+                    &mut ParquetSink::new(&mut out, compression, &[]),
+                    &mut |_| Ok(()),
+                )?,
+                Format::Csv => fetch_into(
+                    &dsn,
+                    &user,
+                    &password,
+                    &sql,
+                    &[],
+                    &config,
+                    &mut CsvSink::new(&mut out, csv),
+                    &mut |_| Ok(()),
+                )?,
+                Format::Json => fetch_into(
+                    &dsn,
+                    &user,
+                    &password,
+                    &sql,
+                    &[],
+                    &config,
+                    &mut NdjsonSink::new(
+                        &mut out,
+                        export::timestamp_format("iso")?,
+                        export::decimal_format("number")?,
+                    ),
+                    &mut |_| Ok(()),
+                )?,
+            };
+            out.flush()?;
+            rows
+        }
+    };
+    eprintln!("DEBUG: ibarrow query wrote {} rows", rows);
+    Ok(())
+}
+
+fn tables(source: &Source, args: &CatalogArgs) -> Result<()> {
+    let conn = source.connect()?;
+    let rows: Vec<Vec<String>> = fetch_rows(&conn, &catalog::tables_sql(args.system), Some("%"))?
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .take(2)
+                .map(Option::unwrap_or_default)
+                .collect()
+        })
+        .collect();
+    if args.json {
+        let rows = rows
+            .iter()
+            .map(|row| json!({"name": row[0], "type": row[1]}))
+            .collect::<Vec<_>>();
+        return print_json(&rows);
+    }
+    print_table(&["NAME", "TYPE"], &rows)
+}
+
+fn columns(source: &Source, args: &CatalogArgs) -> Result<()> {
+    let conn = source.connect()?;
+    let table = resolve_table(&conn, args.target.as_deref().unwrap_or_default())?;
+    let declared = fetch_rows(&conn, COLUMNS_SQL, Some(&table))?;
+    drop(conn);
+    let arrow = table_schema(source, &table)?;
+
+    let int = |value: &Option<String>| value.as_deref().and_then(|text| text.parse::<i64>().ok());
+    let rows: Vec<Vec<String>> = declared
+        .iter()
+        .map(|row| {
+            let name = row[0].clone().unwrap_or_default();
+            let sql_type = sql_type(
+                int(&row[1]).unwrap_or_default(),
+                int(&row[2]).unwrap_or_default(),
+                int(&row[3]).unwrap_or_default(),
+                int(&row[4]).unwrap_or_default(),
+                int(&row[5]).unwrap_or_default(),
+                int(&row[6]),
+            );
+            let arrow_type = arrow
+                .field_with_name(&name)
+                .map(|field| field.data_type().to_string())
+                .unwrap_or_default();
+            let nullable = if int(&row[7]).unwrap_or_default() == 0 {
+                "YES"
+            } else {
+                "NO"
+            };
+            vec![name, sql_type, arrow_type, nullable.to_string()]
+        })
+        .collect();
+    if args.json {
+        let rows = rows
+            .iter()
+            .map(|row| {
+                json!({
+                    "name": row[0],
+                    "sql_type": row[1],
+                    "arrow_type": row[2],
+                    "nullable": row[3] == "YES",
+                })
+            })
+            .collect::<Vec<_>>();
+        return print_json(&rows);
+    }
+    print_table(&["NAME", "SQL TYPE", "ARROW TYPE", "NULLABLE"], &rows)
+}
+
+fn schema(source: &Source, args: &CatalogArgs) -> Result<()> {
+    let target = args.target.as_deref().unwrap_or_default();
+    let query = scan::source(target);
+    let schema = if query == lexer::statement(target) {
+        let table = resolve_table(&source.connect()?, target)?;
+        table_schema(source, &table)?
+    } else {
+        scan::schema(
+            &source.dsn,
+            &source.user,
+            &source.password,
+            &query,
+            &source.config,
+        )?
+    };
+    if args.json {
+        let rows = schema
+            .fields()
+            .iter()
+            .map(|field| {
+                json!({
+                    "name": field.name(),
+                    "type": field.data_type().to_string(),
+                    "nullable": field.is_nullable(),
+                })
+            })
+            .collect::<Vec<_>>();
+        return print_json(&rows);
+    }
+    let mut out = io::stdout().lock();
+    for field in schema.fields() {
+        let not_null = if field.is_nullable() { "" } else { " not null" };
+        writeln!(out, "{}: {}{}", field.name(), field.data_type(), not_null)?;
+    }
+    Ok(())
+}
+
+// Stored name of a column as given in SQL: quoted names keep their spelling, others are
+// upper-cased
+fn stored_name(column: &str) -> String {
+    match column
+        .trim()
+        .strip_prefix('"')
+        .and_then(|name| name.strip_suffix('"'))
+    {
+        Some(name) => name.replace("\"\"", "\""),
+        None => column.trim().to_uppercase(),
+    }
+}
+
+fn copy_table(source: &Source, args: &CopyArgs) -> Result<()> {
+    let conn = source.connect()?;
+    let table = resolve_table(&conn, args.table.as_deref().unwrap_or_default())?;
+    let key = match &args.key {
+        Some(key) => Some(key.clone()),
+        None => match catalog::primary_key(&conn, &table)?.as_slice() {
+            [column] => Some(quote_identifier(column)),
+            _ => None,
+        },
+    };
+    // Batches are committed one by one, so rows sharing a key value can be split between a
+    // committed batch and a lost one, and resuming after that value would skip the rest
+    if let (Some(key), true) = (&args.key, args.resume) {
+        let unique = fetch_rows(&conn, UNIQUE_COLUMNS_SQL, Some(&table))?;
+        let name = stored_name(key);
+        if !unique
+            .iter()
+            .any(|row| row[0].as_deref() == Some(name.as_str()))
+        {
+            return Err(anyhow!(
+                "--resume needs a unique --key: {} is not the only column of a primary key or \
+                 unique index on {}",
+                key,
+                table
+            ));
+        }
+    }
+    drop(conn);
+
+    let target = Source {
+        dsn: args.to.clone().unwrap_or_default(),
+        user: args.to_user.clone().unwrap_or_else(|| source.user.clone()),
+        password: args
+            .to_password
+            .clone()
+            .unwrap_or_else(|| source.password.clone()),
+        config: source.config.clone(),
+    };
+    let conn = target.connect()?;
+    let wanted = args.target_table.as_deref().unwrap_or(&table);
+    let target_table = match resolve_table(&conn, wanted) {
+        Ok(found) => Some(found),
+        Err(_) if args.create => None,
+        Err(_) => {
+            return Err(anyhow!(
+                "target table '{}' does not exist (pass --create to create it)",
+                wanted
+            ))
+        }
+    };
+
+    // Rows go over in key order, so everything up to the target's highest key has been
+    // committed and a resumed copy continues right after it
+    let last_key = match (&key, &target_table, args.resume) {
+        (None, _, true) => {
+            return Err(anyhow!(
+                "--resume needs --key or a single-column primary key on {}",
+                table
+            ))
+        }
+        (Some(key), Some(target_table), true) => {
+            let sql = format!(
+                "SELECT {key} FROM {} ORDER BY {key} DESC ROWS 1",
+                quote_identifier(target_table),
+                key = key
+            );
+            let (_, batches) = collect_batches(
+                &target.dsn,
+                &target.user,
+                &target.password,
+                &sql,
+                &target.config,
+            )?;
+            match batches.iter().find(|batch| batch.num_rows() > 0) {
+                Some(batch) => restart::last_key_literal(batch, key)?,
+                None => None,
+            }
+        }
+        _ => None,
+    };
+    let mut sql = format!("SELECT * FROM {}", quote_identifier(&table));
+    if let Some(key) = &key {
+        if let Some(last) = &last_key {
+            sql.push_str(&format!(" WHERE {} > {}", key, last));
+            eprintln!(
+                "DEBUG: Resuming the copy of {} after {} = {}",
+                table, key, last
+            );
+        }
+        sql.push_str(&format!(" ORDER BY {}", key));
+    }
+
+    conn.set_autocommit(false)?;
+    let into = quote_identifier(target_table.as_deref().unwrap_or(wanted));
+    let mut sink = CopySink {
+        conn: &conn,
+        table: into.clone(),
+        create: target_table.is_none(),
+        config: &target.config,
+        writer: None,
+        rows: 0,
+        started: Instant::now(),
+        reported: Instant::now(),
+    };
+    let copied = fetch_into(
+        &source.dsn,
+        &source.user,
+        &source.password,
+        &sql,
+        &[],
+        &source.config,
+        &mut sink,
+        &mut |_| Ok(()),
+    );
+    let rows = sink.rows;
+    drop(sink);
+    if let Err(e) = copied {
+        // Batches committed so far stay; the one in flight is undone
+        let _ = conn.rollback();
+        return Err(e.context(format!(
+            "copy stopped after {} rows were committed to {}",
+            rows, into
+        )));
+    }
+    eprintln!("Copied {} rows from {} into {}", rows, table, into);
+    Ok(())
+}
+
+// Bulk-inserts each batch into the target table with array-bound parameters and commits it in
+// its own transaction
+struct CopySink<'c> {
+    conn: &'c Connection<'c>,
+    table: String,
+    // Create the table from the source schema in `begin`
+    create: bool,
+    config: &'c QueryConfig,
+    writer: Option<OdbcWriter<StatementImpl<'c>>>,
+    rows: usize,
+    started: Instant,
+    reported: Instant,
+}
+
+impl CopySink<'_> {
+    fn report(&mut self, done: bool) {
+        if !done && self.reported.elapsed() < PROGRESS_INTERVAL {
+            return;
+        }
+        self.reported = Instant::now();
+        let secs = self.started.elapsed().as_secs_f64();
+        let rate = if secs > 0.0 {
+            self.rows as f64 / secs
+        } else {
+            0.0
+        };
+        eprintln!(
+            "{}: {} rows in {:.1}s ({:.0} rows/s)",
+            self.table, self.rows, secs, rate
+        );
+    }
+}
+
+impl BatchSink for CopySink<'_> {
+    fn begin(&mut self, schema: &SchemaRef) -> Result<()> {
+        if self.create {
+            create_table(self.conn, &self.table, schema)?;
+            self.conn.commit()?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        if batch.num_rows() == 0 {
+            return Ok(());
+        }
+        if self.writer.is_none() {
+            let schema = batch.schema();
+            let columns: Vec<String> = schema
+                .fields()
+                .iter()
+                .map(|field| quote_identifier(field.name()))
+                .collect();
+            let sql = format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                self.table,
+                columns.join(", "),
+                vec!["?"; columns.len()].join(", ")
+            );
+            access::check(&sql, self.config)?;
+            eprintln!("DEBUG: {}", sql);
+            let capacity = batch
+                .num_rows()
+                .max(self.config.batch_size.unwrap_or(0) as usize);
+            let prepared = self.conn.prepare(&sql)?;
+            self.writer = Some(OdbcWriter::new(capacity, &schema, prepared)?);
+        }
+        let writer = self.writer.as_mut().expect("writer was just created");
+        writer.write_batch(batch)?;
+        writer.flush()?;
+        self.conn.commit()?;
+        self.rows += batch.num_rows();
+        self.report(false);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(writer) = self.writer.as_mut() {
+            writer.flush()?;
+        }
+        self.conn.commit()?;
+        self.report(true);
+        Ok(())
+    }
+}
+
+// Timings of one run at one batch size
+#[derive(Clone, Copy)]
+struct BenchRun {
+    // Rows per batch the reader settled on; lower than asked when max_bytes_per_batch wins
+    batch_rows: usize,
+    rows: usize,
+    execute: Duration,
+    fetch: Duration,
+    peak_rss: Option<u64>,
+    batch_bytes: usize,
+}
+
+impl BenchRun {
+    fn total(&self) -> Duration {
+        self.execute + self.fetch
+    }
+}
+
+fn bench(source: Source, args: &QueryArgs, bench: &BenchArgs, catalog: &CatalogArgs) -> Result<()> {
+    let sql = read_sql(args)?;
+    access::check(&sql, &source.config)?;
+    let sql = charset::transcode(&sql, &[], source.config.sql_charset.as_deref())?;
+    let sizes = if bench.batch_sizes.is_empty() {
+        vec![1000, 10000, 50000]
+    } else {
+        bench.batch_sizes.clone()
+    };
+    let limit = args.max_rows.map(|rows| rows as usize);
+
+    let conn = source.connect()?;
+    let mut results = Vec::new();
+    for size in sizes {
+        let config = QueryConfig {
+            batch_size: Some(size),
+            ..source.config.clone()
+        };
+        let mut best: Option<BenchRun> = None;
+        for _ in 0..bench.runs.unwrap_or(1) {
+            let run = bench_run(&conn, &sql, &config, limit)?;
+            if best.is_none_or(|best| run.total() < best.total()) {
+                best = Some(run);
+            }
+        }
+        let best = best.expect("at least one run");
+        eprintln!(
+            "DEBUG: batch_size={} took {:.3}s",
+            size,
+            best.total().as_secs_f64()
+        );
+        results.push((size, best));
+    }
+
+    let rate = |run: &BenchRun| {
+        let secs = run.total().as_secs_f64();
+        if secs > 0.0 {
+            run.rows as f64 / secs
+        } else {
+            0.0
+        }
+    };
+    if catalog.json {
+        let rows = results
+            .iter()
+            .map(|(size, run)| {
+                json!({
+                    "batch_size": size,
+                    "batch_rows": run.batch_rows,
+                    "rows": run.rows,
+                    "execute_secs": run.execute.as_secs_f64(),
+                    "fetch_secs": run.fetch.as_secs_f64(),
+                    "total_secs": run.total().as_secs_f64(),
+                    "rows_per_sec": rate(run),
+                    "peak_rss_bytes": run.peak_rss,
+                    "batch_bytes": run.batch_bytes,
+                })
+            })
+            .collect::<Vec<_>>();
+        return print_json(&rows);
+    }
+    let secs = |duration: Duration| format!("{:.3}s", duration.as_secs_f64());
+    let rows: Vec<Vec<String>> = results
+        .iter()
+        .map(|(size, run)| {
+            let batch = if run.batch_rows < *size as usize {
+                format!("{} ({})", size, run.batch_rows)
+            } else {
+                size.to_string()
+            };
+            vec![
+                batch,
+                run.rows.to_string(),
+                secs(run.execute),
+                secs(run.fetch),
+                secs(run.total()),
+                format!("{:.0}", rate(run)),
+                run.peak_rss
+                    .map(mebibytes)
+                    .unwrap_or_else(|| "-".to_string()),
+                mebibytes(run.batch_bytes as u64),
+            ]
+        })
+        .collect();
+    print_table(
+        &[
+            "BATCH SIZE",
+            "ROWS",
+            "EXECUTE",
+            "FETCH",
+            "TOTAL",
+            "ROWS/S",
+            "PEAK RSS",
+            "BATCH MEM",
+        ],
+        &rows,
+    )?;
+    if let Some((size, _)) = results
+        .iter()
+        .min_by(|(_, a), (_, b)| a.total().cmp(&b.total()))
+    {
+        println!("\nFastest: batch_size={}", size);
+    }
+    Ok(())
+}
+
+// Runs `sql` once through the Arrow reader, timing the execute and then the reads. The reader
+// converts each rowset as soon as it is fetched, so the fetch time includes the conversion.
+fn bench_run(
+    conn: &Connection<'_>,
+    sql: &Transcoded,
+    config: &QueryConfig,
+    limit: Option<usize>,
+) -> Result<BenchRun> {
+    reset_peak_rss();
+    let statement = conn.preallocate()?.into_handle();
+    let started = Instant::now();
+    let cursor = sql.execute(statement)?;
+    let execute = started.elapsed();
+    let mut run = BenchRun {
+        batch_rows: 0,
+        rows: 0,
+        execute,
+        fetch: Duration::ZERO,
+        peak_rss: None,
+        batch_bytes: 0,
+    };
+    if let Some(cursor) = cursor {
+        let reader = build_reader(cursor, config)?;
+        run.batch_rows = reader.max_rows_per_batch();
+        let started = Instant::now();
+        for batch in reader {
+            let batch = batch?;
+            run.rows += batch.num_rows();
+            run.batch_bytes = run.batch_bytes.max(batch.get_array_memory_size());
+            if limit.is_some_and(|limit| run.rows >= limit) {
+                break;
+            }
+        }
+        run.fetch = started.elapsed();
+    }
+    run.peak_rss = peak_rss();
+    Ok(run)
+}
+
+fn mebibytes(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+// Linux keeps the peak resident set size in /proc/self/status; writing 5 to clear_refs starts
+// it over from the current size
+fn reset_peak_rss() {
+    let _ = std::fs::write("/proc/self/clear_refs", "5");
+}
+
+fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+// The stored name of `name`: as typed, or upper-cased the way Firebird stores unquoted names
+fn resolve_table(conn: &Connection<'_>, name: &str) -> Result<String> {
+    write::find_table(conn, name)?.ok_or_else(|| anyhow!("table '{}' not found", name.trim()))
+}
+
+// Arrow schema of a table as ibarrow reads it, without fetching rows
+fn table_schema(source: &Source, table: &str) -> Result<std::sync::Arc<Schema>> {
+    catalog::table_schema(
+        &source.dsn,
+        &source.user,
+        &source.password,
+        table,
+        &source.config,
+    )
+}
+
+// Every row of a small catalog query as text, NULL as None
+fn fetch_rows(
+    conn: &Connection<'_>,
+    sql: &str,
+    param: Option<&str>,
+) -> Result<Vec<Vec<Option<String>>>> {
+    let cursor = match param {
+        Some(value) => conn.execute(sql, &value.into_parameter(), None)?,
+        None => conn.execute(sql, (), None)?,
+    };
+    let mut rows = Vec::new();
+    if let Some(mut cursor) = cursor {
+        let columns = cursor.num_result_cols()?.max(0) as u16;
+        let mut buf = Vec::new();
+        while let Some(mut row) = cursor.next_row()? {
+            let mut values = Vec::with_capacity(columns as usize);
+            for column in 1..=columns {
+                values.push(
+                    row.get_text(column, &mut buf)?
+                        .then(|| String::from_utf8_lossy(&buf).trim().to_string()),
+                );
+            }
+            rows.push(values);
+        }
+    }
+    Ok(rows)
+}
+
+// Firebird/InterBase DDL type from the RDB$FIELDS columns describing it
+fn sql_type(
+    field_type: i64,
+    sub_type: i64,
+    length: i64,
+    precision: i64,
+    scale: i64,
+    char_length: Option<i64>,
+) -> String {
+    let exact = |default_precision: i64, name: &str| {
+        if scale < 0 || sub_type == 1 || sub_type == 2 {
+            let kind = if sub_type == 2 { "DECIMAL" } else { "NUMERIC" };
+            let precision = if precision > 0 {
+                precision
+            } else {
+                default_precision
+            };
+            format!("{}({}, {})", kind, precision, -scale)
+        } else {
+            name.to_string()
+        }
+    };
+    let chars = char_length.unwrap_or(length);
+    match field_type {
+        7 => exact(4, "SMALLINT"),
+        8 => exact(9, "INTEGER"),
+        16 => exact(18, "BIGINT"),
+        26 => exact(38, "INT128"),
+        // Dialect 1 stores NUMERIC/DECIMAL with more than 9 digits as DOUBLE PRECISION
+        27 if scale < 0 => format!("NUMERIC(15, {})", -scale),
+        10 => "FLOAT".to_string(),
+        27 => "DOUBLE PRECISION".to_string(),
+        11 => "D_FLOAT".to_string(),
+        9 => "QUAD".to_string(),
+        12 => "DATE".to_string(),
+        13 => "TIME".to_string(),
+        28 => "TIME WITH TIME ZONE".to_string(),
+        35 => "TIMESTAMP".to_string(),
+        29 => "TIMESTAMP WITH TIME ZONE".to_string(),
+        14 => format!("CHAR({})", chars),
+        37 => format!("VARCHAR({})", chars),
+        40 => format!("CSTRING({})", chars),
+        23 => "BOOLEAN".to_string(),
+        24 => "DECFLOAT(16)".to_string(),
+        25 => "DECFLOAT(34)".to_string(),
+        261 => match sub_type {
+            0 => "BLOB SUB_TYPE BINARY".to_string(),
+            1 => "BLOB SUB_TYPE TEXT".to_string(),
+            other => format!("BLOB SUB_TYPE {}", other),
+        },
+        other => format!("UNKNOWN({})", other),
+    }
+}
+
+// Left-aligned columns sized to their widest cell
+fn print_table(headers: &[&str], rows: &[Vec<String>]) -> Result<()> {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut out = io::stdout().lock();
+    let mut line = |cells: Vec<&str>| -> io::Result<()> {
+        let text = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(out, "{}", text.trim_end())
+    };
+    line(headers.to_vec())?;
+    for row in rows {
+        line(row.iter().map(String::as_str).collect())?;
+    }
+    Ok(())
+}
+
+fn print_json(rows: &[serde_json::Value]) -> Result<()> {
+    let mut out = io::stdout().lock();
+    for row in rows {
+        writeln!(out, "{}", row)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Local, NaiveDateTime, Utc};
+use odbc_api::Environment;
+
+use crate::migrate::fetch_texts;
+use crate::{build_connection_string, diagnostics, driverenv, retry, QueryConfig};
+
+// Server wall clock as fractional days since 1970-01-01 in the session's timezone.
+// Timestamp subtraction works on every Firebird and InterBase version.
+const WALL_CLOCK_SQL: &str = "SELECT CAST(CAST(CURRENT_TIMESTAMP AS TIMESTAMP) - \
+     CAST('1970-01-01 00:00:00' AS TIMESTAMP) AS DOUBLE PRECISION) FROM RDB$DATABASE";
+// Firebird 4+ only: session offset from UTC in minutes and the session timezone name
+const UTC_OFFSET_SQL: &str = "SELECT EXTRACT(TIMEZONE_HOUR FROM CURRENT_TIMESTAMP) * 60 + \
+     EXTRACT(TIMEZONE_MINUTE FROM CURRENT_TIMESTAMP) FROM RDB$DATABASE";
+const TIMEZONE_SQL: &str = "SELECT RDB$GET_CONTEXT('SYSTEM', 'SESSION_TIMEZONE') FROM RDB$DATABASE";
+
+pub struct ServerTime {
+    pub server_time: NaiveDateTime,
+    pub client_time: NaiveDateTime,
+    pub skew_seconds: f64,
+    pub round_trip_seconds: f64,
+    pub utc_offset_minutes: Option<i32>,
+    pub timezone: Option<String>,
+}
+
+// Reads the server clock and compares it with the client clock at the midpoint of the round
+// trip. Without a server UTC offset (before Firebird 4) both clocks are assumed to share the
+// client's timezone.
+pub fn server_time(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    config: &QueryConfig,
+) -> Result<ServerTime> {
+    diagnostics::clear();
+    let env = Environment::new()?;
+    let conn_str = build_connection_string(dsn, user, password, config);
+    let conn = retry::with_retry(config, || driverenv::connect(&env, &conn_str, config))?;
+
+    let before = Utc::now();
+    let days = fetch_texts(&conn, WALL_CLOCK_SQL, None)?;
+    let after = Utc::now();
+    let days: f64 = days
+        .first()
+        .and_then(|d| d.replace(',', ".").parse().ok())
+        .ok_or_else(|| anyhow!("Could not read the server time from its SQL result"))?;
+    let server_time = DateTime::UNIX_EPOCH.naive_utc()
+        + Duration::microseconds((days * 86_400_000_000.0).round() as i64);
+
+    let round_trip = after - before;
+    let client_utc = before + round_trip / 2;
+    let client_time = client_utc.with_timezone(&Local).naive_local();
+
+    // Older servers reject these statements; their answer is simply unknown
+    let utc_offset_minutes = fetch_texts(&conn, UTC_OFFSET_SQL, None)
+        .ok()
+        .and_then(|t| t.first()?.parse().ok());
+    let timezone = fetch_texts(&conn, TIMEZONE_SQL, None)
+        .ok()
+        .and_then(|t| t.into_iter().next())
+        .filter(|tz| !tz.is_empty());
+    diagnostics::clear();
+
+    let server_utc = match utc_offset_minutes {
+        Some(minutes) => server_time - Duration::minutes(minutes as i64),
+        None => server_time - (client_time - client_utc.naive_utc()),
+    };
+    let skew = server_utc - client_utc.naive_utc();
+    Ok(ServerTime {
+        server_time,
+        client_time,
+        skew_seconds: skew.num_microseconds().unwrap_or(0) as f64 / 1e6,
+        round_trip_seconds: round_trip.num_microseconds().unwrap_or(0) as f64 / 1e6,
+        utc_offset_minutes,
+        timezone,
+    })
+}
@@ -0,0 +1,82 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::QueryConfig;
+
+pub use ibarrow_core::compression::{named_options, write_options};
+
+// Who reads the IPC stream ibarrow produces
+#[derive(Debug, Clone, Copy)]
+pub enum Consumer {
+    Polars,
+    PyArrow,
+    // Raw bytes or files: the reader is unknown
+    Bytes,
+}
+
+// Leading `major.minor` of a module's __version__
+pub fn module_version(module: &Bound<'_, PyModule>) -> Option<(u32, u32)> {
+    let version: String = module.getattr("__version__").ok()?.extract().ok()?;
+    let mut parts = version.split('.').map(|p| p.parse::<u32>().ok());
+    Some((parts.next()??, parts.next().flatten().unwrap_or(0)))
+}
+
+// Best codec the installed consumer can decode, or "none"
+fn negotiate(py: Python<'_>, consumer: Consumer) -> &'static str {
+    match consumer {
+        Consumer::Polars => match py
+            .import_bound("polars")
+            .ok()
+            .as_ref()
+            .and_then(module_version)
+        {
+            // Compressed IPC buffers are read since polars 0.15
+            Some(version) if version >= (0, 15) => "zstd",
+            _ => "none",
+        },
+        Consumer::PyArrow => {
+            let pyarrow = match py.import_bound("pyarrow") {
+                Ok(pyarrow) => pyarrow,
+                Err(_) => return "none",
+            };
+            // IPC body compression arrived in pyarrow 2.0, and codecs are optional at build time
+            if module_version(&pyarrow).is_none_or(|v| v < (2, 0)) {
+                return "none";
+            }
+            let available = |name: &str| -> bool {
+                pyarrow
+                    .getattr("Codec")
+                    .and_then(|codec| codec.call_method1("is_available", (name,)))
+                    .and_then(|ok| ok.is_truthy())
+                    .unwrap_or(false)
+            };
+            if available("zstd") {
+                "zstd"
+            } else if available("lz4") {
+                "lz4"
+            } else {
+                "none"
+            }
+        }
+        Consumer::Bytes => "none",
+    }
+}
+
+// Copy of `config` with `ipc_compression: "auto"` replaced by the codec `consumer` supports.
+// Explicit codecs are kept as an override and unset means no compression.
+pub fn for_consumer(
+    py: Python<'_>,
+    config: &QueryConfig,
+    consumer: Consumer,
+) -> PyResult<QueryConfig> {
+    write_options(config).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let mut config = config.clone();
+    if let Some(setting) = &config.ipc_compression {
+        if setting.eq_ignore_ascii_case("auto") {
+            let chosen = negotiate(py, consumer);
+            eprintln!("DEBUG: IPC compression for {:?}: {}", consumer, chosen);
+            config.ipc_compression = Some(chosen.to_string());
+        }
+    }
+    Ok(config)
+}
@@ -0,0 +1,503 @@
+use anyhow::Result;
+use arrow::array::{Array, ArrayRef, AsArray};
+use arrow::datatypes::{
+    DataType, Date32Type, Date64Type, Decimal128Type, Decimal256Type, Float32Type, Float64Type,
+    Int16Type, Int32Type, Int64Type, Int8Type, SchemaRef, Time32MillisecondType, Time32SecondType,
+    Time64MicrosecondType, Time64NanosecondType, TimeUnit, TimestampMicrosecondType,
+    TimestampMillisecondType, TimestampNanosecondType, TimestampSecondType, UInt16Type, UInt32Type,
+    UInt64Type, UInt8Type,
+};
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use chrono::NaiveDateTime;
+use odbc_api::Connection;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyRuntimeError, PyStopIteration, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyList, PyTuple};
+
+use crate::ibis::ibis_type;
+use crate::params::{self, QueryParams, SqlParam};
+use crate::{
+    access, build_reader, charset, limit_rows, map_query_error, transaction, IbarrowConnection,
+    QueryConfig,
+};
+use std::sync::Mutex;
+
+// Module globals of PEP 249: both `?` and `:name` placeholders are accepted, and connections
+// may be shared between threads
+pub const APILEVEL: &str = "2.0";
+pub const THREADSAFETY: u8 = 2;
+pub const PARAMSTYLE: &str = "qmark";
+
+// PEP 249 exception hierarchy. PyConnectionError, PySQLError and PyArrowError derive from it;
+// PyTimeoutError and PyAccessError keep deriving from TimeoutError and PermissionError.
+create_exception!(ibarrow, Warning, PyException);
+create_exception!(ibarrow, Error, PyException);
+create_exception!(ibarrow, InterfaceError, Error);
+create_exception!(ibarrow, DatabaseError, Error);
+create_exception!(ibarrow, DataError, DatabaseError);
+create_exception!(ibarrow, OperationalError, DatabaseError);
+create_exception!(ibarrow, IntegrityError, DatabaseError);
+create_exception!(ibarrow, InternalError, DatabaseError);
+create_exception!(ibarrow, ProgrammingError, DatabaseError);
+create_exception!(ibarrow, NotSupportedError, DatabaseError);
+
+pub fn add_exceptions(py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("Warning", py.get_type_bound::<Warning>())?;
+    m.add("Error", py.get_type_bound::<Error>())?;
+    m.add("InterfaceError", py.get_type_bound::<InterfaceError>())?;
+    m.add("DatabaseError", py.get_type_bound::<DatabaseError>())?;
+    m.add("DataError", py.get_type_bound::<DataError>())?;
+    m.add("OperationalError", py.get_type_bound::<OperationalError>())?;
+    m.add("IntegrityError", py.get_type_bound::<IntegrityError>())?;
+    m.add("InternalError", py.get_type_bound::<InternalError>())?;
+    m.add("ProgrammingError", py.get_type_bound::<ProgrammingError>())?;
+    m.add(
+        "NotSupportedError",
+        py.get_type_bound::<NotSupportedError>(),
+    )?;
+    Ok(())
+}
+
+// Statements whose rows are read through IbarrowConnection.query(); anything else runs as a
+// prepared statement reporting the rows it affected
+fn returns_rows(sql: &str) -> bool {
+    let keyword = sql
+        .trim_start()
+        .split(|c: char| c.is_whitespace() || c == '(')
+        .next()
+        .unwrap_or_default()
+        .to_uppercase();
+    keyword == "SELECT" || keyword == "WITH"
+}
+
+// What a statement run by execute_on() produced: the rows it affected, or the rows
+// it returned (INSERT ... RETURNING, EXECUTE PROCEDURE)
+pub struct Executed {
+    pub rowcount: Option<usize>,
+    pub rows: Option<(SchemaRef, Vec<RecordBatch>)>,
+}
+
+// The DB-API transaction of an IbarrowConnection: a connection with autocommit off, opened
+// by the first cursor statement that is not a query and held until commit() or rollback()
+pub type HeldTransaction = Mutex<Option<Connection<'static>>>;
+
+// Runs `sql` once per parameter set in the connection's DB-API transaction, opening it first
+// if none is held. Nothing is committed: a failed statement leaves the transaction open for
+// the caller to roll back, as PEP 249 expects.
+pub fn execute_in_transaction(
+    conn: &IbarrowConnection,
+    sql: &str,
+    param_sets: &[Vec<SqlParam>],
+) -> Result<Executed> {
+    access::check(sql, &conn.config)?;
+    let mut held = conn.transaction.lock().unwrap_or_else(|e| e.into_inner());
+    let tx = match held.take() {
+        Some(tx) => tx,
+        None => transaction::open(&conn.dsn, &conn.user, &conn.password, &conn.config)?,
+    };
+    let outcome = execute_on(held.insert(tx), sql, param_sets, &conn.config);
+    eprintln!(
+        "DEBUG: Executed statement {} times in the open transaction",
+        param_sets.len()
+    );
+    outcome
+}
+
+// Commits or rolls back the connection's DB-API transaction and closes its connection;
+// nothing happens when none is held
+pub fn end_transaction(conn: &IbarrowConnection, commit: bool) -> Result<()> {
+    let tx = conn
+        .transaction
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take();
+    match tx {
+        Some(tx) if commit => Ok(tx.commit()?),
+        Some(tx) => Ok(tx.rollback()?),
+        None => Ok(()),
+    }
+}
+
+// Runs `sql` once per parameter set on `conn` through one prepared statement, leaving
+// transaction handling to the caller. The affected rows of all executions are summed; rows
+// returned are kept from the last one.
+pub fn execute_on(
+    conn: &Connection<'_>,
+    sql: &str,
+    param_sets: &[Vec<SqlParam>],
+    config: &QueryConfig,
+) -> Result<Executed> {
+    let statement = charset::transcode(sql, &[], config.sql_charset.as_deref())?;
+    let mut prepared = statement.prepare(conn.preallocate()?.into_handle())?;
+    let mut executed = Executed {
+        rowcount: Some(0),
+        rows: None,
+    };
+    for params in param_sets {
+        let returned = match prepared.execute(&statement.encode(params)?)? {
+            Some(cursor) => {
+                let reader = build_reader(cursor, config)?;
+                let schema = reader.schema();
+                let batches = limit_rows(reader, config).collect::<Result<Vec<_>, _>>()?;
+                let rows = batches.iter().map(RecordBatch::num_rows).sum::<usize>();
+                executed.rows = Some((schema, batches));
+                Some(rows)
+            }
+            None => None,
+        };
+        let affected = match returned {
+            Some(rows) => Some(rows),
+            None => prepared.row_count()?,
+        };
+        executed.rowcount = executed.rowcount.zip(affected).map(|(a, b)| a + b);
+    }
+    Ok(executed)
+}
+
+fn temporal_error(row: usize) -> PyErr {
+    PyValueError::new_err(format!("Row {} holds a date or time out of range", row))
+}
+
+// Python value of one cell: int, float, bool, str, bytes, decimal.Decimal, datetime.date,
+// datetime.datetime (timezone-aware for zoned timestamps) or datetime.time. Other types come
+// back as their text form.
+fn py_value(
+    py: Python<'_>,
+    decimal: &Bound<'_, PyAny>,
+    array: &ArrayRef,
+    row: usize,
+) -> PyResult<Py<PyAny>> {
+    if array.is_null(row) {
+        return Ok(py.None());
+    }
+    let value = match array.data_type() {
+        DataType::Boolean => array.as_boolean().value(row).into_py(py),
+        DataType::Int8 => array.as_primitive::<Int8Type>().value(row).into_py(py),
+        DataType::Int16 => array.as_primitive::<Int16Type>().value(row).into_py(py),
+        DataType::Int32 => array.as_primitive::<Int32Type>().value(row).into_py(py),
+        DataType::Int64 => array.as_primitive::<Int64Type>().value(row).into_py(py),
+        DataType::UInt8 => array.as_primitive::<UInt8Type>().value(row).into_py(py),
+        DataType::UInt16 => array.as_primitive::<UInt16Type>().value(row).into_py(py),
+        DataType::UInt32 => array.as_primitive::<UInt32Type>().value(row).into_py(py),
+        DataType::UInt64 => array.as_primitive::<UInt64Type>().value(row).into_py(py),
+        DataType::Float32 => array.as_primitive::<Float32Type>().value(row).into_py(py),
+        DataType::Float64 => array.as_primitive::<Float64Type>().value(row).into_py(py),
+        DataType::Decimal128(_, _) => {
+            let text = array.as_primitive::<Decimal128Type>().value_as_string(row);
+            decimal.call1((text,))?.unbind()
+        }
+        DataType::Decimal256(_, _) => {
+            let text = array.as_primitive::<Decimal256Type>().value_as_string(row);
+            decimal.call1((text,))?.unbind()
+        }
+        DataType::Utf8 => array.as_string::<i32>().value(row).into_py(py),
+        DataType::LargeUtf8 => array.as_string::<i64>().value(row).into_py(py),
+        DataType::Binary => PyBytes::new_bound(py, array.as_binary::<i32>().value(row)).into_py(py),
+        DataType::LargeBinary => {
+            PyBytes::new_bound(py, array.as_binary::<i64>().value(row)).into_py(py)
+        }
+        DataType::Date32 => array
+            .as_primitive::<Date32Type>()
+            .value_as_date(row)
+            .ok_or_else(|| temporal_error(row))?
+            .into_py(py),
+        DataType::Date64 => array
+            .as_primitive::<Date64Type>()
+            .value_as_date(row)
+            .ok_or_else(|| temporal_error(row))?
+            .into_py(py),
+        DataType::Timestamp(unit, zone) => {
+            let datetime: Option<NaiveDateTime> = match unit {
+                TimeUnit::Second => array
+                    .as_primitive::<TimestampSecondType>()
+                    .value_as_datetime(row),
+                TimeUnit::Millisecond => array
+                    .as_primitive::<TimestampMillisecondType>()
+                    .value_as_datetime(row),
+                TimeUnit::Microsecond => array
+                    .as_primitive::<TimestampMicrosecondType>()
+                    .value_as_datetime(row),
+                TimeUnit::Nanosecond => array
+                    .as_primitive::<TimestampNanosecondType>()
+                    .value_as_datetime(row),
+            };
+            let datetime = datetime.ok_or_else(|| temporal_error(row))?;
+            match zone {
+                // Zoned Arrow timestamps hold UTC instants
+                Some(_) => datetime.and_utc().into_py(py),
+                None => datetime.into_py(py),
+            }
+        }
+        DataType::Time32(TimeUnit::Second) => array
+            .as_primitive::<Time32SecondType>()
+            .value_as_time(row)
+            .ok_or_else(|| temporal_error(row))?
+            .into_py(py),
+        DataType::Time32(_) => array
+            .as_primitive::<Time32MillisecondType>()
+            .value_as_time(row)
+            .ok_or_else(|| temporal_error(row))?
+            .into_py(py),
+        DataType::Time64(TimeUnit::Microsecond) => array
+            .as_primitive::<Time64MicrosecondType>()
+            .value_as_time(row)
+            .ok_or_else(|| temporal_error(row))?
+            .into_py(py),
+        DataType::Time64(_) => array
+            .as_primitive::<Time64NanosecondType>()
+            .value_as_time(row)
+            .ok_or_else(|| temporal_error(row))?
+            .into_py(py),
+        DataType::Dictionary(_, _) => {
+            let dictionary = array.as_any_dictionary();
+            let key = dictionary.normalized_keys()[row];
+            return py_value(py, decimal, dictionary.values(), key);
+        }
+        _ => ArrayFormatter::try_new(array.as_ref(), &FormatOptions::default())
+            .map_err(|e| PyValueError::new_err(e.to_string()))?
+            .value(row)
+            .to_string()
+            .into_py(py),
+    };
+    Ok(value)
+}
+
+// DB-API 2.0 (PEP 249) cursor. Other statements than SELECT and WITH run in the connection's
+// held transaction (see execute_in_transaction), as do queries while one is open; other
+// queries run through IbarrowConnection.query(). Rows are turned into tuples straight from
+// the Arrow batches as they are fetched, without pyarrow.
+#[pyclass]
+pub struct Cursor {
+    connection: Py<IbarrowConnection>,
+    schema: Option<SchemaRef>,
+    batches: Vec<RecordBatch>,
+    // Position of the next row: batch and row within it
+    batch: usize,
+    row: usize,
+    rowcount: i64,
+    closed: bool,
+    #[pyo3(get, set)]
+    arraysize: usize,
+}
+
+impl Cursor {
+    pub fn new(connection: Py<IbarrowConnection>) -> Self {
+        Self {
+            connection,
+            schema: None,
+            batches: Vec::new(),
+            batch: 0,
+            row: 0,
+            rowcount: -1,
+            closed: false,
+            arraysize: 1,
+        }
+    }
+
+    fn check_open(&self) -> PyResult<()> {
+        if self.closed {
+            return Err(PyRuntimeError::new_err("Cursor is closed"));
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self, rows: Option<(SchemaRef, Vec<RecordBatch>)>, rowcount: i64) {
+        // A statement without a result set has no description
+        let rows = rows.filter(|(schema, _)| !schema.fields().is_empty());
+        (self.schema, self.batches) = match rows {
+            Some((schema, batches)) => (Some(schema), batches),
+            None => (None, Vec::new()),
+        };
+        self.batch = 0;
+        self.row = 0;
+        self.rowcount = rowcount;
+    }
+
+    fn run(&mut self, py: Python<'_>, sql: &str, param_sets: Vec<Vec<SqlParam>>) -> PyResult<()> {
+        let guard = self.connection.borrow(py);
+        let conn: &IbarrowConnection = &guard;
+        let executed = py
+            .allow_threads(|| execute_in_transaction(conn, sql, &param_sets))
+            .map_err(map_query_error)?;
+        drop(guard);
+        let rowcount = executed.rowcount.map_or(-1, |rows| rows as i64);
+        self.reset(executed.rows, rowcount);
+        Ok(())
+    }
+
+    // Up to `size` of the remaining rows (all of them for None) as tuples
+    fn take(&mut self, py: Python<'_>, size: Option<usize>) -> PyResult<Vec<Py<PyAny>>> {
+        self.check_open()?;
+        if self.schema.is_none() {
+            return Err(PyRuntimeError::new_err(
+                "No result set: execute() a query that returns rows first",
+            ));
+        }
+        let decimal = py.import_bound("decimal")?.getattr("Decimal")?;
+        let mut rows = Vec::new();
+        while size.is_none_or(|size| rows.len() < size) {
+            let Some(batch) = self.batches.get(self.batch) else {
+                break;
+            };
+            if self.row >= batch.num_rows() {
+                self.batch += 1;
+                self.row = 0;
+                continue;
+            }
+            let values = batch
+                .columns()
+                .iter()
+                .map(|column| py_value(py, &decimal, column, self.row))
+                .collect::<PyResult<Vec<_>>>()?;
+            rows.push(PyTuple::new_bound(py, values).into_any().unbind());
+            self.row += 1;
+        }
+        Ok(rows)
+    }
+}
+
+#[pymethods]
+impl Cursor {
+    #[pyo3(signature = (operation, parameters=None))]
+    fn execute<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        py: Python<'py>,
+        operation: &str,
+        parameters: Option<QueryParams>,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        slf.check_open()?;
+        // Inside a transaction queries run on its connection, to see its uncommitted writes
+        let in_transaction = slf
+            .connection
+            .borrow(py)
+            .transaction
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some();
+        if returns_rows(operation) && !in_transaction {
+            let result = slf
+                .connection
+                .borrow(py)
+                .query(py, operation, parameters, None, None)?;
+            let rows = result.rows() as i64;
+            slf.reset(Some((result.schema().clone(), result.batches()?)), rows);
+            return Ok(slf);
+        }
+        let (sql, params) = params::bind(operation, parameters)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        slf.run(py, &sql, vec![params])?;
+        Ok(slf)
+    }
+
+    // Runs `operation` once per parameter set through one prepared statement
+    fn executemany<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        py: Python<'py>,
+        operation: &str,
+        seq_of_parameters: Vec<QueryParams>,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        slf.check_open()?;
+        let mut sql = operation.to_string();
+        let mut param_sets = Vec::with_capacity(seq_of_parameters.len());
+        for parameters in seq_of_parameters {
+            let (bound, params) = params::bind(operation, Some(parameters))
+                .map_err(|e| PyValueError::new_err(e.to_string()))?;
+            sql = bound;
+            param_sets.push(params);
+        }
+        if param_sets.is_empty() {
+            slf.reset(None, 0);
+            return Ok(slf);
+        }
+        slf.run(py, &sql, param_sets)?;
+        Ok(slf)
+    }
+
+    // One (name, type_code, None, None, None, None, null_ok) tuple per column, type_code being
+    // the Ibis dtype; None before a query that returns rows has run
+    #[getter]
+    fn description(&self, py: Python<'_>) -> PyResult<Option<Py<PyList>>> {
+        let Some(schema) = &self.schema else {
+            return Ok(None);
+        };
+        let columns = PyList::empty_bound(py);
+        for field in schema.fields() {
+            let column = (
+                field.name(),
+                ibis_type(field.data_type()),
+                py.None(),
+                py.None(),
+                py.None(),
+                py.None(),
+                field.is_nullable(),
+            );
+            columns.append(column)?;
+        }
+        Ok(Some(columns.unbind()))
+    }
+
+    // Rows returned or affected by the last execute(), or -1 when unknown
+    #[getter]
+    fn rowcount(&self) -> i64 {
+        self.rowcount
+    }
+
+    fn fetchone(&mut self, py: Python<'_>) -> PyResult<Option<Py<PyAny>>> {
+        Ok(self.take(py, Some(1))?.into_iter().next())
+    }
+
+    #[pyo3(signature = (size=None))]
+    fn fetchmany(&mut self, py: Python<'_>, size: Option<usize>) -> PyResult<Vec<Py<PyAny>>> {
+        let size = size.unwrap_or(self.arraysize);
+        self.take(py, Some(size))
+    }
+
+    fn fetchall(&mut self, py: Python<'_>) -> PyResult<Vec<Py<PyAny>>> {
+        self.take(py, None)
+    }
+
+    // The rows of the last query as a pyarrow.Table, without going through tuples
+    fn fetch_arrow_table(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        self.check_open()?;
+        let schema = self.schema.clone().ok_or_else(|| {
+            PyRuntimeError::new_err("No result set: execute() a query that returns rows first")
+        })?;
+        crate::interop::pyarrow_table_from_batches(py, schema, self.batches.clone())
+    }
+
+    // Accepted and ignored, as PEP 249 allows
+    fn setinputsizes(&self, _sizes: &Bound<'_, PyAny>) {}
+
+    #[pyo3(signature = (_size, _column=None))]
+    fn setoutputsize(&self, _size: &Bound<'_, PyAny>, _column: Option<&Bound<'_, PyAny>>) {}
+
+    fn close(&mut self) {
+        self.closed = true;
+        self.reset(None, -1);
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        self.fetchone(py)?
+            .ok_or_else(|| PyStopIteration::new_err(()))
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> bool {
+        self.close();
+        false
+    }
+}
@@ -0,0 +1,64 @@
+use arrow::datatypes::Schema;
+use arrow::ffi::FFI_ArrowSchema;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyCapsule;
+use std::collections::HashMap;
+
+use crate::dialect::quote_identifier;
+use crate::write;
+
+// Column types and CREATE TABLE text live in ibarrow-core; this module reads schemas from Python
+pub use ibarrow_core::ddl::{
+    column_type, create_table_sql, validate_varchar_length, Dialect, DEFAULT_VARCHAR_LENGTH,
+};
+
+// Reads a schema from an object exporting the Arrow PyCapsule schema interface (a pyarrow
+// Schema, Field or Table, a Polars DataFrame), or from anything batches_from_py accepts
+fn schema_from_py(schema: &Bound<'_, PyAny>) -> PyResult<Schema> {
+    if schema.hasattr("__arrow_c_schema__")? {
+        let capsule = schema.call_method0("__arrow_c_schema__")?;
+        let capsule = capsule.downcast::<PyCapsule>()?;
+        // Only borrowed: the capsule's destructor still releases it
+        let ffi = unsafe { &*(capsule.pointer() as *const FFI_ArrowSchema) };
+        return Schema::try_from(ffi)
+            .map_err(|e| PyValueError::new_err(format!("Arrow C schema import failed: {}", e)));
+    }
+    match write::batches_from_py(schema) {
+        Ok(batches) => Ok(batches.schema().as_ref().clone()),
+        Err(e) if e.is_instance_of::<PyTypeError>(schema.py()) => {
+            Err(PyTypeError::new_err(format!(
+                "Cannot read a schema from a {}: pass an object with __arrow_c_schema__ or \
+                 __arrow_c_stream__, or Arrow IPC stream bytes",
+                schema.get_type().name()?
+            )))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// CREATE TABLE text for an Arrow schema, to review or edit before running it
+#[pyfunction]
+#[pyo3(signature = (schema, table, dialect="firebird", overrides=None, varchar_length=Some(DEFAULT_VARCHAR_LENGTH)))]
+pub fn schema_to_ddl(
+    schema: &Bound<'_, PyAny>,
+    table: &str,
+    dialect: &str,
+    overrides: Option<HashMap<String, String>>,
+    varchar_length: Option<u32>,
+) -> PyResult<String> {
+    if table.trim().is_empty() {
+        return Err(PyValueError::new_err("table must not be empty"));
+    }
+    validate_varchar_length(varchar_length).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let dialect = Dialect::parse(dialect).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let schema = schema_from_py(schema)?;
+    create_table_sql(
+        &quote_identifier(table),
+        &schema,
+        dialect,
+        &overrides.unwrap_or_default(),
+        varchar_length,
+    )
+    .map_err(|e| PyValueError::new_err(e.to_string()))
+}
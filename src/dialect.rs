@@ -0,0 +1,62 @@
+use anyhow::{anyhow, Result};
+use pyo3::prelude::*;
+
+use crate::{backend, quoting, QueryConfig};
+
+// Whether `name` must be double-quoted to keep its spelling (see quoting::requires_quotes)
+#[pyfunction]
+pub fn requires_quotes(name: &str) -> bool {
+    quoting::requires_quotes(name)
+}
+
+// `name` as it must appear in Firebird SQL (see quoting::quote_identifier)
+#[pyfunction]
+pub fn quote_identifier(name: &str) -> String {
+    quoting::quote_identifier(name)
+}
+
+#[pyfunction]
+pub fn _reserved_words() -> Vec<&'static str> {
+    quoting::RESERVED.to_vec()
+}
+
+// The DSN of a SQLAlchemy URL: its `dsn` query argument, or a Firebird `host/port:database`
+// connection string that connect() turns into a DATABASE= connection
+#[pyfunction]
+#[pyo3(signature = (host=None, port=None, database=None, dsn=None))]
+pub fn _url_dsn(
+    host: Option<&str>,
+    port: Option<u16>,
+    database: Option<&str>,
+    dsn: Option<&str>,
+) -> PyResult<String> {
+    if let Some(dsn) = dsn {
+        return Ok(dsn.to_string());
+    }
+    let database = database
+        .filter(|database| !database.is_empty())
+        .ok_or_else(|| {
+            pyo3::exceptions::PyValueError::new_err(
+                "The URL needs a database path or a dsn query argument",
+            )
+        })?;
+    Ok(match (host, port) {
+        (Some(host), Some(port)) => format!("{}/{}:{}", host, port, database),
+        (Some(host), None) => format!("{}:{}", host, database),
+        _ => database.to_string(),
+    })
+}
+
+// Server version as numbers, e.g. (4, 0, 2), from the ENGINE_VERSION context variable
+// (Firebird 2.1+)
+pub fn server_version(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    config: &QueryConfig,
+) -> Result<Vec<u32>> {
+    backend::open(dsn, user, password, config)?
+        .metadata(config)?
+        .server_version
+        .ok_or_else(|| anyhow!("The server did not report ENGINE_VERSION"))
+}
@@ -0,0 +1,213 @@
+use pyo3::exceptions::{PyImportError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+
+use crate::partition;
+use crate::{
+    map_query_error, query_pandas_impl, query_pyarrow_impl, DtypeBackend, IbarrowConnection,
+    QueryConfig,
+};
+
+// Ray only turns Python functions into tasks, so the builtin is called through a lambda, which
+// is pickled by value and imports ibarrow on the worker
+const RAY_TASK: &str = "lambda *args: __import__('ibarrow')._read_partition_arrow(*args)";
+
+// Config of the tasks reading one range each. A row limit cannot be shared between tasks and
+// dictionary codes would differ from one partition to the next. Storage options may hold keys
+// and are of no use to a read.
+fn task_config(config: &QueryConfig) -> QueryConfig {
+    QueryConfig {
+        max_rows: None,
+        dictionary_columns: None,
+        dictionaries: None,
+        progress: None,
+        storage_options: None,
+        ..config.clone()
+    }
+}
+
+// Checks the `credentials_env` prefix before any task is built
+fn validate_prefix(prefix: &str) -> PyResult<()> {
+    if prefix.is_empty()
+        || !prefix
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return Err(PyValueError::new_err(format!(
+            "credentials_env must be an environment variable prefix such as IBARROW, got '{}'",
+            prefix
+        )));
+    }
+    Ok(())
+}
+
+// DSN, user and password of a partition task, read on the worker from `<prefix>_DSN`,
+// `<prefix>_USER` and `<prefix>_PASSWORD`, so they are never part of the pickled task graph
+fn worker_credentials(prefix: &str) -> PyResult<(String, String, String)> {
+    validate_prefix(prefix)?;
+    let var = |name: &str| std::env::var(format!("{}_{}", prefix, name)).ok();
+    let dsn = var("DSN").ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "{}_DSN is not set on this worker; partition tasks read their connection from the \
+             environment",
+            prefix
+        ))
+    })?;
+    Ok((
+        dsn,
+        var("USER").unwrap_or_default(),
+        var("PASSWORD").unwrap_or_default(),
+    ))
+}
+
+fn decode_config(config: &str) -> PyResult<QueryConfig> {
+    serde_json::from_str(config)
+        .map_err(|e| PyValueError::new_err(format!("Invalid partition config: {}", e)))
+}
+
+fn encode_config(config: &QueryConfig) -> PyResult<String> {
+    serde_json::to_string(config)
+        .map_err(|e| PyValueError::new_err(format!("Cannot serialize the config: {}", e)))
+}
+
+// The range queries of `sql` on `column`, run without the GIL
+fn plan(
+    py: Python<'_>,
+    conn: &IbarrowConnection,
+    sql: &str,
+    column: &str,
+    partitions: usize,
+    config: &QueryConfig,
+) -> PyResult<Vec<String>> {
+    let queries = py
+        .allow_threads(|| {
+            partition::range_queries(
+                &conn.dsn,
+                &conn.user,
+                &conn.password,
+                sql,
+                column,
+                partitions,
+                config,
+            )
+        })
+        .map_err(map_query_error)?;
+    eprintln!(
+        "DEBUG: Planned {} partitions on '{}'",
+        queries.len(),
+        column
+    );
+    Ok(queries)
+}
+
+// Task run by the cluster for one range query: `credentials` is the environment variable
+// prefix the worker reads its connection from and `config` the QueryConfig as JSON, so the
+// task and its arguments pickle by value and reference
+#[pyfunction]
+#[pyo3(signature = (credentials, sql, config, dtype_backend=None))]
+pub fn _read_partition(
+    py: Python<'_>,
+    credentials: &str,
+    sql: &str,
+    config: &str,
+    dtype_backend: Option<&str>,
+) -> PyResult<Py<PyAny>> {
+    let config = decode_config(config)?;
+    let backend =
+        DtypeBackend::parse(dtype_backend).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let (dsn, user, password) = worker_credentials(credentials)?;
+    query_pandas_impl(py, &dsn, &user, &password, sql, &config, backend)
+}
+
+// Same as _read_partition, returning a pyarrow.Table
+#[pyfunction]
+pub fn _read_partition_arrow(
+    py: Python<'_>,
+    credentials: &str,
+    sql: &str,
+    config: &str,
+) -> PyResult<Py<PyAny>> {
+    let config = decode_config(config)?;
+    let (dsn, user, password) = worker_credentials(credentials)?;
+    query_pyarrow_impl(py, &dsn, &user, &password, sql, &config)
+}
+
+// A Dask DataFrame with one lazily executed range query of `column` per partition. Only the
+// bounds and the column types are read here; the ranges are fetched by the Dask workers.
+pub fn query_dask(
+    py: Python<'_>,
+    conn: &IbarrowConnection,
+    sql: &str,
+    column: &str,
+    npartitions: usize,
+    dtype_backend: &str,
+    credentials_env: &str,
+) -> PyResult<Py<PyAny>> {
+    let backend = DtypeBackend::parse(Some(dtype_backend))
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    partition::validate(column, npartitions).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    validate_prefix(credentials_env)?;
+    let (dask, dataframe) = py
+        .import_bound("dask")
+        .and_then(|dask| Ok((dask, py.import_bound("dask.dataframe")?)))
+        .map_err(|_| {
+            PyImportError::new_err("query_dask needs dask (pip install \"dask[dataframe]\")")
+        })?;
+    let config = task_config(&conn.config);
+    let queries = plan(py, conn, sql, column, npartitions, &config)?;
+    let meta = query_pandas_impl(
+        py,
+        &conn.dsn,
+        &conn.user,
+        &conn.password,
+        &partition::empty_sql(sql),
+        &config,
+        backend,
+    )?;
+    let encoded = encode_config(&config)?;
+    let read = dask
+        .getattr("delayed")?
+        .call1((py.import_bound("ibarrow")?.getattr("_read_partition")?,))?;
+    let parts = PyList::empty_bound(py);
+    for query in &queries {
+        parts.append(read.call1((credentials_env, query, &encoded, dtype_backend))?)?;
+    }
+    let kwargs = PyDict::new_bound(py);
+    kwargs.set_item("meta", meta)?;
+    Ok(dataframe
+        .getattr("from_delayed")?
+        .call((parts,), Some(&kwargs))?
+        .unbind())
+}
+
+// A Ray Dataset with one Arrow block per range query of `column`. Every range becomes a Ray task
+// returning a pyarrow.Table, so the blocks are fetched in parallel by the Ray workers.
+pub fn to_ray_dataset(
+    py: Python<'_>,
+    conn: &IbarrowConnection,
+    sql: &str,
+    column: &str,
+    num_partitions: usize,
+    credentials_env: &str,
+) -> PyResult<Py<PyAny>> {
+    partition::validate(column, num_partitions)
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    validate_prefix(credentials_env)?;
+    let (ray, data) = py
+        .import_bound("ray")
+        .and_then(|ray| Ok((ray, py.import_bound("ray.data")?)))
+        .map_err(|_| {
+            PyImportError::new_err("to_ray_dataset needs ray (pip install \"ray[data]\")")
+        })?;
+    let config = task_config(&conn.config);
+    let queries = plan(py, conn, sql, column, num_partitions, &config)?;
+    let encoded = encode_config(&config)?;
+    let task = ray
+        .getattr("remote")?
+        .call1((py.eval_bound(RAY_TASK, None, None)?,))?;
+    let refs = PyList::empty_bound(py);
+    for query in &queries {
+        refs.append(task.call_method1("remote", (credentials_env, query, &encoded))?)?;
+    }
+    Ok(data.getattr("from_arrow_refs")?.call1((refs,))?.unbind())
+}
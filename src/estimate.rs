@@ -0,0 +1,253 @@
+use anyhow::{anyhow, Result};
+use ibarrow_core::lexer;
+use odbc_api::handles::StatementImpl;
+use odbc_api::{
+    Connection, Cursor, CursorImpl, DataType, Environment, Preallocated, ResultSetMetadata,
+};
+use std::time::Instant;
+
+use crate::migrate::fetch_texts;
+use crate::watchdog::{Watchdog, WatchedStatement};
+use crate::{
+    access, build_connection_string, build_reader, diagnostics, driverenv, retry, QueryConfig,
+};
+
+// Resource limits a scheduler wants an extract to stay within
+#[derive(Debug, Default)]
+pub struct Budget {
+    pub max_rows: Option<u64>,
+    pub max_bytes: Option<u64>,
+    pub max_seconds: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+pub struct Estimate {
+    pub plan: Option<String>,
+    pub columns: usize,
+    // Rows of each table the statement reads, from index statistics
+    pub table_rows: Vec<(String, Option<u64>)>,
+    // Exact, from COUNT(*)
+    pub rows: Option<u64>,
+    pub count_seconds: Option<f64>,
+    pub catalog_row_bytes: u64,
+    pub sampled_rows: usize,
+    pub sampled_row_bytes: Option<u64>,
+    pub sample_seconds: f64,
+    pub estimated_bytes: Option<u64>,
+    pub estimated_seconds: Option<f64>,
+    pub exceeded: Vec<&'static str>,
+}
+
+// Arrow bytes per value for a column as described by the driver. Text and binary columns count
+// their declared maximum, so this is an upper bound.
+fn column_width(data_type: &DataType, config: &QueryConfig) -> u64 {
+    let max_text = config.max_text_size.unwrap_or(65536) as u64;
+    let max_binary = config.max_binary_size.unwrap_or(65536) as u64;
+    let declared = |length: Option<std::num::NonZeroUsize>, max: u64| {
+        length.map_or(max, |l| (l.get() as u64).min(max)) + 4
+    };
+    match data_type {
+        DataType::Bit | DataType::TinyInt => 1,
+        DataType::SmallInt => 2,
+        DataType::Integer | DataType::Real | DataType::Date => 4,
+        DataType::BigInt
+        | DataType::Double
+        | DataType::Float { .. }
+        | DataType::Time { .. }
+        | DataType::Timestamp { .. } => 8,
+        DataType::Numeric { .. } | DataType::Decimal { .. } => 16,
+        DataType::Char { length }
+        | DataType::Varchar { length }
+        | DataType::WChar { length }
+        | DataType::WVarchar { length }
+        | DataType::LongVarchar { length }
+        | DataType::WLongVarchar { length } => declared(*length, max_text),
+        DataType::Binary { length }
+        | DataType::Varbinary { length }
+        | DataType::LongVarbinary { length } => declared(*length, max_binary),
+        other => other.display_size().map_or(8, |size| size.get() as u64),
+    }
+}
+
+// Smallest selectivity among a table's unique indices. The server keeps 1 / distinct keys per
+// index, so for a unique one that is 1 / rows as of the last SET STATISTICS or index rebuild.
+const UNIQUE_SELECTIVITY_SQL: &str = "SELECT MIN(RDB$STATISTICS) FROM RDB$INDICES \
+     WHERE RDB$RELATION_NAME = ? AND RDB$UNIQUE_FLAG = 1 AND RDB$STATISTICS > 0";
+
+// Rows of `table` by its index statistics, without reading the table. None for tables
+// without a unique index, with statistics never computed, or that are views.
+fn statistics_rows(conn: &Connection<'_>, table: &str) -> Option<u64> {
+    let texts = fetch_texts(conn, UNIQUE_SELECTIVITY_SQL, Some(table)).ok()?;
+    let selectivity: f64 = texts.first()?.parse().ok()?;
+    (selectivity > 0.0).then(|| (1.0 / selectivity).round() as u64)
+}
+
+// The query as a derived table, without a trailing terminator
+fn derived(sql: &str) -> String {
+    lexer::statement(sql).to_string()
+}
+
+fn execute<'c>(
+    conn: &'c Connection<'_>,
+    sql: &str,
+    watchdog: &Watchdog,
+) -> Result<CursorImpl<WatchedStatement<StatementImpl<'c>>>> {
+    watchdog.check()?;
+    let statement = watchdog.watch(conn.preallocate()?.into_handle());
+    // The handle comes straight from allocation, with nothing bound yet
+    unsafe { Preallocated::new(statement) }
+        .into_cursor(sql, ())?
+        .ok_or_else(|| anyhow!("Estimate query did not return a result set: {}", sql))
+}
+
+// Plan of the statement prepared on this attachment, read from the monitoring tables
+// (Firebird 3+). Older servers and InterBase have no such column.
+fn monitored_plan(conn: &Connection<'_>) -> Option<String> {
+    for column in ["MON$EXPLAINED_PLAN", "MON$PLAN"] {
+        let sql = format!(
+            "SELECT {} FROM MON$STATEMENTS WHERE MON$ATTACHMENT_ID = CURRENT_CONNECTION \
+             AND MON$STATE = 0 AND MON$SQL_TEXT NOT CONTAINING 'MON$STATEMENTS'",
+            column
+        );
+        let plans = fetch_texts(conn, &sql, None).unwrap_or_default();
+        if let Some(plan) = plans.into_iter().find(|plan| !plan.is_empty()) {
+            return Some(plan);
+        }
+    }
+    None
+}
+
+// Predicts the size and duration of an extract without running it in full: the plan, the rows
+// of the tables it reads by their index statistics, the row width declared by the result
+// columns and a timed sample of `sample_rows`. With `count` it also runs an exact COUNT(*) over
+// the query, which reads every row the query does and so costs about as much as its scan.
+#[allow(clippy::too_many_arguments)]
+pub fn estimate(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+    sample_rows: usize,
+    count: bool,
+    budget: &Budget,
+) -> Result<Estimate> {
+    access::check(sql, config)?;
+    let watchdog = Watchdog::start(config);
+    let result = estimate_watched(
+        dsn,
+        user,
+        password,
+        sql,
+        config,
+        sample_rows,
+        count,
+        budget,
+        &watchdog,
+    );
+    watchdog.finish(result)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn estimate_watched(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+    sample_rows: usize,
+    count: bool,
+    budget: &Budget,
+    watchdog: &Watchdog,
+) -> Result<Estimate> {
+    diagnostics::clear();
+    let env = Environment::new()?;
+    let conn_str = build_connection_string(dsn, user, password, config);
+    let conn = retry::with_retry(config, || driverenv::connect(&env, &conn_str, config))?;
+    let mut estimate = Estimate::default();
+
+    // Preparing compiles the plan and describes the result without executing anything
+    {
+        let mut prepared = conn.prepare(sql)?;
+        let columns = prepared.num_result_cols()? as u16;
+        for column in 1..=columns {
+            let data_type = prepared.col_data_type(column)?;
+            estimate.catalog_row_bytes += column_width(&data_type, config);
+        }
+        estimate.columns = columns as usize;
+        estimate.plan = monitored_plan(&conn);
+        estimate.table_rows = access::tables(sql)
+            .into_iter()
+            .map(|table| {
+                let rows = statistics_rows(&conn, &table);
+                (table, rows)
+            })
+            .collect();
+        // Servers without monitoring columns leave diagnostics that are not worth reporting
+        diagnostics::clear();
+    }
+
+    let inner = derived(sql);
+    if count {
+        let started = Instant::now();
+        let count_sql = format!("SELECT COUNT(*) FROM ({}) AS IBARROW_ESTIMATE", inner);
+        let mut cursor = execute(&conn, &count_sql, watchdog)?;
+        let mut text = Vec::new();
+        if let Some(mut row) = cursor.next_row()? {
+            if row.get_text(1, &mut text)? {
+                estimate.rows = String::from_utf8_lossy(&text).trim().parse().ok();
+            }
+        }
+        estimate.count_seconds = Some(started.elapsed().as_secs_f64());
+    }
+
+    if sample_rows > 0 {
+        let started = Instant::now();
+        let sample_sql = format!(
+            "SELECT FIRST {} * FROM ({}) AS IBARROW_SAMPLE",
+            sample_rows, inner
+        );
+        let mut sampled_bytes = 0;
+        for batch in build_reader(execute(&conn, &sample_sql, watchdog)?, config)? {
+            watchdog.check()?;
+            let batch = batch.map_err(|e| anyhow!("ERROR: Failed to read batch: {}", e))?;
+            estimate.sampled_rows += batch.num_rows();
+            sampled_bytes += batch.get_array_memory_size() as u64;
+        }
+        estimate.sample_seconds = started.elapsed().as_secs_f64();
+        if estimate.sampled_rows > 0 {
+            estimate.sampled_row_bytes = Some(sampled_bytes / estimate.sampled_rows as u64);
+        }
+    }
+
+    if let Some(rows) = estimate.rows {
+        let row_bytes = estimate
+            .sampled_row_bytes
+            .unwrap_or(estimate.catalog_row_bytes);
+        estimate.estimated_bytes = Some(rows * row_bytes);
+        if estimate.sampled_rows > 0 {
+            let per_row = estimate.sample_seconds / estimate.sampled_rows as f64;
+            estimate.estimated_seconds = Some(per_row * rows as f64);
+        }
+    }
+
+    // Unknown figures never count against a budget
+    let over = |value: Option<f64>, limit: Option<f64>| match (value, limit) {
+        (Some(value), Some(limit)) => value > limit,
+        _ => false,
+    };
+    if over(
+        estimate.rows.map(|r| r as f64),
+        budget.max_rows.map(|r| r as f64),
+    ) {
+        estimate.exceeded.push("max_rows");
+    }
+    let bytes = estimate.estimated_bytes.map(|b| b as f64);
+    if over(bytes, budget.max_bytes.map(|b| b as f64)) {
+        estimate.exceeded.push("max_bytes");
+    }
+    if over(estimate.estimated_seconds, budget.max_seconds) {
+        estimate.exceeded.push("max_seconds");
+    }
+    Ok(estimate)
+}
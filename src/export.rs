@@ -0,0 +1,1369 @@
+use anyhow::{anyhow, Result};
+use arrow::array::UInt32Array;
+use arrow::compute::{cast, take_record_batch};
+use arrow::csv::{self, WriterBuilder};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::json;
+use arrow::record_batch::RecordBatch;
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use arrow_ipc::writer::{FileWriter, IpcWriteOptions};
+use chrono::format::{Item, StrftimeItems};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, Encoding, ZstdLevel};
+use parquet::file::properties::{WriterProperties, WriterPropertiesBuilder};
+use parquet::schema::types::ColumnPath;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::objstore::{self, ObjectWriter};
+use crate::sidecar::{Recorded, Sidecar};
+use crate::sink::BatchSink;
+use crate::{fetch_into, find_column, write_arrow_ipc_impl, QueryConfig, IPC_WRITE_BUFFER};
+
+// Parquet codec by name, as accepted by query_to_parquet. Only ZSTD takes a level (1-22).
+pub fn compression(name: &str, level: Option<i32>) -> Result<Compression> {
+    let compression = match name.to_lowercase().as_str() {
+        "snappy" => Compression::SNAPPY,
+        "zstd" => Compression::ZSTD(match level {
+            Some(level) => ZstdLevel::try_new(level)
+                .map_err(|_| anyhow!("ZSTD compression level must be between 1 and 22"))?,
+            None => ZstdLevel::default(),
+        }),
+        "lz4" => Compression::LZ4_RAW,
+        "none" | "uncompressed" => Compression::UNCOMPRESSED,
+        other => {
+            return Err(anyhow!(
+                "Unknown Parquet compression '{}': expected 'snappy', 'zstd', 'lz4' or 'none'",
+                other
+            ))
+        }
+    };
+    if level.is_some() && !matches!(compression, Compression::ZSTD(_)) {
+        return Err(anyhow!("Only 'zstd' compression takes a level"));
+    }
+    Ok(compression)
+}
+
+// Parquet encoding by name. Dictionary encoding is switched on with `dictionary` instead.
+fn encoding(name: &str) -> Result<Encoding> {
+    match name.to_lowercase().as_str() {
+        "plain" => Ok(Encoding::PLAIN),
+        "byte_stream_split" => Ok(Encoding::BYTE_STREAM_SPLIT),
+        "delta_binary_packed" => Ok(Encoding::DELTA_BINARY_PACKED),
+        "delta_length_byte_array" => Ok(Encoding::DELTA_LENGTH_BYTE_ARRAY),
+        "delta_byte_array" => Ok(Encoding::DELTA_BYTE_ARRAY),
+        other => Err(anyhow!(
+            "Unknown Parquet encoding '{}': expected 'plain', 'byte_stream_split', \
+             'delta_binary_packed', 'delta_length_byte_array' or 'delta_byte_array'",
+            other
+        )),
+    }
+}
+
+// Settings of one column in query_to_parquet's `columns`, overriding the file-wide ones
+#[derive(Debug, Default, Clone)]
+pub struct ColumnOptions {
+    compression: Option<Compression>,
+    // Fallback encoding, used when dictionary encoding is off or its dictionary grows too big
+    encoding: Option<Encoding>,
+    dictionary: Option<bool>,
+}
+
+// Reads {column: {"compression", "level", "encoding", "dictionary"}}
+pub fn column_options(
+    columns: Option<&Bound<'_, PyDict>>,
+) -> PyResult<Vec<(String, ColumnOptions)>> {
+    let Some(columns) = columns else {
+        return Ok(Vec::new());
+    };
+    let invalid = |e: anyhow::Error| PyValueError::new_err(e.to_string());
+    let mut parsed = Vec::new();
+    for (name, settings) in columns.iter() {
+        let name: String = name.extract()?;
+        let settings = settings.downcast::<PyDict>().map_err(|_| {
+            PyValueError::new_err(format!("Settings for column '{}' must be a dict", name))
+        })?;
+        let mut codec: Option<String> = None;
+        let mut level: Option<i32> = None;
+        let mut options = ColumnOptions::default();
+        for (key, value) in settings.iter() {
+            match key.extract::<String>()?.as_str() {
+                "compression" => codec = Some(value.extract()?),
+                "level" => level = Some(value.extract()?),
+                "encoding" => {
+                    options.encoding = Some(encoding(&value.extract::<String>()?).map_err(invalid)?)
+                }
+                "dictionary" => options.dictionary = Some(value.extract()?),
+                other => {
+                    return Err(PyValueError::new_err(format!(
+                        "Unknown Parquet setting '{}' for column '{}': expected 'compression', \
+                         'level', 'encoding' or 'dictionary'",
+                        other, name
+                    )))
+                }
+            }
+        }
+        options.compression = match (codec, level) {
+            (Some(codec), level) => Some(compression(&codec, level).map_err(invalid)?),
+            (None, Some(_)) => {
+                return Err(PyValueError::new_err(format!(
+                    "Column '{}' sets a compression level without a compression",
+                    name
+                )))
+            }
+            (None, None) => None,
+        };
+        parsed.push((name, options));
+    }
+    Ok(parsed)
+}
+
+// Applies `columns` to the columns of `schema`, matching names as find_column does
+fn with_columns(
+    mut builder: WriterPropertiesBuilder,
+    schema: &SchemaRef,
+    columns: &[(String, ColumnOptions)],
+) -> Result<WriterPropertiesBuilder> {
+    for (name, options) in columns {
+        let index = find_column(schema, name)
+            .ok_or_else(|| anyhow!("Parquet column '{}' is not in the result set", name))?;
+        let path = ColumnPath::from(schema.field(index).name().as_str());
+        if let Some(compression) = options.compression {
+            builder = builder.set_column_compression(path.clone(), compression);
+        }
+        if let Some(encoding) = options.encoding {
+            builder = builder.set_column_encoding(path.clone(), encoding);
+        }
+        if let Some(dictionary) = options.dictionary {
+            builder = builder.set_column_dictionary_enabled(path, dictionary);
+        }
+    }
+    Ok(builder)
+}
+
+// Where a single-file export writes: a staging file next to the destination path, or an
+// object store upload
+enum Destination {
+    File(BufWriter<File>),
+    Object(ObjectWriter),
+}
+
+impl Write for Destination {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Destination::File(file) => file.write(buf),
+            Destination::Object(upload) => upload.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Destination::File(file) => file.flush(),
+            Destination::Object(upload) => upload.flush(),
+        }
+    }
+}
+
+impl Destination {
+    // Bytes written so far, buffered ones included. Rolled-over exports, the only users, write
+    // local files.
+    fn size(&self) -> u64 {
+        match self {
+            Destination::File(file) => {
+                let flushed = file.get_ref().metadata().map_or(0, |meta| meta.len());
+                flushed + file.buffer().len() as u64
+            }
+            Destination::Object(_) => 0,
+        }
+    }
+}
+
+// A sink encoding one file into a Destination, which it hands back once finished
+trait FileSink: BatchSink {
+    fn output(&mut self) -> Option<Destination>;
+}
+
+// A file sink that knows how large its file has grown, so exports can roll over by size
+trait SizedSink: FileSink {
+    fn size(&self) -> u64;
+}
+
+// Runs the query of an export into `sink`, with the sidecar, if any, watching the batches
+#[allow(clippy::too_many_arguments)]
+fn fetch_recorded(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+    sink: &mut dyn BatchSink,
+    sidecar: Option<&mut Sidecar>,
+) -> Result<usize> {
+    let mut sink = Recorded {
+        inner: sink,
+        sidecar,
+    };
+    fetch_into(
+        dsn,
+        user,
+        password,
+        sql,
+        &[],
+        config,
+        &mut sink,
+        &mut |_| Ok(()),
+    )
+}
+
+// Streams the result of `sql` through the sink `sink_for` builds over the destination for
+// `path`. Object store URLs are uploaded directly. Local files are written next to `path`
+// first and moved into place once complete, so a failed export leaves any previous file
+// intact. The sidecar, if any, is written once the file is in place.
+#[allow(clippy::too_many_arguments)]
+fn export<S: FileSink>(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+    path: &Path,
+    format: &str,
+    mut sidecar: Option<Sidecar>,
+    sink_for: impl FnOnce(Destination) -> S,
+) -> Result<usize> {
+    let rows = export_file(
+        dsn,
+        user,
+        password,
+        sql,
+        config,
+        path,
+        format,
+        sidecar.as_mut(),
+        sink_for,
+    )?;
+    if let Some(sidecar) = &sidecar {
+        let files = [(path.display().to_string(), rows)];
+        sidecar.write(path, &format.to_lowercase(), sql, config, &files)?;
+    }
+    Ok(rows)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn export_file<S: FileSink>(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+    path: &Path,
+    format: &str,
+    sidecar: Option<&mut Sidecar>,
+    sink_for: impl FnOnce(Destination) -> S,
+) -> Result<usize> {
+    if let Some(url) = objstore::url(path) {
+        let mut sink = sink_for(Destination::Object(ObjectWriter::create(url, config)?));
+        let rows = fetch_recorded(dsn, user, password, sql, config, &mut sink, sidecar)?;
+        match sink.output() {
+            Some(Destination::Object(upload)) => upload.complete()?,
+            _ => return Err(anyhow!("ERROR: {} file was not finished", format)),
+        }
+        return Ok(rows);
+    }
+    let staging = format!("{}.tmp", path.display());
+    let file = File::create(&staging)
+        .map_err(|e| anyhow!("Failed to create {} file {}: {}", format, staging, e))?;
+    let mut sink = sink_for(Destination::File(BufWriter::new(file)));
+    let rows =
+        fetch_recorded(dsn, user, password, sql, config, &mut sink, sidecar).and_then(|rows| {
+            // Close the file before moving it
+            drop(sink.output());
+            fs::rename(&staging, path).map_err(|e| {
+                anyhow!(
+                    "Failed to move {} file into {}: {}",
+                    format,
+                    path.display(),
+                    e
+                )
+            })?;
+            Ok(rows)
+        });
+    if rows.is_err() {
+        drop(sink);
+        let _ = fs::remove_file(&staging);
+    }
+    rows
+}
+
+// Writes the Arrow IPC stream of `sql` to an object store URL or a local file, for
+// query_to_ipc and the CLI. Like the other single-file exports, a local file is written next
+// to `path` and moved into place once complete, so a failed query leaves any previous file
+// intact.
+pub fn ipc_to_path(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+    path: &Path,
+) -> Result<usize> {
+    if let Some(url) = objstore::url(path) {
+        let mut out = ObjectWriter::create(url, config)?;
+        let rows = write_arrow_ipc_impl(dsn, user, password, sql, &[], config, &mut out)?;
+        out.complete()?;
+        return Ok(rows);
+    }
+    let staging = format!("{}.tmp", path.display());
+    let file = File::create(&staging)
+        .map_err(|e| anyhow!("Failed to create IPC file {}: {}", staging, e))?;
+    let mut out = BufWriter::with_capacity(IPC_WRITE_BUFFER, file);
+    let rows =
+        write_arrow_ipc_impl(dsn, user, password, sql, &[], config, &mut out).and_then(|rows| {
+            out.flush()?;
+            Ok(rows)
+        });
+    // Close the file before moving or removing it
+    drop(out);
+    let rows = rows.and_then(|rows| {
+        fs::rename(&staging, path)
+            .map_err(|e| anyhow!("Failed to move IPC file into {}: {}", path.display(), e))?;
+        Ok(rows)
+    });
+    if rows.is_err() {
+        let _ = fs::remove_file(&staging);
+    }
+    rows
+}
+
+// Encodes the batches into a Parquet file as they arrive
+pub struct ParquetSink<'a, W: Write + Send> {
+    file: Option<W>,
+    compression: Compression,
+    columns: &'a [(String, ColumnOptions)],
+    writer: Option<ArrowWriter<W>>,
+}
+
+impl<'a, W: Write + Send> ParquetSink<'a, W> {
+    pub fn new(file: W, compression: Compression, columns: &'a [(String, ColumnOptions)]) -> Self {
+        Self {
+            file: Some(file),
+            compression,
+            columns,
+            writer: None,
+        }
+    }
+}
+
+impl<W: Write + Send> BatchSink for ParquetSink<'_, W> {
+    fn begin(&mut self, schema: &SchemaRef) -> Result<()> {
+        let file = self
+            .file
+            .take()
+            .ok_or_else(|| anyhow!("ERROR: Parquet file was already started"))?;
+        let builder = WriterProperties::builder().set_compression(self.compression);
+        let properties = with_columns(builder, schema, self.columns)?.build();
+        let writer = ArrowWriter::try_new(file, schema.clone(), Some(properties))?;
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| anyhow!("ERROR: Parquet file was not started"))?;
+        Ok(writer.write(batch)?)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(writer) = self.writer.take() {
+            let mut file = writer.into_inner()?;
+            file.flush()?;
+            self.file = Some(file);
+        }
+        Ok(())
+    }
+}
+
+impl FileSink for ParquetSink<'_, Destination> {
+    fn output(&mut self) -> Option<Destination> {
+        self.file.take()
+    }
+}
+
+impl SizedSink for ParquetSink<'_, Destination> {
+    // Row groups are buffered in memory until flushed, so their estimated size counts too
+    fn size(&self) -> u64 {
+        self.writer.as_ref().map_or(0, |writer| {
+            (writer.bytes_written() + writer.in_progress_size()) as u64
+        })
+    }
+}
+
+// Encodes the batches as CSV rows as they arrive; the header comes from the schema
+pub struct CsvSink<W: Write> {
+    out: Option<W>,
+    builder: Option<WriterBuilder>,
+    writer: Option<csv::Writer<W>>,
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(out: W, builder: WriterBuilder) -> Self {
+        Self {
+            out: Some(out),
+            builder: Some(builder),
+            writer: None,
+        }
+    }
+}
+
+impl<W: Write> BatchSink for CsvSink<W> {
+    fn begin(&mut self, _schema: &SchemaRef) -> Result<()> {
+        let (out, builder) = self
+            .out
+            .take()
+            .zip(self.builder.take())
+            .ok_or_else(|| anyhow!("ERROR: CSV file was already started"))?;
+        self.writer = Some(builder.build(out));
+        Ok(())
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| anyhow!("ERROR: CSV file was not started"))?;
+        Ok(writer.write(batch)?)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(writer) = self.writer.take() {
+            let mut out = writer.into_inner();
+            out.flush()?;
+            self.out = Some(out);
+        }
+        Ok(())
+    }
+}
+
+impl FileSink for CsvSink<Destination> {
+    fn output(&mut self) -> Option<Destination> {
+        self.out.take()
+    }
+}
+
+// How query_to_ndjson writes timestamps
+#[derive(Debug, Clone)]
+pub enum TimestampFormat {
+    // RFC 3339 strings
+    Iso,
+    // Integer seconds, milliseconds or microseconds since the epoch
+    Epoch(TimeUnit),
+    // Strings in a strftime pattern
+    Pattern(String),
+}
+
+pub fn timestamp_format(name: &str) -> Result<TimestampFormat> {
+    Ok(match name {
+        "iso" => TimestampFormat::Iso,
+        "epoch_s" => TimestampFormat::Epoch(TimeUnit::Second),
+        "epoch_ms" => TimestampFormat::Epoch(TimeUnit::Millisecond),
+        "epoch_us" => TimestampFormat::Epoch(TimeUnit::Microsecond),
+        pattern if pattern.contains('%') => {
+            // The JSON writer copies formatted timestamps into strings without escaping them
+            if pattern.contains(['"', '\\']) || pattern.chars().any(char::is_control) {
+                return Err(anyhow!(
+                    "timestamp_format must not contain quotes, backslashes or control characters"
+                ));
+            }
+            if StrftimeItems::new(pattern).any(|item| item == Item::Error) {
+                return Err(anyhow!("Invalid strftime pattern '{}'", pattern));
+            }
+            TimestampFormat::Pattern(pattern.to_string())
+        }
+        other => {
+            return Err(anyhow!(
+                "Unknown timestamp_format '{}': expected 'iso', 'epoch_s', 'epoch_ms', \
+                 'epoch_us' or a strftime pattern",
+                other
+            ))
+        }
+    })
+}
+
+// How query_to_ndjson writes decimals
+#[derive(Debug, Clone, Copy)]
+pub enum DecimalFormat {
+    // JSON numbers with every digit
+    Number,
+    // Strings, for consumers that would round large numbers
+    String,
+    // Nearest double
+    Float,
+}
+
+pub fn decimal_format(name: &str) -> Result<DecimalFormat> {
+    match name {
+        "number" => Ok(DecimalFormat::Number),
+        "string" => Ok(DecimalFormat::String),
+        "float" => Ok(DecimalFormat::Float),
+        other => Err(anyhow!(
+            "Unknown decimal_format '{}': expected 'number', 'string' or 'float'",
+            other
+        )),
+    }
+}
+
+// Encodes the batches as one JSON object per row as they arrive
+pub struct NdjsonSink<W: Write> {
+    out: Option<W>,
+    timestamps: TimestampFormat,
+    decimals: DecimalFormat,
+    writer: Option<json::LineDelimitedWriter<W>>,
+}
+
+impl<W: Write> NdjsonSink<W> {
+    pub fn new(out: W, timestamps: TimestampFormat, decimals: DecimalFormat) -> Self {
+        Self {
+            out: Some(out),
+            timestamps,
+            decimals,
+            writer: None,
+        }
+    }
+
+    // Applies the timestamp and decimal formats the JSON writer has no option for
+    fn convert(&self, batch: &RecordBatch) -> Result<RecordBatch> {
+        let mut fields = Vec::with_capacity(batch.num_columns());
+        let mut columns = Vec::with_capacity(batch.num_columns());
+        for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+            let column = match (field.data_type(), &self.timestamps, self.decimals) {
+                (DataType::Timestamp(_, tz), TimestampFormat::Epoch(unit), _) => {
+                    let column = cast(column, &DataType::Timestamp(*unit, tz.clone()))?;
+                    cast(&column, &DataType::Int64)?
+                }
+                (
+                    DataType::Decimal32(..)
+                    | DataType::Decimal64(..)
+                    | DataType::Decimal128(..)
+                    | DataType::Decimal256(..),
+                    _,
+                    DecimalFormat::String,
+                ) => cast(column, &DataType::Utf8)?,
+                (
+                    DataType::Decimal32(..)
+                    | DataType::Decimal64(..)
+                    | DataType::Decimal128(..)
+                    | DataType::Decimal256(..),
+                    _,
+                    DecimalFormat::Float,
+                ) => cast(column, &DataType::Float64)?,
+                _ => column.clone(),
+            };
+            fields.push(Field::clone(field).with_data_type(column.data_type().clone()));
+            columns.push(column);
+        }
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(fields)),
+            columns,
+        )?)
+    }
+}
+
+impl<W: Write> BatchSink for NdjsonSink<W> {
+    fn begin(&mut self, _schema: &SchemaRef) -> Result<()> {
+        let out = self
+            .out
+            .take()
+            .ok_or_else(|| anyhow!("ERROR: NDJSON file was already started"))?;
+        let mut builder = json::WriterBuilder::new();
+        if let TimestampFormat::Pattern(pattern) = &self.timestamps {
+            builder = builder
+                .with_timestamp_format(pattern.clone())
+                .with_timestamp_tz_format(pattern.clone());
+        }
+        self.writer = Some(builder.build(out));
+        Ok(())
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        let batch = self.convert(batch)?;
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| anyhow!("ERROR: NDJSON file was not started"))?;
+        Ok(writer.write(&batch)?)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.finish()?;
+            let mut out = writer.into_inner();
+            out.flush()?;
+            self.out = Some(out);
+        }
+        Ok(())
+    }
+}
+
+impl FileSink for NdjsonSink<Destination> {
+    fn output(&mut self) -> Option<Destination> {
+        self.out.take()
+    }
+}
+
+// Writes the batches into an Arrow IPC file (Feather v2), whose footer indexes every batch
+struct FeatherSink {
+    out: Option<Destination>,
+    options: IpcWriteOptions,
+    writer: Option<FileWriter<Destination>>,
+}
+
+impl BatchSink for FeatherSink {
+    fn begin(&mut self, schema: &SchemaRef) -> Result<()> {
+        let out = self
+            .out
+            .take()
+            .ok_or_else(|| anyhow!("ERROR: Arrow file was already started"))?;
+        let writer = FileWriter::try_new_with_options(out, schema, self.options.clone())?;
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| anyhow!("ERROR: Arrow file was not started"))?;
+        Ok(writer.write(batch)?)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.finish()?;
+            let mut out = writer.into_inner()?;
+            out.flush()?;
+            self.out = Some(out);
+        }
+        Ok(())
+    }
+}
+
+impl FileSink for FeatherSink {
+    fn output(&mut self) -> Option<Destination> {
+        self.out.take()
+    }
+}
+
+impl SizedSink for FeatherSink {
+    fn size(&self) -> u64 {
+        self.writer
+            .as_ref()
+            .map_or(0, |writer| writer.get_ref().size())
+    }
+}
+
+// When a rolled-over export starts its next file; neither limit set writes a single file
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rollover {
+    pub max_rows: Option<u64>,
+    pub max_bytes: Option<u64>,
+}
+
+impl Rollover {
+    pub fn new(max_rows: Option<u64>, max_bytes: Option<u64>) -> Result<Self> {
+        if max_rows == Some(0) || max_bytes == Some(0) {
+            return Err(anyhow!(
+                "max_file_rows and max_file_bytes must be greater than 0"
+            ));
+        }
+        Ok(Self {
+            max_rows,
+            max_bytes,
+        })
+    }
+
+    fn is_set(&self) -> bool {
+        self.max_rows.is_some() || self.max_bytes.is_some()
+    }
+}
+
+// Name of the `index`th file of a rolled-over export
+fn part_name(index: usize, extension: &str) -> String {
+    format!("part-{:04}.{}", index, extension)
+}
+
+// Writes the batches into dir/part-0000.<extension>, part-0001.<extension>, ..., starting the
+// next file once the current one holds `max_rows` rows or has grown to `max_bytes`. Batches are
+// split to keep the row limit exact; the byte limit is checked after every batch.
+struct RolloverSink<'a, S, F> {
+    dir: &'a Path,
+    extension: &'a str,
+    limits: Rollover,
+    sink_for: F,
+    schema: Option<SchemaRef>,
+    current: Option<S>,
+    // Rows in the current file
+    rows: u64,
+    // Staging file of every file started, moved into place once the export succeeded, and
+    // the rows written to it
+    staged: Vec<(PathBuf, usize)>,
+}
+
+impl<S: SizedSink, F: FnMut(Destination) -> S> RolloverSink<'_, S, F> {
+    fn start_part(&mut self) -> Result<&mut S> {
+        if self.current.is_none() {
+            let schema = self
+                .schema
+                .clone()
+                .ok_or_else(|| anyhow!("ERROR: Rolled-over export was not started"))?;
+            let staging = self.dir.join(format!(
+                "{}.tmp",
+                part_name(self.staged.len(), self.extension)
+            ));
+            let file = File::create(&staging)
+                .map_err(|e| anyhow!("Failed to create file {}: {}", staging.display(), e))?;
+            self.staged.push((staging, 0));
+            let mut sink = (self.sink_for)(Destination::File(BufWriter::new(file)));
+            sink.begin(&schema)?;
+            self.current = Some(sink);
+            self.rows = 0;
+        }
+        self.current
+            .as_mut()
+            .ok_or_else(|| anyhow!("ERROR: Rolled-over export has no open file"))
+    }
+
+    fn finish_part(&mut self) -> Result<()> {
+        if let Some(mut sink) = self.current.take() {
+            sink.finish()?;
+            // Close the file before it is moved
+            drop(sink.output());
+        }
+        Ok(())
+    }
+
+    // Removes the files of a failed export
+    fn discard(&mut self) {
+        drop(self.current.take());
+        for (staging, _) in &self.staged {
+            let _ = fs::remove_file(staging);
+        }
+    }
+}
+
+impl<S: SizedSink, F: FnMut(Destination) -> S> BatchSink for RolloverSink<'_, S, F> {
+    fn begin(&mut self, schema: &SchemaRef) -> Result<()> {
+        self.schema = Some(schema.clone());
+        Ok(())
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        let mut offset = 0;
+        while offset < batch.num_rows() {
+            let room = self
+                .limits
+                .max_rows
+                .map_or(u64::MAX, |max| max.saturating_sub(self.rows));
+            let length = (batch.num_rows() - offset).min(room.try_into().unwrap_or(usize::MAX));
+            let sink = self.start_part()?;
+            sink.write(&batch.slice(offset, length))?;
+            let size = sink.size();
+            self.rows += length as u64;
+            if let Some((_, rows)) = self.staged.last_mut() {
+                *rows += length;
+            }
+            offset += length;
+            let full = self.limits.max_rows.is_some_and(|max| self.rows >= max)
+                || self.limits.max_bytes.is_some_and(|max| size >= max);
+            if full {
+                self.finish_part()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        // An empty result still gets one file carrying the schema
+        if self.staged.is_empty() {
+            self.start_part()?;
+        }
+        self.finish_part()?;
+        for (index, (staging, _)) in self.staged.iter().enumerate() {
+            let path = self.dir.join(part_name(index, self.extension));
+            fs::rename(staging, &path)
+                .map_err(|e| anyhow!("Failed to move file into {}: {}", path.display(), e))?;
+        }
+        // Parts beyond this export's last one belong to an earlier, larger export
+        let mut stale = self.staged.len();
+        while fs::remove_file(self.dir.join(part_name(stale, self.extension))).is_ok() {
+            stale += 1;
+        }
+        eprintln!(
+            "DEBUG: Wrote {} files under {}",
+            self.staged.len(),
+            self.dir.display()
+        );
+        Ok(())
+    }
+}
+
+// Streams the result of `sql` into numbered files in the directory `dir`, rolling over to the
+// next file at the `limits`. The files only replace earlier ones once the export succeeded.
+#[allow(clippy::too_many_arguments)]
+fn export_parts<S: SizedSink>(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+    dir: &Path,
+    extension: &str,
+    limits: Rollover,
+    mut sidecar: Option<Sidecar>,
+    sink_for: impl FnMut(Destination) -> S,
+) -> Result<usize> {
+    if objstore::url(dir).is_some() {
+        return Err(anyhow!(
+            "max_file_rows and max_file_bytes write to a local directory; object store URLs \
+             are not supported"
+        ));
+    }
+    fs::create_dir_all(dir)
+        .map_err(|e| anyhow!("Failed to create directory {}: {}", dir.display(), e))?;
+    let mut sink = RolloverSink {
+        dir,
+        extension,
+        limits,
+        sink_for,
+        schema: None,
+        current: None,
+        rows: 0,
+        staged: Vec::new(),
+    };
+    let rows = fetch_recorded(
+        dsn,
+        user,
+        password,
+        sql,
+        config,
+        &mut sink,
+        sidecar.as_mut(),
+    );
+    if rows.is_err() {
+        sink.discard();
+    }
+    let rows = rows?;
+    if let Some(sidecar) = &sidecar {
+        let files: Vec<(String, usize)> = sink
+            .staged
+            .iter()
+            .enumerate()
+            .map(|(index, (_, rows))| {
+                let path = dir.join(part_name(index, extension));
+                (path.display().to_string(), *rows)
+            })
+            .collect();
+        sidecar.write(dir, extension, sql, config, &files)?;
+    }
+    Ok(rows)
+}
+
+// Directory name Hive uses for NULL partition values
+const HIVE_NULL: &str = "__HIVE_DEFAULT_PARTITION__";
+
+// Characters Hive percent-encodes in partition directory names, besides control characters
+const HIVE_ESCAPED: &str = "\"#%'*/:=?\\{[]^";
+
+fn hive_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_control() || HIVE_ESCAPED.contains(c) {
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+// Most partition files a dataset export keeps open at once
+const MAX_OPEN_PARTITIONS: usize = 64;
+
+// One file of a partition, written to `staging` and moved to `path` once the export succeeded
+struct HivePart {
+    staging: PathBuf,
+    path: PathBuf,
+    rows: usize,
+}
+
+struct HiveFile {
+    dir: PathBuf,
+    // Files written for this partition, the open one last
+    parts: Vec<HivePart>,
+    writer: Option<ArrowWriter<BufWriter<File>>>,
+    // When the partition was last written, to close the least recently used file first
+    used: u64,
+}
+
+impl HiveFile {
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| anyhow!("ERROR: Parquet dataset was already finished"))?;
+        writer.write(batch)?;
+        if let Some(part) = self.parts.last_mut() {
+            part.rows += batch.num_rows();
+        }
+        Ok(())
+    }
+}
+
+// Splits the batches on the `partition_by` columns into a Hive-style directory layout,
+// dir/YEAR=2024/REGION=EU/part-....parquet, with one file per partition for this export.
+// The partition columns live in the directory names and are left out of the files.
+struct HiveSink<'a> {
+    dir: &'a Path,
+    partition_by: &'a [String],
+    compression: Compression,
+    columns: &'a [(String, ColumnOptions)],
+    // Shared by every file of one export, so a later export adds files instead of replacing
+    file_stem: String,
+    // Result columns holding the partition values, in partition_by order, and the others
+    keys: Vec<usize>,
+    values: Vec<usize>,
+    names: Vec<String>,
+    schema: Option<SchemaRef>,
+    properties: Option<WriterProperties>,
+    files: HashMap<String, HiveFile>,
+    // Counts writes, for HiveFile.used
+    clock: u64,
+}
+
+impl HiveSink<'_> {
+    // The partition's open file. At most MAX_OPEN_PARTITIONS files stay open: the least
+    // recently written one is closed to make room, and its partition starts another file,
+    // part-<id>-1.parquet, part-<id>-2.parquet, ..., if it comes up again.
+    fn file(&mut self, partition: &str) -> Result<&mut HiveFile> {
+        self.clock += 1;
+        let open = self
+            .files
+            .get(partition)
+            .and_then(|file| file.writer.as_ref())
+            .is_some();
+        if !open {
+            self.close_least_recent()?;
+            let schema = self
+                .schema
+                .clone()
+                .ok_or_else(|| anyhow!("ERROR: Parquet dataset was not started"))?;
+            let dir = self.dir.join(partition);
+            let file = self
+                .files
+                .entry(partition.to_string())
+                .or_insert_with(|| HiveFile {
+                    dir,
+                    parts: Vec::new(),
+                    writer: None,
+                    used: 0,
+                });
+            fs::create_dir_all(&file.dir)
+                .map_err(|e| anyhow!("Failed to create partition {}: {}", file.dir.display(), e))?;
+            let name = match file.parts.len() {
+                0 => format!("{}.parquet", self.file_stem),
+                n => format!("{}-{}.parquet", self.file_stem, n),
+            };
+            let staging = file.dir.join(format!("{}.tmp", name));
+            let created = File::create(&staging).map_err(|e| {
+                anyhow!("Failed to create Parquet file {}: {}", staging.display(), e)
+            })?;
+            file.writer = Some(ArrowWriter::try_new(
+                BufWriter::new(created),
+                schema,
+                self.properties.clone(),
+            )?);
+            file.parts.push(HivePart {
+                path: file.dir.join(name),
+                staging,
+                rows: 0,
+            });
+        }
+        let file = self
+            .files
+            .get_mut(partition)
+            .ok_or_else(|| anyhow!("ERROR: Parquet partition {} was not opened", partition))?;
+        file.used = self.clock;
+        Ok(file)
+    }
+
+    // Closes the least recently written file once MAX_OPEN_PARTITIONS are open
+    fn close_least_recent(&mut self) -> Result<()> {
+        let open = self
+            .files
+            .values()
+            .filter(|file| file.writer.is_some())
+            .count();
+        if open < MAX_OPEN_PARTITIONS {
+            return Ok(());
+        }
+        let oldest = self
+            .files
+            .values_mut()
+            .filter(|file| file.writer.is_some())
+            .min_by_key(|file| file.used);
+        if let Some(writer) = oldest.and_then(|file| file.writer.take()) {
+            writer.close()?;
+        }
+        Ok(())
+    }
+
+    // Every file written, with its final path and row count
+    fn parts(&self) -> impl Iterator<Item = &HivePart> {
+        self.files.values().flat_map(|file| &file.parts)
+    }
+
+    // Removes the files of a failed export
+    fn discard(&mut self) {
+        for file in self.files.values_mut() {
+            drop(file.writer.take());
+            for part in &file.parts {
+                let _ = fs::remove_file(&part.staging);
+            }
+        }
+    }
+}
+
+impl BatchSink for HiveSink<'_> {
+    fn begin(&mut self, schema: &SchemaRef) -> Result<()> {
+        for name in self.partition_by {
+            let index = find_column(schema, name).ok_or_else(|| {
+                anyhow!("partition_by column '{}' is not in the result set", name)
+            })?;
+            if self.keys.contains(&index) {
+                return Err(anyhow!("partition_by lists column '{}' twice", name));
+            }
+            self.keys.push(index);
+            self.names.push(schema.field(index).name().clone());
+        }
+        self.values = (0..schema.fields().len())
+            .filter(|index| !self.keys.contains(index))
+            .collect();
+        if self.values.is_empty() {
+            return Err(anyhow!(
+                "partition_by must leave at least one column to write to the files"
+            ));
+        }
+        let schema = Arc::new(schema.project(&self.values)?);
+        let builder = WriterProperties::builder().set_compression(self.compression);
+        self.properties = Some(with_columns(builder, &schema, self.columns)?.build());
+        self.schema = Some(schema);
+        Ok(())
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        let options = FormatOptions::default();
+        let formatters = self
+            .keys
+            .iter()
+            .map(|&index| ArrayFormatter::try_new(batch.column(index), &options))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Row indices of each partition, in order of first appearance
+        let mut order: Vec<String> = Vec::new();
+        let mut rows: HashMap<String, Vec<u32>> = HashMap::new();
+        for row in 0..batch.num_rows() {
+            let partition = self
+                .keys
+                .iter()
+                .zip(&formatters)
+                .zip(&self.names)
+                .map(|((&index, formatter), name)| {
+                    let value = if batch.column(index).is_null(row) {
+                        HIVE_NULL.to_string()
+                    } else {
+                        hive_escape(&formatter.value(row).to_string())
+                    };
+                    format!("{}={}", hive_escape(name), value)
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            rows.entry(partition.clone())
+                .or_insert_with(|| {
+                    order.push(partition);
+                    Vec::new()
+                })
+                .push(row as u32);
+        }
+        let values = batch.project(&self.values)?;
+        for partition in order {
+            let indices = UInt32Array::from(rows.remove(&partition).unwrap_or_default());
+            let part = take_record_batch(&values, &indices)?;
+            self.file(&partition)?.write(&part)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for file in self.files.values_mut() {
+            if let Some(writer) = file.writer.take() {
+                writer.close()?;
+            }
+        }
+        for part in self.parts() {
+            fs::rename(&part.staging, &part.path).map_err(|e| {
+                anyhow!(
+                    "Failed to move Parquet file into {}: {}",
+                    part.path.display(),
+                    e
+                )
+            })?;
+        }
+        eprintln!(
+            "DEBUG: Wrote {} files for {} partitions under {}",
+            self.parts().count(),
+            self.files.len(),
+            self.dir.display()
+        );
+        Ok(())
+    }
+}
+
+// Streams the result of `sql` into a Parquet file at `path` and returns the number of rows.
+// With `partition_by`, `path` is a directory receiving a Hive-partitioned dataset instead, and
+// with `rollover` limits a directory of numbered files.
+#[allow(clippy::too_many_arguments)]
+pub fn query_to_parquet(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+    path: &Path,
+    compression: Compression,
+    columns: &[(String, ColumnOptions)],
+    partition_by: &[String],
+    rollover: Rollover,
+    sidecar: Option<Sidecar>,
+) -> Result<usize> {
+    let parquet_sink = |file| ParquetSink::new(file, compression, columns);
+    if rollover.is_set() {
+        if !partition_by.is_empty() {
+            return Err(anyhow!(
+                "partition_by cannot be combined with max_file_rows or max_file_bytes"
+            ));
+        }
+        return export_parts(
+            dsn,
+            user,
+            password,
+            sql,
+            config,
+            path,
+            "parquet",
+            rollover,
+            sidecar,
+            parquet_sink,
+        );
+    }
+    if !partition_by.is_empty() {
+        if objstore::url(path).is_some() {
+            return Err(anyhow!(
+                "partition_by writes to a local directory; object store URLs are not supported"
+            ));
+        }
+        return query_to_dataset(
+            dsn,
+            user,
+            password,
+            sql,
+            config,
+            path,
+            compression,
+            columns,
+            partition_by,
+            sidecar,
+        );
+    }
+    export(
+        dsn,
+        user,
+        password,
+        sql,
+        config,
+        path,
+        "Parquet",
+        sidecar,
+        parquet_sink,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn query_to_dataset(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+    dir: &Path,
+    compression: Compression,
+    columns: &[(String, ColumnOptions)],
+    partition_by: &[String],
+    mut sidecar: Option<Sidecar>,
+) -> Result<usize> {
+    fs::create_dir_all(dir).map_err(|e| {
+        anyhow!(
+            "Failed to create dataset directory {}: {}",
+            dir.display(),
+            e
+        )
+    })?;
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let mut sink = HiveSink {
+        dir,
+        partition_by,
+        compression,
+        columns,
+        file_stem: format!("part-{:x}-{}", stamp, std::process::id()),
+        keys: Vec::new(),
+        values: Vec::new(),
+        names: Vec::new(),
+        schema: None,
+        properties: None,
+        files: HashMap::new(),
+        clock: 0,
+    };
+    let rows = fetch_recorded(
+        dsn,
+        user,
+        password,
+        sql,
+        config,
+        &mut sink,
+        sidecar.as_mut(),
+    );
+    if rows.is_err() {
+        sink.discard();
+    }
+    let rows = rows?;
+    if let Some(sidecar) = &sidecar {
+        let mut files: Vec<(String, usize)> = sink
+            .parts()
+            .map(|part| (part.path.display().to_string(), part.rows))
+            .collect();
+        files.sort();
+        sidecar.write(dir, "parquet", sql, config, &files)?;
+    }
+    Ok(rows)
+}
+
+// Streams the result of `sql` into a CSV file at `path` and returns the number of rows
+#[allow(clippy::too_many_arguments)]
+pub fn query_to_csv(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+    path: &Path,
+    delimiter: u8,
+    header: bool,
+    sidecar: Option<Sidecar>,
+) -> Result<usize> {
+    let builder = WriterBuilder::new()
+        .with_delimiter(delimiter)
+        .with_header(header);
+    export(
+        dsn,
+        user,
+        password,
+        sql,
+        config,
+        path,
+        "CSV",
+        sidecar,
+        |out| CsvSink::new(out, builder),
+    )
+}
+
+// Streams the result of `sql` into a newline-delimited JSON file at `path` and returns the
+// number of rows
+#[allow(clippy::too_many_arguments)]
+pub fn query_to_ndjson(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+    path: &Path,
+    timestamps: TimestampFormat,
+    decimals: DecimalFormat,
+    sidecar: Option<Sidecar>,
+) -> Result<usize> {
+    export(
+        dsn,
+        user,
+        password,
+        sql,
+        config,
+        path,
+        "NDJSON",
+        sidecar,
+        |out| NdjsonSink::new(out, timestamps, decimals),
+    )
+}
+
+// Streams the result of `sql` into an Arrow IPC file at `path` and returns the number of rows.
+// With `rollover` limits, `path` is a directory of numbered files instead.
+#[allow(clippy::too_many_arguments)]
+pub fn query_to_feather(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+    path: &Path,
+    options: IpcWriteOptions,
+    rollover: Rollover,
+    sidecar: Option<Sidecar>,
+) -> Result<usize> {
+    let feather_sink = |out| FeatherSink {
+        out: Some(out),
+        options: options.clone(),
+        writer: None,
+    };
+    if rollover.is_set() {
+        return export_parts(
+            dsn,
+            user,
+            password,
+            sql,
+            config,
+            path,
+            "arrow",
+            rollover,
+            sidecar,
+            feather_sink,
+        );
+    }
+    export(
+        dsn,
+        user,
+        password,
+        sql,
+        config,
+        path,
+        "Arrow",
+        sidecar,
+        feather_sink,
+    )
+}
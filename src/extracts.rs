@@ -0,0 +1,176 @@
+use anyhow::{anyhow, Result};
+use pyo3::exceptions::PyKeyError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{map_query_error, write_arrow_ipc_impl, IbarrowConnection};
+
+// A named extract: the SQL to run, where to land it and when it was last refreshed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractDefinition {
+    pub sql: String,
+    pub destination: String,
+    pub schedule: Option<String>,
+    pub max_age_secs: Option<u64>,
+    pub last_refreshed_at: Option<u64>,
+    pub last_row_count: Option<usize>,
+    pub last_duration_secs: Option<f64>,
+}
+
+impl ExtractDefinition {
+    fn is_stale(&self, now: u64) -> bool {
+        match (self.last_refreshed_at, self.max_age_secs) {
+            (None, _) => true,
+            (Some(at), Some(max_age)) => now.saturating_sub(at) > max_age,
+            (Some(_), None) => false,
+        }
+    }
+
+    fn to_dict<'py>(&self, py: Python<'py>, name: &str) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("name", name)?;
+        dict.set_item("sql", &self.sql)?;
+        dict.set_item("destination", &self.destination)?;
+        dict.set_item("schedule", &self.schedule)?;
+        dict.set_item("max_age_secs", self.max_age_secs)?;
+        dict.set_item("last_refreshed_at", self.last_refreshed_at)?;
+        dict.set_item("last_row_count", self.last_row_count)?;
+        dict.set_item("last_duration_secs", self.last_duration_secs)?;
+        dict.set_item("stale", self.is_stale(unix_now()))?;
+        Ok(dict)
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn load_registry(path: &Path) -> Result<BTreeMap<String, ExtractDefinition>> {
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let text = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read extract registry {}: {}", path.display(), e))?;
+    serde_json::from_str(&text)
+        .map_err(|e| anyhow!("Invalid extract registry {}: {}", path.display(), e))
+}
+
+fn save_registry(path: &Path, extracts: &BTreeMap<String, ExtractDefinition>) -> Result<()> {
+    let text = serde_json::to_string_pretty(extracts)?;
+    fs::write(path, text)
+        .map_err(|e| anyhow!("Failed to write extract registry {}: {}", path.display(), e))
+}
+
+// Local registry of named extracts persisted as a JSON file
+#[pyclass]
+pub struct ExtractRegistry {
+    path: PathBuf,
+    connection: Py<IbarrowConnection>,
+}
+
+#[pymethods]
+impl ExtractRegistry {
+    #[new]
+    fn new(connection: Py<IbarrowConnection>, path: PathBuf) -> Self {
+        Self { path, connection }
+    }
+
+    #[pyo3(signature = (name, sql, destination, schedule=None, max_age_secs=None))]
+    fn register(
+        &self,
+        name: &str,
+        sql: &str,
+        destination: &str,
+        schedule: Option<String>,
+        max_age_secs: Option<u64>,
+    ) -> PyResult<()> {
+        let mut extracts = load_registry(&self.path).map_err(map_query_error)?;
+        extracts.insert(
+            name.to_string(),
+            ExtractDefinition {
+                sql: sql.to_string(),
+                destination: destination.to_string(),
+                schedule,
+                max_age_secs,
+                last_refreshed_at: None,
+                last_row_count: None,
+                last_duration_secs: None,
+            },
+        );
+        save_registry(&self.path, &extracts).map_err(map_query_error)
+    }
+
+    fn remove(&self, name: &str) -> PyResult<()> {
+        let mut extracts = load_registry(&self.path).map_err(map_query_error)?;
+        if extracts.remove(name).is_none() {
+            return Err(PyKeyError::new_err(format!("Unknown extract '{}'", name)));
+        }
+        save_registry(&self.path, &extracts).map_err(map_query_error)
+    }
+
+    fn list(&self, py: Python<'_>) -> PyResult<Vec<Py<PyDict>>> {
+        let extracts = load_registry(&self.path).map_err(map_query_error)?;
+        extracts
+            .iter()
+            .map(|(name, extract)| Ok(extract.to_dict(py, name)?.unbind()))
+            .collect()
+    }
+
+    // Re-runs the extract's SQL into its destination and records freshness metadata
+    fn refresh(&self, py: Python<'_>, extract_name: &str) -> PyResult<Py<PyDict>> {
+        let mut extracts = load_registry(&self.path).map_err(map_query_error)?;
+        let extract = extracts
+            .get_mut(extract_name)
+            .ok_or_else(|| PyKeyError::new_err(format!("Unknown extract '{}'", extract_name)))?;
+
+        let conn = self.connection.borrow(py);
+        let started = Instant::now();
+        // Write next to the destination first so a failed refresh keeps the previous extract
+        let staging = format!("{}.tmp", extract.destination);
+        let file = File::create(&staging).map_err(|e| {
+            map_query_error(anyhow!("Failed to create extract file {}: {}", staging, e))
+        })?;
+        let mut writer = BufWriter::new(file);
+        let (dsn, user, password, config) = (&conn.dsn, &conn.user, &conn.password, &conn.config);
+        let sql = &extract.sql;
+        let rows = py
+            .allow_threads(|| {
+                write_arrow_ipc_impl(dsn, user, password, sql, &[], config, &mut writer)
+            })
+            .map_err(|e| {
+                let _ = fs::remove_file(&staging);
+                map_query_error(e)
+            })?;
+        writer
+            .flush()
+            .map_err(|e| map_query_error(anyhow!("Failed to flush extract file: {}", e)))?;
+        drop(writer);
+        fs::rename(&staging, &extract.destination).map_err(|e| {
+            map_query_error(anyhow!(
+                "Failed to move extract into {}: {}",
+                extract.destination,
+                e
+            ))
+        })?;
+
+        extract.last_refreshed_at = Some(unix_now());
+        extract.last_row_count = Some(rows);
+        extract.last_duration_secs = Some(started.elapsed().as_secs_f64());
+        let dict = extract.to_dict(py, extract_name)?.unbind();
+        save_registry(&self.path, &extracts).map_err(map_query_error)?;
+        Ok(dict)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("ExtractRegistry(path='{}')", self.path.display())
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::Result;
+use arrow::array::{Array, AsArray};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Schema};
+
+use crate::params::SqlParam;
+use crate::sink::Collected;
+use crate::{catalog, fetch_into, QueryConfig};
+
+// Ibis dtype string of an Arrow type, e.g. "int32", "decimal(18, 2)" or "array<string>"
+pub fn ibis_type(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Null => "null".to_string(),
+        DataType::Boolean => "boolean".to_string(),
+        DataType::Int8 => "int8".to_string(),
+        DataType::Int16 => "int16".to_string(),
+        DataType::Int32 => "int32".to_string(),
+        DataType::Int64 => "int64".to_string(),
+        DataType::UInt8 => "uint8".to_string(),
+        DataType::UInt16 => "uint16".to_string(),
+        DataType::UInt32 => "uint32".to_string(),
+        DataType::UInt64 => "uint64".to_string(),
+        DataType::Float16 => "float16".to_string(),
+        DataType::Float32 => "float32".to_string(),
+        DataType::Float64 => "float64".to_string(),
+        DataType::Decimal128(precision, scale) | DataType::Decimal256(precision, scale) => {
+            format!("decimal({}, {})", precision, scale)
+        }
+        DataType::Utf8 | DataType::LargeUtf8 | DataType::Utf8View => "string".to_string(),
+        DataType::Binary
+        | DataType::LargeBinary
+        | DataType::BinaryView
+        | DataType::FixedSizeBinary(_) => "binary".to_string(),
+        DataType::Date32 | DataType::Date64 => "date".to_string(),
+        DataType::Time32(_) | DataType::Time64(_) => "time".to_string(),
+        DataType::Timestamp(_, None) => "timestamp".to_string(),
+        DataType::Timestamp(_, Some(zone)) => format!("timestamp('{}')", zone),
+        DataType::Dictionary(_, values) => ibis_type(values),
+        DataType::List(field) | DataType::LargeList(field) | DataType::FixedSizeList(field, _) => {
+            format!("array<{}>", ibis_type(field.data_type()))
+        }
+        DataType::Struct(fields) => format!(
+            "struct<{}>",
+            fields
+                .iter()
+                .map(|field| format!("{}: {}", field.name(), ibis_type(field.data_type())))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        _ => "unknown".to_string(),
+    }
+}
+
+// (name, Ibis dtype) per column, the dtype prefixed with "!" when the column is NOT NULL
+pub fn schema_types(schema: &Schema) -> Vec<(String, String)> {
+    schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let dtype = ibis_type(field.data_type());
+            let dtype = if field.is_nullable() {
+                dtype
+            } else {
+                format!("!{}", dtype)
+            };
+            (field.name().clone(), dtype)
+        })
+        .collect()
+}
+
+pub fn table_names(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    config: &QueryConfig,
+) -> Result<Vec<String>> {
+    let mut collected = Collected::default();
+    fetch_into(
+        dsn,
+        user,
+        password,
+        &catalog::tables_sql(false),
+        &[SqlParam::Text("%".to_string())],
+        config,
+        &mut collected,
+        &mut |_| Ok(()),
+    )?;
+    let mut tables = Vec::new();
+    for batch in &collected.batches {
+        let names = cast(batch.column(0), &DataType::Utf8)?;
+        let names = names.as_string::<i32>();
+        tables.extend(
+            (0..names.len())
+                .filter(|&row| names.is_valid(row))
+                .map(|row| names.value(row).to_string()),
+        );
+    }
+    Ok(tables)
+}
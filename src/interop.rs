@@ -0,0 +1,324 @@
+use anyhow::Result;
+use arrow::array::{Array, ArrayRef, StructArray};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::ffi::to_ffi;
+use arrow::ffi_stream::FFI_ArrowArrayStream;
+use arrow::record_batch::{RecordBatch, RecordBatchIterator};
+use pyo3::exceptions::{PyImportError, PyRuntimeError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyCapsule, PyDict};
+use std::ffi::CString;
+use std::io::{self, Write};
+use std::os::raw::{c_int, c_void};
+use std::sync::Mutex;
+
+use crate::compression::module_version;
+use crate::QueryConfig;
+
+pub use ibarrow_core::interop::{mode, validate, Interop};
+
+// Exports a record batch through the Arrow C Data Interface as (schema, array) capsules
+pub fn batch_to_capsules(py: Python<'_>, batch: RecordBatch) -> Result<(Py<PyAny>, Py<PyAny>)> {
+    let array_data = StructArray::from(batch).into_data();
+    let (ffi_array, ffi_schema) = to_ffi(&array_data)?;
+
+    let schema_capsule = PyCapsule::new_bound(py, ffi_schema, Some(CString::new("arrow_schema")?))?;
+    let array_capsule = PyCapsule::new_bound(py, ffi_array, Some(CString::new("arrow_array")?))?;
+
+    Ok((schema_capsule.into(), array_capsule.into()))
+}
+
+// Hands a record batch to pyarrow without copying the buffers
+pub fn batch_to_pyarrow(py: Python<'_>, batch: RecordBatch) -> PyResult<Py<PyAny>> {
+    let (schema_capsule, array_capsule) =
+        batch_to_capsules(py, batch).map_err(crate::map_query_error)?;
+    let batch = py
+        .import_bound("pyarrow")?
+        .getattr("RecordBatch")?
+        .call_method1("_import_from_c_capsule", (schema_capsule, array_capsule))?;
+    Ok(batch.unbind())
+}
+
+// Arrow PyCapsule stream over batches already in memory. Exporting moves the batches to the
+// consumer without copying a buffer, so it can be exported once.
+#[pyclass]
+pub struct BatchStream {
+    schema: SchemaRef,
+    batches: Mutex<Option<Vec<RecordBatch>>>,
+}
+
+impl BatchStream {
+    pub fn new(schema: SchemaRef, batches: Vec<RecordBatch>) -> Self {
+        Self {
+            schema,
+            batches: Mutex::new(Some(batches)),
+        }
+    }
+}
+
+#[pymethods]
+impl BatchStream {
+    #[pyo3(signature = (requested_schema=None))]
+    fn __arrow_c_stream__(
+        &self,
+        py: Python<'_>,
+        requested_schema: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Py<PyCapsule>> {
+        let _ = requested_schema;
+        let batches = self
+            .batches
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+            .ok_or_else(|| PyRuntimeError::new_err("Arrow stream was already exported"))?;
+        let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), self.schema.clone());
+        let stream = FFI_ArrowArrayStream::new(Box::new(reader));
+        Ok(PyCapsule::new_bound(py, stream, Some(CString::new("arrow_array_stream")?))?.unbind())
+    }
+}
+
+// Polars imports Arrow PyCapsule streams since 1.3
+pub fn polars_reads_c_stream(py: Python<'_>) -> bool {
+    py.import_bound("polars")
+        .ok()
+        .as_ref()
+        .and_then(module_version)
+        .is_some_and(|version| version >= (1, 3))
+}
+
+// Builds a Polars DataFrame that takes over the batches' buffers through the C stream
+pub fn polars_from_batches(
+    py: Python<'_>,
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+) -> PyResult<Py<PyAny>> {
+    let stream = Py::new(py, BatchStream::new(schema, batches))?;
+    let df = py
+        .import_bound("polars")?
+        .getattr("DataFrame")?
+        .call1((stream,))?;
+    Ok(df.unbind())
+}
+
+// pyarrow imports Arrow PyCapsule streams through RecordBatchReader.from_stream since 15.0
+pub fn pyarrow_reads_c_stream(py: Python<'_>) -> bool {
+    py.import_bound("pyarrow")
+        .ok()
+        .as_ref()
+        .and_then(module_version)
+        .is_some_and(|version| version >= (15, 0))
+}
+
+// Builds a pyarrow.Table over the batches' buffers through the C stream
+pub fn pyarrow_table_from_batches(
+    py: Python<'_>,
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+) -> PyResult<Py<PyAny>> {
+    let stream = Py::new(py, BatchStream::new(schema, batches))?;
+    let table = py
+        .import_bound("pyarrow")?
+        .getattr("RecordBatchReader")?
+        .call_method1("from_stream", (stream,))?
+        .call_method0("read_all")?;
+    Ok(table.unbind())
+}
+
+// Settles Interop::Auto: pyarrow if it is installed, else Arrow PyCapsules through Polars.
+// Spill files are only read by pyarrow, so spilling keeps the pyarrow path.
+pub fn resolve(py: Python<'_>, config: &QueryConfig) -> Result<Interop> {
+    let mode = mode(config)?;
+    if mode != Interop::Auto {
+        return Ok(mode);
+    }
+    if config.spill_threshold.is_some() || py.import_bound("pyarrow").is_ok() {
+        return Ok(Interop::PyArrow);
+    }
+    eprintln!("DEBUG: pyarrow is not installed, building pandas through Polars");
+    Ok(Interop::Capsule)
+}
+
+// Column the pandas interchange protocol can take: decimals become float64 and times text,
+// which it has no types for
+fn interchange_column(field: &Field, column: &ArrayRef) -> Result<(Field, ArrayRef)> {
+    let target = match field.data_type() {
+        DataType::Decimal128(_, _) | DataType::Decimal256(_, _) => DataType::Float64,
+        DataType::Time32(_) | DataType::Time64(_) => DataType::Utf8,
+        _ => return Ok((field.clone(), column.clone())),
+    };
+    let column = cast(column, &target)?;
+    Ok((field.clone().with_data_type(target), column))
+}
+
+fn interchange_batch(batch: &RecordBatch) -> Result<RecordBatch> {
+    let (fields, columns): (Vec<Field>, Vec<ArrayRef>) = batch
+        .schema_ref()
+        .fields()
+        .iter()
+        .zip(batch.columns())
+        .map(|(field, column)| interchange_column(field, column))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .unzip();
+    Ok(RecordBatch::try_new(Schema::new(fields).into(), columns)?)
+}
+
+// Polars DataFrame of the batches: through the C stream on Polars 1.3+, through an IPC stream
+// and polars.read_ipc on older versions
+fn polars_frame(
+    py: Python<'_>,
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+) -> PyResult<Py<PyAny>> {
+    if polars_reads_c_stream(py) {
+        return polars_from_batches(py, schema, batches);
+    }
+    let polars = py.import_bound("polars").map_err(|_| {
+        PyImportError::new_err(
+            "query_pandas needs pyarrow or polars (pip install pyarrow, or pip install polars)",
+        )
+    })?;
+    let mut bytes = Vec::new();
+    let mut writer = arrow_ipc::writer::StreamWriter::try_new(&mut bytes, &schema)
+        .map_err(|e| crate::map_query_error(e.into()))?;
+    for batch in &batches {
+        writer
+            .write(batch)
+            .map_err(|e| crate::map_query_error(e.into()))?;
+    }
+    writer
+        .finish()
+        .map_err(|e| crate::map_query_error(e.into()))?;
+    drop(writer);
+    let buf = py
+        .import_bound("io")?
+        .getattr("BytesIO")?
+        .call1((PyBytes::new_bound(py, &bytes),))?;
+    Ok(polars.getattr("read_ipc")?.call1((buf,))?.unbind())
+}
+
+// Builds a Pandas DataFrame without pyarrow: Polars takes the batches and pandas reads the
+// Polars frame through the dataframe interchange protocol, which only needs NumPy
+pub fn pandas_from_batches(
+    py: Python<'_>,
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+) -> PyResult<Py<PyAny>> {
+    let empty = RecordBatch::new_empty(schema);
+    let schema = interchange_batch(&empty)
+        .map_err(crate::map_query_error)?
+        .schema();
+    let batches = batches
+        .iter()
+        .map(interchange_batch)
+        .collect::<Result<Vec<_>>>()
+        .map_err(crate::map_query_error)?;
+    let df = polars_frame(py, schema, batches)?;
+    let kwargs = PyDict::new_bound(py);
+    kwargs.set_item("allow_copy", true)?;
+    eprintln!("DEBUG: Converting Polars DataFrame to Pandas through the interchange protocol");
+    let pandas = py
+        .import_bound("pandas.api.interchange")?
+        .getattr("from_dataframe")?
+        .call((df,), Some(&kwargs))?;
+    Ok(pandas.unbind())
+}
+
+// Read-only buffer-protocol view of bytes owned by Rust, so a result can reach Python without
+// being copied into a `bytes` object. The bytes live as long as any memoryview of them.
+#[pyclass(frozen)]
+pub struct IpcBuffer {
+    data: Vec<u8>,
+}
+
+impl IpcBuffer {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self { data }
+    }
+}
+
+#[pymethods]
+impl IpcBuffer {
+    unsafe fn __getbuffer__(
+        slf: Bound<'_, Self>,
+        view: *mut pyo3::ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        let data = &slf.get().data;
+        // Fills a contiguous, read-only, one-dimensional view and takes a reference to `slf`
+        if pyo3::ffi::PyBuffer_FillInfo(
+            view,
+            slf.as_ptr(),
+            data.as_ptr() as *mut c_void,
+            data.len() as pyo3::ffi::Py_ssize_t,
+            1,
+            flags,
+        ) == -1
+        {
+            return Err(PyErr::fetch(slf.py()));
+        }
+        Ok(())
+    }
+
+    unsafe fn __releasebuffer__(&self, _view: *mut pyo3::ffi::Py_buffer) {}
+
+    fn __len__(&self) -> usize {
+        self.data.len()
+    }
+}
+
+// std::io::Write over a Python object: anything with `write()` (file, BytesIO,
+// socket.makefile()) or, failing that, `sendall()` (socket). The GIL is taken for each call,
+// so wrap it in a BufWriter.
+pub struct PyWriter {
+    target: Py<PyAny>,
+    method: &'static str,
+}
+
+impl PyWriter {
+    pub fn accepts(target: &Bound<'_, PyAny>) -> PyResult<bool> {
+        Ok(target.hasattr("write")? || target.hasattr("sendall")?)
+    }
+
+    pub fn new(target: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let method = if target.hasattr("write")? {
+            "write"
+        } else {
+            "sendall"
+        };
+        Ok(Self {
+            target: target.clone().unbind(),
+            method,
+        })
+    }
+}
+
+impl Write for PyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Python::with_gil(|py| {
+            let bytes = PyBytes::new_bound(py, buf);
+            let written = self
+                .target
+                .bind(py)
+                .call_method1(self.method, (bytes,))
+                .map_err(io::Error::other)?;
+            // Raw files may write less than asked; buffered ones and sendall() take it all
+            Ok(written
+                .extract::<Option<usize>>()
+                .ok()
+                .flatten()
+                .unwrap_or(buf.len()))
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Python::with_gil(|py| {
+            let target = self.target.bind(py);
+            if target.hasattr("flush").map_err(io::Error::other)? {
+                target.call_method0("flush").map_err(io::Error::other)?;
+            }
+            Ok(())
+        })
+    }
+}
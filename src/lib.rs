@@ -1,85 +1,139 @@
 use anyhow::{anyhow, Result};
-use arrow::array::Array;
+use arrow::array::{Array, ArrayData, StructArray};
+use arrow::compute::concat_batches;
+use arrow::datatypes::{Schema, SchemaRef};
 use arrow::ffi::to_ffi;
-use arrow::record_batch::RecordBatchReader;
-use arrow_ipc::writer::StreamWriter;
-use arrow_odbc::OdbcReaderBuilder;
-use odbc_api::{ConnectionOptions, Environment};
-use pyo3::exceptions::PyRuntimeError;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use pyo3::exceptions::{PyIOError, PyImportError, PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
-use pyo3::types::{PyBytes, PyCapsule};
-use serde::{Deserialize, Serialize};
+use pyo3::types::{PyBytes, PyCapsule, PyDict, PyList, PyMemoryView, PyTuple};
+use serde::Serialize;
+use std::collections::HashMap;
 use std::ffi::CString;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 use pyo3::create_exception;
-use pyo3::exceptions::PyException;
-
-// Helper function to handle long DSN names by converting to direct connection string
-fn build_connection_string(dsn: &str, user: &str, password: &str, config: &QueryConfig) -> String {
-    // Check if dsn is already a full connection string
-    let mut conn_str = if dsn.contains("DRIVER=") || dsn.contains("SERVER=") {
-        // It's already a connection string, use it directly
-        format!("{};UID={};PWD={};", dsn, user, password)
+
+mod adbc;
+mod async_conn;
+mod batches;
+mod block;
+mod bundle;
+mod catalog;
+#[cfg(feature = "cli")]
+pub mod cli;
+mod clock;
+mod compression;
+mod dbapi;
+mod ddl;
+mod dialect;
+mod distributed;
+mod estimate;
+mod export;
+mod extracts;
+mod ibis;
+mod interop;
+mod load;
+mod manifest;
+mod many;
+mod migrate;
+mod objstore;
+mod partition;
+mod pipe;
+mod polling;
+mod recipe;
+mod result;
+mod scan;
+mod scroll;
+mod server;
+mod sidecar;
+mod snapshot;
+mod spill;
+mod split;
+mod sqlfile;
+mod stream;
+mod tensor;
+#[cfg(feature = "testing")]
+mod testing;
+mod topk;
+mod transaction;
+mod worker;
+mod write;
+mod zonemap;
+
+// The ODBC to Arrow pipeline lives in ibarrow-core; these modules are bindings and Python-only
+// features on top of it
+use ibarrow_core::{
+    access, arrays, backend, charset, context, diagnostics, dictionary, domains, driverenv,
+    drivermgr, emptystr, params, progress, quoting, retry, sink, txwatch, watchdog,
+};
+use ibarrow_core::{
+    build_connection_string, build_reader, find_column, limit_rows, reader_builder, rows_left,
+    trim_batch, BatchReader,
+};
+
+pub use ibarrow_core::QueryConfig;
+
+use compression::Consumer;
+use params::{QueryParams, SqlParam};
+use progress::Progress;
+use sink::{BatchSink, Collected, IpcSink};
+use spill::Spilled;
+use watchdog::Watchdog;
+
+create_exception!(ibarrow, PyConnectionError, dbapi::OperationalError);
+create_exception!(ibarrow, PySQLError, dbapi::DatabaseError);
+create_exception!(ibarrow, PyArrowError, dbapi::DataError);
+create_exception!(ibarrow, PyTimeoutError, pyo3::exceptions::PyTimeoutError);
+create_exception!(ibarrow, PyAccessError, pyo3::exceptions::PyPermissionError);
+
+// Maps an error from the ODBC/Arrow layer to the matching Python exception. The full chain of
+// ODBC diagnostic records is attached as `diagnostics`.
+fn map_query_error(e: anyhow::Error) -> PyErr {
+    // Exceptions raised by Python hooks such as QueryConfig.progress pass through unchanged
+    let e = match e.downcast::<PyErr>() {
+        Ok(err) => return err,
+        Err(e) => e,
+    };
+    let records = diagnostics::records_for(&e);
+    let msg = e.to_string();
+    let err = if msg.contains("timed out") {
+        PyTimeoutError::new_err(format!("Timeout Error: {}", msg))
+    } else if msg.starts_with("Access denied") {
+        PyAccessError::new_err(msg)
+    } else if msg.contains("IM002") || msg.contains("connection") {
+        PyConnectionError::new_err(format!("Connection Error: {}", msg))
+    } else if msg.contains("State: 23") {
+        // SQLSTATE class 23: constraint violations
+        dbapi::IntegrityError::new_err(format!("Integrity Error: {}", msg))
+    } else if msg.contains("SQL") || msg.contains("syntax") {
+        PySQLError::new_err(format!("SQL Error: {}", msg))
+    } else if msg.contains("Arrow") || msg.contains("c_data") {
+        PyArrowError::new_err(format!("Arrow Error: {}", msg))
     } else {
-        // Check if DSN contains a file path (common cause of long DSN names)
-        let is_file_path = dsn.contains("\\")
-            || dsn.contains("/")
-            || dsn.contains(":")
-            || dsn.ends_with(".fdb")
-            || dsn.ends_with(".gdb");
-
-        if is_file_path || dsn.len() > 32 {
-            // Convert file path or long DSN to direct connection string
-            // Use DATABASE parameter for file paths, which is more elegant
-            if is_file_path {
-                format!(
-                    "DRIVER={{InterBase ODBC Driver}};DATABASE={};UID={};PWD={};",
-                    dsn, user, password
-                )
-            } else {
-                format!(
-                    "DRIVER={{InterBase ODBC Driver}};DSN={};UID={};PWD={};",
-                    dsn, user, password
-                )
-            }
-        } else {
-            // It's a DSN, use DSN format
-            format!("DSN={};UID={};PWD={};", dsn, user, password)
-        }
+        PyRuntimeError::new_err(msg)
     };
-
-    if config.read_only {
-        conn_str.push_str("ReadOnly=1;");
-    }
-
-    if let Some(timeout) = config.connection_timeout {
-        conn_str.push_str(&format!("Connection Timeout={};", timeout));
-    }
-
-    if let Some(timeout) = config.query_timeout {
-        conn_str.push_str(&format!("Query Timeout={};", timeout));
-    }
-
-    if let Some(level) = &config.isolation_level {
-        match level.to_lowercase().as_str() {
-            "read_uncommitted" => conn_str.push_str("Isolation Level=ReadUncommitted;"),
-            "read_committed" => conn_str.push_str("Isolation Level=ReadCommitted;"),
-            "repeatable_read" => conn_str.push_str("Isolation Level=RepeatableRead;"),
-            "serializable" => conn_str.push_str("Isolation Level=Serializable;"),
-            "snapshot" => conn_str.push_str("Isolation Level=Snapshot;"),
-            _ => {
-                // If unknown level, pass through as-is (driver-specific)
-                conn_str.push_str(&format!("Isolation Level={};", level));
-            }
+    Python::with_gil(|py| {
+        let chain = PyList::empty_bound(py);
+        for record in &records {
+            let (state, native_error, message) = diagnostics::parse(record);
+            let entry = PyDict::new_bound(py);
+            let _ = entry.set_item("state", state);
+            let _ = entry.set_item("native_error", native_error);
+            let _ = entry.set_item("message", message);
+            let _ = chain.append(entry);
         }
-    }
-
-    conn_str
+        if let Err(e) = err.value_bound(py).setattr("diagnostics", chain) {
+            eprintln!("WARN: could not attach ODBC diagnostics: {}", e);
+        }
+    });
+    err
 }
 
-create_exception!(ibarrow, PyConnectionError, PyException);
-create_exception!(ibarrow, PySQLError, PyException);
-create_exception!(ibarrow, PyArrowError, PyException);
+// Bytes query_to_ipc gathers before each write to its target
+const IPC_WRITE_BUFFER: usize = 1 << 20;
 
 // Connection class for maintaining database session
 #[pyclass]
@@ -88,85 +142,1320 @@ pub struct IbarrowConnection {
     user: String,
     password: String,
     config: QueryConfig,
+    // DB-API transaction opened by cursor statements (see dbapi::execute_in_transaction)
+    transaction: dbapi::HeldTransaction,
+}
+
+impl IbarrowConnection {
+    // Writes the batches to `target`, checking they can be written before connecting
+    fn write(
+        &self,
+        py: Python<'_>,
+        target: write::Target<'_>,
+        batches: write::Batches,
+    ) -> PyResult<usize> {
+        write::insert_schema(&batches.schema())
+            .and_then(|_| target.validate(&batches.schema()))
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        py.allow_threads(|| {
+            write::write_arrow(
+                &self.dsn,
+                &self.user,
+                &self.password,
+                &target,
+                batches,
+                &self.config,
+            )
+        })
+        .map_err(map_query_error)
+    }
+
+    // Inserts DataFrame or file batches with their columns matched to the table's
+    fn write_frame(
+        &self,
+        py: Python<'_>,
+        table: &str,
+        batches: write::Batches,
+        coerce: bool,
+        mode: write::Mode,
+    ) -> PyResult<usize> {
+        if table.trim().is_empty() {
+            return Err(PyValueError::new_err("table must not be empty"));
+        }
+        let columns = if coerce {
+            write::Columns::Coerced
+        } else {
+            write::Columns::Matched
+        };
+        self.write(py, write::Target::insert(table, columns, mode), batches)
+    }
+
+    // The connection's config with a per-query max_rows taking precedence
+    fn limited(&self, max_rows: Option<u64>) -> QueryConfig {
+        QueryConfig {
+            max_rows: max_rows.or(self.config.max_rows),
+            ..self.config.clone()
+        }
+    }
 }
 
 #[pymethods]
 impl IbarrowConnection {
     #[new]
     fn new(dsn: &str, user: &str, password: &str, config: Option<&QueryConfig>) -> Self {
-        let config = config
-            .cloned()
-            .unwrap_or_else(|| QueryConfig::new(None, None, None, None, None, None, None));
+        let config = config.cloned().unwrap_or_default();
         Self {
             dsn: dsn.to_string(),
             user: user.to_string(),
             password: password.to_string(),
             config,
+            transaction: Default::default(),
         }
     }
 
-    fn query_arrow_ipc(&self, sql: &str) -> PyResult<Py<PyAny>> {
+    // With copy=False, returns a read-only memoryview over the Rust buffer instead of bytes
+    #[pyo3(signature = (sql, max_rows=None, copy=true))]
+    fn query_arrow_ipc(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        max_rows: Option<u64>,
+        copy: bool,
+    ) -> PyResult<Py<PyAny>> {
         eprintln!("DEBUG: query_arrow_ipc called with SQL: {}", sql);
-        let bytes = query_arrow_ipc_impl(&self.dsn, &self.user, &self.password, sql, &self.config)
+        let config = compression::for_consumer(py, &self.limited(max_rows), Consumer::Bytes)?;
+        // Release the GIL while the driver executes and fetches
+        let bytes = py
+            .allow_threads(|| {
+                query_arrow_ipc_impl(&self.dsn, &self.user, &self.password, sql, &config)
+            })
             .map_err(|e| {
-                let msg = e.to_string();
-                eprintln!("ERROR: query_arrow_ipc_impl failed: {}", msg);
-                if msg.contains("IM002") || msg.contains("connection") {
-                    PyConnectionError::new_err(format!("Connection Error: {}", msg))
-                } else if msg.contains("SQL") || msg.contains("syntax") {
-                    PySQLError::new_err(format!("SQL Error: {}", msg))
-                } else if msg.contains("Arrow") || msg.contains("c_data") {
-                    PyArrowError::new_err(format!("Arrow Error: {}", msg))
-                } else {
-                    PyRuntimeError::new_err(msg)
-                }
+                eprintln!("ERROR: query_arrow_ipc_impl failed: {}", e);
+                map_query_error(e)
             })?;
 
+        if !copy {
+            let buffer = Bound::new(py, interop::IpcBuffer::new(bytes))?;
+            return Ok(PyMemoryView::from_bound(&buffer)?.into_any().unbind());
+        }
         // Convert Vec<u8> to Python bytes object
-        Python::with_gil(|py| {
-            let py_bytes = PyBytes::new_bound(py, &bytes);
-            Ok(py_bytes.into())
+        let py_bytes = PyBytes::new_bound(py, &bytes);
+        Ok(py_bytes.into())
+    }
+
+    // Writes the Arrow IPC stream to `target` as the batches arrive and returns the row count.
+    // `target` is a path, an s3://, gs:// or az:// URL, or an object with write() or sendall();
+    // only one buffer of the stream is held in memory at a time.
+    #[pyo3(signature = (sql, target, max_rows=None))]
+    fn query_to_ipc(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        target: &Bound<'_, PyAny>,
+        max_rows: Option<u64>,
+    ) -> PyResult<usize> {
+        eprintln!("DEBUG: query_to_ipc called with SQL: {}", sql);
+        let config = compression::for_consumer(py, &self.limited(max_rows), Consumer::Bytes)?;
+        let result = if interop::PyWriter::accepts(target)? {
+            let mut out =
+                BufWriter::with_capacity(IPC_WRITE_BUFFER, interop::PyWriter::new(target)?);
+            py.allow_threads(|| {
+                let rows = write_arrow_ipc_impl(
+                    &self.dsn,
+                    &self.user,
+                    &self.password,
+                    sql,
+                    &[],
+                    &config,
+                    &mut out,
+                )?;
+                out.flush()?;
+                Ok(rows)
+            })
+        } else {
+            let path: PathBuf = target.extract().map_err(|_| {
+                PyValueError::new_err(
+                    "target must be a path or an object with write() or sendall()",
+                )
+            })?;
+            py.allow_threads(|| {
+                export::ipc_to_path(&self.dsn, &self.user, &self.password, sql, &config, &path)
+            })
+        };
+        result.map_err(|e| {
+            eprintln!("ERROR: query_to_ipc failed: {}", e);
+            map_query_error(e)
         })
     }
 
-    fn query_polars(&self, sql: &str) -> PyResult<Py<PyAny>> {
-        query_polars_impl(&self.dsn, &self.user, &self.password, sql, &self.config)
+    // Streams the result into a Parquet file at `path` and returns the row count
+    // `columns` maps column names to their own compression, level, encoding and dictionary
+    // settings. With max_file_rows or max_file_bytes, `path` is a directory of part files.
+    #[pyo3(signature = (
+        sql,
+        path,
+        compression="snappy",
+        max_rows=None,
+        compression_level=None,
+        columns=None,
+        partition_by=None,
+        max_file_rows=None,
+        max_file_bytes=None,
+        sidecar=false,
+        stats_column=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn query_to_parquet(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        path: PathBuf,
+        compression: &str,
+        max_rows: Option<u64>,
+        compression_level: Option<i32>,
+        columns: Option<&Bound<'_, PyDict>>,
+        partition_by: Option<Vec<String>>,
+        max_file_rows: Option<u64>,
+        max_file_bytes: Option<u64>,
+        sidecar: bool,
+        stats_column: Option<String>,
+    ) -> PyResult<usize> {
+        eprintln!("DEBUG: query_to_parquet called with SQL: {}", sql);
+        let compression = export::compression(compression, compression_level)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let columns = export::column_options(columns)?;
+        let rollover = export::Rollover::new(max_file_rows, max_file_bytes)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let sidecar = sidecar::Sidecar::new(sidecar, stats_column)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let config = self.limited(max_rows);
+        py.allow_threads(|| {
+            export::query_to_parquet(
+                &self.dsn,
+                &self.user,
+                &self.password,
+                sql,
+                &config,
+                &path,
+                compression,
+                &columns,
+                partition_by.as_deref().unwrap_or_default(),
+                rollover,
+                sidecar,
+            )
+        })
+        .map_err(|e| {
+            eprintln!("ERROR: query_to_parquet failed: {}", e);
+            map_query_error(e)
+        })
     }
 
-    fn query_pandas(&self, sql: &str) -> PyResult<Py<PyAny>> {
-        query_pandas_impl(&self.dsn, &self.user, &self.password, sql, &self.config)
+    // Streams the result into a CSV file for consumers that read neither Arrow nor Parquet.
+    // `delimiter` is a single ASCII character; `header` writes the column names first.
+    #[pyo3(signature = (
+        sql,
+        path,
+        delimiter=",",
+        header=true,
+        max_rows=None,
+        sidecar=false,
+        stats_column=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn query_to_csv(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        path: PathBuf,
+        delimiter: &str,
+        header: bool,
+        max_rows: Option<u64>,
+        sidecar: bool,
+        stats_column: Option<String>,
+    ) -> PyResult<usize> {
+        eprintln!("DEBUG: query_to_csv called with SQL: {}", sql);
+        let delimiter = match delimiter.as_bytes() {
+            [byte] if byte.is_ascii() => *byte,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "delimiter must be a single ASCII character, got {:?}",
+                    delimiter
+                )))
+            }
+        };
+        let sidecar = sidecar::Sidecar::new(sidecar, stats_column)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let config = self.limited(max_rows);
+        py.allow_threads(|| {
+            export::query_to_csv(
+                &self.dsn,
+                &self.user,
+                &self.password,
+                sql,
+                &config,
+                &path,
+                delimiter,
+                header,
+                sidecar,
+            )
+        })
+        .map_err(|e| {
+            eprintln!("ERROR: query_to_csv failed: {}", e);
+            map_query_error(e)
+        })
     }
 
-    fn query_arrow_c_data(&self, sql: &str, return_dataframe: Option<bool>) -> PyResult<Py<PyAny>> {
+    // Streams the result into a newline-delimited JSON file, one object per row
+    #[pyo3(signature = (
+        sql,
+        path,
+        timestamp_format="iso",
+        decimal_format="number",
+        max_rows=None,
+        sidecar=false,
+        stats_column=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn query_to_ndjson(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        path: PathBuf,
+        timestamp_format: &str,
+        decimal_format: &str,
+        max_rows: Option<u64>,
+        sidecar: bool,
+        stats_column: Option<String>,
+    ) -> PyResult<usize> {
+        eprintln!("DEBUG: query_to_ndjson called with SQL: {}", sql);
+        let timestamps = export::timestamp_format(timestamp_format)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let decimals = export::decimal_format(decimal_format)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let sidecar = sidecar::Sidecar::new(sidecar, stats_column)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let config = self.limited(max_rows);
+        py.allow_threads(|| {
+            export::query_to_ndjson(
+                &self.dsn,
+                &self.user,
+                &self.password,
+                sql,
+                &config,
+                &path,
+                timestamps,
+                decimals,
+                sidecar,
+            )
+        })
+        .map_err(|e| {
+            eprintln!("ERROR: query_to_ndjson failed: {}", e);
+            map_query_error(e)
+        })
+    }
+
+    // Writes the result as an Arrow IPC file (Feather v2), which readers can memory-map.
+    // `compression` overrides QueryConfig.ipc_compression for this file. With max_file_rows or
+    // max_file_bytes, `path` is a directory of part files.
+    #[pyo3(signature = (
+        sql,
+        path,
+        compression=None,
+        max_rows=None,
+        max_file_rows=None,
+        max_file_bytes=None,
+        sidecar=false,
+        stats_column=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn query_to_feather(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        path: PathBuf,
+        compression: Option<&str>,
+        max_rows: Option<u64>,
+        max_file_rows: Option<u64>,
+        max_file_bytes: Option<u64>,
+        sidecar: bool,
+        stats_column: Option<String>,
+    ) -> PyResult<usize> {
+        eprintln!("DEBUG: query_to_feather called with SQL: {}", sql);
+        let rollover = export::Rollover::new(max_file_rows, max_file_bytes)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let sidecar = sidecar::Sidecar::new(sidecar, stats_column)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let config = self.limited(max_rows);
+        let options = match compression {
+            Some(name) => compression::named_options(name),
+            None => compression::write_options(&config),
+        }
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        py.allow_threads(|| {
+            export::query_to_feather(
+                &self.dsn,
+                &self.user,
+                &self.password,
+                sql,
+                &config,
+                &path,
+                options,
+                rollover,
+                sidecar,
+            )
+        })
+        .map_err(|e| {
+            eprintln!("ERROR: query_to_feather failed: {}", e);
+            map_query_error(e)
+        })
+    }
+
+    // With `split`, returns one DataFrame per fraction instead (see query_split_impl)
+    #[pyo3(signature = (sql, max_rows=None, split=None, seed=0))]
+    fn query_polars(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        max_rows: Option<u64>,
+        split: Option<Vec<f64>>,
+        seed: u64,
+    ) -> PyResult<Py<PyAny>> {
+        let config = self.limited(max_rows);
+        if let Some(split) = split {
+            let config = compression::for_consumer(py, &config, Consumer::Polars)?;
+            return query_split_impl(py, self, sql, &config, &split, seed, polars_from_ipc);
+        }
+        query_polars_impl(py, &self.dsn, &self.user, &self.password, sql, &config)
+    }
+
+    // dtype_backend="pyarrow" gives pandas.ArrowDtype columns instead of NumPy ones
+    #[pyo3(signature = (sql, max_rows=None, split=None, seed=0, dtype_backend=None))]
+    fn query_pandas(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        max_rows: Option<u64>,
+        split: Option<Vec<f64>>,
+        seed: u64,
+        dtype_backend: Option<&str>,
+    ) -> PyResult<Py<PyAny>> {
+        let backend =
+            DtypeBackend::parse(dtype_backend).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let config = self.limited(max_rows);
+        if let Some(split) = split {
+            let config = compression::for_consumer(py, &config, Consumer::PyArrow)?;
+            let convert = match (capsule_interop(py, &config, backend)?, backend) {
+                (true, _) => pandas_capsule_from_ipc,
+                (false, DtypeBackend::Numpy) => pandas_from_ipc,
+                (false, DtypeBackend::PyArrow) => pandas_arrow_from_ipc,
+            };
+            return query_split_impl(py, self, sql, &config, &split, seed, convert);
+        }
+        query_pandas_impl(
+            py,
+            &self.dsn,
+            &self.user,
+            &self.password,
+            sql,
+            &config,
+            backend,
+        )
+    }
+
+    // Runs the query and returns it as a DuckDB relation on `duckdb_conn` (the default DuckDB
+    // connection if None), over a pyarrow.Table so the relation can be queried repeatedly.
+    // With `name` the result is also registered as a view for use in DuckDB SQL.
+    #[pyo3(signature = (sql, duckdb_conn=None, name=None, max_rows=None))]
+    fn query_duckdb(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        duckdb_conn: Option<&Bound<'_, PyAny>>,
+        name: Option<&str>,
+        max_rows: Option<u64>,
+    ) -> PyResult<Py<PyAny>> {
+        if name.is_some_and(|name| name.trim().is_empty()) {
+            return Err(PyValueError::new_err("name must not be empty"));
+        }
+        // Module-level duckdb functions work on its default connection
+        let target = match duckdb_conn {
+            Some(conn) => conn.clone(),
+            None => py
+                .import_bound("duckdb")
+                .map_err(|_| {
+                    PyImportError::new_err("query_duckdb needs duckdb (pip install duckdb)")
+                })?
+                .into_any(),
+        };
+        let config = self.limited(max_rows);
+        let table = query_pyarrow_impl(py, &self.dsn, &self.user, &self.password, sql, &config)?;
+        if let Some(name) = name {
+            target.call_method1("register", (name, table.bind(py)))?;
+            eprintln!("DEBUG: Registered the result as DuckDB view {}", name);
+        }
+        Ok(target.call_method1("from_arrow", (table,))?.unbind())
+    }
+
+    #[pyo3(signature = (sql, return_dataframe=None, max_rows=None))]
+    fn query_arrow_c_data(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        return_dataframe: Option<bool>,
+        max_rows: Option<u64>,
+    ) -> PyResult<Py<PyAny>> {
         query_arrow_c_data_with_df(
+            py,
             &self.dsn,
             &self.user,
             &self.password,
             sql,
-            &self.config,
+            &self.limited(max_rows),
             return_dataframe,
         )
     }
 
-    fn test_connection(&self) -> PyResult<bool> {
-        // Test connection with a query that always returns data
-        // Use RDB$DATABASE which exists in all Firebird/InterBase databases
-        match query_arrow_ipc_impl(
+    // Predicts rows, bytes and duration of an extract from its plan, a COUNT(*), the declared
+    // column widths and a timed sample, and checks them against optional budgets
+    #[pyo3(signature = (
+        sql,
+        sample_rows=1000,
+        count=true,
+        max_rows=None,
+        max_bytes=None,
+        max_seconds=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn estimate(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        sample_rows: usize,
+        count: bool,
+        max_rows: Option<u64>,
+        max_bytes: Option<u64>,
+        max_seconds: Option<f64>,
+    ) -> PyResult<Py<PyDict>> {
+        let budget = estimate::Budget {
+            max_rows,
+            max_bytes,
+            max_seconds,
+        };
+        let estimate = py
+            .allow_threads(|| {
+                estimate::estimate(
+                    &self.dsn,
+                    &self.user,
+                    &self.password,
+                    sql,
+                    &self.config,
+                    sample_rows,
+                    count,
+                    &budget,
+                )
+            })
+            .map_err(map_query_error)?;
+        let result = PyDict::new_bound(py);
+        result.set_item("plan", estimate.plan)?;
+        result.set_item("columns", estimate.columns)?;
+        let table_rows = PyDict::new_bound(py);
+        for (table, rows) in estimate.table_rows {
+            table_rows.set_item(table, rows)?;
+        }
+        result.set_item("table_rows", table_rows)?;
+        result.set_item("rows", estimate.rows)?;
+        result.set_item("count_seconds", estimate.count_seconds)?;
+        result.set_item("catalog_row_bytes", estimate.catalog_row_bytes)?;
+        result.set_item("sampled_rows", estimate.sampled_rows)?;
+        result.set_item("sampled_row_bytes", estimate.sampled_row_bytes)?;
+        result.set_item("sample_seconds", estimate.sample_seconds)?;
+        result.set_item("estimated_bytes", estimate.estimated_bytes)?;
+        result.set_item("estimated_seconds", estimate.estimated_seconds)?;
+        result.set_item("within_budget", estimate.exceeded.is_empty())?;
+        result.set_item("exceeded", estimate.exceeded)?;
+        Ok(result.unbind())
+    }
+
+    // Server clock compared with the client clock, plus the session timezone when the server
+    // reports one (Firebird 4+)
+    fn server_time(&self, py: Python<'_>) -> PyResult<Py<PyDict>> {
+        let time = py
+            .allow_threads(|| {
+                clock::server_time(&self.dsn, &self.user, &self.password, &self.config)
+            })
+            .map_err(map_query_error)?;
+        let result = PyDict::new_bound(py);
+        result.set_item("server_time", time.server_time)?;
+        result.set_item("client_time", time.client_time)?;
+        result.set_item("skew_seconds", time.skew_seconds)?;
+        result.set_item("round_trip_seconds", time.round_trip_seconds)?;
+        result.set_item("utc_offset_minutes", time.utc_offset_minutes)?;
+        result.set_item("timezone", time.timezone)?;
+        Ok(result.unbind())
+    }
+
+    // Sets a context variable (RDB$SET_CONTEXT) on every connection this object opens from now
+    // on, retries and reconnects included. A None value stops setting it; values are sent as text.
+    #[pyo3(signature = (namespace, name, value))]
+    fn set_context(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        value: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<()> {
+        let value = value
+            .map(|value| value.str()?.extract::<String>())
+            .transpose()?;
+        context::set(&mut self.config, namespace, name, value)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    // Opens a read-only snapshot transaction on its own connection and returns it with its
+    // snapshot number (Firebird 4+). Connections configured with
+    // QueryConfig(snapshot_number=...) then read the same database state while it is open.
+    fn snapshot(&self, py: Python<'_>) -> PyResult<snapshot::Snapshot> {
+        py.allow_threads(|| {
+            snapshot::Snapshot::open(&self.dsn, &self.user, &self.password, &self.config)
+        })
+        .map_err(map_query_error)
+    }
+
+    // Applies pending versioned .sql files from `dir`, recording them in `table`
+    #[pyo3(signature = (dir, dry_run=false, table="IBARROW_MIGRATIONS", encoding="utf-8"))]
+    fn migrate(
+        &self,
+        py: Python<'_>,
+        dir: PathBuf,
+        dry_run: bool,
+        table: &str,
+        encoding: &str,
+    ) -> PyResult<Vec<String>> {
+        let migrations = migrate::load_migrations(&dir, encoding)
+            .map_err(|e| PyIOError::new_err(e.to_string()))?;
+        py.allow_threads(|| {
+            migrate::run_migrations(
+                &self.dsn,
+                &self.user,
+                &self.password,
+                &self.config,
+                &migrations,
+                table,
+                dry_run,
+            )
+        })
+        .map_err(map_query_error)
+    }
+
+    // Exports {name: sql} queries, run in one read-only snapshot, as a tar bundle of Parquet
+    // files plus manifest.json. `zone_map` maps table names to columns whose min/max are
+    // recorded in the manifest. Returns the manifest.
+    #[pyo3(signature = (queries, path, zone_map=None))]
+    fn export_bundle(
+        &self,
+        py: Python<'_>,
+        queries: &Bound<'_, PyDict>,
+        path: PathBuf,
+        zone_map: Option<HashMap<String, Vec<String>>>,
+    ) -> PyResult<Py<PyAny>> {
+        let queries: Vec<(String, String)> = block::ordered_items(Some(queries))?;
+        let zone_map = zone_map.unwrap_or_default();
+        bundle::validate_names(&queries)
+            .and_then(|_| bundle::validate_zone_map(&queries, &zone_map))
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let manifest = py
+            .allow_threads(|| {
+                bundle::export_bundle(
+                    &self.dsn,
+                    &self.user,
+                    &self.password,
+                    &self.config,
+                    &queries,
+                    &zone_map,
+                    &path,
+                )
+            })
+            .map_err(map_query_error)?;
+        json_to_py(py, &manifest)
+    }
+
+    // Runs the query once and returns an IbarrowResult to convert as needed
+    // `top_k` maps column names to how many of their most frequent values to track while
+    // fetching; the summary is available as `result.top_k`
+    #[pyo3(signature = (sql, params=None, max_rows=None, top_k=None))]
+    fn query(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        params: Option<QueryParams>,
+        max_rows: Option<u64>,
+        top_k: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<result::IbarrowResult> {
+        let (sql, params) =
+            params::bind(sql, params).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let top_k: Vec<(String, usize)> = block::ordered_items(top_k)?;
+        if top_k.iter().any(|(_, k)| *k == 0) {
+            return Err(PyValueError::new_err("top_k sizes must be greater than 0"));
+        }
+        // The result may be converted several ways, so "auto" cannot pick a consumer
+        let config = compression::for_consumer(py, &self.limited(max_rows), Consumer::Bytes)?;
+        let mut sketch = topk::TopK::new(&top_k);
+        let mut last = None;
+        let mut bytes = Vec::new();
+        let rowcount = py
+            .allow_threads(|| {
+                write_arrow_ipc_observed(
+                    &self.dsn,
+                    &self.user,
+                    &self.password,
+                    &sql,
+                    &params,
+                    &config,
+                    &mut bytes,
+                    &mut |batch| {
+                        last = Some(batch.clone());
+                        sketch.observe(batch)
+                    },
+                )
+            })
+            .map_err(map_query_error)?;
+        let summary = (!top_k.is_empty()).then(|| sketch.summary());
+        let categories = last
+            .as_ref()
+            .map(dictionary::categories)
+            .unwrap_or_default();
+        Ok(result::IbarrowResult::new(bytes, rowcount)?
+            .with_top_k(summary)
+            .with_dictionaries(categories))
+    }
+
+    // Splits the query into `num_partitions` ranges of an integer column between its MIN and
+    // MAX, reads them on separate connections in parallel and returns the concatenated result.
+    // With snapshot=True every connection reads at one Firebird 4 snapshot.
+    #[pyo3(signature = (sql, partition_column, num_partitions, max_rows=None, snapshot=false))]
+    fn read_partitioned(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        partition_column: &str,
+        num_partitions: usize,
+        max_rows: Option<u64>,
+        snapshot: bool,
+    ) -> PyResult<result::IbarrowResult> {
+        partition::validate(partition_column, num_partitions)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let config = compression::for_consumer(py, &self.limited(max_rows), Consumer::Bytes)?;
+        let partitioned = py
+            .allow_threads(|| {
+                partition::read_partitioned(
+                    &self.dsn,
+                    &self.user,
+                    &self.password,
+                    sql,
+                    partition_column,
+                    num_partitions,
+                    snapshot,
+                    &config,
+                )
+            })
+            .map_err(map_query_error)?;
+        Ok(
+            result::IbarrowResult::new(partitioned.ipc, partitioned.rowcount)?
+                .with_dictionaries(partitioned.dictionaries),
+        )
+    }
+
+    // Dask DataFrame with one range of `partition_column` per partition, read lazily by the
+    // Dask workers, which connect with the `credentials_env` variables (see
+    // distributed::query_dask)
+    #[pyo3(signature = (sql, partition_column, npartitions, dtype_backend="pyarrow", credentials_env="IBARROW"))]
+    fn query_dask(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        partition_column: &str,
+        npartitions: usize,
+        dtype_backend: &str,
+        credentials_env: &str,
+    ) -> PyResult<Py<PyAny>> {
+        distributed::query_dask(
+            py,
+            self,
+            sql,
+            partition_column,
+            npartitions,
+            dtype_backend,
+            credentials_env,
+        )
+    }
+
+    // Ray Dataset of Arrow blocks, one Ray task per range of `partition_column` (see
+    // distributed::to_ray_dataset)
+    #[pyo3(signature = (sql, partition_column, num_partitions, credentials_env="IBARROW"))]
+    fn to_ray_dataset(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        partition_column: &str,
+        num_partitions: usize,
+        credentials_env: &str,
+    ) -> PyResult<Py<PyAny>> {
+        distributed::to_ray_dataset(
+            py,
+            self,
+            sql,
+            partition_column,
+            num_partitions,
+            credentials_env,
+        )
+    }
+
+    // DB-API 2.0 cursor (see dbapi::Cursor)
+    fn cursor(slf: PyRef<'_, Self>) -> dbapi::Cursor {
+        dbapi::Cursor::new(slf.into())
+    }
+
+    // Tables and views as an IbarrowResult (see catalog::list_tables); `pattern` is a LIKE
+    // pattern for the names
+    #[pyo3(signature = (include_system=false, pattern=None))]
+    fn list_tables(
+        &self,
+        py: Python<'_>,
+        include_system: bool,
+        pattern: Option<&str>,
+    ) -> PyResult<Py<catalog::TableList>> {
+        catalog::list_tables(py, self, include_system, pattern)
+    }
+
+    // pyarrow.Schema of a table, each field carrying the driver's type for the column under
+    // its "db_type" metadata key
+    fn table_schema(&self, py: Python<'_>, table: &str) -> PyResult<Py<PyAny>> {
+        catalog::table_schema_py(py, self, table)
+    }
+
+    // Columns of a table's primary key (see catalog::constraints)
+    fn primary_keys(&self, py: Python<'_>, table: &str) -> PyResult<result::IbarrowResult> {
+        catalog::constraints(py, self, table, catalog::Constraints::PrimaryKeys)
+    }
+
+    // Foreign keys a table declares and the keys they reference
+    fn foreign_keys(&self, py: Python<'_>, table: &str) -> PyResult<result::IbarrowResult> {
+        catalog::constraints(py, self, table, catalog::Constraints::ForeignKeys)
+    }
+
+    // Indexes on a table, one row per indexed column
+    fn indexes(&self, py: Python<'_>, table: &str) -> PyResult<result::IbarrowResult> {
+        catalog::constraints(py, self, table, catalog::Constraints::Indexes)
+    }
+
+    // Names of the user tables and views, for Ibis' list_tables()
+    fn table_names(&self, py: Python<'_>) -> PyResult<Vec<String>> {
+        py.allow_threads(|| ibis::table_names(&self.dsn, &self.user, &self.password, &self.config))
+            .map_err(map_query_error)
+    }
+
+    // Column names and Ibis dtypes ("!" marks NOT NULL columns) of a table or query, read
+    // without fetching rows
+    fn get_schema<'py>(&self, py: Python<'py>, table_or_sql: &str) -> PyResult<Bound<'py, PyDict>> {
+        if table_or_sql.trim().is_empty() {
+            return Err(PyValueError::new_err("table_or_sql must not be empty"));
+        }
+        let source = scan::source(table_or_sql);
+        let schema = py
+            .allow_threads(|| {
+                scan::schema(&self.dsn, &self.user, &self.password, &source, &self.config)
+            })
+            .map_err(map_query_error)?;
+        let types = PyDict::new_bound(py);
+        for (name, dtype) in ibis::schema_types(&schema) {
+            types.set_item(name, dtype)?;
+        }
+        Ok(types)
+    }
+
+    // Runs a .sql file (with @include lines and comments stripped) and returns a Polars DataFrame
+    #[pyo3(signature = (path, params=None, encoding="utf-8", max_rows=None))]
+    fn query_file(
+        &self,
+        py: Python<'_>,
+        path: PathBuf,
+        params: Option<QueryParams>,
+        encoding: &str,
+        max_rows: Option<u64>,
+    ) -> PyResult<Py<PyAny>> {
+        let sql = sqlfile::load_sql_file(&path, encoding)
+            .map_err(|e| PyIOError::new_err(e.to_string()))?;
+        let (sql, params) =
+            params::bind(&sql, params).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let config = compression::for_consumer(py, &self.limited(max_rows), Consumer::Polars)?;
+
+        let mut bytes = Vec::new();
+        py.allow_threads(|| {
+            write_arrow_ipc_impl(
+                &self.dsn,
+                &self.user,
+                &self.password,
+                &sql,
+                &params,
+                &config,
+                &mut bytes,
+            )
+        })
+        .map_err(map_query_error)?;
+        polars_from_ipc(py, &bytes)
+    }
+
+    // Iterator of pyarrow.RecordBatch objects with at most `batch_size` rows each. Only
+    // `prefetch` batches are held in memory; fetching pauses until the consumer asks for more.
+    #[pyo3(signature = (sql, batch_size=None, prefetch=1, max_rows=None))]
+    fn fetch_batches(
+        &self,
+        sql: &str,
+        batch_size: Option<usize>,
+        prefetch: usize,
+        max_rows: Option<u64>,
+    ) -> PyResult<batches::BatchIterator> {
+        if batch_size == Some(0) {
+            return Err(PyValueError::new_err("batch_size must be greater than 0"));
+        }
+        Ok(batches::BatchIterator::start(
             &self.dsn,
             &self.user,
             &self.password,
-            "SELECT 1 as test_value FROM RDB$DATABASE",
-            &self.config,
-        ) {
+            sql,
+            &self.limited(max_rows),
+            batch_size,
+            prefetch,
+        ))
+    }
+
+    // Executes the query and returns an ArrowStream exporting the live cursor through the Arrow
+    // PyCapsule interface, for lazy consumers that read it later. The connection is held until
+    // the stream is read to the end, released or closed, or left idle for `idle_timeout` seconds.
+    // With `materialize` every batch is fetched now and no connection is held.
+    #[pyo3(signature = (sql, idle_timeout=Some(300.0), max_rows=None, materialize=false))]
+    fn arrow_stream(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        idle_timeout: Option<f64>,
+        max_rows: Option<u64>,
+        materialize: bool,
+    ) -> PyResult<stream::ArrowStream> {
+        let idle_timeout = idle_timeout
+            .map(Duration::try_from_secs_f64)
+            .transpose()
+            .map_err(|e| PyValueError::new_err(format!("Invalid idle_timeout: {}", e)))?;
+        let config = self.limited(max_rows);
+        py.allow_threads(|| {
+            stream::ArrowStream::open(
+                &self.dsn,
+                &self.user,
+                &self.password,
+                sql,
+                &config,
+                idle_timeout,
+                materialize,
+            )
+        })
+        .map_err(map_query_error)
+    }
+
+    // Executes the query on its own connection with a scrollable cursor, so pages can be read
+    // with fetch(n) and revisited with seek(offset) without running the query again. Raises
+    // an error if the driver only offers forward-only cursors.
+    fn scroll_cursor(&self, py: Python<'_>, sql: &str) -> PyResult<scroll::ScrollCursor> {
+        py.allow_threads(|| {
+            scroll::ScrollCursor::open(&self.dsn, &self.user, &self.password, sql, &self.config)
+        })
+        .map_err(map_query_error)
+    }
+
+    // Builds and runs an EXECUTE BLOCK; rows emitted with SUSPEND come back as a Polars DataFrame
+    #[pyo3(signature = (body, params=None, returns=None, declare=None, param_types=None))]
+    fn execute_block(
+        &self,
+        py: Python<'_>,
+        body: &str,
+        params: Option<&Bound<'_, PyDict>>,
+        returns: Option<&Bound<'_, PyDict>>,
+        declare: Option<&Bound<'_, PyDict>>,
+        param_types: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Py<PyAny>> {
+        let params: Vec<(String, SqlParam)> = block::ordered_items(params)?;
+        let (sql, values) = block::build_execute_block(
+            body,
+            &params,
+            &block::ordered_items(param_types)?,
+            &block::ordered_items(returns)?,
+            &block::ordered_items(declare)?,
+        )
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        // EXECUTE BLOCK cannot be wrapped in a derived table, so restarts do not apply
+        let config = QueryConfig {
+            max_restarts: None,
+            ..compression::for_consumer(py, &self.config, Consumer::Polars)?
+        };
+        let mut bytes = Vec::new();
+        py.allow_threads(|| {
+            write_arrow_ipc_impl(
+                &self.dsn,
+                &self.user,
+                &self.password,
+                &sql,
+                &values,
+                &config,
+                &mut bytes,
+            )
+        })
+        .map_err(map_query_error)?;
+        polars_from_ipc(py, &bytes)
+    }
+
+    // Runs the queries concurrently using ODBC asynchronous execution and returns one
+    // Polars DataFrame per query, in order
+    #[pyo3(signature = (queries, poll_interval_ms=10))]
+    fn query_many(
+        &self,
+        py: Python<'_>,
+        queries: Vec<String>,
+        poll_interval_ms: u64,
+    ) -> PyResult<Py<PyList>> {
+        let queries: Vec<(String, Vec<SqlParam>)> =
+            queries.into_iter().map(|sql| (sql, Vec::new())).collect();
+        let config = compression::for_consumer(py, &self.config, Consumer::Polars)?;
+        let results = py
+            .allow_threads(|| {
+                polling::query_many_ipc(
+                    &self.dsn,
+                    &self.user,
+                    &self.password,
+                    &queries,
+                    &config,
+                    Duration::from_millis(poll_interval_ms),
+                )
+            })
+            .map_err(map_query_error)?;
+        let frames = results
+            .iter()
+            .map(|bytes| polars_from_ipc(py, bytes))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyList::new_bound(py, frames).unbind())
+    }
+
+    // Runs {name: sql} queries concurrently, each on its own connection with at most
+    // `max_workers` open at once, and returns {name: Polars DataFrame}
+    #[pyo3(signature = (queries, max_workers=8, max_rows=None))]
+    fn run_many(
+        &self,
+        py: Python<'_>,
+        queries: &Bound<'_, PyDict>,
+        max_workers: usize,
+        max_rows: Option<u64>,
+    ) -> PyResult<Py<PyDict>> {
+        let queries: Vec<(String, String)> = block::ordered_items(Some(queries))?;
+        many::validate(max_workers).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let config = compression::for_consumer(py, &self.limited(max_rows), Consumer::Polars)?;
+        let results = py
+            .allow_threads(|| {
+                many::run_many(
+                    &self.dsn,
+                    &self.user,
+                    &self.password,
+                    &queries,
+                    &config,
+                    max_workers,
+                )
+            })
+            .map_err(map_query_error)?;
+        let frames = PyDict::new_bound(py);
+        for ((name, _), bytes) in queries.iter().zip(&results) {
+            frames.set_item(name, polars_from_ipc(py, bytes)?)?;
+        }
+        Ok(frames.unbind())
+    }
+
+    // Runs the named extracts of a YAML or JSON manifest, up to `max_workers` at a time
+    // (default: the manifest's max_workers, else 4), and returns a summary report. `only`
+    // restricts the run to some of the extracts.
+    #[pyo3(signature = (path, max_workers=None, only=None))]
+    fn run_manifest(
+        &self,
+        py: Python<'_>,
+        path: PathBuf,
+        max_workers: Option<usize>,
+        only: Option<Vec<String>>,
+    ) -> PyResult<Py<PyDict>> {
+        let manifest = manifest::load(py, &path).map_err(map_query_error)?;
+        let started = Instant::now();
+        let outcomes = py
+            .allow_threads(|| {
+                manifest::run(
+                    &self.dsn,
+                    &self.user,
+                    &self.password,
+                    &manifest,
+                    &self.config,
+                    max_workers,
+                    only.as_deref(),
+                )
+            })
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        manifest::report(py, &outcomes, started.elapsed().as_secs_f64())
+    }
+
+    // Bulk-inserts Arrow data into an existing table with array-bound parameters, in one
+    // transaction, and returns the rows inserted. `data` is Arrow IPC stream bytes or any object
+    // with __arrow_c_stream__; its column names are the table's. `mode` is "append",
+    // "truncate" (delete the table's rows first) or "replace" (recreate it from the data).
+    #[pyo3(signature = (table_name, data, mode="append"))]
+    fn write_arrow(
+        &self,
+        py: Python<'_>,
+        table_name: &str,
+        data: &Bound<'_, PyAny>,
+        mode: &str,
+    ) -> PyResult<usize> {
+        if table_name.trim().is_empty() {
+            return Err(PyValueError::new_err("table_name must not be empty"));
+        }
+        let mode = write::Mode::parse(mode).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let batches = write::batches_from_py(data)?;
+        let target = write::Target::insert(table_name, write::Columns::Matched, mode);
+        self.write(py, target, batches)
+    }
+
+    // Bulk UPDATE OR INSERT of Arrow data: rows whose `key_columns` match an existing row
+    // update it, the others are inserted. Data and mode as for write_arrow; returns the rows
+    // written.
+    #[pyo3(signature = (table, data, key_columns, mode="append"))]
+    fn upsert_arrow(
+        &self,
+        py: Python<'_>,
+        table: &str,
+        data: &Bound<'_, PyAny>,
+        key_columns: Vec<String>,
+        mode: &str,
+    ) -> PyResult<usize> {
+        if table.trim().is_empty() {
+            return Err(PyValueError::new_err("table must not be empty"));
+        }
+        if key_columns.is_empty() {
+            return Err(PyValueError::new_err("key_columns must not be empty"));
+        }
+        let mode = write::Mode::parse(mode).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let batches = write::batches_from_py(data)?;
+        let target = write::Target {
+            table,
+            columns: write::Columns::Matched,
+            mode,
+            matching: &key_columns,
+        };
+        self.write(py, target, batches)
+    }
+
+    // Bulk-inserts a Polars DataFrame (or LazyFrame) into `table` like write_arrow, matching
+    // its columns to the table's ignoring case. `coerce` casts them to the table's types first;
+    // `mode` as for write_arrow.
+    #[pyo3(signature = (df, table, coerce=false, mode="append"))]
+    fn write_polars(
+        &self,
+        py: Python<'_>,
+        df: &Bound<'_, PyAny>,
+        table: &str,
+        coerce: bool,
+        mode: &str,
+    ) -> PyResult<usize> {
+        let mode = write::Mode::parse(mode).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let batches = write::polars_batches(df)?;
+        self.write_frame(py, table, batches, coerce, mode)
+    }
+
+    // write_polars for a pandas DataFrame; the index is not written
+    #[pyo3(signature = (df, table, coerce=false, mode="append"))]
+    fn write_pandas(
+        &self,
+        py: Python<'_>,
+        df: &Bound<'_, PyAny>,
+        table: &str,
+        coerce: bool,
+        mode: &str,
+    ) -> PyResult<usize> {
+        let mode = write::Mode::parse(mode).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let batches = write::pandas_batches(df)?;
+        self.write_frame(py, table, batches, coerce, mode)
+    }
+
+    // Streams a Parquet or CSV file into `table` through write_arrow, matching its columns to
+    // the table's ignoring case. `format` defaults to the file extension; `coerce` casts the
+    // columns to the table's types, reading CSV values as text first; `mode` as for write_arrow.
+    #[pyo3(signature = (path, table, format=None, delimiter=",", coerce=false, mode="append"))]
+    #[allow(clippy::too_many_arguments)]
+    fn load_file(
+        &self,
+        py: Python<'_>,
+        path: PathBuf,
+        table: &str,
+        format: Option<&str>,
+        delimiter: &str,
+        coerce: bool,
+        mode: &str,
+    ) -> PyResult<usize> {
+        let format = load::FileFormat::resolve(format, &path)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let delimiter = match delimiter.as_bytes() {
+            [byte] if byte.is_ascii() => *byte,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "delimiter must be a single ASCII character, got {:?}",
+                    delimiter
+                )))
+            }
+        };
+        let mode = write::Mode::parse(mode).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let batch_rows = self
+            .config
+            .batch_size
+            .map_or(write::WRITE_BATCH_ROWS, |rows| rows as usize);
+        let batches = load::open(&path, format, delimiter, coerce, batch_rows)
+            .map_err(|e| PyIOError::new_err(e.to_string()))?;
+        self.write_frame(py, table, batches, coerce, mode)
+    }
+
+    // Runs a list of statements (SQL strings or (sql, params) pairs) in one transaction and
+    // returns the rows each affected, or calls `fn_or_statements(tx)` with a Transaction handle
+    // and returns what it returns. Commits at the end; any error rolls everything back.
+    fn run_in_transaction(
+        &self,
+        py: Python<'_>,
+        fn_or_statements: &Bound<'_, PyAny>,
+    ) -> PyResult<Py<PyAny>> {
+        if fn_or_statements.is_callable() {
+            let tx = py
+                .allow_threads(|| {
+                    transaction::Transaction::open(
+                        &self.dsn,
+                        &self.user,
+                        &self.password,
+                        &self.config,
+                    )
+                })
+                .map_err(map_query_error)?;
+            let tx = Bound::new(py, tx)?;
+            let outcome = fn_or_statements.call1((tx.clone(),));
+            let guard = tx.borrow();
+            let handle: &transaction::Transaction = &guard;
+            let commit = outcome.is_ok();
+            let finished = py.allow_threads(|| handle.finish(commit));
+            return match (outcome, finished) {
+                (Ok(value), Ok(())) => Ok(value.unbind()),
+                (Ok(_), Err(e)) => Err(map_query_error(e)),
+                (Err(err), finished) => {
+                    if let Err(e) = finished {
+                        eprintln!("WARN: Failed to roll back the transaction: {}", e);
+                    }
+                    Err(err)
+                }
+            };
+        }
+        if fn_or_statements.is_instance_of::<pyo3::types::PyString>() {
+            return Err(pyo3::exceptions::PyTypeError::new_err(
+                "run_in_transaction takes a list of statements or a callable, not a string",
+            ));
+        }
+        let statements = transaction::statements(fn_or_statements)?;
+        if statements.is_empty() {
+            return Ok(PyList::empty_bound(py).into_any().unbind());
+        }
+        let counts = py
+            .allow_threads(|| {
+                transaction::run_statements(
+                    &self.dsn,
+                    &self.user,
+                    &self.password,
+                    &statements,
+                    &self.config,
+                )
+            })
+            .map_err(map_query_error)?;
+        Ok(counts.into_py(py))
+    }
+
+    fn test_connection(&self, py: Python<'_>) -> PyResult<bool> {
+        // Test connection with a query that always returns data
+        // Use RDB$DATABASE which exists in all Firebird/InterBase databases
+        match py.allow_threads(|| {
+            query_arrow_ipc_impl(
+                &self.dsn,
+                &self.user,
+                &self.password,
+                "SELECT 1 as test_value FROM RDB$DATABASE",
+                &self.config,
+            )
+        }) {
             Ok(_) => Ok(true),
             Err(_) => Ok(false),
         }
     }
 
-    fn close(&self) -> PyResult<()> {
-        // ibarrow uses stateless connections, so close() is a no-op
-        // This method exists for compatibility with database connection patterns
-        Ok(())
+    // End the DB-API transaction cursor statements opened; other methods commit on their own
+    fn commit(&self, py: Python<'_>) -> PyResult<()> {
+        py.allow_threads(|| dbapi::end_transaction(self, true))
+            .map_err(map_query_error)
+    }
+
+    fn rollback(&self, py: Python<'_>) -> PyResult<()> {
+        py.allow_threads(|| dbapi::end_transaction(self, false))
+            .map_err(map_query_error)
+    }
+
+    // Server version as a tuple of numbers, e.g. (4, 0, 2)
+    fn server_version<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyTuple>> {
+        let version = py
+            .allow_threads(|| {
+                dialect::server_version(&self.dsn, &self.user, &self.password, &self.config)
+            })
+            .map_err(map_query_error)?;
+        Ok(PyTuple::new_bound(py, version))
+    }
+
+    // Starts an HTTP query service on a background thread: POST SQL (or JSON with sql, params,
+    // format and max_rows) to /query for an Arrow IPC stream or Parquet file. Port 0 picks a
+    // free port; with `token`, clients must send `Authorization: Bearer <token>`. The service
+    // is read-only unless `read_only` is False, and browsers may only call it from
+    // `allowed_origins`.
+    #[pyo3(signature = (host="127.0.0.1", port=8000, token=None, max_connections=8, read_only=true, allowed_origins=None))]
+    fn serve(
+        &self,
+        host: &str,
+        port: u16,
+        token: Option<String>,
+        max_connections: usize,
+        read_only: bool,
+        allowed_origins: Option<Vec<String>>,
+    ) -> PyResult<server::QueryServer> {
+        check_driver_manager(Some(&self.config))?;
+        let config = QueryConfig {
+            read_only: read_only || self.config.read_only,
+            ..self.config.clone()
+        };
+        server::QueryServer::start(
+            &self.dsn,
+            &self.user,
+            &self.password,
+            config,
+            host,
+            port,
+            token,
+            allowed_origins.unwrap_or_default(),
+            max_connections,
+        )
+    }
+
+    // Rolls back the DB-API transaction, if one is open; every other call is stateless
+    fn close(&self, py: Python<'_>) -> PyResult<()> {
+        py.allow_threads(|| dbapi::end_transaction(self, false))
+            .map_err(map_query_error)
     }
 
     fn __repr__(&self) -> String {
@@ -177,49 +1466,6 @@ impl IbarrowConnection {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[pyclass]
-pub struct QueryConfig {
-    #[pyo3(get, set)]
-    pub batch_size: Option<u32>,
-    #[pyo3(get, set)]
-    pub max_text_size: Option<u32>,
-    #[pyo3(get, set)]
-    pub max_binary_size: Option<u32>,
-    #[pyo3(get, set)]
-    pub read_only: bool,
-    #[pyo3(get, set)]
-    pub connection_timeout: Option<u32>,
-    #[pyo3(get, set)]
-    pub query_timeout: Option<u32>,
-    #[pyo3(get, set)]
-    pub isolation_level: Option<String>,
-}
-
-#[pymethods]
-impl QueryConfig {
-    #[new]
-    fn new(
-        batch_size: Option<u32>,
-        max_text_size: Option<u32>,
-        max_binary_size: Option<u32>,
-        read_only: Option<bool>,
-        connection_timeout: Option<u32>,
-        query_timeout: Option<u32>,
-        isolation_level: Option<String>,
-    ) -> Self {
-        Self {
-            batch_size,
-            max_text_size,
-            max_binary_size,
-            read_only: read_only.unwrap_or(false),
-            connection_timeout,
-            query_timeout,
-            isolation_level,
-        }
-    }
-}
-
 // Implementation function for Arrow IPC
 fn query_arrow_ipc_impl(
     dsn: &str,
@@ -228,230 +1474,383 @@ fn query_arrow_ipc_impl(
     sql: &str,
     config: &QueryConfig,
 ) -> Result<Vec<u8>> {
-    let env = Environment::new()?;
-
-    // Build connection string with long DSN name handling
-    let conn_str = build_connection_string(dsn, user, password, config);
-
-    let conn = env.connect_with_connection_string(&conn_str, ConnectionOptions::default())?;
-
-    let cursor = match conn.execute(sql, (), None)? {
-        Some(cursor) => cursor,
-        None => {
-            // Query executed successfully but returned no result set
-            // Return a valid empty Arrow stream with empty schema
-            eprintln!("DEBUG: Creating empty Arrow stream for cursor None");
-            let mut bytes = Vec::<u8>::new();
-            use arrow::datatypes::Schema;
-            let schema = Schema::empty();
-            let schema_ref = std::sync::Arc::new(schema);
-
-            let mut writer = StreamWriter::try_new(&mut bytes, &schema_ref).map_err(|e| {
-                anyhow!(
-                    "ERROR: Failed to create StreamWriter for empty schema: {}",
-                    e
-                )
-            })?;
-            let empty_batch = arrow::record_batch::RecordBatch::new_empty(schema_ref);
-            writer
-                .write(&empty_batch)
-                .map_err(|e| anyhow!("ERROR: Failed to write empty batch: {}", e))?;
-            writer
-                .finish()
-                .map_err(|e| anyhow!("ERROR: Failed to finish empty stream writer: {}", e))?;
-            eprintln!(
-                "DEBUG: Successfully created empty Arrow stream ({} bytes)",
-                bytes.len()
-            );
-            return Ok(bytes);
-        }
-    };
-
-    let text_size = config.max_text_size.unwrap_or(65536);
-    let binary_size = config.max_binary_size.unwrap_or(65536);
-
-    let mut builder = OdbcReaderBuilder::new();
-    builder.with_max_text_size(text_size as usize);
-    builder.with_max_binary_size(binary_size as usize);
-
-    let arrow_record_batches = builder.build(cursor)?;
-
     let mut bytes = Vec::<u8>::new();
-    {
-        let schema = arrow_record_batches.schema();
-        eprintln!(
-            "DEBUG: Creating StreamWriter with schema: {} fields",
-            schema.fields().len()
-        );
+    write_arrow_ipc_impl(dsn, user, password, sql, &[], config, &mut bytes)?;
+    Ok(bytes)
+}
 
-        // Pipelining: write each batch immediately as it's fetched
-        // This keeps memory usage constant instead of accumulating all data
-        let mut writer = StreamWriter::try_new(&mut bytes, &schema)
-            .map_err(|e| anyhow!("ERROR: Failed to create StreamWriter: {}", e))?;
-
-        let mut wrote = false;
-        let mut batch_count = 0;
-        for batch in arrow_record_batches {
-            let batch =
-                batch.map_err(|e| anyhow!("ERROR: Failed to read batch {}: {}", batch_count, e))?;
-            writer
-                .write(&batch)
-                .map_err(|e| anyhow!("ERROR: Failed to write batch {}: {}", batch_count, e))?;
-            wrote = true;
-            batch_count += 1;
-            // Each batch is written immediately, freeing memory
-            // Memory usage stays constant regardless of dataset size
-        }
+// Streams the query result as an Arrow IPC stream into any writer and
+// returns the number of rows written
+#[allow(clippy::too_many_arguments)]
+fn write_arrow_ipc_impl<W: Write>(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    params: &[SqlParam],
+    config: &QueryConfig,
+    out: &mut W,
+) -> Result<usize> {
+    write_arrow_ipc_observed(dsn, user, password, sql, params, config, out, &mut |_| {
+        Ok(())
+    })
+}
 
-        // If no data was written, write an empty batch to ensure valid stream
-        if !wrote {
-            eprintln!("DEBUG: No data batches, writing empty batch");
-            use arrow::record_batch::RecordBatch;
-            let empty_batch = RecordBatch::new_empty(schema.clone());
-            writer
-                .write(&empty_batch)
-                .map_err(|e| anyhow!("ERROR: Failed to write empty batch: {}", e))?;
-        } else {
-            eprintln!("DEBUG: Wrote {} data batches", batch_count);
-        }
+// Like write_arrow_ipc_impl, handing every written batch to `observe` as well
+#[allow(clippy::too_many_arguments)]
+fn write_arrow_ipc_observed<W: Write>(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    params: &[SqlParam],
+    config: &QueryConfig,
+    out: &mut W,
+    observe: &mut dyn FnMut(&RecordBatch) -> Result<()>,
+) -> Result<usize> {
+    let mut sink = IpcSink::new(out, compression::write_options(config)?);
+    fetch_into(dsn, user, password, sql, params, config, &mut sink, observe)
+}
 
-        // Always finish the writer to ensure proper footer - guaranteed execution
-        writer
-            .finish()
-            .map_err(|e| anyhow!("ERROR: Failed to finish StreamWriter: {}", e))?;
-        eprintln!(
-            "DEBUG: Successfully finished Arrow stream ({} bytes)",
-            bytes.len()
-        );
+// Runs `sql` and hands the result to `sink` batch by batch, observing every batch. With
+// QueryConfig.worker_process the ODBC work happens in a child process.
+#[allow(clippy::too_many_arguments)]
+fn fetch_into(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    params: &[SqlParam],
+    config: &QueryConfig,
+    sink: &mut dyn BatchSink,
+    observe: &mut dyn FnMut(&RecordBatch) -> Result<()>,
+) -> Result<usize> {
+    if config.worker_process {
+        return worker::fetch_into(dsn, user, password, sql, params, config, sink, observe);
     }
-
-    Ok(bytes)
+    ibarrow_core::fetch_into(dsn, user, password, sql, params, config, sink, observe)
 }
 
 // Implementation function for Polars
 fn query_polars_impl(
+    py: Python<'_>,
     dsn: &str,
     user: &str,
     password: &str,
     sql: &str,
     config: &QueryConfig,
 ) -> PyResult<Py<PyAny>> {
-    // High-level wrapper: use Arrow IPC for maximum compatibility with Polars
     eprintln!("DEBUG: query_polars_impl called");
-    let bytes = query_arrow_ipc_impl(dsn, user, password, sql, config).map_err(|e| {
-        let msg = e.to_string();
+    // Recent Polars takes the fetched batches through the Arrow C stream without a copy.
+    // Spilling needs the IPC stream, as do older Polars versions.
+    if config.spill_threshold.is_none() && interop::polars_reads_c_stream(py) {
+        let (schema, batches) = py
+            .allow_threads(|| collect_batches(dsn, user, password, sql, config))
+            .map_err(|e| {
+                eprintln!("ERROR: query_polars_impl - collect_batches failed: {}", e);
+                map_query_error(e)
+            })?;
         eprintln!(
-            "ERROR: query_polars_impl - query_arrow_ipc_impl failed: {}",
-            msg
+            "DEBUG: Handing {} batches to Polars through the C stream",
+            batches.len()
         );
-        if msg.contains("IM002") || msg.contains("connection") {
-            PyConnectionError::new_err(format!("Connection Error: {}", msg))
-        } else if msg.contains("SQL") || msg.contains("syntax") {
-            PySQLError::new_err(format!("SQL Error: {}", msg))
-        } else if msg.contains("Arrow") || msg.contains("c_data") {
-            PyArrowError::new_err(format!("Arrow Error: {}", msg))
-        } else {
-            PyRuntimeError::new_err(msg)
-        }
+        return interop::polars_from_batches(py, schema, batches);
+    }
+
+    // Use Arrow IPC for maximum compatibility with Polars
+    let config = compression::for_consumer(py, config, Consumer::Polars)?;
+    let result = py
+        .allow_threads(|| spill::query(dsn, user, password, sql, &config))
+        .map_err(|e| {
+            eprintln!(
+                "ERROR: query_polars_impl - query_arrow_ipc_impl failed: {}",
+                e
+            );
+            map_query_error(e)
+        })?;
+
+    match result {
+        Spilled::Memory(bytes) => polars_from_ipc(py, &bytes),
+        Spilled::File(path) => spill::map_polars(py, &path),
+    }
+}
+
+// Runs `sql` and keeps every batch in memory as fetched
+fn collect_batches(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+) -> Result<(SchemaRef, Vec<RecordBatch>)> {
+    let mut collected = Collected::default();
+    fetch_into(
+        dsn,
+        user,
+        password,
+        sql,
+        &[],
+        config,
+        &mut collected,
+        &mut |_| Ok(()),
+    )?;
+    let schema = collected
+        .schema
+        .unwrap_or_else(|| std::sync::Arc::new(Schema::empty()));
+    Ok((schema, collected.batches))
+}
+
+// Return Polars DataFrame directly from Arrow IPC bytes
+fn polars_from_ipc(py: Python<'_>, bytes: &[u8]) -> PyResult<Py<PyAny>> {
+    eprintln!(
+        "DEBUG: Converting {} bytes to Polars DataFrame",
+        bytes.len()
+    );
+    let polars = py.import_bound("polars")?;
+    let io = py.import_bound("io")?;
+
+    // Create BytesIO object for polars.read_ipc
+    let py_bytes = PyBytes::new_bound(py, bytes);
+    let buf = io.getattr("BytesIO")?.call1((py_bytes,))?;
+
+    // Use polars.read_ipc with proper error handling
+    eprintln!("DEBUG: Calling polars.read_ipc");
+    let df = polars.getattr("read_ipc")?.call1((buf,)).map_err(|e| {
+        eprintln!("ERROR: polars.read_ipc failed: {}", e);
+        e
     })?;
+    eprintln!("DEBUG: Successfully created Polars DataFrame");
+    Ok(df.into())
+}
 
-    // Return Polars DataFrame directly from Arrow IPC bytes
-    Python::with_gil(|py| {
-        eprintln!(
-            "DEBUG: Converting {} bytes to Polars DataFrame",
-            bytes.len()
-        );
-        let polars = py.import_bound("polars")?;
-        let io = py.import_bound("io")?;
+// Reads an Arrow IPC stream into a Pandas DataFrame via pyarrow
+fn pandas_from_ipc(py: Python<'_>, bytes: &[u8]) -> PyResult<Py<PyAny>> {
+    table_to_pandas(py, pyarrow_table_from_ipc(py, bytes)?, DtypeBackend::Numpy)
+}
 
-        // Create BytesIO object for polars.read_ipc
-        let py_bytes = PyBytes::new_bound(py, &bytes);
-        let buf = io.getattr("BytesIO")?.call1((py_bytes,))?;
+// Reads an Arrow IPC stream into a Pandas DataFrame with pyarrow-backed columns
+fn pandas_arrow_from_ipc(py: Python<'_>, bytes: &[u8]) -> PyResult<Py<PyAny>> {
+    table_to_pandas(
+        py,
+        pyarrow_table_from_ipc(py, bytes)?,
+        DtypeBackend::PyArrow,
+    )
+}
+
+// Streams `sql` once, routing each row by a seeded hash of its values into one part per
+// fraction of `split`, and returns the parts converted by `convert` as a list
+fn query_split_impl(
+    py: Python<'_>,
+    conn: &IbarrowConnection,
+    sql: &str,
+    config: &QueryConfig,
+    split: &[f64],
+    seed: u64,
+    convert: fn(Python<'_>, &[u8]) -> PyResult<Py<PyAny>>,
+) -> PyResult<Py<PyAny>> {
+    split::validate(split).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let parts = py
+        .allow_threads(|| {
+            split::query(
+                &conn.dsn,
+                &conn.user,
+                &conn.password,
+                sql,
+                config,
+                split,
+                seed,
+            )
+        })
+        .map_err(map_query_error)?;
+    let list = PyList::empty_bound(py);
+    for part in &parts {
+        list.append(convert(py, part)?)?;
+    }
+    Ok(list.into_any().unbind())
+}
 
-        // Use polars.read_ipc with proper error handling
-        eprintln!("DEBUG: Calling polars.read_ipc");
-        let df = polars.getattr("read_ipc")?.call1((buf,)).map_err(|e| {
-            eprintln!("ERROR: polars.read_ipc failed: {}", e);
+// Reads an Arrow IPC stream into a pyarrow.Table
+fn pyarrow_table_from_ipc(py: Python<'_>, bytes: &[u8]) -> PyResult<Py<PyAny>> {
+    let pyarrow = py.import_bound("pyarrow")?;
+    let io = py.import_bound("io")?;
+
+    let py_bytes = PyBytes::new_bound(py, bytes);
+    let buf = io.getattr("BytesIO")?.call1((py_bytes,))?;
+
+    eprintln!("DEBUG: Opening PyArrow IPC stream");
+    let table = pyarrow
+        .getattr("ipc")?
+        .getattr("open_stream")?
+        .call1((buf,))?
+        .getattr("read_all")?
+        .call0()
+        .map_err(|e| {
+            eprintln!("ERROR: PyArrow IPC read_all failed: {}", e);
             e
         })?;
-        eprintln!("DEBUG: Successfully created Polars DataFrame");
-        Ok(df.into())
-    })
+    Ok(table.unbind())
 }
 
 // Implementation function for Pandas
 fn query_pandas_impl(
+    py: Python<'_>,
     dsn: &str,
     user: &str,
     password: &str,
     sql: &str,
     config: &QueryConfig,
+    backend: DtypeBackend,
 ) -> PyResult<Py<PyAny>> {
-    // High-level wrapper: use Arrow IPC for maximum compatibility with Pandas
     eprintln!("DEBUG: query_pandas_impl called");
-    let bytes = query_arrow_ipc_impl(dsn, user, password, sql, config).map_err(|e| {
-        let msg = e.to_string();
-        eprintln!(
-            "ERROR: query_pandas_impl - query_arrow_ipc_impl failed: {}",
-            msg
-        );
-        if msg.contains("IM002") || msg.contains("connection") {
-            PyConnectionError::new_err(format!("Connection Error: {}", msg))
-        } else if msg.contains("SQL") || msg.contains("syntax") {
-            PySQLError::new_err(format!("SQL Error: {}", msg))
-        } else if msg.contains("Arrow") || msg.contains("c_data") {
-            PyArrowError::new_err(format!("Arrow Error: {}", msg))
-        } else {
-            PyRuntimeError::new_err(msg)
-        }
-    })?;
-    Python::with_gil(|py| {
+    if capsule_interop(py, config, backend)? {
+        let (schema, batches) = py
+            .allow_threads(|| collect_batches(dsn, user, password, sql, config))
+            .map_err(map_query_error)?;
+        return interop::pandas_from_batches(py, schema, batches);
+    }
+    let table = query_pyarrow_impl(py, dsn, user, password, sql, config)?;
+    table_to_pandas(py, table, backend)
+}
+
+// Whether pandas is built through Arrow PyCapsules rather than pyarrow; pandas.ArrowDtype
+// columns are pyarrow arrays, so they cannot be
+fn capsule_interop(py: Python<'_>, config: &QueryConfig, backend: DtypeBackend) -> PyResult<bool> {
+    interop::validate(config).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let mode = interop::mode(config).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    if mode == interop::Interop::Auto && backend == DtypeBackend::PyArrow {
+        return Ok(false);
+    }
+    let capsule = interop::resolve(py, config).map_err(|e| PyValueError::new_err(e.to_string()))?
+        == interop::Interop::Capsule;
+    if capsule && backend == DtypeBackend::PyArrow {
+        return Err(PyValueError::new_err(
+            "dtype_backend='pyarrow' needs pyarrow and cannot be used with arrow_interop='capsule'",
+        ));
+    }
+    Ok(capsule)
+}
+
+// Reads an Arrow IPC stream into a Pandas DataFrame through Arrow PyCapsules, without pyarrow
+fn pandas_capsule_from_ipc(py: Python<'_>, bytes: &[u8]) -> PyResult<Py<PyAny>> {
+    let reader = arrow_ipc::reader::StreamReader::try_new(std::io::Cursor::new(bytes), None)
+        .map_err(|e| map_query_error(e.into()))?;
+    let schema = reader.schema();
+    let batches = reader
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| map_query_error(e.into()))?;
+    interop::pandas_from_batches(py, schema, batches)
+}
+
+// Runs `sql` into a pyarrow.Table
+fn query_pyarrow_impl(
+    py: Python<'_>,
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+) -> PyResult<Py<PyAny>> {
+    // pyarrow 15+ imports the fetched batches through the Arrow C stream without a copy.
+    // Spilling needs the IPC stream, as do older pyarrow versions.
+    if config.spill_threshold.is_none() && interop::pyarrow_reads_c_stream(py) {
+        let (schema, batches) = py
+            .allow_threads(|| collect_batches(dsn, user, password, sql, config))
+            .map_err(|e| {
+                eprintln!("ERROR: query_pyarrow_impl - collect_batches failed: {}", e);
+                map_query_error(e)
+            })?;
         eprintln!(
-            "DEBUG: Converting {} bytes to Pandas DataFrame via PyArrow",
-            bytes.len()
+            "DEBUG: Handing {} batches to PyArrow through the C stream",
+            batches.len()
         );
-        let pyarrow = py.import_bound("pyarrow")?;
-        let io = py.import_bound("io")?;
+        return interop::pyarrow_table_from_batches(py, schema, batches);
+    }
 
-        let py_bytes = PyBytes::new_bound(py, &bytes);
-        let buf = io.getattr("BytesIO")?.call1((py_bytes,))?;
-
-        eprintln!("DEBUG: Opening PyArrow IPC stream");
-        let table = pyarrow
-            .getattr("ipc")?
-            .getattr("open_stream")?
-            .call1((buf,))?
-            .getattr("read_all")?
-            .call0()
-            .map_err(|e| {
-                eprintln!("ERROR: PyArrow IPC read_all failed: {}", e);
+    // Use Arrow IPC for maximum compatibility with older pyarrow
+    let config = compression::for_consumer(py, config, Consumer::PyArrow)?;
+    let result = py
+        .allow_threads(|| spill::query(dsn, user, password, sql, &config))
+        .map_err(|e| {
+            eprintln!(
+                "ERROR: query_pyarrow_impl - query_arrow_ipc_impl failed: {}",
                 e
-            })?;
+            );
+            map_query_error(e)
+        })?;
+    match result {
+        Spilled::Memory(bytes) => {
+            eprintln!("DEBUG: Converting {} bytes to a PyArrow table", bytes.len());
+            pyarrow_table_from_ipc(py, &bytes)
+        }
+        Spilled::File(path) => spill::map_table(py, &path),
+    }
+}
 
-        eprintln!("DEBUG: Converting PyArrow table to Pandas");
-        let df = table.getattr("to_pandas")?.call0()?;
-        eprintln!("DEBUG: Successfully created Pandas DataFrame");
-        Ok(df.into())
-    })
+// Column types of a Pandas DataFrame built from Arrow data
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DtypeBackend {
+    // NumPy dtypes: strings become objects, decimals Python Decimals, nullable ints floats
+    Numpy,
+    // pandas.ArrowDtype columns that keep the Arrow arrays and types as they are
+    PyArrow,
 }
 
-// Implementation function for Arrow C Data Interface
-fn query_arrow_c_data_impl(
+impl DtypeBackend {
+    pub fn parse(name: Option<&str>) -> Result<Self> {
+        match name {
+            None | Some("numpy") => Ok(DtypeBackend::Numpy),
+            Some("pyarrow") => Ok(DtypeBackend::PyArrow),
+            Some(other) => Err(anyhow!(
+                "Unknown dtype_backend '{}': expected 'numpy' or 'pyarrow'",
+                other
+            )),
+        }
+    }
+}
+
+// Converts a pyarrow.Table nothing else refers to. `self_destruct` frees each column once it
+// has been converted, so Arrow and Pandas copies of the data never coexist in full.
+fn table_to_pandas(py: Python<'_>, table: Py<PyAny>, backend: DtypeBackend) -> PyResult<Py<PyAny>> {
+    eprintln!("DEBUG: Converting PyArrow table to Pandas");
+    let kwargs = PyDict::new_bound(py);
+    kwargs.set_item("split_blocks", true)?;
+    kwargs.set_item("self_destruct", true)?;
+    if backend == DtypeBackend::PyArrow {
+        let arrow_dtype = py.import_bound("pandas")?.getattr("ArrowDtype")?;
+        kwargs.set_item("types_mapper", arrow_dtype)?;
+    }
+    let df = table
+        .bind(py)
+        .getattr("to_pandas")?
+        .call((), Some(&kwargs))?;
+    eprintln!("DEBUG: Successfully created Pandas DataFrame");
+    Ok(df.unbind())
+}
+
+// Fetches the result for the Arrow C Data Interface; runs without the GIL
+fn fetch_arrow_c_data(
     dsn: &str,
     user: &str,
     password: &str,
     sql: &str,
     config: &QueryConfig,
-) -> Result<(Py<PyAny>, Py<PyAny>)> {
-    let env = Environment::new()?;
-
-    // Build connection string with long DSN name handling
-    let conn_str = build_connection_string(dsn, user, password, config);
-
-    let conn = env.connect_with_connection_string(&conn_str, ConnectionOptions::default())?;
+) -> Result<ArrayData> {
+    let watchdog = Watchdog::start(config);
+    let result = fetch_arrow_c_data_watched(dsn, user, password, sql, config, &watchdog);
+    watchdog.finish(result)
+}
 
-    let cursor = match conn.execute(sql, (), None)? {
-        Some(cursor) => cursor,
+fn fetch_arrow_c_data_watched(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+    watchdog: &Watchdog,
+) -> Result<ArrayData> {
+    let backend = backend::open(dsn, user, password, config)?;
+    let arrow_record_batches = match backend.batches(sql, &[], config, watchdog)? {
+        Some(reader) => reader,
         None => {
             // Query executed successfully but returned no result set
             // Return empty C Data Interface result
@@ -459,50 +1858,50 @@ fn query_arrow_c_data_impl(
         }
     };
 
-    let text_size = config.max_text_size.unwrap_or(65536);
-    let binary_size = config.max_binary_size.unwrap_or(65536);
-
-    let mut builder = OdbcReaderBuilder::new();
-    builder.with_max_text_size(text_size as usize);
-    builder.with_max_binary_size(binary_size as usize);
-
-    let arrow_record_batches = builder.build(cursor)?;
+    let schema = arrow_record_batches.schema();
 
     // Collect all batches
+    let mut progress = Progress::new(config);
     let mut batches = Vec::new();
-    for batch in arrow_record_batches {
-        batches.push(batch?);
+    for batch in limit_rows(arrow_record_batches, config) {
+        watchdog.check()?;
+        let batch = batch?;
+        progress.batch(&batch)?;
+        batches.push(batch);
     }
 
-    if batches.is_empty() {
-        return Err(anyhow!("No data returned from query"));
-    }
-
-    // Use the first batch for Arrow C Data Interface
-    let first_batch = &batches[0];
-    let _schema = first_batch.schema();
+    // The C Data Interface exports a single array, so every batch is concatenated into it.
+    // An empty result is a valid empty array with the query's schema.
+    let batch = concat_batches(&schema, &batches)
+        .map_err(|e| anyhow!("Arrow Error: failed to concatenate batches: {}", e))?;
+    Ok(StructArray::from(batch).into_data())
+}
 
-    // Convert RecordBatch to StructArray for FFI
-    use arrow::array::StructArray;
-    let struct_array = StructArray::from(first_batch.clone());
-    let array_data = struct_array.into_data();
+// Implementation function for Arrow C Data Interface
+fn query_arrow_c_data_impl(
+    py: Python<'_>,
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+) -> Result<(Py<PyAny>, Py<PyAny>)> {
+    // Release the GIL while the driver executes and fetches
+    let array_data = py.allow_threads(|| fetch_arrow_c_data(dsn, user, password, sql, config))?;
 
     // Convert to Arrow C Data Interface using the correct approach
     let (ffi_array, ffi_schema) = to_ffi(&array_data)?;
 
-    Python::with_gil(|py| {
-        // Create PyCapsules for schema and array
-        let schema_capsule =
-            PyCapsule::new_bound(py, ffi_schema, Some(CString::new("arrow_schema")?))?;
-        let array_capsule =
-            PyCapsule::new_bound(py, ffi_array, Some(CString::new("arrow_array")?))?;
+    // Create PyCapsules for schema and array
+    let schema_capsule = PyCapsule::new_bound(py, ffi_schema, Some(CString::new("arrow_schema")?))?;
+    let array_capsule = PyCapsule::new_bound(py, ffi_array, Some(CString::new("arrow_array")?))?;
 
-        Ok((schema_capsule.into(), array_capsule.into()))
-    })
+    Ok((schema_capsule.into(), array_capsule.into()))
 }
 
 // Implementation function for Arrow C Data with DataFrame option
 fn query_arrow_c_data_with_df(
+    py: Python<'_>,
     dsn: &str,
     user: &str,
     password: &str,
@@ -512,50 +1911,40 @@ fn query_arrow_c_data_with_df(
 ) -> PyResult<Py<PyAny>> {
     let return_df = return_dataframe.unwrap_or(false);
 
-    match query_arrow_c_data_impl(dsn, user, password, sql, config) {
+    // Polars 1.3+ takes the result through the Arrow C stream, so pyarrow is not needed
+    if return_df && interop::polars_reads_c_stream(py) {
+        let array_data = py
+            .allow_threads(|| fetch_arrow_c_data(dsn, user, password, sql, config))
+            .map_err(map_query_error)?;
+        let batch = RecordBatch::from(StructArray::from(array_data));
+        return interop::polars_from_batches(py, batch.schema(), vec![batch]);
+    }
+
+    match query_arrow_c_data_impl(py, dsn, user, password, sql, config) {
         Ok((schema_capsule, array_capsule)) => {
             if return_df {
                 // Return Polars DataFrame directly
-                Python::with_gil(|py| {
-                    let polars = py.import_bound("polars")?;
-                    let pa = py.import_bound("pyarrow")?;
-
-                    let schema = pa
-                        .getattr("Schema")?
-                        .getattr("_import_from_c")?
-                        .call1((schema_capsule,))?;
-                    let array = pa
-                        .getattr("RecordBatch")?
-                        .getattr("_import_from_c")?
-                        .call1((array_capsule, schema))?;
-
-                    let df = polars.getattr("from_arrow")?.call1((array,))?;
-                    Ok(df.into())
-                })
+                let polars = py.import_bound("polars")?;
+                let pa = py.import_bound("pyarrow")?;
+
+                let schema = pa
+                    .getattr("Schema")?
+                    .getattr("_import_from_c")?
+                    .call1((schema_capsule,))?;
+                let array = pa
+                    .getattr("RecordBatch")?
+                    .getattr("_import_from_c")?
+                    .call1((array_capsule, schema))?;
+
+                let df = polars.getattr("from_arrow")?.call1((array,))?;
+                Ok(df.into())
             } else {
                 // Return PyCapsules for manual control
-                Python::with_gil(|py| {
-                    let tuple = (schema_capsule, array_capsule);
-                    Ok(tuple.into_py(py))
-                })
-            }
-        }
-        Err(e) => {
-            let msg = e.to_string();
-
-            if msg.contains("IM002") || msg.contains("connection") {
-                Err(PyConnectionError::new_err(format!(
-                    "Connection Error: {}",
-                    msg
-                )))
-            } else if msg.contains("SQL") || msg.contains("syntax") {
-                Err(PySQLError::new_err(format!("SQL Error: {}", msg)))
-            } else if msg.contains("Arrow") || msg.contains("c_data") {
-                Err(PyArrowError::new_err(format!("Arrow Error: {}", msg)))
-            } else {
-                Err(pyo3::exceptions::PyRuntimeError::new_err(msg))
+                let tuple = (schema_capsule, array_capsule);
+                Ok(tuple.into_py(py))
             }
         }
+        Err(e) => Err(map_query_error(e)),
     }
 }
 
@@ -567,20 +1956,139 @@ fn connect(
     password: &str,
     config: Option<&QueryConfig>,
 ) -> PyResult<IbarrowConnection> {
+    check_driver_manager(config)?;
     Ok(IbarrowConnection::new(dsn, user, password, config))
 }
 
+// Refuses to connect when the loaded ODBC driver manager cannot work (see drivermgr::check)
+pub(crate) fn check_driver_manager(config: Option<&QueryConfig>) -> PyResult<()> {
+    drivermgr::check(config.and_then(|config| config.driver_manager.as_deref()))
+        .map_err(|e| PyConnectionError::new_err(format!("Connection Error: {}", e)))
+}
+
+// The ODBC driver manager ibarrow is using on this machine, as {"name", "library", "loaded"}
+#[pyfunction]
+fn driver_manager(py: Python<'_>) -> PyResult<Py<PyAny>> {
+    json_to_py(py, &drivermgr::detect())
+}
+
+// Reads a bundle written by export_bundle() as {"manifest": dict, "tables": {name: pyarrow.Table}}
+#[pyfunction]
+#[pyo3(signature = (path, verify=true))]
+fn load_bundle(py: Python<'_>, path: PathBuf, verify: bool) -> PyResult<Py<PyDict>> {
+    let (manifest, tables) = py
+        .allow_threads(|| bundle::load_bundle(&path, verify))
+        .map_err(|e| PyIOError::new_err(e.to_string()))?;
+    let result = PyDict::new_bound(py);
+    result.set_item("manifest", json_to_py(py, &manifest)?)?;
+    let loaded = PyDict::new_bound(py);
+    for (name, ipc) in tables {
+        loaded.set_item(name, pyarrow_table_from_ipc(py, &ipc)?)?;
+    }
+    result.set_item("tables", loaded)?;
+    Ok(result.unbind())
+}
+
+// Converts a serializable value to plain Python dicts and lists
+fn json_to_py<T: Serialize>(py: Python<'_>, value: &T) -> PyResult<Py<PyAny>> {
+    let text = serde_json::to_string(value).map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    Ok(py
+        .import_bound("json")?
+        .call_method1("loads", (text,))?
+        .unbind())
+}
+
+// Restricts connections whose QueryConfig.access_role is `role` to the given tables.
+// Each table maps to its allowed columns, or to None for every column.
+#[pyfunction]
+fn set_access_policy(role: &str, tables: HashMap<String, Option<Vec<String>>>) {
+    access::set_policy(role, tables);
+}
+
+// Removes the policy for `role`, or every policy when no role is given
+#[pyfunction]
+#[pyo3(signature = (role=None))]
+fn clear_access_policy(role: Option<&str>) {
+    access::clear_policy(role);
+}
+
+// Adds `metadata` to the Arrow fields of columns whose Firebird domain matches `pattern`,
+// where `*` matches any run of characters and `?` a single one
+#[pyfunction]
+fn set_domain_metadata(pattern: &str, metadata: HashMap<String, String>) {
+    domains::set_rule(pattern, metadata);
+}
+
+// Removes the rule for `pattern`, or every rule when no pattern is given
+#[pyfunction]
+#[pyo3(signature = (pattern=None))]
+fn clear_domain_metadata(pattern: Option<&str>) {
+    domains::clear_rules(pattern);
+}
+
+// Registered domain rules as (pattern, metadata) pairs, in the order they apply
+#[pyfunction]
+fn domain_metadata() -> Vec<(String, HashMap<String, String>)> {
+    domains::registered()
+}
+
+// The extension module of the `ibarrow` package (python/ibarrow), which re-exports it
 #[pymodule]
+#[pyo3(name = "_ibarrow")]
 fn ibarrow(_py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
+    diagnostics::install();
     // Register the connection class and standalone function
     m.add_class::<IbarrowConnection>()?;
     m.add_class::<QueryConfig>()?;
+    m.add_class::<result::IbarrowResult>()?;
+    m.add_class::<catalog::TableList>()?;
+    m.add_class::<async_conn::AsyncIbarrowConnection>()?;
+    m.add_class::<async_conn::AsyncBatchStream>()?;
+    m.add_class::<batches::BatchIterator>()?;
+    m.add_class::<stream::ArrowStream>()?;
+    m.add_class::<extracts::ExtractRegistry>()?;
+    m.add_class::<snapshot::Snapshot>()?;
+    m.add_class::<scroll::ScrollCursor>()?;
+    m.add_class::<transaction::Transaction>()?;
+    m.add_class::<scan::ScanSource>()?;
+    m.add_class::<scan::ScanIterator>()?;
+    m.add_class::<dbapi::Cursor>()?;
+    m.add_class::<server::QueryServer>()?;
+    m.add_class::<recipe::Recipe>()?;
     m.add_function(wrap_pyfunction!(connect, m)?)?;
+    m.add_function(wrap_pyfunction!(async_conn::connect_async, m)?)?;
+    m.add_function(wrap_pyfunction!(scan::scan, m)?)?;
+    m.add_function(wrap_pyfunction!(load_bundle, m)?)?;
+    m.add_function(wrap_pyfunction!(pipe::pipe, m)?)?;
+    m.add_function(wrap_pyfunction!(ddl::schema_to_ddl, m)?)?;
+    m.add_function(wrap_pyfunction!(set_access_policy, m)?)?;
+    m.add_function(wrap_pyfunction!(clear_access_policy, m)?)?;
+    m.add_function(wrap_pyfunction!(set_domain_metadata, m)?)?;
+    m.add_function(wrap_pyfunction!(clear_domain_metadata, m)?)?;
+    m.add_function(wrap_pyfunction!(domain_metadata, m)?)?;
+    m.add_function(wrap_pyfunction!(worker::_worker_main, m)?)?;
+    m.add_function(wrap_pyfunction!(distributed::_read_partition, m)?)?;
+    m.add_function(wrap_pyfunction!(distributed::_read_partition_arrow, m)?)?;
+    m.add_function(wrap_pyfunction!(driver_manager, m)?)?;
     m.add(
         "PyConnectionError",
         _py.get_type_bound::<PyConnectionError>(),
     )?;
+    m.add_function(wrap_pyfunction!(dialect::requires_quotes, m)?)?;
+    m.add_function(wrap_pyfunction!(dialect::quote_identifier, m)?)?;
+    m.add_function(wrap_pyfunction!(dialect::_reserved_words, m)?)?;
+    m.add_function(wrap_pyfunction!(dialect::_url_dsn, m)?)?;
+    m.add_function(wrap_pyfunction!(adbc::adbc_driver_path, m)?)?;
+    m.add_function(wrap_pyfunction!(adbc::adbc_connect, m)?)?;
+    dbapi::add_exceptions(_py, m)?;
+    m.add("apilevel", dbapi::APILEVEL)?;
+    m.add("threadsafety", dbapi::THREADSAFETY)?;
+    m.add("paramstyle", dbapi::PARAMSTYLE)?;
     m.add("PySQLError", _py.get_type_bound::<PySQLError>())?;
     m.add("PyArrowError", _py.get_type_bound::<PyArrowError>())?;
+    m.add("PyTimeoutError", _py.get_type_bound::<PyTimeoutError>())?;
+    m.add("PyAccessError", _py.get_type_bound::<PyAccessError>())?;
+    #[cfg(feature = "testing")]
+    testing::register(_py, m)?;
     Ok(())
 }
@@ -0,0 +1,89 @@
+use anyhow::{anyhow, Result};
+use arrow::csv::reader::Format;
+use arrow::csv::ReaderBuilder;
+use arrow::datatypes::{DataType, Field, Schema};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::write::Batches;
+
+// Rows CSV type inference reads
+const INFER_ROWS: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileFormat {
+    Parquet,
+    Csv,
+}
+
+impl FileFormat {
+    // `format` when given, else the one the file extension names
+    pub fn resolve(format: Option<&str>, path: &Path) -> Result<Self> {
+        let name = match format {
+            Some(format) => format.to_lowercase(),
+            None => path
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or_default()
+                .to_lowercase(),
+        };
+        match name.as_str() {
+            "parquet" | "pq" => Ok(Self::Parquet),
+            "csv" => Ok(Self::Csv),
+            _ if format.is_some() => Err(anyhow!(
+                "Unknown format '{}': expected 'parquet' or 'csv'",
+                name
+            )),
+            _ => Err(anyhow!(
+                "Cannot tell the format of {} from its extension; pass format='parquet' or 'csv'",
+                path.display()
+            )),
+        }
+    }
+}
+
+// Opens `path` as a stream of batches of up to `batch_rows` rows. CSV files need a header row;
+// their column types are inferred from the first rows, or all read as text with `text`, for a
+// cast to the table's types.
+pub fn open(
+    path: &Path,
+    format: FileFormat,
+    delimiter: u8,
+    text: bool,
+    batch_rows: usize,
+) -> Result<Batches> {
+    let mut file =
+        File::open(path).map_err(|e| anyhow!("Failed to open {}: {}", path.display(), e))?;
+    match format {
+        FileFormat::Parquet => Ok(Box::new(
+            ParquetRecordBatchReaderBuilder::try_new(file)?
+                .with_batch_size(batch_rows)
+                .build()?,
+        )),
+        FileFormat::Csv => {
+            let format = Format::default()
+                .with_header(true)
+                .with_delimiter(delimiter);
+            let (mut schema, _) = format.clone().infer_schema(&mut file, Some(INFER_ROWS))?;
+            if text {
+                schema = Schema::new(
+                    schema
+                        .fields()
+                        .iter()
+                        .map(|field| Field::new(field.name(), DataType::Utf8, true))
+                        .collect::<Vec<_>>(),
+                );
+            }
+            file.seek(SeekFrom::Start(0))?;
+            Ok(Box::new(
+                ReaderBuilder::new(Arc::new(schema))
+                    .with_format(format)
+                    .with_batch_size(batch_rows)
+                    .build(file)?,
+            ))
+        }
+    }
+}
@@ -0,0 +1,379 @@
+use anyhow::{anyhow, Result};
+use arrow_ipc::writer::IpcWriteOptions;
+use parquet::basic::Compression;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+use crate::export::{self, Rollover};
+use crate::sidecar::Sidecar;
+use crate::{compression, QueryConfig};
+
+const DEFAULT_WORKERS: usize = 4;
+
+// A manifest file: the extracts to run and how many may run at once
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    max_workers: Option<usize>,
+    extracts: Vec<Extract>,
+}
+
+// One named extract of a manifest
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Extract {
+    name: String,
+    sql: String,
+    destination: String,
+    // parquet, csv, ndjson or feather; taken from the destination's extension when missing
+    format: Option<String>,
+    compression: Option<String>,
+    compression_level: Option<i32>,
+    partition_by: Option<Vec<String>>,
+    max_file_rows: Option<u64>,
+    max_file_bytes: Option<u64>,
+    max_rows: Option<u64>,
+    // Writes <destination>.meta.json, with min and max of stats_column
+    #[serde(default)]
+    sidecar: bool,
+    stats_column: Option<String>,
+    // Passed through to the report for the scheduler running the manifest; not acted on
+    schedule: Option<String>,
+}
+
+// How an extract is written, resolved before anything runs
+enum Output {
+    Parquet {
+        compression: Compression,
+        partition_by: Vec<String>,
+    },
+    Csv,
+    Ndjson,
+    Feather {
+        options: IpcWriteOptions,
+    },
+}
+
+impl Output {
+    fn format(&self) -> &'static str {
+        match self {
+            Output::Parquet { .. } => "parquet",
+            Output::Csv => "csv",
+            Output::Ndjson => "ndjson",
+            Output::Feather { .. } => "feather",
+        }
+    }
+}
+
+struct Planned {
+    extract: Extract,
+    output: Output,
+    rollover: Rollover,
+}
+
+// Reads a manifest. JSON files are parsed directly; YAML goes through PyYAML, which has to be
+// installed, and is handed over as JSON.
+pub fn load(py: Python<'_>, path: &Path) -> Result<Manifest> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read manifest {}: {}", path.display(), e))?;
+    let json = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => text,
+        _ => {
+            let yaml = py
+                .import_bound("yaml")
+                .map_err(|_| anyhow!("Reading YAML manifests needs PyYAML (pip install pyyaml)"))?;
+            let value = yaml.call_method1("safe_load", (text,))?;
+            let kwargs = PyDict::new_bound(py);
+            // Dates and times in the YAML become strings
+            kwargs.set_item("default", py.import_bound("builtins")?.getattr("str")?)?;
+            py.import_bound("json")?
+                .call_method("dumps", (value,), Some(&kwargs))?
+                .extract()?
+        }
+    };
+    serde_json::from_str(&json).map_err(|e| anyhow!("Invalid manifest {}: {}", path.display(), e))
+}
+
+fn format_for(extract: &Extract) -> Result<String> {
+    if let Some(format) = &extract.format {
+        return Ok(format.to_lowercase());
+    }
+    let extension = Path::new(&extract.destination)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase);
+    Ok(match extension.as_deref() {
+        Some("parquet") => "parquet",
+        Some("csv") => "csv",
+        Some("ndjson" | "jsonl") => "ndjson",
+        Some("arrow" | "feather") => "feather",
+        _ => {
+            return Err(anyhow!(
+                "cannot tell the format from destination '{}'; set format",
+                extract.destination
+            ))
+        }
+    }
+    .to_string())
+}
+
+fn plan_extract(extract: &Extract, config: &QueryConfig) -> Result<Planned> {
+    let format = format_for(extract)?;
+    Sidecar::new(extract.sidecar, extract.stats_column.clone())?;
+    let rollover = Rollover::new(extract.max_file_rows, extract.max_file_bytes)?;
+    let rolls = extract.max_file_rows.is_some() || extract.max_file_bytes.is_some();
+    let partitioned = extract.partition_by.as_ref().is_some_and(|p| !p.is_empty());
+    let compressed = extract.compression.is_some() || extract.compression_level.is_some();
+    let output = match format.as_str() {
+        "parquet" => Output::Parquet {
+            compression: export::compression(
+                extract.compression.as_deref().unwrap_or("snappy"),
+                extract.compression_level,
+            )?,
+            partition_by: extract.partition_by.clone().unwrap_or_default(),
+        },
+        "feather" => {
+            if extract.compression_level.is_some() {
+                return Err(anyhow!("compression_level only applies to parquet"));
+            }
+            let options = match &extract.compression {
+                Some(name) => compression::named_options(name)?,
+                None => compression::write_options(config)?,
+            };
+            Output::Feather { options }
+        }
+        "csv" | "ndjson" if compressed => {
+            return Err(anyhow!("compression does not apply to {}", format))
+        }
+        "csv" => Output::Csv,
+        "ndjson" => Output::Ndjson,
+        other => {
+            return Err(anyhow!(
+                "Unknown format '{}': expected 'parquet', 'csv', 'ndjson' or 'feather'",
+                other
+            ))
+        }
+    };
+    if partitioned && !matches!(output, Output::Parquet { .. }) {
+        return Err(anyhow!("partition_by only applies to parquet"));
+    }
+    if rolls && partitioned {
+        return Err(anyhow!(
+            "partition_by cannot be combined with max_file_rows or max_file_bytes"
+        ));
+    }
+    if rolls && matches!(output, Output::Csv | Output::Ndjson) {
+        return Err(anyhow!(
+            "max_file_rows and max_file_bytes only apply to parquet and feather"
+        ));
+    }
+    Ok(Planned {
+        extract: extract.clone(),
+        output,
+        rollover,
+    })
+}
+
+// Checks every extract, so a mistake late in the manifest fails before anything runs
+fn plan(
+    manifest: &Manifest,
+    config: &QueryConfig,
+    only: Option<&[String]>,
+) -> Result<Vec<Planned>> {
+    let mut names = HashSet::new();
+    for extract in &manifest.extracts {
+        if !names.insert(extract.name.as_str()) {
+            return Err(anyhow!("Manifest lists extract '{}' twice", extract.name));
+        }
+    }
+    for name in only.unwrap_or_default() {
+        if !names.contains(name.as_str()) {
+            return Err(anyhow!("Manifest has no extract '{}'", name));
+        }
+    }
+    manifest
+        .extracts
+        .iter()
+        .filter(|extract| only.is_none_or(|only| only.contains(&extract.name)))
+        .map(|extract| {
+            plan_extract(extract, config).map_err(|e| anyhow!("Extract '{}': {}", extract.name, e))
+        })
+        .collect()
+}
+
+fn run_extract(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    planned: &Planned,
+    config: &QueryConfig,
+) -> Result<usize> {
+    let extract = &planned.extract;
+    let config = QueryConfig {
+        max_rows: extract.max_rows.or(config.max_rows),
+        ..config.clone()
+    };
+    let (sql, path) = (extract.sql.as_str(), Path::new(&extract.destination));
+    let sidecar = Sidecar::new(extract.sidecar, extract.stats_column.clone())?;
+    match &planned.output {
+        Output::Parquet {
+            compression,
+            partition_by,
+        } => export::query_to_parquet(
+            dsn,
+            user,
+            password,
+            sql,
+            &config,
+            path,
+            *compression,
+            &[],
+            partition_by,
+            planned.rollover,
+            sidecar,
+        ),
+        Output::Csv => {
+            export::query_to_csv(dsn, user, password, sql, &config, path, b',', true, sidecar)
+        }
+        Output::Ndjson => export::query_to_ndjson(
+            dsn,
+            user,
+            password,
+            sql,
+            &config,
+            path,
+            export::TimestampFormat::Iso,
+            export::DecimalFormat::Number,
+            sidecar,
+        ),
+        Output::Feather { options } => export::query_to_feather(
+            dsn,
+            user,
+            password,
+            sql,
+            &config,
+            path,
+            options.clone(),
+            planned.rollover,
+            sidecar,
+        ),
+    }
+}
+
+// Outcome of one extract of a run
+pub struct Outcome {
+    pub name: String,
+    pub format: &'static str,
+    pub destination: String,
+    pub schedule: Option<String>,
+    pub rows: Result<usize>,
+    pub duration_secs: f64,
+}
+
+// Runs the extracts of `manifest` (those named in `only`, if given) on up to `max_workers`
+// threads, or the manifest's max_workers. A failed extract does not stop the others; every
+// outcome is reported in manifest order.
+pub fn run(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    manifest: &Manifest,
+    config: &QueryConfig,
+    max_workers: Option<usize>,
+    only: Option<&[String]>,
+) -> Result<Vec<Outcome>> {
+    let planned = plan(manifest, config, only)?;
+    let max_workers = max_workers
+        .or(manifest.max_workers)
+        .unwrap_or(DEFAULT_WORKERS);
+    if max_workers == 0 {
+        return Err(anyhow!("max_workers must be greater than 0"));
+    }
+    let workers = max_workers.min(planned.len());
+    eprintln!(
+        "DEBUG: run_manifest running {} extracts on {} connections",
+        planned.len(),
+        workers
+    );
+    let next = AtomicUsize::new(0);
+    let outcomes: Mutex<Vec<Option<Outcome>>> =
+        Mutex::new((0..planned.len()).map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(job) = planned.get(index) else {
+                    break;
+                };
+                let started = Instant::now();
+                let rows = run_extract(dsn, user, password, job, config);
+                match &rows {
+                    Ok(rows) => {
+                        eprintln!("DEBUG: Extract '{}' wrote {} rows", job.extract.name, rows)
+                    }
+                    Err(e) => eprintln!("ERROR: Extract '{}' failed: {}", job.extract.name, e),
+                }
+                let outcome = Outcome {
+                    name: job.extract.name.clone(),
+                    format: job.output.format(),
+                    destination: job.extract.destination.clone(),
+                    schedule: job.extract.schedule.clone(),
+                    rows,
+                    duration_secs: started.elapsed().as_secs_f64(),
+                };
+                outcomes.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(outcome);
+            });
+        }
+    });
+    Ok(outcomes
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .flatten()
+        .collect())
+}
+
+// The summary run_manifest returns: totals plus one entry per extract
+pub fn report(py: Python<'_>, outcomes: &[Outcome], duration_secs: f64) -> PyResult<Py<PyDict>> {
+    let extracts = PyList::empty_bound(py);
+    let (mut succeeded, mut failed, mut total_rows) = (0, 0, 0);
+    for outcome in outcomes {
+        let entry = PyDict::new_bound(py);
+        entry.set_item("name", &outcome.name)?;
+        entry.set_item("format", outcome.format)?;
+        entry.set_item("destination", &outcome.destination)?;
+        entry.set_item("schedule", &outcome.schedule)?;
+        entry.set_item("duration_secs", outcome.duration_secs)?;
+        match &outcome.rows {
+            Ok(rows) => {
+                succeeded += 1;
+                total_rows += rows;
+                entry.set_item("status", "ok")?;
+                entry.set_item("rows", rows)?;
+                entry.set_item("error", py.None())?;
+            }
+            Err(e) => {
+                failed += 1;
+                entry.set_item("status", "failed")?;
+                entry.set_item("rows", py.None())?;
+                entry.set_item("error", e.to_string())?;
+            }
+        }
+        extracts.append(entry)?;
+    }
+    let summary = PyDict::new_bound(py);
+    summary.set_item("succeeded", succeeded)?;
+    summary.set_item("failed", failed)?;
+    summary.set_item("rows", total_rows)?;
+    summary.set_item("duration_secs", duration_secs)?;
+    summary.set_item("extracts", extracts)?;
+    Ok(summary.unbind())
+}
@@ -0,0 +1,62 @@
+use anyhow::{anyhow, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::{query_arrow_ipc_impl, QueryConfig};
+
+pub fn validate(max_workers: usize) -> Result<()> {
+    if max_workers == 0 {
+        return Err(anyhow!("max_workers must be greater than 0"));
+    }
+    Ok(())
+}
+
+// Runs the named queries on up to `max_workers` threads, each thread taking the next waiting
+// query once its previous one finished, so at most `max_workers` connections are open at once.
+// Returns one Arrow IPC stream per query, in input order.
+pub fn run_many(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    queries: &[(String, String)],
+    config: &QueryConfig,
+    max_workers: usize,
+) -> Result<Vec<Vec<u8>>> {
+    validate(max_workers)?;
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<Vec<u8>>>>> =
+        Mutex::new((0..queries.len()).map(|_| None).collect());
+    let workers = max_workers.min(queries.len());
+    eprintln!(
+        "DEBUG: run_many running {} queries on {} connections",
+        queries.len(),
+        workers
+    );
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some((name, sql)) = queries.get(index) else {
+                    break;
+                };
+                let result = query_arrow_ipc_impl(dsn, user, password, sql, config)
+                    .map_err(|e| anyhow!("Query '{}' failed: {}", name, e));
+                let failed = result.is_err();
+                results.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(result);
+                // Leave the remaining queries unstarted once one has failed
+                if failed {
+                    next.store(queries.len(), Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    // Queries are taken in order, so any query left unstarted comes after the failure that
+    // stopped the run and the first error in input order is reported
+    results
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .map(|result| result.unwrap_or_else(|| Err(anyhow!("run_many stopped early"))))
+        .collect()
+}
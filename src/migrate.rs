@@ -0,0 +1,156 @@
+use anyhow::{anyhow, Result};
+use odbc_api::{Environment, IntoParameter};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::block::split_statements;
+use crate::sqlfile::load_sql_file;
+use crate::{build_connection_string, catalog, diagnostics, driverenv, retry, QueryConfig};
+
+pub use ibarrow_core::catalog::fetch_texts;
+
+// A versioned .sql file, e.g. `V003__add_orders_index.sql` or `003_add_orders_index.sql`
+pub struct Migration {
+    pub version: u64,
+    pub name: String,
+    pub statements: Vec<String>,
+}
+
+// Leading number of a migration file name, with an optional `V` prefix
+fn parse_version(file_name: &str) -> Option<u64> {
+    let rest = file_name.strip_prefix(['V', 'v']).unwrap_or(file_name);
+    let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+// Loads every versioned .sql file in `dir`, ordered by version
+pub fn load_migrations(dir: &Path, encoding: &str) -> Result<Vec<Migration>> {
+    let entries = fs::read_dir(dir).map_err(|e| {
+        anyhow!(
+            "Failed to read migrations directory {}: {}",
+            dir.display(),
+            e
+        )
+    })?;
+    let mut files: Vec<(u64, String, PathBuf)> = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("sql") {
+            continue;
+        }
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default()
+            .to_string();
+        // Unversioned files are left alone so they can be @included
+        if let Some(version) = parse_version(&name) {
+            files.push((version, name, path));
+        }
+    }
+    files.sort();
+    for pair in files.windows(2) {
+        if pair[0].0 == pair[1].0 {
+            return Err(anyhow!(
+                "Migrations {} and {} share version {}",
+                pair[0].1,
+                pair[1].1,
+                pair[0].0
+            ));
+        }
+    }
+
+    files
+        .into_iter()
+        .map(|(version, name, path)| {
+            let sql = load_sql_file(&path, encoding)?;
+            // Statements are executed one by one, without their terminators
+            let statements = split_statements(&sql)
+                .into_iter()
+                .map(|s| s.trim_end_matches(';').trim().to_string())
+                .collect();
+            Ok(Migration {
+                version,
+                name,
+                statements,
+            })
+        })
+        .collect()
+}
+
+// Applies the migrations not yet recorded in `table`, each file in its own transaction.
+// Returns the file names applied or, with `dry_run`, the ones that would be.
+pub fn run_migrations(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    config: &QueryConfig,
+    migrations: &[Migration],
+    table: &str,
+    dry_run: bool,
+) -> Result<Vec<String>> {
+    // Migrations run arbitrary DDL, which a table/column policy cannot vet
+    if let Some(role) = &config.access_role {
+        return Err(anyhow!(
+            "Access denied: role {} may not run migrations",
+            role.to_uppercase()
+        ));
+    }
+    diagnostics::clear();
+    let env = Environment::new()?;
+    let conn_str = build_connection_string(dsn, user, password, config);
+    let conn = retry::with_retry(config, || driverenv::connect(&env, &conn_str, config))?;
+
+    let exists = catalog::table_exists(&conn, &table.to_uppercase())?;
+    let applied: HashSet<u64> = if exists {
+        fetch_texts(&conn, &format!("SELECT VERSION FROM {}", table), None)?
+            .iter()
+            .filter_map(|v| v.parse().ok())
+            .collect()
+    } else {
+        HashSet::new()
+    };
+    let pending: Vec<&Migration> = migrations
+        .iter()
+        .filter(|m| !applied.contains(&m.version))
+        .collect();
+    if dry_run || pending.is_empty() {
+        return Ok(pending.iter().map(|m| m.name.clone()).collect());
+    }
+
+    if !exists {
+        conn.execute(
+            &format!(
+                "CREATE TABLE {} (VERSION BIGINT NOT NULL PRIMARY KEY, NAME VARCHAR(255) NOT NULL, \
+                 APPLIED_AT TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL)",
+                table
+            ),
+            (),
+            None,
+        )?;
+    }
+
+    conn.set_autocommit(false)?;
+    let insert = format!("INSERT INTO {} (VERSION, NAME) VALUES (?, ?)", table);
+    let mut done = Vec::new();
+    for migration in pending {
+        eprintln!("DEBUG: Applying migration {}", migration.name);
+        let outcome = migration
+            .statements
+            .iter()
+            .try_for_each(|statement| conn.execute(statement, (), None).map(|_| ()))
+            .and_then(|_| {
+                let version = migration.version as i64;
+                let name = migration.name.as_str().into_parameter();
+                conn.execute(&insert, (&version, &name), None).map(|_| ())
+            })
+            .and_then(|_| conn.commit());
+        if let Err(e) = outcome {
+            let _ = conn.rollback();
+            return Err(anyhow!("Migration {} failed: {}", migration.name, e));
+        }
+        done.push(migration.name.clone());
+    }
+    Ok(done)
+}
@@ -0,0 +1,207 @@
+use anyhow::{anyhow, Result};
+#[cfg(feature = "cloud")]
+use object_store::aws::AmazonS3Builder;
+#[cfg(feature = "cloud")]
+use object_store::azure::MicrosoftAzureBuilder;
+#[cfg(feature = "cloud")]
+use object_store::gcp::GoogleCloudStorageBuilder;
+#[cfg(feature = "cloud")]
+use object_store::path::Path as ObjectPath;
+#[cfg(feature = "cloud")]
+use object_store::{ObjectStore, ObjectStoreScheme, WriteMultipart};
+#[cfg(feature = "cloud")]
+use std::collections::HashMap;
+#[cfg(feature = "cloud")]
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::path::Path;
+#[cfg(feature = "cloud")]
+use std::str::FromStr;
+#[cfg(feature = "cloud")]
+use tokio::runtime::Runtime;
+#[cfg(feature = "cloud")]
+use url::Url;
+
+use crate::QueryConfig;
+
+// Destinations written straight to object storage instead of the local disk
+const SCHEMES: [&str; 7] = [
+    "s3://", "s3a://", "gs://", "az://", "azure://", "abfs://", "abfss://",
+];
+// Parts uploaded at once before writes wait for one to finish
+#[cfg(feature = "cloud")]
+const PARTS_IN_FLIGHT: usize = 4;
+
+// The object URL `path` stands for, if it names one
+pub fn url(path: &Path) -> Option<&str> {
+    let text = path.to_str()?;
+    SCHEMES
+        .iter()
+        .any(|scheme| text.to_lowercase().starts_with(scheme))
+        .then_some(text)
+}
+
+// Applies QueryConfig.storage_options on top of a builder configured from the environment.
+// Unlike object_store::parse_url_opts, unknown keys are an error rather than ignored.
+#[cfg(feature = "cloud")]
+fn configure<B, K>(
+    mut builder: B,
+    options: &HashMap<String, String>,
+    set: fn(B, K, String) -> B,
+) -> Result<B>
+where
+    K: FromStr,
+    K::Err: Display,
+{
+    for (key, value) in options {
+        let key = key
+            .parse()
+            .map_err(|e| anyhow!("Invalid storage option '{}': {}", key, e))?;
+        builder = set(builder, key, value.clone());
+    }
+    Ok(builder)
+}
+
+#[cfg(feature = "cloud")]
+fn open(url: &str, config: &QueryConfig) -> Result<(Box<dyn ObjectStore>, ObjectPath)> {
+    let parsed = Url::parse(url).map_err(|e| anyhow!("Invalid object store URL {}: {}", url, e))?;
+    let (scheme, path) = ObjectStoreScheme::parse(&parsed)
+        .map_err(|e| anyhow!("Unsupported object store URL {}: {}", url, e))?;
+    let path = ObjectPath::parse(path)?;
+    let options = config.storage_options.clone().unwrap_or_default();
+    let store: Box<dyn ObjectStore> = match scheme {
+        ObjectStoreScheme::AmazonS3 => Box::new(
+            configure(
+                AmazonS3Builder::from_env().with_url(url),
+                &options,
+                AmazonS3Builder::with_config,
+            )?
+            .build()?,
+        ),
+        ObjectStoreScheme::GoogleCloudStorage => Box::new(
+            configure(
+                GoogleCloudStorageBuilder::from_env().with_url(url),
+                &options,
+                GoogleCloudStorageBuilder::with_config,
+            )?
+            .build()?,
+        ),
+        ObjectStoreScheme::MicrosoftAzure => Box::new(
+            configure(
+                MicrosoftAzureBuilder::from_env().with_url(url),
+                &options,
+                MicrosoftAzureBuilder::with_config,
+            )?
+            .build()?,
+        ),
+        other => {
+            return Err(anyhow!(
+                "Unsupported object store URL {} ({:?})",
+                url,
+                other
+            ))
+        }
+    };
+    Ok((store, path))
+}
+
+// Streams bytes into an object as a multipart upload. Nothing appears at the destination until
+// complete() succeeds, so a failed export leaves any existing object intact; dropping the
+// writer before that aborts the upload.
+#[cfg(feature = "cloud")]
+pub struct ObjectWriter {
+    runtime: Runtime,
+    upload: Option<WriteMultipart>,
+    url: String,
+}
+
+#[cfg(feature = "cloud")]
+impl ObjectWriter {
+    pub fn create(url: &str, config: &QueryConfig) -> Result<Self> {
+        let (store, path) = open(url, config)?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let upload = runtime
+            .block_on(store.put_multipart(&path))
+            .map_err(|e| anyhow!("Failed to start upload to {}: {}", url, e))?;
+        eprintln!("DEBUG: Uploading to {}", url);
+        Ok(Self {
+            runtime,
+            upload: Some(WriteMultipart::new(upload)),
+            url: url.to_string(),
+        })
+    }
+
+    pub fn complete(mut self) -> Result<()> {
+        let upload = self
+            .upload
+            .take()
+            .ok_or_else(|| anyhow!("ERROR: upload to {} already finished", self.url))?;
+        self.runtime
+            .block_on(upload.finish())
+            .map_err(|e| anyhow!("Failed to complete upload to {}: {}", self.url, e))?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cloud")]
+impl Write for ObjectWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let upload = self
+            .upload
+            .as_mut()
+            .ok_or_else(|| io::Error::other("upload already finished"))?;
+        // Full parts are spawned onto the runtime, which only runs them inside block_on
+        let _guard = self.runtime.enter();
+        upload.write(buf);
+        self.runtime
+            .block_on(upload.wait_for_capacity(PARTS_IN_FLIGHT))
+            .map_err(io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cloud")]
+impl Drop for ObjectWriter {
+    fn drop(&mut self) {
+        if let Some(upload) = self.upload.take() {
+            eprintln!("WARN: Aborting upload to {}", self.url);
+            let _ = self.runtime.block_on(upload.abort());
+        }
+    }
+}
+
+// Without the `cloud` feature object store URLs are still recognised, so they fail with a
+// clear error instead of being written as local paths, but nothing can be uploaded
+#[cfg(not(feature = "cloud"))]
+pub struct ObjectWriter(std::convert::Infallible);
+
+#[cfg(not(feature = "cloud"))]
+impl ObjectWriter {
+    pub fn create(url: &str, _config: &QueryConfig) -> Result<Self> {
+        Err(anyhow!(
+            "Cannot write to {}: ibarrow was built without the `cloud` feature",
+            url
+        ))
+    }
+
+    pub fn complete(self) -> Result<()> {
+        match self.0 {}
+    }
+}
+
+#[cfg(not(feature = "cloud"))]
+impl Write for ObjectWriter {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        match self.0 {}
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.0 {}
+    }
+}
@@ -0,0 +1,388 @@
+use anyhow::{anyhow, Result};
+use arrow::array::{Array, AsArray};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Int64Type, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use ibarrow_core::lexer::{self, Kind};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::dialect::quote_identifier;
+use crate::sink::{BatchSink, IpcSink};
+use crate::snapshot::Snapshot;
+use crate::{
+    collect_batches, compression, dictionary, fetch_into, rows_left, trim_batch, QueryConfig,
+};
+
+// Alias for the derived table used when wrapping the user's query
+const PARTITION_ALIAS: &str = "IBARROW_PARTITION";
+
+// Partitions read at once, each on its own connection; the rest wait for a free reader
+const MAX_WORKERS: usize = 8;
+
+// Batches a partition reader fetches ahead of the partition being written
+const READ_AHEAD: usize = 4;
+
+pub fn validate(column: &str, num_partitions: usize) -> Result<()> {
+    column_sql(column)?;
+    if num_partitions == 0 {
+        return Err(anyhow!("num_partitions must be greater than 0"));
+    }
+    Ok(())
+}
+
+// `column` as it goes into the range conditions. It must name one column of the query,
+// unquoted (case-insensitive) or double-quoted, so nothing else can ride along into the SQL.
+fn column_sql(column: &str) -> Result<String> {
+    let mut tokens = lexer::tokenize(column).filter(|token| !token.is_trivia());
+    let name = match (tokens.next(), tokens.next()) {
+        (None, _) => return Err(anyhow!("partition_column must not be empty")),
+        (Some(token), None) if matches!(token.kind, Kind::Word | Kind::QuotedIdentifier) => {
+            token.identifier()
+        }
+        _ => None,
+    };
+    name.map(|name| quote_identifier(&name)).ok_or_else(|| {
+        anyhow!(
+            "partition_column must be a column name, quoted if needed, not '{}'",
+            column.trim()
+        )
+    })
+}
+
+fn derived(sql: &str) -> String {
+    lexer::statement(sql).to_string()
+}
+
+fn is_integer(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Decimal128(_, scale) | DataType::Decimal256(_, scale) => *scale == 0,
+        other => other.is_integer(),
+    }
+}
+
+// MIN and MAX of `column` over the result of `sql`, or None when it has no non-NULL values
+fn bounds(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    column: &str,
+    config: &QueryConfig,
+) -> Result<Option<(i64, i64)>> {
+    let column = &column_sql(column)?;
+    let bounds_sql = format!(
+        "SELECT MIN({}), MAX({}) FROM ({}) AS {}",
+        column,
+        column,
+        derived(sql),
+        PARTITION_ALIAS
+    );
+    let (_, batches) = collect_batches(dsn, user, password, &bounds_sql, config)?;
+    let Some(batch) = batches.iter().find(|batch| batch.num_rows() > 0) else {
+        return Ok(None);
+    };
+    let mut values = Vec::with_capacity(2);
+    for array in batch.columns() {
+        if !is_integer(array.data_type()) {
+            return Err(anyhow!(
+                "partition_column '{}' must be an integer column, not {}",
+                column,
+                array.data_type()
+            ));
+        }
+        let array = cast(array, &DataType::Int64)?;
+        let array = array.as_primitive::<Int64Type>();
+        if array.is_null(0) {
+            return Ok(None);
+        }
+        values.push(array.value(0));
+    }
+    match values[..] {
+        [min, max] => Ok(Some((min, max))),
+        _ => Err(anyhow!(
+            "partition bounds query returned {} columns",
+            values.len()
+        )),
+    }
+}
+
+// One query per range. The first range also takes the NULLs and everything below MIN, the last
+// one everything above MAX, so rows written after the bounds were read are not lost.
+fn partition_sql(
+    sql: &str,
+    column: &str,
+    min: i64,
+    max: i64,
+    num_partitions: usize,
+) -> Vec<String> {
+    let span = i128::from(max) - i128::from(min) + 1;
+    let count = span.min(num_partitions as i128);
+    if count <= 1 {
+        return vec![sql.to_string()];
+    }
+    let cut = |i: i128| i128::from(min) + span * i / count;
+    (0..count)
+        .map(|i| {
+            let condition = if i == 0 {
+                format!("({} < {} OR {} IS NULL)", column, cut(1), column)
+            } else if i == count - 1 {
+                format!("{} >= {}", column, cut(i))
+            } else {
+                format!("{} >= {} AND {} < {}", column, cut(i), column, cut(i + 1))
+            };
+            format!(
+                "SELECT * FROM ({}) AS {} WHERE {}",
+                derived(sql),
+                PARTITION_ALIAS,
+                condition
+            )
+        })
+        .collect()
+}
+
+// The range queries that split `sql` on `column`, or `sql` alone when the column has no
+// non-NULL values. Also used by engines that schedule the partitions themselves.
+pub fn range_queries(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    column: &str,
+    num_partitions: usize,
+    config: &QueryConfig,
+) -> Result<Vec<String>> {
+    validate(column, num_partitions)?;
+    let bounds_config = QueryConfig {
+        dictionary_columns: None,
+        dictionaries: None,
+        restart_key: None,
+        max_restarts: None,
+        progress: None,
+        ..config.clone()
+    };
+    Ok(
+        match bounds(dsn, user, password, sql, column, &bounds_config)? {
+            Some((min, max)) => partition_sql(sql, &column_sql(column)?, min, max, num_partitions),
+            None => vec![sql.to_string()],
+        },
+    )
+}
+
+// A query returning the columns of `sql` and no rows
+pub fn empty_sql(sql: &str) -> String {
+    format!(
+        "SELECT * FROM ({}) AS {} WHERE 1 = 0",
+        derived(sql),
+        PARTITION_ALIAS
+    )
+}
+
+pub struct Partitioned {
+    pub ipc: Vec<u8>,
+    pub rowcount: usize,
+    // Categories of the dictionary columns, see dictionary::categories
+    pub dictionaries: Vec<(String, Vec<String>)>,
+}
+
+// Splits `sql` into `num_partitions` ranges of the integer `column` between its MIN and MAX,
+// fetches them on separate connections, at most MAX_WORKERS at a time, and returns the
+// concatenated result, in range order, as an Arrow IPC stream. Batches are written as they
+// arrive, so only the few each reader fetches ahead are held besides the result. With
+// `consistent`, the bounds and every partition are read at one Firebird 4 snapshot held open
+// for the duration.
+#[allow(clippy::too_many_arguments)]
+pub fn read_partitioned(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    column: &str,
+    num_partitions: usize,
+    consistent: bool,
+    config: &QueryConfig,
+) -> Result<Partitioned> {
+    validate(column, num_partitions)?;
+    // A configured snapshot_number already puts every connection at the same snapshot
+    let snapshot = match (consistent, config.snapshot_number) {
+        (true, None) => Some(Snapshot::open(dsn, user, password, config)?),
+        _ => None,
+    };
+    let config = &QueryConfig {
+        snapshot_number: snapshot
+            .as_ref()
+            .map(Snapshot::number)
+            .or(config.snapshot_number),
+        ..config.clone()
+    };
+    let result = read_ranges(dsn, user, password, sql, column, num_partitions, config);
+    if let Some(snapshot) = snapshot {
+        snapshot.release()?;
+    }
+    result
+}
+
+// What a partition reader hands to the writing thread
+enum Part {
+    Schema(SchemaRef),
+    Batch(RecordBatch),
+    // Every batch of the partition was sent
+    Done,
+}
+
+// Passes a partition's batches on to the writing thread as they are fetched
+struct PartSink<'a>(&'a SyncSender<Result<Part>>);
+
+impl PartSink<'_> {
+    fn send(&self, part: Part) -> Result<()> {
+        self.0
+            .send(Ok(part))
+            .map_err(|_| anyhow!("partitioned read stopped"))
+    }
+}
+
+impl BatchSink for PartSink<'_> {
+    fn begin(&mut self, schema: &SchemaRef) -> Result<()> {
+        self.send(Part::Schema(schema.clone()))
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        self.send(Part::Batch(batch.clone()))
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn read_ranges(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    column: &str,
+    num_partitions: usize,
+    config: &QueryConfig,
+) -> Result<Partitioned> {
+    // Each partition fetches plain batches and dictionaries are built over the combined
+    // result. Progress is not reported, as the partitions would interleave their counts.
+    let queries = range_queries(dsn, user, password, sql, column, num_partitions, config)?;
+    let workers = queries.len().min(MAX_WORKERS);
+    eprintln!(
+        "DEBUG: read_partitioned running {} partitions on '{}' with {} readers",
+        queries.len(),
+        column,
+        workers
+    );
+    let part_config = &QueryConfig {
+        dictionary_columns: None,
+        dictionaries: None,
+        progress: None,
+        ..config.clone()
+    };
+    let (senders, receivers): (Vec<_>, Vec<_>) =
+        queries.iter().map(|_| sync_channel(READ_AHEAD)).unzip();
+    // Readers take partitions in range order, so the one being written is always running
+    let jobs = Mutex::new(queries.iter().zip(senders));
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| loop {
+                    let job = jobs.lock().unwrap_or_else(|e| e.into_inner()).next();
+                    let Some((query, sender)) = job else {
+                        break;
+                    };
+                    let mut sink = PartSink(&sender);
+                    let read = fetch_into(
+                        dsn,
+                        user,
+                        password,
+                        query,
+                        &[],
+                        part_config,
+                        &mut sink,
+                        &mut |_| Ok(()),
+                    );
+                    let _ = sender.send(read.map(|_| Part::Done));
+                })
+            })
+            .collect();
+        let result = write_parts(receivers, config);
+        // Partitions nobody reads any more are not started; running ones stop at their next
+        // batch, as their receiver is gone
+        jobs.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .by_ref()
+            .for_each(drop);
+        for handle in handles {
+            let _ = handle.join();
+        }
+        result
+    })
+}
+
+// Writes the partitions' batches, in range order, into one IPC stream
+fn write_parts(
+    receivers: Vec<Receiver<Result<Part>>>,
+    config: &QueryConfig,
+) -> Result<Partitioned> {
+    let mut encoder = dictionary::Encoder::new(config);
+    let mut encoded = None;
+    let mut bytes = Vec::new();
+    let mut rows = 0;
+    let mut last = None;
+    {
+        let mut sink = IpcSink::new(&mut bytes, compression::write_options(config)?);
+        'parts: for receiver in &receivers {
+            loop {
+                let part = receiver
+                    .recv()
+                    .map_err(|_| anyhow!("ERROR: partition reader thread panicked"))??;
+                match part {
+                    Part::Schema(schema) => {
+                        if encoded.is_none() {
+                            let schema = encoder.schema(&schema)?;
+                            sink.begin(&schema)?;
+                            encoded = Some(schema);
+                        }
+                    }
+                    Part::Batch(batch) => {
+                        if rows_left(config, rows) == Some(0) {
+                            break 'parts;
+                        }
+                        let Some(schema) = &encoded else {
+                            return Err(anyhow!("partition sent rows before its schema"));
+                        };
+                        let batch = trim_batch(batch, rows_left(config, rows));
+                        let batch = encoder.encode(batch, schema)?;
+                        rows += batch.num_rows();
+                        sink.write(&batch)?;
+                        last = Some(batch);
+                    }
+                    Part::Done => break,
+                }
+            }
+        }
+        let schema = match encoded {
+            Some(schema) => schema,
+            None => {
+                let schema = encoder.schema(&Arc::new(Schema::empty()))?;
+                sink.begin(&schema)?;
+                schema
+            }
+        };
+        if last.is_none() {
+            sink.write(&RecordBatch::new_empty(schema))?;
+        }
+        sink.finish()?;
+    }
+    Ok(Partitioned {
+        ipc: bytes,
+        rowcount: rows,
+        dictionaries: last
+            .as_ref()
+            .map(dictionary::categories)
+            .unwrap_or_default(),
+    })
+}
@@ -0,0 +1,170 @@
+use anyhow::{anyhow, Result};
+use arrow::datatypes::SchemaRef;
+use arrow::error::ArrowError;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread;
+
+use crate::sink::BatchSink;
+use crate::write::{self, Columns, Mode, Target};
+use crate::{fetch_into, map_query_error, IbarrowConnection, QueryConfig};
+
+// Batches fetched but not yet written; the source waits while the target catches up
+const PIPE_DEPTH: usize = 2;
+
+enum Piece {
+    Schema(SchemaRef),
+    Batch(RecordBatch),
+}
+
+// Hands the source's batches to the writing thread
+struct ChannelSink {
+    tx: SyncSender<Result<Piece>>,
+}
+
+impl ChannelSink {
+    fn send(&self, piece: Piece) -> Result<()> {
+        self.tx
+            .send(Ok(piece))
+            .map_err(|_| anyhow!("The target stopped reading"))
+    }
+}
+
+impl BatchSink for ChannelSink {
+    fn begin(&mut self, schema: &SchemaRef) -> Result<()> {
+        self.send(Piece::Schema(schema.clone()))
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        if batch.num_rows() == 0 {
+            return Ok(());
+        }
+        self.send(Piece::Batch(batch.clone()))
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+// The batches arriving from the source. A source error ends the stream with that error, so
+// the target rolls back rather than committing what came before it.
+struct ChannelReader {
+    schema: SchemaRef,
+    rx: Receiver<Result<Piece>>,
+}
+
+impl Iterator for ChannelReader {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.rx.recv().ok()? {
+            Ok(Piece::Batch(batch)) => Some(Ok(batch)),
+            Ok(Piece::Schema(_)) => Some(Err(ArrowError::ExternalError(
+                anyhow!("The source sent its schema twice").into(),
+            ))),
+            Err(e) => Some(Err(ArrowError::ExternalError(e.into()))),
+        }
+    }
+}
+
+impl RecordBatchReader for ChannelReader {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+// With batch_size overriding the connection's own
+fn sized(config: &QueryConfig, batch_size: Option<u32>) -> QueryConfig {
+    QueryConfig {
+        batch_size: batch_size.or(config.batch_size),
+        ..config.clone()
+    }
+}
+
+// Runs `sql` on `source` and bulk-inserts its rows into `table` on `target`, fetching the next
+// batches on another thread while the current ones are written. The target writes in one
+// transaction, rolled back if either side fails. Returns the rows copied.
+fn run(
+    source: &IbarrowConnection,
+    sql: &str,
+    target: &IbarrowConnection,
+    table: &str,
+    batch_size: Option<u32>,
+    mode: Mode,
+) -> Result<usize> {
+    let source_config = sized(&source.config, batch_size);
+    let target_config = sized(&target.config, batch_size);
+    let (tx, rx) = sync_channel::<Result<Piece>>(PIPE_DEPTH);
+    thread::scope(|scope| {
+        let fetcher = scope.spawn(move || {
+            let mut sink = ChannelSink { tx: tx.clone() };
+            let fetched = fetch_into(
+                &source.dsn,
+                &source.user,
+                &source.password,
+                sql,
+                &[],
+                &source_config,
+                &mut sink,
+                &mut |_| Ok(()),
+            );
+            if let Err(e) = fetched {
+                // The writer may already be gone, having failed first
+                let _ = tx.send(Err(e));
+            }
+        });
+        let schema = match rx.recv() {
+            Ok(Ok(Piece::Schema(schema))) => schema,
+            Ok(Ok(Piece::Batch(_))) => {
+                return Err(anyhow!("The source sent rows before its schema"))
+            }
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err(anyhow!("The source returned no result set")),
+        };
+        let batches = Box::new(ChannelReader { schema, rx });
+        let written = write::write_arrow(
+            &target.dsn,
+            &target.user,
+            &target.password,
+            &Target::insert(table, Columns::Matched, mode),
+            batches,
+            &target_config,
+        );
+        if fetcher.join().is_err() {
+            return Err(anyhow!("The source thread panicked"));
+        }
+        let rows = written?;
+        eprintln!("DEBUG: Piped {} rows into {}", rows, table);
+        Ok(rows)
+    })
+}
+
+// Streams the rows of `sql` on `source_conn` into `target_table` on `target_conn` without
+// passing them through Python, and returns the rows copied (see run). `batch_size`
+// overrides both connections' batch_size; `mode` is as for write_arrow.
+#[pyfunction]
+#[pyo3(signature = (source_conn, sql, target_conn, target_table, batch_size=None, mode="append"))]
+pub fn pipe(
+    py: Python<'_>,
+    source_conn: PyRef<'_, IbarrowConnection>,
+    sql: &str,
+    target_conn: PyRef<'_, IbarrowConnection>,
+    target_table: &str,
+    batch_size: Option<u32>,
+    mode: &str,
+) -> PyResult<usize> {
+    if batch_size == Some(0) {
+        return Err(PyValueError::new_err("batch_size must be greater than 0"));
+    }
+    if target_table.trim().is_empty() {
+        return Err(PyValueError::new_err("target_table must not be empty"));
+    }
+    let mode = write::Mode::parse(mode).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let source: &IbarrowConnection = &source_conn;
+    let target: &IbarrowConnection = &target_conn;
+    py.allow_threads(|| run(source, sql, target, target_table, batch_size, mode))
+        .map_err(map_query_error)
+}
@@ -0,0 +1,215 @@
+use anyhow::{anyhow, Result};
+use arrow::datatypes::Schema;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+use arrow_ipc::writer::StreamWriter;
+use odbc_api::handles::{SqlResult, Statement, StatementImpl};
+use odbc_api::parameter::InputParameter;
+use odbc_api::{Connection, CursorImpl, Environment};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::charset::Transcoded;
+use crate::driverenv;
+use crate::params::{self, SqlParam};
+use crate::progress::Progress;
+use crate::retry;
+use crate::watchdog::{self, Watchdog};
+use crate::{
+    access, build_connection_string, build_reader, charset, compression, diagnostics, limit_rows,
+    QueryConfig,
+};
+
+// A statement submitted with SQL_ATTR_ASYNC_ENABLE, re-polled until the driver is done with it
+struct PendingStatement<'conn> {
+    statement: StatementImpl<'conn>,
+    sql: Transcoded,
+    // Bound by pointer, so they must outlive the execution
+    _inputs: Vec<Box<dyn InputParameter>>,
+    outcome: Option<Result<bool>>,
+}
+
+impl PendingStatement<'_> {
+    // One SQLExecDirect call; in polling mode it returns SQL_STILL_EXECUTING until finished
+    fn poll(&mut self) {
+        let result = self.sql.exec_direct(&mut self.statement);
+        self.outcome = match result {
+            SqlResult::StillExecuting => None,
+            SqlResult::NoData => Some(Ok(false)),
+            SqlResult::NeedData => Some(Err(anyhow!("Statement requested data at execution"))),
+            other => Some(
+                other
+                    .into_result(&self.statement)
+                    .map(|_| true)
+                    .map_err(anyhow::Error::from),
+            ),
+        };
+    }
+}
+
+// Runs several queries concurrently from a single thread using ODBC asynchronous execution
+// (polling mode). Each query gets its own connection so the server can work on all of them
+// at once. Drivers without async support execute each statement to completion on the first
+// poll, which degrades to sequential execution with the same results.
+pub fn query_many_ipc(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    queries: &[(String, Vec<SqlParam>)],
+    config: &QueryConfig,
+    poll_interval: Duration,
+) -> Result<Vec<Vec<u8>>> {
+    let watchdog = Watchdog::start(config);
+    let result = query_many_watched(
+        dsn,
+        user,
+        password,
+        queries,
+        config,
+        poll_interval,
+        &watchdog,
+    );
+    watchdog.finish(result)
+}
+
+fn query_many_watched(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    queries: &[(String, Vec<SqlParam>)],
+    config: &QueryConfig,
+    poll_interval: Duration,
+    watchdog: &Watchdog,
+) -> Result<Vec<Vec<u8>>> {
+    // Vet every query before any of them is sent
+    for (sql, _) in queries {
+        access::check(sql, config)?;
+    }
+    diagnostics::clear();
+    let env = Environment::new()?;
+    let conn_str = build_connection_string(dsn, user, password, config);
+    let connections = queries
+        .iter()
+        .map(|_| retry::with_retry(config, || driverenv::connect(&env, &conn_str, config)))
+        .collect::<Result<Vec<Connection>>>()?;
+
+    let mut pending = Vec::with_capacity(queries.len());
+    let mut failure = None;
+    for (conn, (sql, params)) in connections.iter().zip(queries) {
+        match submit(conn, sql, params, config) {
+            Ok(statement) => pending.push(statement),
+            Err(e) => {
+                failure = Some(e);
+                break;
+            }
+        }
+    }
+
+    let mut cancelled = false;
+    loop {
+        // Past the deadline, statements still running are cancelled and polled until they stop
+        let cancel = !cancelled && watchdog.check().is_err();
+        cancelled |= cancel;
+        let mut executing = 0;
+        for statement in pending.iter_mut().filter(|s| s.outcome.is_none()) {
+            if cancel {
+                watchdog::cancel(&mut statement.statement);
+            }
+            statement.poll();
+            if statement.outcome.is_none() {
+                executing += 1;
+            }
+        }
+        if executing == 0 {
+            break;
+        }
+        thread::sleep(poll_interval);
+    }
+    // Statements already in flight are drained first; freeing a busy handle is an ODBC error
+    if let Some(e) = failure {
+        return Err(e);
+    }
+    eprintln!("DEBUG: {} statements finished executing", pending.len());
+
+    // Fetching goes through arrow-odbc, which needs a synchronous cursor
+    let mut results = Vec::with_capacity(pending.len());
+    // Totals run across all queries
+    let mut progress = Progress::new(config);
+    for mut statement in pending {
+        let has_cursor = statement.outcome.take().unwrap_or(Ok(false))?;
+        let mut bytes = Vec::new();
+        if has_cursor {
+            statement
+                .statement
+                .set_async_enable(false)
+                .into_result(&statement.statement)?;
+            // The handle has just executed successfully and is in cursor state
+            let cursor = unsafe { CursorImpl::new(watchdog.watch(statement.statement)) };
+            let reader = build_reader(cursor, config)?;
+            let schema = reader.schema();
+            let mut writer = StreamWriter::try_new_with_options(
+                &mut bytes,
+                &schema,
+                compression::write_options(config)?,
+            )
+            .map_err(|e| anyhow!("ERROR: Failed to create StreamWriter: {}", e))?;
+            for batch in limit_rows(reader, config) {
+                watchdog.check()?;
+                let batch = batch.map_err(|e| anyhow!("ERROR: Failed to read batch: {}", e))?;
+                progress.batch(&batch)?;
+                writer
+                    .write(&batch)
+                    .map_err(|e| anyhow!("ERROR: Failed to write batch: {}", e))?;
+            }
+            writer
+                .finish()
+                .map_err(|e| anyhow!("ERROR: Failed to finish stream writer: {}", e))?;
+        } else {
+            let schema = Arc::new(Schema::empty());
+            let mut writer = StreamWriter::try_new_with_options(
+                &mut bytes,
+                &schema,
+                compression::write_options(config)?,
+            )
+            .map_err(|e| anyhow!("ERROR: Failed to create StreamWriter: {}", e))?;
+            writer
+                .write(&RecordBatch::new_empty(schema))
+                .map_err(|e| anyhow!("ERROR: Failed to write empty batch: {}", e))?;
+            writer
+                .finish()
+                .map_err(|e| anyhow!("ERROR: Failed to finish stream writer: {}", e))?;
+        }
+        results.push(bytes);
+    }
+    Ok(results)
+}
+
+// Allocates a statement, binds its parameters, enables polling mode and starts execution
+fn submit<'conn>(
+    conn: &'conn Connection<'_>,
+    sql: &str,
+    params: &[SqlParam],
+    config: &QueryConfig,
+) -> Result<PendingStatement<'conn>> {
+    let sql = charset::transcode(sql, params, config.sql_charset.as_deref())?;
+    let mut statement = conn.preallocate()?.into_handle();
+    let inputs = params::to_inputs(&sql.params);
+    for (index, input) in inputs.iter().enumerate() {
+        unsafe { statement.bind_input_parameter(index as u16 + 1, input.as_ref()) }
+            .into_result(&statement)?;
+    }
+    if let Err(e) = statement.set_async_enable(true).into_result(&statement) {
+        eprintln!(
+            "DEBUG: Driver does not support asynchronous execution, running blocking: {}",
+            e
+        );
+    }
+    let mut pending = PendingStatement {
+        statement,
+        sql,
+        _inputs: inputs,
+        outcome: None,
+    };
+    pending.poll();
+    Ok(pending)
+}
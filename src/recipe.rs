@@ -0,0 +1,368 @@
+use anyhow::{anyhow, Result};
+use pyo3::exceptions::{PyIOError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyBytes, PyDate, PyDateTime, PyDict, PyFloat, PyLong, PyString, PyTime};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::compression::{self, Consumer};
+use crate::params::{self, SqlParam};
+use ibarrow_core::validate_config;
+
+use crate::{
+    dictionary, json_to_py, map_query_error, pandas_from_ipc, polars_from_ipc,
+    pyarrow_table_from_ipc, result, write_arrow_ipc_observed, IbarrowConnection, QueryConfig,
+};
+
+// Layout of serialized recipes; bumped when it changes incompatibly
+const FORMAT: u32 = 1;
+
+const OUTPUTS: [&str; 5] = ["polars", "pandas", "arrow", "ipc", "result"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ParamType {
+    Int,
+    Float,
+    Str,
+    Bool,
+    Date,
+    Datetime,
+    Time,
+    Decimal,
+}
+
+// A params_schema entry such as "int" or "date?"; a trailing `?` allows None
+fn param_type(name: &str, declared: &str) -> Result<(ParamType, bool)> {
+    let (kind, nullable) = match declared.strip_suffix('?') {
+        Some(kind) => (kind, true),
+        None => (declared, false),
+    };
+    let kind = match kind.to_lowercase().as_str() {
+        "int" => ParamType::Int,
+        "float" => ParamType::Float,
+        "str" => ParamType::Str,
+        "bool" => ParamType::Bool,
+        "date" => ParamType::Date,
+        "datetime" => ParamType::Datetime,
+        "time" => ParamType::Time,
+        "decimal" => ParamType::Decimal,
+        other => {
+            return Err(anyhow!(
+                "Unknown type '{}' for parameter :{}: expected 'int', 'float', 'str', 'bool', \
+                 'date', 'datetime', 'time' or 'decimal', optionally followed by '?'",
+                other,
+                name
+            ))
+        }
+    };
+    Ok((kind, nullable))
+}
+
+fn matches_type(value: &Bound<'_, PyAny>, kind: ParamType) -> PyResult<bool> {
+    let is_int = value.is_instance_of::<PyLong>() && !value.is_instance_of::<PyBool>();
+    Ok(match kind {
+        ParamType::Int => is_int,
+        ParamType::Float => is_int || value.is_instance_of::<PyFloat>(),
+        ParamType::Str => value.is_instance_of::<PyString>(),
+        ParamType::Bool => value.is_instance_of::<PyBool>(),
+        // datetime is a subclass of date
+        ParamType::Date => {
+            value.is_instance_of::<PyDate>() && !value.is_instance_of::<PyDateTime>()
+        }
+        ParamType::Datetime => value.is_instance_of::<PyDateTime>(),
+        ParamType::Time => value.is_instance_of::<PyTime>(),
+        ParamType::Decimal => {
+            let decimal = value.py().import_bound("decimal")?.getattr("Decimal")?;
+            is_int || value.is_instance(&decimal)?
+        }
+    })
+}
+
+// A vetted extraction definition: SQL with `:name` parameters, the types those parameters
+// must have, the QueryConfig to run it with and the form of the result. Recipes round-trip
+// through JSON, so they can be reviewed and versioned as files and run on any connection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[pyclass]
+pub struct Recipe {
+    #[pyo3(get)]
+    sql: String,
+    params_schema: BTreeMap<String, String>,
+    #[pyo3(get)]
+    config: Option<QueryConfig>,
+    #[pyo3(get)]
+    output: String,
+    #[pyo3(get)]
+    name: Option<String>,
+    #[pyo3(get)]
+    version: u32,
+}
+
+// Serialized form, tagged with the layout it was written in
+#[derive(Serialize, Deserialize)]
+struct Stored {
+    format: u32,
+    #[serde(flatten)]
+    recipe: Recipe,
+}
+
+impl Recipe {
+    // Serialized form. Settings that carry credentials (driver_env, storage_options) stay
+    // out of it: they come from the connection the recipe runs on.
+    fn stored(&self) -> Stored {
+        let mut recipe = self.clone();
+        if let Some(config) = &mut recipe.config {
+            config.driver_env = None;
+            config.storage_options = None;
+        }
+        Stored {
+            format: FORMAT,
+            recipe,
+        }
+    }
+
+    // Checks the output, the declared types and that the SQL placeholders and params_schema
+    // name the same parameters
+    fn check(&self) -> Result<()> {
+        if !OUTPUTS.contains(&self.output.as_str()) {
+            return Err(anyhow!(
+                "Unknown recipe output '{}': expected 'polars', 'pandas', 'arrow', 'ipc' or 'result'",
+                self.output
+            ));
+        }
+        for (name, declared) in &self.params_schema {
+            param_type(name, declared)?;
+        }
+        let used: BTreeSet<String> = params::placeholders(&self.sql).into_iter().collect();
+        if let Some(name) = used
+            .iter()
+            .find(|name| !self.params_schema.contains_key(*name))
+        {
+            return Err(anyhow!(
+                "SQL parameter :{} is not declared in params_schema",
+                name
+            ));
+        }
+        if let Some(name) = self.params_schema.keys().find(|name| !used.contains(*name)) {
+            return Err(anyhow!(
+                "params_schema declares :{}, which the SQL does not use",
+                name
+            ));
+        }
+        Ok(())
+    }
+
+    // Checks `values` against params_schema and converts them for binding. Optional
+    // parameters left out are bound as NULL.
+    fn values(&self, values: Option<&Bound<'_, PyDict>>) -> PyResult<HashMap<String, SqlParam>> {
+        if let Some(values) = values {
+            for key in values.keys() {
+                let key: String = key.extract()?;
+                if !self.params_schema.contains_key(&key) {
+                    return Err(PyValueError::new_err(format!(
+                        "Unknown parameter '{}' for this recipe",
+                        key
+                    )));
+                }
+            }
+        }
+        let mut bound = HashMap::new();
+        for (name, declared) in &self.params_schema {
+            let (kind, nullable) =
+                param_type(name, declared).map_err(|e| PyValueError::new_err(e.to_string()))?;
+            let value = match values.map(|values| values.get_item(name)).transpose()? {
+                Some(Some(value)) if !value.is_none() => value,
+                _ if nullable => {
+                    bound.insert(name.clone(), SqlParam::Null);
+                    continue;
+                }
+                _ => {
+                    return Err(PyValueError::new_err(format!(
+                        "Missing value for parameter :{}",
+                        name
+                    )))
+                }
+            };
+            if !matches_type(&value, kind)? {
+                return Err(PyTypeError::new_err(format!(
+                    "Parameter :{} must be {}, not {}",
+                    name,
+                    declared.trim_end_matches('?'),
+                    value.get_type().name()?
+                )));
+            }
+            bound.insert(name.clone(), value.extract()?);
+        }
+        Ok(bound)
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let stored: Stored =
+            serde_json::from_str(text).map_err(|e| anyhow!("Invalid recipe: {}", e))?;
+        if stored.format > FORMAT {
+            return Err(anyhow!(
+                "Recipe format {} is newer than this ibarrow supports ({})",
+                stored.format,
+                FORMAT
+            ));
+        }
+        stored.recipe.check()?;
+        Ok(stored.recipe)
+    }
+}
+
+#[pymethods]
+impl Recipe {
+    #[new]
+    #[pyo3(signature = (sql, params_schema=None, config=None, output="polars", name=None, version=1))]
+    fn new(
+        sql: String,
+        params_schema: Option<BTreeMap<String, String>>,
+        config: Option<QueryConfig>,
+        output: &str,
+        name: Option<String>,
+        version: u32,
+    ) -> PyResult<Self> {
+        let recipe = Self {
+            sql,
+            params_schema: params_schema.unwrap_or_default(),
+            config,
+            output: output.to_lowercase(),
+            name,
+            version,
+        };
+        recipe
+            .check()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(recipe)
+    }
+
+    #[getter]
+    fn params_schema(&self) -> BTreeMap<String, String> {
+        self.params_schema.clone()
+    }
+
+    // Checks `params` against params_schema without running anything
+    #[pyo3(signature = (params=None))]
+    fn validate(&self, params: Option<&Bound<'_, PyDict>>) -> PyResult<()> {
+        self.values(params).map(|_| ())
+    }
+
+    // Runs the recipe with the connection's DSN and credentials. The recipe's config, when
+    // set, is laid over the connection's (see QueryConfig::overlaid), so the connection's
+    // access role and session context still apply.
+    #[pyo3(signature = (conn, params=None, max_rows=None))]
+    fn run(
+        &self,
+        py: Python<'_>,
+        conn: PyRef<'_, IbarrowConnection>,
+        params: Option<&Bound<'_, PyDict>>,
+        max_rows: Option<u64>,
+    ) -> PyResult<Py<PyAny>> {
+        let conn: &IbarrowConnection = &conn;
+        let values = self.values(params)?;
+        let (sql, params) = params::bind_named(&self.sql, &values)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let base = match &self.config {
+            Some(config) => conn.config.overlaid(config),
+            None => conn.config.clone(),
+        };
+        let config = QueryConfig {
+            max_rows: max_rows.or(base.max_rows),
+            ..base
+        };
+        let consumer = match self.output.as_str() {
+            "polars" => Consumer::Polars,
+            "pandas" | "arrow" => Consumer::PyArrow,
+            _ => Consumer::Bytes,
+        };
+        let config = compression::for_consumer(py, &config, consumer)?;
+        eprintln!(
+            "DEBUG: Running recipe {} v{}",
+            self.name.as_deref().unwrap_or("<unnamed>"),
+            self.version
+        );
+        let mut last = None;
+        let mut bytes = Vec::new();
+        let rowcount = py
+            .allow_threads(|| {
+                write_arrow_ipc_observed(
+                    &conn.dsn,
+                    &conn.user,
+                    &conn.password,
+                    &sql,
+                    &params,
+                    &config,
+                    &mut bytes,
+                    &mut |batch| {
+                        last = Some(batch.clone());
+                        Ok(())
+                    },
+                )
+            })
+            .map_err(map_query_error)?;
+        match self.output.as_str() {
+            "polars" => polars_from_ipc(py, &bytes),
+            "pandas" => pandas_from_ipc(py, &bytes),
+            "arrow" => pyarrow_table_from_ipc(py, &bytes),
+            "ipc" => Ok(PyBytes::new_bound(py, &bytes).into_any().unbind()),
+            _ => {
+                let categories = last
+                    .as_ref()
+                    .map(dictionary::categories)
+                    .unwrap_or_default();
+                let result =
+                    result::IbarrowResult::new(bytes, rowcount)?.with_dictionaries(categories);
+                Ok(Py::new(py, result)?.into_any())
+            }
+        }
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string_pretty(&self.stored())
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    // The recipe as a dict, as written by to_json
+    fn to_dict(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        json_to_py(py, &self.stored())
+    }
+
+    #[staticmethod]
+    fn from_json(text: &str) -> PyResult<Self> {
+        let recipe = Self::parse(text).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        if let Some(config) = &recipe.config {
+            validate_config(config).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        }
+        Ok(recipe)
+    }
+
+    fn save(&self, path: PathBuf) -> PyResult<()> {
+        fs::write(&path, self.to_json()?).map_err(|e| {
+            PyIOError::new_err(format!("Failed to write recipe {}: {}", path.display(), e))
+        })
+    }
+
+    #[staticmethod]
+    fn load(path: PathBuf) -> PyResult<Self> {
+        let text = fs::read_to_string(&path).map_err(|e| {
+            PyIOError::new_err(format!("Failed to read recipe {}: {}", path.display(), e))
+        })?;
+        Self::from_json(&text)
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        serde_json::to_value(self).ok() == serde_json::to_value(other).ok()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Recipe(name={:?}, version={}, output='{}', params={:?})",
+            self.name,
+            self.version,
+            self.output,
+            self.params_schema.keys().collect::<Vec<_>>()
+        )
+    }
+}
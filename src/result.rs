@@ -0,0 +1,285 @@
+use arrow::datatypes::SchemaRef;
+use arrow::ffi_stream::FFI_ArrowArrayStream;
+use arrow::record_batch::RecordBatch;
+use arrow_ipc::reader::StreamReader;
+use pyo3::exceptions::{PyImportError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyCapsule, PyDict, PyList, PyTuple};
+use std::ffi::CString;
+use std::io::Cursor;
+use std::sync::Arc;
+
+use crate::tensor::{self, Library, TensorType};
+use crate::topk::Summary;
+use crate::{polars_from_ipc, pyarrow_table_from_ipc, PyArrowError};
+
+// A fetched query result held as an Arrow IPC stream. The query runs once; each conversion
+// is done on demand from the buffered stream.
+#[pyclass(subclass)]
+pub struct IbarrowResult {
+    // Shared with the readers behind exported C streams
+    ipc: Arc<[u8]>,
+    schema: SchemaRef,
+    #[pyo3(get)]
+    rowcount: usize,
+    top_k: Option<Summary>,
+    dictionaries: Vec<(String, Vec<String>)>,
+}
+
+impl IbarrowResult {
+    pub fn new(ipc: Vec<u8>, rowcount: usize) -> PyResult<Self> {
+        let schema = StreamReader::try_new(Cursor::new(&ipc), None)
+            .map_err(|e| PyArrowError::new_err(format!("Arrow Error: {}", e)))?
+            .schema();
+        Ok(Self {
+            ipc: ipc.into(),
+            schema,
+            rowcount,
+            top_k: None,
+            dictionaries: Vec::new(),
+        })
+    }
+
+    pub fn with_top_k(mut self, top_k: Option<Summary>) -> Self {
+        self.top_k = top_k;
+        self
+    }
+
+    pub fn with_dictionaries(mut self, dictionaries: Vec<(String, Vec<String>)>) -> Self {
+        self.dictionaries = dictionaries;
+        self
+    }
+
+    pub fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rowcount
+    }
+
+    pub fn batches(&self) -> PyResult<Vec<RecordBatch>> {
+        StreamReader::try_new(Cursor::new(Arc::clone(&self.ipc)), None)
+            .and_then(|reader| reader.collect())
+            .map_err(|e| PyArrowError::new_err(format!("Arrow Error: {}", e)))
+    }
+
+    // Feature matrix and optional label vector for to_torch() and to_tf_dataset()
+    #[allow(clippy::too_many_arguments)]
+    fn tensors(
+        &self,
+        py: Python<'_>,
+        module: &Bound<'_, PyModule>,
+        library: Library,
+        columns: Option<Vec<String>>,
+        label: Option<&str>,
+        dtype: &str,
+        label_dtype: Option<&str>,
+    ) -> PyResult<(Py<PyAny>, Option<Py<PyAny>>)> {
+        let invalid = |e: anyhow::Error| PyValueError::new_err(e.to_string());
+        let dtype = TensorType::parse(dtype).map_err(invalid)?;
+        let label_dtype = label_dtype
+            .map(TensorType::parse)
+            .transpose()
+            .map_err(invalid)?
+            .unwrap_or(dtype);
+        let label = label
+            .map(|name| tensor::column(&self.schema, name))
+            .transpose()
+            .map_err(invalid)?;
+        let features =
+            tensor::feature_columns(&self.schema, columns.as_deref(), label).map_err(invalid)?;
+        let batches = self.batches()?;
+        let rows = batches.iter().map(RecordBatch::num_rows).sum();
+        let shape = vec![rows, features.len()];
+        let x = tensor::build(py, module, library, &batches, &features, dtype, shape)?;
+        let y = label
+            .map(|label| {
+                let shape = vec![rows];
+                tensor::build(py, module, library, &batches, &[label], label_dtype, shape)
+            })
+            .transpose()?;
+        Ok((x, y))
+    }
+}
+
+#[pymethods]
+impl IbarrowResult {
+    fn to_polars(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        polars_from_ipc(py, &self.ipc)
+    }
+
+    fn to_pandas(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        let table = pyarrow_table_from_ipc(py, &self.ipc)?;
+        Ok(table.bind(py).call_method0("to_pandas")?.unbind())
+    }
+
+    // pyarrow.Table
+    fn to_arrow(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        pyarrow_table_from_ipc(py, &self.ipc)
+    }
+
+    fn to_ipc_bytes(&self, py: Python<'_>) -> Py<PyBytes> {
+        PyBytes::new_bound(py, &self.ipc).unbind()
+    }
+
+    // DataFrame Interchange Protocol (seaborn, plotly, ...), served by pyarrow.Table
+    #[pyo3(signature = (nan_as_null=false, allow_copy=true))]
+    fn __dataframe__(
+        &self,
+        py: Python<'_>,
+        nan_as_null: bool,
+        allow_copy: bool,
+    ) -> PyResult<Py<PyAny>> {
+        let table = pyarrow_table_from_ipc(py, &self.ipc)?;
+        Ok(table
+            .bind(py)
+            .call_method1("__dataframe__", (nan_as_null, allow_copy))?
+            .unbind())
+    }
+
+    // Arrow PyCapsule stream interface, consumed by pyarrow.table(), polars.from_arrow(),
+    // DuckDB and other libraries that understand the protocol. Each call exports a fresh
+    // stream over the buffered batches. `requested_schema` is a hint and is ignored.
+    #[pyo3(signature = (requested_schema=None))]
+    fn __arrow_c_stream__(
+        &self,
+        py: Python<'_>,
+        requested_schema: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Py<PyCapsule>> {
+        let _ = requested_schema;
+        let reader = StreamReader::try_new(Cursor::new(Arc::clone(&self.ipc)), None)
+            .map_err(|e| PyArrowError::new_err(format!("Arrow Error: {}", e)))?;
+        let stream = FFI_ArrowArrayStream::new(Box::new(reader));
+        let name = CString::new("arrow_array_stream")?;
+        Ok(PyCapsule::new_bound(py, stream, Some(name))?.unbind())
+    }
+
+    // Column metadata as a list of {"name", "type", "nullable"} dicts
+    #[getter]
+    fn columns(&self, py: Python<'_>) -> PyResult<Py<PyList>> {
+        let columns = PyList::empty_bound(py);
+        for field in self.schema.fields() {
+            let column = PyDict::new_bound(py);
+            column.set_item("name", field.name())?;
+            column.set_item("type", field.data_type().to_string())?;
+            column.set_item("nullable", field.is_nullable())?;
+            columns.append(column)?;
+        }
+        Ok(columns.unbind())
+    }
+
+    // Approximate most frequent values per column requested with query(top_k=...), as
+    // {column: [{"value", "count", "error"}]} with the most frequent first; None if not requested
+    #[getter]
+    fn top_k(&self, py: Python<'_>) -> PyResult<Option<Py<PyDict>>> {
+        let Some(summary) = &self.top_k else {
+            return Ok(None);
+        };
+        let result = PyDict::new_bound(py);
+        for (column, values) in summary {
+            let entries = PyList::empty_bound(py);
+            for (value, count, error) in values {
+                let entry = PyDict::new_bound(py);
+                entry.set_item("value", value)?;
+                entry.set_item("count", count)?;
+                entry.set_item("error", error)?;
+                entries.append(entry)?;
+            }
+            result.set_item(column, entries)?;
+        }
+        Ok(Some(result.unbind()))
+    }
+
+    // Final category list of every dictionary-encoded column, as {column: [category, ...]} where
+    // a category's position is its code. Pass it back as QueryConfig(dictionaries=...) to get
+    // the same codes in a later extract.
+    #[getter]
+    fn dictionaries(&self, py: Python<'_>) -> PyResult<Py<PyDict>> {
+        let result = PyDict::new_bound(py);
+        for (column, categories) in &self.dictionaries {
+            result.set_item(column, categories)?;
+        }
+        Ok(result.unbind())
+    }
+
+    // torch.Tensor of shape (rows, columns) holding `columns`, by default every numeric and
+    // boolean column; with `label`, a (features, labels) tuple whose labels are a 1-D tensor
+    #[pyo3(signature = (columns=None, label=None, dtype="float32", label_dtype=None))]
+    fn to_torch(
+        &self,
+        py: Python<'_>,
+        columns: Option<Vec<String>>,
+        label: Option<&str>,
+        dtype: &str,
+        label_dtype: Option<&str>,
+    ) -> PyResult<Py<PyAny>> {
+        let torch = py
+            .import_bound("torch")
+            .map_err(|_| PyImportError::new_err("to_torch needs PyTorch (pip install torch)"))?;
+        let (x, y) = self.tensors(
+            py,
+            &torch,
+            Library::Torch,
+            columns,
+            label,
+            dtype,
+            label_dtype,
+        )?;
+        Ok(match y {
+            Some(y) => PyTuple::new_bound(py, [x, y]).into_any().unbind(),
+            None => x,
+        })
+    }
+
+    // tf.data.Dataset of feature rows, or of (features, label) pairs with `label`, batched
+    // when `batch_size` is given
+    #[pyo3(signature = (columns=None, label=None, dtype="float32", label_dtype=None, batch_size=None))]
+    fn to_tf_dataset(
+        &self,
+        py: Python<'_>,
+        columns: Option<Vec<String>>,
+        label: Option<&str>,
+        dtype: &str,
+        label_dtype: Option<&str>,
+        batch_size: Option<usize>,
+    ) -> PyResult<Py<PyAny>> {
+        let tf = py.import_bound("tensorflow").map_err(|_| {
+            PyImportError::new_err("to_tf_dataset needs TensorFlow (pip install tensorflow)")
+        })?;
+        let numpy = py.import_bound("numpy")?;
+        let (x, y) = self.tensors(
+            py,
+            &numpy,
+            Library::NumPy,
+            columns,
+            label,
+            dtype,
+            label_dtype,
+        )?;
+        let slices = match y {
+            Some(y) => PyTuple::new_bound(py, [x, y]).into_any(),
+            None => x.into_bound(py),
+        };
+        let mut dataset = tf
+            .getattr("data")?
+            .getattr("Dataset")?
+            .call_method1("from_tensor_slices", (slices,))?;
+        if let Some(batch_size) = batch_size {
+            dataset = dataset.call_method1("batch", (batch_size,))?;
+        }
+        Ok(dataset.unbind())
+    }
+
+    fn __len__(&self) -> usize {
+        self.rowcount
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "IbarrowResult(columns={}, rowcount={})",
+            self.schema.fields().len(),
+            self.rowcount
+        )
+    }
+}
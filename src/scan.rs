@@ -0,0 +1,327 @@
+use anyhow::Result;
+use arrow::datatypes::SchemaRef;
+use ibarrow_core::lexer;
+use pyo3::exceptions::{PyImportError, PyStopIteration, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use serde_json::Value;
+
+use crate::batches::BatchIterator;
+use crate::interop::polars_from_batches;
+use crate::{backend, map_query_error, IbarrowConnection, QueryConfig};
+
+// Alias for the derived table when scanning a query rather than a table
+const SCAN_ALIAS: &str = "IBARROW_SCAN";
+
+// What follows FROM: a query becomes a derived table, anything else is taken as a table name
+pub fn source(table_or_sql: &str) -> String {
+    let trimmed = lexer::statement(table_or_sql);
+    let keyword = trimmed
+        .split(|c: char| c.is_whitespace() || c == '(')
+        .next()
+        .unwrap_or_default()
+        .to_uppercase();
+    if keyword == "SELECT" || keyword == "WITH" {
+        format!("({}) AS {}", trimmed, SCAN_ALIAS)
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+// SQL literal of a serialized Polars scalar, e.g. {"Int32": 5}, {"Dyn": {"Int": 5}} or
+// {"Scalar": {"dtype": ..., "value": {"String": "x"}}} depending on the Polars version. Dates,
+// decimals and other types are not translated.
+fn literal(value: &Value) -> Option<String> {
+    let Value::Object(map) = value else {
+        return None;
+    };
+    let (kind, inner) = map.iter().next().filter(|_| map.len() == 1)?;
+    match (kind.as_str(), inner) {
+        ("Dyn", inner) => literal(inner),
+        ("Scalar", Value::Object(scalar)) => literal(scalar.get("value")?),
+        (kind, Value::Number(number))
+            if kind.starts_with("Int") || kind.starts_with("UInt") || kind.starts_with("Float") =>
+        {
+            number
+                .as_f64()
+                .filter(|value| value.is_finite())
+                .map(|_| number.to_string())
+        }
+        ("String" | "Str", Value::String(text)) => Some(format!("'{}'", text.replace('\'', "''"))),
+        ("Boolean" | "Bool", Value::Bool(flag)) => {
+            Some(if *flag { "TRUE" } else { "FALSE" }.to_string())
+        }
+        _ => None,
+    }
+}
+
+// Firebird condition equivalent to a serialized Polars expression, or None if it cannot be
+// translated
+fn condition(expr: &Value) -> Option<String> {
+    let Value::Object(map) = expr else {
+        return None;
+    };
+    let (kind, inner) = map.iter().next().filter(|_| map.len() == 1)?;
+    match kind.as_str() {
+        "Column" => Some(quote(inner.as_str()?)),
+        "Literal" => literal(inner),
+        "BinaryExpr" => {
+            let left = condition(inner.get("left")?)?;
+            let right = condition(inner.get("right")?)?;
+            let op = match inner.get("op")?.as_str()? {
+                "Eq" => "=",
+                "NotEq" => "<>",
+                "Lt" => "<",
+                "LtEq" => "<=",
+                "Gt" => ">",
+                "GtEq" => ">=",
+                "EqValidity" => "IS NOT DISTINCT FROM",
+                "NotEqValidity" => "IS DISTINCT FROM",
+                "And" | "LogicalAnd" => "AND",
+                "Or" | "LogicalOr" => "OR",
+                _ => return None,
+            };
+            Some(format!("({} {} {})", left, op, right))
+        }
+        "Function" => {
+            let [input] = inner.get("input")?.as_array()?.as_slice() else {
+                return None;
+            };
+            let input = condition(input)?;
+            match inner.get("function")?.get("Boolean")?.as_str()? {
+                "IsNull" => Some(format!("({} IS NULL)", input)),
+                "IsNotNull" => Some(format!("({} IS NOT NULL)", input)),
+                "Not" => Some(format!("(NOT {})", input)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// The terms of a chain of ANDs
+fn conjuncts(expr: &Value) -> Vec<&Value> {
+    if let Some(inner) = expr.get("BinaryExpr") {
+        let op = inner.get("op").and_then(Value::as_str);
+        if let (Some("And" | "LogicalAnd"), Some(left), Some(right)) =
+            (op, inner.get("left"), inner.get("right"))
+        {
+            let mut terms = conjuncts(left);
+            terms.extend(conjuncts(right));
+            return terms;
+        }
+    }
+    vec![expr]
+}
+
+// WHERE clause for the translatable terms of a serialized predicate. Terms that cannot be
+// translated are left out, which only makes the server return more rows.
+fn where_clause(predicate: &Value) -> Option<String> {
+    let terms: Vec<String> = conjuncts(predicate)
+        .into_iter()
+        .filter_map(condition)
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" AND "))
+}
+
+fn scan_sql(
+    source: &str,
+    columns: Option<&[String]>,
+    filter: Option<&str>,
+    limit: Option<usize>,
+) -> String {
+    let list = match columns {
+        Some(columns) if !columns.is_empty() => columns
+            .iter()
+            .map(|c| quote(c))
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => "*".to_string(),
+    };
+    let mut sql = format!("SELECT {} FROM {}", list, source);
+    if let Some(filter) = filter {
+        sql.push_str(&format!(" WHERE {}", filter));
+    }
+    if let Some(limit) = limit {
+        sql.push_str(&format!(" ROWS {}", limit));
+    }
+    sql
+}
+
+// Serializes a Polars expression to its JSON tree. Polars warns that the format is unstable,
+// so the warning is silenced; versions that cannot serialize get no pushdown.
+fn serialize(py: Python<'_>, predicate: &Bound<'_, PyAny>) -> Option<Value> {
+    let warnings = py.import_bound("warnings").ok()?;
+    let guard = warnings.call_method0("catch_warnings").ok()?;
+    guard.call_method0("__enter__").ok()?;
+    let _ = warnings.call_method1("simplefilter", ("ignore",));
+    let kwargs = PyDict::new_bound(py);
+    let json = kwargs.set_item("format", "json").ok().and_then(|_| {
+        predicate
+            .getattr("meta")
+            .and_then(|meta| meta.call_method("serialize", (), Some(&kwargs)))
+            .and_then(|json| json.extract::<String>())
+            .ok()
+    });
+    let _ = guard.call_method1("__exit__", (py.None(), py.None(), py.None()));
+    serde_json::from_str(&json?).ok()
+}
+
+// The callable handed to polars.io.plugins.register_io_source. Polars calls it with the
+// projection, predicate, row limit and batch size its optimizer settled on.
+#[pyclass]
+pub struct ScanSource {
+    dsn: String,
+    user: String,
+    password: String,
+    config: QueryConfig,
+    source: String,
+}
+
+#[pymethods]
+impl ScanSource {
+    fn __call__(
+        &self,
+        py: Python<'_>,
+        with_columns: Option<Vec<String>>,
+        predicate: Option<Bound<'_, PyAny>>,
+        n_rows: Option<usize>,
+        batch_size: Option<usize>,
+    ) -> ScanIterator {
+        let filter = predicate
+            .as_ref()
+            .and_then(|predicate| serialize(py, predicate))
+            .as_ref()
+            .and_then(where_clause);
+        // A ROWS limit would count rows the predicate, checked again below, may still drop
+        let limit = n_rows.filter(|_| predicate.is_none());
+        let sql = scan_sql(
+            &self.source,
+            with_columns.as_deref(),
+            filter.as_deref(),
+            limit,
+        );
+        eprintln!("DEBUG: scan running {}", sql);
+        let batches = BatchIterator::start(
+            &self.dsn,
+            &self.user,
+            &self.password,
+            &sql,
+            &self.config,
+            batch_size,
+            1,
+        );
+        ScanIterator {
+            batches,
+            with_columns,
+            predicate: predicate.map(Bound::unbind),
+            remaining: n_rows,
+        }
+    }
+}
+
+// Polars DataFrames of a running scan. The server's collation and CHAR padding may let rows
+// through the pushed-down WHERE clause that the Polars predicate rejects, so every frame is
+// filtered again.
+#[pyclass]
+pub struct ScanIterator {
+    batches: BatchIterator,
+    with_columns: Option<Vec<String>>,
+    predicate: Option<Py<PyAny>>,
+    remaining: Option<usize>,
+}
+
+#[pymethods]
+impl ScanIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        if self.remaining == Some(0) {
+            self.batches.close();
+            return Err(PyStopIteration::new_err(()));
+        }
+        let batch = match self.batches.recv(py) {
+            Some(Ok(batch)) => batch,
+            Some(Err(e)) => {
+                self.batches.close();
+                return Err(map_query_error(e));
+            }
+            None => return Err(PyStopIteration::new_err(())),
+        };
+        let mut df = polars_from_batches(py, batch.schema(), vec![batch])?.into_bound(py);
+        if let Some(columns) = &self.with_columns {
+            df = df.call_method1("select", (columns.clone(),))?;
+        }
+        if let Some(predicate) = &self.predicate {
+            df = df.call_method1("filter", (predicate.bind(py),))?;
+        }
+        if let Some(remaining) = self.remaining.as_mut() {
+            df = df.call_method1("head", (*remaining,))?;
+            *remaining -= df.len()?.min(*remaining);
+        }
+        Ok(df.unbind())
+    }
+}
+
+pub fn schema(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    source: &str,
+    config: &QueryConfig,
+) -> Result<SchemaRef> {
+    let sql = format!("SELECT * FROM {}", source);
+    backend::open(dsn, user, password, config)?.schema(&sql, &[], config)
+}
+
+// Lazily scans a table or query into a Polars LazyFrame through Polars' IO plugin interface.
+// The columns the plan needs become the SELECT list and the filters it pushes down become the
+// WHERE clause, so only the rows and columns the plan uses leave the server.
+#[pyfunction]
+pub fn scan(
+    py: Python<'_>,
+    conn: PyRef<'_, IbarrowConnection>,
+    table_or_sql: &str,
+) -> PyResult<Py<PyAny>> {
+    if table_or_sql.trim().is_empty() {
+        return Err(PyValueError::new_err("table_or_sql must not be empty"));
+    }
+    let register = py
+        .import_bound("polars.io.plugins")
+        .and_then(|plugins| plugins.getattr("register_io_source"))
+        .map_err(|_| {
+            PyImportError::new_err(
+                "scan needs a Polars version with IO plugins (polars.io.plugins)",
+            )
+        })?;
+    let io_source = ScanSource {
+        dsn: conn.dsn.clone(),
+        user: conn.user.clone(),
+        password: conn.password.clone(),
+        config: conn.config.clone(),
+        source: source(table_or_sql),
+    };
+    let schema = py
+        .allow_threads(|| {
+            schema(
+                &io_source.dsn,
+                &io_source.user,
+                &io_source.password,
+                &io_source.source,
+                &io_source.config,
+            )
+        })
+        .map_err(map_query_error)?;
+    let empty = polars_from_batches(py, schema, Vec::new())?;
+    let kwargs = PyDict::new_bound(py);
+    kwargs.set_item("schema", empty.bind(py).getattr("schema")?)?;
+    Ok(register
+        .call((Py::new(py, io_source)?,), Some(&kwargs))?
+        .unbind())
+}
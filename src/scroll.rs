@@ -0,0 +1,266 @@
+use anyhow::{anyhow, Result};
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::{RecordBatch, RecordBatchIterator, RecordBatchReader};
+use odbc_api::handles::{AsStatementRef, SqlResult, Statement, StatementConnection};
+use odbc_api::sys::{
+    FetchOrientation, Len, Pointer, SQLFetchScroll, SQLSetStmtAttr, SqlReturn, StatementAttribute,
+};
+use odbc_api::{Connection, CursorImpl};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::sync::{Arc, Mutex};
+
+use crate::domains::Domains;
+use crate::sink::{BatchSink, IpcSink};
+use crate::txwatch::TransactionWatch;
+use crate::{
+    access, arrays, build_connection_string, charset, compression, diagnostics, domains, driverenv,
+    emptystr, interop, map_query_error, polars_from_ipc, reader_builder, retry, QueryConfig,
+};
+
+// SQL_ATTR_CURSOR_SCROLLABLE value asking for a cursor that can fetch in any direction
+const SQL_SCROLLABLE: usize = 1;
+
+type ScrollableCursor = CursorImpl<StatementConnection<Connection<'static>>>;
+
+// Turns a raw return code into Ok(false) for SQL_NO_DATA, or an error with the driver's
+// diagnostics
+fn check(ret: SqlReturn, function: &'static str, cursor: &mut ScrollableCursor) -> Result<bool> {
+    let result = match ret {
+        SqlReturn::SUCCESS => SqlResult::Success(()),
+        SqlReturn::SUCCESS_WITH_INFO => SqlResult::SuccessWithInfo(()),
+        SqlReturn::NO_DATA => SqlResult::NoData,
+        _ => SqlResult::Error { function },
+    };
+    Ok(result.into_result_bool(&cursor.as_stmt_ref())?)
+}
+
+// Connects and executes `sql` on a statement asking for a scrollable cursor. Drivers that only
+// offer forward-only cursors refuse the statement attribute.
+fn open(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+) -> Result<Option<(ScrollableCursor, Domains)>> {
+    access::check(sql, config)?;
+    diagnostics::clear();
+    let conn_str = build_connection_string(dsn, user, password, config);
+    let sql = charset::transcode(sql, &[], config.sql_charset.as_deref())?;
+    let env = odbc_api::environment()?;
+    retry::with_retry(config, || {
+        let conn = driverenv::connect(env, &conn_str, config)?;
+        let domains = domains::resolve(&conn, &conn_str, &sql)?;
+        let mut statement = conn.into_preallocated()?.into_handle();
+        let handle = statement.as_stmt_ref().as_sys();
+        let ret = unsafe {
+            SQLSetStmtAttr(
+                handle,
+                StatementAttribute::CursorScrollable,
+                SQL_SCROLLABLE as Pointer,
+                0,
+            )
+        };
+        if !matches!(ret, SqlReturn::SUCCESS | SqlReturn::SUCCESS_WITH_INFO) {
+            return Err(anyhow!(
+                "The ODBC driver does not support scrollable cursors"
+            ));
+        }
+        let cursor = sql.execute(statement)?;
+        Ok(cursor.map(|cursor| (cursor, domains)))
+    })
+}
+
+// Returned by IbarrowConnection.scroll_cursor(): a scrollable cursor kept open on its own
+// connection, read a page at a time with fetch() and repositioned with seek(), so a result set
+// can be paged backward and forward without running the query again
+#[pyclass]
+pub struct ScrollCursor {
+    cursor: Arc<Mutex<Option<ScrollableCursor>>>,
+    config: QueryConfig,
+    // 0-based row the next fetch() starts at
+    position: Mutex<u64>,
+    schema: SchemaRef,
+    domains: Domains,
+    padded: Vec<bool>,
+    watch: Mutex<Option<TransactionWatch>>,
+}
+
+impl ScrollCursor {
+    pub fn open(
+        dsn: &str,
+        user: &str,
+        password: &str,
+        sql: &str,
+        config: &QueryConfig,
+    ) -> Result<Self> {
+        let (mut cursor, domains) = open(dsn, user, password, sql, config)?
+            .ok_or_else(|| anyhow!("scroll_cursor needs a query that returns a result set"))?;
+        let padded = emptystr::padded(&mut cursor)?;
+        let reader = reader_builder(config).build(cursor)?;
+        let schema = reader.schema();
+        let cursor = reader.into_cursor()?;
+        eprintln!("DEBUG: Opened scrollable cursor");
+        let cursor = Arc::new(Mutex::new(Some(cursor)));
+        let held = Arc::clone(&cursor);
+        // Closing the connection rolls the transaction back
+        let watch = TransactionWatch::start(config, "ScrollCursor", move || {
+            held.lock().unwrap_or_else(|e| e.into_inner()).take();
+        })?;
+        Ok(Self {
+            cursor,
+            config: config.clone(),
+            position: Mutex::new(0),
+            schema,
+            domains,
+            padded,
+            watch: Mutex::new(watch),
+        })
+    }
+
+    // Reads the next `rows` rows, or fewer at the end of the result
+    fn fetch_batch(&self, rows: usize) -> Result<RecordBatch> {
+        let mut guard = self.cursor.lock().unwrap_or_else(|e| e.into_inner());
+        let cursor = guard.take().ok_or_else(|| self.closed_error())?;
+        let mut builder = reader_builder(&self.config);
+        builder.with_max_num_rows_per_batch(rows);
+        let mut reader = builder.build(cursor)?;
+        let batch = reader.next().transpose()?;
+        *guard = Some(reader.into_cursor()?);
+        let batch = batch.unwrap_or_else(|| RecordBatch::new_empty(self.schema.clone()));
+        *self.position.lock().unwrap_or_else(|e| e.into_inner()) += batch.num_rows() as u64;
+        Ok(batch)
+    }
+
+    // Positions the cursor so the next fetch starts at the 0-based row `offset`
+    fn seek_to(&self, offset: u64) -> Result<()> {
+        let mut guard = self.cursor.lock().unwrap_or_else(|e| e.into_inner());
+        let cursor = guard.as_mut().ok_or_else(|| self.closed_error())?;
+        let offset =
+            Len::try_from(offset).map_err(|_| anyhow!("offset {} is too large", offset))?;
+        // One-row rowset with nothing bound: moves onto row `offset` (1-based) without reading
+        // it, so the next rowset starts right after it. Row 0 is before the first row.
+        let mut statement = cursor.as_stmt_ref();
+        unsafe { statement.set_row_array_size(1) }.into_result(&statement)?;
+        let handle = statement.as_sys();
+        let ret = unsafe { SQLFetchScroll(handle, FetchOrientation::Absolute, offset) };
+        check(ret, "SQLFetchScroll", cursor).map_err(|e| {
+            anyhow!(
+                "Failed to seek the cursor; the driver may not support scrolling: {}",
+                e
+            )
+        })?;
+        *self.position.lock().unwrap_or_else(|e| e.into_inner()) = offset as u64;
+        Ok(())
+    }
+
+    fn closed_error(&self) -> anyhow::Error {
+        let watch = self.watch.lock().unwrap_or_else(|e| e.into_inner());
+        watch
+            .as_ref()
+            .and_then(TransactionWatch::rolled_back)
+            .unwrap_or_else(|| anyhow!("Scroll cursor was closed"))
+    }
+
+    fn position(&self) -> u64 {
+        *self.position.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[pymethods]
+impl ScrollCursor {
+    // Next `n` rows from the current position as a Polars DataFrame; fewer at the end of the
+    // result and none past it
+    fn fetch(&self, py: Python<'_>, n: usize) -> PyResult<Py<PyAny>> {
+        if n == 0 {
+            return Err(PyValueError::new_err("n must be greater than 0"));
+        }
+        let (schema, batches) = py
+            .allow_threads(|| -> Result<_> {
+                let batch = self.fetch_batch(n)?;
+                let reader = Box::new(RecordBatchIterator::new(
+                    vec![Ok(batch)],
+                    self.schema.clone(),
+                ));
+                let reader = domains::wrap(reader, &self.domains);
+                let reader = arrays::wrap(reader, &self.config)?;
+                let reader = emptystr::wrap(reader, &self.padded, &self.config)?;
+                let schema = reader.schema();
+                Ok((schema, reader.collect::<Result<Vec<_>, _>>()?))
+            })
+            .map_err(map_query_error)?;
+        if interop::polars_reads_c_stream(py) {
+            return interop::polars_from_batches(py, schema, batches);
+        }
+        let mut bytes = Vec::new();
+        py.allow_threads(|| -> Result<()> {
+            let mut sink = IpcSink::new(&mut bytes, compression::write_options(&self.config)?);
+            sink.begin(&schema)?;
+            for batch in &batches {
+                sink.write(batch)?;
+            }
+            sink.finish()
+        })
+        .map_err(map_query_error)?;
+        polars_from_ipc(py, &bytes)
+    }
+
+    // Moves to the 0-based row `offset`; the next fetch() starts there
+    fn seek(&self, py: Python<'_>, offset: u64) -> PyResult<()> {
+        py.allow_threads(|| self.seek_to(offset))
+            .map_err(map_query_error)
+    }
+
+    // 0-based row the next fetch() starts at
+    #[getter(position)]
+    fn get_position(&self) -> u64 {
+        self.position()
+    }
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.cursor
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_none()
+    }
+
+    // Column names of the result set
+    #[getter]
+    fn columns(&self) -> Vec<String> {
+        self.schema
+            .fields()
+            .iter()
+            .map(|field| field.name().clone())
+            .collect()
+    }
+
+    // Closes the cursor and its connection
+    fn close(&self) {
+        self.watch.lock().unwrap_or_else(|e| e.into_inner()).take();
+        self.cursor.lock().unwrap_or_else(|e| e.into_inner()).take();
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &self,
+        _exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> bool {
+        self.close();
+        false
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ScrollCursor(position={}, closed={})",
+            self.position(),
+            self.closed()
+        )
+    }
+}
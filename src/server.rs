@@ -0,0 +1,704 @@
+use anyhow::{anyhow, Result};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufWriter, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::export::{self, ParquetSink};
+use crate::params::{self, QueryParams, SqlParam};
+use crate::{access, fetch_into, retry, write_arrow_ipc_impl, QueryConfig, IPC_WRITE_BUFFER};
+
+// Largest request head and SQL body accepted
+const MAX_HEAD_BYTES: usize = 64 * 1024;
+const MAX_BODY_BYTES: usize = 1 << 20;
+// How often the accept loop looks for a shutdown request
+const ACCEPT_POLL: Duration = Duration::from_millis(50);
+// A client that sends nothing for this long is dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+const IPC_CONTENT_TYPE: &str = "application/vnd.apache.arrow.stream";
+const PARQUET_CONTENT_TYPE: &str = "application/vnd.apache.parquet";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ipc,
+    Parquet,
+}
+
+impl Format {
+    fn parse(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "ipc" | "arrow" => Ok(Format::Ipc),
+            "parquet" => Ok(Format::Parquet),
+            _ => Err(anyhow!("Unknown format '{}'; use 'ipc' or 'parquet'", name)),
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Ipc => IPC_CONTENT_TYPE,
+            Format::Parquet => PARQUET_CONTENT_TYPE,
+        }
+    }
+}
+
+// What every request runs with: the connection it queries through, the bearer token clients
+// must present, if any, and the browser origins allowed to call it
+struct Service {
+    dsn: String,
+    user: String,
+    password: String,
+    config: QueryConfig,
+    token: Option<String>,
+    allowed_origins: Vec<String>,
+    max_connections: usize,
+    active: AtomicUsize,
+    // Numbers queries in the log, which never shows their SQL or parameters
+    queries: AtomicUsize,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+// A query as posted: the SQL text itself, or JSON with `sql` and optional `params`,
+// `format`, `max_rows` and `compression`
+struct QueryRequest {
+    sql: String,
+    params: Vec<SqlParam>,
+    format: Format,
+    max_rows: Option<u64>,
+    compression: String,
+}
+
+// A request the client got wrong, answered with this status
+#[derive(Debug)]
+struct HttpError {
+    status: u16,
+    message: String,
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+fn http_error(status: u16, message: impl Into<String>) -> anyhow::Error {
+    anyhow::Error::new(HttpError {
+        status,
+        message: message.into(),
+    })
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "Internal Server Error",
+    }
+}
+
+// HTTP status for a failed query, chosen like map_query_error picks the Python exception
+fn status_of(error: &anyhow::Error) -> u16 {
+    if let Some(error) = error.downcast_ref::<HttpError>() {
+        return error.status;
+    }
+    let msg = error.to_string();
+    if msg.contains("timed out") {
+        504
+    } else if msg.starts_with("Access denied") {
+        403
+    } else if msg.contains("IM002") || msg.contains("connection") {
+        502
+    } else if msg.contains("SQL") || msg.contains("syntax") {
+        400
+    } else {
+        500
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: u16, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason(status),
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+fn respond_error(stream: &mut TcpStream, error: &anyhow::Error) -> io::Result<()> {
+    let status = status_of(error);
+    let body = json!({
+        "error": error.to_string(),
+        "sqlstate": retry::sqlstate(error),
+    });
+    respond(
+        stream,
+        status,
+        "application/json",
+        body.to_string().as_bytes(),
+    )
+}
+
+// Response body sent with chunked transfer encoding. The status line and headers go out with
+// the first bytes, so a query that fails before producing any output is still answered with
+// an error status.
+struct ChunkedBody {
+    stream: TcpStream,
+    content_type: &'static str,
+    started: bool,
+}
+
+impl ChunkedBody {
+    fn start(&mut self) -> io::Result<()> {
+        if !self.started {
+            write!(
+                self.stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n",
+                self.content_type
+            )?;
+            self.started = true;
+        }
+        Ok(())
+    }
+
+    // Sends the last chunk, which tells the client the body is complete
+    fn finish(mut self) -> io::Result<()> {
+        self.start()?;
+        self.stream.write_all(b"0\r\n\r\n")?;
+        self.stream.flush()
+    }
+}
+
+impl Write for ChunkedBody {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.start()?;
+        write!(self.stream, "{:x}\r\n", buf.len())?;
+        self.stream.write_all(buf)?;
+        self.stream.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let (head_len, mut request) = loop {
+        let read = stream.read(&mut chunk)?;
+        if read == 0 {
+            return Err(http_error(
+                400,
+                "Connection closed before the request was complete",
+            ));
+        }
+        buf.extend_from_slice(&chunk[..read]);
+        let mut headers = [httparse::EMPTY_HEADER; 64];
+        let mut parsed = httparse::Request::new(&mut headers);
+        match parsed
+            .parse(&buf)
+            .map_err(|e| http_error(400, format!("Malformed request: {}", e)))?
+        {
+            httparse::Status::Complete(len) => {
+                let target = parsed.path.unwrap_or("/");
+                let (path, query) = target.split_once('?').unwrap_or((target, ""));
+                let request = Request {
+                    method: parsed.method.unwrap_or_default().to_string(),
+                    path: path.to_string(),
+                    query: url::form_urlencoded::parse(query.as_bytes())
+                        .into_owned()
+                        .collect(),
+                    headers: parsed
+                        .headers
+                        .iter()
+                        .map(|header| {
+                            (
+                                header.name.to_lowercase(),
+                                String::from_utf8_lossy(header.value).to_string(),
+                            )
+                        })
+                        .collect(),
+                    body: Vec::new(),
+                };
+                break (len, request);
+            }
+            httparse::Status::Partial if buf.len() > MAX_HEAD_BYTES => {
+                return Err(http_error(413, "Request headers are too large"));
+            }
+            httparse::Status::Partial => {}
+        }
+    };
+    if request.header("transfer-encoding").is_some() {
+        return Err(http_error(
+            411,
+            "Send the request body with a Content-Length",
+        ));
+    }
+    let length: usize = match request.header("content-length") {
+        Some(length) => length
+            .trim()
+            .parse()
+            .map_err(|_| http_error(400, "Invalid Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY_BYTES {
+        return Err(http_error(413, "The request body is too large"));
+    }
+    let mut body = buf.split_off(head_len);
+    body.truncate(length);
+    while body.len() < length {
+        let read = stream.read(&mut chunk)?;
+        if read == 0 {
+            return Err(http_error(
+                400,
+                "Connection closed before the body was complete",
+            ));
+        }
+        body.extend_from_slice(&chunk[..read.min(length - body.len())]);
+    }
+    request.body = body;
+    Ok(request)
+}
+
+// A query parameter given in JSON
+fn json_param(value: &Value) -> SqlParam {
+    match value {
+        Value::Null => SqlParam::Null,
+        Value::Bool(b) => SqlParam::Bool(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlParam::Int(i),
+            None => SqlParam::Float(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => SqlParam::Text(s.clone()),
+        other => SqlParam::Text(other.to_string()),
+    }
+}
+
+fn parse_query(service: &Service, request: &Request) -> Result<QueryRequest> {
+    let text = String::from_utf8(request.body.clone())
+        .map_err(|_| http_error(400, "The request body is not valid UTF-8"))?;
+    let is_json = request
+        .header("content-type")
+        .is_some_and(|content_type| content_type.starts_with("application/json"));
+    // A web page can POST text/plain anywhere without a CORS preflight, but not JSON, and
+    // without a token nothing else tells such a request from the user's own
+    if !is_json && service.token.is_none() {
+        return Err(http_error(
+            415,
+            "Post JSON (Content-Type: application/json), or start the service with a token \
+             to post SQL text",
+        ));
+    }
+    let (sql, params, format, max_rows, compression) = if is_json {
+        let body: Value = serde_json::from_str(&text)
+            .map_err(|e| http_error(400, format!("Invalid JSON body: {}", e)))?;
+        let sql = body["sql"]
+            .as_str()
+            .ok_or_else(|| http_error(400, "The JSON body needs a 'sql' string"))?
+            .to_string();
+        let params = match &body["params"] {
+            Value::Null => None,
+            Value::Array(values) => Some(QueryParams::Positional(
+                values.iter().map(json_param).collect(),
+            )),
+            Value::Object(values) => Some(QueryParams::Named(
+                values
+                    .iter()
+                    .map(|(name, value)| (name.clone(), json_param(value)))
+                    .collect(),
+            )),
+            _ => return Err(http_error(400, "'params' must be a list or an object")),
+        };
+        let max_rows = match &body["max_rows"] {
+            Value::Null => None,
+            value => Some(
+                value
+                    .as_u64()
+                    .ok_or_else(|| http_error(400, "'max_rows' must be a positive integer"))?,
+            ),
+        };
+        (
+            sql,
+            params,
+            body["format"].as_str().map(str::to_string),
+            max_rows,
+            body["compression"].as_str().map(str::to_string),
+        )
+    } else {
+        (text, None, None, None, None)
+    };
+    if sql.trim().is_empty() {
+        return Err(http_error(400, "No SQL was posted"));
+    }
+    if service.config.read_only && !access::is_query(&sql) {
+        return Err(http_error(
+            403,
+            "The service is read-only: only SELECT statements may be posted",
+        ));
+    }
+    // The body wins over the query string, which wins over the Accept header
+    let format = match format.or_else(|| request.query.get("format").cloned()) {
+        Some(name) => Format::parse(&name).map_err(|e| http_error(400, e.to_string()))?,
+        None if request
+            .header("accept")
+            .is_some_and(|accept| accept.contains(PARQUET_CONTENT_TYPE)) =>
+        {
+            Format::Parquet
+        }
+        None => Format::Ipc,
+    };
+    let (sql, params) = params::bind(&sql, params).map_err(|e| http_error(400, e.to_string()))?;
+    Ok(QueryRequest {
+        sql,
+        params,
+        format,
+        max_rows,
+        compression: compression
+            .or_else(|| request.query.get("compression").cloned())
+            .unwrap_or_else(|| "snappy".to_string()),
+    })
+}
+
+// Compares every byte whatever the first difference, so response times do not reveal how
+// much of a guessed token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn authorized(service: &Service, request: &Request) -> bool {
+    match &service.token {
+        Some(token) => request
+            .header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|presented| {
+                constant_time_eq(presented.trim().as_bytes(), token.as_bytes())
+            }),
+        None => true,
+    }
+}
+
+// Browsers send Origin with cross-site requests; other clients do not send it at all
+fn origin_allowed(service: &Service, request: &Request) -> bool {
+    match request.header("origin") {
+        Some(origin) => service
+            .allowed_origins
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(origin.trim())),
+        None => true,
+    }
+}
+
+// Runs the posted query and streams its result; errors before the first byte of output get
+// an error response, later ones cut the response short
+fn run_query(service: &Service, stream: TcpStream, request: &Request) -> Result<()> {
+    let mut stream = stream;
+    let query = match parse_query(service, request) {
+        Ok(query) => query,
+        Err(e) => return Ok(respond_error(&mut stream, &e)?),
+    };
+    let id = service.queries.fetch_add(1, Ordering::Relaxed) + 1;
+    let started = Instant::now();
+    eprintln!("DEBUG: HTTP query #{} started", id);
+    // A client may ask for fewer rows than the operator allows, never more
+    let max_rows = match (query.max_rows, service.config.max_rows) {
+        (Some(asked), Some(cap)) => Some(asked.min(cap)),
+        (asked, cap) => asked.or(cap),
+    };
+    let config = QueryConfig {
+        max_rows,
+        ..service.config.clone()
+    };
+    let body = ChunkedBody {
+        stream,
+        content_type: query.format.content_type(),
+        started: false,
+    };
+    let mut out = BufWriter::with_capacity(IPC_WRITE_BUFFER, body);
+    let (dsn, user, password) = (&service.dsn, &service.user, &service.password);
+    let result = match query.format {
+        Format::Ipc => write_arrow_ipc_impl(
+            dsn,
+            user,
+            password,
+            &query.sql,
+            &query.params,
+            &config,
+            &mut out,
+        ),
+        Format::Parquet => export::compression(&query.compression, None)
+            .map_err(|e| http_error(400, e.to_string()))
+            .and_then(|compression| {
+                let mut sink = ParquetSink::new(&mut out, compression, &[]);
+                fetch_into(
+                    dsn,
+                    user,
+                    password,
+                    &query.sql,
+                    &query.params,
+                    &config,
+                    &mut sink,
+                    &mut |_| Ok(()),
+                )
+            }),
+    };
+    match result {
+        Ok(rows) => {
+            let body = out.into_inner().map_err(|e| e.into_error())?;
+            body.finish()?;
+            eprintln!(
+                "DEBUG: HTTP query #{} returned {} rows in {:?}",
+                id,
+                rows,
+                started.elapsed()
+            );
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!(
+                "ERROR: HTTP query #{} failed after {:?}: {}",
+                id,
+                started.elapsed(),
+                e
+            );
+            let (mut body, _) = out.into_parts();
+            if !body.started {
+                respond_error(&mut body.stream, &e)?;
+            }
+            // Otherwise the response ends without its last chunk, which clients report as
+            // an incomplete body
+            Ok(())
+        }
+    }
+}
+
+fn handle(service: &Service, mut stream: TcpStream) -> Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let request = match read_request(&mut stream) {
+        Ok(request) => request,
+        Err(e) => return Ok(respond_error(&mut stream, &e)?),
+    };
+    if !origin_allowed(service, &request) {
+        let error = http_error(403, "Requests from this origin are not allowed");
+        return Ok(respond_error(&mut stream, &error)?);
+    }
+    if !authorized(service, &request) {
+        let error = http_error(401, "A valid bearer token is required");
+        return Ok(respond_error(&mut stream, &error)?);
+    }
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/health") => Ok(respond(&mut stream, 200, "text/plain", b"ok")?),
+        ("POST", "/query") => run_query(service, stream, &request),
+        (_, "/health") | (_, "/query") => {
+            let error = http_error(405, format!("{} is not allowed here", request.method));
+            Ok(respond_error(&mut stream, &error)?)
+        }
+        _ => {
+            let error = http_error(404, format!("No such endpoint: {}", request.path));
+            Ok(respond_error(&mut stream, &error)?)
+        }
+    }
+}
+
+fn accept_loop(listener: TcpListener, service: Arc<Service>, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::SeqCst) {
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(ACCEPT_POLL);
+                continue;
+            }
+            Err(e) => {
+                eprintln!("WARN: HTTP accept failed: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = stream.set_nonblocking(false) {
+            eprintln!("WARN: HTTP connection setup failed: {}", e);
+            continue;
+        }
+        if service.active.fetch_add(1, Ordering::SeqCst) >= service.max_connections {
+            service.active.fetch_sub(1, Ordering::SeqCst);
+            let error = http_error(503, "Too many queries in flight; retry later");
+            let _ = respond_error(&mut stream, &error);
+            let _ = stream.shutdown(Shutdown::Both);
+            continue;
+        }
+        let service = Arc::clone(&service);
+        thread::spawn(move || {
+            if let Err(e) = handle(&service, stream) {
+                eprintln!("WARN: HTTP request failed: {}", e);
+            }
+            service.active.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    eprintln!("DEBUG: HTTP query service stopped");
+}
+
+// HTTP query service running on a background thread: POST SQL to /query and get the result
+// back as an Arrow IPC stream or a Parquet file; GET /health answers "ok"
+#[pyclass]
+pub struct QueryServer {
+    #[pyo3(get)]
+    host: String,
+    #[pyo3(get)]
+    port: u16,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl QueryServer {
+    #[allow(clippy::too_many_arguments)]
+    pub fn start(
+        dsn: &str,
+        user: &str,
+        password: &str,
+        config: QueryConfig,
+        host: &str,
+        port: u16,
+        token: Option<String>,
+        allowed_origins: Vec<String>,
+        max_connections: usize,
+    ) -> PyResult<Self> {
+        if max_connections == 0 {
+            return Err(PyValueError::new_err("max_connections must be at least 1"));
+        }
+        if token
+            .as_deref()
+            .is_some_and(|token| token.trim().is_empty())
+        {
+            return Err(PyValueError::new_err("token must not be empty"));
+        }
+        let listener = TcpListener::bind((host, port))?;
+        let address = listener.local_addr()?;
+        if token.is_none() && !address.ip().is_loopback() {
+            return Err(PyValueError::new_err(format!(
+                "Serving on {} needs a token; without one only 127.0.0.1 or ::1 is allowed",
+                host
+            )));
+        }
+        listener.set_nonblocking(true)?;
+        let port = address.port();
+        let service = Arc::new(Service {
+            dsn: dsn.to_string(),
+            user: user.to_string(),
+            password: password.to_string(),
+            config,
+            token,
+            allowed_origins,
+            max_connections,
+            active: AtomicUsize::new(0),
+            queries: AtomicUsize::new(0),
+        });
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || accept_loop(listener, service, stop))
+        };
+        eprintln!("DEBUG: HTTP query service listening on {}:{}", host, port);
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+#[pymethods]
+impl QueryServer {
+    #[getter]
+    fn url(&self) -> String {
+        format!("http://{}:{}", self.host, self.port)
+    }
+
+    // Stops accepting requests; queries already running finish on their own threads
+    fn shutdown(&mut self, py: Python<'_>) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            py.allow_threads(|| {
+                let _ = thread.join();
+            });
+        }
+    }
+
+    // Blocks until Ctrl+C, then shuts the service down
+    fn serve_forever(&mut self, py: Python<'_>) -> PyResult<()> {
+        while self.thread.is_some() {
+            if let Err(e) = py.check_signals() {
+                self.shutdown(py);
+                return Err(e);
+            }
+            py.allow_threads(|| thread::sleep(Duration::from_millis(200)));
+        }
+        Ok(())
+    }
+
+    fn __enter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        py: Python<'_>,
+        _exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> bool {
+        self.shutdown(py);
+        false
+    }
+
+    fn __repr__(&self) -> String {
+        let state = if self.thread.is_some() {
+            "running"
+        } else {
+            "stopped"
+        };
+        format!("QueryServer(url='{}', {})", self.url(), state)
+    }
+}
+
+impl Drop for QueryServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
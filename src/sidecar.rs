@@ -0,0 +1,210 @@
+use anyhow::{anyhow, Result};
+use arrow::array::{make_comparator, Array, ArrayRef};
+use arrow::compute::SortOptions;
+use arrow::datatypes::{DataType, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use serde_json::{json, Map, Value};
+use std::cmp::Ordering;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::objstore::{self, ObjectWriter};
+use crate::sink::BatchSink;
+use crate::{find_column, QueryConfig};
+
+// Metadata written next to a file export, <path>.meta.json, so orchestration tools can check a
+// load without opening the data: the source SQL, the Arrow schema, the rows of every file and,
+// with a stats column, that column's minimum and maximum
+pub struct Sidecar {
+    column: Option<String>,
+    schema: Option<SchemaRef>,
+    index: Option<usize>,
+    // Single-row arrays holding the smallest and largest non-null value seen
+    min: Option<ArrayRef>,
+    max: Option<ArrayRef>,
+}
+
+impl Sidecar {
+    // A sidecar for an export with `sidecar` set; `column` is the stats column
+    pub fn new(enabled: bool, column: Option<String>) -> Result<Option<Self>> {
+        if !enabled {
+            if column.is_some() {
+                return Err(anyhow!("stats_column needs sidecar=True"));
+            }
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            column,
+            schema: None,
+            index: None,
+            min: None,
+            max: None,
+        }))
+    }
+
+    fn begin(&mut self, schema: &SchemaRef) -> Result<()> {
+        if let Some(column) = &self.column {
+            self.index =
+                Some(find_column(schema, column).ok_or_else(|| {
+                    anyhow!("stats_column '{}' is not in the result set", column)
+                })?);
+        }
+        self.schema = Some(schema.clone());
+        Ok(())
+    }
+
+    fn observe(&mut self, batch: &RecordBatch) -> Result<()> {
+        let Some(index) = self.index else {
+            return Ok(());
+        };
+        let column = batch.column(index);
+        let compare = make_comparator(column, column, SortOptions::default())?;
+        let mut rows = (0..column.len()).filter(|&row| column.is_valid(row));
+        let Some(first) = rows.next() else {
+            return Ok(());
+        };
+        let (mut low, mut high) = (first, first);
+        for row in rows {
+            if compare(row, low) == Ordering::Less {
+                low = row;
+            }
+            if compare(row, high) == Ordering::Greater {
+                high = row;
+            }
+        }
+        self.min = Some(pick(self.min.take(), column.slice(low, 1), Ordering::Less)?);
+        self.max = Some(pick(
+            self.max.take(),
+            column.slice(high, 1),
+            Ordering::Greater,
+        )?);
+        Ok(())
+    }
+
+    // Writes the sidecar of the export to `destination`, which produced `files` with their rows
+    pub fn write(
+        &self,
+        destination: &Path,
+        format: &str,
+        sql: &str,
+        config: &QueryConfig,
+        files: &[(String, usize)],
+    ) -> Result<()> {
+        let schema = self
+            .schema
+            .as_ref()
+            .ok_or_else(|| anyhow!("ERROR: Sidecar has no schema"))?;
+        let fields: Vec<Value> = schema
+            .fields()
+            .iter()
+            .map(|field| {
+                let mut entry = json!({
+                    "name": field.name(),
+                    "type": field.data_type().to_string(),
+                    "nullable": field.is_nullable(),
+                });
+                if !field.metadata().is_empty() {
+                    entry["metadata"] = json!(field.metadata());
+                }
+                entry
+            })
+            .collect();
+        let mut sidecar = Map::new();
+        sidecar.insert("sql".into(), json!(sql));
+        sidecar.insert("format".into(), json!(format));
+        sidecar.insert(
+            "destination".into(),
+            json!(destination.display().to_string()),
+        );
+        sidecar.insert("created_at".into(), json!(chrono::Utc::now().to_rfc3339()));
+        sidecar.insert(
+            "rows".into(),
+            json!(files.iter().map(|(_, rows)| rows).sum::<usize>()),
+        );
+        sidecar.insert("schema".into(), Value::Array(fields));
+        sidecar.insert(
+            "files".into(),
+            files
+                .iter()
+                .map(|(path, rows)| json!({"path": path, "rows": rows}))
+                .collect(),
+        );
+        if let Some(index) = self.index {
+            sidecar.insert(
+                "stats".into(),
+                json!({
+                    "column": schema.field(index).name(),
+                    "min": self.min.as_ref().map(value).transpose()?,
+                    "max": self.max.as_ref().map(value).transpose()?,
+                }),
+            );
+        }
+        let text = serde_json::to_string_pretty(&Value::Object(sidecar))?;
+        let path = format!("{}.meta.json", destination.display());
+        if let Some(url) = objstore::url(Path::new(&path)) {
+            let mut upload = ObjectWriter::create(url, config)?;
+            upload.write_all(text.as_bytes())?;
+            return upload.complete();
+        }
+        fs::write(&path, text).map_err(|e| anyhow!("Failed to write sidecar {}: {}", path, e))?;
+        eprintln!("DEBUG: Wrote sidecar {}", path);
+        Ok(())
+    }
+}
+
+// The smaller (`wanted` Less) or larger (Greater) of two single-row arrays
+fn pick(current: Option<ArrayRef>, candidate: ArrayRef, wanted: Ordering) -> Result<ArrayRef> {
+    let Some(current) = current else {
+        return Ok(candidate);
+    };
+    let compare = make_comparator(&candidate, &current, SortOptions::default())?;
+    Ok(if compare(0, 0) == wanted {
+        candidate
+    } else {
+        current
+    })
+}
+
+// JSON value of a single-row array: numbers for integer and float columns, text otherwise
+fn value(array: &ArrayRef) -> Result<Value> {
+    let text = ArrayFormatter::try_new(array.as_ref(), &FormatOptions::default())?
+        .value(0)
+        .to_string();
+    let numeric = array.data_type().is_integer() || array.data_type().is_floating();
+    let number = numeric
+        .then(|| serde_json::from_str::<serde_json::Number>(&text).ok())
+        .flatten();
+    Ok(match (number, array.data_type()) {
+        (Some(number), _) => Value::Number(number),
+        (None, DataType::Boolean) => Value::Bool(text == "true"),
+        (None, _) => Value::String(text),
+    })
+}
+
+// Passes the batches on to `inner` while the sidecar records the schema and column stats
+pub struct Recorded<'a> {
+    pub inner: &'a mut dyn BatchSink,
+    pub sidecar: Option<&'a mut Sidecar>,
+}
+
+impl BatchSink for Recorded<'_> {
+    fn begin(&mut self, schema: &SchemaRef) -> Result<()> {
+        if let Some(sidecar) = self.sidecar.as_deref_mut() {
+            sidecar.begin(schema)?;
+        }
+        self.inner.begin(schema)
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        if let Some(sidecar) = self.sidecar.as_deref_mut() {
+            sidecar.observe(batch)?;
+        }
+        self.inner.write(batch)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.inner.finish()
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::Result;
+use ibarrow_core::snapshot::{current, transaction_sql};
+use odbc_api::Connection;
+use pyo3::prelude::*;
+use std::sync::{Arc, Mutex};
+
+use crate::txwatch::TransactionWatch;
+use crate::{build_connection_string, diagnostics, driverenv, map_query_error, retry, QueryConfig};
+
+// Returned by IbarrowConnection.snapshot(): a read-only snapshot transaction held open on its
+// own connection. Firebird only starts transactions AT SNAPSHOT NUMBER n while a transaction
+// owning snapshot n is active, so keep this open until every reader has started.
+#[pyclass]
+pub struct Snapshot {
+    #[pyo3(get)]
+    number: i64,
+    conn: Arc<Mutex<Option<Connection<'static>>>>,
+    watch: Mutex<Option<TransactionWatch>>,
+}
+
+impl Snapshot {
+    pub fn open(dsn: &str, user: &str, password: &str, config: &QueryConfig) -> Result<Self> {
+        let config = QueryConfig {
+            read_only: true,
+            isolation_level: Some("snapshot".to_string()),
+            ..config.clone()
+        };
+        diagnostics::clear();
+        let env = odbc_api::environment()?;
+        let conn_str = build_connection_string(dsn, user, password, &config);
+        let conn = retry::with_retry(&config, || driverenv::connect(env, &conn_str, &config))?;
+        conn.set_autocommit(false)?;
+        let number = current(&conn)?;
+        eprintln!("DEBUG: Holding snapshot {}", number);
+        let conn = Arc::new(Mutex::new(Some(conn)));
+        let held = Arc::clone(&conn);
+        let watch = TransactionWatch::start(&config, "Snapshot", move || {
+            if let Err(e) = rollback(&held) {
+                eprintln!("WARN: Failed to roll back the snapshot transaction: {}", e);
+            }
+        })?;
+        Ok(Self {
+            number,
+            conn,
+            watch: Mutex::new(watch),
+        })
+    }
+
+    pub fn number(&self) -> i64 {
+        self.number
+    }
+
+    pub fn release(&self) -> Result<()> {
+        self.watch.lock().unwrap_or_else(|e| e.into_inner()).take();
+        rollback(&self.conn)
+    }
+}
+
+// Ends the snapshot transaction and closes its connection
+fn rollback(conn: &Mutex<Option<Connection<'static>>>) -> Result<()> {
+    let conn = conn.lock().unwrap_or_else(|e| e.into_inner()).take();
+    if let Some(conn) = conn {
+        // Read-only transaction: nothing to keep
+        conn.rollback()?;
+    }
+    Ok(())
+}
+
+#[pymethods]
+impl Snapshot {
+    // Statement that starts a transaction at this snapshot, for other clients
+    #[getter]
+    fn transaction_sql(&self) -> String {
+        transaction_sql(self.number)
+    }
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.conn
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_none()
+    }
+
+    // Ends the transaction; transactions can no longer be started at this snapshot unless
+    // another transaction still holds it
+    fn close(&self, py: Python<'_>) -> PyResult<()> {
+        py.allow_threads(|| self.release()).map_err(map_query_error)
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &self,
+        py: Python<'_>,
+        _exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<bool> {
+        self.close(py)?;
+        Ok(false)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Snapshot(number={})", self.number)
+    }
+}
@@ -0,0 +1,193 @@
+use anyhow::Result;
+use pyo3::prelude::*;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{interop, write_arrow_ipc_impl, QueryConfig};
+
+static NEXT_FILE: AtomicU64 = AtomicU64::new(0);
+
+// Where a query result ended up
+pub enum Spilled {
+    Memory(Vec<u8>),
+    // Arrow IPC stream in a temp file, removed once read by map_table or map_polars
+    File(PathBuf),
+}
+
+// Arrow IPC sink that keeps the stream in memory until it grows past
+// QueryConfig.spill_threshold, then moves it to a temp file in QueryConfig.spill_dir.
+// The file is removed again if the query fails.
+pub struct Spool {
+    threshold: usize,
+    dir: PathBuf,
+    buf: Vec<u8>,
+    file: Option<(BufWriter<File>, PathBuf)>,
+}
+
+impl Spool {
+    pub fn new(config: &QueryConfig) -> Self {
+        Self {
+            threshold: config
+                .spill_threshold
+                .map_or(usize::MAX, |bytes| bytes as usize),
+            dir: config
+                .spill_dir
+                .as_ref()
+                .map_or_else(std::env::temp_dir, PathBuf::from),
+            buf: Vec::new(),
+            file: None,
+        }
+    }
+
+    fn spill(&mut self) -> std::io::Result<()> {
+        let path = self.dir.join(format!(
+            "ibarrow-spill-{}-{}.arrows",
+            std::process::id(),
+            NEXT_FILE.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| {
+                std::io::Error::new(
+                    e.kind(),
+                    format!("could not create spill file {}: {}", path.display(), e),
+                )
+            })?;
+        eprintln!(
+            "DEBUG: Result exceeded {} bytes, spilling to {}",
+            self.threshold,
+            path.display()
+        );
+        let mut file = BufWriter::new(file);
+        let written = file.write_all(&self.buf);
+        // Kept even if the write failed, so Drop removes the file
+        self.file = Some((file, path));
+        self.buf = Vec::new();
+        written
+    }
+
+    pub fn finish(mut self) -> Result<Spilled> {
+        match self.file.take() {
+            None => Ok(Spilled::Memory(std::mem::take(&mut self.buf))),
+            Some((mut file, path)) => {
+                if let Err(e) = file.flush() {
+                    let _ = fs::remove_file(&path);
+                    return Err(e.into());
+                }
+                Ok(Spilled::File(path))
+            }
+        }
+    }
+}
+
+impl Write for Spool {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        if self.file.is_none() && self.buf.len() + data.len() > self.threshold {
+            self.spill()?;
+        }
+        match self.file.as_mut() {
+            Some((file, _)) => file.write(data),
+            None => self.buf.write(data),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.file.as_mut() {
+            Some((file, _)) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        if let Some((_, path)) = self.file.take() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// Runs `sql` into a Spool, so results above the threshold never sit in memory as one buffer
+pub fn query(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+) -> Result<Spilled> {
+    let mut spool = Spool::new(config);
+    write_arrow_ipc_impl(dsn, user, password, sql, &[], config, &mut spool)?;
+    spool.finish()
+}
+
+// A spilled stream as a pyarrow.Table. Its buffers point into the mapped file, so only the
+// pages that are touched get loaded (see read).
+pub fn map_table(py: Python<'_>, path: &Path) -> PyResult<Py<PyAny>> {
+    read(py, path, |reader| reader.call_method0("read_all"))
+}
+
+// A spilled stream as a Polars DataFrame. Polars 1.3+ takes the batches from the reader one at
+// a time through the C stream, so no pyarrow.Table of the whole result is built first.
+pub fn map_polars(py: Python<'_>, path: &Path) -> PyResult<Py<PyAny>> {
+    let polars = py.import_bound("polars")?;
+    read(py, path, |reader| {
+        if interop::polars_reads_c_stream(py) && reader.hasattr("__arrow_c_stream__")? {
+            polars.getattr("DataFrame")?.call1((reader,))
+        } else {
+            let table = reader.call_method0("read_all")?;
+            polars.getattr("from_arrow")?.call1((table,))
+        }
+    })
+}
+
+// Hands a reader over the spilled stream to `consume`, then removes the file. The file is
+// memory-mapped, and an unlinked file stays readable for as long as the mapping holds it.
+// Windows cannot remove a mapped file, so there it is read through a plain file handle, which
+// is closed before the file is removed.
+fn read<'py>(
+    py: Python<'py>,
+    path: &Path,
+    consume: impl FnOnce(&Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>>,
+) -> PyResult<Py<PyAny>> {
+    let result = read_stream(py, path, consume);
+    if let Err(e) = fs::remove_file(path) {
+        eprintln!(
+            "WARN: could not remove spill file {}: {}",
+            path.display(),
+            e
+        );
+    }
+    result
+}
+
+fn read_stream<'py>(
+    py: Python<'py>,
+    path: &Path,
+    consume: impl FnOnce(&Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>>,
+) -> PyResult<Py<PyAny>> {
+    let pyarrow = py.import_bound("pyarrow")?;
+    let open = if cfg!(windows) {
+        "OSFile"
+    } else {
+        "memory_map"
+    };
+    let source = pyarrow
+        .getattr(open)?
+        .call1((path.to_string_lossy().as_ref(), "r"))?;
+    let reader = pyarrow
+        .getattr("ipc")?
+        .getattr("open_stream")?
+        .call1((&source,))?;
+    let result = consume(&reader).map_err(|e| {
+        eprintln!("ERROR: reading spill file failed: {}", e);
+        e
+    });
+    if cfg!(windows) {
+        source.call_method0("close")?;
+    }
+    Ok(result?.unbind())
+}
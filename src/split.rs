@@ -0,0 +1,220 @@
+use anyhow::{anyhow, Result};
+use arrow::array::{Array, AsArray, BooleanArray};
+use arrow::compute::{filter_record_batch, take};
+use arrow::datatypes::{DataType, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use arrow_ipc::writer::IpcWriteOptions;
+
+use crate::sink::{BatchSink, IpcSink};
+use crate::{compression, fetch_into, QueryConfig};
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+pub fn validate(fractions: &[f64]) -> Result<()> {
+    if fractions.len() < 2 {
+        return Err(anyhow!("split needs at least two fractions"));
+    }
+    if fractions.iter().any(|f| !f.is_finite() || *f <= 0.0) {
+        return Err(anyhow!("split fractions must be positive numbers"));
+    }
+    let total: f64 = fractions.iter().sum();
+    if (total - 1.0).abs() > 1e-9 {
+        return Err(anyhow!("split fractions must add up to 1, got {}", total));
+    }
+    Ok(())
+}
+
+// Seeded FNV-1a with a splitmix64 finalizer: stable across platforms and releases, unlike
+// std's hasher, so a seed always gives the same split. A row's values are folded into its
+// state with fnv() one column at a time, then mixed by finish().
+fn fnv(mut h: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        h ^= u64::from(*byte);
+        h = h.wrapping_mul(FNV_PRIME);
+    }
+    h
+}
+
+fn finish(mut h: u64) -> u64 {
+    h ^= h >> 30;
+    h = h.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h ^= h >> 27;
+    h = h.wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
+}
+
+// Variable-length bytes with their length first, so ("ab", "c") and ("a", "bc") differ
+fn fnv_sized(h: u64, bytes: &[u8]) -> u64 {
+    fnv(fnv(h, &(bytes.len() as u64).to_le_bytes()), bytes)
+}
+
+// Folds each row's value of `array` into its state. The encoding only depends on the values:
+// a null marker, then the little-endian bytes of fixed-width values, length-prefixed text and
+// binary, and the display text of anything else. Dictionary columns hash their categories and
+// every string and binary layout hashes alike, so neither the batching nor arrow's row format,
+// which may change between arrow releases, moves a row to another part.
+fn fold(states: &mut [u64], array: &dyn Array) -> Result<()> {
+    if let Some(dictionary) = array.as_any_dictionary_opt() {
+        let values = take(dictionary.values().as_ref(), dictionary.keys(), None)?;
+        return fold(states, values.as_ref());
+    }
+    let data_type = array.data_type();
+    let data = array.to_data();
+    let width = data_type.primitive_width();
+    let formatter = match data_type {
+        DataType::Boolean
+        | DataType::Utf8
+        | DataType::LargeUtf8
+        | DataType::Utf8View
+        | DataType::Binary
+        | DataType::LargeBinary
+        | DataType::BinaryView
+        | DataType::FixedSizeBinary(_) => None,
+        _ if width.is_some() => None,
+        _ => Some(
+            ArrayFormatter::try_new(array, &FormatOptions::default())
+                .map_err(|e| anyhow!("split cannot hash {} columns: {}", data_type, e))?,
+        ),
+    };
+    for (row, state) in states.iter_mut().enumerate() {
+        if array.is_null(row) {
+            *state = fnv(*state, &[0]);
+            continue;
+        }
+        let h = fnv(*state, &[1]);
+        *state = match data_type {
+            DataType::Boolean => fnv(h, &[array.as_boolean().value(row) as u8]),
+            DataType::Utf8 => fnv_sized(h, array.as_string::<i32>().value(row).as_bytes()),
+            DataType::LargeUtf8 => fnv_sized(h, array.as_string::<i64>().value(row).as_bytes()),
+            DataType::Utf8View => fnv_sized(h, array.as_string_view().value(row).as_bytes()),
+            DataType::Binary => fnv_sized(h, array.as_binary::<i32>().value(row)),
+            DataType::LargeBinary => fnv_sized(h, array.as_binary::<i64>().value(row)),
+            DataType::BinaryView => fnv_sized(h, array.as_binary_view().value(row)),
+            DataType::FixedSizeBinary(_) => fnv(h, array.as_fixed_size_binary().value(row)),
+            _ => match (&formatter, width) {
+                (Some(formatter), _) => fnv_sized(h, formatter.value(row).to_string().as_bytes()),
+                (None, Some(width)) => {
+                    let start = (data.offset() + row) * width;
+                    fnv(h, &data.buffers()[0].as_slice()[start..start + width])
+                }
+                (None, None) => h,
+            },
+        };
+    }
+    Ok(())
+}
+
+// Routes every row to one of several sinks by a hash of its values, so the same rows land in
+// the same part whatever the batch size or row order
+pub struct SplitSink<'a> {
+    // Upper bound of each part in [0, 1)
+    cuts: Vec<f64>,
+    seed: u64,
+    parts: Vec<IpcSink<'a, Vec<u8>>>,
+    wrote: Vec<bool>,
+    schema: Option<SchemaRef>,
+}
+
+impl<'a> SplitSink<'a> {
+    pub fn new(
+        fractions: &[f64],
+        seed: u64,
+        outputs: &'a mut [Vec<u8>],
+        options: &IpcWriteOptions,
+    ) -> Self {
+        let cuts = fractions
+            .iter()
+            .scan(0.0, |total, fraction| {
+                *total += fraction;
+                Some(*total)
+            })
+            .collect();
+        Self {
+            cuts,
+            seed,
+            parts: outputs
+                .iter_mut()
+                .map(|out| IpcSink::new(out, options.clone()))
+                .collect(),
+            wrote: vec![false; fractions.len()],
+            schema: None,
+        }
+    }
+
+    fn part_of(&self, state: u64) -> usize {
+        // Top 53 bits as a uniform number in [0, 1)
+        let unit = (finish(state) >> 11) as f64 / (1u64 << 53) as f64;
+        self.cuts
+            .iter()
+            .position(|cut| unit < *cut)
+            .unwrap_or(self.cuts.len() - 1)
+    }
+}
+
+impl BatchSink for SplitSink<'_> {
+    fn begin(&mut self, schema: &SchemaRef) -> Result<()> {
+        self.schema = Some(schema.clone());
+        for part in &mut self.parts {
+            part.begin(schema)?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        let mut states = vec![FNV_OFFSET ^ self.seed; batch.num_rows()];
+        for column in batch.columns() {
+            fold(&mut states, column.as_ref())?;
+        }
+        let assigned: Vec<usize> = states.iter().map(|state| self.part_of(*state)).collect();
+        for (index, part) in self.parts.iter_mut().enumerate() {
+            let mask: BooleanArray = assigned.iter().map(|p| Some(*p == index)).collect();
+            let rows = filter_record_batch(batch, &mask)?;
+            if rows.num_rows() > 0 {
+                part.write(&rows)?;
+                self.wrote[index] = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for (part, wrote) in self.parts.iter_mut().zip(&self.wrote) {
+            // Readers expect at least one batch, as in the unsplit stream
+            if let (false, Some(schema)) = (wrote, &self.schema) {
+                part.write(&RecordBatch::new_empty(schema.clone()))?;
+            }
+            part.finish()?;
+        }
+        Ok(())
+    }
+}
+
+// Runs `sql` once and returns one Arrow IPC stream per fraction
+pub fn query(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    config: &QueryConfig,
+    fractions: &[f64],
+    seed: u64,
+) -> Result<Vec<Vec<u8>>> {
+    let mut outputs = vec![Vec::new(); fractions.len()];
+    let options = compression::write_options(config)?;
+    {
+        let mut sink = SplitSink::new(fractions, seed, &mut outputs, &options);
+        fetch_into(
+            dsn,
+            user,
+            password,
+            sql,
+            &[],
+            config,
+            &mut sink,
+            &mut |_| Ok(()),
+        )?;
+    }
+    Ok(outputs)
+}
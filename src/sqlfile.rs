@@ -0,0 +1,53 @@
+use anyhow::{anyhow, Result};
+use encoding_rs::Encoding;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ibarrow_core::lexer::strip_comments;
+
+// Reads a .sql file, expands `@include <file>` lines and strips comments
+pub fn load_sql_file(path: &Path, encoding: &str) -> Result<String> {
+    let encoding = Encoding::for_label(encoding.as_bytes())
+        .ok_or_else(|| anyhow!("Unknown encoding '{}'", encoding))?;
+    let mut stack = Vec::new();
+    let text = expand_includes(path, encoding, &mut stack)?;
+    Ok(strip_comments(&text).trim().to_string())
+}
+
+fn expand_includes(
+    path: &Path,
+    encoding: &'static Encoding,
+    stack: &mut Vec<PathBuf>,
+) -> Result<String> {
+    let canonical = fs::canonicalize(path)
+        .map_err(|e| anyhow!("Failed to open SQL file {}: {}", path.display(), e))?;
+    if stack.contains(&canonical) {
+        return Err(anyhow!("Circular @include of {}", path.display()));
+    }
+    let raw = fs::read(&canonical)
+        .map_err(|e| anyhow!("Failed to read SQL file {}: {}", path.display(), e))?;
+    let (text, _, had_errors) = encoding.decode(&raw);
+    if had_errors {
+        return Err(anyhow!(
+            "SQL file {} is not valid {}",
+            path.display(),
+            encoding.name()
+        ));
+    }
+
+    stack.push(canonical.clone());
+    let base = canonical.parent().unwrap_or_else(|| Path::new("."));
+    let mut out = String::with_capacity(text.len());
+    for line in text.lines() {
+        match line.trim().strip_prefix("@include") {
+            Some(target) if target.starts_with(char::is_whitespace) => {
+                let target = target.trim().trim_matches(|c| c == '"' || c == '\'');
+                out.push_str(&expand_includes(&base.join(target), encoding, stack)?);
+            }
+            _ => out.push_str(line),
+        }
+        out.push('\n');
+    }
+    stack.pop();
+    Ok(out)
+}
@@ -0,0 +1,317 @@
+use anyhow::{anyhow, Result};
+use arrow::datatypes::{Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::ffi_stream::FFI_ArrowArrayStream;
+use arrow::record_batch::{RecordBatch, RecordBatchIterator, RecordBatchReader};
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::PyCapsule;
+use std::ffi::CString;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::progress::Progress;
+use crate::watchdog::Watchdog;
+use crate::{backend, rows_left, trim_batch, BatchReader, QueryConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum End {
+    Open,
+    Exhausted,
+    Closed,
+    TimedOut,
+}
+
+// The live cursor, shared by the Python handle, the exported C stream and the idle reaper
+struct Live {
+    reader: Option<BatchReader>,
+    // Keeps query_timeout enforced for as long as the cursor is read
+    watchdog: Option<Watchdog>,
+    progress: Progress,
+    // Rows QueryConfig.max_rows still allows
+    left: Option<usize>,
+    rows: usize,
+    last_used: Instant,
+    idle_timeout: Option<Duration>,
+    end: End,
+}
+
+impl Live {
+    // Frees the cursor and its connection
+    fn release(&mut self, end: End) {
+        if self.end == End::Open {
+            self.end = end;
+        }
+        self.reader = None;
+        self.watchdog = None;
+    }
+
+    fn next_batch(&mut self) -> Option<Result<RecordBatch>> {
+        match self.end {
+            End::Open => {}
+            End::Exhausted => return None,
+            End::Closed => return Some(Err(anyhow!("Arrow stream was closed"))),
+            End::TimedOut => {
+                let idle = self.idle_timeout.unwrap_or_default().as_secs_f64();
+                return Some(Err(anyhow!(
+                    "Arrow stream was idle for over {} seconds; its connection was released",
+                    idle
+                )));
+            }
+        }
+        let left = self.left;
+        let next = match (left, self.reader.as_mut()) {
+            (Some(0), _) | (_, None) => None,
+            (_, Some(reader)) => reader.next(),
+        };
+        self.last_used = Instant::now();
+        let result = next.map(|batch| -> Result<RecordBatch> {
+            if let Some(watchdog) = &self.watchdog {
+                watchdog.check()?;
+            }
+            let batch = trim_batch(batch?, left);
+            self.rows += batch.num_rows();
+            self.left = left.map(|left| left - batch.num_rows());
+            self.progress.batch(&batch)?;
+            Ok(batch)
+        });
+        match result {
+            Some(Ok(batch)) => Some(Ok(batch)),
+            Some(Err(e)) => {
+                self.release(End::Exhausted);
+                Some(Err(e))
+            }
+            None => {
+                eprintln!("DEBUG: Arrow stream finished ({} rows)", self.rows);
+                self.release(End::Exhausted);
+                None
+            }
+        }
+    }
+}
+
+type Shared = Arc<(Mutex<Live>, Condvar)>;
+
+fn lock(shared: &Shared) -> MutexGuard<'_, Live> {
+    shared.0.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn release(shared: &Shared, end: End) {
+    lock(shared).release(end);
+    shared.1.notify_all();
+}
+
+// Releases the connection once nothing has been read for `idle_timeout`
+fn reap(shared: Shared, idle_timeout: Duration) {
+    let mut live = lock(&shared);
+    while live.end == End::Open {
+        let deadline = live.last_used + idle_timeout;
+        let now = Instant::now();
+        if now >= deadline {
+            eprintln!("WARN: Arrow stream idle for too long, releasing its connection");
+            live.release(End::TimedOut);
+            break;
+        }
+        live = shared
+            .1
+            .wait_timeout(live, deadline - now)
+            .unwrap_or_else(|e| e.into_inner())
+            .0;
+    }
+}
+
+// The C stream handed to the consumer. Releasing it releases the cursor.
+struct LiveReader {
+    shared: Shared,
+    schema: SchemaRef,
+}
+
+impl Iterator for LiveReader {
+    type Item = std::result::Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        lock(&self.shared)
+            .next_batch()
+            .map(|batch| batch.map_err(|e| ArrowError::ExternalError(e.into())))
+    }
+}
+
+impl RecordBatchReader for LiveReader {
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+}
+
+impl Drop for LiveReader {
+    fn drop(&mut self) {
+        release(&self.shared, End::Closed);
+    }
+}
+
+// Returned by IbarrowConnection.arrow_stream(): an Arrow PyCapsule stream over a live cursor.
+// The cursor and its connection stay open until the stream is fully read, the consumer
+// releases it, close() is called or it sits idle for longer than `idle_timeout`.
+// A materialized stream fetches everything up front instead and holds no connection.
+#[pyclass]
+pub struct ArrowStream {
+    shared: Shared,
+    schema: SchemaRef,
+    exported: Mutex<bool>,
+    materialized: bool,
+    batches: Mutex<Option<Vec<RecordBatch>>>,
+}
+
+impl ArrowStream {
+    pub fn open(
+        dsn: &str,
+        user: &str,
+        password: &str,
+        sql: &str,
+        config: &QueryConfig,
+        idle_timeout: Option<Duration>,
+        materialize: bool,
+    ) -> Result<Self> {
+        let watchdog = Watchdog::start(config);
+        let opened = backend::open(dsn, user, password, config)
+            .and_then(|backend| backend.batches(sql, &[], config, &watchdog))
+            .map(|reader| {
+                // Nothing to stream: an empty stream with an empty schema
+                reader.unwrap_or_else(|| {
+                    Box::new(RecordBatchIterator::new(
+                        Vec::new(),
+                        Arc::new(Schema::empty()),
+                    ))
+                })
+            });
+        let reader = match opened {
+            Ok(reader) => reader,
+            Err(e) => return watchdog.finish(Err(e)),
+        };
+        let schema = reader.schema();
+
+        let shared: Shared = Arc::new((
+            Mutex::new(Live {
+                reader: Some(reader),
+                watchdog: Some(watchdog),
+                progress: Progress::new(config),
+                left: rows_left(config, 0),
+                rows: 0,
+                last_used: Instant::now(),
+                idle_timeout,
+                end: End::Open,
+            }),
+            Condvar::new(),
+        ));
+        let mut batches = None;
+        if materialize {
+            // Reading to the end releases the connection
+            let mut live = lock(&shared);
+            let mut fetched = Vec::new();
+            while let Some(batch) = live.next_batch() {
+                fetched.push(batch?);
+            }
+            eprintln!("DEBUG: Materialized {} batches", fetched.len());
+            batches = Some(fetched);
+        } else if let Some(idle_timeout) = idle_timeout {
+            let shared = Arc::clone(&shared);
+            thread::spawn(move || reap(shared, idle_timeout));
+        }
+        Ok(Self {
+            shared,
+            schema,
+            exported: Mutex::new(false),
+            materialized: materialize,
+            batches: Mutex::new(batches),
+        })
+    }
+}
+
+#[pymethods]
+impl ArrowStream {
+    // Arrow PyCapsule stream interface. A live cursor can be read once, so only the first call
+    // succeeds; materialized batches can be exported any number of times. `requested_schema`
+    // is a hint and is ignored.
+    #[pyo3(signature = (requested_schema=None))]
+    fn __arrow_c_stream__(
+        &self,
+        py: Python<'_>,
+        requested_schema: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Py<PyCapsule>> {
+        let _ = requested_schema;
+        let name = CString::new("arrow_array_stream")?;
+        if self.materialized {
+            let batches = self
+                .batches
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone()
+                .ok_or_else(|| PyRuntimeError::new_err("Arrow stream was closed"))?;
+            let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), self.schema.clone());
+            let stream = FFI_ArrowArrayStream::new(Box::new(reader));
+            return Ok(PyCapsule::new_bound(py, stream, Some(name))?.unbind());
+        }
+        {
+            let mut exported = self.exported.lock().unwrap_or_else(|e| e.into_inner());
+            if *exported {
+                return Err(PyRuntimeError::new_err(
+                    "Arrow stream was already exported; run the query again to read it twice",
+                ));
+            }
+            *exported = true;
+        }
+        let reader = LiveReader {
+            shared: Arc::clone(&self.shared),
+            schema: self.schema.clone(),
+        };
+        let stream = FFI_ArrowArrayStream::new(Box::new(reader));
+        Ok(PyCapsule::new_bound(py, stream, Some(name))?.unbind())
+    }
+
+    // True once the cursor has been released. The GIL is released while waiting for a
+    // batch being fetched, whose progress hook may need it.
+    #[getter]
+    fn closed(&self, py: Python<'_>) -> bool {
+        py.allow_threads(|| lock(&self.shared).reader.is_none())
+    }
+
+    // True when every batch was fetched up front and no connection is held
+    #[getter]
+    fn materialized(&self) -> bool {
+        self.materialized
+    }
+
+    // Releases the cursor and its connection, or the materialized batches; later reads of the
+    // stream fail
+    fn close(&self, py: Python<'_>) {
+        self.batches
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        py.allow_threads(|| release(&self.shared, End::Closed));
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &self,
+        py: Python<'_>,
+        _exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> bool {
+        self.close(py);
+        false
+    }
+}
+
+impl Drop for ArrowStream {
+    fn drop(&mut self) {
+        // An exported stream belongs to its consumer from then on
+        if !*self.exported.lock().unwrap_or_else(|e| e.into_inner()) {
+            Python::with_gil(|py| py.allow_threads(|| release(&self.shared, End::Closed)));
+        }
+    }
+}
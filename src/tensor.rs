@@ -0,0 +1,233 @@
+use anyhow::{anyhow, Result};
+use arrow::array::{Array, ArrayRef, AsArray};
+use arrow::compute::{cast_with_options, CastOptions};
+use arrow::datatypes::{
+    ArrowPrimitiveType, DataType, Float32Type, Float64Type, Int32Type, Int64Type, Schema,
+    ToByteSlice,
+};
+use arrow::record_batch::RecordBatch;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::find_column;
+
+// Element type of the tensors built by IbarrowResult.to_torch() and to_tf_dataset()
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TensorType {
+    Float32,
+    Float64,
+    Int32,
+    Int64,
+}
+
+impl TensorType {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "float32" => Ok(TensorType::Float32),
+            "float64" => Ok(TensorType::Float64),
+            "int32" => Ok(TensorType::Int32),
+            "int64" => Ok(TensorType::Int64),
+            other => Err(anyhow!(
+                "Unknown dtype '{}': expected 'float32', 'float64', 'int32' or 'int64'",
+                other
+            )),
+        }
+    }
+
+    // Name shared by torch and NumPy
+    pub fn name(self) -> &'static str {
+        match self {
+            TensorType::Float32 => "float32",
+            TensorType::Float64 => "float64",
+            TensorType::Int32 => "int32",
+            TensorType::Int64 => "int64",
+        }
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            TensorType::Float32 | TensorType::Int32 => 4,
+            TensorType::Float64 | TensorType::Int64 => 8,
+        }
+    }
+}
+
+fn is_numeric(data_type: &DataType) -> bool {
+    data_type.is_numeric() || *data_type == DataType::Boolean
+}
+
+// Indexes of the feature columns: the named ones, or every numeric and boolean column other
+// than the label
+pub fn feature_columns(
+    schema: &Schema,
+    columns: Option<&[String]>,
+    label: Option<usize>,
+) -> Result<Vec<usize>> {
+    let Some(columns) = columns else {
+        return Ok(schema
+            .fields()
+            .iter()
+            .enumerate()
+            .filter(|(index, field)| Some(*index) != label && is_numeric(field.data_type()))
+            .map(|(index, _)| index)
+            .collect());
+    };
+    columns.iter().map(|name| column(schema, name)).collect()
+}
+
+// Index of a numeric column
+pub fn column(schema: &Schema, name: &str) -> Result<usize> {
+    let index = find_column(schema, name)
+        .ok_or_else(|| anyhow!("Column '{}' is not in the result", name))?;
+    let data_type = schema.field(index).data_type();
+    if !is_numeric(data_type) {
+        return Err(anyhow!(
+            "Column '{}' is {}, not a numeric column",
+            name,
+            data_type
+        ));
+    }
+    Ok(index)
+}
+
+fn write_column<T: ArrowPrimitiveType>(
+    array: &ArrayRef,
+    name: &str,
+    out: &mut [u8],
+    first_row: usize,
+    column: usize,
+    width: usize,
+    null: Option<T::Native>,
+) -> Result<()> {
+    // A safe cast turns values that do not fit into NULLs, which would pass as missing values
+    let options = CastOptions {
+        safe: false,
+        ..Default::default()
+    };
+    let array = cast_with_options(array, &T::DATA_TYPE, &options).map_err(|e| {
+        anyhow!(
+            "Column '{}' has values that do not fit in {}: {}",
+            name,
+            T::DATA_TYPE,
+            e
+        )
+    })?;
+    if array.null_count() > 0 && null.is_none() {
+        return Err(anyhow!(
+            "Column '{}' has NULLs, which an integer tensor cannot hold; use a float dtype",
+            name
+        ));
+    }
+    let array = array.as_primitive::<T>();
+    let size = std::mem::size_of::<T::Native>();
+    for row in 0..array.len() {
+        let value = match null {
+            Some(null) if array.is_null(row) => null,
+            _ => array.value(row),
+        };
+        let at = ((first_row + row) * width + column) * size;
+        out[at..at + size].copy_from_slice(value.to_byte_slice());
+    }
+    Ok(())
+}
+
+// Writes `columns` of the batches into `out` as a row-major (rows, columns) matrix of `dtype`,
+// casting each column. NULLs become NaN in float tensors and are an error in integer ones.
+pub fn fill(
+    batches: &[RecordBatch],
+    columns: &[usize],
+    dtype: TensorType,
+    out: &mut [u8],
+) -> Result<()> {
+    let rows: usize = batches.iter().map(RecordBatch::num_rows).sum();
+    if out.len() != rows * columns.len() * dtype.size() {
+        return Err(anyhow!(
+            "ERROR: Tensor buffer holds {} bytes, expected {}",
+            out.len(),
+            rows * columns.len() * dtype.size()
+        ));
+    }
+    let width = columns.len();
+    let mut first_row = 0;
+    for batch in batches {
+        for (position, &index) in columns.iter().enumerate() {
+            let array = batch.column(index);
+            let name = batch.schema_ref().field(index).name().clone();
+            match dtype {
+                TensorType::Float32 => write_column::<Float32Type>(
+                    array,
+                    &name,
+                    out,
+                    first_row,
+                    position,
+                    width,
+                    Some(f32::NAN),
+                ),
+                TensorType::Float64 => write_column::<Float64Type>(
+                    array,
+                    &name,
+                    out,
+                    first_row,
+                    position,
+                    width,
+                    Some(f64::NAN),
+                ),
+                TensorType::Int32 => {
+                    write_column::<Int32Type>(array, &name, out, first_row, position, width, None)
+                }
+                TensorType::Int64 => {
+                    write_column::<Int64Type>(array, &name, out, first_row, position, width, None)
+                }
+            }?;
+        }
+        first_row += batch.num_rows();
+    }
+    Ok(())
+}
+
+// Where the tensors are allocated: torch tensors, or NumPy arrays for TensorFlow
+#[derive(Debug, Clone, Copy)]
+pub enum Library {
+    Torch,
+    NumPy,
+}
+
+// Allocates an uninitialized CPU tensor of `shape` in `module` (torch or numpy) and fills it
+// with `columns` of the batches from Rust, without an intermediate copy
+pub fn build(
+    py: Python<'_>,
+    module: &Bound<'_, PyModule>,
+    library: Library,
+    batches: &[RecordBatch],
+    columns: &[usize],
+    dtype: TensorType,
+    shape: Vec<usize>,
+) -> PyResult<Py<PyAny>> {
+    let kwargs = PyDict::new_bound(py);
+    let (tensor, address) = match library {
+        Library::Torch => {
+            kwargs.set_item("dtype", module.getattr(dtype.name())?)?;
+            let tensor = module.call_method("empty", (shape,), Some(&kwargs))?;
+            let address: usize = tensor.call_method0("data_ptr")?.extract()?;
+            (tensor, address)
+        }
+        Library::NumPy => {
+            kwargs.set_item("dtype", dtype.name())?;
+            let array = module.call_method("empty", (shape,), Some(&kwargs))?;
+            let address: usize = array.getattr("ctypes")?.getattr("data")?.extract()?;
+            (array, address)
+        }
+    };
+    let rows: usize = batches.iter().map(RecordBatch::num_rows).sum();
+    let len = rows * columns.len() * dtype.size();
+    if len > 0 {
+        py.allow_threads(|| {
+            // The freshly allocated, contiguous buffer of `tensor`, which is kept alive here
+            let out = unsafe { std::slice::from_raw_parts_mut(address as *mut u8, len) };
+            fill(batches, columns, dtype, out)
+        })
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    }
+    Ok(tensor.unbind())
+}
@@ -0,0 +1,182 @@
+use pyo3::exceptions::{PyRuntimeError, PyTimeoutError};
+use pyo3::prelude::*;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{query_arrow_ipc_impl, IbarrowConnection, QueryConfig};
+
+const DATABASE_PATH: &str = "/var/lib/firebird/data/scratch.fdb";
+
+// Runs a docker CLI command and returns its trimmed stdout
+fn docker(args: &[&str]) -> PyResult<String> {
+    let output = Command::new("docker").args(args).output().map_err(|e| {
+        PyRuntimeError::new_err(format!(
+            "ibarrow.testing needs the docker CLI on PATH: {}",
+            e
+        ))
+    })?;
+    if !output.status.success() {
+        return Err(PyRuntimeError::new_err(format!(
+            "docker {} failed: {}",
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// A disposable Firebird server in a Docker container with a scratch database.
+// The container is removed by stop(), on leaving a `with` block, or when the object is dropped.
+#[pyclass]
+pub struct TestServer {
+    container_id: Option<String>,
+    #[pyo3(get)]
+    dsn: String,
+    #[pyo3(get)]
+    user: String,
+    #[pyo3(get)]
+    password: String,
+    #[pyo3(get)]
+    connection: Py<IbarrowConnection>,
+}
+
+#[pymethods]
+impl TestServer {
+    fn stop(&mut self) -> PyResult<()> {
+        if let Some(id) = self.container_id.take() {
+            docker(&["rm", "-f", &id])?;
+        }
+        Ok(())
+    }
+
+    fn __enter__(&self, py: Python<'_>) -> Py<IbarrowConnection> {
+        self.connection.clone_ref(py)
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<bool> {
+        self.stop()?;
+        Ok(false)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "TestServer(container={}, dsn='{}')",
+            self.container_id.as_deref().unwrap_or("stopped"),
+            self.dsn
+        )
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(id) = self.container_id.take() {
+            let _ = Command::new("docker").args(["rm", "-f", &id]).output();
+        }
+    }
+}
+
+// Starts firebirdsql/firebird:<version>, creates a scratch database and waits until it
+// accepts ODBC connections. Requires Docker and the Firebird ODBC driver on the host.
+#[pyfunction]
+#[pyo3(signature = (
+    version="4.0",
+    driver="Firebird/InterBase(r) driver",
+    password="masterkey",
+    startup_timeout=120,
+))]
+fn start_server(
+    py: Python<'_>,
+    version: &str,
+    driver: &str,
+    password: &str,
+    startup_timeout: u64,
+) -> PyResult<TestServer> {
+    let image = format!("firebirdsql/firebird:{}", version);
+    let root_password = format!("FIREBIRD_ROOT_PASSWORD={}", password);
+    let id = docker(&[
+        "run",
+        "-d",
+        "--rm",
+        "-p",
+        "127.0.0.1::3050",
+        "-e",
+        &root_password,
+        "-e",
+        "FIREBIRD_DATABASE=scratch.fdb",
+        &image,
+    ])?;
+
+    // From here on the container is cleaned up if startup fails
+    let mut server = TestServer {
+        container_id: Some(id.clone()),
+        dsn: String::new(),
+        user: "SYSDBA".to_string(),
+        password: password.to_string(),
+        connection: Py::new(py, IbarrowConnection::new("", "", "", None))?,
+    };
+    let port = docker(&["port", &id, "3050/tcp"])?;
+    let port = port
+        .lines()
+        .next()
+        .and_then(|line| line.rsplit(':').next())
+        .ok_or_else(|| PyRuntimeError::new_err(format!("No port published for {}", id)))?;
+    server.dsn = format!(
+        "DRIVER={{{}}};DBNAME=127.0.0.1/{}:{}",
+        driver, port, DATABASE_PATH
+    );
+
+    // The server needs a few seconds to create the database
+    let deadline = Instant::now() + Duration::from_secs(startup_timeout);
+    let config = QueryConfig::default();
+    loop {
+        let ready = py.allow_threads(|| {
+            query_arrow_ipc_impl(
+                &server.dsn,
+                &server.user,
+                &server.password,
+                "SELECT 1 FROM RDB$DATABASE",
+                &config,
+            )
+        });
+        match ready {
+            Ok(_) => break,
+            Err(e) if Instant::now() >= deadline => {
+                server.stop()?;
+                return Err(PyTimeoutError::new_err(format!(
+                    "Firebird {} did not accept connections within {}s: {}",
+                    version, startup_timeout, e
+                )));
+            }
+            Err(_) => {
+                py.check_signals()?;
+                py.allow_threads(|| thread::sleep(Duration::from_millis(500)));
+            }
+        }
+    }
+
+    server.connection = Py::new(
+        py,
+        IbarrowConnection::new(&server.dsn, &server.user, &server.password, None),
+    )?;
+    Ok(server)
+}
+
+// Exposes the helpers as the `ibarrow.testing` submodule
+pub fn register(py: Python<'_>, parent: &Bound<'_, PyModule>) -> PyResult<()> {
+    let testing = PyModule::new_bound(py, "testing")?;
+    testing.add_class::<TestServer>()?;
+    testing.add_function(wrap_pyfunction!(start_server, &testing)?)?;
+    ibarrow_core::faults::register(&testing)?;
+    parent.add_submodule(&testing)?;
+    // Make `import ibarrow.testing` work for an extension submodule
+    py.import_bound("sys")?
+        .getattr("modules")?
+        .set_item("ibarrow.testing", &testing)?;
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use anyhow::{anyhow, Result};
+use arrow::record_batch::RecordBatch;
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::find_column;
+
+// Counters kept per requested value; more counters tighten the error bound
+const COUNTERS_PER_VALUE: usize = 10;
+const MIN_COUNTERS: usize = 64;
+
+// A value as displayed by Arrow, or None for NULL
+type Value = Option<String>;
+
+// Space-saving sketch (Metwally et al.): a fixed number of counters approximating the most
+// frequent values of a stream. Every value seen more than rows / capacity times is present, and
+// each count overestimates the true frequency by at most its recorded error.
+struct SpaceSaving {
+    capacity: usize,
+    counters: HashMap<Value, (u64, u64)>,
+    // (count, value) of every counter, so the smallest one is found without a scan
+    order: BTreeSet<(u64, Value)>,
+}
+
+impl SpaceSaving {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            counters: HashMap::with_capacity(capacity),
+            order: BTreeSet::new(),
+        }
+    }
+
+    fn insert(&mut self, value: Value) {
+        if let Some((count, _)) = self.counters.get_mut(&value) {
+            self.order.remove(&(*count, value.clone()));
+            *count += 1;
+            self.order.insert((*count, value));
+        } else if self.counters.len() < self.capacity {
+            self.counters.insert(value.clone(), (1, 0));
+            self.order.insert((1, value));
+        } else if let Some((min, evicted)) = self.order.pop_first() {
+            // The new value takes over the smallest counter and inherits its count as error
+            self.counters.remove(&evicted);
+            self.counters.insert(value.clone(), (min + 1, min));
+            self.order.insert((min + 1, value));
+        }
+    }
+
+    // The `k` largest counters as (value, count, error), most frequent first
+    fn top(&self, k: usize) -> Vec<(Value, u64, u64)> {
+        self.order
+            .iter()
+            .rev()
+            .take(k)
+            .map(|(count, value)| (value.clone(), *count, self.counters[value].1))
+            .collect()
+    }
+}
+
+struct Column {
+    name: String,
+    k: usize,
+    index: Option<usize>,
+    sketch: SpaceSaving,
+}
+
+// Approximate top-K value frequencies of selected result columns, updated batch by batch
+// while the result is fetched
+pub struct TopK {
+    columns: Vec<Column>,
+}
+
+pub type Summary = Vec<(String, Vec<(Value, u64, u64)>)>;
+
+impl TopK {
+    pub fn new(columns: &[(String, usize)]) -> Self {
+        Self {
+            columns: columns
+                .iter()
+                .map(|(name, k)| Column {
+                    name: name.clone(),
+                    k: *k,
+                    index: None,
+                    sketch: SpaceSaving::new((k * COUNTERS_PER_VALUE).max(MIN_COUNTERS)),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn observe(&mut self, batch: &RecordBatch) -> Result<()> {
+        let schema = batch.schema();
+        let options = FormatOptions::default();
+        for column in &mut self.columns {
+            let index = match column.index {
+                Some(index) => index,
+                None => {
+                    let index = find_column(&schema, &column.name).ok_or_else(|| {
+                        anyhow!("top_k column '{}' is not in the result set", column.name)
+                    })?;
+                    column.index = Some(index);
+                    index
+                }
+            };
+            let array = batch.column(index);
+            let formatter = ArrayFormatter::try_new(array.as_ref(), &options)?;
+            for row in 0..array.len() {
+                let value = if array.is_null(row) {
+                    None
+                } else {
+                    Some(formatter.value(row).to_string())
+                };
+                column.sketch.insert(value);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn summary(&self) -> Summary {
+        self.columns
+            .iter()
+            .map(|column| (column.name.clone(), column.sketch.top(column.k)))
+            .collect()
+    }
+}
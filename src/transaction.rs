@@ -0,0 +1,238 @@
+use anyhow::{anyhow, Result};
+use odbc_api::Connection;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use std::sync::{Arc, Mutex};
+
+use crate::dbapi::execute_on;
+use crate::params::{self, QueryParams, SqlParam};
+use crate::sink::{BatchSink, IpcSink};
+use crate::txwatch::TransactionWatch;
+use crate::{
+    access, build_connection_string, compression, diagnostics, driverenv, map_query_error, result,
+    retry, write, QueryConfig,
+};
+
+// SQL text with its parameters bound to `?` markers
+pub type Statement = (String, Vec<SqlParam>);
+
+// Reads run_in_transaction's statements: each is SQL text or a (sql, params) pair
+pub fn statements(items: &Bound<'_, PyAny>) -> PyResult<Vec<Statement>> {
+    let mut statements = Vec::new();
+    for item in items.iter()? {
+        let item = item?;
+        let (sql, params) = match item.extract::<String>() {
+            Ok(sql) => (sql, None),
+            Err(_) => item
+                .extract::<(String, Option<QueryParams>)>()
+                .map_err(|_| {
+                    PyTypeError::new_err("Statements must be SQL strings or (sql, params) pairs")
+                })?,
+        };
+        statements
+            .push(params::bind(&sql, params).map_err(|e| PyValueError::new_err(e.to_string()))?);
+    }
+    Ok(statements)
+}
+
+// Connects with autocommit off, so everything until commit or rollback is one transaction
+pub(crate) fn open(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    config: &QueryConfig,
+) -> Result<Connection<'static>> {
+    diagnostics::clear();
+    let env = odbc_api::environment()?;
+    let conn_str = build_connection_string(dsn, user, password, config);
+    let conn = retry::with_retry(config, || driverenv::connect(env, &conn_str, config))?;
+    conn.set_autocommit(false)?;
+    Ok(conn)
+}
+
+// Runs the statements in order in one transaction, committed after the last one and rolled
+// back if any fails. Returns the rows each affected, where the driver reports it.
+pub fn run_statements(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    statements: &[Statement],
+    config: &QueryConfig,
+) -> Result<Vec<Option<usize>>> {
+    for (sql, _) in statements {
+        access::check(sql, config)?;
+    }
+    let conn = open(dsn, user, password, config)?;
+    let outcome = statements
+        .iter()
+        .enumerate()
+        .map(|(i, (sql, params))| {
+            execute_on(&conn, sql, std::slice::from_ref(params), config)
+                .map(|executed| executed.rowcount)
+                .map_err(|e| e.context(format!("Statement {} of the transaction failed", i + 1)))
+        })
+        .collect::<Result<Vec<_>>>()
+        .and_then(|counts| {
+            conn.commit()?;
+            Ok(counts)
+        });
+    if outcome.is_err() {
+        let _ = conn.rollback();
+    }
+    eprintln!(
+        "DEBUG: Ran {} statements in one transaction",
+        statements.len()
+    );
+    outcome
+}
+
+type Shared = Arc<Mutex<Option<Connection<'static>>>>;
+
+// Handed to a run_in_transaction callback: statements it runs through this handle share one
+// connection and transaction, committed when the callback returns and rolled back if it raises
+#[pyclass]
+pub struct Transaction {
+    conn: Shared,
+    config: QueryConfig,
+    watch: Mutex<Option<TransactionWatch>>,
+}
+
+impl Transaction {
+    pub fn open(dsn: &str, user: &str, password: &str, config: &QueryConfig) -> Result<Self> {
+        let conn: Shared = Arc::new(Mutex::new(Some(open(dsn, user, password, config)?)));
+        let held = Arc::clone(&conn);
+        let watch = TransactionWatch::start(config, "Transaction", move || {
+            if let Err(e) = end(&held, false) {
+                eprintln!("WARN: Failed to roll back the transaction: {}", e);
+            }
+        })?;
+        Ok(Self {
+            conn,
+            config: config.clone(),
+            watch: Mutex::new(watch),
+        })
+    }
+
+    // Commits or rolls back and closes the connection. Committing fails if the transaction
+    // was already rolled back for exceeding max_transaction_age.
+    pub fn finish(&self, commit: bool) -> Result<()> {
+        self.watch.lock().unwrap_or_else(|e| e.into_inner()).take();
+        end(&self.conn, commit)
+    }
+
+    fn with_conn<T>(&self, work: impl FnOnce(&Connection<'static>) -> Result<T>) -> Result<T> {
+        let conn = self.conn.lock().unwrap_or_else(|e| e.into_inner());
+        let conn = conn
+            .as_ref()
+            .ok_or_else(|| anyhow!("The transaction is no longer open"))?;
+        work(conn)
+    }
+}
+
+fn end(conn: &Mutex<Option<Connection<'static>>>, commit: bool) -> Result<()> {
+    let conn = conn.lock().unwrap_or_else(|e| e.into_inner()).take();
+    match conn {
+        Some(conn) if commit => Ok(conn.commit()?),
+        Some(conn) => Ok(conn.rollback()?),
+        None if commit => Err(anyhow!(
+            "The transaction is no longer open and was not committed"
+        )),
+        None => Ok(()),
+    }
+}
+
+#[pymethods]
+impl Transaction {
+    // Runs a statement and returns the rows it affected, or None if the driver does not say
+    #[pyo3(signature = (sql, params=None))]
+    fn execute(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        params: Option<QueryParams>,
+    ) -> PyResult<Option<usize>> {
+        let (sql, params) =
+            params::bind(sql, params).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        py.allow_threads(|| {
+            access::check(&sql, &self.config)?;
+            self.with_conn(|conn| {
+                execute_on(conn, &sql, &[params], &self.config).map(|executed| executed.rowcount)
+            })
+        })
+        .map_err(map_query_error)
+    }
+
+    // Runs a query inside the transaction, so it sees the transaction's own changes, and
+    // returns an IbarrowResult like IbarrowConnection.query()
+    #[pyo3(signature = (sql, params=None))]
+    fn query(
+        &self,
+        py: Python<'_>,
+        sql: &str,
+        params: Option<QueryParams>,
+    ) -> PyResult<result::IbarrowResult> {
+        let (sql, params) =
+            params::bind(sql, params).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let config = compression::for_consumer(py, &self.config, compression::Consumer::Bytes)?;
+        let (bytes, rows) = py
+            .allow_threads(|| {
+                access::check(&sql, &config)?;
+                let (schema, batches) = self
+                    .with_conn(|conn| execute_on(conn, &sql, &[params], &config))?
+                    .rows
+                    .ok_or_else(|| anyhow!("The statement returned no result set"))?;
+                let mut bytes = Vec::new();
+                let mut sink = IpcSink::new(&mut bytes, compression::write_options(&config)?);
+                sink.begin(&schema)?;
+                for batch in &batches {
+                    sink.write(batch)?;
+                }
+                sink.finish()?;
+                drop(sink);
+                let rows = batches.iter().map(|batch| batch.num_rows()).sum();
+                Ok((bytes, rows))
+            })
+            .map_err(map_query_error)?;
+        result::IbarrowResult::new(bytes, rows)
+    }
+
+    // Bulk-inserts Arrow data into an existing table as IbarrowConnection.write_arrow() does,
+    // inside the transaction
+    fn write_arrow(
+        &self,
+        py: Python<'_>,
+        table_name: &str,
+        data: &Bound<'_, PyAny>,
+    ) -> PyResult<usize> {
+        if table_name.trim().is_empty() {
+            return Err(PyValueError::new_err("table_name must not be empty"));
+        }
+        let batches = write::batches_from_py(data)?;
+        let schema = write::insert_schema(&batches.schema())
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let target =
+            write::Target::insert(table_name, write::Columns::Matched, write::Mode::Append);
+        py.allow_threads(|| {
+            access::check(&target.sql(&schema), &self.config)?;
+            self.with_conn(|conn| {
+                let (table, schema, _) = write::resolve(conn, &target, &schema, &self.config)?;
+                let sql = write::Target::insert(&table, target.columns, target.mode).sql(&schema);
+                write::insert_batches(conn, &sql, &schema, batches, &self.config)
+            })
+        })
+        .map_err(map_query_error)
+    }
+
+    #[getter]
+    fn closed(&self) -> bool {
+        self.conn
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_none()
+    }
+
+    fn __repr__(&self) -> String {
+        let state = if self.closed() { "closed" } else { "open" };
+        format!("Transaction({})", state)
+    }
+}
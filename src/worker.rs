@@ -0,0 +1,163 @@
+use anyhow::{anyhow, Result};
+use arrow::record_batch::RecordBatch;
+use arrow_ipc::reader::StreamReader;
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::params::SqlParam;
+use crate::progress::Progress;
+use crate::sink::BatchSink;
+use crate::{access, write_arrow_ipc_impl, QueryConfig};
+
+// Prefix of the stderr line carrying the worker's error message
+const ERROR_MARKER: &str = "IBARROW_WORKER_ERROR: ";
+
+// What the parent sends the worker on stdin
+#[derive(Serialize, Deserialize)]
+struct Request {
+    dsn: String,
+    user: String,
+    password: String,
+    sql: String,
+    params: Vec<SqlParam>,
+    config: QueryConfig,
+}
+
+// Runs the query in a helper process (the current Python interpreter running
+// ibarrow._worker_main) and hands the batches of the Arrow IPC stream it returns on stdout to
+// `sink`. A driver that crashes only takes the helper down and surfaces here as an error.
+#[allow(clippy::too_many_arguments)]
+pub fn fetch_into(
+    dsn: &str,
+    user: &str,
+    password: &str,
+    sql: &str,
+    params: &[SqlParam],
+    config: &QueryConfig,
+    sink: &mut dyn BatchSink,
+    observe: &mut dyn FnMut(&RecordBatch) -> Result<()>,
+) -> Result<usize> {
+    // Access policies live in this process, so the statement is vetted before it leaves
+    access::check(sql, config)?;
+    let python: String = Python::with_gil(|py| {
+        py.import_bound("sys")?
+            .getattr("executable")?
+            .extract::<Option<String>>()
+    })?
+    .filter(|path| !path.is_empty())
+    .ok_or_else(|| anyhow!("worker_process needs sys.executable to start the ODBC worker"))?;
+
+    let request = Request {
+        dsn: dsn.to_string(),
+        user: user.to_string(),
+        password: password.to_string(),
+        sql: sql.to_string(),
+        params: params.to_vec(),
+        // Compression is decided for the sink here, and hooks and policies stay in this process
+        config: QueryConfig {
+            worker_process: false,
+            access_role: None,
+            ipc_compression: None,
+            progress: None,
+            ..config.clone()
+        },
+    };
+    eprintln!("DEBUG: Starting ODBC worker process {}", python);
+    let mut child = Command::new(&python)
+        .args(["-c", "import ibarrow; ibarrow._worker_main()"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("Failed to start ODBC worker process {}: {}", python, e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        serde_json::to_writer(&mut stdin, &request)?;
+    }
+    // The worker's log lines are passed through; its error message is kept
+    let stderr = child.stderr.take().map(|stderr| {
+        thread::spawn(move || {
+            let mut message = None;
+            for line in BufReader::new(stderr).lines().map_while(|line| line.ok()) {
+                match line.strip_prefix(ERROR_MARKER) {
+                    Some(error) => message = Some(error.to_string()),
+                    None => eprintln!("{}", line),
+                }
+            }
+            message
+        })
+    });
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("ODBC worker process has no stdout"))?;
+    let copied = copy_stream(BufReader::new(stdout), config, sink, observe);
+    if copied.is_err() {
+        let _ = child.kill();
+    }
+    let status = child.wait()?;
+    let message = stderr.and_then(|thread| thread.join().ok()).flatten();
+    if !status.success() {
+        return Err(match message {
+            Some(message) => anyhow!(message),
+            None => anyhow!("ODBC worker process crashed ({})", status),
+        });
+    }
+    copied
+}
+
+fn copy_stream<R: Read>(
+    input: R,
+    config: &QueryConfig,
+    sink: &mut dyn BatchSink,
+    observe: &mut dyn FnMut(&RecordBatch) -> Result<()>,
+) -> Result<usize> {
+    let reader = StreamReader::try_new(input, None)
+        .map_err(|e| anyhow!("ERROR: Invalid Arrow stream from ODBC worker: {}", e))?;
+    sink.begin(&reader.schema())?;
+    let mut progress = Progress::new(config);
+    let mut rows = 0;
+    for batch in reader {
+        let batch = batch.map_err(|e| anyhow!("ERROR: Failed to read worker batch: {}", e))?;
+        observe(&batch)?;
+        progress.batch(&batch)?;
+        sink.write(&batch)
+            .map_err(|e| anyhow!("ERROR: Failed to write batch: {}", e))?;
+        rows += batch.num_rows();
+    }
+    sink.finish()?;
+    Ok(rows)
+}
+
+// Entry point of the worker process: reads a Request from stdin and writes the result to
+// stdout as an Arrow IPC stream. Failures end the process with the message on stderr.
+#[pyfunction]
+pub fn _worker_main(py: Python<'_>) {
+    let result = py.allow_threads(|| -> Result<()> {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        let request: Request = serde_json::from_str(&input)
+            .map_err(|e| anyhow!("Invalid ODBC worker request: {}", e))?;
+        let stdout = std::io::stdout();
+        let mut out = BufWriter::new(stdout.lock());
+        write_arrow_ipc_impl(
+            &request.dsn,
+            &request.user,
+            &request.password,
+            &request.sql,
+            &request.params,
+            &request.config,
+            &mut out,
+        )?;
+        out.flush()?;
+        Ok(())
+    });
+    if let Err(e) = result {
+        eprintln!("{}{}", ERROR_MARKER, e.to_string().replace('\n', " "));
+        std::process::exit(1);
+    }
+}
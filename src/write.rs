@@ -0,0 +1,96 @@
+use anyhow::anyhow;
+use arrow::ffi_stream::{ArrowArrayStreamReader, FFI_ArrowArrayStream};
+use arrow_ipc::reader::StreamReader;
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::{PyImportError, PyTypeError};
+use pyo3::prelude::*;
+use pyo3::types::{PyCapsule, PyDict};
+use std::io::Cursor;
+
+// Inserting, truncating and replacing live in ibarrow-core; this module reads the data from
+// Python objects
+#[cfg(feature = "cli")]
+pub use ibarrow_core::write::find_table;
+pub use ibarrow_core::write::{
+    create_table, insert_batches, insert_schema, resolve, write_arrow, Batches, Columns, Mode,
+    Target, WRITE_BATCH_ROWS,
+};
+
+// Reads the data handed to the write methods: an Arrow IPC stream (bytes, bytearray or
+// memoryview) or any object exporting the Arrow PyCapsule stream interface, such as a pyarrow
+// Table or RecordBatchReader (pyarrow 14+), a Polars DataFrame (Polars 1.3+) or an ibarrow result
+pub fn batches_from_py(data: &Bound<'_, PyAny>) -> PyResult<Batches> {
+    if data.hasattr("__arrow_c_stream__")? {
+        let capsule = data.call_method0("__arrow_c_stream__")?;
+        let capsule = capsule.downcast::<PyCapsule>()?;
+        // Moving the stream out leaves a released one behind for the capsule's destructor
+        let reader = unsafe {
+            ArrowArrayStreamReader::from_raw(capsule.pointer() as *mut FFI_ArrowArrayStream)
+        }
+        .map_err(|e| crate::map_query_error(anyhow!("Arrow C stream import failed: {}", e)))?;
+        return Ok(Box::new(reader));
+    }
+    if let Ok(buffer) = PyBuffer::<u8>::get_bound(data) {
+        let bytes = buffer.to_vec(data.py())?;
+        let reader = StreamReader::try_new(Cursor::new(bytes), None)
+            .map_err(|e| crate::map_query_error(anyhow!("Invalid Arrow IPC stream: {}", e)))?;
+        return Ok(Box::new(reader));
+    }
+    Err(PyTypeError::new_err(format!(
+        "Cannot write a {}: pass Arrow IPC stream bytes or an object with __arrow_c_stream__",
+        data.get_type().name()?
+    )))
+}
+
+// Whether `value` is an instance of `module`.`name`, checked without importing the module
+fn is_instance_of(value: &Bound<'_, PyAny>, module: &str, names: &[&str]) -> PyResult<bool> {
+    let class = value.get_type();
+    let class_module: String = class.getattr("__module__")?.extract()?;
+    let top = class_module.split('.').next().unwrap_or_default();
+    Ok(top == module && names.contains(&class.name()?.as_ref()))
+}
+
+// Arrow data of a Polars DataFrame, or of a LazyFrame once collected
+pub fn polars_batches(df: &Bound<'_, PyAny>) -> PyResult<Batches> {
+    if !is_instance_of(df, "polars", &["DataFrame", "LazyFrame"])? {
+        return Err(PyTypeError::new_err(format!(
+            "write_polars needs a Polars DataFrame, not a {}",
+            df.get_type().name()?
+        )));
+    }
+    let df = if is_instance_of(df, "polars", &["LazyFrame"])? {
+        df.call_method0("collect")?
+    } else {
+        df.clone()
+    };
+    // Polars before 1.3 has no __arrow_c_stream__ and goes through pyarrow
+    if df.hasattr("__arrow_c_stream__")? {
+        batches_from_py(&df)
+    } else {
+        batches_from_py(&df.call_method0("to_arrow")?)
+    }
+}
+
+// Arrow data of a pandas DataFrame, without its index. Converted by pyarrow, or by Polars
+// when pyarrow is not installed.
+pub fn pandas_batches(df: &Bound<'_, PyAny>) -> PyResult<Batches> {
+    if !is_instance_of(df, "pandas", &["DataFrame"])? {
+        return Err(PyTypeError::new_err(format!(
+            "write_pandas needs a pandas DataFrame, not a {}",
+            df.get_type().name()?
+        )));
+    }
+    let py = df.py();
+    if let Ok(pyarrow) = py.import_bound("pyarrow") {
+        let kwargs = PyDict::new_bound(py);
+        kwargs.set_item("preserve_index", false)?;
+        let table = pyarrow
+            .getattr("Table")?
+            .call_method("from_pandas", (df,), Some(&kwargs))?;
+        return batches_from_py(&table);
+    }
+    let polars = py.import_bound("polars").map_err(|_| {
+        PyImportError::new_err("write_pandas needs pyarrow or Polars to convert the DataFrame")
+    })?;
+    polars_batches(&polars.call_method1("from_pandas", (df,))?)
+}
@@ -0,0 +1,137 @@
+use anyhow::{anyhow, Result};
+use arrow::array::{Array, ArrayRef};
+use arrow::compute::{concat, sort_to_indices, take, SortOptions};
+use arrow::datatypes::DataType;
+use arrow::record_batch::RecordBatch;
+use arrow::util::display::{ArrayFormatter, FormatOptions};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::find_column;
+
+// Range of one column within one exported file. Readers can skip the file when a predicate
+// cannot match anything between `min` and `max`. Both are null when the column only held NULLs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Zone {
+    pub min: Value,
+    pub max: Value,
+    pub null_count: usize,
+}
+
+struct Column {
+    name: String,
+    index: Option<usize>,
+    // Single-value arrays, so any orderable Arrow type is compared by the sort kernel
+    min: Option<ArrayRef>,
+    max: Option<ArrayRef>,
+    null_count: usize,
+}
+
+// Smallest (or largest) non-null value of `array`, as a one-element array
+fn extreme(array: &dyn Array, descending: bool) -> Result<Option<ArrayRef>> {
+    if array.null_count() == array.len() {
+        return Ok(None);
+    }
+    let options = SortOptions {
+        descending,
+        nulls_first: false,
+    };
+    let indices = sort_to_indices(array, Some(options), Some(1))?;
+    Ok(Some(take(array, &indices, None)?))
+}
+
+fn merge(
+    current: Option<ArrayRef>,
+    batch: Option<ArrayRef>,
+    descending: bool,
+) -> Result<Option<ArrayRef>> {
+    match (current, batch) {
+        (Some(current), Some(batch)) => extreme(&concat(&[&current, &batch])?, descending),
+        (current, batch) => Ok(current.or(batch)),
+    }
+}
+
+// Integers, floats and booleans stay JSON scalars; decimals, dates, timestamps and text are
+// written as Arrow displays them
+fn to_json(value: Option<&ArrayRef>) -> Result<Value> {
+    let Some(value) = value else {
+        return Ok(Value::Null);
+    };
+    let text = ArrayFormatter::try_new(value.as_ref(), &FormatOptions::default())?
+        .value(0)
+        .to_string();
+    let data_type = value.data_type();
+    if data_type.is_integer() || data_type.is_floating() || *data_type == DataType::Boolean {
+        if let Ok(scalar) = serde_json::from_str::<Value>(&text) {
+            return Ok(scalar);
+        }
+    }
+    Ok(Value::String(text))
+}
+
+// Per-column min/max/null count of one exported file, updated batch by batch
+pub struct ZoneMap {
+    columns: Vec<Column>,
+}
+
+impl ZoneMap {
+    pub fn new(columns: &[String]) -> Self {
+        Self {
+            columns: columns
+                .iter()
+                .map(|name| Column {
+                    name: name.clone(),
+                    index: None,
+                    min: None,
+                    max: None,
+                    null_count: 0,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn observe(&mut self, batch: &RecordBatch) -> Result<()> {
+        for column in &mut self.columns {
+            let index = match column.index {
+                Some(index) => index,
+                None => {
+                    let index = find_column(&batch.schema(), &column.name).ok_or_else(|| {
+                        anyhow!("zone_map column '{}' is not in the result set", column.name)
+                    })?;
+                    column.index = Some(index);
+                    index
+                }
+            };
+            let array = batch.column(index);
+            column.null_count += array.null_count();
+            let unordered = |e: anyhow::Error| {
+                anyhow!(
+                    "zone_map column '{}' of type {} cannot be ordered: {}",
+                    column.name,
+                    array.data_type(),
+                    e
+                )
+            };
+            let min = extreme(array, false).and_then(|min| merge(column.min.take(), min, false));
+            let max = extreme(array, true).and_then(|max| merge(column.max.take(), max, true));
+            column.min = min.map_err(unordered)?;
+            column.max = max.map_err(unordered)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(&self) -> Result<BTreeMap<String, Zone>> {
+        self.columns
+            .iter()
+            .map(|column| {
+                let zone = Zone {
+                    min: to_json(column.min.as_ref())?,
+                    max: to_json(column.max.as_ref())?,
+                    null_count: column.null_count,
+                };
+                Ok((column.name.clone(), zone))
+            })
+            .collect()
+    }
+}
@@ -2,6 +2,12 @@
 Basic tests for the ibarrow module.
 """
 
+import asyncio
+import io
+import os
+import shutil
+import subprocess
+
 import pytest
 import ibarrow
 
@@ -100,3 +106,1899 @@ def test_query_arrow_c_data_with_dataframe_invalid_connection():
             dsn="invalid_dsn", user="invalid_user", password="invalid_password"
         )
         conn.query_arrow_c_data("SELECT 1", return_dataframe=True)
+
+
+def test_extract_registry_roundtrip(tmp_path):
+    """Test registering, listing and removing named extracts."""
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+    registry = ibarrow.ExtractRegistry(conn, str(tmp_path / "extracts.json"))
+    registry.register(
+        "sales",
+        "SELECT * FROM SALES",
+        str(tmp_path / "sales.arrow"),
+        schedule="daily",
+        max_age_secs=86400,
+    )
+
+    extracts = registry.list()
+    assert len(extracts) == 1
+    assert extracts[0]["name"] == "sales"
+    assert extracts[0]["schedule"] == "daily"
+    assert extracts[0]["last_refreshed_at"] is None
+    assert extracts[0]["stale"] is True
+
+    registry.remove("sales")
+    assert registry.list() == []
+    with pytest.raises(KeyError):
+        registry.remove("sales")
+
+
+def test_extract_refresh_invalid_connection(tmp_path):
+    """Test that a failed refresh raises and leaves no destination file."""
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+    registry = ibarrow.ExtractRegistry(conn, str(tmp_path / "extracts.json"))
+    destination = tmp_path / "sales.arrow"
+    registry.register("sales", "SELECT 1", str(destination))
+
+    with pytest.raises(ibarrow.PyConnectionError):
+        registry.refresh("sales")
+    assert not destination.exists()
+    assert registry.list()[0]["last_refreshed_at"] is None
+
+
+def test_async_query_invalid_connection():
+    """Test that awaiting an async query surfaces connection errors."""
+    conn = ibarrow.connect_async(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+
+    async def run():
+        return await conn.query_polars("SELECT 1")
+
+    with pytest.raises(ibarrow.PyConnectionError):
+        asyncio.run(run())
+
+
+def test_async_test_connection_invalid():
+    """Test async test_connection returns False for an invalid DSN."""
+    conn = ibarrow.AsyncIbarrowConnection(
+        "invalid_dsn", "invalid_user", "invalid_password"
+    )
+
+    async def run():
+        return await conn.test_connection()
+
+    assert asyncio.run(run()) is False
+
+
+def test_async_calls_beyond_worker_pool():
+    """Test more concurrent async calls than pool workers all complete."""
+    conn = ibarrow.AsyncIbarrowConnection(
+        "invalid_dsn", "invalid_user", "invalid_password"
+    )
+
+    async def run():
+        return await asyncio.gather(*(conn.test_connection() for _ in range(20)))
+
+    assert asyncio.run(run()) == [False] * 20
+
+
+def test_query_config_restart_options():
+    """Test restart options on QueryConfig."""
+    config = ibarrow.QueryConfig(max_restarts=3, restart_key="ID")
+    assert config.max_restarts == 3
+    assert config.restart_key == "ID"
+
+    default = ibarrow.QueryConfig()
+    assert default.max_restarts is None
+    assert default.restart_key is None
+
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn = ibarrow.connect(
+            dsn="invalid_dsn",
+            user="invalid_user",
+            password="invalid_password",
+            config=config,
+        )
+        conn.query_arrow_ipc("SELECT ID FROM CUSTOMERS")
+
+    # Restarts resume by key, so they need one
+    conn = ibarrow.connect(
+        dsn="invalid_dsn",
+        user="invalid_user",
+        password="invalid_password",
+        config=ibarrow.QueryConfig(max_restarts=3),
+    )
+    with pytest.raises(RuntimeError, match="restart_key"):
+        conn.query_arrow_ipc("SELECT ID FROM CUSTOMERS")
+
+
+def test_async_stream_invalid_connection():
+    """Test that async batch streaming surfaces connection errors."""
+    conn = ibarrow.connect_async(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+
+    async def run():
+        return [batch async for batch in conn.stream("SELECT 1")]
+
+    with pytest.raises(ibarrow.PyConnectionError):
+        asyncio.run(run())
+
+
+def test_query_file_missing_parameter(tmp_path):
+    """Test that query_file reports unbound named parameters before connecting."""
+    (tmp_path / "filters.sql").write_text("WHERE ID = :id -- customer id\n")
+    (tmp_path / "report.sql").write_text(
+        "/* report */\nSELECT * FROM CUSTOMERS\n@include filters.sql\n"
+    )
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+
+    with pytest.raises(ValueError):
+        conn.query_file(str(tmp_path / "report.sql"), params={})
+
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_file(str(tmp_path / "report.sql"), params={"id": 42})
+
+
+def test_query_file_circular_include(tmp_path):
+    """Test that circular @include directives are rejected."""
+    (tmp_path / "a.sql").write_text("@include b.sql\n")
+    (tmp_path / "b.sql").write_text("@include a.sql\n")
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+
+    with pytest.raises(OSError):
+        conn.query_file(str(tmp_path / "a.sql"))
+    with pytest.raises(OSError):
+        conn.query_file(str(tmp_path / "missing.sql"))
+
+
+def test_sql_charset_config():
+    """Test sql_charset validation and non-ASCII queries on a legacy charset."""
+    config = ibarrow.QueryConfig(sql_charset="WIN1252")
+    assert config.sql_charset == "WIN1252"
+
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password", config=config
+    )
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_arrow_ipc("SELECT * FROM CLIENTES WHERE CIDADE = 'São Paulo'")
+
+    conn = ibarrow.connect(
+        dsn="invalid_dsn",
+        user="invalid_user",
+        password="invalid_password",
+        config=ibarrow.QueryConfig(sql_charset="KLINGON"),
+    )
+    with pytest.raises(RuntimeError):
+        conn.query_arrow_ipc("SELECT 1 FROM RDB$DATABASE")
+
+
+def test_query_many_invalid_connection():
+    """Test that concurrent execution reports connection errors."""
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+
+    assert conn.query_many([]) == []
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_many(["SELECT 1 FROM RDB$DATABASE", "SELECT 2 FROM RDB$DATABASE"])
+
+
+def test_execute_block_validation():
+    """Test EXECUTE BLOCK argument validation and execution errors."""
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+
+    with pytest.raises(ValueError):
+        conn.execute_block("  -- nothing to run\n")
+    with pytest.raises(ValueError):
+        conn.execute_block(
+            "SUSPEND", params={"x": 1}, param_types={"y": "INTEGER"}
+        )
+
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.execute_block(
+            "FOR SELECT ID FROM ORDERS WHERE CUSTOMER_ID = :customer INTO :id DO SUSPEND",
+            params={"customer": 42},
+            returns={"id": "INTEGER"},
+        )
+
+
+def test_timeout_error_type():
+    """Test that the timeout exception is exported and is a TimeoutError."""
+    assert issubclass(ibarrow.PyTimeoutError, TimeoutError)
+
+    conn = ibarrow.connect(
+        dsn="invalid_dsn",
+        user="invalid_user",
+        password="invalid_password",
+        config=ibarrow.QueryConfig(query_timeout=30),
+    )
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_arrow_ipc("SELECT 1 FROM RDB$DATABASE")
+
+
+def test_testing_start_server():
+    """Test the Docker-backed test server (only in builds with the testing feature)."""
+    testing = pytest.importorskip("ibarrow.testing")
+
+    docker = shutil.which("docker")
+    if docker is None or subprocess.run([docker, "info"], capture_output=True).returncode:
+        with pytest.raises(RuntimeError):
+            testing.start_server()
+        return
+
+    with testing.start_server(version="4.0") as conn:
+        assert conn.test_connection()
+
+
+def test_retry_policy_config():
+    """Test retry policy options and that retries give up with the original error."""
+    config = ibarrow.QueryConfig(
+        retry_attempts=3, retry_backoff_ms=10, retry_sqlstates=["IM002", "08S01"]
+    )
+    assert config.retry_attempts == 3
+    assert config.retry_backoff_ms == 10
+    assert config.retry_sqlstates == ["IM002", "08S01"]
+
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password", config=config
+    )
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_arrow_ipc("SELECT 1 FROM RDB$DATABASE")
+
+
+def test_query_result_invalid_connection():
+    """Test that conn.query() validates parameters and reports connection errors."""
+    assert hasattr(ibarrow, "IbarrowResult")
+    # Arrow PyCapsule stream protocol, used by pyarrow.table(result) and polars.from_arrow(result)
+    assert hasattr(ibarrow.IbarrowResult, "__arrow_c_stream__")
+    # DataFrame Interchange Protocol, used by seaborn, plotly and friends
+    assert hasattr(ibarrow.IbarrowResult, "__dataframe__")
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+
+    with pytest.raises(ValueError):
+        conn.query("SELECT * FROM ORDERS WHERE ID = :id", params={})
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query("SELECT * FROM ORDERS WHERE ID = :id", params={"id": 1})
+
+
+def test_migrate_validation(tmp_path):
+    """Test migration discovery errors and connection failures."""
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+
+    with pytest.raises(OSError):
+        conn.migrate(str(tmp_path / "missing"))
+
+    (tmp_path / "V001__create_orders.sql").write_text(
+        "CREATE TABLE ORDERS (ID INTEGER NOT NULL PRIMARY KEY);\n"
+    )
+    (tmp_path / "V002__orders_index.sql").write_text(
+        "CREATE INDEX IDX_ORDERS_ID ON ORDERS (ID)\n"
+    )
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.migrate(str(tmp_path), dry_run=True)
+
+    (tmp_path / "002_duplicate.sql").write_text("SELECT 1 FROM RDB$DATABASE\n")
+    with pytest.raises(OSError):
+        conn.migrate(str(tmp_path))
+
+
+def test_access_policy():
+    """Test that access policies reject disallowed tables and columns before connecting."""
+    ibarrow.set_access_policy(
+        "analyst", {"ORDERS": ["ID", "STATUS", "AMOUNT"], "REGIONS": None}
+    )
+    try:
+        conn = ibarrow.connect(
+            dsn="invalid_dsn",
+            user="invalid_user",
+            password="invalid_password",
+            config=ibarrow.QueryConfig(access_role="analyst"),
+        )
+        assert issubclass(ibarrow.PyAccessError, PermissionError)
+
+        for sql in [
+            "SELECT * FROM CUSTOMERS",
+            "SELECT * FROM ORDERS",
+            "SELECT O.CUSTOMER_ID FROM ORDERS O",
+            "SELECT ID, CREDIT_CARD FROM ORDERS WHERE STATUS = 'OPEN'",
+            "SELECT R.NAME FROM REGIONS R JOIN CUSTOMERS C ON C.REGION = R.ID",
+            "SELECT CREDIT_CARD FROM RDB$DATABASE",
+            "SELECT D.CREDIT_CARD FROM RDB$DATABASE D",
+            "EXECUTE BLOCK AS BEGIN END",
+            "EXECUTE STATEMENT 'SELECT * FROM CUSTOMERS'",
+            "EXECUTE PROCEDURE P",
+            "INSERT INTO ORDERS (ID) VALUES (1)",
+            "DELETE FROM ORDERS",
+            "SELECT ID FROM ORDERS; DROP TABLE ORDERS",
+            'SELECT "CREDIT_CARD" FROM ORDERS',
+            'SELECT ID, "FROM" FROM ORDERS',
+            'SELECT ID FROM "orders"',
+        ]:
+            with pytest.raises(ibarrow.PyAccessError):
+                conn.query_arrow_ipc(sql)
+        with pytest.raises(ibarrow.PyAccessError):
+            conn.query_many(["SELECT ID FROM ORDERS", "SELECT * FROM CUSTOMERS"])
+
+        # Allowed statements get as far as connecting
+        for sql in [
+            "SELECT ID, AMOUNT AS TOTAL FROM ORDERS WHERE STATUS = 'OPEN'",
+            "SELECT O.ID, R.* FROM ORDERS O JOIN REGIONS R ON R.ID = O.ID",
+            "SELECT EXTRACT(YEAR FROM CURRENT_DATE) FROM RDB$DATABASE",
+            "SELECT O.ID, D.RDB$CHARACTER_SET_NAME FROM ORDERS O CROSS JOIN RDB$DATABASE D",
+            'SELECT "ID" FROM "ORDERS" -- ; DROP TABLE ORDERS',
+        ]:
+            with pytest.raises(ibarrow.PyConnectionError):
+                conn.query_arrow_ipc(sql)
+        with pytest.raises(ibarrow.PyConnectionError):
+            conn.query("SELECT AMOUNT FROM ORDERS WHERE ID = :id", params={"id": 1})
+
+        ibarrow.clear_access_policy("analyst")
+        with pytest.raises(ibarrow.PyAccessError):
+            conn.query_arrow_ipc("SELECT ID FROM ORDERS")
+    finally:
+        ibarrow.clear_access_policy()
+
+
+def test_access_policy_alias_scopes():
+    """Test that aliases resolve in their own query block and ambiguous ones are rejected."""
+    ibarrow.set_access_policy("analyst", {"ORDERS": ["ID"], "REGIONS": None})
+    try:
+        conn = ibarrow.connect(
+            dsn="invalid_dsn",
+            user="invalid_user",
+            password="invalid_password",
+            config=ibarrow.QueryConfig(access_role="analyst"),
+        )
+        for sql in [
+            "SELECT R.CREDIT_CARD FROM ORDERS R WHERE EXISTS (SELECT 1 FROM REGIONS R)",
+            "SELECT (SELECT MAX(R.NAME) FROM REGIONS R), R.CREDIT_CARD FROM ORDERS R",
+            "SELECT R.CREDIT_CARD FROM ORDERS R JOIN REGIONS R ON 1 = 1",
+            "SELECT ORDERS.CREDIT_CARD FROM ORDERS JOIN REGIONS ORDERS ON 1 = 1",
+            "SELECT R.CREDIT_CARD FROM ORDERS R UNION ALL SELECT R.NAME FROM REGIONS R",
+        ]:
+            with pytest.raises(ibarrow.PyAccessError):
+                conn.query_arrow_ipc(sql)
+
+        for sql in [
+            "SELECT R.NAME FROM REGIONS R UNION ALL SELECT R.ID FROM ORDERS R",
+            "SELECT O.ID FROM ORDERS O WHERE EXISTS (SELECT 1 FROM ORDERS P WHERE P.ID = O.ID)",
+            "SELECT A.ID, B.ID FROM ORDERS A JOIN ORDERS B ON B.ID = A.ID",
+            "SELECT ID FROM ORDERS WHERE ID IN (SELECT ID FROM ORDERS)",
+        ]:
+            with pytest.raises(ibarrow.PyConnectionError):
+                conn.query_arrow_ipc(sql)
+    finally:
+        ibarrow.clear_access_policy()
+
+
+def test_access_policy_ctes():
+    """Test that only WITH-list names are CTEs and never stand in for real tables."""
+    ibarrow.set_access_policy("analyst", {"ORDERS": ["ID"], "REGIONS": None})
+    try:
+        conn = ibarrow.connect(
+            dsn="invalid_dsn",
+            user="invalid_user",
+            password="invalid_password",
+            config=ibarrow.QueryConfig(access_role="analyst"),
+        )
+        for sql in [
+            "SELECT * FROM CUSTOMERS WINDOW CUSTOMERS AS ()",
+            "WITH ORDERS AS (SELECT CREDIT_CARD FROM ORDERS) SELECT * FROM ORDERS",
+            "WITH X AS (SELECT ID FROM CUSTOMERS) SELECT ID FROM X",
+            "SELECT D.ID FROM (WITH CUSTOMERS AS (SELECT ID FROM ORDERS) "
+            "SELECT ID FROM CUSTOMERS) D, CUSTOMERS",
+            "WITH A AS (SELECT ID FROM B), B AS (SELECT ID FROM REGIONS) SELECT ID FROM A",
+        ]:
+            with pytest.raises(ibarrow.PyAccessError):
+                conn.query_arrow_ipc(sql)
+
+        for sql in [
+            "WITH X AS (SELECT ID FROM ORDERS) SELECT ID FROM X",
+            "WITH A AS (SELECT ID FROM ORDERS), B AS (SELECT ID FROM A) SELECT B.ID FROM B",
+            "WITH RECURSIVE T (N) AS (SELECT 1 FROM RDB$DATABASE "
+            "UNION ALL SELECT N + 1 FROM T WHERE N < 5) SELECT N FROM T",
+        ]:
+            with pytest.raises(ibarrow.PyConnectionError):
+                conn.query_arrow_ipc(sql)
+    finally:
+        ibarrow.clear_access_policy()
+
+
+def test_domain_metadata_rules():
+    """Test domain metadata rules can be registered, replaced and cleared."""
+    try:
+        ibarrow.set_domain_metadata("*_pct", {"unit": "percent"})
+        ibarrow.set_domain_metadata("D_AMOUNT_*", {"unit": "currency"})
+        ibarrow.set_domain_metadata("*_PCT", {"unit": "percent", "scale": "100"})
+        assert ibarrow.domain_metadata() == [
+            ("*_PCT", {"unit": "percent", "scale": "100"}),
+            ("D_AMOUNT_*", {"unit": "currency"}),
+        ]
+        conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+        with pytest.raises(ibarrow.PyConnectionError):
+            conn.query_arrow_ipc("SELECT DISCOUNT FROM ORDERS")
+        ibarrow.clear_domain_metadata("d_amount_*")
+        assert ibarrow.domain_metadata() == [("*_PCT", {"unit": "percent", "scale": "100"})]
+    finally:
+        ibarrow.clear_domain_metadata()
+    assert ibarrow.domain_metadata() == []
+
+
+def test_fetch_batches_invalid_connection():
+    """Test that fetch_batches validates batch_size and surfaces connection errors lazily."""
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+
+    with pytest.raises(ValueError):
+        conn.fetch_batches("SELECT * FROM ORDERS", batch_size=0)
+
+    batches = conn.fetch_batches("SELECT * FROM ORDERS", batch_size=10_000)
+    assert iter(batches) is batches
+    with pytest.raises(ibarrow.PyConnectionError):
+        next(batches)
+    assert list(batches) == []
+
+    with conn.fetch_batches("SELECT * FROM ORDERS") as batches:
+        batches.close()
+        assert list(batches) == []
+
+
+def test_bundle_validation(tmp_path):
+    """Test bundle export validation and reading bundles back."""
+    import json
+    import tarfile
+
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+    bundle = tmp_path / "orders.ibarrow"
+
+    with pytest.raises(ValueError):
+        conn.export_bundle({}, str(bundle))
+    with pytest.raises(ValueError):
+        conn.export_bundle({"../orders": "SELECT * FROM ORDERS"}, str(bundle))
+    with pytest.raises(ValueError):
+        conn.export_bundle(
+            {"orders": "SELECT * FROM ORDERS"}, str(bundle), zone_map={"customers": ["ID"]}
+        )
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.export_bundle({"orders": "SELECT * FROM ORDERS"}, str(bundle))
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.export_bundle(
+            {"orders": "SELECT * FROM ORDERS"}, str(bundle), zone_map={"orders": ["ORDER_DATE"]}
+        )
+    assert list(tmp_path.iterdir()) == []
+
+    with pytest.raises(OSError):
+        ibarrow.load_bundle(str(bundle))
+
+    manifest = {
+        "format": "ibarrow-bundle",
+        "version": 1,
+        "ibarrow_version": "test",
+        "created_at": 0,
+        "tables": [],
+    }
+    manifest_file = tmp_path / "manifest.json"
+    manifest_file.write_text(json.dumps(manifest))
+    with tarfile.open(bundle, "w") as archive:
+        archive.add(manifest_file, arcname="manifest.json")
+    loaded = ibarrow.load_bundle(str(bundle))
+    assert loaded["manifest"]["format"] == "ibarrow-bundle"
+    assert loaded["tables"] == {}
+
+    manifest["tables"].append(
+        {
+            "name": "orders",
+            "file": "orders.parquet",
+            "sql": "SELECT * FROM ORDERS",
+            "rows": 0,
+            "bytes": 0,
+            "sha256": "",
+            "fetched_at": 0,
+            "columns": [],
+        }
+    )
+    manifest_file.write_text(json.dumps(manifest))
+    with tarfile.open(bundle, "w") as archive:
+        archive.add(manifest_file, arcname="manifest.json")
+    with pytest.raises(OSError):
+        ibarrow.load_bundle(str(bundle))
+
+
+def test_ipc_compression_config():
+    """Test IPC compression settings are validated before connecting."""
+    config = ibarrow.QueryConfig(ipc_compression="auto")
+    assert config.ipc_compression == "auto"
+
+    conn = ibarrow.connect(
+        dsn="invalid_dsn",
+        user="invalid_user",
+        password="invalid_password",
+        config=ibarrow.QueryConfig(ipc_compression="gzip"),
+    )
+    with pytest.raises(ValueError):
+        conn.query_arrow_ipc("SELECT 1 FROM RDB$DATABASE")
+
+    for codec in ["auto", "none", "lz4", "zstd"]:
+        conn = ibarrow.connect(
+            dsn="invalid_dsn",
+            user="invalid_user",
+            password="invalid_password",
+            config=ibarrow.QueryConfig(ipc_compression=codec),
+        )
+        with pytest.raises(ibarrow.PyConnectionError):
+            conn.query_arrow_ipc("SELECT 1 FROM RDB$DATABASE")
+
+
+def test_server_time_invalid_connection():
+    """Test server_time with invalid connection parameters."""
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.server_time()
+
+
+def test_error_diagnostics_chain():
+    """Test that raised errors carry the ODBC diagnostic records."""
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+    with pytest.raises(ibarrow.PyConnectionError) as excinfo:
+        conn.query_arrow_ipc("SELECT 1 FROM RDB$DATABASE")
+
+    diagnostics = excinfo.value.diagnostics
+    assert isinstance(diagnostics, list)
+    assert diagnostics
+    assert diagnostics[0]["state"] == "IM002"
+    assert set(diagnostics[0]) == {"state", "native_error", "message"}
+
+    # Errors raised from iterator threads keep their records too
+    with pytest.raises(ibarrow.PyConnectionError) as excinfo:
+        next(conn.fetch_batches("SELECT 1 FROM RDB$DATABASE"))
+    assert excinfo.value.diagnostics[0]["state"] == "IM002"
+
+
+def test_estimate_invalid_connection():
+    """Test estimate with invalid connection parameters."""
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.estimate("SELECT * FROM SALES", sample_rows=100, max_rows=1_000_000)
+
+
+def test_max_rows_option():
+    """Test max_rows on QueryConfig and as a per-query kwarg."""
+    config = ibarrow.QueryConfig(max_rows=100)
+    assert config.max_rows == 100
+    assert ibarrow.QueryConfig().max_rows is None
+
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password", config=config
+    )
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_arrow_ipc("SELECT * FROM SALES", max_rows=10)
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query("SELECT * FROM SALES", max_rows=10)
+    with pytest.raises(ibarrow.PyConnectionError):
+        next(conn.fetch_batches("SELECT * FROM SALES", max_rows=10))
+
+
+def test_query_top_k_validation():
+    """Test top_k sizes are validated before connecting."""
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+    with pytest.raises(ValueError):
+        conn.query("SELECT * FROM SALES", top_k={"REGION": 0})
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query("SELECT * FROM SALES", top_k={"REGION": 5})
+
+
+def test_progress_callback_config():
+    """Test progress hooks are stored on QueryConfig and not called before any batch."""
+    calls = []
+    config = ibarrow.QueryConfig(progress=lambda rows, size: calls.append((rows, size)))
+    assert callable(config.progress)
+    assert ibarrow.QueryConfig().progress is None
+
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password", config=config
+    )
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_arrow_ipc("SELECT * FROM SALES")
+    assert calls == []
+
+
+def test_prefetch_config():
+    """Test the prefetch flag on QueryConfig."""
+    assert ibarrow.QueryConfig().prefetch is False
+    config = ibarrow.QueryConfig(prefetch=True)
+    assert config.prefetch is True
+
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password", config=config
+    )
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_arrow_ipc("SELECT * FROM SALES")
+    with pytest.raises(ibarrow.PyConnectionError):
+        next(conn.fetch_batches("SELECT * FROM SALES"))
+
+
+def test_arrow_stream_invalid_connection():
+    """Test arrow_stream argument validation and connection errors."""
+    assert hasattr(ibarrow.ArrowStream, "__arrow_c_stream__")
+    assert hasattr(ibarrow.ArrowStream, "close")
+
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password"
+    )
+    with pytest.raises(ValueError):
+        conn.arrow_stream("SELECT * FROM SALES", idle_timeout=-1)
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.arrow_stream("SELECT * FROM SALES")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.arrow_stream("SELECT * FROM SALES", idle_timeout=None, max_rows=10)
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.arrow_stream("SELECT * FROM SALES", materialize=True)
+
+
+def test_batch_size_validation():
+    """Test batch_size must be positive, in the constructor and the setter."""
+    with pytest.raises(ValueError):
+        ibarrow.QueryConfig(batch_size=0)
+
+    config = ibarrow.QueryConfig(batch_size=5000)
+    with pytest.raises(ValueError):
+        config.batch_size = 0
+    assert config.batch_size == 5000
+    config.batch_size = None
+    assert config.batch_size is None
+
+
+def test_max_bytes_per_batch_config():
+    """Test max_bytes_per_batch is stored and must be positive."""
+    config = ibarrow.QueryConfig(batch_size=100_000, max_bytes_per_batch=64 * 1024 * 1024)
+    assert config.max_bytes_per_batch == 64 * 1024 * 1024
+    with pytest.raises(ValueError):
+        ibarrow.QueryConfig(max_bytes_per_batch=0)
+    with pytest.raises(ValueError):
+        config.max_bytes_per_batch = 0
+
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password", config=config
+    )
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_arrow_ipc("SELECT * FROM SALES")
+
+
+def test_worker_process_config():
+    """Test worker_process runs the query in a helper process and reports its errors."""
+    config = ibarrow.QueryConfig(worker_process=True)
+    assert config.worker_process is True
+    assert ibarrow.QueryConfig().worker_process is False
+
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password", config=config
+    )
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_arrow_ipc("SELECT * FROM SALES")
+
+
+def test_driver_manager_detection():
+    """Test driver manager detection and the driver_manager override."""
+    info = ibarrow.driver_manager()
+    assert set(info) == {"name", "library", "loaded"}
+    assert info["name"] in (None, "unixodbc", "iodbc", "windows")
+
+    config = ibarrow.QueryConfig(driver_manager="unixODBC")
+    assert config.driver_manager == "unixodbc"
+    with pytest.raises(ValueError):
+        ibarrow.QueryConfig(driver_manager="odbc32")
+    with pytest.raises(ValueError):
+        config.driver_manager = "iodbc2"
+
+
+def test_spill_config(tmp_path):
+    """Test spill settings are stored and a failed query leaves no spill file behind."""
+    config = ibarrow.QueryConfig(spill_threshold=0, spill_dir=str(tmp_path))
+    assert config.spill_threshold == 0
+    assert config.spill_dir == str(tmp_path)
+    assert ibarrow.QueryConfig().spill_threshold is None
+
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password", config=config
+    )
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_polars("SELECT * FROM SALES")
+    assert list(tmp_path.iterdir()) == []
+
+
+def test_driver_env_config():
+    """Test driver_env is stored, validated and passed in the connection string."""
+    config = ibarrow.QueryConfig(driver_env={"ISC_USER": "SYSDBA", "ISC_PASSWORD": "masterkey"})
+    assert config.driver_env == {"ISC_USER": "SYSDBA", "ISC_PASSWORD": "masterkey"}
+    with pytest.raises(ValueError):
+        ibarrow.QueryConfig(driver_env={"A=B": "x"})
+    with pytest.raises(ValueError):
+        config.driver_env = {"": "x"}
+    # The process environment is never changed, so other variables are refused
+    with pytest.raises(ValueError, match="connection string"):
+        ibarrow.QueryConfig(driver_env={"FIREBIRD": "/opt/firebird"})
+    with pytest.raises(ValueError):
+        ibarrow.QueryConfig(driver_env={"ISC_PASSWORD": "a;b"})
+
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password", config=config
+    )
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_arrow_ipc("SELECT * FROM SALES")
+
+
+def test_query_split_validation():
+    """Test split fractions are validated before the query runs."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    for split in ([1.0], [0.5, 0.6], [0.8, 0.0, 0.2], [float("nan"), 0.5]):
+        with pytest.raises(ValueError):
+            conn.query_polars("SELECT * FROM SALES", split=split)
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_pandas("SELECT * FROM SALES", split=[0.8, 0.2], seed=42)
+
+
+def test_dictionary_config():
+    """Test dictionary encoding settings are stored for consistent category codes."""
+    config = ibarrow.QueryConfig(
+        dictionary_columns=["REGION"], dictionaries={"STATUS": ["open", "closed"]}
+    )
+    assert config.dictionary_columns == ["REGION"]
+    assert config.dictionaries == {"STATUS": ["open", "closed"]}
+    assert ibarrow.QueryConfig().dictionaries is None
+
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password", config=config
+    )
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query("SELECT REGION, STATUS FROM SALES")
+
+
+def test_query_arrow_ipc_no_copy_invalid_connection():
+    """Test query_arrow_ipc accepts copy=False and still reports connection errors."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_arrow_ipc("SELECT * FROM SALES", copy=False)
+
+
+def test_empty_string_normalization_config():
+    """Test empty_as_null and null_as_empty are stored and mutually exclusive."""
+    assert ibarrow.QueryConfig(empty_as_null=True).empty_as_null is True
+    assert ibarrow.QueryConfig(null_as_empty=True).null_as_empty is True
+    config = ibarrow.QueryConfig()
+    assert config.empty_as_null is False and config.null_as_empty is False
+    with pytest.raises(ValueError):
+        ibarrow.QueryConfig(empty_as_null=True, null_as_empty=True)
+
+
+def test_query_to_ipc_targets(tmp_path):
+    """Test query_to_ipc rejects unusable targets and leaves no file behind on failure."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    with pytest.raises(ValueError):
+        conn.query_to_ipc("SELECT * FROM SALES", 42)
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_to_ipc("SELECT * FROM SALES", io.BytesIO())
+    path = tmp_path / "sales.arrows"
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_to_ipc("SELECT * FROM SALES", path)
+    assert not path.exists()
+    assert not (tmp_path / "sales.arrows.tmp").exists()
+    # A failed query keeps the previous file
+    path.write_bytes(b"previous")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_to_ipc("SELECT * FROM SALES", path)
+    assert path.read_bytes() == b"previous"
+
+
+def test_read_partitioned_validation():
+    """Test read_partitioned validates its arguments before connecting."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    with pytest.raises(ValueError):
+        conn.read_partitioned("SELECT * FROM SALES", "SALE_ID", 0)
+    with pytest.raises(ValueError):
+        conn.read_partitioned("SELECT * FROM SALES", " ", 4)
+    for column in ["SALE_ID) OR (1 = 1", "SALE_ID + 1", "SALE_ID; DROP TABLE SALES"]:
+        with pytest.raises(ValueError, match="column name"):
+            conn.read_partitioned("SELECT * FROM SALES", column, 4)
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.read_partitioned("SELECT * FROM SALES", "SALE_ID", 4)
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.read_partitioned("SELECT * FROM SALES", '"Sale Id"', 4)
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.read_partitioned("SELECT * FROM SALES", "SALE_ID", 4, snapshot=True)
+
+
+def test_snapshot_number_config():
+    """Test snapshot_number is stored, validated, and snapshot() reports connection errors."""
+    assert ibarrow.QueryConfig(snapshot_number=42).snapshot_number == 42
+    config = ibarrow.QueryConfig()
+    assert config.snapshot_number is None
+    config.snapshot_number = 7
+    assert config.snapshot_number == 7
+    with pytest.raises(ValueError):
+        ibarrow.QueryConfig(snapshot_number=0)
+    with pytest.raises(ValueError):
+        config.snapshot_number = -1
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.snapshot()
+
+
+def test_run_many_validation():
+    """Test run_many validates max_workers and handles empty and failing batches of queries."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    assert conn.run_many({}) == {}
+    with pytest.raises(ValueError):
+        conn.run_many({"sales": "SELECT * FROM SALES"}, max_workers=0)
+    with pytest.raises(ibarrow.PyConnectionError, match="sales"):
+        conn.run_many({"sales": "SELECT * FROM SALES", "stock": "SELECT * FROM STOCK"})
+
+
+def test_target_batch_bytes_config():
+    """Test target_batch_bytes is stored and validated."""
+    assert ibarrow.QueryConfig(target_batch_bytes=8 << 20).target_batch_bytes == 8 << 20
+    config = ibarrow.QueryConfig()
+    assert config.target_batch_bytes is None
+    config.target_batch_bytes = 1024
+    assert config.target_batch_bytes == 1024
+    with pytest.raises(ValueError):
+        ibarrow.QueryConfig(target_batch_bytes=0)
+    with pytest.raises(ValueError):
+        config.target_batch_bytes = 0
+
+
+def test_adaptive_fetch_config():
+    """Test adaptive_fetch is stored and defaults to off."""
+    assert ibarrow.QueryConfig().adaptive_fetch is False
+    config = ibarrow.QueryConfig(adaptive_fetch=True, target_batch_bytes=4 << 20)
+    assert config.adaptive_fetch is True
+    config.adaptive_fetch = False
+    assert config.adaptive_fetch is False
+
+
+def test_query_to_parquet_validation(tmp_path):
+    """Test query_to_parquet checks the codec and leaves no file behind on failure."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    path = tmp_path / "sales.parquet"
+    with pytest.raises(ValueError):
+        conn.query_to_parquet("SELECT * FROM SALES", path, compression="brotli")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_to_parquet("SELECT * FROM SALES", path, compression="zstd")
+    assert list(tmp_path.iterdir()) == []
+
+
+def test_query_to_parquet_column_options(tmp_path):
+    """Test query_to_parquet validates compression levels and per-column settings."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    path = tmp_path / "readings.parquet"
+    with pytest.raises(ValueError):
+        conn.query_to_parquet("SELECT * FROM READINGS", path, compression="snappy", compression_level=3)
+    with pytest.raises(ValueError):
+        conn.query_to_parquet("SELECT * FROM READINGS", path, compression="zstd", compression_level=99)
+    with pytest.raises(ValueError):
+        conn.query_to_parquet("SELECT * FROM READINGS", path, columns={"VALUE": {"encoding": "rle_dictionary"}})
+    with pytest.raises(ValueError):
+        conn.query_to_parquet("SELECT * FROM READINGS", path, columns={"VALUE": {"codec": "zstd"}})
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_to_parquet(
+            "SELECT * FROM READINGS",
+            path,
+            compression="zstd",
+            compression_level=9,
+            columns={
+                "SENSOR_NAME": {"dictionary": True, "compression": "zstd", "level": 3},
+                "VALUE": {"dictionary": False, "encoding": "byte_stream_split"},
+            },
+        )
+
+
+def test_query_to_parquet_partition_by(tmp_path):
+    """Test a partitioned Parquet export reports connection errors and leaves no files behind."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    dataset = tmp_path / "sales"
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_to_parquet("SELECT * FROM SALES", dataset, partition_by=["YEAR", "REGION"])
+    assert list(dataset.rglob("*.parquet*")) == []
+
+
+def test_query_to_csv(tmp_path):
+    """Test query_to_csv checks the delimiter and leaves no file behind on failure."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    path = tmp_path / "customers.csv"
+    for delimiter in ["", ";;", "\u00a7"]:
+        with pytest.raises(ValueError):
+            conn.query_to_csv("SELECT * FROM CUSTOMERS", path, delimiter=delimiter)
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_to_csv("SELECT * FROM CUSTOMERS", path, delimiter="\t", header=False)
+    assert list(tmp_path.iterdir()) == []
+
+
+def test_query_to_ndjson(tmp_path):
+    """Test query_to_ndjson checks its formats and leaves no file behind on failure."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    path = tmp_path / "audit.ndjson"
+    for options in [
+        {"timestamp_format": "unix"},
+        {"timestamp_format": "%Q"},
+        {"timestamp_format": '%Y"%m'},
+        {"decimal_format": "double"},
+    ]:
+        with pytest.raises(ValueError):
+            conn.query_to_ndjson("SELECT * FROM AUDIT_LOG", path, **options)
+    for timestamp_format in ["iso", "epoch_ms", "%Y-%m-%d %H:%M:%S"]:
+        with pytest.raises(ibarrow.PyConnectionError):
+            conn.query_to_ndjson(
+                "SELECT * FROM AUDIT_LOG", path, timestamp_format=timestamp_format, decimal_format="string"
+            )
+    assert list(tmp_path.iterdir()) == []
+
+
+def test_query_to_feather(tmp_path):
+    """Test query_to_feather checks the codec and leaves no file behind on failure."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    path = tmp_path / "measurements.arrow"
+    with pytest.raises(ValueError):
+        conn.query_to_feather("SELECT * FROM MEASUREMENTS", path, compression="brotli")
+    for compression in [None, "none", "lz4", "zstd"]:
+        with pytest.raises(ibarrow.PyConnectionError):
+            conn.query_to_feather("SELECT * FROM MEASUREMENTS", path, compression=compression)
+    assert list(tmp_path.iterdir()) == []
+
+
+def test_export_rollover(tmp_path):
+    """Test file rollover limits are validated and a failed export leaves no parts."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    parts = tmp_path / "events"
+    with pytest.raises(ValueError):
+        conn.query_to_parquet("SELECT * FROM EVENTS", parts, max_file_rows=0)
+    with pytest.raises(ValueError):
+        conn.query_to_feather("SELECT * FROM EVENTS", parts, max_file_bytes=0)
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_to_parquet("SELECT * FROM EVENTS", parts, max_file_rows=1000)
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_to_feather("SELECT * FROM EVENTS", parts, max_file_bytes=1 << 20)
+    assert list(parts.iterdir()) == []
+    with pytest.raises(RuntimeError, match="partition_by"):
+        conn.query_to_parquet("SELECT * FROM EVENTS", tmp_path, partition_by=["DAY"], max_file_rows=10)
+    with pytest.raises(RuntimeError, match="object store"):
+        conn.query_to_feather("SELECT * FROM EVENTS", "s3://bucket/events", max_file_rows=10)
+
+
+def test_export_sidecar(tmp_path):
+    """Test stats_column needs sidecar=True and a failed export writes no sidecar."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    path = tmp_path / "sales.parquet"
+    with pytest.raises(ValueError, match="sidecar"):
+        conn.query_to_parquet("SELECT * FROM SALES", path, stats_column="SALE_ID")
+    with pytest.raises(ValueError, match="sidecar"):
+        conn.query_to_csv("SELECT * FROM SALES", tmp_path / "sales.csv", stats_column="SALE_ID")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_to_parquet("SELECT * FROM SALES", path, sidecar=True, stats_column="SALE_ID")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_to_ndjson("SELECT * FROM SALES", tmp_path / "sales.ndjson", sidecar=True)
+    assert list(tmp_path.glob("*.meta.json")) == []
+
+def test_query_duckdb():
+    """Test query_duckdb validates its view name and reports connection errors."""
+
+    class FakeDuckDB:
+        def __init__(self):
+            self.calls = []
+
+        def register(self, name, table):
+            self.calls.append(("register", name))
+
+        def from_arrow(self, table):
+            self.calls.append(("from_arrow", None))
+
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    db = FakeDuckDB()
+    with pytest.raises(ValueError, match="name"):
+        conn.query_duckdb("SELECT * FROM CUSTOMERS", duckdb_conn=db, name=" ")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_duckdb("SELECT * FROM CUSTOMERS", duckdb_conn=db, name="customers")
+    assert db.calls == []
+    try:
+        import duckdb  # noqa: F401
+    except ImportError:
+        with pytest.raises(ImportError, match="duckdb"):
+            conn.query_duckdb("SELECT * FROM CUSTOMERS")
+
+def test_scan():
+    """Test scan validates its source and needs Polars IO plugins or a reachable server."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    with pytest.raises(ValueError):
+        ibarrow.scan(conn, "  ")
+    try:
+        import polars.io.plugins  # noqa: F401
+    except ImportError:
+        with pytest.raises(ImportError, match="IO plugins"):
+            ibarrow.scan(conn, "ORDERS")
+    else:
+        with pytest.raises(ibarrow.PyConnectionError):
+            ibarrow.scan(conn, "SELECT * FROM ORDERS")
+
+def test_query_pandas_dtype_backend():
+    """Test query_pandas validates dtype_backend before connecting."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    with pytest.raises(ValueError, match="dtype_backend"):
+        conn.query_pandas("SELECT * FROM INVOICES", dtype_backend="numpy_nullable")
+    with pytest.raises(ValueError, match="dtype_backend"):
+        conn.query_pandas("SELECT * FROM INVOICES", split=[0.5, 0.5], dtype_backend="arrow")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_pandas("SELECT * FROM INVOICES", dtype_backend="pyarrow")
+
+def test_query_dask():
+    """Test query_dask validates its arguments and partition tasks report connection errors."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    with pytest.raises(ValueError):
+        conn.query_dask("SELECT * FROM EVENTS", "EVENT_ID", 0)
+    with pytest.raises(ValueError):
+        conn.query_dask("SELECT * FROM EVENTS", " ", 4)
+    with pytest.raises(ValueError, match="dtype_backend"):
+        conn.query_dask("SELECT * FROM EVENTS", "EVENT_ID", 4, dtype_backend="arrow")
+    try:
+        import dask.dataframe  # noqa: F401
+    except ImportError:
+        with pytest.raises(ImportError, match="dask"):
+            conn.query_dask("SELECT * FROM EVENTS", "EVENT_ID", 4)
+    else:
+        with pytest.raises(ibarrow.PyConnectionError):
+            conn.query_dask("SELECT * FROM EVENTS", "EVENT_ID", 4)
+    with pytest.raises(ValueError, match="credentials_env"):
+        conn.query_dask("SELECT * FROM EVENTS", "EVENT_ID", 4, credentials_env="IB-ARROW")
+    with pytest.raises(ValueError, match="config"):
+        ibarrow._read_partition("IBARROW_TEST", "SELECT 1 FROM RDB$DATABASE", "{")
+    with pytest.raises(ValueError, match="IBARROW_TEST_DSN"):
+        ibarrow._read_partition("IBARROW_TEST", "SELECT 1 FROM RDB$DATABASE", "{}")
+    os.environ["IBARROW_TEST_DSN"] = "invalid_dsn"
+    try:
+        with pytest.raises(ibarrow.PyConnectionError):
+            ibarrow._read_partition("IBARROW_TEST", "SELECT 1 FROM RDB$DATABASE", "{}")
+    finally:
+        del os.environ["IBARROW_TEST_DSN"]
+
+def test_to_ray_dataset():
+    """Test to_ray_dataset validates its arguments and Arrow partition tasks report errors."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    with pytest.raises(ValueError):
+        conn.to_ray_dataset("SELECT * FROM EVENTS", "EVENT_ID", 0)
+    try:
+        import ray.data  # noqa: F401
+    except ImportError:
+        with pytest.raises(ImportError, match="ray"):
+            conn.to_ray_dataset("SELECT * FROM EVENTS", "EVENT_ID", 4)
+    with pytest.raises(ValueError, match="credentials_env"):
+        conn.to_ray_dataset("SELECT * FROM EVENTS", "EVENT_ID", 4, credentials_env="")
+    os.environ["IBARROW_TEST_DSN"] = "invalid_dsn"
+    try:
+        with pytest.raises(ibarrow.PyConnectionError):
+            ibarrow._read_partition_arrow("IBARROW_TEST", "SELECT 1 FROM RDB$DATABASE", "{}")
+    finally:
+        del os.environ["IBARROW_TEST_DSN"]
+
+def test_result_tensors():
+    """Test IbarrowResult exposes tensor conversions and query reports connection errors."""
+    assert hasattr(ibarrow.IbarrowResult, "to_torch")
+    assert hasattr(ibarrow.IbarrowResult, "to_tf_dataset")
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query("SELECT AGE, INCOME, CHURNED FROM CUSTOMER_FEATURES").to_torch(label="CHURNED")
+
+def test_ibis_hooks():
+    """Test the cursor, table_names and get_schema hooks validate input and report connection errors."""
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    cur = conn.cursor()
+    assert cur.description is None
+    assert cur.rowcount == -1
+    with pytest.raises(RuntimeError):
+        cur.fetchall()
+    with pytest.raises(ibarrow.PyConnectionError):
+        cur.execute("SELECT * FROM ORDERS")
+    cur.close()
+    with pytest.raises(RuntimeError, match="closed"):
+        cur.execute("SELECT * FROM ORDERS")
+    with pytest.raises(ValueError):
+        conn.get_schema(" ")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.get_schema("ORDERS")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.table_names()
+
+def test_arrow_interop_capsule():
+    """Test arrow_interop validation and that capsule mode rejects pyarrow-only options."""
+    with pytest.raises(ValueError, match="arrow_interop"):
+        ibarrow.QueryConfig(arrow_interop="nanoarrow")
+    with pytest.raises(ValueError, match="spill_threshold"):
+        ibarrow.QueryConfig(arrow_interop="capsule", spill_threshold=1 << 20)
+    config = ibarrow.QueryConfig(arrow_interop="capsule")
+    assert config.arrow_interop == "capsule"
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password", config=config)
+    with pytest.raises(ValueError, match="dtype_backend"):
+        conn.query_pandas("SELECT * FROM INVOICES", dtype_backend="pyarrow")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_pandas("SELECT * FROM INVOICES")
+
+def test_arrow_interop_auto():
+    """Test arrow_interop accepts auto and query_pandas falls back without pyarrow."""
+    config = ibarrow.QueryConfig(arrow_interop="auto")
+    assert config.arrow_interop == "auto"
+    assert ibarrow.QueryConfig().arrow_interop is None
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password", config=config)
+    # The query fails before any conversion, whichever of pyarrow and Polars is installed
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_pandas("SELECT * FROM INVOICES")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_pandas("SELECT * FROM INVOICES", split=[0.5, 0.5])
+
+def test_dbapi():
+    """Test the DB-API 2.0 globals and cursor behaviour without a reachable server."""
+    assert ibarrow.apilevel == "2.0"
+    assert ibarrow.threadsafety == 2
+    assert ibarrow.paramstyle == "qmark"
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    conn.commit()
+    with conn.cursor() as cur:
+        assert cur.arraysize == 1
+        cur.executemany("INSERT INTO EVENTS (ID) VALUES (?)", [])
+        assert cur.rowcount == 0
+        assert cur.description is None
+        with pytest.raises(RuntimeError, match="No result set"):
+            cur.fetchone()
+        with pytest.raises(ibarrow.PyConnectionError):
+            cur.execute("UPDATE EVENTS SET KIND = ? WHERE ID = ?", ("open", 1))
+        with pytest.raises(ibarrow.PyConnectionError):
+            cur.executemany("INSERT INTO EVENTS (ID, KIND) VALUES (:id, :kind)", [{"id": 1, "kind": "open"}])
+
+def test_sqlalchemy_support():
+    """Test identifier quoting, URL DSNs, the PEP 249 exceptions and the dialect lookup."""
+    assert ibarrow.quote_identifier("ORDERS") == "ORDERS"
+    assert ibarrow.quote_identifier("Orders") == '"Orders"'
+    assert ibarrow.quote_identifier("USER") == '"USER"'
+    assert ibarrow.quote_identifier('A"B') == '"A""B"'
+    assert ibarrow.requires_quotes("1ST")
+    assert not ibarrow.requires_quotes("RDB$FIELD_1")
+    assert ibarrow._url_dsn("db", 3050, "/data/x.fdb") == "db/3050:/data/x.fdb"
+    assert ibarrow._url_dsn(None, None, None, "MY_DSN") == "MY_DSN"
+    with pytest.raises(ValueError):
+        ibarrow._url_dsn("db")
+    assert issubclass(ibarrow.PyConnectionError, ibarrow.OperationalError)
+    assert issubclass(ibarrow.PySQLError, ibarrow.DatabaseError)
+    assert issubclass(ibarrow.IntegrityError, ibarrow.Error)
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    # Without an open transaction there is nothing to end
+    conn.commit()
+    conn.rollback()
+    # Statements other than queries open the transaction, so they connect straight away
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.cursor().execute("INSERT INTO EVENTS (ID) VALUES (?)", (1,))
+    conn.rollback()
+    conn.close()
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.server_version()
+    try:
+        import sqlalchemy  # noqa: F401
+    except ImportError:
+        with pytest.raises(ImportError, match="sqlalchemy"):
+            ibarrow.IbarrowDialect
+    with pytest.raises(AttributeError):
+        ibarrow.no_such_attribute
+
+def test_adbc_driver():
+    """Test the ADBC entry point through ctypes and the adbc_connect helper."""
+    import ctypes
+
+    class AdbcError(ctypes.Structure):
+        _fields_ = [
+            ("message", ctypes.c_char_p),
+            ("vendor_code", ctypes.c_int32),
+            ("sqlstate", ctypes.c_char * 5),
+            ("release", ctypes.c_void_p),
+        ]
+
+    class Handle(ctypes.Structure):
+        _fields_ = [("private_data", ctypes.c_void_p), ("private_driver", ctypes.c_void_p)]
+
+    lib = ctypes.CDLL(ibarrow.adbc_driver_path())
+    lib.AdbcDriverInit.restype = ctypes.c_uint8
+    driver = (ctypes.c_void_p * 30)()
+    error = AdbcError()
+    assert lib.AdbcDriverInit(1001000, driver, ctypes.byref(error)) == 2  # NOT_IMPLEMENTED
+    assert lib.AdbcDriverInit(1000000, driver, ctypes.byref(error)) == 0
+
+    def call(index, *argtypes):
+        return ctypes.CFUNCTYPE(ctypes.c_uint8, *argtypes)(driver[index])
+
+    ptr = ctypes.POINTER
+    database, connection, statement = Handle(), Handle(), Handle()
+    assert call(4, ptr(Handle), ptr(AdbcError))(database, error) == 0  # DatabaseNew
+    set_option = call(5, ptr(Handle), ctypes.c_char_p, ctypes.c_char_p, ptr(AdbcError))
+    assert set_option(database, b"uri", b"invalid_dsn", error) == 0
+    assert set_option(database, b"ibarrow.config", b"{\"batch_size\": 100}", error) == 0
+    assert set_option(database, b"ibarrow.config", b"not json", error) == 5  # INVALID_ARGUMENT
+    assert b"ibarrow.config" in error.message
+    assert call(3, ptr(Handle), ptr(AdbcError))(database, error) == 0  # DatabaseInit
+    assert call(13, ptr(Handle), ptr(AdbcError))(connection, error) == 0  # ConnectionNew
+    connection_init = call(12, ptr(Handle), ptr(Handle), ptr(AdbcError))
+    assert connection_init(connection, database, error) not in (0, 2)
+    assert error.message
+    # No statements on a connection that failed to open
+    assert call(23, ptr(Handle), ptr(Handle), ptr(AdbcError))(connection, statement, error) == 6
+    assert call(16, ptr(Handle), ptr(AdbcError))(connection, error) == 0  # ConnectionRelease
+    assert call(6, ptr(Handle), ptr(AdbcError))(database, error) == 0  # DatabaseRelease
+    assert call(6, ptr(Handle), ptr(AdbcError))(database, error) == 6
+    # NULL handles are invalid arguments, not crashes
+    assert set_option(None, b"uri", b"invalid_dsn", error) == 5
+    assert b"database is NULL" in error.message
+    assert call(3, ptr(Handle), ptr(AdbcError))(None, error) == 5  # DatabaseInit
+    assert call(14, ptr(Handle), ctypes.c_char_p, ctypes.c_char_p, ptr(AdbcError))(
+        None, b"adbc.connection.autocommit", b"true", error
+    ) == 5  # ConnectionSetOption
+    assert connection_init(None, database, error) == 5
+    assert b"connection is NULL" in error.message
+    assert call(13, ptr(Handle), ptr(AdbcError))(connection, error) == 0
+    assert connection_init(connection, None, error) == 5
+    assert b"database is NULL" in error.message
+    assert call(16, ptr(Handle), ptr(AdbcError))(connection, error) == 0
+    assert call(16, ptr(Handle), ptr(AdbcError))(None, error) == 5
+
+    try:
+        import adbc_driver_manager  # noqa: F401
+    except ImportError:
+        with pytest.raises(ImportError, match="adbc-driver-manager"):
+            ibarrow.adbc_connect("invalid_dsn")
+
+def test_http_query_service():
+    """Test the HTTP query service's routing, auth and error responses."""
+    import json
+    import urllib.error
+    import urllib.request
+
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    with pytest.raises(ValueError):
+        conn.serve(port=0, max_connections=0)
+    with conn.serve(port=0, token="secret") as server:
+        assert server.port > 0
+        assert server.url == f"http://127.0.0.1:{server.port}"
+
+        def request(path, data=None, headers=None):
+            req = urllib.request.Request(server.url + path, data=data, headers=headers or {})
+            try:
+                with urllib.request.urlopen(req) as response:
+                    return response.status, response.read()
+            except urllib.error.HTTPError as e:
+                return e.code, json.loads(e.read())
+
+        auth = {"Authorization": "Bearer secret"}
+        assert request("/health")[0] == 401
+        assert request("/health", headers=auth) == (200, b"ok")
+        status, body = request("/query", b"SELECT 1 FROM RDB$DATABASE", auth)
+        assert status == 502 and body["sqlstate"] == "IM002"
+        status, body = request(
+            "/query",
+            json.dumps({"sql": "SELECT ?", "params": [1], "format": "csv"}).encode(),
+            {**auth, "Content-Type": "application/json"},
+        )
+        assert status == 400 and "format" in body["error"]
+        assert request("/missing", headers=auth)[0] == 404
+        status, body = request("/query", b"DELETE FROM ORDERS", auth)
+        assert status == 403 and "read-only" in body["error"]
+        status, _ = request(
+            "/query", b"SELECT 1 FROM RDB$DATABASE", {**auth, "Origin": "https://evil.example"}
+        )
+        assert status == 403
+    assert "stopped" in repr(server)
+
+    with pytest.raises(ValueError, match="token"):
+        conn.serve(host="0.0.0.0", port=0)
+    with conn.serve(port=0, allowed_origins=["https://app.example"]) as server:
+        req = urllib.request.Request(server.url + "/query", data=b"SELECT 1 FROM RDB$DATABASE")
+        with pytest.raises(urllib.error.HTTPError) as error:
+            urllib.request.urlopen(req)
+        assert error.value.code == 415
+        req = urllib.request.Request(
+            server.url + "/query",
+            data=json.dumps({"sql": "SELECT 1 FROM RDB$DATABASE"}).encode(),
+            headers={"Content-Type": "application/json", "Origin": "https://app.example"},
+        )
+        with pytest.raises(urllib.error.HTTPError) as error:
+            urllib.request.urlopen(req)
+        assert error.value.code == 502
+
+def test_cli_query():
+    """Test the ibarrow command-line client (skipped unless the binary is built)."""
+    binary = os.environ.get("IBARROW_CLI") or shutil.which("ibarrow")
+    if binary is None:
+        pytest.skip("ibarrow binary not built (cargo build --features cli)")
+
+    usage = subprocess.run([binary, "query"], capture_output=True, text=True)
+    assert usage.returncode == 2
+    assert "no SQL given" in usage.stderr
+
+    failed = subprocess.run(
+        [binary, "query", "--dsn", "invalid_dsn", "SELECT 1 FROM RDB$DATABASE"],
+        capture_output=True,
+        text=True,
+    )
+    assert failed.returncode == 1
+    assert "ERROR:" in failed.stderr
+    assert failed.stdout == ""
+
+
+def test_cli_catalog_commands():
+    """Test the ibarrow tables/columns/schema subcommands (skipped unless the binary is built)."""
+    binary = os.environ.get("IBARROW_CLI") or shutil.which("ibarrow")
+    if binary is None:
+        pytest.skip("ibarrow binary not built (cargo build --features cli)")
+
+    missing = subprocess.run([binary, "columns"], capture_output=True, text=True)
+    assert missing.returncode == 2
+    assert "needs a table name" in missing.stderr
+
+    wrong = subprocess.run([binary, "tables", "--format", "csv"], capture_output=True, text=True)
+    assert wrong.returncode == 2
+
+    for args in (["tables"], ["columns", "ORDERS"], ["schema", "ORDERS", "--json"]):
+        failed = subprocess.run(
+            [binary, *args, "--dsn", "invalid_dsn"], capture_output=True, text=True
+        )
+        assert failed.returncode == 1
+        assert "ERROR:" in failed.stderr
+
+
+def test_cli_copy():
+    """Test ibarrow copy argument handling (skipped unless the binary is built)."""
+    binary = os.environ.get("IBARROW_CLI") or shutil.which("ibarrow")
+    if binary is None:
+        pytest.skip("ibarrow binary not built (cargo build --features cli)")
+
+    no_target = subprocess.run(
+        [binary, "copy", "--from", "invalid_dsn", "--table", "ORDERS"],
+        capture_output=True,
+        text=True,
+    )
+    assert no_target.returncode == 2
+    assert "--to" in no_target.stderr
+
+    failed = subprocess.run(
+        [binary, "copy", "--from", "invalid_dsn", "--to", "other_dsn", "-t", "ORDERS", "--resume"],
+        capture_output=True,
+        text=True,
+    )
+    assert failed.returncode == 1
+    assert "ERROR:" in failed.stderr
+
+
+def test_cli_bench():
+    """Test ibarrow bench argument handling (skipped unless the binary is built)."""
+    binary = os.environ.get("IBARROW_CLI") or shutil.which("ibarrow")
+    if binary is None:
+        pytest.skip("ibarrow binary not built (cargo build --features cli)")
+
+    bad_sizes = subprocess.run(
+        [binary, "bench", "--batch-sizes", "1000,0", "SELECT 1 FROM RDB$DATABASE"],
+        capture_output=True,
+        text=True,
+    )
+    assert bad_sizes.returncode == 2
+    assert "greater than 0" in bad_sizes.stderr
+
+    failed = subprocess.run(
+        [binary, "bench", "--dsn", "invalid_dsn", "--json", "SELECT 1 FROM RDB$DATABASE"],
+        capture_output=True,
+        text=True,
+    )
+    assert failed.returncode == 1
+    assert "ERROR:" in failed.stderr
+
+
+def test_backend_config():
+    """Test QueryConfig.backend selects the transport and rejects unknown ones."""
+    assert ibarrow.QueryConfig().backend is None
+    config = ibarrow.QueryConfig(backend="ODBC")
+    assert config.backend == "odbc"
+    with pytest.raises(ValueError, match="backend"):
+        ibarrow.QueryConfig(backend="carrier-pigeon")
+    with pytest.raises(ValueError, match="backend"):
+        config.backend = "carrier-pigeon"
+
+    conn = ibarrow.connect("NONEXISTENT_DSN", "user", "pass", config=config)
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_arrow_ipc("SELECT 1 FROM RDB$DATABASE")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.server_version()
+
+
+def test_firebird_backend():
+    """Test backend="firebird" reaches the server over the wire protocol, without ODBC."""
+    import socket
+
+    config = ibarrow.QueryConfig(backend="Firebird")
+    assert config.backend == "firebird"
+
+    # A port nothing listens on
+    with socket.socket() as probe:
+        probe.bind(("127.0.0.1", 0))
+        port = probe.getsockname()[1]
+    conn = ibarrow.connect(f"127.0.0.1/{port}:employee", "SYSDBA", "masterkey", config=config)
+    with pytest.raises(ibarrow.PyConnectionError, match="Firebird connection"):
+        conn.query_arrow_ipc("SELECT 1 AS X FROM RDB$DATABASE")
+    with pytest.raises(ibarrow.PyConnectionError, match="Firebird connection"):
+        conn.server_version()
+
+    conn = ibarrow.connect("DRIVER={Firebird};DBNAME=employee", "SYSDBA", "x", config=config)
+    with pytest.raises(Exception, match="not an ODBC connection string"):
+        conn.query_arrow_ipc("SELECT 1 AS X FROM RDB$DATABASE")
+
+
+def test_write_arrow_validation():
+    """Test write_arrow rejects data it cannot read before connecting."""
+    conn = ibarrow.connect("NONEXISTENT_DSN", "user", "pass")
+    with pytest.raises(TypeError, match="__arrow_c_stream__"):
+        conn.write_arrow("ORDERS", 42)
+    with pytest.raises(ibarrow.PyArrowError, match="IPC"):
+        conn.write_arrow("ORDERS", b"not an arrow stream")
+    with pytest.raises(ValueError, match="table_name"):
+        conn.write_arrow(" ", b"")
+
+    pa = pytest.importorskip("pyarrow")
+    data = pa.table({"ID": [1, 2], "NAME": ["a", None]})
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.write_arrow("ORDERS", data)
+    with pytest.raises(ValueError, match="Cannot insert column TAGS"):
+        conn.write_arrow("ORDERS", pa.table({"TAGS": [[1], [2]]}))
+
+
+def test_write_dataframe_validation():
+    """Test write_polars and write_pandas only take their own DataFrames."""
+    conn = ibarrow.connect("NONEXISTENT_DSN", "user", "pass")
+    with pytest.raises(TypeError, match="Polars DataFrame"):
+        conn.write_polars({"ID": [1]}, "ORDERS")
+    with pytest.raises(TypeError, match="pandas DataFrame"):
+        conn.write_pandas([1, 2], "ORDERS", coerce=True)
+
+    pl = pytest.importorskip("polars")
+    with pytest.raises(ValueError, match="table"):
+        conn.write_polars(pl.DataFrame({"id": [1]}), "")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.write_polars(pl.DataFrame({"id": [1]}).lazy(), "ORDERS")
+
+
+def test_upsert_arrow_validation():
+    """Test upsert_arrow needs key columns that are in the data."""
+    conn = ibarrow.connect("NONEXISTENT_DSN", "user", "pass")
+    with pytest.raises(ValueError, match="key_columns"):
+        conn.upsert_arrow("ORDERS", b"", [])
+    with pytest.raises(TypeError, match="__arrow_c_stream__"):
+        conn.upsert_arrow("ORDERS", 42, ["ID"])
+
+    pa = pytest.importorskip("pyarrow")
+    data = pa.table({"ID": [1, 2], "STATUS": ["new", "paid"]})
+    with pytest.raises(ValueError, match="Key column CODE"):
+        conn.upsert_arrow("ORDERS", data, ["CODE"])
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.upsert_arrow("ORDERS", data, ["ID"])
+
+
+def test_load_file(tmp_path):
+    """Test load_file picks the format, reads the file and then connects."""
+    conn = ibarrow.connect("NONEXISTENT_DSN", "user", "pass")
+    path = tmp_path / "orders.csv"
+    path.write_text("id;amount;placed\n1;9.50;2024-01-02\n2;;2024-01-03\n")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.load_file(path, "ORDERS", delimiter=";")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.load_file(str(path), "ORDERS", delimiter=";", coerce=True)
+
+    with pytest.raises(ValueError, match="extension"):
+        conn.load_file(tmp_path / "orders.txt", "ORDERS")
+    with pytest.raises(ValueError, match="Unknown format 'xml'"):
+        conn.load_file(path, "ORDERS", format="xml")
+    with pytest.raises(ValueError, match="delimiter"):
+        conn.load_file(path, "ORDERS", delimiter="::")
+    with pytest.raises(OSError, match="missing.parquet"):
+        conn.load_file(tmp_path / "missing.parquet", "ORDERS")
+    with pytest.raises(OSError):
+        conn.load_file(path, "ORDERS", format="parquet")
+
+
+def test_write_modes(tmp_path):
+    """Test every write API takes mode and replace is denied to access-restricted roles."""
+    conn = ibarrow.connect("NONEXISTENT_DSN", "user", "pass")
+    path = tmp_path / "orders.csv"
+    path.write_text("ID,AMOUNT\n1,9.5\n")
+    with pytest.raises(ValueError, match="Unknown mode 'overwrite'"):
+        conn.write_arrow("ORDERS", b"", mode="overwrite")
+    with pytest.raises(ValueError, match="mode"):
+        conn.upsert_arrow("ORDERS", b"", ["ID"], mode="merge")
+    with pytest.raises(ValueError, match="mode"):
+        conn.load_file(path, "ORDERS", mode="")
+    for mode in ["append", "truncate", "REPLACE"]:
+        with pytest.raises(ibarrow.PyConnectionError):
+            conn.load_file(path, "ORDERS", mode=mode)
+
+    ibarrow.set_access_policy("loader", {"ORDERS": None})
+    try:
+        restricted = ibarrow.connect(
+            "NONEXISTENT_DSN",
+            "user",
+            "pass",
+            config=ibarrow.QueryConfig(access_role="loader"),
+        )
+        # Roles may only read, so every mode is refused before any DDL
+        for mode in ["append", "truncate", "replace"]:
+            with pytest.raises(ibarrow.PyAccessError, match="may only run SELECT"):
+                restricted.load_file(path, "ORDERS", mode=mode)
+    finally:
+        ibarrow.clear_access_policy()
+
+
+def test_run_in_transaction():
+    """Test run_in_transaction validates statements and connects before calling back."""
+    conn = ibarrow.connect("NONEXISTENT_DSN", "user", "pass")
+    assert conn.run_in_transaction([]) == []
+    with pytest.raises(TypeError, match="not a string"):
+        conn.run_in_transaction("DELETE FROM ORDERS")
+    with pytest.raises(TypeError, match="pairs"):
+        conn.run_in_transaction(["DELETE FROM ORDERS", 42])
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.run_in_transaction(
+            [
+                "DELETE FROM ORDERS WHERE STATUS = 'VOID'",
+                ("UPDATE ORDERS SET STATUS = :status WHERE ID = :id", {"status": "PAID", "id": 7}),
+            ]
+        )
+
+    called = []
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.run_in_transaction(lambda tx: called.append(tx))
+    assert called == []
+    assert ibarrow.Transaction.__name__ == "Transaction"
+
+
+def test_pipe():
+    """Test ibarrow.pipe validates its arguments and reports source connection errors."""
+    source = ibarrow.connect("NONEXISTENT_SOURCE", "user", "pass")
+    target = ibarrow.connect("NONEXISTENT_TARGET", "user", "pass")
+    sql = "SELECT * FROM ORDERS"
+    with pytest.raises(ValueError, match="batch_size"):
+        ibarrow.pipe(source, sql, target, "ORDERS", batch_size=0)
+    with pytest.raises(ValueError, match="target_table"):
+        ibarrow.pipe(source, sql, target, "")
+    with pytest.raises(ValueError, match="mode"):
+        ibarrow.pipe(source, sql, target, "ORDERS", mode="upsert")
+    with pytest.raises(ibarrow.PyConnectionError, match="NONEXISTENT_SOURCE|IM002"):
+        ibarrow.pipe(source, sql, target, "ORDERS", batch_size=5000, mode="truncate")
+
+
+def test_schema_to_ddl():
+    """Test ibarrow.schema_to_ddl maps Arrow types and applies overrides."""
+    with pytest.raises(TypeError, match="schema"):
+        ibarrow.schema_to_ddl(42, "ORDERS")
+    with pytest.raises(ValueError, match="dialect"):
+        ibarrow.schema_to_ddl(b"", "ORDERS", dialect="postgres")
+    with pytest.raises(ValueError, match="table"):
+        ibarrow.schema_to_ddl(b"", " ")
+    with pytest.raises(ValueError, match="varchar_length"):
+        ibarrow.schema_to_ddl(b"", "ORDERS", varchar_length=0)
+
+    pa = pytest.importorskip("pyarrow")
+    schema = pa.schema(
+        [
+            pa.field("ID", pa.int64(), nullable=False),
+            pa.field("Name", pa.string()),
+            pa.field("PRICE", pa.decimal128(40, 2)),
+            pa.field("TAGS", pa.list_(pa.string())),
+            pa.field("RATE", pa.decimal256(40, 39)),
+            pa.field("CODE", pa.string()),
+        ]
+    )
+    with pytest.raises(ValueError, match="TAGS"):
+        ibarrow.schema_to_ddl(schema, "ORDERS")
+    with pytest.raises(ValueError, match="MISSING"):
+        ibarrow.schema_to_ddl(schema, "ORDERS", overrides={"MISSING": "INTEGER"})
+
+    ddl = ibarrow.schema_to_ddl(
+        schema, "ORDERS", overrides={"Name": "VARCHAR(100)", "TAGS": "BLOB SUB_TYPE TEXT"}
+    )
+    assert ddl.startswith("CREATE TABLE ORDERS (")
+    assert "ID BIGINT NOT NULL" in ddl
+    assert '"Name" VARCHAR(100)' in ddl
+    assert "PRICE NUMERIC(38, 2)" in ddl
+    assert "RATE NUMERIC(38, 38)" in ddl
+    assert "CODE VARCHAR(255)" in ddl
+
+    ddl = ibarrow.schema_to_ddl(
+        schema, "ORDERS", dialect="interbase", overrides={"TAGS": "BLOB"}, varchar_length=None
+    )
+    assert "ID NUMERIC(18, 0) NOT NULL" in ddl
+    assert "PRICE NUMERIC(18, 2)" in ddl
+    assert "RATE NUMERIC(18, 18)" in ddl
+    assert "CODE BLOB SUB_TYPE 1" in ddl
+
+
+def test_list_tables():
+    """Test conn.list_tables reports connection errors for every filter combination."""
+    conn = ibarrow.connect("NONEXISTENT_DSN", "user", "pass")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.list_tables()
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.list_tables(include_system=True, pattern="CUST%")
+    # The result still reads as the list of names list_tables() used to return
+    assert issubclass(ibarrow.TableList, ibarrow.IbarrowResult)
+
+
+def test_table_schema():
+    """Test conn.table_schema validates the table name and reports connection errors."""
+    conn = ibarrow.connect("NONEXISTENT_DSN", "user", "pass")
+    with pytest.raises(ValueError, match="table"):
+        conn.table_schema("  ")
+
+    pytest.importorskip("pyarrow")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.table_schema("CUSTOMERS")
+
+
+def test_key_and_index_metadata():
+    """Test primary_keys, foreign_keys and indexes validate the table and report connection errors."""
+    conn = ibarrow.connect("NONEXISTENT_DSN", "user", "pass")
+    for method in (conn.primary_keys, conn.foreign_keys, conn.indexes):
+        with pytest.raises(ValueError, match="table"):
+            method("")
+        with pytest.raises(ibarrow.PyConnectionError):
+            method("ORDERS")
+
+
+def test_scroll_cursor_connection_error():
+    """Test scroll_cursor reports connection errors and ScrollCursor is exported."""
+    assert hasattr(ibarrow, "ScrollCursor")
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.scroll_cursor("SELECT * FROM SALES")
+
+
+def test_recipe_validation_and_round_trip(tmp_path):
+    """Test Recipe validates its definition and parameters and round-trips through JSON."""
+    import datetime
+
+    recipe = ibarrow.Recipe(
+        "SELECT * FROM SALES WHERE REGION = :region AND SOLD_AT >= :since",
+        params_schema={"region": "str", "since": "date?"},
+        config=ibarrow.QueryConfig(batch_size=500),
+        output="pandas",
+        name="sales_by_region",
+        version=3,
+    )
+    assert recipe.params_schema == {"region": "str", "since": "date?"}
+    recipe.validate({"region": "EU", "since": datetime.date(2024, 1, 1)})
+    recipe.validate({"region": "EU"})
+    with pytest.raises(ValueError):
+        recipe.validate({})
+    with pytest.raises(ValueError):
+        recipe.validate({"region": "EU", "country": "PT"})
+    with pytest.raises(TypeError):
+        recipe.validate({"region": 7})
+    with pytest.raises(TypeError):
+        recipe.validate({"region": "EU", "since": datetime.datetime(2024, 1, 1)})
+
+    with pytest.raises(ValueError):
+        ibarrow.Recipe("SELECT * FROM SALES WHERE REGION = :region")
+    with pytest.raises(ValueError):
+        ibarrow.Recipe("SELECT * FROM SALES", params_schema={"region": "str"})
+    with pytest.raises(ValueError):
+        ibarrow.Recipe("SELECT * FROM SALES", output="excel")
+    with pytest.raises(ValueError):
+        ibarrow.Recipe("SELECT * FROM SALES WHERE ID = :id", params_schema={"id": "uuid"})
+
+    loaded = ibarrow.Recipe.from_json(recipe.to_json())
+    assert loaded == recipe
+    assert loaded.config.batch_size == 500
+    assert recipe.to_dict()["format"] == 1
+    path = tmp_path / "sales.recipe.json"
+    recipe.save(path)
+    assert ibarrow.Recipe.load(path).version == 3
+    with pytest.raises(ValueError, match="newer"):
+        ibarrow.Recipe.from_json(recipe.to_json().replace('"format": 1', '"format": 99'))
+
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    with pytest.raises(ibarrow.PyConnectionError):
+        recipe.run(conn, {"region": "EU"})
+
+    # Credentials are not written out
+    secret = ibarrow.Recipe(
+        "SELECT * FROM SALES",
+        config=ibarrow.QueryConfig(
+            driver_env={"ISC_PASSWORD": "s3cret"},
+            storage_options={"aws_secret_access_key": "s3cret"},
+        ),
+    )
+    assert "s3cret" not in secret.to_json()
+    assert secret.to_dict()["config"]["driver_env"] is None
+
+    # The recipe's config is laid over the connection's, keeping its access role
+    ibarrow.set_access_policy("analyst", {"ORDERS": None})
+    try:
+        restricted = ibarrow.connect(
+            dsn="invalid_dsn",
+            user="invalid_user",
+            password="invalid_password",
+            config=ibarrow.QueryConfig(access_role="analyst"),
+        )
+        with pytest.raises(ibarrow.PyAccessError):
+            recipe.run(restricted, {"region": "EU"})
+    finally:
+        ibarrow.clear_access_policy()
+
+
+def test_object_store_destinations():
+    """Test object store URLs take storage_options and reject settings they do not know."""
+    config = ibarrow.QueryConfig(storage_options={"aws_region": "eu-west-1"})
+    assert config.storage_options == {"aws_region": "eu-west-1"}
+    config = ibarrow.QueryConfig(storage_options={"bogus_option": "1"})
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password", config=config
+    )
+    with pytest.raises(RuntimeError, match="storage option"):
+        conn.query_to_parquet("SELECT * FROM SALES", "s3://bucket/sales.parquet")
+    with pytest.raises(RuntimeError, match="storage option"):
+        conn.query_to_ipc("SELECT * FROM SALES", "gs://bucket/sales.arrows")
+    with pytest.raises(RuntimeError, match="object store"):
+        conn.query_to_parquet("SELECT * FROM SALES", "az://container/sales", partition_by=["YEAR"])
+
+
+def test_fault_injection():
+    """Test injected connection drops go through retry handling (testing feature builds only)."""
+    testing = pytest.importorskip("ibarrow.testing")
+    with pytest.raises(ValueError):
+        testing.inject_fault("disk_full")
+    testing.inject_fault("connection_drop", times=2)
+    config = ibarrow.QueryConfig(retry_attempts=3, retry_backoff_ms=1)
+    conn = ibarrow.connect(
+        dsn="invalid_dsn", user="invalid_user", password="invalid_password", config=config
+    )
+    try:
+        with pytest.raises(ibarrow.PyConnectionError):
+            conn.query_polars("SELECT 1 FROM RDB$DATABASE")
+        assert testing.injected_faults()[0]["fired"] == 2
+    finally:
+        testing.clear_faults()
+    assert testing.injected_faults() == []
+
+
+def test_array_columns_config():
+    """Test array_columns checks element type declarations."""
+    config = ibarrow.QueryConfig(array_columns={"READINGS": "DOUBLE PRECISION[10]", "GRID": "NUMERIC(9,2)[3,4]"})
+    assert config.array_columns == {"READINGS": "DOUBLE PRECISION[10]", "GRID": "NUMERIC(9,2)[3,4]"}
+    config.array_columns = {"TAGS": "varchar(20)"}
+    assert config.array_columns == {"TAGS": "varchar(20)"}
+    for declared in ["BLOB", "INTEGER[3", "NUMERIC(2,5)"]:
+        with pytest.raises(ValueError):
+            ibarrow.QueryConfig(array_columns={"READINGS": declared})
+        with pytest.raises(ValueError):
+            config.array_columns = {"READINGS": declared}
+    assert config.array_columns == {"TAGS": "varchar(20)"}
+
+
+def test_transaction_age_config():
+    """Test max_transaction_age and transaction_age_action are validated."""
+    config = ibarrow.QueryConfig(max_transaction_age=600, transaction_age_action="rollback")
+    assert config.max_transaction_age == 600
+    assert config.transaction_age_action == "rollback"
+    with pytest.raises(ValueError):
+        ibarrow.QueryConfig(max_transaction_age=0)
+    with pytest.raises(ValueError):
+        ibarrow.QueryConfig(max_transaction_age=600, transaction_age_action="commit")
+    with pytest.raises(ValueError):
+        config.transaction_age_action = "commit"
+    with pytest.raises(ValueError):
+        config.max_transaction_age = 0
+    config.transaction_age_action = "warn"
+    assert config.transaction_age_action == "warn"
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password", config=config)
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.snapshot()
+
+
+def test_transaction_refresh_config():
+    """Test transaction_refresh_secs is validated and needs restart_key."""
+    config = ibarrow.QueryConfig(transaction_refresh_secs=900, restart_key="ID")
+    assert config.transaction_refresh_secs == 900
+    with pytest.raises(ValueError):
+        ibarrow.QueryConfig(transaction_refresh_secs=0)
+    with pytest.raises(ValueError):
+        config.transaction_refresh_secs = 0
+    config.transaction_refresh_secs = None
+    assert config.transaction_refresh_secs is None
+
+
+def test_set_context():
+    """Test set_context validates variables and records them for new connections."""
+    config = ibarrow.QueryConfig(session_context={"USER_SESSION": {"TENANT_ID": "7"}})
+    assert config.session_context == {"USER_SESSION": {"TENANT_ID": "7"}}
+    with pytest.raises(ValueError):
+        ibarrow.QueryConfig(session_context={"SYSTEM": {"TENANT_ID": "7"}})
+    with pytest.raises(ValueError):
+        config.session_context = {"USER_SESSION": {"TENANT_ID": "x" * 256}}
+
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password", config=config)
+    conn.set_context("USER_SESSION", "TENANT_ID", 42)
+    conn.set_context("USER_SESSION", "REGION", None)
+    with pytest.raises(ValueError):
+        conn.set_context("USER_TRANSACTION", "REGION", "EU")
+    with pytest.raises(ValueError):
+        conn.set_context("user_session", "TENANT_ID", 42)
+    with pytest.raises(ValueError):
+        conn.set_context("USER_SESSION", "", 42)
+    with pytest.raises(ibarrow.PyConnectionError):
+        conn.query_arrow_ipc("SELECT 1 FROM RDB$DATABASE")
+
+
+def test_run_manifest(tmp_path):
+    """Test run_manifest validates the manifest up front and reports failed extracts."""
+    pytest.importorskip("yaml")
+    conn = ibarrow.connect(dsn="invalid_dsn", user="invalid_user", password="invalid_password")
+    manifest = tmp_path / "extracts.yaml"
+    manifest.write_text(
+        "max_workers: 2\n"
+        "extracts:\n"
+        "  - name: sales\n"
+        "    sql: SELECT * FROM SALES\n"
+        f"    destination: {tmp_path / 'sales.parquet'}\n"
+        "    compression: zstd\n"
+        "    schedule: '0 2 * * *'\n"
+        "  - name: customers\n"
+        "    sql: SELECT * FROM CUSTOMERS\n"
+        f"    destination: {tmp_path / 'customers.csv'}\n"
+    )
+    report = conn.run_manifest(manifest)
+    assert report["succeeded"] == 0
+    assert report["failed"] == 2
+    assert [extract["name"] for extract in report["extracts"]] == ["sales", "customers"]
+    assert [extract["format"] for extract in report["extracts"]] == ["parquet", "csv"]
+    assert report["extracts"][0]["schedule"] == "0 2 * * *"
+    assert all(extract["status"] == "failed" for extract in report["extracts"])
+
+    report = conn.run_manifest(manifest, only=["customers"])
+    assert [extract["name"] for extract in report["extracts"]] == ["customers"]
+    with pytest.raises(ValueError):
+        conn.run_manifest(manifest, only=["orders"])
+    with pytest.raises(ValueError):
+        conn.run_manifest(manifest, max_workers=0)
+
+    broken = tmp_path / "broken.json"
+    broken.write_text(
+        '{"extracts": [{"name": "sales", "sql": "SELECT 1 FROM RDB$DATABASE", '
+        '"destination": "sales.csv", "compression": "zstd"}]}'
+    )
+    with pytest.raises(ValueError):
+        conn.run_manifest(broken)
+    broken.write_text('{"extracts": [{"name": "sales", "sql": "SELECT 1", "destination": "x.csv", "typo": 1}]}')
+    with pytest.raises(RuntimeError, match="Invalid manifest"):
+        conn.run_manifest(broken)
+    assert sorted(path.name for path in tmp_path.iterdir()) == ["broken.json", "extracts.yaml"]